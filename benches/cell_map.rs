@@ -0,0 +1,189 @@
+//! Benchmarks of core [`CellMap`] operations.
+//!
+//! Run with `cargo bench`. To check for performance regressions save a baseline before making a
+//! change with `cargo bench -- --save-baseline before`, then compare against it afterwards with
+//! `cargo bench -- --baseline before`.
+
+use cell_map::{
+    filters::{Backend, InflationParams},
+    Bounds, CellMap, CellMapParams, Layer,
+};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use nalgebra::{Point2, Vector2};
+use serde::{Deserialize, Serialize};
+
+// ------------------------------------------------------------------------------------------------
+// UTILITIES
+// ------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, Layer, Serialize, Deserialize)]
+enum Layers {
+    Height,
+    Gradient,
+    Roughness,
+}
+
+/// Map sizes, in cells along each axis, to benchmark.
+const SIZES: [isize; 2] = [100, 500];
+
+/// Builds a square map with the given number of cells along each axis, and a non-trivial
+/// transform to the parent frame.
+fn build_map(size: isize) -> CellMap<Layers, f64> {
+    let mut map = CellMap::new(CellMapParams {
+        cell_size: Vector2::new(0.1, 0.1),
+        cell_bounds: Bounds::new((0, size), (0, size)).unwrap(),
+        position_in_parent: Vector2::new(1.0, -2.0),
+        rotation_in_parent_rad: 0.3,
+        ..Default::default()
+    });
+
+    map.iter_mut()
+        .indexed()
+        .for_each(|((_, idx), v)| *v = (idx.x as f64 * 0.1).sin() + (idx.y as f64 * 0.2).cos());
+
+    map
+}
+
+// ------------------------------------------------------------------------------------------------
+// BENCHMARKS
+// ------------------------------------------------------------------------------------------------
+
+fn iterators(c: &mut Criterion) {
+    let mut group = c.benchmark_group("iterators");
+
+    for &size in SIZES.iter() {
+        let mut map = build_map(size);
+
+        group.bench_with_input(BenchmarkId::new("iter", size), &size, |b, _| {
+            b.iter(|| map.iter().sum::<f64>())
+        });
+        group.bench_with_input(BenchmarkId::new("iter_indexed", size), &size, |b, _| {
+            b.iter(|| {
+                map.iter()
+                    .indexed()
+                    .map(|((_, idx), v)| idx.x as f64 * v)
+                    .sum::<f64>()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("iter_positioned", size), &size, |b, _| {
+            b.iter(|| {
+                map.iter()
+                    .positioned()
+                    .map(|((_, pos), v)| pos.x * v)
+                    .sum::<f64>()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("iter_mut", size), &size, |b, _| {
+            b.iter(|| map.iter_mut().for_each(|v| *v += 1.0))
+        });
+        group.bench_with_input(BenchmarkId::new("line_iter", size), &size, |b, _| {
+            let end = map.position(Point2::new(size as usize - 1, size as usize / 2));
+            let start = map.position(Point2::new(0, 0)).unwrap();
+            b.iter(|| {
+                map.line_iter(start, end.unwrap())
+                    .unwrap()
+                    .layer(Layers::Height)
+                    .sum::<f64>()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn windows(c: &mut Criterion) {
+    let mut group = c.benchmark_group("windows");
+
+    for &size in SIZES.iter() {
+        let mut map = build_map(size);
+        let semi_width = Vector2::new(2, 2);
+
+        group.bench_with_input(BenchmarkId::new("window_iter", size), &size, |b, _| {
+            b.iter(|| {
+                map.window_iter(semi_width)
+                    .unwrap()
+                    .layer(Layers::Height)
+                    .map(|w| w.sum())
+                    .sum::<f64>()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("window_map", size), &size, |b, _| {
+            b.iter(|| {
+                map.window_map(Layers::Height, Layers::Roughness, semi_width, |w| {
+                    w.std(0.0)
+                })
+                .unwrap()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("box_filter", size), &size, |b, _| {
+            b.iter(|| map.box_filter(Layers::Height, Layers::Gradient, semi_width))
+        });
+        group.bench_with_input(BenchmarkId::new("gaussian_filter", size), &size, |b, _| {
+            b.iter(|| {
+                map.gaussian_filter(Layers::Height, Layers::Gradient, 0.5)
+                    .unwrap()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("inflate", size), &size, |b, _| {
+            let params = InflationParams {
+                inscribed_radius: 0.3,
+                inflation_radius: 1.0,
+                cost_scaling_factor: 3.0,
+            };
+            b.iter(|| {
+                map.inflate(
+                    Layers::Height,
+                    Layers::Gradient,
+                    |h| h > 1.5,
+                    &params,
+                    &Backend::Cpu,
+                )
+                .unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn transforms(c: &mut Criterion) {
+    let mut group = c.benchmark_group("transforms");
+    let map = build_map(100);
+
+    group.bench_function("index", |b| {
+        b.iter(|| map.index(black_box(Point2::new(3.2, 1.7))))
+    });
+    group.bench_function("position", |b| {
+        b.iter(|| map.position(black_box(Point2::new(31, 17))))
+    });
+
+    group.finish();
+}
+
+fn serialisation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("serialisation");
+
+    for &size in SIZES.iter() {
+        let map = build_map(size);
+
+        group.bench_with_input(BenchmarkId::new("to_cell_map_file", size), &size, |b, _| {
+            b.iter(|| map.to_cell_map_file())
+        });
+        group.bench_with_input(BenchmarkId::new("clone", size), &size, |b, _| {
+            b.iter(|| map.clone())
+        });
+
+        let json = serde_json::to_vec(&map).unwrap();
+        group.bench_with_input(BenchmarkId::new("to_json", size), &size, |b, _| {
+            b.iter(|| serde_json::to_vec(&map).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("from_json", size), &size, |b, _| {
+            b.iter(|| serde_json::from_slice::<CellMap<Layers, f64>>(&json).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, iterators, windows, transforms, serialisation);
+criterion_main!(benches);
@@ -14,7 +14,7 @@ use syn::{parse_macro_input, DeriveInput};
 // DERIVES
 // ------------------------------------------------------------------------------------------------
 
-#[proc_macro_derive(Layer)]
+#[proc_macro_derive(Layer, attributes(layer))]
 pub fn derive_layer(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
@@ -53,6 +53,34 @@ pub fn derive_layer(input: TokenStream) -> TokenStream {
         }
     });
 
+    let var_attrs: Vec<_> = variants
+        .iter()
+        .map(|v| parse_layer_attrs(&v.attrs))
+        .collect();
+
+    // Map the varients into the match patterns we need for the groups function
+    let var_groups_patterns = variants.iter().zip(var_attrs.iter()).map(|(v, a)| {
+        let var_name = &v.ident;
+        let groups = &a.groups;
+
+        quote! {
+            #name::#var_name => &[#(#groups),*]
+        }
+    });
+
+    // Map the varients into the match patterns we need for the default_value function
+    let var_default_patterns = variants.iter().zip(var_attrs.iter()).map(|(v, a)| {
+        let var_name = &v.ident;
+        let default = match &a.default {
+            Some(d) => quote! { ::std::option::Option::Some(#d) },
+            None => quote! { ::std::option::Option::None },
+        };
+
+        quote! {
+            #name::#var_name => #default
+        }
+    });
+
     let first_var_name = &variants[0].ident;
 
     let num_variants = variants.len();
@@ -79,8 +107,184 @@ pub fn derive_layer(input: TokenStream) -> TokenStream {
             fn all() -> Vec<Self> {
                 vec![#(#var_all_patterns),*]
             }
+
+            fn groups(&self) -> &'static [&'static str] {
+                match self {
+                    #(#var_groups_patterns),*
+                }
+            }
+
+            fn default_value(&self) -> ::std::option::Option<f64> {
+                match self {
+                    #(#var_default_patterns),*
+                }
+            }
+        }
+    };
+
+    impled.into()
+}
+
+#[proc_macro_derive(CellView, attributes(cell_view))]
+pub fn derive_cell_view(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    // Check input is a struct with named fields
+    let fields = match input.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(f),
+            ..
+        }) => f.named,
+        _ => panic!("CellView can only be derived on structs with named fields"),
+    };
+
+    if fields.is_empty() {
+        panic!("CellView can't be derived on a struct with no fields");
+    }
+
+    // Get the type name and the layer type from the container attribute
+    let name = &input.ident;
+    let layer_type = parse_cell_view_path(&input.attrs, "layer").unwrap_or_else(|| {
+        panic!("CellView requires the layer type, for example #[cell_view(layer = MyLayer)]")
+    });
+
+    // All fields must have the same type, which is the cell type of the map
+    let cell_type = &fields[0].ty;
+
+    // Each field is read from the layer variant with the same name in UpperCamelCase, unless it's
+    // overriden with the variant attribute
+    let field_names: Vec<_> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+    let variants = fields.iter().map(|f| {
+        parse_cell_view_path(&f.attrs, "variant").unwrap_or_else(|| {
+            let ident = syn::Ident::new(
+                &to_upper_camel_case(&f.ident.as_ref().unwrap().to_string()),
+                f.ident.as_ref().unwrap().span(),
+            );
+            ident.into()
+        })
+    });
+    let indices = 0..fields.len();
+
+    let impled = quote! {
+        impl ::cell_map::CellView<#layer_type, #cell_type> for #name {
+            fn layers() -> Vec<#layer_type> {
+                vec![#(#layer_type::#variants),*]
+            }
+
+            fn from_values(values: &[#cell_type]) -> Self {
+                Self {
+                    #(#field_names: ::std::clone::Clone::clone(&values[#indices])),*
+                }
+            }
+
+            fn to_values(&self) -> Vec<#cell_type> {
+                vec![#(::std::clone::Clone::clone(&self.#field_names)),*]
+            }
         }
     };
 
     impled.into()
 }
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// The options given by the `#[layer(...)]` attributes of a variant.
+#[derive(Default)]
+struct LayerAttrs {
+    /// The groups given by `group = "name"` entries. A variant may be in many groups, either by
+    /// repeating the attribute or by listing many `group` entries in one attribute.
+    groups: Vec<String>,
+
+    /// The expression given by a `default = expr` entry.
+    default: Option<syn::Expr>,
+}
+
+/// Parses the `#[layer(...)]` attributes of a variant.
+fn parse_layer_attrs(attrs: &[syn::Attribute]) -> LayerAttrs {
+    let mut layer_attrs = LayerAttrs::default();
+
+    for attr in attrs.iter().filter(|a| a.path.is_ident("layer")) {
+        let parsed = attr.parse_args_with(|input: syn::parse::ParseStream| {
+            while !input.is_empty() {
+                let key: syn::Ident = input.parse()?;
+                input.parse::<syn::Token![=]>()?;
+
+                if key == "group" {
+                    layer_attrs
+                        .groups
+                        .push(input.parse::<syn::LitStr>()?.value());
+                } else if key == "default" {
+                    layer_attrs.default = Some(input.parse()?);
+                } else {
+                    return Err(syn::Error::new(key.span(), "unknown key"));
+                }
+
+                if !input.is_empty() {
+                    input.parse::<syn::Token![,]>()?;
+                }
+            }
+
+            Ok(())
+        });
+
+        if let Err(e) = parsed {
+            panic!(
+                "Invalid layer attribute ({}), expected group = \"name\" or default = value",
+                e
+            );
+        }
+    }
+
+    layer_attrs
+}
+
+/// Gets the path given by a `key = path` entry in the `#[cell_view(...)]` attributes.
+fn parse_cell_view_path(attrs: &[syn::Attribute], key: &str) -> Option<syn::Path> {
+    let mut found = None;
+
+    for attr in attrs.iter().filter(|a| a.path.is_ident("cell_view")) {
+        let parsed = attr.parse_args_with(|input: syn::parse::ParseStream| {
+            while !input.is_empty() {
+                let entry: syn::Ident = input.parse()?;
+                input.parse::<syn::Token![=]>()?;
+                let path: syn::Path = input.parse()?;
+
+                if entry == key {
+                    found = Some(path);
+                } else if entry != "layer" && entry != "variant" {
+                    return Err(syn::Error::new(entry.span(), "unknown key"));
+                }
+
+                if !input.is_empty() {
+                    input.parse::<syn::Token![,]>()?;
+                }
+            }
+
+            Ok(())
+        });
+
+        if let Err(e) = parsed {
+            panic!(
+                "Invalid cell_view attribute ({}), expected layer = Type or variant = Variant",
+                e
+            );
+        }
+    }
+
+    found
+}
+
+/// Converts a snake_case field name into the UpperCamelCase name of a layer variant.
+fn to_upper_camel_case(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
@@ -5,4 +5,7 @@ fn tests() {
     let t = trybuild::TestCases::new();
     t.pass("tests/layer-pass.rs");
     t.compile_fail("tests/layer-fail.rs");
+    t.compile_fail("tests/layer-group-fail.rs");
+    t.pass("tests/cell-view-pass.rs");
+    t.compile_fail("tests/cell-view-fail.rs");
 }
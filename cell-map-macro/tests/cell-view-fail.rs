@@ -0,0 +1,10 @@
+//! Tests that CellView requires the layer type
+
+use cell_map::CellView;
+
+#[derive(CellView)]
+struct Terrain {
+    height: f64,
+}
+
+fn main() {}
@@ -0,0 +1,35 @@
+//! Test that the CellView trait can be derived for structs
+
+use cell_map::{CellView, Layer};
+
+#[derive(Layer, Clone, Debug, PartialEq)]
+pub enum MyLayer {
+    Height,
+    Roughness,
+    TraversalCost,
+}
+
+#[derive(CellView, Debug, PartialEq)]
+#[cell_view(layer = MyLayer)]
+pub struct Terrain {
+    traversal_cost: f32,
+    #[cell_view(variant = Height)]
+    z: f32,
+}
+
+fn main() {
+    assert_eq!(
+        <Terrain as CellView<MyLayer, f32>>::layers(),
+        vec![MyLayer::TraversalCost, MyLayer::Height]
+    );
+
+    let terrain = Terrain::from_values(&[1.0, 2.0]);
+    assert_eq!(
+        terrain,
+        Terrain {
+            traversal_cost: 1.0,
+            z: 2.0
+        }
+    );
+    assert_eq!(terrain.to_values(), vec![1.0, 2.0]);
+}
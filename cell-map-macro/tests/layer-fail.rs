@@ -4,3 +4,5 @@ use cell_map::Layer;
 
 #[derive(Layer)]
 struct NotALayer;
+
+fn main() {}
@@ -0,0 +1,11 @@
+//! Tests that unknown layer attributes are rejected
+
+use cell_map::Layer;
+
+#[derive(Layer, Clone)]
+enum MyLayer {
+    #[layer(name = "height")]
+    Height,
+}
+
+fn main() {}
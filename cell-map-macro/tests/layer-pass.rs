@@ -7,3 +7,31 @@ pub enum MyLayer {
     Height,
     Gradient,
 }
+
+#[derive(Layer, Clone, Debug, PartialEq)]
+pub enum GroupedLayer {
+    #[layer(group = "terrain")]
+    Height,
+    #[layer(group = "terrain")]
+    #[layer(group = "planning")]
+    Gradient,
+    #[layer(group = "planning", group = "costs", default = 1.0)]
+    Cost,
+    #[layer(default = -f64::MAX)]
+    Other,
+}
+
+fn main() {
+    assert!(MyLayer::Height.groups().is_empty());
+
+    assert_eq!(GroupedLayer::Gradient.groups(), &["terrain", "planning"]);
+    assert_eq!(GroupedLayer::Cost.groups(), &["planning", "costs"]);
+    assert!(GroupedLayer::Other.groups().is_empty());
+    assert_eq!(GroupedLayer::Height.default_value(), None);
+    assert_eq!(GroupedLayer::Cost.default_value(), Some(1.0));
+    assert_eq!(GroupedLayer::Other.default_value(), Some(-f64::MAX));
+    assert_eq!(
+        GroupedLayer::in_group("planning"),
+        vec![GroupedLayer::Gradient, GroupedLayer::Cost]
+    );
+}
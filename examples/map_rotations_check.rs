@@ -12,6 +12,7 @@ enum Layers {
     Layer2,
 }
 
+#[cfg_attr(not(feature = "debug_maps"), allow(unused_variables))]
 fn main() {
     let translated = CellMap::<Layers, f64>::new(CellMapParams {
         cell_size: Vector2::new(1.0, 1.0),
@@ -36,7 +37,7 @@ fn main() {
         ..Default::default()
     });
 
-    #[cfg(all(feature = "debug_maps"))]
+    #[cfg(feature = "debug_maps")]
     {
         use cell_map::write_debug_map;
 
@@ -0,0 +1,208 @@
+//! Provides the [`GridAccess`] and [`GridAccessMut`] traits, which abstract over the map-like
+//! types in this crate so that algorithms can be written once and run on any of them.
+//!
+//! The traits are implemented by [`CellMap`], [`CellMapView`], [`CellMapViewMut`] and, with the
+//! `mmap` feature, [`MmapCellMap`](crate::MmapCellMap). A [`TileStore`](crate::TileStore)
+//! doesn't implement them, since reading from it may load tiles from disk and so needs mutable
+//! access, but the tiles it returns are [`CellMap`]s which do.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+
+use crate::{cell_map::Bounds, CellMap, CellMapView, CellMapViewMut, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// TRAITS
+// ------------------------------------------------------------------------------------------------
+
+/// Read access to a grid of cells with layers of type `L` and values of type `T`.
+pub trait GridAccess<L, T>
+where
+    L: Layer,
+{
+    /// Returns the size of the cells in the grid.
+    fn cell_size(&self) -> Vector2<f64>;
+
+    /// Returns the bounds of the grid.
+    fn cell_bounds(&self) -> Bounds;
+
+    /// Returns the cell index of the given parent-frame position, or `None` if the position is
+    /// outside the grid.
+    fn index(&self, position: Point2<f64>) -> Option<Point2<usize>>;
+
+    /// Returns the parent-frame position of the centre of the given cell, or `None` if the index
+    /// is outside the grid.
+    fn position(&self, index: Point2<usize>) -> Option<Point2<f64>>;
+
+    /// Returns a reference to the value at the given layer and index, or `None` if the index is
+    /// outside the grid.
+    fn get(&self, layer: L, index: Point2<usize>) -> Option<&T>;
+
+    /// Returns the number of cells in each direction of the grid.
+    fn num_cells(&self) -> Vector2<usize> {
+        self.cell_bounds().get_num_cells()
+    }
+
+    /// Returns a reference to the value at the given layer and parent-frame position, or `None`
+    /// if the position is outside the grid.
+    fn get_at(&self, layer: L, position: Point2<f64>) -> Option<&T> {
+        self.get(layer, self.index(position)?)
+    }
+}
+
+/// Write access to a grid of cells, in addition to the read access of [`GridAccess`].
+pub trait GridAccessMut<L, T>: GridAccess<L, T>
+where
+    L: Layer,
+{
+    /// Returns a mutable reference to the value at the given layer and index, or `None` if the
+    /// index is outside the grid.
+    fn get_mut(&mut self, layer: L, index: Point2<usize>) -> Option<&mut T>;
+
+    /// Sets the value at the given layer and index.
+    ///
+    /// Returns [`Error::IndexOutsideLayer`] if the index is outside the grid.
+    fn set(&mut self, layer: L, index: Point2<usize>, value: T) -> Result<(), Error> {
+        let layer_index = layer.to_index();
+        let bounds = self.cell_bounds();
+        *self.get_mut(layer, index).ok_or(Error::IndexOutsideLayer(
+            layer_index,
+            index,
+            bounds,
+        ))? = value;
+        Ok(())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+/// Implements [`GridAccess`] for a type by forwarding to its inherent methods of the same names.
+macro_rules! forward_grid_access {
+    ($ty:ty $(, $bound:path)?) => {
+        impl<L, T> GridAccess<L, T> for $ty
+        where
+            L: Layer,
+            $(T: $bound,)?
+        {
+            fn cell_size(&self) -> Vector2<f64> {
+                <$ty>::cell_size(self)
+            }
+
+            fn cell_bounds(&self) -> Bounds {
+                <$ty>::cell_bounds(self)
+            }
+
+            fn index(&self, position: Point2<f64>) -> Option<Point2<usize>> {
+                <$ty>::index(self, position)
+            }
+
+            fn position(&self, index: Point2<usize>) -> Option<Point2<f64>> {
+                <$ty>::position(self, index)
+            }
+
+            fn get(&self, layer: L, index: Point2<usize>) -> Option<&T> {
+                <$ty>::get(self, layer, index)
+            }
+
+            fn num_cells(&self) -> Vector2<usize> {
+                <$ty>::num_cells(self)
+            }
+        }
+    };
+}
+
+forward_grid_access!(CellMap<L, T>);
+forward_grid_access!(CellMapView<'_, L, T>);
+forward_grid_access!(CellMapViewMut<'_, L, T>);
+#[cfg(feature = "mmap")]
+forward_grid_access!(crate::MmapCellMap<L, T>, bytemuck::Pod);
+
+impl<L, T> GridAccessMut<L, T> for CellMap<L, T>
+where
+    L: Layer,
+{
+    fn get_mut(&mut self, layer: L, index: Point2<usize>) -> Option<&mut T> {
+        CellMap::get_mut(self, layer, index)
+    }
+
+    fn set(&mut self, layer: L, index: Point2<usize>, value: T) -> Result<(), Error> {
+        CellMap::set(self, layer, index, value)
+    }
+}
+
+impl<L, T> GridAccessMut<L, T> for CellMapViewMut<'_, L, T>
+where
+    L: Layer,
+{
+    fn get_mut(&mut self, layer: L, index: Point2<usize>) -> Option<&mut T> {
+        CellMapViewMut::get_mut(self, layer, index)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::TestLayers, CellMapParams};
+
+    /// Sums a layer of any grid, to check that algorithms can be written against the traits.
+    fn layer_sum<G: GridAccess<TestLayers, f64>>(grid: &G, layer: TestLayers) -> f64 {
+        let num_cells = grid.num_cells();
+        (0..num_cells.y)
+            .flat_map(|y| (0..num_cells.x).map(move |x| Point2::new(x, y)))
+            .filter_map(|i| grid.get(layer, i))
+            .sum()
+    }
+
+    /// Doubles a layer of any mutable grid.
+    fn double<G: GridAccessMut<TestLayers, f64>>(grid: &mut G, layer: TestLayers) {
+        let num_cells = grid.num_cells();
+        for y in 0..num_cells.y {
+            for x in 0..num_cells.x {
+                let index = Point2::new(x, y);
+                let value = *grid.get(layer, index).unwrap();
+                grid.set(layer, index, value * 2.0).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn grid_access() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 4), (0, 3)).unwrap(),
+                ..Default::default()
+            },
+            1.0,
+        );
+
+        assert_eq!(layer_sum(&map, TestLayers::Layer0), 12.0);
+        assert_eq!(
+            GridAccess::get_at(&map, TestLayers::Layer0, Point2::new(1.5, 2.5)),
+            Some(&1.0)
+        );
+        assert!(GridAccessMut::set(&mut map, TestLayers::Layer0, Point2::new(4, 0), 1.0).is_err());
+
+        double(&mut map, TestLayers::Layer1);
+        assert_eq!(layer_sum(&map, TestLayers::Layer1), 24.0);
+
+        let view = map.view_as(|layer: &TestLayers| *layer);
+        assert_eq!(layer_sum(&view, TestLayers::Layer1), 24.0);
+
+        // Mutable views only cover their region
+        let regions = [Bounds::new((0, 2), (0, 3)).unwrap()];
+        let mut views = map.split_regions_mut(&regions).unwrap();
+        assert_eq!(views[0].num_cells(), Vector2::new(2, 3));
+        double(&mut views[0], TestLayers::Layer1);
+        drop(views);
+        assert_eq!(layer_sum(&map, TestLayers::Layer1), 36.0);
+    }
+}
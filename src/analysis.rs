@@ -0,0 +1,571 @@
+//! Provides terrain analysis methods which derive hazard metrics, curvatures and basins from height
+//! layers.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, VecDeque},
+};
+
+use nalgebra::{Matrix3, Point2, Point3, Vector3};
+use ndarray::{s, Array2, ArrayView2};
+
+use crate::{
+    cell_map::{cells_within, Bounds},
+    potential::Entry,
+    CellMap, Error, Layer,
+};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A plane fitted to a region of a height layer by [`CellMap::fit_plane()`].
+///
+/// The plane is the set of points $\mathbf{p}$ in the parent frame, with the height as the `z`
+/// coordinate, which satisfy $\mathbf{n} \cdot \mathbf{p} = d$, where $\mathbf{n}$ is `normal`
+/// and $d$ is `offset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaneFit {
+    /// The unit normal of the plane, which always points up (has a non-negative `z` component).
+    pub normal: Vector3<f64>,
+
+    /// The signed distance of the plane from the origin along the normal.
+    pub offset: f64,
+
+    /// The root mean square perpendicular distance of the cells from the plane.
+    pub residual: f64,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Computes the step height of `height_layer`, the largest absolute height difference between
+    /// each cell and the cells whose centres are within `radius` of it, writing the result into
+    /// `dst_layer`.
+    ///
+    /// The maximum and minimum heights in each disc are found with sliding window extrema along
+    /// each row, so the cost per cell grows linearly with the radius in cells rather than with its
+    /// square. `NaN` cells in the disc are ignored, and cells which are `NaN` themselves have `NaN`
+    /// step height.
+    pub fn step_height(&mut self, height_layer: L, dst_layer: L, radius: f64) {
+        self[dst_layer] = self.compute_step_height(height_layer, radius);
+    }
+
+    /// Returns the step height of `height_layer` for the given `radius`, see
+    /// [`CellMap::step_height()`].
+    pub(crate) fn compute_step_height(&self, height_layer: L, radius: f64) -> Array2<f64> {
+        let cell_size = self.cell_size();
+        let data = self[height_layer].view();
+        let (rows, cols) = data.dim();
+
+        // Half width in cells of the disc's row at each y offset
+        let semi_height = self.radius_in_cells(radius).y;
+        let half_widths: Vec<usize> = (0..=semi_height)
+            .map(|dy| {
+                let dy = dy as f64 * cell_size.y;
+                cells_within((radius * radius - dy * dy).max(0.0).sqrt(), cell_size.x)
+            })
+            .collect();
+
+        // Sliding extrema along rows for each distinct half width
+        let mut row_extrema: HashMap<usize, (Array2<f64>, Array2<f64>)> = HashMap::new();
+        for &w in &half_widths {
+            row_extrema.entry(w).or_insert_with(|| {
+                (
+                    sliding_row_max(data, w, 1.0),
+                    sliding_row_max(data, w, -1.0),
+                )
+            });
+        }
+
+        Array2::from_shape_fn((rows, cols), |(y, x)| {
+            let height = data[(y, x)];
+            if height.is_nan() {
+                return f64::NAN;
+            }
+
+            let (mut max, mut min) = (height, height);
+            for (dy, w) in half_widths.iter().enumerate() {
+                let (row_max, row_min) = &row_extrema[w];
+
+                for row in [y.checked_sub(dy), Some(y + dy).filter(|&r| r < rows)]
+                    .iter()
+                    .flatten()
+                {
+                    max = max.max(row_max[(*row, x)]);
+                    min = min.min(-row_min[(*row, x)]);
+                }
+            }
+
+            (max - height).max(height - min)
+        })
+    }
+
+    /// Segments `height_layer` into basins with a watershed, writing the label of each cell's
+    /// basin into `dst_layer` and returning the number of basins.
+    ///
+    /// Each regional minimum, a connected plateau of cells with no lower neighbours, seeds a basin
+    /// labelled from `0.0` upwards, and basins are grown by flooding outwards from the lowest cells
+    /// first, so each cell joins the basin that water falling on it would drain into. Cells are
+    /// 8-connected, and `NaN` cells are not part of any basin and are labelled `NaN`.
+    pub fn watershed(&mut self, height_layer: L, dst_layer: L) -> usize {
+        let (labels, num_basins) = watershed_labels(self[height_layer].view());
+        self[dst_layer] = labels.mapv(|l| l.map_or(f64::NAN, |l| l as f64));
+        num_basins
+    }
+
+    /// Computes the profile curvature of `height_layer`, the curvature of the surface along the
+    /// direction of steepest slope, writing the result into `dst_layer`.
+    ///
+    /// Using the derivatives described in [`CellMap::plan_curvature()`] the profile curvature is
+    ///
+    /// $$ k_v = -\frac{p^2 r + 2pqs + q^2 t}{(p^2 + q^2) (1 + p^2 + q^2)^{3/2}} $$
+    ///
+    /// in inverse parent-frame units, which is negative where the slope gets steeper downhill, such
+    /// as the brink of a cliff, and where flow accelerates. Flat cells have zero curvature.
+    pub fn profile_curvature(&mut self, height_layer: L, dst_layer: L) {
+        self[dst_layer] = self.curvature(height_layer, |p, q, r, s, t| {
+            let grad_sq = p * p + q * q;
+            -(p * p * r + 2.0 * p * q * s + q * q * t) / (grad_sq * (1.0 + grad_sq).powf(1.5))
+        });
+    }
+
+    /// Computes the plan curvature of `height_layer`, the curvature of the contour line through
+    /// each cell, writing the result into `dst_layer`.
+    ///
+    /// The first derivatives $p = \partial z / \partial x$ and $q = \partial z / \partial y$, and
+    /// second derivatives $r = \partial^2 z / \partial x^2$, $s = \partial^2 z / \partial x
+    /// \partial y$ and $t = \partial^2 z / \partial y^2$ are found by finite differences over
+    /// the 3x3 window around each cell, scaled by the cell size, and the plan curvature is
+    ///
+    /// $$ k_p = -\frac{q^2 r - 2pqs + p^2 t}{(p^2 + q^2)^{3/2}} $$
+    ///
+    /// in inverse parent-frame units, which is negative in hollows where flow converges and
+    /// positive on spurs where it diverges. Flat cells have zero curvature. Cells on the edge of
+    /// the map, or with a `NaN` cell in their window, are `NaN`.
+    pub fn plan_curvature(&mut self, height_layer: L, dst_layer: L) {
+        self[dst_layer] = self.curvature(height_layer, |p, q, r, s, t| {
+            let grad_sq = p * p + q * q;
+            -(q * q * r - 2.0 * p * q * s + p * p * t) / grad_sq.powf(1.5)
+        });
+    }
+
+    /// Evaluates `func` with the derivatives `p`, `q`, `r`, `s` and `t` of `height_layer` at each
+    /// cell, as described in [`CellMap::plan_curvature()`], returning zero for flat cells.
+    fn curvature<F>(&self, height_layer: L, func: F) -> Array2<f64>
+    where
+        F: Fn(f64, f64, f64, f64, f64) -> f64,
+    {
+        let data = self[height_layer].view();
+        let (rows, cols) = data.dim();
+        let h = self.cell_size();
+
+        Array2::from_shape_fn((rows, cols), |(y, x)| {
+            if x == 0 || y == 0 || x + 1 == cols || y + 1 == rows {
+                return f64::NAN;
+            }
+            let z = |dx: isize, dy: isize| {
+                data[((y as isize + dy) as usize, (x as isize + dx) as usize)]
+            };
+
+            let p = (z(1, 0) - z(-1, 0)) / (2.0 * h.x);
+            let q = (z(0, 1) - z(0, -1)) / (2.0 * h.y);
+            let r = (z(1, 0) - 2.0 * z(0, 0) + z(-1, 0)) / (h.x * h.x);
+            let t = (z(0, 1) - 2.0 * z(0, 0) + z(0, -1)) / (h.y * h.y);
+            let s = (z(1, 1) - z(-1, 1) - z(1, -1) + z(-1, -1)) / (4.0 * h.x * h.y);
+
+            if [p, q, r, s, t].iter().any(|v| v.is_nan()) {
+                f64::NAN
+            } else if p == 0.0 && q == 0.0 {
+                0.0
+            } else {
+                func(p, q, r, s, t)
+            }
+        })
+    }
+
+    /// Fits a plane to the valid (non-`NaN`) cells of `layer` within `region`, which must lie
+    /// entirely inside the map.
+    ///
+    /// The plane is found with a total least squares fit to the centre of each cell in the parent
+    /// frame, with the cell's value as its height, so the residual measures perpendicular rather
+    /// than vertical distance. At least three valid cells are needed. If all valid cells are in a
+    /// line the fit isn't unique, and any plane containing the line may be returned.
+    pub fn fit_plane(&self, layer: L, region: Bounds) -> Result<PlaneFit, Error> {
+        if !region.is_valid() {
+            return Err(Error::InvalidBounds(region));
+        }
+
+        if self.metadata.cell_bounds.intersect(&region) != Some(region) {
+            return Err(Error::BoundsOutsideMap(region, self.metadata.cell_bounds));
+        }
+
+        let slice = self
+            .metadata
+            .cell_bounds
+            .get_slice_of_other(&region)
+            .ok_or(Error::BoundsOutsideMap(region, self.metadata.cell_bounds))?;
+        let data = self
+            .try_layer(layer)?
+            .slice(s![slice.y.0..slice.y.1, slice.x.0..slice.x.1]);
+
+        let points: Vec<Point3<f64>> = data
+            .indexed_iter()
+            .filter(|(_, h)| !h.is_nan())
+            .map(|((y, x), &h)| {
+                let p = self.position_unchecked(Point2::new(x + slice.x.0, y + slice.y.0));
+                Point3::new(p.x, p.y, h)
+            })
+            .collect();
+
+        if points.len() < 3 {
+            return Err(Error::NotEnoughValidCells(3, points.len()));
+        }
+
+        let n = points.len() as f64;
+        let centroid = points
+            .iter()
+            .fold(Vector3::zeros(), |acc, p| acc + p.coords)
+            / n;
+        let covariance = points.iter().fold(Matrix3::zeros(), |acc, p| {
+            let d = p.coords - centroid;
+            acc + d * d.transpose()
+        }) / n;
+
+        // The normal is the direction of least variance
+        let eigen = covariance.symmetric_eigen();
+        let min_index = eigen.eigenvalues.imin();
+        let min_value = eigen.eigenvalues[min_index];
+        let mut normal: Vector3<f64> = eigen.eigenvectors.column(min_index).into();
+        if normal.z < 0.0 {
+            normal = -normal;
+        }
+
+        Ok(PlaneFit {
+            normal,
+            offset: normal.dot(&centroid),
+            residual: min_value.max(0.0).sqrt(),
+        })
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Labels the basins of `data` with a priority-flood watershed, returning the label of each cell
+/// and the number of basins.
+fn watershed_labels(data: ArrayView2<'_, f64>) -> (Array2<Option<usize>>, usize) {
+    let (rows, cols) = data.dim();
+    let neighbours = |(y, x): (usize, usize)| {
+        (y.saturating_sub(1)..(y + 2).min(rows))
+            .flat_map(move |ny| (x.saturating_sub(1)..(x + 2).min(cols)).map(move |nx| (ny, nx)))
+            .filter(move |&n| n != (y, x) && !data[n].is_nan())
+    };
+
+    let mut labels = Array2::from_elem((rows, cols), None);
+    let mut visited = Array2::from_elem((rows, cols), false);
+    let mut queue = BinaryHeap::new();
+    let mut num_basins = 0;
+
+    // Find the regional minima by searching each plateau of equal height
+    for ((y, x), &height) in data.indexed_iter() {
+        if visited[(y, x)] || height.is_nan() {
+            continue;
+        }
+
+        let mut plateau = vec![(y, x)];
+        let mut is_minimum = true;
+        visited[(y, x)] = true;
+        let mut i = 0;
+
+        while i < plateau.len() {
+            for n in neighbours(plateau[i]) {
+                if data[n] < height {
+                    is_minimum = false;
+                } else if data[n] == height && !visited[n] {
+                    visited[n] = true;
+                    plateau.push(n);
+                }
+            }
+            i += 1;
+        }
+
+        if is_minimum {
+            for &index in &plateau {
+                labels[index] = Some(num_basins);
+                queue.push(Reverse(Entry {
+                    value: height,
+                    index,
+                }));
+            }
+            num_basins += 1;
+        }
+    }
+
+    // Flood outwards from the minima, lowest cells first
+    while let Some(Reverse(Entry { value, index })) = queue.pop() {
+        let label = labels[index];
+
+        for n in neighbours(index) {
+            if labels[n].is_none() {
+                labels[n] = label;
+                queue.push(Reverse(Entry {
+                    value: value.max(data[n]),
+                    index: n,
+                }));
+            }
+        }
+    }
+
+    (labels, num_basins)
+}
+
+/// Finds the maximum of `sign * value` in a window of `semi_width` cells either side of each cell
+/// along its row, ignoring `NaN` values, using a monotonic queue. Windows with no valid values are
+/// `-inf`. Passing a `sign` of `-1.0` gives the negated minimum.
+fn sliding_row_max(data: ArrayView2<'_, f64>, semi_width: usize, sign: f64) -> Array2<f64> {
+    let (rows, cols) = data.dim();
+    let mut out = Array2::from_elem((rows, cols), f64::NEG_INFINITY);
+    let mut queue: VecDeque<(usize, f64)> = VecDeque::new();
+
+    for y in 0..rows {
+        queue.clear();
+        let mut next = 0;
+
+        for x in 0..cols {
+            // Push values up to the right edge of the window, keeping the queue decreasing
+            while next < cols && next <= x + semi_width {
+                let v = sign * data[(y, next)];
+                if !v.is_nan() {
+                    while queue.back().is_some_and(|&(_, b)| b <= v) {
+                        queue.pop_back();
+                    }
+                    queue.push_back((next, v));
+                }
+                next += 1;
+            }
+
+            // Drop values which have left the window on the left
+            while queue.front().is_some_and(|&(i, _)| i + semi_width < x) {
+                queue.pop_front();
+            }
+
+            if let Some(&(_, v)) = queue.front() {
+                out[(y, x)] = v;
+            }
+        }
+    }
+
+    out
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point2, Vector2};
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn step_height() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 12), (0, 9)).unwrap(),
+                cell_size: Vector2::new(0.1, 0.2),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // Pseudo-random heights with a few holes
+        for ((_, index), height) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            *height = ((index.x * 7 + index.y * 13) % 11) as f64 * 0.1;
+        }
+        map[(TestLayers::Layer0, Point2::new(3, 4))] = f64::NAN;
+        map[(TestLayers::Layer0, Point2::new(7, 1))] = f64::NAN;
+
+        let radius = 0.45;
+        map.step_height(TestLayers::Layer0, TestLayers::Layer1, radius);
+
+        // Compare against a brute force search of the disc around each cell
+        for ((_, index), &height) in map.iter().layer(TestLayers::Layer0).indexed() {
+            let step = map[(TestLayers::Layer1, index)];
+            if height.is_nan() {
+                assert!(step.is_nan());
+                continue;
+            }
+
+            let centre = map.position(index).unwrap();
+            let expected = map
+                .iter()
+                .layer(TestLayers::Layer0)
+                .indexed()
+                .filter(|((_, other), v)| {
+                    !v.is_nan() && (map.position(*other).unwrap() - centre).norm() <= radius
+                })
+                .map(|(_, v)| (v - height).abs())
+                .fold(0.0, f64::max);
+
+            assert!((step - expected).abs() < 1e-12, "{} at {}", step, index);
+        }
+    }
+
+    #[test]
+    fn sliding_max() {
+        let data = ndarray::arr2(&[[1.0, 3.0, f64::NAN, 2.0, 0.0, 5.0]]);
+
+        assert_eq!(
+            sliding_row_max(data.view(), 1, 1.0),
+            ndarray::arr2(&[[3.0, 3.0, 3.0, 2.0, 5.0, 5.0]])
+        );
+        assert_eq!(
+            sliding_row_max(data.view(), 1, -1.0),
+            ndarray::arr2(&[[-1.0, -1.0, -2.0, 0.0, 0.0, 0.0]])
+        );
+        assert_eq!(
+            sliding_row_max(data.view(), 0, 1.0)[(0, 2)],
+            f64::NEG_INFINITY
+        );
+    }
+
+    #[test]
+    fn watershed() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 16), (0, 5)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // Two valleys along y, at x = 3 and x = 12, with the ridge between x = 7 and x = 8
+        for ((_, index), height) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            let x = index.x as f64;
+            *height = (x - 3.0).abs().min((x - 12.0).abs());
+        }
+        map[(TestLayers::Layer0, Point2::new(0, 0))] = f64::NAN;
+
+        assert_eq!(map.watershed(TestLayers::Layer0, TestLayers::Layer1), 2);
+        for ((_, index), &label) in map.iter().layer(TestLayers::Layer1).indexed() {
+            if index == Point2::new(0, 0) {
+                assert!(label.is_nan());
+            } else if index.x <= 7 {
+                assert_eq!(label, 0.0);
+            } else {
+                assert_eq!(label, 1.0);
+            }
+        }
+
+        // A flat layer is a single basin
+        assert_eq!(map.watershed(TestLayers::Layer2, TestLayers::Layer1), 1);
+    }
+
+    #[test]
+    fn curvature() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 7), (0, 5)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.25),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // A bowl z = (x^2 + y^2) / 2 about the cell at (1, 2), whose derivatives are exact with
+        // central differences
+        let centre = map.position(Point2::new(1, 2)).unwrap();
+        let positions: Vec<_> = map.iter().indexed().map(|((_, i), _)| i).collect();
+        for &index in &positions {
+            let d = map.position(index).unwrap() - centre;
+            map[(TestLayers::Layer0, index)] = d.norm_squared() / 2.0;
+        }
+        map[(TestLayers::Layer0, Point2::new(5, 3))] = f64::NAN;
+
+        map.plan_curvature(TestLayers::Layer0, TestLayers::Layer1);
+        map.profile_curvature(TestLayers::Layer0, TestLayers::Layer2);
+
+        // At distance d from the bottom, the contours are circles of radius d and the profile is
+        // the parabola z = d^2 / 2
+        for &index in &[Point2::new(3, 2), Point2::new(2, 3), Point2::new(4, 1)] {
+            let d = (map.position(index).unwrap() - centre).norm();
+            assert_f64_eq!(map[(TestLayers::Layer1, index)], -1.0 / d, 1e-9);
+            assert_f64_eq!(
+                map[(TestLayers::Layer2, index)],
+                -1.0 / (1.0 + d * d).powf(1.5),
+                1e-9
+            );
+        }
+
+        // The bottom of the bowl is flat, and edges and cells next to NaNs are NaN
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(1, 2))], 0.0);
+        assert!(map[(TestLayers::Layer1, Point2::new(0, 2))].is_nan());
+        assert!(map[(TestLayers::Layer2, Point2::new(4, 4))].is_nan());
+        assert!(map[(TestLayers::Layer2, Point2::new(4, 3))].is_nan());
+    }
+
+    #[test]
+    fn fit_plane() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-5, 5), (-5, 5)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            f64::NAN,
+        );
+
+        // The plane z = 0.2x - 0.1y + 1 inside the region, with a NaN hole
+        let region = Bounds::new((-2, 3), (0, 4)).unwrap();
+        for x in 0..10 {
+            for y in 0..10 {
+                let index = Point2::new(x, y);
+                let p = map.position(index).unwrap();
+                map[(TestLayers::Layer0, index)] = 0.2 * p.x - 0.1 * p.y + 1.0;
+            }
+        }
+        map[(TestLayers::Layer0, Point2::new(4, 6))] = f64::NAN;
+
+        let fit = map.fit_plane(TestLayers::Layer0, region).unwrap();
+        let expected = Vector3::new(-0.2, 0.1, 1.0).normalize();
+        assert!((fit.normal - expected).norm() < 1e-9);
+        assert!((fit.offset - expected.z).abs() < 1e-9);
+        assert!(fit.residual < 1e-6);
+
+        // Alternating heights give a flat plane with a residual of the alternation's amplitude
+        for ((_, index), height) in map.iter_mut().layer(TestLayers::Layer1).indexed() {
+            *height = if (index.x + index.y) % 2 == 0 {
+                0.1
+            } else {
+                -0.1
+            };
+        }
+        let fit = map
+            .fit_plane(TestLayers::Layer1, Bounds::new((-2, 2), (0, 4)).unwrap())
+            .unwrap();
+        assert!((fit.normal - Vector3::z()).norm() < 1e-9);
+        assert!((fit.residual - 0.1).abs() < 1e-9);
+
+        assert!(matches!(
+            map.fit_plane(TestLayers::Layer2, region),
+            Err(Error::NotEnoughValidCells(3, 0))
+        ));
+        assert!(matches!(
+            map.fit_plane(TestLayers::Layer0, Bounds::new((0, 6), (0, 1)).unwrap()),
+            Err(Error::BoundsOutsideMap(_, _))
+        ));
+    }
+}
@@ -0,0 +1,196 @@
+//! Provides area-weighted queries over arbitrary polygons, such as the fraction of a science
+//! target's footprint which has been observed.
+//!
+//! Cells which are only partly inside a polygon are weighted by the area of the cell inside it,
+//! found by clipping the polygon against the cell, so results don't depend on whether cell
+//! centres happen to fall inside the polygon.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+
+use crate::{CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+{
+    /// Returns the fraction of the area of `polygon` which is covered by cells of `layer` for
+    /// which `predicate` returns `true`.
+    ///
+    /// `polygon` is a ring of points in the parent frame, which is implicitly closed and may be
+    /// concave but must not intersect itself. Parts of the polygon outside the map count as not
+    /// covered. Returns `None` if the polygon has no area.
+    pub fn area_fraction<F>(&self, layer: L, polygon: &[Point2<f64>], predicate: F) -> Option<f64>
+    where
+        F: Fn(&T) -> bool,
+    {
+        // Work in the map frame relative to the first cell, where cell (x, y) is the unit square
+        // with its lowest corner at (x, y). Since the transform is affine the ratio of areas is
+        // the same as in the parent frame.
+        let bounds = self.cell_bounds();
+        let offset = Vector2::new(bounds.x.0 as f64, bounds.y.0 as f64);
+        let to_parent = self.to_parent();
+        let ring: Vec<Point2<f64>> = polygon
+            .iter()
+            .map(|p| to_parent.inverse_transform_point(p) - offset)
+            .collect();
+
+        let total = signed_area(&ring).abs();
+        if total == 0.0 || !total.is_finite() {
+            return None;
+        }
+
+        // Only cells overlapping the polygon's bounding box can be covered
+        let (min, max) = ring.iter().fold(
+            (
+                Point2::new(f64::INFINITY, f64::INFINITY),
+                Point2::new(f64::NEG_INFINITY, f64::NEG_INFINITY),
+            ),
+            |(min, max), p| (min.inf(p), max.sup(p)),
+        );
+        let num_cells = self.num_cells();
+        let range = |min: f64, max: f64, num: usize| {
+            (min.floor().max(0.0) as usize)..(max.ceil().max(0.0) as usize).min(num)
+        };
+
+        let data = &self[layer];
+        let mut covered = 0.0;
+
+        for y in range(min.y, max.y, num_cells.y) {
+            for x in range(min.x, max.x, num_cells.x) {
+                if predicate(&data[(y, x)]) {
+                    covered += signed_area(&clip_to_cell(&ring, x as f64, y as f64)).abs();
+                }
+            }
+        }
+
+        Some(covered / total)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Returns the signed area of the given implicitly closed ring, positive if it's anticlockwise.
+fn signed_area(ring: &[Point2<f64>]) -> f64 {
+    ring.iter()
+        .zip(ring.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum::<f64>()
+        / 2.0
+}
+
+/// Clips `ring` to the unit square with its lowest corner at `(x, y)`, using Sutherland-Hodgman
+/// clipping.
+///
+/// The result of clipping a concave ring may contain zero-width slivers along the square's edges,
+/// but these don't change its area.
+fn clip_to_cell(ring: &[Point2<f64>], x: f64, y: f64) -> Vec<Point2<f64>> {
+    [
+        (0, x, true),
+        (0, x + 1.0, false),
+        (1, y, true),
+        (1, y + 1.0, false),
+    ]
+    .iter()
+    .fold(ring.to_vec(), |ring, &(axis, bound, keep_above)| {
+        clip_half_plane(&ring, axis, bound, keep_above)
+    })
+}
+
+/// Clips `ring` to the half plane of points whose coordinate along `axis` is above `bound` if
+/// `keep_above` is `true`, or below it otherwise.
+fn clip_half_plane(
+    ring: &[Point2<f64>],
+    axis: usize,
+    bound: f64,
+    keep_above: bool,
+) -> Vec<Point2<f64>> {
+    let inside = |p: &Point2<f64>| (p[axis] >= bound) == keep_above || p[axis] == bound;
+    let mut clipped = Vec::with_capacity(ring.len() + 2);
+
+    for (a, b) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+        if inside(a) {
+            clipped.push(*a);
+        }
+
+        if inside(a) != inside(b) {
+            let t = (bound - a[axis]) / (b[axis] - a[axis]);
+            clipped.push(a + (b - a) * t);
+        }
+    }
+
+    clipped
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn area_fraction() {
+        let mut map = CellMap::<TestLayers, bool>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 4), (0, 4)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            true,
+        );
+        for y in 0..4 {
+            for x in 0..2 {
+                map[(TestLayers::Layer0, Point2::new(x, y))] = false;
+            }
+        }
+        let fraction = |polygon: &[(f64, f64)]| {
+            let polygon: Vec<_> = polygon.iter().map(|&(x, y)| Point2::new(x, y)).collect();
+            map.area_fraction(TestLayers::Layer0, &polygon, |&v| v)
+        };
+        let close = |a: Option<f64>, b: f64| (a.unwrap() - b).abs() < 1e-12;
+
+        // A square straddling the boundary between the uncovered and covered halves
+        let square = [(0.25, 0.25), (1.25, 0.25), (1.25, 1.25), (0.25, 1.25)];
+        assert!(close(fraction(&square), 0.25));
+
+        // Orientation doesn't matter
+        let reversed: Vec<_> = square.iter().rev().cloned().collect();
+        assert!(close(fraction(&reversed), 0.25));
+
+        // A triangle with one corner in the covered half
+        assert!(close(fraction(&[(0.0, 0.0), (2.0, 0.0), (0.0, 2.0)]), 0.25));
+
+        // A concave L shape, covered only in its lower arm
+        let l_shape = [
+            (0.5, 0.5),
+            (1.5, 0.5),
+            (1.5, 1.0),
+            (1.0, 1.0),
+            (1.0, 2.0),
+            (0.5, 2.0),
+        ];
+        assert!(close(fraction(&l_shape), 0.25));
+
+        // Parts outside the map aren't covered
+        assert!(close(
+            fraction(&[(1.0, 1.0), (3.0, 1.0), (3.0, 3.0), (1.0, 3.0)]),
+            0.25
+        ));
+
+        // Degenerate polygons have no area
+        assert_eq!(fraction(&[(0.0, 0.0), (1.0, 1.0)]), None);
+        assert_eq!(fraction(&[]), None);
+    }
+}
@@ -0,0 +1,199 @@
+//! Provides the [`AtomicLayer`] type, a single layer of atomic counters which can be updated from
+//! many threads at once without locking.
+//!
+//! This is intended for accumulating hit counts from several sensor threads, which would otherwise
+//! have to lock the whole map for every update. Once the counts are accumulated they can be moved
+//! into a layer of a normal [`CellMap`] with [`AtomicLayer::take_into()`].
+//!
+//! ```
+//! use cell_map::{atomic::AtomicLayer, Bounds, CellMap, CellMapParams, Layer};
+//! use nalgebra::Point2;
+//!
+//! #[derive(Layer, Clone, Debug)]
+//! enum MyLayer {
+//!     Hits,
+//! }
+//!
+//! let params = CellMapParams {
+//!     cell_bounds: Bounds::new((0, 10), (0, 10)).unwrap(),
+//!     ..Default::default()
+//! };
+//! let hits = AtomicLayer::new(params);
+//!
+//! std::thread::scope(|scope| {
+//!     for _ in 0..4 {
+//!         scope.spawn(|| hits.fetch_add_position(Point2::new(2.5, 3.5), 1));
+//!     }
+//! });
+//!
+//! let mut map = CellMap::<MyLayer, u32>::new_from_elem(params, 0);
+//! hits.take_into(&mut map, MyLayer::Hits).unwrap();
+//! assert_eq!(map[(MyLayer::Hits, Point2::new(2, 3))], 4);
+//! ```
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use nalgebra::{Point2, Vector2};
+use ndarray::Array2;
+
+use crate::{
+    cell_map::Bounds, extensions::Point2Ext, map_metadata::CellMapMetadata, CellMap, CellMapParams,
+    Error, Layer,
+};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A single layer of [`AtomicU32`] counters, with the same shape and position as a [`CellMap`]
+/// built from the same [`CellMapParams`].
+///
+/// All updates take `&self`, so the layer can be shared between threads by reference or in an
+/// [`Arc`](std::sync::Arc). Updates use relaxed ordering, since each counter is independent.
+#[derive(Debug)]
+pub struct AtomicLayer {
+    data: Array2<AtomicU32>,
+    metadata: CellMapMetadata,
+    params: CellMapParams,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl AtomicLayer {
+    /// Creates a new layer with the given parameters, with every counter set to zero.
+    pub fn new(params: CellMapParams) -> Self {
+        Self {
+            data: Array2::from_shape_simple_fn(params.cell_bounds.get_shape(), || {
+                AtomicU32::new(0)
+            }),
+            metadata: params.into(),
+            params,
+        }
+    }
+
+    /// Returns the number of cells in each direction of the layer.
+    pub fn num_cells(&self) -> Vector2<usize> {
+        self.metadata.num_cells
+    }
+
+    /// Returns the bounds of the layer.
+    pub fn cell_bounds(&self) -> Bounds {
+        self.metadata.cell_bounds
+    }
+
+    /// Returns the parameters used to build this layer.
+    pub fn params(&self) -> CellMapParams {
+        self.params
+    }
+
+    /// Returns the current value of the counter at the given index, or `None` if the index is
+    /// outside the layer.
+    pub fn load(&self, index: Point2<usize>) -> Option<u32> {
+        self.data
+            .get(index.as_array2_index())
+            .map(|c| c.load(Ordering::Relaxed))
+    }
+
+    /// Adds `value` to the counter at the given index, wrapping on overflow, and returns the
+    /// previous value, or `None` if the index is outside the layer.
+    pub fn fetch_add(&self, index: Point2<usize>, value: u32) -> Option<u32> {
+        self.data
+            .get(index.as_array2_index())
+            .map(|c| c.fetch_add(value, Ordering::Relaxed))
+    }
+
+    /// Adds `value` to the counter of the cell containing the given parent-frame position, and
+    /// returns the previous value, or `None` if the position is outside the layer.
+    pub fn fetch_add_position(&self, position: Point2<f64>, value: u32) -> Option<u32> {
+        self.fetch_add(self.metadata.index(position)?, value)
+    }
+
+    /// Returns a copy of the current value of every counter.
+    ///
+    /// Counters which are updated while the snapshot is being taken may or may not include the
+    /// update.
+    pub fn snapshot(&self) -> Array2<u32> {
+        self.data.map(|c| c.load(Ordering::Relaxed))
+    }
+
+    /// Resets every counter to zero, returning the values they held.
+    ///
+    /// Each counter is swapped individually, so no update made during the reset is lost, it will
+    /// either be included in the returned values or remain in the layer.
+    pub fn take(&self) -> Array2<u32> {
+        self.data.map(|c| c.swap(0, Ordering::Relaxed))
+    }
+
+    /// Resets every counter to zero, adding the values they held to `layer` of `map`, saturating
+    /// on overflow.
+    ///
+    /// The map must have the same shape as this layer, otherwise [`Error::LayerWrongShape`] is
+    /// returned and the counters are unchanged.
+    pub fn take_into<L: Layer>(&self, map: &mut CellMap<L, u32>, layer: L) -> Result<(), Error> {
+        let shape = self.data.dim();
+        let map_shape = map.cell_bounds().get_shape();
+        if shape != map_shape {
+            return Err(Error::LayerWrongShape(shape, map_shape));
+        }
+
+        map[layer].zip_mut_with(&self.data, |v, c| {
+            *v = v.saturating_add(c.swap(0, Ordering::Relaxed))
+        });
+
+        Ok(())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestLayers;
+
+    #[test]
+    fn concurrent_counts() {
+        let params = CellMapParams {
+            cell_bounds: Bounds::new((-2, 2), (0, 3)).unwrap(),
+            ..Default::default()
+        };
+        let hits = AtomicLayer::new(params);
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        hits.fetch_add(Point2::new(1, 2), 1).unwrap();
+                        hits.fetch_add_position(Point2::new(1.5, 0.5), 2).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(hits.load(Point2::new(1, 2)), Some(800));
+        assert_eq!(hits.load(Point2::new(3, 0)), Some(1600));
+        assert_eq!(hits.fetch_add(Point2::new(4, 0), 1), None);
+        assert_eq!(hits.fetch_add_position(Point2::new(2.5, 0.5), 1), None);
+        assert_eq!(hits.snapshot().sum(), 2400);
+
+        let mut map = CellMap::<TestLayers, u32>::new_from_elem(params, 1);
+        hits.take_into(&mut map, TestLayers::Layer1).unwrap();
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(1, 2))], 801);
+        assert_eq!(map.iter().layer(TestLayers::Layer0).sum::<u32>(), 12);
+        assert_eq!(hits.take().sum(), 0);
+
+        let mut small = map.submap(Bounds::new((-2, 2), (0, 2)).unwrap()).unwrap();
+        assert!(matches!(
+            hits.take_into(&mut small, TestLayers::Layer0),
+            Err(Error::LayerWrongShape(_, _))
+        ));
+    }
+}
@@ -0,0 +1,311 @@
+//! Provides cellular automata on the layers of a [`CellMap`], where each step computes the new
+//! value of every cell from its current value and the values of its neighbours.
+//!
+//! This is a general tool for simple simulations on the map, such as diffusion, the spread of a
+//! fire across terrain, or prototyping rules like Conway's Game of Life. Every cell is updated
+//! from the values before the step, so the result doesn't depend on the order cells are visited,
+//! and with the `parallel` feature steps can be run on the `rayon` thread pool with
+//! [`CellMap::par_automaton_step()`].
+//!
+//! ```
+//! use cell_map::{Bounds, CellMap, CellMapParams, Layer};
+//! use nalgebra::Point2;
+//!
+//! #[derive(Layer, Clone, Debug)]
+//! enum MyLayer {
+//!     Alive,
+//! }
+//!
+//! let mut map = CellMap::<MyLayer, bool>::new(CellMapParams {
+//!     cell_bounds: Bounds::new((0, 5), (0, 5)).unwrap(),
+//!     ..Default::default()
+//! });
+//!
+//! // A blinker, which flips between a horizontal and a vertical line
+//! for x in 1..4 {
+//!     map[(MyLayer::Alive, Point2::new(x, 2))] = true;
+//! }
+//!
+//! let life = |&alive: &bool, n: cell_map::automaton::Neighbourhood<bool>| {
+//!     matches!((alive, n.count(|&v| v)), (true, 2) | (_, 3))
+//! };
+//! map.automaton_step(MyLayer::Alive, MyLayer::Alive, life);
+//!
+//! assert!(map[(MyLayer::Alive, Point2::new(2, 1))]);
+//! assert!(map[(MyLayer::Alive, Point2::new(2, 3))]);
+//! assert!(!map[(MyLayer::Alive, Point2::new(1, 2))]);
+//! ```
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(feature = "parallel")]
+use ndarray::Zip;
+use ndarray::{Array2, ArrayView2, IndexLonger};
+
+use crate::{CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The offsets of the 8 neighbours of a cell, in the order they're stored in a [`Neighbourhood`].
+const NEIGHBOURS: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// The values of the 8 neighbours of a cell, passed to the rule of a cellular automaton.
+///
+/// Neighbours outside the map are missing from the neighbourhood, so cells on the edge of the map
+/// have fewer neighbours.
+#[derive(Debug, Clone)]
+pub struct Neighbourhood<'a, T> {
+    values: [Option<&'a T>; 8],
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<'a, T> Neighbourhood<'a, T> {
+    /// Gets the neighbourhood of the cell at `(row, column)` in `data`.
+    fn new(data: &ArrayView2<'a, T>, (y, x): (usize, usize)) -> Self {
+        let mut values = [None; 8];
+
+        for (value, (dx, dy)) in values.iter_mut().zip(NEIGHBOURS.iter()) {
+            *value = y
+                .checked_add_signed(*dy)
+                .zip(x.checked_add_signed(*dx))
+                .and_then(|i| IndexLonger::get(data, i));
+        }
+
+        Self { values }
+    }
+
+    /// Returns the value of the neighbour at offset `(dx, dy)` from the cell in the x and y axes,
+    /// or `None` if the neighbour is outside the map or the offset isn't to one of the 8
+    /// neighbours.
+    pub fn get(&self, dx: isize, dy: isize) -> Option<&'a T> {
+        let i = NEIGHBOURS.iter().position(|&o| o == (dx, dy))?;
+        self.values[i]
+    }
+
+    /// Returns an iterator over the values of all neighbours inside the map (the Moore
+    /// neighbourhood).
+    pub fn iter(&self) -> impl Iterator<Item = &'a T> + '_ {
+        self.values.iter().filter_map(|v| *v)
+    }
+
+    /// Returns an iterator over the values of the 4 neighbours which share an edge with the cell
+    /// (the von Neumann neighbourhood), skipping those outside the map.
+    pub fn orthogonal(&self) -> impl Iterator<Item = &'a T> + '_ {
+        [1, 3, 4, 6].iter().filter_map(move |&i| self.values[i])
+    }
+
+    /// Returns the number of neighbours inside the map.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns whether every neighbour is outside the map, which is only the case in maps one cell
+    /// in size.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of neighbours for which `predicate` returns `true`.
+    pub fn count<P>(&self, predicate: P) -> usize
+    where
+        P: Fn(&T) -> bool,
+    {
+        self.iter().filter(|v| predicate(v)).count()
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+{
+    /// Runs one step of a cellular automaton, setting each cell of `dst_layer` to the result of
+    /// `rule` for the value of the cell in `src_layer` and its [`Neighbourhood`] in `src_layer`.
+    ///
+    /// The new values are written into a separate buffer, so every cell is updated from the values
+    /// before the step even if `src_layer` and `dst_layer` are the same.
+    pub fn automaton_step<F>(&mut self, src_layer: L, dst_layer: L, rule: F)
+    where
+        F: Fn(&T, Neighbourhood<T>) -> T,
+    {
+        trace_span!(
+            "automaton_step",
+            num_cells = self.num_cells().iter().product::<usize>()
+        );
+
+        self[dst_layer] = step(self[src_layer].view(), &rule);
+    }
+
+    /// Runs `steps` steps of a cellular automaton on `layer`, as for
+    /// [`CellMap::automaton_step()`].
+    ///
+    /// The layer and a single buffer are swapped between steps, so only one extra layer is
+    /// allocated however many steps are run.
+    pub fn run_automaton<F>(&mut self, layer: L, steps: usize, rule: F)
+    where
+        F: Fn(&T, Neighbourhood<T>) -> T,
+    {
+        trace_span!("run_automaton", steps = steps);
+
+        if steps == 0 {
+            return;
+        }
+
+        let data = &mut self[layer];
+        let mut buffer = step(data.view(), &rule);
+        for _ in 1..steps {
+            std::mem::swap(data, &mut buffer);
+            step_into(data.view(), &mut buffer, &rule);
+        }
+        std::mem::swap(data, &mut buffer);
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Send + Sync,
+{
+    /// Parallel version of [`CellMap::automaton_step()`], which evaluates `rule` on the `rayon`
+    /// thread pool.
+    pub fn par_automaton_step<F>(&mut self, src_layer: L, dst_layer: L, rule: F)
+    where
+        F: Fn(&T, Neighbourhood<T>) -> T + Send + Sync,
+    {
+        trace_span!(
+            "par_automaton_step",
+            num_cells = self.num_cells().iter().product::<usize>()
+        );
+
+        let data = self[src_layer].view();
+        self[dst_layer] = Zip::indexed(data)
+            .par_map_collect(|index, value| rule(value, Neighbourhood::new(&data, index)));
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Applies `rule` to every cell of `data`, returning the new values.
+fn step<T, F>(data: ArrayView2<'_, T>, rule: &F) -> Array2<T>
+where
+    F: Fn(&T, Neighbourhood<T>) -> T,
+{
+    Array2::from_shape_fn(data.dim(), |index| {
+        rule(&data[index], Neighbourhood::new(&data, index))
+    })
+}
+
+/// Applies `rule` to every cell of `data`, writing the new values into `out`, which must have the
+/// same shape.
+fn step_into<T, F>(data: ArrayView2<'_, T>, out: &mut Array2<T>, rule: &F)
+where
+    F: Fn(&T, Neighbourhood<T>) -> T,
+{
+    for (index, value) in out.indexed_iter_mut() {
+        *value = rule(&data[index], Neighbourhood::new(&data, index));
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    fn new_map() -> CellMap<TestLayers, f64> {
+        let mut map = CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 5), (0, 4)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        for ((_, index), v) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            *v = (index.x + 10 * index.y) as f64;
+        }
+
+        map
+    }
+
+    #[test]
+    fn neighbourhoods() {
+        let mut map = new_map();
+
+        // Count the neighbours of each cell, and take the value of the neighbour below
+        map.automaton_step(TestLayers::Layer0, TestLayers::Layer1, |_, n| {
+            n.len() as f64
+        });
+        map.automaton_step(TestLayers::Layer0, TestLayers::Layer2, |&v, n| {
+            *n.get(0, -1).unwrap_or(&v)
+        });
+
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(0, 0))], 3.0);
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(2, 0))], 5.0);
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(2, 2))], 8.0);
+        assert_eq!(map[(TestLayers::Layer2, Point2::new(3, 2))], 13.0);
+        assert_eq!(map[(TestLayers::Layer2, Point2::new(3, 0))], 3.0);
+
+        // The sum of the orthogonal neighbours, updating the layer in place
+        map.automaton_step(TestLayers::Layer0, TestLayers::Layer0, |_, n| {
+            n.orthogonal().sum()
+        });
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(1, 1))], 44.0);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(4, 3))], 57.0);
+    }
+
+    #[test]
+    fn run_automaton() {
+        let mut map = new_map();
+        let mut stepped = map.clone();
+
+        // Spread the maximum value across the map
+        let rule = |&v: &f64, n: Neighbourhood<f64>| n.iter().fold(v, |a, &b| a.max(b));
+
+        map.run_automaton(TestLayers::Layer0, 2, rule);
+        stepped.automaton_step(TestLayers::Layer0, TestLayers::Layer0, rule);
+        stepped.automaton_step(TestLayers::Layer0, TestLayers::Layer0, rule);
+        assert_eq!(map[TestLayers::Layer0], stepped[TestLayers::Layer0]);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(2, 1))], 34.0);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(1, 0))], 23.0);
+
+        map.run_automaton(TestLayers::Layer0, 0, |_, _| 0.0);
+        assert_eq!(map[TestLayers::Layer0], stepped[TestLayers::Layer0]);
+
+        #[cfg(feature = "parallel")]
+        {
+            let mut par_map = new_map();
+            par_map.par_automaton_step(TestLayers::Layer0, TestLayers::Layer1, rule);
+            stepped = new_map();
+            stepped.automaton_step(TestLayers::Layer0, TestLayers::Layer1, rule);
+            assert_eq!(par_map[TestLayers::Layer1], stepped[TestLayers::Layer1]);
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! Provides the [`BitLayer`] type, a single `bool` layer packed into a bitset.
+//!
+//! A `bool` layer of a [`CellMap`] uses a byte per cell, while a [`BitLayer`] uses a bit, so maps
+//! which carry several boolean masks can store them in an eighth of the memory. Logical operations
+//! between masks work on 64 cells at a time.
+//!
+//! ```
+//! use cell_map::{bits::BitLayer, Bounds, CellMap, CellMapParams, Layer};
+//! use nalgebra::Point2;
+//!
+//! #[derive(Layer, Clone, Debug)]
+//! enum MyLayer {
+//!     Explored,
+//!     Obstacle,
+//! }
+//!
+//! let params = CellMapParams {
+//!     cell_bounds: Bounds::new((0, 10), (0, 10)).unwrap(),
+//!     ..Default::default()
+//! };
+//! let mut explored = BitLayer::new(params);
+//! let mut obstacles = BitLayer::new(params);
+//! explored.set(Point2::new(2, 3), true).unwrap();
+//! explored.set(Point2::new(4, 3), true).unwrap();
+//! obstacles.set(Point2::new(4, 3), true).unwrap();
+//!
+//! // Explored free space
+//! let free = &explored & &!obstacles;
+//! assert_eq!(free.count_ones(), 1);
+//!
+//! let mut map = CellMap::<MyLayer, bool>::new(params);
+//! free.write_into(&mut map, MyLayer::Explored).unwrap();
+//! assert!(map[(MyLayer::Explored, Point2::new(2, 3))]);
+//! ```
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+
+use nalgebra::{Point2, Vector2};
+
+use crate::{
+    cell_map::Bounds, map_metadata::CellMapMetadata, CellMap, CellMapParams, Error, Layer,
+};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The number of cells stored in each word of a [`BitLayer`].
+const WORD_BITS: usize = 64;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A single `bool` layer packed into a bitset, with the same shape and position as a [`CellMap`]
+/// built from the same [`CellMapParams`].
+///
+/// Cells are stored in the same order as [`CellMap::iter()`] visits them, in row-major order, so
+/// [`BitLayer::iter()`] yields the same sequence of values as iterating over the equivalent
+/// `bool` layer.
+///
+/// The logical operators `&`, `|`, `^` and `!` are implemented between layers, and panic if the
+/// layers have different shapes, in the same way as `ndarray`'s arithmetic operators.
+#[derive(Debug, Clone)]
+pub struct BitLayer {
+    words: Vec<u64>,
+    metadata: CellMapMetadata,
+    params: CellMapParams,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl BitLayer {
+    /// Creates a new layer with the given parameters, with every cell set to `false`.
+    pub fn new(params: CellMapParams) -> Self {
+        let metadata: CellMapMetadata = params.into();
+        let num_cells = metadata.num_cells.x * metadata.num_cells.y;
+
+        Self {
+            words: vec![0; num_cells.div_ceil(WORD_BITS)],
+            metadata,
+            params,
+        }
+    }
+
+    /// Packs `layer` of `map` into a new layer.
+    pub fn from_layer<L: Layer>(map: &CellMap<L, bool>, layer: L) -> Self {
+        let mut bits = Self::new(map.params());
+
+        // Layers may not be in standard layout, but iterate in logical order anyway
+        for (i, &v) in map[layer].iter().enumerate() {
+            if v {
+                bits.words[i / WORD_BITS] |= 1 << (i % WORD_BITS);
+            }
+        }
+
+        bits
+    }
+
+    /// Unpacks this layer into `layer` of `map`.
+    ///
+    /// The map must have the same shape as this layer, otherwise [`Error::LayerWrongShape`] is
+    /// returned and the map is unchanged.
+    pub fn write_into<L: Layer>(&self, map: &mut CellMap<L, bool>, layer: L) -> Result<(), Error> {
+        let shape = self.metadata.cell_bounds.get_shape();
+        let map_shape = map.cell_bounds().get_shape();
+        if shape != map_shape {
+            return Err(Error::LayerWrongShape(shape, map_shape));
+        }
+
+        for (v, bit) in map[layer].iter_mut().zip(self.iter()) {
+            *v = bit;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of cells in each direction of the layer.
+    pub fn num_cells(&self) -> Vector2<usize> {
+        self.metadata.num_cells
+    }
+
+    /// Returns the bounds of the layer.
+    pub fn cell_bounds(&self) -> Bounds {
+        self.metadata.cell_bounds
+    }
+
+    /// Returns the parameters used to build this layer.
+    pub fn params(&self) -> CellMapParams {
+        self.params
+    }
+
+    /// Returns the value of the cell at the given index, or `None` if the index is outside the
+    /// layer.
+    pub fn get(&self, index: Point2<usize>) -> Option<bool> {
+        let bit = self.bit(index)?;
+        Some(self.words[bit / WORD_BITS] & (1 << (bit % WORD_BITS)) != 0)
+    }
+
+    /// Returns the value of the cell containing the given parent-frame position, or `None` if the
+    /// position is outside the layer.
+    pub fn get_position(&self, position: Point2<f64>) -> Option<bool> {
+        self.get(self.metadata.index(position)?)
+    }
+
+    /// Sets the value of the cell at the given index.
+    ///
+    /// Returns [`Error::IndexOutsideMap`] if the index is outside the layer.
+    pub fn set(&mut self, index: Point2<usize>, value: bool) -> Result<(), Error> {
+        let bit = self.bit(index).ok_or(Error::IndexOutsideMap(index))?;
+        let mask = 1 << (bit % WORD_BITS);
+
+        if value {
+            self.words[bit / WORD_BITS] |= mask;
+        } else {
+            self.words[bit / WORD_BITS] &= !mask;
+        }
+
+        Ok(())
+    }
+
+    /// Sets every cell to `value`.
+    pub fn fill(&mut self, value: bool) {
+        let word = if value { !0 } else { 0 };
+        self.words.iter_mut().for_each(|w| *w = word);
+        self.clear_padding();
+    }
+
+    /// Returns the number of cells which are `true`.
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Returns whether any cell is `true`.
+    pub fn any(&self) -> bool {
+        self.words.iter().any(|&w| w != 0)
+    }
+
+    /// Returns an iterator over the value of every cell, in the same order as [`CellMap::iter()`].
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len()).map(move |bit| self.words[bit / WORD_BITS] & (1 << (bit % WORD_BITS)) != 0)
+    }
+
+    /// Returns an iterator over the index and value of every cell, in the same order as
+    /// [`CellMap::iter()`].
+    pub fn indexed(&self) -> impl Iterator<Item = (Point2<usize>, bool)> + '_ {
+        let num_x = self.metadata.num_cells.x;
+        self.iter()
+            .enumerate()
+            .map(move |(i, v)| (Point2::new(i % num_x, i / num_x), v))
+    }
+
+    /// Returns the number of cells in the layer.
+    fn len(&self) -> usize {
+        self.metadata.num_cells.x * self.metadata.num_cells.y
+    }
+
+    /// Returns the position of the bit for the given index, or `None` if the index is outside the
+    /// layer.
+    fn bit(&self, index: Point2<usize>) -> Option<usize> {
+        if self.metadata.is_in_map(index) {
+            Some(index.y * self.metadata.num_cells.x + index.x)
+        } else {
+            None
+        }
+    }
+
+    /// Clears the unused bits at the end of the last word, so that they don't affect counts or
+    /// comparisons.
+    fn clear_padding(&mut self) {
+        let used = self.len() % WORD_BITS;
+        if let (Some(last), true) = (self.words.last_mut(), used != 0) {
+            *last &= (1 << used) - 1;
+        }
+    }
+
+    /// Combines each word of this layer with the same word of `other`.
+    fn zip_words<F: Fn(&mut u64, u64)>(&mut self, other: &BitLayer, func: F) {
+        let (shape, other_shape) = (
+            self.metadata.cell_bounds.get_shape(),
+            other.metadata.cell_bounds.get_shape(),
+        );
+        assert!(
+            shape == other_shape,
+            "Cannot combine bit layers with shapes {:?} and {:?}",
+            shape,
+            other_shape
+        );
+
+        for (a, &b) in self.words.iter_mut().zip(other.words.iter()) {
+            func(a, b);
+        }
+    }
+}
+
+impl PartialEq for BitLayer {
+    fn eq(&self, other: &Self) -> bool {
+        self.metadata.cell_bounds == other.metadata.cell_bounds
+            && self.metadata.to_parent == other.metadata.to_parent
+            && self.words == other.words
+    }
+}
+
+impl<L> CellMap<L, bool>
+where
+    L: Layer,
+{
+    /// Packs `layer` into a [`BitLayer`].
+    pub fn pack_layer(&self, layer: L) -> BitLayer {
+        BitLayer::from_layer(self, layer)
+    }
+}
+
+impl Not for BitLayer {
+    type Output = BitLayer;
+
+    fn not(mut self) -> Self::Output {
+        self.words.iter_mut().for_each(|w| *w = !*w);
+        self.clear_padding();
+        self
+    }
+}
+
+impl Not for &BitLayer {
+    type Output = BitLayer;
+
+    fn not(self) -> Self::Output {
+        !self.clone()
+    }
+}
+
+/// Implements a logical operator between [`BitLayer`]s, both in place and by reference.
+macro_rules! impl_bit_layer_op {
+    ($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident, $op:tt) => {
+        impl $assign_trait<&BitLayer> for BitLayer {
+            fn $assign_method(&mut self, rhs: &BitLayer) {
+                self.zip_words(rhs, |a, b| *a $op b);
+            }
+        }
+
+        impl $trait<&BitLayer> for &BitLayer {
+            type Output = BitLayer;
+
+            fn $method(self, rhs: &BitLayer) -> Self::Output {
+                let mut out = self.clone();
+                out.$assign_method(rhs);
+                out
+            }
+        }
+    };
+}
+
+impl_bit_layer_op!(BitAnd, bitand, BitAndAssign, bitand_assign, &=);
+impl_bit_layer_op!(BitOr, bitor, BitOrAssign, bitor_assign, |=);
+impl_bit_layer_op!(BitXor, bitxor, BitXorAssign, bitxor_assign, ^=);
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestLayers;
+
+    #[test]
+    fn bit_layer() {
+        // 9 x 8 cells, so the last word is partly used
+        let params = CellMapParams {
+            cell_bounds: Bounds::new((-4, 5), (0, 8)).unwrap(),
+            ..Default::default()
+        };
+        let mut map = CellMap::<TestLayers, bool>::new(params);
+        for ((_, index), v) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            *v = (index.x + index.y) % 3 == 0;
+        }
+
+        let bits = map.pack_layer(TestLayers::Layer0);
+        assert_eq!(bits.words.len(), 2);
+        assert!(bits
+            .iter()
+            .eq(map.iter().layer(TestLayers::Layer0).copied()));
+        assert_eq!(
+            bits.count_ones(),
+            map.iter().layer(TestLayers::Layer0).filter(|&&v| v).count()
+        );
+        assert_eq!(bits.get(Point2::new(8, 7)), Some(true));
+        assert_eq!(bits.get(Point2::new(9, 7)), None);
+        assert_eq!(bits.get_position(Point2::new(-3.5, 1.5)), Some(false));
+        assert!(bits
+            .indexed()
+            .all(|(i, v)| map[(TestLayers::Layer0, i)] == v));
+
+        // Logical operations, where not must leave the padding clear
+        let mut other = BitLayer::new(params);
+        other.set(Point2::new(0, 0), true).unwrap();
+        other.set(Point2::new(1, 0), true).unwrap();
+        assert!(other.set(Point2::new(0, 8), true).is_err());
+
+        assert_eq!((&bits & &other).count_ones(), 1);
+        assert_eq!((&bits | &other).count_ones(), bits.count_ones() + 1);
+        assert_eq!((&bits ^ &other).count_ones(), bits.count_ones());
+        assert_eq!((!&bits).count_ones(), 72 - bits.count_ones());
+
+        let mut all = BitLayer::new(params);
+        assert!(!all.any());
+        all.fill(true);
+        assert_eq!(all.count_ones(), 72);
+        all &= &bits;
+        assert_eq!(all, bits);
+
+        // Unpacking
+        (!&bits).write_into(&mut map, TestLayers::Layer1).unwrap();
+        assert!(map[(TestLayers::Layer1, Point2::new(1, 0))]);
+        assert!(!map[(TestLayers::Layer1, Point2::new(2, 1))]);
+
+        let mut small = CellMap::<TestLayers, bool>::new(CellMapParams {
+            cell_bounds: Bounds::new((0, 2), (0, 2)).unwrap(),
+            ..Default::default()
+        });
+        assert!(matches!(
+            bits.write_into(&mut small, TestLayers::Layer0),
+            Err(Error::LayerWrongShape(_, _))
+        ));
+    }
+}
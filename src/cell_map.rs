@@ -7,23 +7,22 @@
 use std::{
     marker::PhantomData,
     ops::{Index, IndexMut},
-    usize,
 };
 
 use nalgebra::{Affine2, Point2, Vector2};
-use ndarray::{s, Array2};
+use ndarray::{s, Array2, ArrayView2, ArrayViewMut2, CowArray, Ix2, Zip};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::{
     cell_map_file::CellMapFile,
-    extensions::Point2Ext,
+    error::unwrap_checked,
     iterators::{
         layerers::Many,
         slicers::{Cells, Line, Windows},
-        CellMapIter, CellMapIterMut,
+        CellMapIter, CellMapIterMut, Layers, LayersMut,
     },
     map_metadata::CellMapMetadata,
-    Error, Layer,
+    math, Error, FromLayerDefault, Layer,
 };
 
 // ------------------------------------------------------------------------------------------------
@@ -97,14 +96,47 @@ pub struct CellMapParams {
     /// 0.9999999999999999]`, which if `floor()`ed to fit into a `usize` would give the incorrect
     /// index `[6, 0]`.
     ///
-    /// When calculating cell index we therefore `floor` the floating point index unless it is
-    /// within `cell_size * cell_boundary_precision`, in which case we round up to the next cell.
-    /// Mutliplying by `cell_size` allows this value to be independent of the scale of the map.
+    /// How this precision is used to classify positions near a boundary is set by
+    /// `boundary_rule`. With the default [`BoundaryRule::EpsilonSnap`] we `floor` the floating
+    /// point index unless it is within `cell_size * cell_boundary_precision`, in which case we
+    /// round up to the next cell. Mutliplying by `cell_size` allows this value to be independent
+    /// of the scale of the map.
     ///
     /// # Default
     ///
     /// The default value is `1e-10`.
     pub cell_boundary_precision: f64,
+
+    /// The direction of the map's `y` axis and the anchor of `position_in_parent` within a cell,
+    /// see [`GridConvention`].
+    ///
+    /// # Default
+    ///
+    /// The default value is [`GridConvention::default()`], which has `y` up and
+    /// `position_in_parent` at the corner of cell `(0, 0)`.
+    #[serde(default)]
+    pub convention: GridConvention,
+
+    /// How positions on or near the boundary between two cells are assigned to a cell, see
+    /// [`BoundaryRule`].
+    ///
+    /// # Default
+    ///
+    /// The default value is [`BoundaryRule::EpsilonSnap`].
+    #[serde(default)]
+    pub boundary_rule: BoundaryRule,
+}
+
+/// Describes how a map's cells are laid out relative to its position in the parent frame, so that
+/// data from sources with different conventions, such as images or ROS maps, isn't mirrored or
+/// shifted by half a cell when imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct GridConvention {
+    /// The direction the map's `y` axis, i.e. increasing row index, points in the map's frame.
+    pub y_axis: YAxis,
+
+    /// Where in cell `(0, 0)` the map's `position_in_parent` is.
+    pub origin: CellOrigin,
 }
 
 /// Rectangular bounds describing the number of cells in each direction of the map.
@@ -122,6 +154,75 @@ pub struct Bounds {
     pub y: (isize, isize),
 }
 
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// The direction of a map's `y` axis, see [`GridConvention`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum YAxis {
+    /// Increasing row index moves along the positive `y` axis of the map's frame, as in a
+    /// right-handed frame with `x` right and `y` up, or a ROS occupancy grid.
+    #[default]
+    Up,
+
+    /// Increasing row index moves along the negative `y` axis of the map's frame, as in an image
+    /// whose first row is at the top.
+    Down,
+}
+
+/// The point within cell `(0, 0)` which a map's `position_in_parent` refers to, see
+/// [`GridConvention`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CellOrigin {
+    /// `position_in_parent` is the outer corner of cell `(0, 0)`, as in ROS occupancy grids and
+    /// area-referenced rasters.
+    #[default]
+    Corner,
+
+    /// `position_in_parent` is the centre of cell `(0, 0)`, as in point-referenced rasters.
+    Centre,
+}
+
+/// How positions on or near the boundary between two cells are assigned to a cell, which decides
+/// the results of [`CellMap::index()`], the first cell of [`CellMap::line_iter()`], and the cells
+/// covered by [`Footprint`](crate::collision::Footprint)s.
+///
+/// Each rule is applied independently along each axis to the position in the map frame, measured
+/// in cells, so that boundaries lie on whole numbers. Since this coordinate comes from floating
+/// point transforms, a position which should lie exactly on a boundary may be a tiny amount
+/// either side of it, and the rules differ in how they handle this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BoundaryRule {
+    /// Coordinates are floored, unless they are within `cell_size * cell_boundary_precision`
+    /// below a boundary, in which case they are snapped up onto it. This was the only behaviour
+    /// before boundary rules were added.
+    #[default]
+    EpsilonSnap,
+
+    /// Coordinates are floored with no tolerance, so each cell is exactly the half-open range
+    /// $[k, k + 1)$ and a position which should be on a boundary may land in the cell below it.
+    HalfOpen,
+
+    /// Coordinates are rounded to the nearest multiple of `cell_boundary_precision` cells before
+    /// being floored with integer arithmetic, so coordinates within half of that distance of a
+    /// boundary, on either side, are placed on it.
+    ///
+    /// `cell_boundary_precision` must be positive, and should be the reciprocal of a whole number
+    /// such as `1e-10`, otherwise this falls back to [`BoundaryRule::HalfOpen`]. Coordinates more
+    /// than $2^{53}$ multiples of the precision from the map's origin lose precision.
+    RoundNearest,
+}
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The relative tolerance used when deciding whether cells are within a parent-frame distance, so
+/// that distances which are whole multiples of the cell size aren't lost to rounding errors, for
+/// example `0.3 / 0.1 = 2.9999999999999996`.
+pub(crate) const DISTANCE_TOLERANCE: f64 = 1e-9;
+
 // ------------------------------------------------------------------------------------------------
 // IMPLS
 // ------------------------------------------------------------------------------------------------
@@ -139,23 +240,23 @@ where
             return Err(Error::WrongNumberOfLayers(L::NUM_LAYERS, data.len()));
         }
 
-        if !data.is_empty() {
-            let layer_cells = (data[0].shape()[0], data[0].shape()[1]);
-
-            if layer_cells != params.cell_bounds.get_shape() {
-                return Err(Error::LayerWrongShape(
-                    layer_cells,
-                    params.cell_bounds.get_shape(),
-                ));
-            }
+        let shape = params.cell_bounds.get_shape();
+        if let Some((i, layer)) = data.iter().enumerate().find(|(_, d)| d.dim() != shape) {
+            return Err(Error::LayerDataWrongShape(i, layer.dim(), shape));
         }
 
-        Ok(Self {
+        Ok(Self::new_from_data_unchecked(params, data))
+    }
+
+    /// Creates a new map from the given data without checking it, for data which is known to
+    /// have one layer of the right shape for each variant of `L`.
+    pub(crate) fn new_from_data_unchecked(params: CellMapParams, data: Vec<Array2<T>>) -> Self {
+        Self {
             data,
             metadata: params.into(),
             params,
             layer_type: PhantomData,
-        })
+        }
     }
 
     /// Returns the size of the cells in the map.
@@ -194,6 +295,7 @@ where
             position_in_parent,
             rotation_in_parent_rad,
             self.metadata.cell_size,
+            self.params.convention,
         );
 
         // Update the parameter values
@@ -214,11 +316,9 @@ where
     /// Get a reference to the value at the given layer and index. Returns `None` if the index is
     /// outside the bounds of the map.
     pub fn get(&self, layer: L, index: Point2<usize>) -> Option<&T> {
-        if self.index_in_map(index) {
-            Some(&self[(layer, index)])
-        } else {
-            None
-        }
+        self.data
+            .get(layer.to_index())
+            .and_then(|data| data.get((index.y, index.x)))
     }
 
     /// Get a reference to the value at the given layer and index, without checking the bounds of
@@ -234,11 +334,9 @@ where
     /// Get a mutable reference to the value at the given layer and index. Returns `None` if the
     /// index is outside the bounds of the map.
     pub fn get_mut(&mut self, layer: L, index: Point2<usize>) -> Option<&mut T> {
-        if self.index_in_map(index) {
-            Some(&mut self[(layer, index)])
-        } else {
-            None
-        }
+        self.data
+            .get_mut(layer.to_index())
+            .and_then(|data| data.get_mut((index.y, index.x)))
     }
 
     /// Get a mutable reference to the value at the given layer and index, without checking the
@@ -254,14 +352,43 @@ where
     /// Set the given layer and index in the map to the given value. Returns an [`Error`] if the
     /// index was outside the map.
     pub fn set(&mut self, layer: L, index: Point2<usize>, value: T) -> Result<(), Error> {
-        if self.index_in_map(index) {
-            self[(layer, index)] = value;
-            Ok(())
-        } else {
-            Err(Error::IndexOutsideMap(index))
+        let cell_bounds = self.metadata.cell_bounds;
+        let layer_index = layer.to_index();
+
+        match self.get_mut(layer, index) {
+            Some(v) => {
+                *v = value;
+                Ok(())
+            }
+            None => Err(Error::IndexOutsideLayer(layer_index, index, cell_bounds)),
         }
     }
 
+    /// Returns the data of the given layer, or [`Error::InvalidLayerIndex`] if the layer's
+    /// [`Layer::to_index()`] doesn't refer to a layer of the map.
+    ///
+    /// This is the checked equivalent of indexing the map with `map[layer]`, which panics in that
+    /// case. It can only fail for a [`Layer`] implementation which doesn't uphold the trait's
+    /// contract, so is intended for code built with the `strict` feature, which mustn't panic.
+    pub fn try_layer(&self, layer: L) -> Result<&Array2<T>, Error> {
+        let index = layer.to_index();
+        self.data
+            .get(index)
+            .ok_or(Error::InvalidLayerIndex(index, self.data.len()))
+    }
+
+    /// Returns the data of the given layer mutably, or [`Error::InvalidLayerIndex`] if the
+    /// layer's [`Layer::to_index()`] doesn't refer to a layer of the map.
+    ///
+    /// This is the checked equivalent of `&mut map[layer]`, see [`CellMap::try_layer()`].
+    pub fn try_layer_mut(&mut self, layer: L) -> Result<&mut Array2<T>, Error> {
+        let index = layer.to_index();
+        let num_layers = self.data.len();
+        self.data
+            .get_mut(index)
+            .ok_or(Error::InvalidLayerIndex(index, num_layers))
+    }
+
     /// Set the given layer and index in the map to the given value, without checking if index is
     /// the map.
     ///
@@ -290,6 +417,63 @@ where
         self.metadata.position_unchecked(index)
     }
 
+    /// Returns the positions in the parent frame of the four corners of the given cell index, in
+    /// anticlockwise order in the map frame starting from the corner with the lowest `x` and `y`.
+    ///
+    /// Returns `None` if the given `index` is not inside the map.
+    pub fn cell_corners(&self, index: Point2<usize>) -> Option<[Point2<f64>; 4]> {
+        if self.index_in_map(index) {
+            Some(self.metadata.cell_corners_unchecked(index))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the smallest and largest corners of the parent-frame axis-aligned box containing
+    /// the given cell index, which is larger than the cell itself if the map is rotated.
+    ///
+    /// Returns `None` if the given `index` is not inside the map.
+    pub fn cell_bounds_in_parent(
+        &self,
+        index: Point2<usize>,
+    ) -> Option<(Point2<f64>, Point2<f64>)> {
+        let corners = self.cell_corners(index)?;
+
+        Some(
+            corners[1..]
+                .iter()
+                .fold((corners[0], corners[0]), |(min, max), p| {
+                    (min.inf(p), max.sup(p))
+                }),
+        )
+    }
+
+    /// Returns the number of whole cells along the `x` and `y` axes whose centres are within the
+    /// parent-frame `radius` of a cell's centre, which differ for non-square cells.
+    ///
+    /// Radii which are whole multiples of the cell size include the cells exactly at the radius,
+    /// despite floating point rounding.
+    pub fn radius_in_cells(&self, radius: f64) -> Vector2<usize> {
+        let cell_size = self.cell_size();
+        Vector2::new(
+            cells_within(radius, cell_size.x),
+            cells_within(radius, cell_size.y),
+        )
+    }
+
+    /// Returns the parent-frame distance between the centres of cells `a` and `b`, taking the
+    /// size of the cells along each axis into account.
+    ///
+    /// Unlike subtracting the results of [`CellMap::position()`] the indices don't have to be
+    /// inside the map.
+    pub fn index_distance(&self, a: Point2<usize>, b: Point2<usize>) -> f64 {
+        let cell_size = self.cell_size();
+        let dx = (a.x as f64 - b.x as f64) * cell_size.x;
+        let dy = (a.y as f64 - b.y as f64) * cell_size.y;
+
+        math::hypot(dx, dy)
+    }
+
     /// Get the cell index of the given poisition.
     ///
     /// Returns `None` if the given `position` is not inside the map.
@@ -311,12 +495,19 @@ where
 
     /// Returns an iterator over each cell in all layers of the map.
     pub fn iter(&self) -> CellMapIter<'_, L, T, Many<L>, Cells> {
-        CellMapIter::<'_, L, T, Many<L>, Cells>::new_cells(self)
+        CellMapIter::<'_, L, T, Many<L>, Cells>::new_cells(
+            Layers::Arrays(&self.data),
+            self.metadata,
+        )
     }
 
     /// Returns a mutable iterator over each cell in all layers of the map.
     pub fn iter_mut(&mut self) -> CellMapIterMut<'_, L, T, Many<L>, Cells> {
-        CellMapIterMut::<'_, L, T, Many<L>, Cells>::new_cells(self)
+        let metadata = self.metadata;
+        CellMapIterMut::<'_, L, T, Many<L>, Cells>::new_cells(
+            LayersMut::Arrays(&mut self.data),
+            metadata,
+        )
     }
 
     /// Returns an iterator over windows of cells in the map.
@@ -328,7 +519,11 @@ where
         &self,
         semi_width: Vector2<usize>,
     ) -> Result<CellMapIter<'_, L, T, Many<L>, Windows>, Error> {
-        CellMapIter::<'_, L, T, Many<L>, Windows>::new_windows(self, semi_width)
+        CellMapIter::<'_, L, T, Many<L>, Windows>::new_windows(
+            Layers::Arrays(&self.data),
+            self.metadata,
+            semi_width,
+        )
     }
 
     /// Returns a mutable iterator over windows of cells in the map.
@@ -340,7 +535,12 @@ where
         &mut self,
         semi_width: Vector2<usize>,
     ) -> Result<CellMapIterMut<'_, L, T, Many<L>, Windows>, Error> {
-        CellMapIterMut::<'_, L, T, Many<L>, Windows>::new_windows(self, semi_width)
+        let metadata = self.metadata;
+        CellMapIterMut::<'_, L, T, Many<L>, Windows>::new_windows(
+            LayersMut::Arrays(&mut self.data),
+            metadata,
+            semi_width,
+        )
     }
 
     /// Returns an iterator over cells along the line joining `start_position` and
@@ -350,7 +550,12 @@ where
         start_position: Point2<f64>,
         end_position: Point2<f64>,
     ) -> Result<CellMapIter<'_, L, T, Many<L>, Line>, Error> {
-        CellMapIter::<'_, L, T, Many<L>, Line>::new_line(self, start_position, end_position)
+        CellMapIter::<'_, L, T, Many<L>, Line>::new_line(
+            Layers::Arrays(&self.data),
+            self.metadata,
+            start_position,
+            end_position,
+        )
     }
 
     /// Returns a mutable iterator over cells along the line joining `start_position` and
@@ -360,7 +565,28 @@ where
         start_position: Point2<f64>,
         end_position: Point2<f64>,
     ) -> Result<CellMapIterMut<'_, L, T, Many<L>, Line>, Error> {
-        CellMapIterMut::<'_, L, T, Many<L>, Line>::new_line(self, start_position, end_position)
+        let metadata = self.metadata;
+        CellMapIterMut::<'_, L, T, Many<L>, Line>::new_line(
+            LayersMut::Arrays(&mut self.data),
+            metadata,
+            start_position,
+            end_position,
+        )
+    }
+
+    /// Returns a view of the given layer.
+    ///
+    /// Layers are indexed `(row, column)`, i.e. `(y, x)`, so the first axis of the view is the
+    /// map's `y` axis. Use [`xy_to_nd()`] and [`nd_to_xy()`] to convert between cell indices and
+    /// array indices.
+    pub fn layer_view(&self, layer: L) -> ArrayView2<'_, T> {
+        self[layer].view()
+    }
+
+    /// Returns a mutable view of the given layer, indexed `(y, x)` as in
+    /// [`CellMap::layer_view()`].
+    pub fn layer_view_mut(&mut self, layer: L) -> ArrayViewMut2<'_, T> {
+        self[layer].view_mut()
     }
 }
 
@@ -375,10 +601,19 @@ where
         CellMapFile::new(self)
     }
 
+    /// Builds a new [`CellMapFile`] containing only the layers in the given group, see
+    /// [`Layer::groups()`].
+    ///
+    /// The file can be loaded back into a map with the same bounds using
+    /// [`CellMap::load_layers()`].
+    pub fn serialise_group(&self, group: &str) -> CellMapFile<L, T> {
+        CellMapFile::new_with_layers(self, L::in_group(group))
+    }
+
     /// Writes the map to the given path as a JSON file.
     #[cfg(feature = "json")]
     pub fn write_json<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), Error> {
-        let map_file = CellMapFile::new(&self);
+        let map_file = CellMapFile::new(self);
         map_file.write_json(path)
     }
 }
@@ -401,9 +636,59 @@ where
     L: Layer,
     T: Clone,
 {
+    /// Replaces the layers stored in `file` with its data, leaving the map's other layers
+    /// unchanged. This is the counterpart of [`CellMap::serialise_group()`].
+    ///
+    /// The file must have the same bounds as the map, otherwise [`Error::BoundsOutsideMap`] is
+    /// returned and the map is unchanged.
+    pub fn load_layers(&mut self, file: CellMapFile<L, T>) -> Result<(), Error> {
+        if file.cell_bounds != self.cell_bounds() {
+            return Err(Error::BoundsOutsideMap(
+                file.cell_bounds,
+                self.cell_bounds(),
+            ));
+        }
+
+        let shape = self.cell_bounds().get_shape();
+        if file.layers.len() != file.data.len() {
+            return Err(Error::WrongNumberOfLayers(
+                file.layers.len(),
+                file.data.len(),
+            ));
+        }
+        if let Some((layer, data)) = file
+            .layers
+            .iter()
+            .zip(file.data.iter())
+            .find(|(_, d)| d.dim() != shape)
+        {
+            return Err(Error::LayerDataWrongShape(
+                layer.to_index(),
+                data.dim(),
+                shape,
+            ));
+        }
+
+        for (layer, data) in file.layers.into_iter().zip(file.data) {
+            *self.try_layer_mut(layer)? = data;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the given layer in standard (row-major) layout, which is needed by APIs that take
+    /// the layer as a contiguous slice, such as image encoders.
+    ///
+    /// Layers are normally already in standard layout, in which case the layer is borrowed, but
+    /// a layer replaced through [`IndexMut`] with, for example, a transposed array is copied.
+    pub fn as_standard_layout(&self, layer: L) -> CowArray<'_, T, Ix2> {
+        self[layer].as_standard_layout()
+    }
+
     /// Creates a new [`CellMap`] from the given params, filling each cell with `elem`.
     pub fn new_from_elem(params: CellMapParams, elem: T) -> Self {
         let data = vec![Array2::from_elem(params.cell_bounds.get_shape(), elem); L::NUM_LAYERS];
+        count!(LayerAllocations, L::NUM_LAYERS);
 
         Self {
             data,
@@ -412,6 +697,256 @@ where
             layer_type: PhantomData,
         }
     }
+
+    /// Maps each window in `src_layer` into a single value which is written into the central cell
+    /// of the window in `dst_layer`.
+    ///
+    /// This is equivalent to iterating over [`CellMap::window_iter()`] on `src_layer` and writing
+    /// the result of `func` into `dst_layer`, but is performed in a single pass over the layer. For
+    /// example this can be used to calculate the local roughness of a height layer.
+    ///
+    /// As with [`CellMap::window_iter()`], cells closer than `semi_width` to the edge of the map
+    /// are not the centre of a full window, so their values in `dst_layer` are left unchanged.
+    /// `src_layer` and `dst_layer` may be the same layer, in which case all windows are evaluated
+    /// on the original data before any cells are overwritten.
+    pub fn window_map<F>(
+        &mut self,
+        src_layer: L,
+        dst_layer: L,
+        semi_width: Vector2<usize>,
+        func: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(ArrayView2<T>) -> T,
+    {
+        trace_span!(
+            "window_map",
+            num_cells = self.num_cells().iter().product::<usize>()
+        );
+        let window_shape = self.window_shape(semi_width)?;
+
+        let mapped = Zip::from(self.try_layer(src_layer)?.windows(window_shape)).map_collect(&func);
+
+        self.window_interior_mut(dst_layer, semi_width)?
+            .assign(&mapped);
+
+        Ok(())
+    }
+
+    /// Returns a copy of the region of the map within the given `bounds`, which must lie entirely
+    /// inside the map.
+    ///
+    /// The returned map has the same cell size and position in the parent frame as this map, so
+    /// each cell of the submap has the same position as the cell it was copied from.
+    pub fn submap(&self, bounds: Bounds) -> Result<CellMap<L, T>, Error> {
+        if !bounds.is_valid() {
+            return Err(Error::InvalidBounds(bounds));
+        }
+
+        if self.metadata.cell_bounds.intersect(&bounds) != Some(bounds) {
+            return Err(Error::BoundsOutsideMap(bounds, self.metadata.cell_bounds));
+        }
+
+        Ok(self.submap_unchecked(bounds))
+    }
+
+    /// Returns a copy of the region of the map within the given `bounds` without checking them.
+    ///
+    /// The bounds must be valid and lie entirely inside the map, which is up to the caller to
+    /// ensure.
+    pub(crate) fn submap_unchecked(&self, bounds: Bounds) -> CellMap<L, T> {
+        let min = self.metadata.cell_bounds.as_corners().0;
+        let data = self
+            .data
+            .iter()
+            .map(|layer| {
+                layer
+                    .slice(s![
+                        bounds.y.0 - min.y..bounds.y.1 - min.y,
+                        bounds.x.0 - min.x..bounds.x.1 - min.x
+                    ])
+                    .to_owned()
+            })
+            .collect();
+
+        let mut params = self.params;
+        params.cell_bounds = bounds;
+
+        CellMap::new_from_data_unchecked(params, data)
+    }
+
+    /// Returns a copy of the map with `n` times fewer cells along each axis, made by taking one
+    /// cell from each block of `n` by `n` cells. This is much cheaper than resampling the map, for
+    /// example to send a preview of it over a slow link.
+    ///
+    /// Each cell of the decimated map covers the same area as its block of `n` by `n` cells,
+    /// and takes the value of the cell closest to the block's centre. Blocks at the maximum edges
+    /// of the map may be partial, in which case the value is taken from the edge of the map. A
+    /// value of `0` for `n` is treated as `1`.
+    ///
+    /// The decimated map's cells are indexed from `(0, 0)`, which is the block at the minimum
+    /// corner of this map, and its position in the parent frame is adjusted so that each block
+    /// keeps its position.
+    pub fn decimate(&self, n: usize) -> CellMap<L, T> {
+        let n = n.max(1);
+        let num_cells = self.num_cells();
+        let shape = (num_cells.y.div_ceil(n), num_cells.x.div_ceil(n));
+
+        let data: Vec<_> = self
+            .data
+            .iter()
+            .map(|layer| {
+                Array2::from_shape_fn(shape, |(y, x)| {
+                    let y = (y * n + n / 2).min(num_cells.y - 1);
+                    let x = (x * n + n / 2).min(num_cells.x - 1);
+                    layer[(y, x)].clone()
+                })
+            })
+            .collect();
+
+        // The parent-frame position of the new map is the corner or centre of the first block,
+        // depending on the map's convention. The first block's corner is at the minimum corner of
+        // the map, in map-frame units of old cells.
+        let offset = match self.params.convention.origin {
+            CellOrigin::Corner => 0.0,
+            CellOrigin::Centre => n as f64 / 2.0,
+        };
+        let (min, _) = self.cell_bounds().as_corners();
+        let position = self
+            .metadata
+            .to_parent
+            .transform_point(&Point2::new(min.x as f64 + offset, min.y as f64 + offset));
+
+        let params = CellMapParams {
+            cell_size: self.cell_size() * n as f64,
+            cell_bounds: Bounds {
+                x: (0, shape.1 as isize),
+                y: (0, shape.0 as isize),
+            },
+            position_in_parent: position.coords,
+            ..self.params
+        };
+
+        count!(LayerAllocations, data.len());
+
+        CellMap {
+            data,
+            metadata: params.into(),
+            params,
+            layer_type: PhantomData,
+        }
+    }
+
+    /// Shrinks the map to the smallest bounds containing every cell of `layer` for which
+    /// `is_valid` returns `true`, returning the new bounds.
+    ///
+    /// Cells keep their positions in the parent frame, so only the bounds change. If there are no
+    /// valid cells the map is cropped to empty bounds at its current minimum corner.
+    pub fn crop_to_valid<F>(&mut self, layer: L, is_valid: F) -> Bounds
+    where
+        F: Fn(&T) -> bool,
+    {
+        let (min, max) = self[layer]
+            .indexed_iter()
+            .filter(|(_, v)| is_valid(v))
+            .fold(
+                (
+                    Point2::new(usize::MAX, usize::MAX),
+                    Point2::new(usize::MIN, usize::MIN),
+                ),
+                |(min, max), ((y, x), _)| {
+                    (
+                        min.inf(&Point2::new(x, y)),
+                        max.sup(&Point2::new(x + 1, y + 1)),
+                    )
+                },
+            );
+        let slice = if min.x <= max.x {
+            Vector2::new((min.x, max.x), (min.y, max.y))
+        } else {
+            Vector2::new((0, 0), (0, 0))
+        };
+        trace_span!("crop_to_valid", old_bounds = ?self.cell_bounds(), slice = ?slice);
+
+        let old = self.metadata.cell_bounds;
+        let bounds = Bounds {
+            x: (old.x.0 + slice.x.0 as isize, old.x.0 + slice.x.1 as isize),
+            y: (old.y.0 + slice.y.0 as isize, old.y.0 + slice.y.1 as isize),
+        };
+
+        for layer in self.data.iter_mut() {
+            *layer = layer
+                .slice(s![slice.y.0..slice.y.1, slice.x.0..slice.x.1])
+                .to_owned();
+        }
+        self.metadata.cell_bounds = bounds;
+        self.params.cell_bounds = bounds;
+        self.metadata.num_cells = bounds.get_num_cells();
+
+        bounds
+    }
+
+    /// Gets the `ndarray` shape of a window with the given `semi_width`, or an error if the window
+    /// would be larger than the map.
+    fn window_shape(&self, semi_width: Vector2<usize>) -> Result<(usize, usize), Error> {
+        let cells = self.num_cells();
+        let window_size = semi_width * 2 + Vector2::new(1, 1);
+
+        if window_size.x > cells.x || window_size.y > cells.y {
+            Err(Error::WindowLargerThanMap(window_size, cells))
+        } else {
+            Ok((window_size.y, window_size.x))
+        }
+    }
+
+    /// Gets a mutable view of the cells in the given layer which are the centre of a full window of
+    /// the given `semi_width`.
+    fn window_interior_mut(
+        &mut self,
+        layer: L,
+        semi_width: Vector2<usize>,
+    ) -> Result<ArrayViewMut2<'_, T>, Error> {
+        let cells = self.num_cells();
+
+        Ok(self.try_layer_mut(layer)?.slice_mut(s![
+            semi_width.y..cells.y - semi_width.y,
+            semi_width.x..cells.x - semi_width.x
+        ]))
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Clone + Send + Sync,
+{
+    /// Parallel version of [`CellMap::window_map()`], which evaluates windows on the `rayon`
+    /// thread pool.
+    pub fn par_window_map<F>(
+        &mut self,
+        src_layer: L,
+        dst_layer: L,
+        semi_width: Vector2<usize>,
+        func: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(ArrayView2<T>) -> T + Send + Sync,
+    {
+        trace_span!(
+            "par_window_map",
+            num_cells = self.num_cells().iter().product::<usize>()
+        );
+        let window_shape = self.window_shape(semi_width)?;
+
+        let mapped =
+            Zip::from(self.try_layer(src_layer)?.windows(window_shape)).par_map_collect(&func);
+
+        self.window_interior_mut(dst_layer, semi_width)?
+            .assign(&mapped);
+
+        Ok(())
+    }
 }
 
 impl<L, T> CellMap<L, T>
@@ -423,6 +958,7 @@ where
     pub fn new(params: CellMapParams) -> Self {
         let data =
             vec![Array2::from_elem(params.cell_bounds.get_shape(), T::default()); L::NUM_LAYERS];
+        count!(LayerAllocations, L::NUM_LAYERS);
 
         Self {
             data,
@@ -438,19 +974,19 @@ where
     // NOTE: It doesn't seem possible to resize an ndarray in place, so we have to allocate a new
     // one.
     pub fn resize(&mut self, new_bounds: Bounds) {
+        trace_span!("resize", old_bounds = ?self.cell_bounds(), new_bounds = ?new_bounds);
+
         // Allocate new data
         let mut data = vec![Array2::from_elem(new_bounds.get_shape(), T::default()); L::NUM_LAYERS];
-
-        // Get the slice describing the position of the old map inside the new map, based on the
-        // bounds. If there's no intersection then we can skip this step
-        if let Some(old_in_new) = new_bounds.get_slice_of_other(&self.metadata.cell_bounds) {
-            // Get the slice of new relative to old. Unwrap is ok sice we already know there's an
-            // intersection.
-            let new_in_old = self
-                .metadata
-                .cell_bounds
-                .get_slice_of_other(&new_bounds)
-                .unwrap();
+        count!(LayerAllocations, L::NUM_LAYERS);
+
+        // Get the slices describing the position of the old map inside the new map and of the new
+        // map inside the old one, based on the bounds. If there's no intersection then we can skip
+        // this step
+        if let (Some(old_in_new), Some(new_in_old)) = (
+            new_bounds.get_slice_of_other(&self.metadata.cell_bounds),
+            self.metadata.cell_bounds.get_slice_of_other(&new_bounds),
+        ) {
             for (new, old) in data.iter_mut().zip(self.data.iter()) {
                 new.slice_mut(s![
                     old_in_new.y.0..old_in_new.y.1,
@@ -469,6 +1005,15 @@ where
         self.metadata.num_cells = new_bounds.get_num_cells();
     }
 
+    /// Sets every cell in the layers of the given group to `T::default()`, see
+    /// [`Layer::groups()`]. Use [`CellMap::reset_layer()`] to use each layer's own default
+    /// instead.
+    pub fn clear_group(&mut self, group: &str) {
+        for layer in L::in_group(group) {
+            self[layer].fill(T::default());
+        }
+    }
+
     /// Merge `other` into self, resizing `self` so that `other` will be fully included in the map.
     ///
     /// Both maps should belong to the same parent frame, and `other.cell_size <= self.cell_size`.
@@ -479,14 +1024,42 @@ where
     /// new value in `self`. The first argument is the value of the cell in `self`, while the
     /// second argument will be the values from cells in `other` whose centres lie within the cell
     /// in `self`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the maps can't be aligned, see [`CellMap::try_merge()`] for a version which
+    /// returns an error instead.
+    #[allow(clippy::panic)]
     pub fn merge<F: Fn(&T, &[T]) -> T>(&mut self, other: &CellMap<L, T>, func: F) {
+        if let Err(e) = self.try_merge(other, func) {
+            panic!("Failed to merge maps: {}", e);
+        }
+    }
+
+    /// Merge `other` into self, as with [`CellMap::merge()`], but returning an error rather than
+    /// panicking if a cell of `other` can't be placed in `self`, for example due to numerical
+    /// issues aligning rotated maps.
+    ///
+    /// If an error is returned `self` will already have been resized to include `other`, but
+    /// may only have some of its layers merged.
+    pub fn try_merge<F: Fn(&T, &[T]) -> T>(
+        &mut self,
+        other: &CellMap<L, T>,
+        func: F,
+    ) -> Result<(), Error> {
+        trace_span!(
+            "merge",
+            num_cells = self.num_cells().iter().product::<usize>(),
+            other_num_cells = other.num_cells().iter().product::<usize>()
+        );
+
         // First get the bounds of `other` wrt `self`, which we have to do by accounting for the
         // potential different alignment of `other` wrt `parent`. We do this by getting the corner
         // points, transforming from `other` to `parent`, then from `parent` to `self`. We have to
         // transform all corner points because rotation may lead to the corners being in different
         // positions than when aligned to `other`.
         let other_bounds = other.cell_bounds();
-        let corners_in_other = vec![
+        let corners_in_other = [
             Point2::new(other_bounds.x.0, other_bounds.y.0).cast(),
             Point2::new(other_bounds.x.1, other_bounds.y.0).cast() + Vector2::new(1.0, 0.0),
             Point2::new(other_bounds.x.0, other_bounds.y.1).cast() + Vector2::new(0.0, 1.0),
@@ -496,33 +1069,14 @@ where
             .iter()
             .map(|c| other.to_parent().transform_point(c))
             .collect();
-        let other_bl_parent = Point2::new(
-            corners_in_parent
-                .iter()
-                .min_by_key(|c| c.x.floor() as isize)
-                .unwrap()
-                .x
-                .floor(),
-            corners_in_parent
-                .iter()
-                .min_by_key(|c| c.y.floor() as isize)
-                .unwrap()
-                .y
-                .floor(),
-        );
-        let other_ur_parent = Point2::new(
-            corners_in_parent
-                .iter()
-                .max_by_key(|c| c.x.ceil() as isize)
-                .unwrap()
-                .x
-                .ceil(),
-            corners_in_parent
-                .iter()
-                .max_by_key(|c| c.y.ceil() as isize)
-                .unwrap()
-                .y
-                .ceil(),
+        let other_bl_parent = corners_in_parent
+            .iter()
+            .fold(Point2::new(f64::INFINITY, f64::INFINITY), |bl, c| {
+                Point2::new(bl.x.min(c.x.floor()), bl.y.min(c.y.floor()))
+            });
+        let other_ur_parent = corners_in_parent.iter().fold(
+            Point2::new(f64::NEG_INFINITY, f64::NEG_INFINITY),
+            |ur, c| Point2::new(ur.x.max(c.x.ceil()), ur.y.max(c.y.ceil())),
         );
         let other_in_self =
             Bounds::from_corner_positions(&self.metadata, other_bl_parent, other_ur_parent);
@@ -538,12 +1092,9 @@ where
         self.resize(new_bounds);
 
         // Get the index offset to go from an index into self to the 2D storage array (see store)
-        let store_slice_in_new = if let Some(slice) = new_bounds.get_slice_of_other(&other_in_self)
-        {
-            slice
-        } else {
-            unreachable!("Other was not inside self's new bounds");
-        };
+        let store_slice_in_new = new_bounds
+            .get_slice_of_other(&other_in_self)
+            .ok_or(Error::BoundsOutsideMap(other_in_self, new_bounds))?;
 
         // For each layer in the map
         for layer in L::all() {
@@ -554,26 +1105,28 @@ where
             // For each cell in other get its position in parent, convert that to a cell index in
             // self, and add that cell's value to the store
             for ((_, pos), val) in other.iter().layer(layer.clone()).positioned() {
-                // The index of pos in self
-                if let Some(idx) = self.index(pos) {
-                    // Get the index into the store array by subtracting the store offset
-                    let store_idx = (idx.cast() - store_offset).map(|e| e as usize);
-
-                    // Mutate the store vector by pushing val into it
-                    if let Some(vec) = store.get_mut(store_idx.as_array2_index()) {
-                        vec.push(val.clone());
-                    } else {
-                        unreachable!("Store index {} was invalid", store_idx);
-                    }
-                } else {
-                    // Point was outside the map, this shouldn't happen
-                    unreachable!("Point in other ({}) was outside self during merge", pos);
-                }
+                // The index of pos in self, which should always be inside the resized map
+                let idx = self
+                    .index(pos)
+                    .ok_or_else(|| Error::PositionOutsideMap("merge".into(), pos))?;
+
+                // Get the index into the store array by subtracting the store offset, and push
+                // val into the store vector
+                let vec = idx
+                    .x
+                    .checked_sub(store_offset.x)
+                    .zip(idx.y.checked_sub(store_offset.y))
+                    .and_then(|(x, y)| store.get_mut((y, x)))
+                    .ok_or_else(|| {
+                        Error::IndexOutsideLayer(layer.to_index(), idx, self.metadata.cell_bounds)
+                    })?;
+                vec.push(val.clone());
             }
 
             // Iterate over the store and self, calling the merge function with the value in self
             // and the values in the store
-            for (self_val, store_vec) in self.data[layer.to_index()]
+            for (self_val, store_vec) in self
+                .try_layer_mut(layer.clone())?
                 .slice_mut(s![
                     store_slice_in_new.y.0..store_slice_in_new.y.1,
                     store_slice_in_new.x.0..store_slice_in_new.x.1,
@@ -584,6 +1137,60 @@ where
                 *self_val = func(self_val, store_vec.as_slice());
             }
         }
+
+        Ok(())
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Default + Clone + FromLayerDefault,
+{
+    /// Returns the value cells of `layer` are reset to, which is the layer's
+    /// [`Layer::default_value()`] if it has one, or `T::default()` otherwise.
+    pub fn layer_default(&self, layer: &L) -> T {
+        layer
+            .default_value()
+            .map_or_else(T::default, T::from_layer_default)
+    }
+
+    /// Sets every cell in `layer` to its default value, see [`CellMap::layer_default()`].
+    pub fn reset_layer(&mut self, layer: L) {
+        let value = self.layer_default(&layer);
+        self[layer].fill(value);
+    }
+
+    /// Sets every cell in every layer to that layer's default value, see
+    /// [`CellMap::layer_default()`].
+    pub fn reset_all(&mut self) {
+        for layer in L::all() {
+            self.reset_layer(layer);
+        }
+    }
+
+    /// Resizes the map into the new bounds, as with [`CellMap::resize()`], but filling newly added
+    /// cells with each layer's default value rather than `T::default()`.
+    ///
+    /// This is useful for rolling maps, where cells scrolled into the map must start from the
+    /// layer's initial value, such as an unknown occupancy probability.
+    pub fn resize_with_defaults(&mut self, new_bounds: Bounds) {
+        let old_bounds = self.cell_bounds();
+        self.resize(new_bounds);
+
+        for layer in L::all() {
+            if layer.default_value().is_none() {
+                continue;
+            }
+
+            let value = self.layer_default(&layer);
+            for ((y, x), v) in self[layer].indexed_iter_mut() {
+                let cell = Point2::new(new_bounds.x.0 + x as isize, new_bounds.y.0 + y as isize);
+                if !old_bounds.contains(cell) {
+                    *v = value.clone();
+                }
+            }
+        }
     }
 }
 
@@ -594,7 +1201,7 @@ where
     type Output = Array2<T>;
 
     fn index(&self, index: L) -> &Self::Output {
-        &self.data[index.to_index()]
+        unwrap_checked(self.try_layer(index))
     }
 }
 
@@ -603,7 +1210,7 @@ where
     L: Layer,
 {
     fn index_mut(&mut self, index: L) -> &mut Self::Output {
-        &mut self.data[index.to_index()]
+        unwrap_checked(self.try_layer_mut(index))
     }
 }
 
@@ -613,8 +1220,10 @@ where
 {
     type Output = T;
 
-    fn index(&self, index: (L, Point2<usize>)) -> &Self::Output {
-        &self[index.0][(index.1.y, index.1.x)]
+    fn index(&self, (layer, index): (L, Point2<usize>)) -> &Self::Output {
+        unwrap_checked(self.get(layer.clone(), index).ok_or_else(|| {
+            Error::IndexOutsideLayer(layer.to_index(), index, self.metadata.cell_bounds)
+        }))
     }
 }
 
@@ -622,8 +1231,112 @@ impl<L, T> IndexMut<(L, Point2<usize>)> for CellMap<L, T>
 where
     L: Layer,
 {
-    fn index_mut(&mut self, index: (L, Point2<usize>)) -> &mut Self::Output {
-        &mut self[index.0][(index.1.y, index.1.x)]
+    fn index_mut(&mut self, (layer, index): (L, Point2<usize>)) -> &mut Self::Output {
+        let cell_bounds = self.metadata.cell_bounds;
+        unwrap_checked(
+            self.get_mut(layer.clone(), index)
+                .ok_or_else(|| Error::IndexOutsideLayer(layer.to_index(), index, cell_bounds)),
+        )
+    }
+}
+
+impl CellMapParams {
+    /// Creates parameters for a map with `num_cells` cells of the given size, whose centre is as
+    /// close to the parent-frame `centre` as possible.
+    ///
+    /// The map's cells are aligned with the parent frame's origin, which is the map's
+    /// `position_in_parent`, so the map's centre is moved onto the nearest cell boundary, or for
+    /// an odd number of cells the nearest cell centre. All other parameters are their defaults.
+    pub fn centred(
+        num_cells: Vector2<usize>,
+        cell_size: Vector2<f64>,
+        centre: Point2<f64>,
+    ) -> Self {
+        let min = Point2::new(
+            (centre.x / cell_size.x - num_cells.x as f64 / 2.0).round() as isize,
+            (centre.y / cell_size.y - num_cells.y as f64 / 2.0).round() as isize,
+        );
+
+        Self::from_min_cell(min, num_cells, cell_size)
+    }
+
+    /// Creates parameters for a map with `num_cells` cells of the given size, whose lowest corner
+    /// is in the cell containing the parent-frame `origin`.
+    ///
+    /// The map's cells are aligned with the parent frame's origin, which is the map's
+    /// `position_in_parent`, so if `origin` isn't on a cell boundary the map starts at the
+    /// boundary below it. All other parameters are their defaults.
+    pub fn from_corner(
+        origin: Point2<f64>,
+        num_cells: Vector2<usize>,
+        cell_size: Vector2<f64>,
+    ) -> Self {
+        let params = Self {
+            cell_size,
+            ..Default::default()
+        };
+        let min = CellMapMetadata::from(params).get_cell(origin);
+
+        Self::from_min_cell(min, num_cells, cell_size)
+    }
+
+    /// Creates parameters for a map with `num_cells` cells of the given size, starting from the
+    /// given map-frame cell.
+    fn from_min_cell(
+        min: Point2<isize>,
+        num_cells: Vector2<usize>,
+        cell_size: Vector2<f64>,
+    ) -> Self {
+        Self {
+            cell_size,
+            cell_bounds: Bounds {
+                x: (min.x, min.x + num_cells.x as isize),
+                y: (min.y, min.y + num_cells.y as isize),
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl BoundaryRule {
+    /// Returns the cell containing the map-frame `coordinate`, measured in cells, along an axis
+    /// with the given `cell_size`.
+    pub(crate) fn cell(&self, coordinate: f64, cell_size: f64, precision: f64) -> isize {
+        match self {
+            Self::EpsilonSnap => (coordinate + cell_size * precision).floor() as isize,
+            Self::HalfOpen => coordinate.floor() as isize,
+            Self::RoundNearest => {
+                let quanta_per_cell = (1.0 / precision).round();
+                if !quanta_per_cell.is_finite() || quanta_per_cell < 1.0 {
+                    return coordinate.floor() as isize;
+                }
+
+                // Both values are whole numbers, so the remainder in div_euclid is exact
+                (coordinate * quanta_per_cell)
+                    .round()
+                    .div_euclid(quanta_per_cell) as isize
+            }
+        }
+    }
+
+    /// Returns the value used to store the rule in binary formats, where the default is `0`.
+    pub(crate) fn to_code(self) -> u8 {
+        match self {
+            Self::EpsilonSnap => 0,
+            Self::HalfOpen => 1,
+            Self::RoundNearest => 2,
+        }
+    }
+
+    /// Returns the rule stored as the given value by [`BoundaryRule::to_code()`].
+    #[cfg(any(feature = "hdf5", feature = "mmap", feature = "protobuf"))]
+    pub(crate) fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::EpsilonSnap),
+            1 => Some(Self::HalfOpen),
+            2 => Some(Self::RoundNearest),
+            _ => None,
+        }
     }
 }
 
@@ -635,6 +1348,8 @@ impl Default for CellMapParams {
             cell_boundary_precision: 1e-10,
             rotation_in_parent_rad: 0.0,
             position_in_parent: Vector2::zeros(),
+            convention: GridConvention::default(),
+            boundary_rule: BoundaryRule::default(),
         }
     }
 }
@@ -820,3 +1535,27 @@ impl Default for Bounds {
         Self::empty()
     }
 }
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Converts a cell index, which is `(x, y)`, into the `(row, column)` index of the cell in a
+/// layer's `ndarray` array, which is `(y, x)`.
+pub fn xy_to_nd(index: Point2<usize>) -> (usize, usize) {
+    (index.y, index.x)
+}
+
+/// Converts the `(row, column)` index of a cell in a layer's `ndarray` array into the cell's
+/// `(x, y)` index, the inverse of [`xy_to_nd()`].
+pub fn nd_to_xy((row, column): (usize, usize)) -> Point2<usize> {
+    Point2::new(column, row)
+}
+
+/// Returns the number of whole cells of size `cell_size` which fit within `distance`, allowing
+/// for rounding errors with [`DISTANCE_TOLERANCE`].
+pub(crate) fn cells_within(distance: f64, cell_size: f64) -> usize {
+    (distance / cell_size * (1.0 + DISTANCE_TOLERANCE))
+        .floor()
+        .max(0.0) as usize
+}
@@ -10,7 +10,10 @@ use nalgebra::{Affine2, Vector2};
 use ndarray::Array2;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::{cell_map::Bounds, CellMap, CellMapParams, Error, Layer};
+use crate::{
+    cell_map::{BoundaryRule, Bounds, GridConvention},
+    CellMap, CellMapParams, Error, Layer,
+};
 
 // ------------------------------------------------------------------------------------------------
 // STRUCTS
@@ -22,7 +25,8 @@ pub struct CellMapFile<L, T>
 where
     L: Layer,
 {
-    /// Number of layers stored in the map
+    /// Number of layers stored in the file, which is less than the number of layers in the map if
+    /// only a group of layers was stored, see [`CellMap::serialise_group()`].
     pub num_layers: usize,
 
     /// The order of layers in the map.
@@ -46,6 +50,18 @@ where
     /// The translation that goes from the parent frame to the map frame, in parent frame units.
     pub from_parent_translation: Vector2<f64>,
 
+    /// The direction of the map's `y` axis and the anchor of its position within a cell.
+    ///
+    /// Defaults to [`GridConvention::default()`] if it's missing from the file.
+    #[serde(default)]
+    pub convention: GridConvention,
+
+    /// How positions near cell boundaries are assigned to cells.
+    ///
+    /// Defaults to [`BoundaryRule::default()`] if it's missing from the file.
+    #[serde(default)]
+    pub boundary_rule: BoundaryRule,
+
     /// The affine transformation matrix that converts from points in the parent frame to the map frame.
     pub from_parent_matrix: Affine2<f64>,
 
@@ -62,6 +78,9 @@ where
     L: Layer,
 {
     /// Converts this file into a [`CellMap`].
+    ///
+    /// Returns [`Error::WrongNumberOfLayers`] if the file only contains some of the map's layers,
+    /// which can instead be loaded into an existing map with [`CellMap::load_layers()`].
     pub fn into_cell_map(self) -> Result<CellMap<L, T>, Error> {
         let params = CellMapParams {
             cell_size: self.cell_size,
@@ -69,6 +88,8 @@ where
             rotation_in_parent_rad: self.from_parent_angle_rad,
             position_in_parent: self.from_parent_translation,
             cell_boundary_precision: self.cell_boundary_precision,
+            convention: self.convention,
+            boundary_rule: self.boundary_rule,
         };
 
         CellMap::new_from_data(params, self.data)
@@ -81,16 +102,32 @@ where
     T: Clone + Serialize,
 {
     pub(crate) fn new(map: &CellMap<L, T>) -> Self {
+        Self::new_with_layers(map, L::all())
+    }
+
+    /// Builds a file containing only the given layers of the map.
+    pub(crate) fn new_with_layers(map: &CellMap<L, T>, layers: Vec<L>) -> Self {
+        count!(FileConversions);
+        trace_span!(
+            "to_cell_map_file",
+            num_cells = map.num_cells().iter().product::<usize>()
+        );
+
         Self {
-            num_layers: L::NUM_LAYERS,
-            layers: L::all(),
+            num_layers: layers.len(),
+            data: layers
+                .iter()
+                .map(|l| map.data[l.to_index()].clone())
+                .collect(),
+            layers,
             cell_bounds: map.metadata.cell_bounds,
             cell_size: map.metadata.cell_size,
             cell_boundary_precision: map.metadata.cell_boundary_precision,
             from_parent_angle_rad: map.params.rotation_in_parent_rad,
             from_parent_translation: map.params.position_in_parent,
+            convention: map.params.convention,
+            boundary_rule: map.params.boundary_rule,
             from_parent_matrix: map.metadata.to_parent.inverse(),
-            data: map.data.clone(),
         }
     }
 }
@@ -104,6 +141,7 @@ where
     /// the written file is JSON.
     #[cfg(feature = "json")]
     pub fn write_json<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), Error> {
+        trace_span!("write_json", path = %path.as_ref().display());
         let file = std::fs::OpenOptions::new()
             .create(true)
             .append(false)
@@ -126,6 +164,7 @@ where
     /// Loads a [`CellMapFile`] from the given path, which points to a JSON file.
     #[cfg(feature = "json")]
     pub fn from_json<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Error> {
+        trace_span!("from_json", path = %path.as_ref().display());
         // Open the file
         let file = std::fs::File::open(path).map_err(Error::IoError)?;
         let map_file: CellMapFile<L, T> =
@@ -0,0 +1,346 @@
+//! Provides the [`CellView`] trait, which maps the fields of a struct onto layers of a map so that
+//! all the values of a cell can be read and written together.
+//!
+//! The trait is normally derived. Each field is read from the layer variant with the same name in
+//! `UpperCamelCase`, which can be overridden with `#[cell_view(variant = Name)]`, and all fields
+//! must have the map's cell type:
+//!
+//! ```
+//! use cell_map::{Bounds, CellMap, CellMapParams, CellView, Layer};
+//! use nalgebra::Point2;
+//!
+//! #[derive(Layer, Clone, Debug)]
+//! enum MyLayer {
+//!     Height,
+//!     Roughness,
+//!     TraversalCost,
+//! }
+//!
+//! #[derive(CellView, Debug, PartialEq)]
+//! #[cell_view(layer = MyLayer)]
+//! struct Terrain {
+//!     height: f64,
+//!     #[cell_view(variant = TraversalCost)]
+//!     cost: f64,
+//! }
+//!
+//! let mut map = CellMap::<MyLayer, f64>::new_from_elem(
+//!     CellMapParams {
+//!         cell_bounds: Bounds::new((0, 5), (0, 5)).unwrap(),
+//!         ..Default::default()
+//!     },
+//!     1.0,
+//! );
+//!
+//! map.set_cell_view(Point2::new(1, 2), &Terrain { height: 2.0, cost: 0.5 })
+//!     .unwrap();
+//! assert_eq!(
+//!     map.cell_view::<Terrain>(Point2::new(1, 2)),
+//!     Some(Terrain { height: 2.0, cost: 0.5 })
+//! );
+//!
+//! let steep = map
+//!     .cell_views::<Terrain>()
+//!     .filter(|(_, t)| t.height > 1.5)
+//!     .count();
+//! assert_eq!(steep, 1);
+//! ```
+//!
+//! Since each layer is stored separately a cell's values aren't next to each other in memory, so
+//! views hold copies of the values rather than references into the map.
+//!
+//! When most accesses read every value of a cell it can be faster to store the cells as structs
+//! instead. [`CellMap::to_aos()`] converts a map into a single-layer map of [`CellView`] structs
+//! with the [`Single`] layer type, and [`CellMap::to_soa()`] converts it back.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::Point2;
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::unwrap_checked, CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// The layer type of a map with only one layer, such as the array-of-structs maps built by
+/// [`CellMap::to_aos()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Single {
+    /// The only layer.
+    Cells,
+}
+
+// ------------------------------------------------------------------------------------------------
+// TRAITS
+// ------------------------------------------------------------------------------------------------
+
+/// A struct whose fields are the values of a cell in a set of layers, see the
+/// [module documentation](self).
+pub trait CellView<L, T>: Sized
+where
+    L: Layer,
+{
+    /// Returns the layer each field is stored in, in field order.
+    fn layers() -> Vec<L>;
+
+    /// Builds the view from the values of a cell in each of [`CellView::layers()`], in the same
+    /// order.
+    fn from_values(values: &[T]) -> Self;
+
+    /// Returns the values of the view's fields, in [`CellView::layers()`] order.
+    fn to_values(&self) -> Vec<T>;
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    /// Returns the values of the cell at `index` as a [`CellView`], or `None` if the index is
+    /// outside the map.
+    pub fn cell_view<V>(&self, index: Point2<usize>) -> Option<V>
+    where
+        V: CellView<L, T>,
+    {
+        let values = V::layers()
+            .into_iter()
+            .map(|layer| self.get(layer, index).cloned())
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(V::from_values(&values))
+    }
+
+    /// Sets the values of the cell at `index` from a [`CellView`], leaving layers which aren't in
+    /// the view unchanged.
+    ///
+    /// Returns [`Error::IndexOutsideMap`] if the index is outside the map.
+    pub fn set_cell_view<V>(&mut self, index: Point2<usize>, view: &V) -> Result<(), Error>
+    where
+        V: CellView<L, T>,
+    {
+        if !self.index_in_map(index) {
+            return Err(Error::IndexOutsideMap(index));
+        }
+
+        for (layer, value) in V::layers().into_iter().zip(view.to_values()) {
+            self.set(layer, index, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns an iterator over the index and [`CellView`] of every cell in the map, in the same
+    /// order as [`CellMap::iter()`] visits the cells of each layer.
+    pub fn cell_views<V>(&self) -> impl Iterator<Item = (Point2<usize>, V)> + '_
+    where
+        V: CellView<L, T>,
+    {
+        let layers: Vec<_> = V::layers().iter().map(|l| &self[l.clone()]).collect();
+        let num_cells = self.num_cells();
+
+        (0..num_cells.y)
+            .flat_map(move |y| (0..num_cells.x).map(move |x| (x, y)))
+            .map(move |(x, y)| {
+                let values: Vec<T> = layers.iter().map(|l| l[(y, x)].clone()).collect();
+                (Point2::new(x, y), V::from_values(&values))
+            })
+    }
+
+    /// Calls `func` with a [`CellView`] of every cell in the map, writing the modified view back
+    /// into the map.
+    pub fn update_cell_views<V, F>(&mut self, mut func: F)
+    where
+        V: CellView<L, T>,
+        F: FnMut(Point2<usize>, &mut V),
+    {
+        let layers = V::layers();
+        let num_cells = self.num_cells();
+
+        for y in 0..num_cells.y {
+            for x in 0..num_cells.x {
+                let values: Vec<T> = layers
+                    .iter()
+                    .map(|l| self[l.clone()][(y, x)].clone())
+                    .collect();
+                let mut view = V::from_values(&values);
+
+                func(Point2::new(x, y), &mut view);
+
+                for (layer, value) in layers.iter().zip(view.to_values()) {
+                    self[layer.clone()][(y, x)] = value;
+                }
+            }
+        }
+    }
+
+    /// Converts this map into an array-of-structs map, whose single layer holds a [`CellView`] of
+    /// each cell. Layers which aren't in the view are dropped.
+    pub fn to_aos<V>(&self) -> CellMap<Single, V>
+    where
+        V: CellView<L, T>,
+    {
+        let layers: Vec<_> = V::layers().iter().map(|l| &self[l.clone()]).collect();
+        let data = Array2::from_shape_fn(self.cell_bounds().get_shape(), |(y, x)| {
+            let values: Vec<T> = layers.iter().map(|l| l[(y, x)].clone()).collect();
+            V::from_values(&values)
+        });
+
+        // The data was built with the map's shape, so needs no checking
+        CellMap::new_from_data_unchecked(self.params(), vec![data])
+    }
+}
+
+impl<V> CellMap<Single, V> {
+    /// Converts this array-of-structs map back into a map with a layer for each field of the
+    /// [`CellView`]. Layers which aren't in the view are filled with `T::default()`.
+    pub fn to_soa<L, T>(&self) -> CellMap<L, T>
+    where
+        L: Layer,
+        T: Clone + Default,
+        V: CellView<L, T>,
+    {
+        let mut map = CellMap::new(self.params());
+        let cells = &self[Single::Cells];
+
+        for (layer, i) in V::layers().into_iter().zip(0..) {
+            map[layer] = cells.map(|v| v.to_values().swap_remove(i));
+        }
+
+        map
+    }
+}
+
+impl Layer for Single {
+    const NUM_LAYERS: usize = 1;
+    const FIRST: Self = Self::Cells;
+
+    fn to_index(&self) -> usize {
+        0
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => Self::Cells,
+            _ => unwrap_checked(Err(Error::InvalidLayerIndex(index, Self::NUM_LAYERS))),
+        }
+    }
+
+    fn all() -> Vec<Self> {
+        vec![Self::Cells]
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Pair {
+        a: f64,
+        b: f64,
+    }
+
+    // The derive can't be used inside this crate, so implement the trait as it would
+    impl CellView<TestLayers, f64> for Pair {
+        fn layers() -> Vec<TestLayers> {
+            vec![TestLayers::Layer2, TestLayers::Layer0]
+        }
+
+        fn from_values(values: &[f64]) -> Self {
+            Self {
+                a: values[0],
+                b: values[1],
+            }
+        }
+
+        fn to_values(&self) -> Vec<f64> {
+            vec![self.a, self.b]
+        }
+    }
+
+    #[test]
+    fn cell_views() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 3), (0, 2)).unwrap(),
+                cell_size: Vector2::new(1.0, 1.0),
+                ..Default::default()
+            },
+            1.0,
+        );
+
+        map.set_cell_view(Point2::new(2, 1), &Pair { a: 3.0, b: 4.0 })
+            .unwrap();
+        assert_eq!(map[(TestLayers::Layer2, Point2::new(2, 1))], 3.0);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(2, 1))], 4.0);
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(2, 1))], 1.0);
+        assert_eq!(
+            map.cell_view(Point2::new(2, 1)),
+            Some(Pair { a: 3.0, b: 4.0 })
+        );
+        assert_eq!(map.cell_view::<Pair>(Point2::new(3, 1)), None);
+        assert!(map
+            .set_cell_view(Point2::new(0, 2), &Pair { a: 0.0, b: 0.0 })
+            .is_err());
+
+        // Views are visited in the same order as the cells of a layer
+        let views: Vec<(Point2<usize>, Pair)> = map.cell_views().collect();
+        assert_eq!(views.len(), 6);
+        assert_eq!(views[1].0, Point2::new(1, 0));
+        assert_eq!(views[5], (Point2::new(2, 1), Pair { a: 3.0, b: 4.0 }));
+
+        map.update_cell_views(|_, p: &mut Pair| p.a += p.b);
+        assert_eq!(map[(TestLayers::Layer2, Point2::new(2, 1))], 7.0);
+        assert_eq!(map[(TestLayers::Layer2, Point2::new(0, 0))], 2.0);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(0, 0))], 1.0);
+    }
+
+    #[test]
+    fn aos_conversion() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-1, 3), (2, 4)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                position_in_parent: Vector2::new(1.0, -2.0),
+                ..Default::default()
+            },
+            1.0,
+        );
+        map[(TestLayers::Layer0, Point2::new(3, 1))] = 5.0;
+        map[(TestLayers::Layer2, Point2::new(3, 1))] = 6.0;
+
+        let aos = map.to_aos::<Pair>();
+        assert_eq!(aos.cell_bounds(), map.cell_bounds());
+        assert_eq!(aos.to_parent(), map.to_parent());
+        assert_eq!(
+            aos[(Single::Cells, Point2::new(3, 1))],
+            Pair { a: 6.0, b: 5.0 }
+        );
+        assert_eq!(
+            aos[(Single::Cells, Point2::new(0, 0))],
+            Pair { a: 1.0, b: 1.0 }
+        );
+
+        // Converting back restores the view's layers, while other layers are defaulted
+        let soa: CellMap<TestLayers, f64> = aos.to_soa();
+        assert_eq!(soa[TestLayers::Layer0], map[TestLayers::Layer0]);
+        assert_eq!(soa[TestLayers::Layer2], map[TestLayers::Layer2]);
+        assert!(soa[TestLayers::Layer1].iter().all(|&v| v == 0.0));
+    }
+}
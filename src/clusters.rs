@@ -0,0 +1,159 @@
+//! Provides detection of clusters of connected cells, such as rock or obstacle blobs in a hazard
+//! layer.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::collections::VecDeque;
+
+use nalgebra::{Point2, Vector2};
+use ndarray::Array2;
+
+use crate::{CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A cluster of connected cells found by [`CellMap::clusters()`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cluster {
+    /// The indices of the cells in the cluster.
+    pub cells: Vec<Point2<usize>>,
+
+    /// The mean position of the centres of the cells, in the parent frame.
+    pub centroid: Point2<f64>,
+
+    /// The smallest corner of the parent-frame axis-aligned box containing every cell.
+    pub min: Point2<f64>,
+
+    /// The largest corner of the parent-frame axis-aligned box containing every cell.
+    pub max: Point2<f64>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+{
+    /// Finds the clusters of cells in `layer` for which `predicate` returns `true`, discarding
+    /// clusters with fewer than `min_size` cells.
+    ///
+    /// Cells are in the same cluster if they share an edge or a corner. Clusters are returned in
+    /// the order of their first cell in row-major order, and the bounding box of each cluster
+    /// covers the whole of each of its cells, not just their centres.
+    pub fn clusters<F>(&self, layer: L, predicate: F, min_size: usize) -> Vec<Cluster>
+    where
+        F: Fn(&T) -> bool,
+    {
+        let data = &self[layer];
+        let (rows, cols) = data.dim();
+
+        let mut visited = Array2::from_elem((rows, cols), false);
+        let mut clusters = Vec::new();
+        let mut queue = VecDeque::new();
+
+        for ((y, x), value) in data.indexed_iter() {
+            if visited[(y, x)] || !predicate(value) {
+                continue;
+            }
+
+            // Flood fill the cluster from this cell
+            let mut cells = Vec::new();
+            visited[(y, x)] = true;
+            queue.push_back((y, x));
+
+            while let Some((y, x)) = queue.pop_front() {
+                cells.push(Point2::new(x, y));
+
+                for ny in y.saturating_sub(1)..(y + 2).min(rows) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(cols) {
+                        if !visited[(ny, nx)] && predicate(&data[(ny, nx)]) {
+                            visited[(ny, nx)] = true;
+                            queue.push_back((ny, nx));
+                        }
+                    }
+                }
+            }
+
+            if cells.len() < min_size {
+                continue;
+            }
+
+            let centroid = cells
+                .iter()
+                .map(|&i| self.position_unchecked(i).coords)
+                .sum::<Vector2<f64>>()
+                / cells.len() as f64;
+
+            let (min, max) = cells
+                .iter()
+                .flat_map(|&i| self.metadata.cell_corners_unchecked(i))
+                .fold(
+                    (
+                        Point2::new(f64::INFINITY, f64::INFINITY),
+                        Point2::new(f64::NEG_INFINITY, f64::NEG_INFINITY),
+                    ),
+                    |(min, max), p| (min.inf(&p), max.sup(&p)),
+                );
+
+            clusters.push(Cluster {
+                cells,
+                centroid: centroid.into(),
+                min,
+                max,
+            });
+        }
+
+        clusters
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn clusters() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-4, 4), (0, 6)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // A 2x2 rock, a diagonal pair, and a single cell of noise
+        for &(x, y) in &[(1, 1), (2, 1), (1, 2), (2, 2), (5, 4), (6, 5), (7, 0)] {
+            map[(TestLayers::Layer0, Point2::new(x, y))] = 1.0;
+        }
+
+        let clusters = map.clusters(TestLayers::Layer0, |&v| v > 0.5, 2);
+        assert_eq!(clusters.len(), 2);
+
+        assert_eq!(clusters[0].cells.len(), 4);
+        assert_eq!(clusters[0].centroid, Point2::new(-1.0, 1.0));
+        assert_eq!(clusters[0].min, Point2::new(-1.5, 0.5));
+        assert_eq!(clusters[0].max, Point2::new(-0.5, 1.5));
+
+        assert_eq!(
+            clusters[1].cells,
+            vec![Point2::new(5, 4), Point2::new(6, 5)]
+        );
+        assert_eq!(clusters[1].min, Point2::new(0.5, 2.0));
+        assert_eq!(clusters[1].max, Point2::new(1.5, 3.0));
+
+        assert_eq!(map.clusters(TestLayers::Layer0, |&v| v > 0.5, 1).len(), 3);
+        assert!(map.clusters(TestLayers::Layer1, |&v| v > 0.5, 1).is_empty());
+    }
+}
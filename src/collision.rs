@@ -0,0 +1,436 @@
+//! Provides collision checking of robot footprints against a map, for local planners checking
+//! candidate trajectories.
+//!
+//! A [`Footprint`] is the shape of the robot in its own frame, which is placed in the map's parent
+//! frame by a pose. A footprint covers every cell whose centre lies inside it, as well as the cell
+//! containing the origin of the footprint, so footprints smaller than a cell still cover a cell.
+//!
+//! As well as checking footprints against obstacles, trajectories can be scored against a cost
+//! layer with [`CellMap::trajectory_cost()`], or by their clearance from obstacles with
+//! [`CellMap::min_obstacle_distance_along()`].
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Isometry2, Point2};
+
+use crate::{CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// The shape of a robot, expressed in the robot's own frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Footprint {
+    /// A circle with the given radius, centred on the robot's origin.
+    Circle(f64),
+
+    /// A simple polygon with the given vertices, in either winding order.
+    Polygon(Vec<Point2<f64>>),
+}
+
+/// How the costs of a footprint at each pose of a trajectory are combined by
+/// [`CellMap::trajectory_cost()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CostAggregate {
+    /// The largest cost of any pose.
+    Max,
+
+    /// The sum of the costs of every pose.
+    Sum,
+
+    /// The mean cost of the poses.
+    Mean,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Footprint {
+    /// Returns a rectangular footprint with the given length along the robot's `x` axis and width
+    /// along its `y` axis, centred on the robot's origin.
+    pub fn rectangle(length: f64, width: f64) -> Self {
+        let (x, y) = (length / 2.0, width / 2.0);
+
+        Self::Polygon(vec![
+            Point2::new(-x, -y),
+            Point2::new(x, -y),
+            Point2::new(x, y),
+            Point2::new(-x, y),
+        ])
+    }
+
+    /// Returns whether the given point in the robot's frame is inside the footprint.
+    pub fn contains(&self, point: Point2<f64>) -> bool {
+        match self {
+            Self::Circle(radius) => point.coords.norm() <= *radius,
+            Self::Polygon(vertices) => {
+                // Count the crossings of a ray from the point along +x with the polygon's edges
+                let mut inside = false;
+
+                for (a, b) in vertices.iter().zip(vertices.iter().cycle().skip(1)) {
+                    if (a.y > point.y) != (b.y > point.y)
+                        && point.x < a.x + (point.y - a.y) * (b.x - a.x) / (b.y - a.y)
+                    {
+                        inside = !inside;
+                    }
+                }
+
+                inside
+            }
+        }
+    }
+
+    /// Returns the smallest and largest `x` and `y` coordinates of the footprint in the robot's
+    /// frame.
+    fn extent(&self) -> (Point2<f64>, Point2<f64>) {
+        match self {
+            Self::Circle(radius) => (Point2::new(-radius, -radius), Point2::new(*radius, *radius)),
+            Self::Polygon(vertices) => vertices
+                .iter()
+                .fold((Point2::origin(), Point2::origin()), |(min, max), v| {
+                    (min.inf(v), max.sup(v))
+                }),
+        }
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+{
+    /// Returns whether `footprint`, placed at `pose` in the parent frame, is free of obstacles in
+    /// `layer`, where `is_obstacle` returns `true` for cells which are obstacles.
+    ///
+    /// Footprints which cover cells outside the map are not free, since the map doesn't know
+    /// what's there.
+    pub fn is_footprint_free<F>(
+        &self,
+        footprint: &Footprint,
+        pose: &Isometry2<f64>,
+        layer: L,
+        is_obstacle: F,
+    ) -> bool
+    where
+        F: Fn(&T) -> bool,
+    {
+        let data = &self[layer];
+
+        self.footprint_cells(footprint, pose)
+            .into_iter()
+            .all(|index| index.is_some_and(|i| !is_obstacle(&data[(i.y, i.x)])))
+    }
+
+    /// Checks `footprint` at each of the given poses along a path, returning the index of the
+    /// first pose which isn't free, or `None` if the whole path is free.
+    ///
+    /// See [`CellMap::is_footprint_free()`] for details.
+    pub fn check_path<F>(
+        &self,
+        footprint: &Footprint,
+        poses: &[Isometry2<f64>],
+        layer: L,
+        is_obstacle: F,
+    ) -> Option<usize>
+    where
+        F: Fn(&T) -> bool,
+    {
+        poses
+            .iter()
+            .position(|pose| !self.is_footprint_free(footprint, pose, layer.clone(), &is_obstacle))
+    }
+
+    /// Returns the clearance of each of the given poses, the distance in parent-frame units from
+    /// the origin of the pose to the centre of the nearest obstacle cell in `obstacle_layer`.
+    ///
+    /// A cell is an obstacle if `is_obstacle` returns `true` for its value. If there are no
+    /// obstacles every clearance is `f64::INFINITY`. The obstacles are indexed once for the whole
+    /// trajectory, so this is much cheaper than a search per pose. To score many trajectories
+    /// against the same map, build the index once with [`CellMap::build_index()`] and query it
+    /// directly instead.
+    pub fn min_obstacle_distance_along<F>(
+        &self,
+        poses: &[Isometry2<f64>],
+        obstacle_layer: L,
+        is_obstacle: F,
+    ) -> Vec<f64>
+    where
+        F: Fn(&T) -> bool,
+    {
+        let index = self.build_index(obstacle_layer, is_obstacle);
+
+        poses
+            .iter()
+            .map(|pose| {
+                index
+                    .nearest(pose * Point2::origin())
+                    .map_or(f64::INFINITY, |(_, d)| d)
+            })
+            .collect()
+    }
+
+    /// Returns the index of every cell covered by `footprint` placed at `pose`, or `None` for
+    /// covered cells which are outside the map.
+    fn footprint_cells(
+        &self,
+        footprint: &Footprint,
+        pose: &Isometry2<f64>,
+    ) -> Vec<Option<Point2<usize>>> {
+        let to_parent = self.to_parent();
+        let bounds = self.cell_bounds();
+
+        // Find the range of map-frame cells which the footprint's bounding box could cover, by
+        // transforming each corner of the box into the map frame
+        let (min, max) = footprint.extent();
+        let corners = [
+            Point2::new(min.x, min.y),
+            Point2::new(max.x, min.y),
+            Point2::new(min.x, max.y),
+            Point2::new(max.x, max.y),
+        ];
+        let (cell_min, cell_max) = corners
+            .iter()
+            .map(|c| to_parent.inverse_transform_point(&(pose * c)))
+            .fold(
+                (
+                    Point2::new(f64::INFINITY, f64::INFINITY),
+                    Point2::new(f64::NEG_INFINITY, f64::NEG_INFINITY),
+                ),
+                |(min, max), c| (min.inf(&c), max.sup(&c)),
+            );
+
+        let origin_cell = self.metadata.get_cell(pose * Point2::origin());
+
+        let mut cells = Vec::new();
+
+        for y in (cell_min.y.floor() as isize)..=(cell_max.y.floor() as isize) {
+            for x in (cell_min.x.floor() as isize)..=(cell_max.x.floor() as isize) {
+                let cell = Point2::new(x, y);
+                let centre =
+                    to_parent.transform_point(&Point2::new(x as f64 + 0.5, y as f64 + 0.5));
+
+                if cell == origin_cell || footprint.contains(pose.inverse_transform_point(&centre))
+                {
+                    cells.push(bounds.get_index(cell));
+                }
+            }
+        }
+
+        cells
+    }
+}
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Returns the cost of a trajectory, by finding the cost of `footprint` at each pose and
+    /// combining them with `aggregate`.
+    ///
+    /// The cost of a footprint at a single pose is the largest cost in `cost_layer` of the cells it
+    /// covers, ignoring `NaN` cells, so costs are expected to be non-negative. Returns `None` if
+    /// there are no poses or if the footprint leaves the map at any pose.
+    pub fn trajectory_cost(
+        &self,
+        footprint: &Footprint,
+        poses: &[Isometry2<f64>],
+        cost_layer: L,
+        aggregate: CostAggregate,
+    ) -> Option<f64> {
+        let data = &self[cost_layer];
+        let mut costs = Vec::with_capacity(poses.len());
+
+        for pose in poses {
+            let mut cost: f64 = 0.0;
+
+            for index in self.footprint_cells(footprint, pose) {
+                let index = index?;
+                cost = cost.max(data[(index.y, index.x)]);
+            }
+
+            costs.push(cost);
+        }
+
+        if costs.is_empty() {
+            return None;
+        }
+
+        Some(match aggregate {
+            CostAggregate::Max => costs.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b)),
+            CostAggregate::Sum => costs.iter().sum(),
+            CostAggregate::Mean => costs.iter().sum::<f64>() / costs.len() as f64,
+        })
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, filters::Backend, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn footprints() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-5, 5), (-5, 5)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // An obstacle in the cell from (1.0, 0.0) to (1.5, 0.5)
+        let obstacle = map.index(Point2::new(1.25, 0.25)).unwrap();
+        map[(TestLayers::Layer0, obstacle)] = 1.0;
+        let is_obstacle = |&v: &f64| v > 0.5;
+
+        let circle = Footprint::Circle(0.6);
+        let at = |x, y, angle| Isometry2::new(Vector2::new(x, y), angle);
+        assert!(map.is_footprint_free(
+            &circle,
+            &at(0.25, 0.25, 0.0),
+            TestLayers::Layer0,
+            is_obstacle
+        ));
+        assert!(!map.is_footprint_free(
+            &circle,
+            &at(0.75, 0.25, 0.0),
+            TestLayers::Layer0,
+            is_obstacle
+        ));
+        assert!(map.is_footprint_free(
+            &circle,
+            &at(0.75, 0.25, 0.0),
+            TestLayers::Layer1,
+            is_obstacle
+        ));
+
+        // A long thin robot only collides when it's turned towards the obstacle
+        let rect = Footprint::rectangle(2.0, 0.2);
+        assert!(rect.contains(Point2::new(0.9, 0.05)));
+        assert!(!rect.contains(Point2::new(0.9, 0.15)));
+        assert!(map.is_footprint_free(
+            &rect,
+            &at(1.25, -0.6, 0.0),
+            TestLayers::Layer0,
+            is_obstacle
+        ));
+        assert!(!map.is_footprint_free(
+            &rect,
+            &at(1.25, -0.6, std::f64::consts::FRAC_PI_2),
+            TestLayers::Layer0,
+            is_obstacle
+        ));
+
+        // Footprints smaller than a cell still cover the cell they're in, and footprints leaving
+        // the map aren't free
+        let tiny = Footprint::Circle(0.01);
+        assert!(!map.is_footprint_free(&tiny, &at(1.1, 0.1, 0.0), TestLayers::Layer0, is_obstacle));
+        assert!(!map.is_footprint_free(
+            &circle,
+            &at(2.3, 0.0, 0.0),
+            TestLayers::Layer0,
+            is_obstacle
+        ));
+
+        let path: Vec<_> = (0..8)
+            .map(|i| at(-1.0 + 0.25 * i as f64, 0.25, 0.0))
+            .collect();
+        assert_eq!(
+            map.check_path(&circle, &path, TestLayers::Layer0, is_obstacle),
+            Some(7)
+        );
+        assert_eq!(
+            map.check_path(&circle, &path[..7], TestLayers::Layer0, is_obstacle),
+            None
+        );
+    }
+
+    #[test]
+    fn trajectory_cost() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 10), (0, 3)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // Costs increase along x
+        for ((_, index), cost) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            *cost = index.x as f64;
+        }
+        map[(TestLayers::Layer0, Point2::new(2, 1))] = f64::NAN;
+
+        let footprint = Footprint::Circle(1.0);
+        let poses: Vec<_> = (1..4)
+            .map(|x| Isometry2::translation(x as f64 + 0.5, 1.5))
+            .collect();
+        let cost =
+            |aggregate| map.trajectory_cost(&footprint, &poses, TestLayers::Layer0, aggregate);
+
+        assert_eq!(cost(CostAggregate::Max), Some(4.0));
+        assert_eq!(cost(CostAggregate::Sum), Some(8.0));
+        assert!((cost(CostAggregate::Mean).unwrap() - 8.0 / 3.0).abs() < 1e-12);
+        assert_eq!(
+            map.trajectory_cost(&footprint, &[], TestLayers::Layer0, CostAggregate::Sum),
+            None
+        );
+        assert_eq!(
+            map.trajectory_cost(
+                &footprint,
+                &[Isometry2::translation(0.2, 1.5)],
+                TestLayers::Layer0,
+                CostAggregate::Max
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn min_obstacle_distance_along() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 10), (0, 10)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+        map[(TestLayers::Layer0, Point2::new(5, 5))] = 1.0;
+        map[(TestLayers::Layer0, Point2::new(0, 9))] = 1.0;
+        let is_obstacle = |&v: &f64| v > 0.5;
+
+        let poses: Vec<_> = (0..10)
+            .map(|x| Isometry2::new(Vector2::new(x as f64 + 0.5, 2.5), x as f64))
+            .collect();
+        let clearance = map.min_obstacle_distance_along(&poses, TestLayers::Layer0, is_obstacle);
+
+        // The clearance matches the distance transform at each pose's cell
+        map.distance_transform(
+            TestLayers::Layer0,
+            TestLayers::Layer1,
+            |v| v > 0.5,
+            &Backend::Cpu,
+        )
+        .unwrap();
+        assert_eq!(clearance.len(), poses.len());
+        for (x, d) in clearance.iter().enumerate() {
+            assert!((d - map[(TestLayers::Layer1, Point2::new(x, 2))]).abs() < 1e-12);
+        }
+        assert_eq!(clearance[5], 3.0);
+
+        assert!(map
+            .min_obstacle_distance_along(&poses, TestLayers::Layer2, is_obstacle)
+            .iter()
+            .all(|d| d.is_infinite()));
+    }
+}
@@ -0,0 +1,230 @@
+//! Provides export of maps in a columnar format, as Arrow record batches or Parquet files, for
+//! analysing map archives with SQL engines or dataframe libraries such as pandas.
+//!
+//! Each row of the export is a single cell of a single layer, with the columns:
+//!
+//! | Column       | Type     | Description                                                    |
+//! |--------------|----------|----------------------------------------------------------------|
+//! | `layer`      | `Utf8`   | The layer's name, from its [`Debug`] implementation            |
+//! | `x`          | `Int64`  | The cell's `x` index in the map frame, including bounds offset |
+//! | `y`          | `Int64`  | The cell's `y` index in the map frame, including bounds offset |
+//! | `position_x` | `Float64`| The `x` position of the cell's centre in the parent frame      |
+//! | `position_y` | `Float64`| The `y` position of the cell's centre in the parent frame      |
+//! | `value`      | varies   | The cell's value, see [`ArrowValue`]                           |
+//!
+//! Rows are ordered by layer, then by `y`, then by `x`.
+//!
+//! This module requires the `arrow` feature, and writing Parquet files also requires the
+//! `parquet` feature.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{fmt::Debug, sync::Arc};
+
+use arrow_array::{
+    ArrayRef, BooleanArray, Float32Array, Float64Array, Int32Array, Int64Array, RecordBatch,
+    StringArray, UInt32Array, UInt64Array, UInt8Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use nalgebra::Point2;
+
+use crate::{CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// TRAITS
+// ------------------------------------------------------------------------------------------------
+
+/// Trait for cell values which can be stored in an Arrow column.
+pub trait ArrowValue: Sized {
+    /// The Arrow type of the column.
+    fn data_type() -> DataType;
+
+    /// Builds a column from the given values.
+    fn to_array(values: Vec<Self>) -> ArrayRef;
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer + Debug,
+    T: ArrowValue + Clone,
+{
+    /// Returns the schema of the record batches produced by [`CellMap::to_record_batch()`].
+    pub fn arrow_schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("layer", DataType::Utf8, false),
+            Field::new("x", DataType::Int64, false),
+            Field::new("y", DataType::Int64, false),
+            Field::new("position_x", DataType::Float64, false),
+            Field::new("position_y", DataType::Float64, false),
+            Field::new("value", T::data_type(), false),
+        ]))
+    }
+
+    /// Exports every cell of every layer of the map as a single Arrow record batch, with the
+    /// columns described in the [module documentation](crate::columnar).
+    pub fn to_record_batch(&self) -> Result<RecordBatch, Error> {
+        let bounds = self.cell_bounds();
+        let num_rows = self.data.len() * self.data.first().map_or(0, |d| d.len());
+
+        let mut layers = Vec::with_capacity(num_rows);
+        let mut xs = Vec::with_capacity(num_rows);
+        let mut ys = Vec::with_capacity(num_rows);
+        let mut position_xs = Vec::with_capacity(num_rows);
+        let mut position_ys = Vec::with_capacity(num_rows);
+        let mut values = Vec::with_capacity(num_rows);
+
+        for (layer, data) in L::all().into_iter().zip(&self.data) {
+            let name = format!("{:?}", layer);
+
+            for ((y, x), value) in data.indexed_iter() {
+                let position = self.position_unchecked(Point2::new(x, y));
+
+                layers.push(name.clone());
+                xs.push(x as i64 + bounds.x.0 as i64);
+                ys.push(y as i64 + bounds.y.0 as i64);
+                position_xs.push(position.x);
+                position_ys.push(position.y);
+                values.push(value.clone());
+            }
+        }
+
+        RecordBatch::try_new(
+            Self::arrow_schema(),
+            vec![
+                Arc::new(StringArray::from(layers)),
+                Arc::new(Int64Array::from(xs)),
+                Arc::new(Int64Array::from(ys)),
+                Arc::new(Float64Array::from(position_xs)),
+                Arc::new(Float64Array::from(position_ys)),
+                T::to_array(values),
+            ],
+        )
+        .map_err(Error::ArrowError)
+    }
+
+    /// Writes the map to the given path as a Parquet file, overwriting any existing file.
+    ///
+    /// The file contains the same columns as [`CellMap::to_record_batch()`].
+    #[cfg(feature = "parquet")]
+    pub fn write_parquet<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), Error> {
+        trace_span!("write_parquet", path = %path.as_ref().display());
+        let batch = self.to_record_batch()?;
+        let file = std::fs::File::create(path).map_err(Error::IoError)?;
+
+        let mut writer = parquet::arrow::ArrowWriter::try_new(file, batch.schema(), None)
+            .map_err(Error::ParquetError)?;
+        writer.write(&batch).map_err(Error::ParquetError)?;
+        writer.close().map_err(Error::ParquetError)?;
+
+        Ok(())
+    }
+}
+
+/// Implements [`ArrowValue`] for types with a matching Arrow array.
+macro_rules! impl_arrow_value {
+    ($($t:ty => $array:ident($data_type:ident)),*) => {
+        $(
+            impl ArrowValue for $t {
+                fn data_type() -> DataType {
+                    DataType::$data_type
+                }
+
+                fn to_array(values: Vec<Self>) -> ArrayRef {
+                    Arc::new($array::from(values))
+                }
+            }
+        )*
+    };
+}
+
+impl_arrow_value!(
+    f64 => Float64Array(Float64),
+    f32 => Float32Array(Float32),
+    i64 => Int64Array(Int64),
+    i32 => Int32Array(Int32),
+    u64 => UInt64Array(UInt64),
+    u32 => UInt32Array(UInt32),
+    u8 => UInt8Array(UInt8),
+    bool => BooleanArray(Boolean)
+);
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::Array;
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    fn new_map() -> CellMap<TestLayers, f64> {
+        let mut map = CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-1, 2), (3, 5)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            0.0,
+        );
+        map[(TestLayers::Layer1, Point2::new(2, 1))] = 7.0;
+        map
+    }
+
+    #[test]
+    fn record_batch() {
+        let batch = new_map().to_record_batch().unwrap();
+        assert_eq!(batch.num_rows(), 18);
+        assert_eq!(batch.schema(), CellMap::<TestLayers, f64>::arrow_schema());
+
+        let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+        let layers = column("layer");
+        let layers = layers.as_any().downcast_ref::<StringArray>().unwrap();
+        let xs = column("x");
+        let xs = xs.as_any().downcast_ref::<Int64Array>().unwrap();
+        let ys = column("y");
+        let ys = ys.as_any().downcast_ref::<Int64Array>().unwrap();
+        let position_xs = column("position_x");
+        let position_xs = position_xs.as_any().downcast_ref::<Float64Array>().unwrap();
+        let values = column("value");
+        let values = values.as_any().downcast_ref::<Float64Array>().unwrap();
+
+        // The last cell of the second layer
+        assert_eq!(layers.value(11), "Layer1");
+        assert_eq!((xs.value(11), ys.value(11)), (1, 4));
+        assert_eq!(position_xs.value(11), 0.75);
+        assert_eq!(values.value(11), 7.0);
+        assert_eq!(values.iter().flatten().sum::<f64>(), 7.0);
+        assert_eq!(layers.value(0), "Layer0");
+        assert_eq!((xs.value(0), ys.value(0)), (-1, 3));
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let map = new_map();
+        let path = std::env::temp_dir().join("cell_map_parquet.parquet");
+        map.write_parquet(&path).unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+        let batches = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(batches, vec![map.to_record_batch().unwrap()]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+//! Provides metrics for comparing a map against a reference map, for example to evaluate the
+//! accuracy of a mapping pipeline against ground truth.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+
+use crate::{CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// TRAITS
+// ------------------------------------------------------------------------------------------------
+
+/// Trait for cell values which can be compared by [`compare()`].
+pub trait CompareValue {
+    /// Returns the value as a float for computing errors, or `None` if the value is invalid and
+    /// the cell should be ignored.
+    fn as_f64(&self) -> Option<f64>;
+
+    /// Returns whether the cell is occupied, for computing the intersection over union.
+    fn is_occupied(&self) -> bool;
+}
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Metrics produced by [`compare()`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ComparisonMetrics {
+    /// The number of valid cells in the reference map.
+    pub num_reference: usize,
+
+    /// The number of reference cells for which the compared map also has a valid cell.
+    pub num_compared: usize,
+
+    /// The fraction of valid reference cells which are valid in the compared map, or `0.0` if the
+    /// reference has no valid cells.
+    pub coverage: f64,
+
+    /// The root mean square error between the compared cells, or `NaN` if no cells were compared.
+    pub rmse: f64,
+
+    /// The intersection over union of the occupied cells among the compared cells, or `1.0` if
+    /// neither map has any occupied cells.
+    pub iou: f64,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl CompareValue for f64 {
+    fn as_f64(&self) -> Option<f64> {
+        if self.is_nan() {
+            None
+        } else {
+            Some(*self)
+        }
+    }
+
+    /// Float cells are occupied if their value is greater than `0.5`, treating them as occupancy
+    /// probabilities.
+    fn is_occupied(&self) -> bool {
+        *self > 0.5
+    }
+}
+
+impl CompareValue for f32 {
+    fn as_f64(&self) -> Option<f64> {
+        (*self as f64).as_f64()
+    }
+
+    /// Float cells are occupied if their value is greater than `0.5`, treating them as occupancy
+    /// probabilities.
+    fn is_occupied(&self) -> bool {
+        *self > 0.5
+    }
+}
+
+impl CompareValue for bool {
+    fn as_f64(&self) -> Option<f64> {
+        Some(if *self { 1.0 } else { 0.0 })
+    }
+
+    fn is_occupied(&self) -> bool {
+        *self
+    }
+}
+
+/// Implements [`CompareValue`] for integer types, whose values are always valid and which are
+/// occupied if they're greater than zero.
+macro_rules! impl_compare_value_int {
+    ($($t:ty),*) => {
+        $(
+            impl CompareValue for $t {
+                fn as_f64(&self) -> Option<f64> {
+                    Some(*self as f64)
+                }
+
+                fn is_occupied(&self) -> bool {
+                    *self > 0
+                }
+            }
+        )*
+    };
+}
+
+impl_compare_value_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Compares `layer` of `map` against the same layer of `reference`.
+///
+/// Each valid cell of the reference is compared with the cell of `map` which contains the centre
+/// of the reference cell in the parent frame, so the maps don't need to have the same bounds,
+/// cell size or position. Reference cells outside `map`, or where `map` is invalid, reduce the
+/// coverage but don't contribute to the other metrics.
+pub fn compare<L, T>(map: &CellMap<L, T>, reference: &CellMap<L, T>, layer: L) -> ComparisonMetrics
+where
+    L: Layer,
+    T: CompareValue,
+{
+    let map_layer = &map[layer.clone()];
+    let mut num_reference = 0;
+    let mut num_compared = 0;
+    let mut sum_sq_error = 0.0;
+    let mut intersection = 0;
+    let mut union = 0;
+
+    for ((y, x), ref_value) in reference[layer].indexed_iter() {
+        let ref_float = match ref_value.as_f64() {
+            Some(v) => v,
+            None => continue,
+        };
+        num_reference += 1;
+
+        let value = map
+            .index(reference.position_unchecked(Point2::new(x, y)))
+            .map(|index| &map_layer[(index.y, index.x)]);
+        let (value, float) = match value.and_then(|v| v.as_f64().map(|f| (v, f))) {
+            Some(v) => v,
+            None => continue,
+        };
+        num_compared += 1;
+
+        sum_sq_error += (float - ref_float).powi(2);
+
+        match (value.is_occupied(), ref_value.is_occupied()) {
+            (true, true) => {
+                intersection += 1;
+                union += 1
+            }
+            (true, false) | (false, true) => union += 1,
+            (false, false) => (),
+        }
+    }
+
+    ComparisonMetrics {
+        num_reference,
+        num_compared,
+        coverage: if num_reference > 0 {
+            num_compared as f64 / num_reference as f64
+        } else {
+            0.0
+        },
+        rmse: if num_compared > 0 {
+            (sum_sq_error / num_compared as f64).sqrt()
+        } else {
+            f64::NAN
+        },
+        iou: if union > 0 {
+            intersection as f64 / union as f64
+        } else {
+            1.0
+        },
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    fn new_map<T: Clone>(bounds: Bounds, elem: T) -> CellMap<TestLayers, T> {
+        CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: bounds,
+                cell_size: Vector2::new(1.0, 1.0),
+                ..Default::default()
+            },
+            elem,
+        )
+    }
+
+    #[test]
+    fn float_layers() {
+        let reference = new_map(Bounds::new((0, 4), (0, 4)).unwrap(), 0.0);
+
+        // The map covers the right half of the reference, with one invalid cell
+        let mut map = new_map(Bounds::new((2, 6), (0, 4)).unwrap(), 0.5);
+        map[(TestLayers::Layer0, Point2::new(0, 0))] = f64::NAN;
+        map[(TestLayers::Layer0, Point2::new(1, 1))] = 1.0;
+
+        let metrics = compare(&map, &reference, TestLayers::Layer0);
+        assert_eq!(metrics.num_reference, 16);
+        assert_eq!(metrics.num_compared, 7);
+        assert_eq!(metrics.coverage, 7.0 / 16.0);
+        assert!((metrics.rmse - ((6.0 * 0.25 + 1.0) / 7.0f64).sqrt()).abs() < 1e-12);
+
+        // Only the cell at 1.0 is occupied, and the reference has none
+        assert_eq!(metrics.iou, 0.0);
+
+        let metrics = compare(&reference, &reference, TestLayers::Layer1);
+        assert_eq!(metrics.coverage, 1.0);
+        assert_eq!(metrics.rmse, 0.0);
+        assert_eq!(metrics.iou, 1.0);
+    }
+
+    #[test]
+    fn bool_layers() {
+        let bounds = Bounds::new((0, 4), (0, 1)).unwrap();
+        let mut reference = new_map(bounds, false);
+        let mut map = new_map(bounds, false);
+
+        for x in 0..3 {
+            reference[(TestLayers::Layer0, Point2::new(x, 0))] = true;
+        }
+        for x in 1..4 {
+            map[(TestLayers::Layer0, Point2::new(x, 0))] = true;
+        }
+
+        let metrics = compare(&map, &reference, TestLayers::Layer0);
+        assert_eq!(metrics.iou, 0.5);
+        assert_eq!(metrics.rmse, 0.5f64.sqrt());
+        assert_eq!(metrics.coverage, 1.0);
+    }
+}
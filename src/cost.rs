@@ -0,0 +1,181 @@
+//! Provides conversion of `f64` cost layers into the `u8` costs used by ROS costmaps, see
+//! [`CellMap::to_u8_cost()`].
+//!
+//! ROS costmaps store [`COST_FREE`] for free space, rising to [`COST_LETHAL`] for cells which
+//! certainly contain an obstacle, and [`COST_UNKNOWN`] for cells with no information. Costs in
+//! this crate are `f64`s, usually between `0.0` and [`LETHAL_COST`], and `NaN` is often used for
+//! cells with no information, so a [`CostMapping`] describes how to scale them and what to do with
+//! `NaN`s.
+//!
+//! [`LETHAL_COST`]: crate::potential::LETHAL_COST
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use ndarray::Array2;
+
+use crate::{potential::LETHAL_COST, CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The `u8` cost of free space.
+pub const COST_FREE: u8 = 0;
+
+/// The `u8` cost of a cell containing an obstacle, which is the highest known cost.
+pub const COST_LETHAL: u8 = 254;
+
+/// The `u8` cost of a cell with no information.
+pub const COST_UNKNOWN: u8 = 255;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Describes how [`CellMap::to_u8_cost()`] converts `f64` costs into `u8` costs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostMapping {
+    /// The cost which is mapped to [`COST_FREE`]. Lower costs are also free.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.0`.
+    pub min: f64,
+
+    /// The cost which is mapped to [`COST_LETHAL`]. Higher costs, including infinity, are also
+    /// lethal.
+    ///
+    /// # Default
+    ///
+    /// The default value is [`LETHAL_COST`].
+    pub max: f64,
+
+    /// How `NaN` costs are converted.
+    ///
+    /// # Default
+    ///
+    /// The default value is [`NanCost::Unknown`].
+    pub nan: NanCost,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// How [`CellMap::to_u8_cost()`] converts `NaN` costs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NanCost {
+    /// `NaN` costs become [`COST_UNKNOWN`].
+    Unknown,
+
+    /// `NaN` costs become [`COST_FREE`].
+    Free,
+
+    /// `NaN` costs become [`COST_LETHAL`], which is the safe choice for planners which treat
+    /// unknown space as free.
+    Lethal,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Converts the costs in `layer` into ROS-style `u8` costs using `mapping`, returning an array
+    /// with the same shape as the layer.
+    ///
+    /// Costs between `mapping.min` and `mapping.max` are scaled linearly onto [`COST_FREE`] to
+    /// [`COST_LETHAL`] and rounded to the nearest integer, saturating outside that range, so only
+    /// `NaN` costs can become [`COST_UNKNOWN`].
+    pub fn to_u8_cost(&self, layer: L, mapping: &CostMapping) -> Array2<u8> {
+        self[layer].mapv(|v| mapping.map(v))
+    }
+}
+
+impl CostMapping {
+    /// Converts a single cost.
+    pub fn map(&self, cost: f64) -> u8 {
+        if cost.is_nan() {
+            return match self.nan {
+                NanCost::Unknown => COST_UNKNOWN,
+                NanCost::Free => COST_FREE,
+                NanCost::Lethal => COST_LETHAL,
+            };
+        }
+
+        if cost >= self.max {
+            COST_LETHAL
+        } else if cost <= self.min {
+            COST_FREE
+        } else {
+            let scaled = (cost - self.min) / (self.max - self.min) * COST_LETHAL as f64;
+            scaled.round() as u8
+        }
+    }
+}
+
+impl Default for CostMapping {
+    fn default() -> Self {
+        Self {
+            min: 0.0,
+            max: LETHAL_COST,
+            nan: NanCost::Unknown,
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn to_u8_cost() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 3), (0, 2)).unwrap(),
+                ..Default::default()
+            },
+            0.5,
+        );
+        map[(TestLayers::Layer0, Point2::new(0, 0))] = -1.0;
+        map[(TestLayers::Layer0, Point2::new(1, 0))] = 1.0;
+        map[(TestLayers::Layer0, Point2::new(2, 0))] = f64::INFINITY;
+        map[(TestLayers::Layer0, Point2::new(0, 1))] = f64::NAN;
+        map[(TestLayers::Layer0, Point2::new(1, 1))] = 0.1;
+
+        let costs = map.to_u8_cost(TestLayers::Layer0, &CostMapping::default());
+        assert_eq!(costs.dim(), (2, 3));
+        assert_eq!(costs.row(0).to_vec(), vec![0, 254, 254]);
+        assert_eq!(costs.row(1).to_vec(), vec![255, 25, 127]);
+
+        let costs = map.to_u8_cost(
+            TestLayers::Layer0,
+            &CostMapping {
+                min: -1.0,
+                max: 0.5,
+                nan: NanCost::Lethal,
+            },
+        );
+        assert_eq!(costs.row(0).to_vec(), vec![0, 254, 254]);
+        assert_eq!(costs.row(1).to_vec(), vec![254, 186, 254]);
+
+        let free = CostMapping {
+            nan: NanCost::Free,
+            ..Default::default()
+        };
+        assert_eq!(free.map(f64::NAN), COST_FREE);
+        assert_eq!(free.map(f64::NEG_INFINITY), COST_FREE);
+    }
+}
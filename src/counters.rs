@@ -0,0 +1,136 @@
+//! Provides internal performance counters, which are enabled by the `counters` feature.
+//!
+//! Counters record how many times expensive internal operations are performed, for example how
+//! many items have been produced by iterators or how many position-to-index transforms have been
+//! calculated. This allows the effect of performance-motivated changes to be measured
+//! objectively, alongside the benchmarks in `benches/`.
+//!
+//! Counters are global to the process and are updated atomically, so they can be used from
+//! multiple threads.
+//!
+//! # Example
+//!
+//! ```
+//! # use cell_map::{counters, CellMap, CellMapParams, Layer, Bounds};
+//! # #[derive(Layer, Clone, Debug)]
+//! # enum MyLayer {
+//! #     Height,
+//! # }
+//! let map = CellMap::<MyLayer, f64>::new(CellMapParams {
+//!     cell_bounds: Bounds::new((0, 5), (0, 5)).unwrap(),
+//!     ..Default::default()
+//! });
+//!
+//! counters::reset();
+//! let _ = map.iter().count();
+//! assert_eq!(counters::snapshot().items_iterated, 25);
+//! ```
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// ------------------------------------------------------------------------------------------------
+// STATICS
+// ------------------------------------------------------------------------------------------------
+
+static ITEMS_ITERATED: AtomicU64 = AtomicU64::new(0);
+static INDEX_TRANSFORMS: AtomicU64 = AtomicU64::new(0);
+static POSITION_TRANSFORMS: AtomicU64 = AtomicU64::new(0);
+static LAYER_ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static FILE_CONVERSIONS: AtomicU64 = AtomicU64::new(0);
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A snapshot of the value of all counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// The number of items produced by all [`CellMapIter`]s and [`CellMapIterMut`]s.
+    ///
+    /// [`CellMapIter`]: crate::iterators::CellMapIter
+    /// [`CellMapIterMut`]: crate::iterators::CellMapIterMut
+    pub items_iterated: u64,
+
+    /// The number of parent-frame positions which have been converted into cell indexes.
+    pub index_transforms: u64,
+
+    /// The number of cell indexes which have been converted into parent-frame positions.
+    pub position_transforms: u64,
+
+    /// The number of layers which have been allocated, for example by [`CellMap::new()`] or
+    /// [`CellMap::resize()`].
+    ///
+    /// [`CellMap::new()`]: crate::CellMap::new
+    /// [`CellMap::resize()`]: crate::CellMap::resize
+    pub layer_allocations: u64,
+
+    /// The number of conversions of a [`CellMap`] into a [`CellMapFile`], which happens on every
+    /// serialisation of a map.
+    ///
+    /// [`CellMap`]: crate::CellMap
+    /// [`CellMapFile`]: crate::cell_map_file::CellMapFile
+    pub file_conversions: u64,
+}
+
+/// Identifies a single counter.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Counter {
+    ItemsIterated,
+    IndexTransforms,
+    PositionTransforms,
+    LayerAllocations,
+    FileConversions,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Counter {
+    fn atomic(&self) -> &'static AtomicU64 {
+        match self {
+            Self::ItemsIterated => &ITEMS_ITERATED,
+            Self::IndexTransforms => &INDEX_TRANSFORMS,
+            Self::PositionTransforms => &POSITION_TRANSFORMS,
+            Self::LayerAllocations => &LAYER_ALLOCATIONS,
+            Self::FileConversions => &FILE_CONVERSIONS,
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Returns the current value of all counters.
+pub fn snapshot() -> Counters {
+    Counters {
+        items_iterated: ITEMS_ITERATED.load(Ordering::Relaxed),
+        index_transforms: INDEX_TRANSFORMS.load(Ordering::Relaxed),
+        position_transforms: POSITION_TRANSFORMS.load(Ordering::Relaxed),
+        layer_allocations: LAYER_ALLOCATIONS.load(Ordering::Relaxed),
+        file_conversions: FILE_CONVERSIONS.load(Ordering::Relaxed),
+    }
+}
+
+/// Resets all counters to zero.
+pub fn reset() {
+    for counter in &[
+        Counter::ItemsIterated,
+        Counter::IndexTransforms,
+        Counter::PositionTransforms,
+        Counter::LayerAllocations,
+        Counter::FileConversions,
+    ] {
+        counter.atomic().store(0, Ordering::Relaxed);
+    }
+}
+
+/// Adds `n` to the given counter.
+pub(crate) fn add(counter: Counter, n: u64) {
+    counter.atomic().fetch_add(n, Ordering::Relaxed);
+}
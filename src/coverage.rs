@@ -0,0 +1,236 @@
+//! Provides coverage planning support, decomposing free space into regions which can each be
+//! covered by a simple back-and-forth (lawn-mower) sweep.
+//!
+//! Free space is decomposed with the boustrophedon cell decomposition, sweeping a line along the
+//! map's `x` axis. Each column of the map is split into spans of free cells, and a region
+//! continues from one column to the next as long as its span connects to exactly one span in the
+//! next column and vice versa. Where obstacles split or join spans, the regions on either side end
+//! and new ones begin, so every region is free of obstacles and can be swept in columns along the
+//! map's `y` axis.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::Point2;
+use ndarray::ArrayView1;
+
+use crate::{CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A column of free cells in a [`CoverageRegion`], from `y_min` to `y_max` inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// The `x` index of the column.
+    pub x: usize,
+
+    /// The `y` index of the first free cell in the span.
+    pub y_min: usize,
+
+    /// The `y` index of the last free cell in the span.
+    pub y_max: usize,
+}
+
+/// A region of free space which can be covered by a single lawn-mower sweep, produced by
+/// [`CellMap::boustrophedon_decomposition()`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CoverageRegion {
+    /// The spans of the region, one per column in increasing `x` order.
+    pub spans: Vec<Span>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Span {
+    /// Returns whether this span shares at least one row with `other`.
+    fn overlaps(&self, other: &Span) -> bool {
+        self.y_min <= other.y_max && other.y_min <= self.y_max
+    }
+}
+
+impl CoverageRegion {
+    /// Returns the number of cells in the region.
+    pub fn num_cells(&self) -> usize {
+        self.spans.iter().map(|s| s.y_max - s.y_min + 1).sum()
+    }
+}
+
+impl<L> CellMap<L, bool>
+where
+    L: Layer,
+{
+    /// Decomposes the free space in `layer`, where `true` cells are free, into regions which can
+    /// each be covered by a lawn-mower sweep.
+    ///
+    /// Regions are returned in the order they are opened by a sweep along `x`, so covering them
+    /// in order moves broadly from one side of the map to the other.
+    pub fn boustrophedon_decomposition(&self, layer: L) -> Vec<CoverageRegion> {
+        let free = &self[layer];
+        let (rows, cols) = free.dim();
+
+        let mut regions: Vec<CoverageRegion> = Vec::new();
+
+        // The spans of the previous column, with the index of the region each belongs to
+        let mut previous: Vec<(usize, Span)> = Vec::new();
+
+        for x in 0..cols {
+            let mut spans = Vec::new();
+            let mut start = None;
+
+            for y in 0..=rows {
+                match (start, y < rows && free[(y, x)]) {
+                    (None, true) => start = Some(y),
+                    (Some(y_min), false) => {
+                        spans.push(Span {
+                            x,
+                            y_min,
+                            y_max: y - 1,
+                        });
+                        start = None;
+                    }
+                    _ => (),
+                }
+            }
+
+            let mut current = Vec::with_capacity(spans.len());
+
+            for span in spans {
+                let mut connected = previous.iter().filter(|(_, p)| p.overlaps(&span));
+
+                // Only continue a region if neither span splits or merges
+                let region = match (connected.next(), connected.next()) {
+                    (Some(&(region, prev)), None)
+                        if num_overlapping(&prev, free.column(x)) == 1 =>
+                    {
+                        region
+                    }
+                    _ => {
+                        regions.push(CoverageRegion::default());
+                        regions.len() - 1
+                    }
+                };
+
+                regions[region].spans.push(span);
+                current.push((region, span));
+            }
+
+            previous = current;
+        }
+
+        regions
+    }
+
+    /// Returns the lawn-mower waypoints covering `region`, in the parent frame.
+    ///
+    /// The sweep moves along each column of the region in alternating directions, stepping
+    /// between columns every `spacing` parent-frame units, typically the width of the robot's
+    /// tool. The waypoints are the centres of the cells at each end of each swept column. The last
+    /// column of the region is always swept so that its far edge is covered.
+    pub fn lawnmower_waypoints(&self, region: &CoverageRegion, spacing: f64) -> Vec<Point2<f64>> {
+        let step = ((spacing / self.cell_size().x).round() as usize).max(1);
+        let last = region.spans.len().saturating_sub(1);
+
+        region
+            .spans
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i.is_multiple_of(step) || *i == last)
+            .enumerate()
+            .flat_map(|(sweep, (_, span))| {
+                let (a, b) = if sweep.is_multiple_of(2) {
+                    (span.y_min, span.y_max)
+                } else {
+                    (span.y_max, span.y_min)
+                };
+
+                [
+                    self.position_unchecked(Point2::new(span.x, a)),
+                    self.position_unchecked(Point2::new(span.x, b)),
+                ]
+            })
+            .collect()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Returns the number of runs of free cells in `column` which overlap `span`.
+fn num_overlapping(span: &Span, column: ArrayView1<'_, bool>) -> usize {
+    let mut count = 0;
+    let mut in_run = false;
+
+    for y in span.y_min..=span.y_max {
+        if column[y] && !in_run {
+            count += 1;
+        }
+        in_run = column[y];
+    }
+
+    count
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn boustrophedon() {
+        let mut map = CellMap::<TestLayers, bool>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 10), (0, 6)).unwrap(),
+                ..Default::default()
+            },
+            true,
+        );
+
+        // A block in the middle of the map splits the free space around it
+        for x in 4..6 {
+            for y in 2..4 {
+                map[(TestLayers::Layer0, Point2::new(x, y))] = false;
+            }
+        }
+
+        let regions = map.boustrophedon_decomposition(TestLayers::Layer0);
+        assert_eq!(regions.len(), 4);
+        assert_eq!(
+            regions.iter().map(|r| r.num_cells()).collect::<Vec<_>>(),
+            vec![24, 4, 4, 24]
+        );
+        assert_eq!(
+            regions[1].spans[0],
+            Span {
+                x: 4,
+                y_min: 0,
+                y_max: 1
+            }
+        );
+
+        // Every column of the first region is swept, alternating direction
+        let waypoints = map.lawnmower_waypoints(&regions[0], 1.0);
+        assert_eq!(waypoints.len(), 8);
+        assert_eq!(waypoints[0], Point2::new(0.5, 0.5));
+        assert_eq!(waypoints[1], Point2::new(0.5, 5.5));
+        assert_eq!(waypoints[2], Point2::new(1.5, 5.5));
+        assert_eq!(waypoints[7], Point2::new(3.5, 0.5));
+
+        // Wider spacing skips columns but still sweeps the last one
+        let waypoints = map.lawnmower_waypoints(&regions[3], 3.0);
+        assert_eq!(waypoints.len(), 4);
+        assert_eq!(waypoints[3], Point2::new(9.5, 0.5));
+
+        // A fully free map is a single region
+        assert_eq!(map.boustrophedon_decomposition(TestLayers::Layer1).len(), 1);
+    }
+}
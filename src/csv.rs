@@ -0,0 +1,247 @@
+//! Provides export of single layers as CSV or TSV files, and import of the same files back into a
+//! map, for quick checks of small maps in spreadsheets or gnuplot.
+//!
+//! Files start with the header row `x,y,pos_x,pos_y,value`, followed by one row for each cell in
+//! order of `y` then `x`. `x` and `y` are the cell's index in the map frame, including the offset
+//! of the map's bounds, and `pos_x` and `pos_y` are the position of the cell's centre in the parent
+//! frame. Values are written with their [`Display`] implementation and read back with [`FromStr`],
+//! so floats round trip exactly.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{
+    convert::TryFrom,
+    fmt::Display,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use nalgebra::Point2;
+
+use crate::{CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The names of the columns in exported files.
+const COLUMNS: [&str; 5] = ["x", "y", "pos_x", "pos_y", "value"];
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Display,
+{
+    /// Writes `layer` to the given path as a comma-separated file, overwriting any existing file.
+    pub fn export_csv<P: AsRef<Path>>(&self, layer: L, path: P) -> Result<(), Error> {
+        self.export_delimited(layer, path, ',')
+    }
+
+    /// Writes `layer` to the given path as a tab-separated file, overwriting any existing file.
+    pub fn export_tsv<P: AsRef<Path>>(&self, layer: L, path: P) -> Result<(), Error> {
+        self.export_delimited(layer, path, '\t')
+    }
+
+    /// Writes `layer` to the given path, with columns separated by `delimiter`.
+    pub fn export_delimited<P: AsRef<Path>>(
+        &self,
+        layer: L,
+        path: P,
+        delimiter: char,
+    ) -> Result<(), Error> {
+        let file = File::create(path).map_err(Error::IoError)?;
+        let mut writer = BufWriter::new(file);
+        let bounds = self.cell_bounds();
+        let sep = delimiter.to_string();
+
+        writeln!(writer, "{}", COLUMNS.join(&sep)).map_err(Error::IoError)?;
+
+        for ((y, x), value) in self[layer].indexed_iter() {
+            let position = self.position_unchecked(Point2::new(x, y));
+
+            writeln!(
+                writer,
+                "{x}{d}{y}{d}{px}{d}{py}{d}{v}",
+                x = x as isize + bounds.x.0,
+                y = y as isize + bounds.y.0,
+                px = position.x,
+                py = position.y,
+                v = value,
+                d = delimiter
+            )
+            .map_err(Error::IoError)?;
+        }
+
+        writer.flush().map_err(Error::IoError)
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: FromStr,
+{
+    /// Reads a comma-separated file written by [`CellMap::export_csv()`] into `layer`, returning
+    /// the number of cells read.
+    ///
+    /// See [`CellMap::import_delimited()`] for details.
+    pub fn import_csv<P: AsRef<Path>>(&mut self, layer: L, path: P) -> Result<usize, Error> {
+        self.import_delimited(layer, path, ',')
+    }
+
+    /// Reads a tab-separated file written by [`CellMap::export_tsv()`] into `layer`, returning the
+    /// number of cells read.
+    ///
+    /// See [`CellMap::import_delimited()`] for details.
+    pub fn import_tsv<P: AsRef<Path>>(&mut self, layer: L, path: P) -> Result<usize, Error> {
+        self.import_delimited(layer, path, '\t')
+    }
+
+    /// Reads a file with columns separated by `delimiter` into `layer`, returning the number of
+    /// cells read.
+    ///
+    /// Cells are located by their `x` and `y` columns, and the position columns are ignored, so
+    /// the file doesn't need to contain every cell of the map, but every cell in it must be inside
+    /// the map. Blank lines are skipped. If an error is returned the rows before the invalid one
+    /// will already have been read into the map.
+    pub fn import_delimited<P: AsRef<Path>>(
+        &mut self,
+        layer: L,
+        path: P,
+        delimiter: char,
+    ) -> Result<usize, Error> {
+        let file = File::open(path).map_err(Error::IoError)?;
+        let mut lines = BufReader::new(file).lines().enumerate();
+        let bounds = self.cell_bounds();
+        let num_cells = self.num_cells();
+        let mut num_read = 0;
+
+        let invalid = |line: usize, reason: String| Error::InvalidCsv(line + 1, reason);
+
+        // Check the header
+        match lines.next() {
+            Some((i, header)) => {
+                let header = header.map_err(Error::IoError)?;
+                let columns: Vec<_> = header.split(delimiter).map(str::trim).collect();
+                if columns != COLUMNS {
+                    return Err(invalid(i, format!("unexpected header {:?}", header)));
+                }
+            }
+            None => return Err(invalid(0, "missing header".into())),
+        }
+
+        for (i, line) in lines {
+            let line = line.map_err(Error::IoError)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let fields: Vec<_> = line.split(delimiter).map(str::trim).collect();
+            if fields.len() != COLUMNS.len() {
+                return Err(invalid(
+                    i,
+                    format!(
+                        "expected {} columns but found {}",
+                        COLUMNS.len(),
+                        fields.len()
+                    ),
+                ));
+            }
+
+            let parse_index = |field: &str, min: isize, len: usize| {
+                field
+                    .parse::<isize>()
+                    .ok()
+                    .and_then(|v| v.checked_sub(min))
+                    .and_then(|v| usize::try_from(v).ok())
+                    .filter(|&v| v < len)
+                    .ok_or_else(|| invalid(i, format!("cell index {} is outside the map", field)))
+            };
+            let x = parse_index(fields[0], bounds.x.0, num_cells.x)?;
+            let y = parse_index(fields[1], bounds.y.0, num_cells.y)?;
+            let value = fields[4]
+                .parse::<T>()
+                .map_err(|_| invalid(i, format!("invalid value {:?}", fields[4])))?;
+
+            self[(layer.clone(), Point2::new(x, y))] = value;
+            num_read += 1;
+        }
+
+        Ok(num_read)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    fn new_map() -> CellMap<TestLayers, f64> {
+        CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-1, 2), (4, 6)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            0.0,
+        )
+    }
+
+    #[test]
+    fn export_and_import() {
+        let mut map = new_map();
+        map[(TestLayers::Layer1, Point2::new(0, 0))] = 0.1;
+        map[(TestLayers::Layer1, Point2::new(2, 1))] = f64::NAN;
+
+        let path = std::env::temp_dir().join("cell_map_export_and_import.csv");
+        map.export_csv(TestLayers::Layer1, &path).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], "x,y,pos_x,pos_y,value");
+        assert_eq!(lines[1], "-1,4,-0.25,2.25,0.1");
+        assert_eq!(lines[6], "1,5,0.75,2.75,NaN");
+
+        let mut imported = new_map();
+        assert_eq!(imported.import_csv(TestLayers::Layer2, &path).unwrap(), 6);
+        assert_eq!(imported[(TestLayers::Layer2, Point2::new(0, 0))], 0.1);
+        assert!(imported[(TestLayers::Layer2, Point2::new(2, 1))].is_nan());
+
+        // TSV files use the same layout, and cells outside the map are rejected
+        map.export_tsv(TestLayers::Layer1, &path).unwrap();
+        assert_eq!(imported.import_tsv(TestLayers::Layer0, &path).unwrap(), 6);
+
+        let mut small = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-1, 2), (4, 5)).unwrap(),
+                ..new_map().params()
+            },
+            0.0,
+        );
+        assert!(matches!(
+            small.import_tsv(TestLayers::Layer0, &path),
+            Err(Error::InvalidCsv(5, _))
+        ));
+        assert!(matches!(
+            small.import_csv(TestLayers::Layer0, &path),
+            Err(Error::InvalidCsv(1, _))
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
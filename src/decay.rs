@@ -0,0 +1,114 @@
+//! Provides decay operators for dynamic layers, whose values should be forgotten over time unless
+//! they are refreshed by new observations.
+//!
+//! Each call to a decay method moves every value a fraction of the way towards a baseline
+//! (`floor`), so calling it once per update cycle gives exponential decay. A standard use is a
+//! dynamic obstacle layer, where each update raises the cells the obstacles are seen in and decay
+//! lowers all other cells back to free space over the following cycles.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use ndarray::{ArrayView2, Zip};
+
+use crate::{CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Decays every value in `layer` towards `floor`, keeping `factor` of its distance from
+    /// `floor`, so each value `v` becomes `floor + (v - floor) * factor`.
+    ///
+    /// `factor` should be between `0.0`, which resets the layer to `floor`, and `1.0`, which
+    /// leaves it unchanged. `NaN` cells stay `NaN`.
+    pub fn decay_layer(&mut self, layer: L, factor: f64, floor: f64) {
+        self[layer].mapv_inplace(|v| decay(v, factor, floor));
+    }
+
+    /// Decays the values in `layer` as in [`CellMap::decay_layer()`], except for cells which are
+    /// `true` in `updated`, so that cells refreshed this cycle keep their new values.
+    ///
+    /// `updated` must have the same shape as the map, otherwise [`Error::LayerWrongShape`] is
+    /// returned and the layer is unchanged.
+    pub fn decay_layer_except(
+        &mut self,
+        layer: L,
+        factor: f64,
+        floor: f64,
+        updated: ArrayView2<'_, bool>,
+    ) -> Result<(), Error> {
+        let shape = self.cell_bounds().get_shape();
+        if updated.dim() != shape {
+            return Err(Error::LayerWrongShape(updated.dim(), shape));
+        }
+
+        Zip::from(&mut self[layer])
+            .and(&updated)
+            .for_each(|v, &updated| {
+                if !updated {
+                    *v = decay(*v, factor, floor)
+                }
+            });
+
+        Ok(())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Decays a single value towards `floor`.
+fn decay(value: f64, factor: f64, floor: f64) -> f64 {
+    floor + (value - floor) * factor
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+    use ndarray::Array2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn decay_layer() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 3), (0, 2)).unwrap(),
+                ..Default::default()
+            },
+            1.0,
+        );
+        map[(TestLayers::Layer0, Point2::new(1, 1))] = f64::NAN;
+
+        map.decay_layer(TestLayers::Layer0, 0.5, 0.2);
+        map.decay_layer(TestLayers::Layer0, 0.5, 0.2);
+        assert!((map[(TestLayers::Layer0, Point2::new(0, 0))] - 0.4).abs() < 1e-12);
+        assert!(map[(TestLayers::Layer0, Point2::new(1, 1))].is_nan());
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(0, 0))], 1.0);
+
+        // Only cells which weren't updated are decayed
+        let mut updated = Array2::from_elem((2, 3), false);
+        updated[[1, 2]] = true;
+        map.decay_layer_except(TestLayers::Layer1, 0.0, -1.0, updated.view())
+            .unwrap();
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(2, 1))], 1.0);
+        assert_eq!(map.iter().layer(TestLayers::Layer1).sum::<f64>(), -4.0);
+
+        assert!(matches!(
+            map.decay_layer_except(TestLayers::Layer1, 0.5, 0.0, updated.t()),
+            Err(Error::LayerWrongShape((3, 2), (2, 3)))
+        ));
+    }
+}
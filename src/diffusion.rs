@@ -0,0 +1,219 @@
+//! Provides an explicit diffusion solver, which spreads the values of a layer into their
+//! neighbours as heat spreads through a plate.
+//!
+//! Diffusion is useful both for smoothing fields, such as costs, without smoothing across
+//! obstacles, and as a simple model of temperature or contamination spreading through an area.
+//! Invalid cells, which are either masked out or `NaN`, are walls: nothing diffuses into, out of,
+//! or across them.
+//!
+//! ```
+//! use cell_map::{Bounds, CellMap, CellMapParams, Layer};
+//! use nalgebra::Point2;
+//!
+//! #[derive(Layer, Clone, Debug)]
+//! enum MyLayer {
+//!     Temperature,
+//! }
+//!
+//! let mut map = CellMap::<MyLayer, f64>::new(CellMapParams {
+//!     cell_bounds: Bounds::new((0, 5), (0, 5)).unwrap(),
+//!     ..Default::default()
+//! });
+//! map[(MyLayer::Temperature, Point2::new(2, 2))] = 100.0;
+//!
+//! map.diffuse(MyLayer::Temperature, 0.25, 10, None).unwrap();
+//!
+//! // Heat has spread out, but none has been lost
+//! assert!(map[(MyLayer::Temperature, Point2::new(0, 0))] > 0.0);
+//! let total: f64 = map.iter().layer(MyLayer::Temperature).sum();
+//! assert!((total - 100.0).abs() < 1e-9);
+//! ```
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use ndarray::{Array2, ArrayView2};
+
+use crate::{CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The largest rate for which [`CellMap::diffuse()`] is stable.
+pub const MAX_DIFFUSION_RATE: f64 = 0.25;
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Diffuses the values of `layer` for the given number of iterations, using the explicit
+    /// finite difference scheme for the heat equation.
+    ///
+    /// Each iteration moves every valid cell towards its valid edge-sharing neighbours by `alpha`
+    /// times the difference between them, where `alpha` is $D \Delta t / \Delta x^2$ for the
+    /// smallest side of the cells. Along the longer side of non-square cells the rate is reduced,
+    /// so values spread at the same speed in the parent frame in both directions. The scheme is
+    /// only stable for rates up to [`MAX_DIFFUSION_RATE`], and other rates return
+    /// [`Error::InvalidDiffusionRate`].
+    ///
+    /// Cells are valid if they aren't `NaN` and, if a `mask` is given, are `true` in the mask.
+    /// Invalid cells are left unchanged and act as insulating boundaries, as do the edges of the
+    /// map, so the total of the valid cells is conserved. Returns [`Error::LayerWrongShape`] if
+    /// the mask doesn't have the same shape as the map.
+    pub fn diffuse(
+        &mut self,
+        layer: L,
+        alpha: f64,
+        iterations: usize,
+        mask: Option<ArrayView2<'_, bool>>,
+    ) -> Result<(), Error> {
+        trace_span!("diffuse", iterations = iterations);
+
+        if !(alpha > 0.0 && alpha <= MAX_DIFFUSION_RATE) {
+            return Err(Error::InvalidDiffusionRate(alpha));
+        }
+
+        let shape = self.cell_bounds().get_shape();
+        if let Some(mask) = &mask {
+            if mask.dim() != shape {
+                return Err(Error::LayerWrongShape(mask.dim(), shape));
+            }
+        }
+
+        let cell_size = self.cell_size();
+        let min_size = cell_size.min();
+        let rate_x = alpha * (min_size / cell_size.x).powi(2);
+        let rate_y = alpha * (min_size / cell_size.y).powi(2);
+
+        let data = &mut self[layer];
+        let valid = Array2::from_shape_fn(shape, |i| {
+            !data[i].is_nan() && mask.as_ref().is_none_or(|m| m[i])
+        });
+
+        let (rows, cols) = shape;
+        let mut buffer = data.clone();
+
+        for _ in 0..iterations {
+            for ((y, x), out) in buffer.indexed_iter_mut() {
+                let value = data[(y, x)];
+                if !valid[(y, x)] {
+                    *out = value;
+                    continue;
+                }
+
+                let flux = |n: (usize, usize), rate: f64| {
+                    if valid[n] {
+                        rate * (data[n] - value)
+                    } else {
+                        0.0
+                    }
+                };
+
+                let mut change = 0.0;
+                if x > 0 {
+                    change += flux((y, x - 1), rate_x);
+                }
+                if x + 1 < cols {
+                    change += flux((y, x + 1), rate_x);
+                }
+                if y > 0 {
+                    change += flux((y - 1, x), rate_y);
+                }
+                if y + 1 < rows {
+                    change += flux((y + 1, x), rate_y);
+                }
+
+                *out = value + change;
+            }
+
+            std::mem::swap(data, &mut buffer);
+        }
+
+        Ok(())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point2, Vector2};
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    fn new_map() -> CellMap<TestLayers, f64> {
+        let mut map = CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 6), (0, 4)).unwrap(),
+                cell_size: Vector2::new(1.0, 0.5),
+                ..Default::default()
+            },
+            0.0,
+        );
+        map[(TestLayers::Layer0, Point2::new(1, 1))] = 48.0;
+
+        map
+    }
+
+    #[test]
+    fn diffuse() {
+        let mut map = new_map();
+
+        // One iteration with square-ish weights: the x neighbours get a quarter of the rate
+        map.diffuse(TestLayers::Layer0, 0.2, 1, None).unwrap();
+        assert_f64_eq!(
+            map[(TestLayers::Layer0, Point2::new(1, 1))],
+            48.0 * 0.5,
+            1e-12
+        );
+        assert_f64_eq!(map[(TestLayers::Layer0, Point2::new(1, 0))], 9.6, 1e-12);
+        assert_f64_eq!(map[(TestLayers::Layer0, Point2::new(2, 1))], 2.4, 1e-12);
+
+        // Values converge to the mean, conserving the total
+        map.diffuse(TestLayers::Layer0, 0.25, 2000, None).unwrap();
+        for &v in map.iter().layer(TestLayers::Layer0) {
+            assert_f64_eq!(v, 2.0, 1e-6);
+        }
+    }
+
+    #[test]
+    fn boundaries() {
+        let mut map = new_map();
+
+        // A NaN wall in column 2, except for the top row which is masked out instead
+        for y in 0..3 {
+            map[(TestLayers::Layer0, Point2::new(2, y))] = f64::NAN;
+        }
+        let mut mask = Array2::from_elem((4, 6), true);
+        mask[(3, 2)] = false;
+        map[(TestLayers::Layer0, Point2::new(2, 3))] = 5.0;
+
+        map.diffuse(TestLayers::Layer0, 0.25, 500, Some(mask.view()))
+            .unwrap();
+
+        // Nothing crosses the wall, and the wall is unchanged
+        assert_f64_eq!(map[(TestLayers::Layer0, Point2::new(0, 0))], 6.0, 1e-6);
+        assert_f64_eq!(map[(TestLayers::Layer0, Point2::new(1, 3))], 6.0, 1e-6);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(3, 2))], 0.0);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(2, 3))], 5.0);
+        assert!(map[(TestLayers::Layer0, Point2::new(2, 1))].is_nan());
+
+        assert!(matches!(
+            map.diffuse(TestLayers::Layer0, 0.3, 1, None),
+            Err(Error::InvalidDiffusionRate(_))
+        ));
+        assert!(matches!(
+            map.diffuse(TestLayers::Layer0, 0.1, 1, Some(mask.t())),
+            Err(Error::LayerWrongShape(_, _))
+        ));
+    }
+}
@@ -12,8 +12,13 @@ use crate::cell_map::Bounds;
 
 /// Standard error type for errors related to [`CellMap`]s.
 ///
+/// New variants may be added in minor releases, so matches on this type need a wildcard arm. To
+/// check for a class of errors without matching every variant use the classifiers such as
+/// [`Error::is_out_of_bounds()`].
+///
 /// [`CellMap`]: crate::CellMap
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
     /// Error returned when trying to construct a [`Windows`] slicer using a `semi_width` which
     /// would create a window larger than the size of the map.
@@ -27,27 +32,236 @@ pub enum Error {
     PositionOutsideMap(String, Point2<f64>),
 
     /// The given index is outside the map.
+    ///
+    /// This is used where no single layer of a map is involved, such as for a
+    /// [`BitLayer`](crate::bits::BitLayer) or a whole [`CellView`](crate::cell_view::CellView).
+    /// Indexing a particular layer of a map gives [`Error::IndexOutsideLayer`] instead.
     #[error("The index {0} is outside the map")]
     IndexOutsideMap(Point2<usize>),
 
+    /// The given index (second) in the layer with the given [`Layer::to_index()`] (first) is
+    /// outside the map's bounds (third).
+    ///
+    /// [`Layer::to_index()`]: crate::Layer::to_index
+    #[error("The index {1} in layer {0} is outside the map's bounds {2:?}")]
+    IndexOutsideLayer(usize, Point2<usize>, Bounds),
+
     /// Wrong number of layers, got (first) but expected (second)
     #[error("Expected {0} layers but found {1}")]
     WrongNumberOfLayers(usize, usize),
 
+    /// The given [`Layer::to_index()`] (first) isn't less than the number of layers (second).
+    ///
+    /// [`Layer::to_index()`]: crate::Layer::to_index
+    #[error("Layer index {0} is out of range for a map with {1} layers")]
+    InvalidLayerIndex(usize, usize),
+
     /// Wrong shape of layer, got (first) but expected (second)
+    ///
+    /// This is used for data which isn't a particular layer of a map, such as masks and frames.
+    /// Data for a particular layer of a map with the wrong shape gives
+    /// [`Error::LayerDataWrongShape`] instead.
     #[error("Expected {0:?} cells in layer, but found {1:?}")]
     LayerWrongShape((usize, usize), (usize, usize)),
 
+    /// Wrong shape of the layer with the given [`Layer::to_index()`] (first), got (second) but
+    /// expected (third)
+    ///
+    /// [`Layer::to_index()`]: crate::Layer::to_index
+    #[error("Expected {2:?} cells in layer {0}, but found {1:?}")]
+    LayerDataWrongShape(usize, (usize, usize), (usize, usize)),
+
+    /// Errors from `ndarray` when building an array from data with the wrong number of elements.
+    #[error("Array shape error: {0}")]
+    ShapeError(#[from] ndarray::ShapeError),
+
     /// Errors associated with `std::io` operations.
     #[error("An IO error occured: {0}")]
-    IoError(std::io::Error),
+    IoError(#[from] std::io::Error),
 
     /// Errors associated with `serde_json` operations.
     #[cfg(feature = "json")]
     #[error("Error in serde_json: {0}")]
-    JsonError(serde_json::Error),
+    JsonError(#[from] serde_json::Error),
+
+    /// Errors encoding or decoding CBOR.
+    #[cfg(feature = "cbor")]
+    #[error("Error in CBOR encoding: {0}")]
+    CborError(String),
+
+    /// Error when a CSV or TSV file being imported is invalid at the given line (first, starting
+    /// from 1), for the given reason (second).
+    #[error("Invalid CSV file at line {0}: {1}")]
+    InvalidCsv(usize, String),
 
     /// Error when bounds are invalid, i.e. the minimum is larger than the maximum
     #[error("The provided bounds are not valid: {0:?}")]
     InvalidBounds(Bounds),
+
+    /// Error when the given bounds (first) are not inside the bounds of the map (second).
+    #[error("The bounds {0:?} are not inside the map's bounds {1:?}")]
+    BoundsOutsideMap(Bounds, Bounds),
+
+    /// Error when two regions (first and second) passed to
+    /// [`CellMap::split_regions_mut()`](crate::CellMap::split_regions_mut), or the bounds of two
+    /// tiles passed to [`CellMap::assemble()`](crate::CellMap::assemble), overlap.
+    #[error("The regions {0:?} and {1:?} overlap")]
+    OverlappingRegions(Bounds, Bounds),
+
+    /// Error when a [`MapClient`](crate::server::MapClient) can't reach its server, because the
+    /// server has been dropped.
+    #[error("The map server has disconnected")]
+    ServerDisconnected,
+
+    /// Error when maps can't be combined into a single map, for the given reason.
+    #[error("The maps can't be combined: {0}")]
+    CannotCombineMaps(String),
+
+    /// Error when a kernel does not have an odd length in `x` (first) or `y` (second), and
+    /// therefore has no central element.
+    #[error("Kernels must have an odd size, but found {0}x{1}")]
+    InvalidKernelSize(usize, usize),
+
+    /// Error when the standard deviation of a Gaussian kernel is not positive and finite.
+    #[error("Gaussian standard deviations must be positive and finite, but found {0}")]
+    InvalidSigma(f64),
+
+    /// Error when an operation needs at least some number (first) of valid cells, but only found
+    /// fewer (second).
+    #[error("Expected at least {0} valid cells but found {1}")]
+    NotEnoughValidCells(usize, usize),
+
+    /// Error when the rate given to [`CellMap::diffuse()`](crate::CellMap::diffuse) is not
+    /// positive or is too large for the solver to be stable.
+    #[error("Diffusion rates must be in (0, 0.25], but found {0}")]
+    InvalidDiffusionRate(f64),
+
+    /// Error when a [`TemporalLayer`](crate::temporal::TemporalLayer) is given a capacity of zero.
+    #[error("Temporal layers must have a non-zero capacity")]
+    InvalidTemporalCapacity,
+
+    /// Error when a memory-mapped map file is not valid for the requested map type.
+    #[cfg(feature = "mmap")]
+    #[error("Invalid memory-mapped map file: {0}")]
+    InvalidMmapFile(String),
+
+    /// Error when a [`TileStore`](crate::TileStore) is given a zero tile size or cache capacity.
+    #[cfg(feature = "tiles")]
+    #[error("Tile stores must have a non-zero tile size and cache capacity")]
+    InvalidTileStoreParams,
+
+    /// Error when a tile loaded from disk doesn't match the parameters of its store.
+    #[cfg(feature = "tiles")]
+    #[error("Tile {0} on disk doesn't match the parameters of the store")]
+    TileMismatch(Point2<isize>),
+
+    /// Error when a sync peer sends a message which isn't valid at that point in the protocol.
+    #[cfg(feature = "cbor")]
+    #[error("Received an unexpected message from the sync peer")]
+    UnexpectedSyncMessage,
+
+    /// Error when a map's hash after syncing (first) doesn't match the hash of the source map
+    /// (second).
+    #[cfg(feature = "cbor")]
+    #[error("Map hash {0:#x} after syncing doesn't match the source's hash {1:#x}")]
+    SyncHashMismatch(u64, u64),
+
+    /// Error when a sync message's length (first) is larger than the maximum (second).
+    #[cfg(feature = "cbor")]
+    #[error("Sync message of {0} bytes is larger than the maximum of {1} bytes")]
+    SyncMessageTooLarge(u64, u64),
+
+    /// Error when a [`MapRegistry`](crate::registry::MapRegistry) has no map with the given name.
+    #[cfg(feature = "json")]
+    #[error("No map named {0:?} in the registry")]
+    MapNotFound(String),
+
+    /// Error when the map with the given name in a
+    /// [`MapRegistry`](crate::registry::MapRegistry) isn't of the requested type.
+    #[cfg(feature = "json")]
+    #[error("The map named {0:?} in the registry isn't of the requested type")]
+    MapWrongType(String),
+
+    /// Errors associated with encoding PNG images.
+    #[cfg(feature = "render_png")]
+    #[error("Error encoding PNG: {0}")]
+    PngError(png::EncodingError),
+
+    /// Errors associated with decoding protobuf messages.
+    #[cfg(feature = "protobuf")]
+    #[error("Error decoding protobuf: {0}")]
+    ProtobufDecodeError(prost::DecodeError),
+
+    /// Error when a protobuf message doesn't describe a valid map of the requested type.
+    #[cfg(feature = "protobuf")]
+    #[error("Invalid protobuf map: {0}")]
+    InvalidProtobuf(String),
+
+    /// Errors associated with building Arrow record batches.
+    #[cfg(feature = "arrow")]
+    #[error("Arrow error: {0}")]
+    ArrowError(arrow_schema::ArrowError),
+
+    /// Errors associated with writing Parquet files.
+    #[cfg(feature = "parquet")]
+    #[error("Parquet error: {0}")]
+    ParquetError(parquet::errors::ParquetError),
+
+    /// Errors associated with reading or writing HDF5 files.
+    #[cfg(feature = "hdf5")]
+    #[error("HDF5 error: {0}")]
+    Hdf5Error(hdf5::Error),
+
+    /// Error when a map's parameters can't be represented by a
+    /// [`FixedPointIndexer`](crate::fixed::FixedPointIndexer).
+    #[error("Invalid fixed-point map parameters: {0}")]
+    InvalidFixedPointParams(String),
+
+    /// Error when an HDF5 file doesn't describe a valid map.
+    #[cfg(feature = "hdf5")]
+    #[error("Invalid HDF5 map file: {0}")]
+    InvalidHdf5File(String),
+
+    /// Errors associated with the GPU filter backend.
+    #[cfg(feature = "gpu")]
+    #[error("GPU error: {0}")]
+    GpuError(String),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Error {
+    /// Returns `true` if the error was caused by a position, index or region lying outside the
+    /// map.
+    pub fn is_out_of_bounds(&self) -> bool {
+        matches!(
+            self,
+            Error::PositionOutsideMap(..)
+                | Error::IndexOutsideMap(..)
+                | Error::IndexOutsideLayer(..)
+                | Error::BoundsOutsideMap(..)
+                | Error::WindowLargerThanMap(..)
+        )
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Unwraps the result of a checked operation inside an accessor which can't return an [`Error`],
+/// such as [`Index`](std::ops::Index), panicking with the error's message if it failed.
+///
+/// This is the only place the crate panics on an internal failure, so that with the `strict`
+/// feature every other failure is returned as an [`Error`]. Code which mustn't panic should use
+/// the checked equivalent of the accessor instead.
+#[allow(clippy::panic)]
+#[track_caller]
+pub(crate) fn unwrap_checked<V>(result: Result<V, Error>) -> V {
+    match result {
+        Ok(value) => value,
+        Err(e) => panic!("{}", e),
+    }
 }
@@ -15,6 +15,7 @@ use crate::iterators::slicers::RectBounds;
 // ------------------------------------------------------------------------------------------------
 
 /// Provides a trait to convert a [`Vector2<T>`] to a shape acceptable to `ndarray`.
+#[allow(dead_code)]
 pub(crate) trait ToShape {
     fn to_shape(&self) -> (usize, usize);
 }
@@ -27,6 +28,7 @@ pub(crate) trait Point2Ext {
 }
 
 /// Provides extension traits to [`ndarray::Affine2<T>`]
+#[allow(dead_code)]
 pub(crate) trait Affine2Ext {
     fn position(&self, index: Point2<usize>) -> Point2<f64>;
 }
@@ -0,0 +1,498 @@
+//! Provides the Euclidean distance transform and obstacle inflation, including incremental
+//! inflation with [`IncrementalInflation`].
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+use ndarray::{Array2, ArrayView1, ArrayView2, ArrayViewMut1, Axis, Zip};
+use serde::{Deserialize, Serialize};
+
+use super::Backend;
+use crate::{math, queue::CellQueue, CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The offsets of the 8 neighbours of a cell.
+const NEIGHBOURS: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Parameters which control how obstacles are inflated by [`CellMap::inflate()`].
+///
+/// The cost of a cell is calculated from the distance $d$ between the centre of the cell and the
+/// centre of the nearest obstacle cell:
+///
+///  - $d \le r_i$: the cell is inside the robot's inscribed radius and has cost `1.0`.
+///  - $r_i < d \le r_{inf}$: the cost decays exponentially as $e^{-k (d - r_i)}$.
+///  - $d > r_{inf}$: the cell is far enough from all obstacles to have cost `0.0`.
+///
+/// where $r_i$ is `inscribed_radius`, $r_{inf}$ is `inflation_radius` and $k$ is
+/// `cost_scaling_factor`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct InflationParams {
+    /// The inscribed radius of the robot, in parent-frame units.
+    pub inscribed_radius: f64,
+
+    /// The distance from an obstacle beyond which cells have zero cost, in parent-frame units.
+    pub inflation_radius: f64,
+
+    /// The rate at which cost decays between the inscribed and inflation radii.
+    pub cost_scaling_factor: f64,
+}
+
+/// Maintains an inflated cost layer as the obstacles it was built from change, so that only the
+/// cells near the changes have to be updated, rather than inflating the whole map each cycle.
+///
+/// The inflation is built by [`IncrementalInflation::new()`], after which the caller changes the
+/// obstacle layer as normal and passes the changed cells to
+/// [`IncrementalInflation::notify_obstacle_changed()`]. Each update costs time proportional to the
+/// number of cells within the inflation radius of the changed obstacles.
+///
+/// Distances are propagated between neighbouring cells with the dynamic brushfire algorithm from
+/// Lau, Sprunk and Burgard, "Efficient grid-based spatial representations for robot navigation in
+/// dynamic environments", which tracks the nearest obstacle of each cell. This gives the exact
+/// Euclidean distance in almost all cases, but in rare configurations a cell may be given an
+/// obstacle which is slightly further away than the true nearest, so costs can differ slightly
+/// from [`CellMap::inflate()`].
+#[derive(Debug, Clone)]
+pub struct IncrementalInflation<L, F> {
+    src_layer: L,
+    dst_layer: L,
+    is_obstacle: F,
+    params: InflationParams,
+    cell_size: Vector2<f64>,
+    cells: Array2<InflationCell>,
+    queue: CellQueue,
+    changed: Vec<(usize, usize)>,
+}
+
+/// The state of a cell in an [`IncrementalInflation`].
+#[derive(Debug, Clone, Copy)]
+struct InflationCell {
+    /// Whether the cell is an obstacle.
+    is_obstacle: bool,
+
+    /// The index of the nearest obstacle, if there is one within the inflation radius.
+    nearest: Option<(usize, usize)>,
+
+    /// The distance to the nearest obstacle, or infinity if there isn't one.
+    distance: f64,
+
+    /// Whether the cell's nearest obstacle has been removed, and the cells which took their
+    /// distance from it still need to be cleared.
+    raise: bool,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl InflationParams {
+    /// Gets the cost of a cell at the given distance from the nearest obstacle.
+    pub fn cost(&self, distance: f64) -> f64 {
+        if distance <= self.inscribed_radius {
+            1.0
+        } else if distance <= self.inflation_radius {
+            math::exp(-self.cost_scaling_factor * (distance - self.inscribed_radius))
+        } else {
+            0.0
+        }
+    }
+}
+
+impl InflationCell {
+    const CLEAR: Self = Self {
+        is_obstacle: false,
+        nearest: None,
+        distance: f64::INFINITY,
+        raise: false,
+    };
+}
+
+impl<L, F> IncrementalInflation<L, F>
+where
+    L: Layer,
+    F: Fn(f64) -> bool,
+{
+    /// Inflates the obstacles in `src_layer` of `map` into `dst_layer`, as with
+    /// [`CellMap::inflate()`], keeping the state needed to update the inflation incrementally.
+    ///
+    /// A cell in `src_layer` is an obstacle if `is_obstacle` returns `true` for its value.
+    pub fn new(
+        map: &mut CellMap<L, f64>,
+        src_layer: L,
+        dst_layer: L,
+        is_obstacle: F,
+        params: InflationParams,
+    ) -> Self {
+        trace_span!(
+            "incremental_inflation",
+            num_cells = map[src_layer.clone()].len()
+        );
+
+        let mut inflation = Self {
+            src_layer,
+            dst_layer,
+            is_obstacle,
+            params,
+            cell_size: map.cell_size(),
+            cells: Array2::from_elem(map.cell_bounds().get_shape(), InflationCell::CLEAR),
+            queue: CellQueue::new(map.cell_size().min()),
+            changed: Vec::new(),
+        };
+
+        let obstacles: Vec<_> = map[inflation.src_layer.clone()]
+            .indexed_iter()
+            .filter(|(_, &v)| (inflation.is_obstacle)(v))
+            .map(|(i, _)| i)
+            .collect();
+        for index in obstacles {
+            inflation.set_obstacle(index);
+        }
+        inflation.propagate();
+
+        let params = inflation.params;
+        map[inflation.dst_layer.clone()] = inflation.cells.map(|c| params.cost(c.distance));
+        inflation.changed.clear();
+
+        inflation
+    }
+
+    /// Returns the parameters of the inflation.
+    pub fn params(&self) -> &InflationParams {
+        &self.params
+    }
+
+    /// Returns the distance, in parent-frame units, from the given cell to its nearest obstacle,
+    /// or infinity if there are no obstacles within the inflation radius.
+    ///
+    /// Returns `None` if the index is outside the map.
+    pub fn distance(&self, index: Point2<usize>) -> Option<f64> {
+        self.cells.get((index.y, index.x)).map(|c| c.distance)
+    }
+
+    /// Updates the inflated cost layer of `map` after the cells at `indices` in the obstacle
+    /// layer have changed.
+    ///
+    /// The indices may include cells which didn't change, or which are listed more than once.
+    /// Changes to obstacle cells which aren't listed are not picked up until they are.
+    ///
+    /// Returns [`Error::LayerDataWrongShape`] if the map's shape has changed since the inflation
+    /// was built, or [`Error::IndexOutsideLayer`] if an index is outside the map, in which case the
+    /// inflation is not changed.
+    pub fn notify_obstacle_changed(
+        &mut self,
+        map: &mut CellMap<L, f64>,
+        indices: &[Point2<usize>],
+    ) -> Result<(), Error> {
+        trace_span!("notify_obstacle_changed", num_changed = indices.len());
+
+        let shape = map.cell_bounds().get_shape();
+        if shape != self.cells.dim() {
+            return Err(Error::LayerDataWrongShape(
+                self.src_layer.to_index(),
+                shape,
+                self.cells.dim(),
+            ));
+        }
+        if let Some(&index) = indices.iter().find(|&&i| !map.index_in_map(i)) {
+            return Err(Error::IndexOutsideLayer(
+                self.src_layer.to_index(),
+                index,
+                map.cell_bounds(),
+            ));
+        }
+
+        for index in indices {
+            let index = (index.y, index.x);
+            let is_obstacle = (self.is_obstacle)(map[self.src_layer.clone()][index]);
+
+            if is_obstacle && !self.cells[index].is_obstacle {
+                self.set_obstacle(index);
+            } else if !is_obstacle && self.cells[index].is_obstacle {
+                self.remove_obstacle(index);
+            }
+        }
+        self.propagate();
+
+        let dst = &mut map[self.dst_layer.clone()];
+        for index in self.changed.drain(..) {
+            dst[index] = self.params.cost(self.cells[index].distance);
+        }
+
+        Ok(())
+    }
+
+    /// Makes the given cell an obstacle, starting a lowering wave from it.
+    fn set_obstacle(&mut self, index: (usize, usize)) {
+        self.cells[index] = InflationCell {
+            is_obstacle: true,
+            nearest: Some(index),
+            distance: 0.0,
+            raise: false,
+        };
+        self.push(index, 0.0);
+    }
+
+    /// Makes the given cell free, starting a raising wave from it which clears the cells whose
+    /// nearest obstacle it was.
+    fn remove_obstacle(&mut self, index: (usize, usize)) {
+        self.cells[index] = InflationCell {
+            raise: true,
+            ..InflationCell::CLEAR
+        };
+        self.push(index, 0.0);
+    }
+
+    /// Processes the queue until every cell has its nearest obstacle.
+    fn propagate(&mut self) {
+        while let Some((index, value)) = self.queue.pop() {
+            let index = (index.y, index.x);
+            let cell = self.cells[index];
+
+            if cell.raise {
+                self.raise(index);
+            } else if value <= cell.distance
+                && cell.nearest.is_some_and(|n| self.cells[n].is_obstacle)
+            {
+                self.lower(index);
+            }
+        }
+    }
+
+    /// Clears the neighbours of `index` whose nearest obstacle has been removed, and queues the
+    /// others so they can propagate their distances into the cleared cells.
+    fn raise(&mut self, index: (usize, usize)) {
+        for n in self.neighbours(index) {
+            let cell = self.cells[n];
+            let nearest = match cell.nearest {
+                Some(nearest) if !cell.raise => nearest,
+                _ => continue,
+            };
+
+            if !self.cells[nearest].is_obstacle {
+                self.cells[n] = InflationCell {
+                    raise: true,
+                    ..InflationCell::CLEAR
+                };
+            }
+            self.push(n, cell.distance);
+        }
+
+        self.cells[index].raise = false;
+    }
+
+    /// Gives the neighbours of `index` its nearest obstacle, if that's nearer than their own.
+    fn lower(&mut self, index: (usize, usize)) {
+        let nearest = match self.cells[index].nearest {
+            Some(nearest) => nearest,
+            None => return,
+        };
+
+        for n in self.neighbours(index) {
+            if self.cells[n].raise {
+                continue;
+            }
+
+            let distance = math::hypot(
+                (n.1 as f64 - nearest.1 as f64) * self.cell_size.x,
+                (n.0 as f64 - nearest.0 as f64) * self.cell_size.y,
+            );
+
+            if distance <= self.params.inflation_radius && distance < self.cells[n].distance {
+                self.cells[n].nearest = Some(nearest);
+                self.cells[n].distance = distance;
+                self.push(n, distance);
+            }
+        }
+    }
+
+    /// Returns the neighbours of `index` which are inside the map.
+    fn neighbours(&self, index: (usize, usize)) -> impl Iterator<Item = (usize, usize)> {
+        let (rows, cols) = self.cells.dim();
+
+        NEIGHBOURS.iter().filter_map(move |(dx, dy)| {
+            let y = index.0.checked_add_signed(*dy).filter(|&y| y < rows)?;
+            let x = index.1.checked_add_signed(*dx).filter(|&x| x < cols)?;
+            Some((y, x))
+        })
+    }
+
+    /// Queues the given cell, and marks its cost as needing to be rewritten.
+    fn push(&mut self, index: (usize, usize), value: f64) {
+        self.changed.push(index);
+        self.queue.push(Point2::new(index.1, index.0), value);
+    }
+}
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Calculates the Euclidean distance, in parent-frame units, from the centre of each cell to
+    /// the centre of the nearest obstacle cell, writing the result into `dst_layer`.
+    ///
+    /// A cell in `src_layer` is an obstacle if `is_obstacle` returns `true` for its value. If
+    /// there are no obstacles in the map all cells are set to `f64::INFINITY`. Non-square cells
+    /// are supported.
+    pub fn distance_transform<F>(
+        &mut self,
+        src_layer: L,
+        dst_layer: L,
+        is_obstacle: F,
+        backend: &Backend,
+    ) -> Result<(), Error>
+    where
+        F: Fn(f64) -> bool,
+    {
+        let obstacles = self[src_layer].mapv(is_obstacle);
+        self[dst_layer] = backend.distance_transform(obstacles.view(), self.cell_size())?;
+        Ok(())
+    }
+
+    /// Returns a mask of the cells whose centres are more than `clearance`, in parent-frame units,
+    /// from the centre of the nearest obstacle cell in `obstacle_layer`.
+    ///
+    /// A cell is an obstacle if `is_obstacle` returns `true` for its value, so `is_obstacle`
+    /// decides whether unknown (`NaN`) cells are safe. If there are no obstacles every cell is
+    /// safe. The distances are computed on the CPU, see [`CellMap::distance_transform()`] to use
+    /// another backend.
+    pub fn safe_cells<F>(&self, obstacle_layer: L, clearance: f64, is_obstacle: F) -> Array2<bool>
+    where
+        F: Fn(f64) -> bool,
+    {
+        let obstacles = self[obstacle_layer].mapv(is_obstacle);
+        distance_transform(obstacles.view(), self.cell_size()).mapv(|d| d > clearance)
+    }
+
+    /// Inflates the obstacles in `src_layer` into a cost layer in `dst_layer`, see
+    /// [`InflationParams`] for how the cost of each cell is calculated.
+    ///
+    /// A cell in `src_layer` is an obstacle if `is_obstacle` returns `true` for its value.
+    pub fn inflate<F>(
+        &mut self,
+        src_layer: L,
+        dst_layer: L,
+        is_obstacle: F,
+        params: &InflationParams,
+        backend: &Backend,
+    ) -> Result<(), Error>
+    where
+        F: Fn(f64) -> bool,
+    {
+        let obstacles = self[src_layer].mapv(is_obstacle);
+        self[dst_layer] = backend.inflate(obstacles.view(), self.cell_size(), params)?;
+        Ok(())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Calculates the Euclidean distance transform of `obstacles` on the CPU.
+///
+/// This uses the separable algorithm from Felzenszwalb and Huttenlocher, "Distance Transforms of
+/// Sampled Functions", first finding squared distances along each row and then combining them
+/// along each column.
+pub(crate) fn distance_transform(
+    obstacles: ArrayView2<bool>,
+    cell_size: Vector2<f64>,
+) -> Array2<f64> {
+    let sq_dist = obstacles.mapv(|o| if o { 0.0 } else { f64::INFINITY });
+
+    let sq_dist = transform_axis(sq_dist.view(), cell_size.x, Axis(1));
+    let mut sq_dist = transform_axis(sq_dist.view(), cell_size.y, Axis(0));
+
+    sq_dist.mapv_inplace(f64::sqrt);
+    sq_dist
+}
+
+/// Applies the 1D squared distance transform to each lane of `sq_dist` along `axis`.
+fn transform_axis(sq_dist: ArrayView2<f64>, spacing: f64, axis: Axis) -> Array2<f64> {
+    let mut out = Array2::zeros(sq_dist.raw_dim());
+
+    Zip::from(out.lanes_mut(axis))
+        .and(sq_dist.lanes(axis))
+        .for_each(|out, lane| transform_lane(lane, spacing, out));
+
+    out
+}
+
+/// Computes the 1D squared distance transform of `f`, i.e. $\min_q (s(p - q))^2 + f(q)$, where
+/// $s$ is the spacing between samples.
+///
+/// Samples with infinite values are not added to the lower envelope of parabolas, so lanes
+/// without any finite values remain infinite.
+fn transform_lane(f: ArrayView1<f64>, spacing: f64, mut out: ArrayViewMut1<f64>) {
+    let n = f.len();
+
+    // Locations of the parabolas in the lower envelope, and the boundaries between them
+    let mut v = vec![0usize; n];
+    let mut z = vec![0.0; n + 1];
+    let mut k: Option<usize> = None;
+
+    let pos = |i: usize| i as f64 * spacing;
+
+    for q in 0..n {
+        if !f[q].is_finite() {
+            continue;
+        }
+
+        loop {
+            match k {
+                None => {
+                    k = Some(0);
+                    v[0] = q;
+                    z[0] = f64::NEG_INFINITY;
+                    z[1] = f64::INFINITY;
+                    break;
+                }
+                Some(kk) => {
+                    let vk = v[kk];
+                    let s = ((f[q] + pos(q) * pos(q)) - (f[vk] + pos(vk) * pos(vk)))
+                        / (2.0 * (pos(q) - pos(vk)));
+
+                    if s <= z[kk] {
+                        k = kk.checked_sub(1);
+                    } else {
+                        v[kk + 1] = q;
+                        z[kk + 1] = s;
+                        z[kk + 2] = f64::INFINITY;
+                        k = Some(kk + 1);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    match k {
+        None => out.fill(f64::INFINITY),
+        Some(_) => {
+            let mut kk = 0;
+            for (p, o) in out.iter_mut().enumerate() {
+                while z[kk + 1] < pos(p) {
+                    kk += 1;
+                }
+                let d = pos(p) - pos(v[kk]);
+                *o = d * d + f[v[kk]];
+            }
+        }
+    }
+}
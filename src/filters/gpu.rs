@@ -0,0 +1,531 @@
+//! Provides the [`GpuBackend`], which computes filters using `wgpu` compute shaders.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::Vector2;
+use ndarray::{Array2, ArrayView2};
+use wgpu::util::DeviceExt;
+
+use super::{InflationParams, SeparableKernel};
+use crate::Error;
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Values at least this large are treated as infinite by the shaders.
+const GPU_INF: f32 = 3.0e38;
+
+/// Size of the 2D workgroups used by per-cell shaders.
+const CELL_WORKGROUP_SIZE: u32 = 8;
+
+/// Size of the 1D workgroups used by per-line shaders.
+const LINE_WORKGROUP_SIZE: u32 = 64;
+
+/// Binding numbers of the shader resources, which must match the `@binding`s in `gpu.wgsl`.
+const PARAMS_BINDING: u32 = 0;
+const INPUT_BINDING: u32 = 1;
+const OUTPUT_BINDING: u32 = 2;
+const KERNEL_BINDING: u32 = 3;
+const ENV_V_BINDING: u32 = 4;
+const ENV_Z_BINDING: u32 = 5;
+
+/// The resources used by the `correlate` entry point.
+const CORRELATE_BINDINGS: &[(u32, BufferKind)] = &[
+    (PARAMS_BINDING, BufferKind::Uniform),
+    (INPUT_BINDING, BufferKind::Read),
+    (OUTPUT_BINDING, BufferKind::ReadWrite),
+    (KERNEL_BINDING, BufferKind::Read),
+];
+
+/// The resources used by the `edt_lines` entry point.
+const EDT_LINES_BINDINGS: &[(u32, BufferKind)] = &[
+    (PARAMS_BINDING, BufferKind::Uniform),
+    (INPUT_BINDING, BufferKind::Read),
+    (OUTPUT_BINDING, BufferKind::ReadWrite),
+    (ENV_V_BINDING, BufferKind::ReadWrite),
+    (ENV_Z_BINDING, BufferKind::ReadWrite),
+];
+
+/// The resources used by the `inflate` entry point.
+const INFLATE_BINDINGS: &[(u32, BufferKind)] = &[
+    (PARAMS_BINDING, BufferKind::Uniform),
+    (INPUT_BINDING, BufferKind::Read),
+    (OUTPUT_BINDING, BufferKind::ReadWrite),
+];
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A [`Backend`] which computes filters on a GPU using `wgpu`.
+///
+/// Creating a [`GpuBackend`] is expensive, since it requests a device from the GPU and compiles
+/// all shaders, so a single backend should be created and reused. Cloning the backend is cheap
+/// and shares the same device.
+///
+/// All computation is performed in single precision.
+///
+/// [`Backend`]: crate::filters::Backend
+#[derive(Debug, Clone)]
+pub struct GpuBackend {
+    context: Arc<GpuContext>,
+}
+
+#[derive(Debug)]
+struct GpuContext {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    correlate: Shader,
+    edt_lines: Shader,
+    inflate: Shader,
+}
+
+/// A compute pipeline and the layout of the resources bound to it.
+#[derive(Debug)]
+struct Shader {
+    pipeline: wgpu::ComputePipeline,
+    layout: wgpu::BindGroupLayout,
+}
+
+/// Parameters passed to the shaders, must match `Params` in `gpu.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Pod, Zeroable)]
+struct Params {
+    width: u32,
+    height: u32,
+    semi_width: u32,
+    axis: u32,
+    spacing: f32,
+    inscribed_radius: f32,
+    inflation_radius: f32,
+    cost_scaling_factor: f32,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// The type of a buffer bound to a shader.
+#[derive(Debug, Clone, Copy)]
+enum BufferKind {
+    Uniform,
+    Read,
+    ReadWrite,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl GpuBackend {
+    /// Creates a new backend using the default GPU adapter, returning an error if no suitable
+    /// adapter or device is available.
+    pub fn new() -> Result<Self, Error> {
+        pollster::block_on(Self::new_async())
+    }
+
+    async fn new_async() -> Result<Self, Error> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                ..Default::default()
+            })
+            .await
+            .ok_or_else(|| Error::GpuError("No suitable GPU adapter found".into()))?;
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("cell_map::GpuBackend"),
+                    required_features: wgpu::Features::empty(),
+                    required_limits: adapter.limits(),
+                    memory_hints: wgpu::MemoryHints::Performance,
+                },
+                None,
+            )
+            .await
+            .map_err(|e| Error::GpuError(e.to_string()))?;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("cell_map::filters"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("gpu.wgsl").into()),
+        });
+
+        // The layouts are given explicitly rather than derived from the shader, so that pipeline
+        // creation fails if the shader's bindings don't match the buffers bound in this module.
+        let shader = |entry_point: &str, bindings: &[(u32, BufferKind)]| {
+            let entries: Vec<_> = bindings
+                .iter()
+                .map(|&(binding, kind)| wgpu::BindGroupLayoutEntry {
+                    binding,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: match kind {
+                            BufferKind::Uniform => wgpu::BufferBindingType::Uniform,
+                            BufferKind::Read => {
+                                wgpu::BufferBindingType::Storage { read_only: true }
+                            }
+                            BufferKind::ReadWrite => {
+                                wgpu::BufferBindingType::Storage { read_only: false }
+                            }
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                })
+                .collect();
+
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(entry_point),
+                entries: &entries,
+            });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(entry_point),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            });
+            let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point,
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
+            Shader { pipeline, layout }
+        };
+
+        let correlate = shader("correlate", CORRELATE_BINDINGS);
+        let edt_lines = shader("edt_lines", EDT_LINES_BINDINGS);
+        let inflate = shader("inflate", INFLATE_BINDINGS);
+
+        Ok(Self {
+            context: Arc::new(GpuContext {
+                device,
+                queue,
+                correlate,
+                edt_lines,
+                inflate,
+            }),
+        })
+    }
+
+    /// Applies the given [`SeparableKernel`] to `data`.
+    pub(crate) fn separable_filter(
+        &self,
+        data: ArrayView2<f64>,
+        kernel: &SeparableKernel,
+    ) -> Result<Array2<f64>, Error> {
+        if data.is_empty() {
+            return Ok(Array2::zeros(data.raw_dim()));
+        }
+
+        let ctx = &self.context;
+        let params = Params::for_data(&data);
+        let input = ctx.storage(&data.iter().map(|&v| v as f32).collect::<Vec<_>>());
+        let temp = ctx.empty_storage(data.len());
+        let output = ctx.empty_storage(data.len());
+        let kernel_x = ctx.storage(&to_f32(kernel.x()));
+        let kernel_y = ctx.storage(&to_f32(kernel.y()));
+
+        let mut encoder = ctx.encoder();
+
+        // Row pass followed by column pass
+        for (axis, src, dst, kern, kern_len) in [
+            (0, &input, &temp, &kernel_x, kernel.x().len()),
+            (1, &temp, &output, &kernel_y, kernel.y().len()),
+        ] {
+            let params = ctx.uniform(Params {
+                axis,
+                semi_width: (kern_len / 2) as u32,
+                ..params
+            });
+            let bind_group = ctx.bind_group(
+                &ctx.correlate,
+                &[
+                    (PARAMS_BINDING, &params),
+                    (INPUT_BINDING, src),
+                    (OUTPUT_BINDING, dst),
+                    (KERNEL_BINDING, kern),
+                ],
+            );
+            ctx.dispatch(
+                &mut encoder,
+                &ctx.correlate.pipeline,
+                &bind_group,
+                cell_workgroups(&data),
+            );
+        }
+
+        let result = ctx.read(encoder, &output)?;
+        from_f32(data.raw_dim(), result)
+    }
+
+    /// Calculates the Euclidean distance transform of `obstacles`.
+    pub(crate) fn distance_transform(
+        &self,
+        obstacles: ArrayView2<bool>,
+        cell_size: Vector2<f64>,
+    ) -> Result<Array2<f64>, Error> {
+        if obstacles.is_empty() {
+            return Ok(Array2::zeros(obstacles.raw_dim()));
+        }
+
+        let ctx = &self.context;
+        let mut encoder = ctx.encoder();
+        let sq_dist = self.encode_sq_distance(&mut encoder, obstacles, cell_size);
+
+        let result = ctx.read(encoder, &sq_dist)?;
+        Ok(from_f32(obstacles.raw_dim(), result)?.mapv(|d| {
+            if d >= GPU_INF as f64 {
+                f64::INFINITY
+            } else {
+                d.sqrt()
+            }
+        }))
+    }
+
+    /// Inflates `obstacles` into a cost array.
+    pub(crate) fn inflate(
+        &self,
+        obstacles: ArrayView2<bool>,
+        cell_size: Vector2<f64>,
+        inflation: &InflationParams,
+    ) -> Result<Array2<f64>, Error> {
+        if obstacles.is_empty() {
+            return Ok(Array2::zeros(obstacles.raw_dim()));
+        }
+
+        let ctx = &self.context;
+        let mut encoder = ctx.encoder();
+        let sq_dist = self.encode_sq_distance(&mut encoder, obstacles, cell_size);
+        let cost = ctx.empty_storage(obstacles.len());
+
+        let params = ctx.uniform(Params {
+            inscribed_radius: inflation.inscribed_radius as f32,
+            inflation_radius: inflation.inflation_radius as f32,
+            cost_scaling_factor: inflation.cost_scaling_factor as f32,
+            ..Params::for_data(&obstacles)
+        });
+        let bind_group = ctx.bind_group(
+            &ctx.inflate,
+            &[
+                (PARAMS_BINDING, &params),
+                (INPUT_BINDING, &sq_dist),
+                (OUTPUT_BINDING, &cost),
+            ],
+        );
+        ctx.dispatch(
+            &mut encoder,
+            &ctx.inflate.pipeline,
+            &bind_group,
+            cell_workgroups(&obstacles),
+        );
+
+        let result = ctx.read(encoder, &cost)?;
+        from_f32(obstacles.raw_dim(), result)
+    }
+
+    /// Encodes the passes needed to calculate the squared distance transform of `obstacles`,
+    /// returning the buffer which will contain the result.
+    fn encode_sq_distance(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        obstacles: ArrayView2<bool>,
+        cell_size: Vector2<f64>,
+    ) -> wgpu::Buffer {
+        let ctx = &self.context;
+        let params = Params::for_data(&obstacles);
+        let (width, height) = (params.width as usize, params.height as usize);
+
+        let input = ctx.storage(
+            &obstacles
+                .iter()
+                .map(|&o| if o { 0.0 } else { GPU_INF })
+                .collect::<Vec<_>>(),
+        );
+        let temp = ctx.empty_storage(obstacles.len());
+        let output = ctx.empty_storage(obstacles.len());
+
+        // Scratch space for the lower envelope of each line, which must be large enough for lines
+        // along either axis
+        let env_v = ctx.empty_storage(width * height);
+        let env_z = ctx.empty_storage((width + 1) * (height + 1));
+
+        for (axis, src, dst, spacing, num_lines) in [
+            (0, &input, &temp, cell_size.x, height),
+            (1, &temp, &output, cell_size.y, width),
+        ] {
+            let params = ctx.uniform(Params {
+                axis,
+                spacing: spacing as f32,
+                ..params
+            });
+            let bind_group = ctx.bind_group(
+                &ctx.edt_lines,
+                &[
+                    (PARAMS_BINDING, &params),
+                    (INPUT_BINDING, src),
+                    (OUTPUT_BINDING, dst),
+                    (ENV_V_BINDING, &env_v),
+                    (ENV_Z_BINDING, &env_z),
+                ],
+            );
+            ctx.dispatch(
+                encoder,
+                &ctx.edt_lines.pipeline,
+                &bind_group,
+                (workgroups(num_lines, LINE_WORKGROUP_SIZE), 1),
+            );
+        }
+
+        output
+    }
+}
+
+impl GpuContext {
+    fn encoder(&self) -> wgpu::CommandEncoder {
+        self.device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None })
+    }
+
+    fn uniform(&self, params: Params) -> wgpu::Buffer {
+        self.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::bytes_of(&params),
+                usage: wgpu::BufferUsages::UNIFORM,
+            })
+    }
+
+    fn storage<D: Pod>(&self, data: &[D]) -> wgpu::Buffer {
+        self.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(data),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            })
+    }
+
+    /// Creates an uninitialised storage buffer of `len` 32-bit elements.
+    fn empty_storage(&self, len: usize) -> wgpu::Buffer {
+        self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (len * 4) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Binds each buffer to the given binding number of `shader`.
+    fn bind_group(&self, shader: &Shader, buffers: &[(u32, &wgpu::Buffer)]) -> wgpu::BindGroup {
+        let entries: Vec<_> = buffers
+            .iter()
+            .map(|&(binding, buffer)| wgpu::BindGroupEntry {
+                binding,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &shader.layout,
+            entries: &entries,
+        })
+    }
+
+    fn dispatch(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::ComputePipeline,
+        bind_group: &wgpu::BindGroup,
+        workgroups: (u32, u32),
+    ) {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+    }
+
+    /// Submits the encoder and reads back the contents of `buffer`.
+    fn read(
+        &self,
+        mut encoder: wgpu::CommandEncoder,
+        buffer: &wgpu::Buffer,
+    ) -> Result<Vec<f32>, Error> {
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: buffer.size(),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |res| {
+            let _ = sender.send(res);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+
+        receiver
+            .recv()
+            .map_err(|e| Error::GpuError(e.to_string()))?
+            .map_err(|e| Error::GpuError(e.to_string()))?;
+
+        let data = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        staging.unmap();
+
+        Ok(data)
+    }
+}
+
+impl Params {
+    fn for_data<T>(data: &ArrayView2<T>) -> Self {
+        Self {
+            width: data.ncols() as u32,
+            height: data.nrows() as u32,
+            ..Default::default()
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+fn to_f32(data: &[f64]) -> Vec<f32> {
+    data.iter().map(|&v| v as f32).collect()
+}
+
+/// Converts data read back from the GPU into an array. The data is in standard (row-major) layout.
+fn from_f32(dim: ndarray::Ix2, data: Vec<f32>) -> Result<Array2<f64>, Error> {
+    Array2::from_shape_vec(dim, data)
+        .map(|data| data.mapv(|v| v as f64))
+        .map_err(|_| Error::GpuError("GPU returned data of the wrong size".into()))
+}
+
+fn workgroups(n: usize, size: u32) -> u32 {
+    (n as u32).div_ceil(size)
+}
+
+fn cell_workgroups<T>(data: &ArrayView2<T>) -> (u32, u32) {
+    (
+        workgroups(data.ncols(), CELL_WORKGROUP_SIZE),
+        workgroups(data.nrows(), CELL_WORKGROUP_SIZE),
+    )
+}
@@ -0,0 +1,158 @@
+// Compute shaders for the GPU filter backend, see `gpu.rs`.
+//
+// Layers are stored in row-major order, i.e. the cell (x, y) is at index `y * width + x`.
+
+struct Params {
+    width: u32,
+    height: u32,
+    // Number of kernel elements either side of the central element.
+    semi_width: u32,
+    // 0 to operate along rows (the x axis), 1 to operate along columns (the y axis).
+    axis: u32,
+    // Distance between the centres of adjacent cells along the axis.
+    spacing: f32,
+    inscribed_radius: f32,
+    inflation_radius: f32,
+    cost_scaling_factor: f32,
+}
+
+// Values at least this large are treated as infinite.
+const INF: f32 = 3.0e38;
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> input: array<f32>;
+@group(0) @binding(2) var<storage, read_write> output: array<f32>;
+@group(0) @binding(3) var<storage, read> kernel: array<f32>;
+@group(0) @binding(4) var<storage, read_write> env_v: array<u32>;
+@group(0) @binding(5) var<storage, read_write> env_z: array<f32>;
+
+// Returns the index into a layer of the `i`th element of the given line along `params.axis`.
+fn line_index(line: u32, i: u32) -> u32 {
+    if params.axis == 0u {
+        return line * params.width + i;
+    }
+    return i * params.width + line;
+}
+
+// Correlates each cell with the 1D kernel along `params.axis`, clamping to the edge of the layer.
+@compute @workgroup_size(8, 8)
+fn correlate(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.width || id.y >= params.height {
+        return;
+    }
+
+    var line = id.y;
+    var pos = i32(id.x);
+    var len = i32(params.width);
+    if params.axis == 1u {
+        line = id.x;
+        pos = i32(id.y);
+        len = i32(params.height);
+    }
+
+    var sum = 0.0;
+    for (var k = 0u; k < params.semi_width * 2u + 1u; k++) {
+        let j = clamp(pos + i32(k) - i32(params.semi_width), 0, len - 1);
+        sum += kernel[k] * input[line_index(line, u32(j))];
+    }
+
+    output[line_index(line, u32(pos))] = sum;
+}
+
+// Computes the 1D squared distance transform of each line along `params.axis`, using the lower
+// envelope of parabolas from Felzenszwalb and Huttenlocher. Infinite samples are skipped.
+@compute @workgroup_size(64)
+fn edt_lines(@builtin(global_invocation_id) id: vec3<u32>) {
+    let line = id.x;
+    var n = params.width;
+    var num_lines = params.height;
+    if params.axis == 1u {
+        n = params.height;
+        num_lines = params.width;
+    }
+    if line >= num_lines {
+        return;
+    }
+
+    let v_base = line * n;
+    let z_base = line * (n + 1u);
+    let s = params.spacing;
+
+    // Number of parabolas in the envelope
+    var count = 0u;
+
+    for (var q = 0u; q < n; q++) {
+        let fq = input[line_index(line, q)];
+        if fq >= INF {
+            continue;
+        }
+        let pq = f32(q) * s;
+
+        loop {
+            if count == 0u {
+                env_v[v_base] = q;
+                env_z[z_base] = -INF;
+                env_z[z_base + 1u] = INF;
+                count = 1u;
+                break;
+            }
+
+            let k = count - 1u;
+            let vk = env_v[v_base + k];
+            let pv = f32(vk) * s;
+            let fv = input[line_index(line, vk)];
+            let inter = ((fq + pq * pq) - (fv + pv * pv)) / (2.0 * (pq - pv));
+
+            if inter <= env_z[z_base + k] {
+                count -= 1u;
+            } else {
+                env_v[v_base + count] = q;
+                env_z[z_base + count] = inter;
+                env_z[z_base + count + 1u] = INF;
+                count += 1u;
+                break;
+            }
+        }
+    }
+
+    if count == 0u {
+        for (var p = 0u; p < n; p++) {
+            output[line_index(line, p)] = INF;
+        }
+        return;
+    }
+
+    var k = 0u;
+    for (var p = 0u; p < n; p++) {
+        let pp = f32(p) * s;
+        while env_z[z_base + k + 1u] < pp {
+            k++;
+        }
+        let vk = env_v[v_base + k];
+        let d = pp - f32(vk) * s;
+        output[line_index(line, p)] = d * d + input[line_index(line, vk)];
+    }
+}
+
+// Converts squared distances into inflated costs.
+@compute @workgroup_size(8, 8)
+fn inflate(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.width || id.y >= params.height {
+        return;
+    }
+
+    let idx = id.y * params.width + id.x;
+    let sq_dist = input[idx];
+
+    var cost = 0.0;
+    if sq_dist < INF {
+        let dist = sqrt(sq_dist);
+        if dist <= params.inscribed_radius {
+            cost = 1.0;
+        } else if dist <= params.inflation_radius {
+            cost = exp(-params.cost_scaling_factor * (dist - params.inscribed_radius));
+        }
+    }
+
+    output[idx] = cost;
+}
@@ -0,0 +1,291 @@
+//! Provides filters which can be applied to the layers of a [`CellMap`].
+//!
+//! Filters read from a source layer and write their result into a destination layer, which may be
+//! the same as the source layer. Cells which would need data from outside the map use the value of
+//! the nearest cell inside the map.
+//!
+//! Expensive filters, such as convolution, the distance transform, and inflation, can be run on
+//! different [`Backend`]s. With the `gpu` feature enabled these can be offloaded to a GPU using
+//! [`GpuBackend`].
+//!
+//! Morphological operations, such as erosion and dilation, use a [`StructuringElement`] sized in
+//! parent-frame units, and are available for both `f64` layers and `bool` masks.
+//!
+//! [`CellMap`]: crate::CellMap
+//! [`GpuBackend`]: crate::filters::GpuBackend
+
+// ------------------------------------------------------------------------------------------------
+// MODULES
+// ------------------------------------------------------------------------------------------------
+
+mod distance;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod morphology;
+#[cfg(test)]
+mod tests;
+
+// ------------------------------------------------------------------------------------------------
+// EXPORTS
+// ------------------------------------------------------------------------------------------------
+
+pub use distance::{IncrementalInflation, InflationParams};
+#[cfg(feature = "gpu")]
+pub use gpu::GpuBackend;
+pub use morphology::StructuringElement;
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::Vector2;
+use ndarray::{Array2, ArrayView1, ArrayView2, ArrayViewMut1, Axis, Zip};
+
+use crate::{math, CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// Selects where expensive filters are computed.
+#[derive(Debug, Clone, Default)]
+#[allow(missing_copy_implementations)]
+pub enum Backend {
+    /// Compute filters on the CPU, in double precision.
+    #[default]
+    Cpu,
+
+    /// Compute filters on a GPU, in single precision.
+    #[cfg(feature = "gpu")]
+    Gpu(GpuBackend),
+}
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A 2D kernel which can be separated into a pair of 1D kernels, one applied along the `x` axis
+/// and one along the `y` axis.
+///
+/// Applying a separable kernel of size $k_x \times k_y$ costs $O(k_x + k_y)$ per cell, rather than
+/// the $O(k_x k_y)$ of the equivalent full 2D kernel.
+///
+/// Kernels are applied as a correlation, i.e. they are not flipped, which for the symmetric
+/// kernels built by [`SeparableKernel::gaussian()`] and [`SeparableKernel::boxcar()`] is identical
+/// to a convolution.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeparableKernel {
+    x: Vec<f64>,
+    y: Vec<f64>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Backend {
+    /// Applies the given [`SeparableKernel`] to `data`, returning the filtered data.
+    pub fn separable_filter(
+        &self,
+        data: ArrayView2<f64>,
+        kernel: &SeparableKernel,
+    ) -> Result<Array2<f64>, Error> {
+        trace_span!("separable_filter", backend = ?self, num_cells = data.len());
+        match self {
+            Self::Cpu => Ok(kernel.apply(data)),
+            #[cfg(feature = "gpu")]
+            Self::Gpu(gpu) => gpu.separable_filter(data, kernel),
+        }
+    }
+
+    /// Calculates the Euclidean distance, in parent-frame units, from each cell to the nearest
+    /// cell for which `obstacles` is `true`, for a map with the given `cell_size`.
+    ///
+    /// If there are no obstacles all cells are set to `f64::INFINITY`.
+    pub fn distance_transform(
+        &self,
+        obstacles: ArrayView2<bool>,
+        cell_size: Vector2<f64>,
+    ) -> Result<Array2<f64>, Error> {
+        trace_span!("distance_transform", backend = ?self, num_cells = obstacles.len());
+        match self {
+            Self::Cpu => Ok(distance::distance_transform(obstacles, cell_size)),
+            #[cfg(feature = "gpu")]
+            Self::Gpu(gpu) => gpu.distance_transform(obstacles, cell_size),
+        }
+    }
+
+    /// Inflates the cells for which `obstacles` is `true` into a cost array, see
+    /// [`InflationParams`].
+    pub fn inflate(
+        &self,
+        obstacles: ArrayView2<bool>,
+        cell_size: Vector2<f64>,
+        params: &InflationParams,
+    ) -> Result<Array2<f64>, Error> {
+        trace_span!("inflate", backend = ?self, num_cells = obstacles.len());
+        match self {
+            Self::Cpu => {
+                Ok(distance::distance_transform(obstacles, cell_size).mapv(|d| params.cost(d)))
+            }
+            #[cfg(feature = "gpu")]
+            Self::Gpu(gpu) => gpu.inflate(obstacles, cell_size, params),
+        }
+    }
+}
+
+impl SeparableKernel {
+    /// Creates a new kernel from the given 1D kernels along the `x` and `y` axes.
+    ///
+    /// Both kernels must have an odd length so that they have a central element, otherwise an
+    /// [`Error::InvalidKernelSize`] is returned.
+    pub fn new(x: Vec<f64>, y: Vec<f64>) -> Result<Self, Error> {
+        if x.len().is_multiple_of(2) || y.len().is_multiple_of(2) {
+            Err(Error::InvalidKernelSize(x.len(), y.len()))
+        } else {
+            Ok(Self { x, y })
+        }
+    }
+
+    /// Creates a normalised Gaussian kernel with standard deviation `sigma`, given in parent-frame
+    /// units, for a map with the given `cell_size`.
+    ///
+    /// The kernel is truncated at three standard deviations from the centre. If `sigma` is not
+    /// positive and finite an [`Error::InvalidSigma`] is returned.
+    pub fn gaussian(sigma: f64, cell_size: Vector2<f64>) -> Result<Self, Error> {
+        if !(sigma > 0.0 && sigma.is_finite()) {
+            return Err(Error::InvalidSigma(sigma));
+        }
+
+        Ok(Self {
+            x: gaussian_1d(sigma, cell_size.x),
+            y: gaussian_1d(sigma, cell_size.y),
+        })
+    }
+
+    /// Creates a normalised box (mean) kernel which covers `semi_width` cells either side of the
+    /// central cell.
+    pub fn boxcar(semi_width: Vector2<usize>) -> Self {
+        let box_1d = |semi_width: usize| {
+            let len = semi_width * 2 + 1;
+            vec![1.0 / len as f64; len]
+        };
+
+        Self {
+            x: box_1d(semi_width.x),
+            y: box_1d(semi_width.y),
+        }
+    }
+
+    /// Returns the kernel applied along the `x` axis.
+    pub fn x(&self) -> &[f64] {
+        &self.x
+    }
+
+    /// Returns the kernel applied along the `y` axis.
+    pub fn y(&self) -> &[f64] {
+        &self.y
+    }
+
+    /// Applies this kernel to the given data, returning the filtered data.
+    pub fn apply(&self, data: ArrayView2<f64>) -> Array2<f64> {
+        // Row pass, along x, followed by column pass, along y
+        let rows = correlate_axis(data, &self.x, Axis(1));
+        correlate_axis(rows.view(), &self.y, Axis(0))
+    }
+}
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Filters `src_layer` with the given [`SeparableKernel`], writing the result into
+    /// `dst_layer`.
+    pub fn separable_filter(&mut self, src_layer: L, dst_layer: L, kernel: &SeparableKernel) {
+        trace_span!(
+            "separable_filter",
+            num_cells = self.num_cells().iter().product::<usize>()
+        );
+        self[dst_layer] = kernel.apply(self[src_layer].view());
+    }
+
+    /// Filters `src_layer` with the given [`SeparableKernel`] on the given [`Backend`], writing
+    /// the result into `dst_layer`.
+    pub fn convolve(
+        &mut self,
+        src_layer: L,
+        dst_layer: L,
+        kernel: &SeparableKernel,
+        backend: &Backend,
+    ) -> Result<(), Error> {
+        self[dst_layer] = backend.separable_filter(self[src_layer].view(), kernel)?;
+        Ok(())
+    }
+
+    /// Smooths `src_layer` with a Gaussian kernel of standard deviation `sigma`, given in
+    /// parent-frame units, writing the result into `dst_layer`.
+    ///
+    /// Returns an [`Error::InvalidSigma`] if `sigma` is not positive and finite.
+    pub fn gaussian_filter(&mut self, src_layer: L, dst_layer: L, sigma: f64) -> Result<(), Error> {
+        let kernel = SeparableKernel::gaussian(sigma, self.cell_size())?;
+        self.separable_filter(src_layer, dst_layer, &kernel);
+        Ok(())
+    }
+
+    /// Replaces each cell in `dst_layer` with the mean of the window of `semi_width` around the
+    /// same cell in `src_layer`.
+    pub fn box_filter(&mut self, src_layer: L, dst_layer: L, semi_width: Vector2<usize>) {
+        let kernel = SeparableKernel::boxcar(semi_width);
+        self.separable_filter(src_layer, dst_layer, &kernel);
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Builds a normalised 1D Gaussian kernel for cells of the given size.
+fn gaussian_1d(sigma: f64, cell_size: f64) -> Vec<f64> {
+    let semi_width = (3.0 * sigma / cell_size).ceil().max(0.0) as isize;
+
+    let mut kernel: Vec<f64> = (-semi_width..=semi_width)
+        .map(|i| {
+            let dist = i as f64 * cell_size;
+            math::exp(-(dist * dist) / (2.0 * sigma * sigma))
+        })
+        .collect();
+
+    let sum: f64 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|k| *k /= sum);
+
+    kernel
+}
+
+/// Correlates each lane of `data` along `axis` with the 1D `kernel`.
+fn correlate_axis(data: ArrayView2<f64>, kernel: &[f64], axis: Axis) -> Array2<f64> {
+    let mut out = Array2::zeros(data.raw_dim());
+
+    Zip::from(out.lanes_mut(axis))
+        .and(data.lanes(axis))
+        .for_each(|out, lane| correlate_lane(lane, kernel, out));
+
+    out
+}
+
+/// Correlates a single lane with the 1D `kernel`, clamping indexes which fall outside the lane to
+/// the nearest edge.
+fn correlate_lane(lane: ArrayView1<f64>, kernel: &[f64], mut out: ArrayViewMut1<f64>) {
+    let len = lane.len() as isize;
+    let semi_width = (kernel.len() / 2) as isize;
+
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = kernel
+            .iter()
+            .enumerate()
+            .map(|(k, w)| {
+                let j = (i as isize + k as isize - semi_width).clamp(0, len - 1);
+                w * lane[j as usize]
+            })
+            .sum();
+    }
+}
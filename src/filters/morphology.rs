@@ -0,0 +1,197 @@
+//! Provides binary and grayscale morphological operations.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::collections::BTreeSet;
+
+use nalgebra::Vector2;
+use ndarray::{Array2, ArrayView2};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cell_map::{cells_within, DISTANCE_TOLERANCE},
+    math, CellMap, Layer,
+};
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// The shape of the neighbourhood used by morphological operations, sized in parent-frame units
+/// so that the same element covers the same area regardless of the map's cell size.
+///
+/// The element covers every cell whose centre is inside the shape when the shape is centred on
+/// the cell being filtered.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StructuringElement {
+    /// A disc with the given radius, for example the radius of the robot.
+    Disc(f64),
+
+    /// A rectangle extending the given distance either side of the centre along the `x` and `y`
+    /// axes.
+    Rectangle(Vector2<f64>),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl StructuringElement {
+    /// Returns the `(x, y)` cell offsets covered by the element for a map with the given
+    /// `cell_size`. The centre offset `(0, 0)` is always included.
+    pub fn offsets(&self, cell_size: Vector2<f64>) -> Vec<(isize, isize)> {
+        let extent = match self {
+            Self::Disc(radius) => Vector2::new(*radius, *radius),
+            Self::Rectangle(semi_size) => *semi_size,
+        };
+        let semi_x = cells_within(extent.x, cell_size.x) as isize;
+        let semi_y = cells_within(extent.y, cell_size.y) as isize;
+
+        (-semi_y..=semi_y)
+            .flat_map(|dy| (-semi_x..=semi_x).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| match self {
+                Self::Disc(radius) => {
+                    math::hypot(dx as f64 * cell_size.x, dy as f64 * cell_size.y)
+                        <= radius * (1.0 + DISTANCE_TOLERANCE)
+                }
+                Self::Rectangle(_) => true,
+            })
+            .chain(std::iter::once((0, 0)))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Replaces each cell in `dst_layer` with the minimum of `src_layer` under `element` centred
+    /// on the cell.
+    ///
+    /// `NaN` cells under the element are ignored, and cells which are `NaN` themselves stay
+    /// `NaN`.
+    pub fn erode(&mut self, src_layer: L, dst_layer: L, element: &StructuringElement) {
+        let offsets = element.offsets(self.cell_size());
+        self[dst_layer] = grayscale(self[src_layer].view(), &offsets, f64::min);
+    }
+
+    /// Replaces each cell in `dst_layer` with the maximum of `src_layer` under `element` centred
+    /// on the cell.
+    ///
+    /// `NaN` cells under the element are ignored, and cells which are `NaN` themselves stay
+    /// `NaN`.
+    pub fn dilate(&mut self, src_layer: L, dst_layer: L, element: &StructuringElement) {
+        let offsets = element.offsets(self.cell_size());
+        self[dst_layer] = grayscale(self[src_layer].view(), &offsets, f64::max);
+    }
+
+    /// Erodes and then dilates `src_layer`, writing the result into `dst_layer`, which removes
+    /// peaks smaller than `element`.
+    pub fn open(&mut self, src_layer: L, dst_layer: L, element: &StructuringElement) {
+        self.erode(src_layer, dst_layer.clone(), element);
+        self.dilate(dst_layer.clone(), dst_layer, element);
+    }
+
+    /// Dilates and then erodes `src_layer`, writing the result into `dst_layer`, which fills pits
+    /// smaller than `element`.
+    pub fn close(&mut self, src_layer: L, dst_layer: L, element: &StructuringElement) {
+        self.dilate(src_layer, dst_layer.clone(), element);
+        self.erode(dst_layer.clone(), dst_layer, element);
+    }
+}
+
+impl<L> CellMap<L, bool>
+where
+    L: Layer,
+{
+    /// Sets each cell in `dst_layer` to `true` only if every cell of `src_layer` under `element`
+    /// centred on the cell is `true`.
+    pub fn erode_mask(&mut self, src_layer: L, dst_layer: L, element: &StructuringElement) {
+        let offsets = element.offsets(self.cell_size());
+        self[dst_layer] = binary(self[src_layer].view(), &offsets, true);
+    }
+
+    /// Sets each cell in `dst_layer` to `true` if any cell of `src_layer` under `element` centred
+    /// on the cell is `true`, for example to grow an obstacle mask by the robot's radius.
+    pub fn dilate_mask(&mut self, src_layer: L, dst_layer: L, element: &StructuringElement) {
+        let offsets = element.offsets(self.cell_size());
+        self[dst_layer] = binary(self[src_layer].view(), &offsets, false);
+    }
+
+    /// Erodes and then dilates `src_layer`, writing the result into `dst_layer`, which removes
+    /// `true` regions smaller than `element`, such as isolated noise.
+    pub fn open_mask(&mut self, src_layer: L, dst_layer: L, element: &StructuringElement) {
+        self.erode_mask(src_layer, dst_layer.clone(), element);
+        self.dilate_mask(dst_layer.clone(), dst_layer, element);
+    }
+
+    /// Dilates and then erodes `src_layer`, writing the result into `dst_layer`, which fills
+    /// `false` holes smaller than `element`.
+    pub fn close_mask(&mut self, src_layer: L, dst_layer: L, element: &StructuringElement) {
+        self.dilate_mask(src_layer, dst_layer.clone(), element);
+        self.erode_mask(dst_layer.clone(), dst_layer, element);
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Folds `func` over the in-map cells of `data` covered by `offsets` around the cell at `(y, x)`.
+fn fold_neighbourhood<T, A, F>(
+    data: &ArrayView2<'_, T>,
+    offsets: &[(isize, isize)],
+    (y, x): (usize, usize),
+    init: A,
+    func: F,
+) -> A
+where
+    T: Copy,
+    F: Fn(A, T) -> A,
+{
+    offsets
+        .iter()
+        .filter_map(|&(dx, dy)| {
+            let nx = x.checked_add_signed(dx)?;
+            let ny = y.checked_add_signed(dy)?;
+            data.get((ny, nx)).copied()
+        })
+        .fold(init, func)
+}
+
+/// Applies a grayscale erosion or dilation, combining neighbouring values with `extremum`.
+fn grayscale(
+    data: ArrayView2<'_, f64>,
+    offsets: &[(isize, isize)],
+    extremum: fn(f64, f64) -> f64,
+) -> Array2<f64> {
+    Array2::from_shape_fn(data.dim(), |index| {
+        let value = data[index];
+        if value.is_nan() {
+            return f64::NAN;
+        }
+
+        fold_neighbourhood(&data, offsets, index, value, |acc, v| {
+            if v.is_nan() {
+                acc
+            } else {
+                extremum(acc, v)
+            }
+        })
+    })
+}
+
+/// Applies a binary erosion if `erode` is `true`, otherwise a binary dilation.
+fn binary(data: ArrayView2<'_, bool>, offsets: &[(isize, isize)], erode: bool) -> Array2<bool> {
+    Array2::from_shape_fn(data.dim(), |index| {
+        if erode {
+            fold_neighbourhood(&data, offsets, index, true, |acc, v| acc && v)
+        } else {
+            fold_neighbourhood(&data, offsets, index, false, |acc, v| acc || v)
+        }
+    })
+}
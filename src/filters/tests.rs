@@ -0,0 +1,401 @@
+//! Tests for filters
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+
+use super::*;
+use crate::{test_utils::TestLayers, Bounds, CellMapParams};
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[test]
+fn kernels() {
+    assert!(SeparableKernel::new(vec![1.0; 2], vec![1.0; 3]).is_err());
+    assert!(SeparableKernel::new(vec![1.0; 3], vec![1.0; 1]).is_ok());
+
+    // Gaussian kernels should be normalised, symmetric, and account for the cell size
+    let kernel = SeparableKernel::gaussian(1.0, Vector2::new(0.5, 1.0)).unwrap();
+    assert_eq!(kernel.x().len(), 13);
+    assert_eq!(kernel.y().len(), 7);
+    assert_f64_eq!(kernel.x().iter().sum::<f64>(), 1.0, 1e-12);
+    assert_f64_eq!(kernel.y().iter().sum::<f64>(), 1.0, 1e-12);
+    assert_f64_iter_eq!(
+        kernel.x(),
+        kernel.x().iter().rev().copied().collect::<Vec<_>>()
+    );
+
+    // Standard deviations which would give a NaN kernel are rejected
+    for &sigma in &[0.0, -1.0, f64::NAN, f64::INFINITY] {
+        assert!(matches!(
+            SeparableKernel::gaussian(sigma, Vector2::new(1.0, 1.0)),
+            Err(Error::InvalidSigma(_))
+        ));
+    }
+}
+
+#[test]
+fn separable_matches_window() {
+    let mut map = CellMap::<TestLayers, f64>::new(CellMapParams {
+        cell_bounds: Bounds::new((0, 8), (0, 6)).unwrap(),
+        ..Default::default()
+    });
+
+    map.iter_mut()
+        .layer(TestLayers::Layer0)
+        .indexed()
+        .for_each(|((_, idx), v)| *v = (idx.x * idx.x + 3 * idx.y) as f64);
+
+    // Box filter the map, and compare it with the mean of each window in the interior
+    let semi_width = Vector2::new(2, 1);
+    map.box_filter(TestLayers::Layer0, TestLayers::Layer1, semi_width);
+    map.window_map(TestLayers::Layer0, TestLayers::Layer2, semi_width, |w| {
+        w.mean().unwrap()
+    })
+    .unwrap();
+
+    for y in 1..5 {
+        for x in 2..6 {
+            let idx = Point2::new(x, y);
+            assert_f64_eq!(
+                map[(TestLayers::Layer1, idx)],
+                map[(TestLayers::Layer2, idx)],
+                1e-9
+            );
+        }
+    }
+
+    // Edge cells are clamped, so filtering a constant layer leaves it unchanged
+    map.iter_mut()
+        .layer(TestLayers::Layer0)
+        .for_each(|v| *v = 2.0);
+    map.gaussian_filter(TestLayers::Layer0, TestLayers::Layer0, 1.5)
+        .unwrap();
+    assert!(map
+        .iter()
+        .layer(TestLayers::Layer0)
+        .all(|&v| (v - 2.0).abs() < 1e-12));
+}
+
+/// Builds a map with a few obstacles (value `1.0`) in layer 0 and non-square cells.
+fn obstacle_map() -> CellMap<TestLayers, f64> {
+    let mut map = CellMap::<TestLayers, f64>::new(CellMapParams {
+        cell_bounds: Bounds::new((0, 12), (0, 9)).unwrap(),
+        cell_size: Vector2::new(0.5, 0.2),
+        ..Default::default()
+    });
+
+    for &(x, y) in &[(2, 3), (9, 7), (10, 1)] {
+        map.set(TestLayers::Layer0, Point2::new(x, y), 1.0).unwrap();
+    }
+
+    map
+}
+
+#[test]
+fn distance_transform() {
+    let mut map = obstacle_map();
+    map.distance_transform(
+        TestLayers::Layer0,
+        TestLayers::Layer1,
+        |v| v > 0.5,
+        &Backend::Cpu,
+    )
+    .unwrap();
+
+    // Compare against the brute force distance to each obstacle
+    let obstacles: Vec<_> = map
+        .iter()
+        .layer(TestLayers::Layer0)
+        .positioned()
+        .filter(|(_, &v)| v > 0.5)
+        .map(|((_, pos), _)| pos)
+        .collect();
+
+    for ((_, pos), &dist) in map.iter().layer(TestLayers::Layer1).positioned() {
+        let expected = obstacles
+            .iter()
+            .map(|o| (o - pos).norm())
+            .fold(f64::INFINITY, f64::min);
+        assert_f64_eq!(dist, expected, 1e-9);
+    }
+
+    // No obstacles gives infinite distance everywhere
+    map.distance_transform(
+        TestLayers::Layer2,
+        TestLayers::Layer1,
+        |v| v > 0.5,
+        &Backend::Cpu,
+    )
+    .unwrap();
+    assert!(map
+        .iter()
+        .layer(TestLayers::Layer1)
+        .all(|v| v.is_infinite()));
+}
+
+#[test]
+fn inflate() {
+    let params = InflationParams {
+        inscribed_radius: 0.3,
+        inflation_radius: 1.0,
+        cost_scaling_factor: 2.0,
+    };
+
+    assert_f64_eq!(params.cost(0.0), 1.0);
+    assert_f64_eq!(params.cost(0.3), 1.0);
+    assert_f64_eq!(params.cost(0.5), (-0.4f64).exp());
+    assert_f64_eq!(params.cost(1.1), 0.0);
+
+    let mut map = obstacle_map();
+    map.inflate(
+        TestLayers::Layer0,
+        TestLayers::Layer1,
+        |v| v > 0.5,
+        &params,
+        &Backend::Cpu,
+    )
+    .unwrap();
+
+    assert_f64_eq!(map[(TestLayers::Layer1, Point2::new(2, 3))], 1.0);
+    assert_f64_eq!(map[(TestLayers::Layer1, Point2::new(2, 4))], 1.0);
+    assert_f64_eq!(
+        map[(TestLayers::Layer1, Point2::new(3, 3))],
+        params.cost(0.5)
+    );
+    assert_f64_eq!(map[(TestLayers::Layer1, Point2::new(6, 3))], 0.0);
+}
+
+#[test]
+fn incremental_inflation() {
+    let params = InflationParams {
+        inscribed_radius: 0.3,
+        inflation_radius: 1.2,
+        cost_scaling_factor: 2.0,
+    };
+    let is_obstacle = |v: f64| v > 0.5;
+
+    // Compares the incrementally inflated layer with a full inflation
+    let check = |map: &CellMap<TestLayers, f64>| {
+        let mut full = map.clone();
+        full.inflate(
+            TestLayers::Layer0,
+            TestLayers::Layer2,
+            is_obstacle,
+            &params,
+            &Backend::Cpu,
+        )
+        .unwrap();
+        assert_f64_iter_eq!(
+            map.iter()
+                .layer(TestLayers::Layer1)
+                .copied()
+                .collect::<Vec<_>>(),
+            full.iter()
+                .layer(TestLayers::Layer2)
+                .copied()
+                .collect::<Vec<_>>(),
+            1e-12
+        );
+    };
+
+    let mut map = obstacle_map();
+    let mut inflation = IncrementalInflation::new(
+        &mut map,
+        TestLayers::Layer0,
+        TestLayers::Layer1,
+        is_obstacle,
+        params,
+    );
+    check(&map);
+    assert_f64_eq!(inflation.distance(Point2::new(2, 4)).unwrap(), 0.2, 1e-12);
+    assert!(inflation.distance(Point2::new(6, 0)).unwrap().is_infinite());
+
+    // Adding and moving obstacles
+    map.set(TestLayers::Layer0, Point2::new(4, 4), 1.0).unwrap();
+    map.set(TestLayers::Layer0, Point2::new(2, 3), 0.0).unwrap();
+    map.set(TestLayers::Layer0, Point2::new(2, 2), 1.0).unwrap();
+    inflation
+        .notify_obstacle_changed(
+            &mut map,
+            &[Point2::new(4, 4), Point2::new(2, 3), Point2::new(2, 2)],
+        )
+        .unwrap();
+    check(&map);
+
+    // Removing every obstacle, with unchanged cells included
+    for &(x, y) in &[(4, 4), (2, 2), (9, 7), (10, 1)] {
+        map.set(TestLayers::Layer0, Point2::new(x, y), 0.0).unwrap();
+    }
+    inflation
+        .notify_obstacle_changed(
+            &mut map,
+            &[
+                Point2::new(4, 4),
+                Point2::new(2, 2),
+                Point2::new(9, 7),
+                Point2::new(10, 1),
+                Point2::new(0, 0),
+            ],
+        )
+        .unwrap();
+    check(&map);
+    assert!(map.iter().layer(TestLayers::Layer1).all(|&v| v == 0.0));
+
+    assert!(matches!(
+        inflation.notify_obstacle_changed(&mut map, &[Point2::new(12, 0)]),
+        Err(Error::IndexOutsideLayer(0, _, _))
+    ));
+}
+
+#[test]
+fn safe_cells() {
+    let mut map = obstacle_map();
+    map.set(TestLayers::Layer0, Point2::new(5, 5), f64::NAN)
+        .unwrap();
+
+    // Cells are 0.5 wide and 0.2 tall, so a clearance of 0.5 excludes one column but two rows
+    // either side of each obstacle
+    let safe = map.safe_cells(TestLayers::Layer0, 0.5, |v| v > 0.5);
+    assert!(!safe[[3, 2]]);
+    assert!(!safe[[3, 3]]);
+    assert!(safe[[3, 4]]);
+    assert!(!safe[[5, 2]]);
+    assert!(safe[[6, 2]]);
+    assert!(safe[[5, 5]]);
+
+    // Treating unknown cells as obstacles
+    let safe = map.safe_cells(TestLayers::Layer0, 0.5, |v| v.is_nan() || v > 0.5);
+    assert!(!safe[[5, 5]]);
+
+    assert!(map
+        .safe_cells(TestLayers::Layer2, 100.0, |v| v > 0.5)
+        .iter()
+        .all(|&s| s));
+}
+
+/// Check that the GPU backend gives the same results as the CPU backend. This test does nothing if
+/// no GPU is available.
+#[cfg(feature = "gpu")]
+#[test]
+fn gpu_backend() {
+    let gpu = match GpuBackend::new() {
+        Ok(gpu) => Backend::Gpu(gpu),
+        Err(e) => {
+            println!("Skipping GPU test: {}", e);
+            return;
+        }
+    };
+
+    let map = obstacle_map();
+    let data = map[TestLayers::Layer0].clone();
+    let obstacles = data.mapv(|v| v > 0.5);
+    let params = InflationParams {
+        inscribed_radius: 0.3,
+        inflation_radius: 1.0,
+        cost_scaling_factor: 2.0,
+    };
+    let kernel = SeparableKernel::gaussian(0.4, map.cell_size()).unwrap();
+    let cpu = Backend::Cpu;
+
+    let filtered = gpu.separable_filter(data.view(), &kernel).unwrap();
+    let expected = cpu.separable_filter(data.view(), &kernel).unwrap();
+    assert_f64_iter_eq!(filtered, expected, 1e-5);
+
+    let dist = gpu
+        .distance_transform(obstacles.view(), map.cell_size())
+        .unwrap();
+    let expected = cpu
+        .distance_transform(obstacles.view(), map.cell_size())
+        .unwrap();
+    assert_f64_iter_eq!(dist, expected, 1e-4);
+
+    let cost = gpu
+        .inflate(obstacles.view(), map.cell_size(), &params)
+        .unwrap();
+    let expected = cpu
+        .inflate(obstacles.view(), map.cell_size(), &params)
+        .unwrap();
+    assert_f64_iter_eq!(cost, expected, 1e-4);
+}
+
+#[test]
+fn morphology() {
+    let cell_size = Vector2::new(1.0, 1.0);
+    assert_eq!(StructuringElement::Disc(1.0).offsets(cell_size).len(), 5);
+    assert_eq!(
+        StructuringElement::Disc(0.1).offsets(cell_size),
+        vec![(0, 0)]
+    );
+    assert_eq!(
+        StructuringElement::Rectangle(Vector2::new(1.0, 0.5))
+            .offsets(Vector2::new(0.5, 0.5))
+            .len(),
+        15
+    );
+
+    // Discs on non-square cells span a different number of cells along each axis, including cells
+    // exactly at the radius
+    let offsets = StructuringElement::Disc(0.3).offsets(Vector2::new(0.1, 0.3));
+    assert_eq!(offsets.len(), 9);
+    assert!(offsets.contains(&(-3, 0)) && offsets.contains(&(0, 1)));
+    assert!(!offsets.contains(&(1, 1)));
+
+    let params = CellMapParams {
+        cell_bounds: Bounds::new((0, 7), (0, 7)).unwrap(),
+        ..Default::default()
+    };
+    let disc = StructuringElement::Disc(1.0);
+
+    // Growing a single obstacle by the disc, and shrinking it back again
+    let mut mask = CellMap::<TestLayers, bool>::new_from_elem(params, false);
+    mask[(TestLayers::Layer0, Point2::new(3, 3))] = true;
+    mask.dilate_mask(TestLayers::Layer0, TestLayers::Layer1, &disc);
+    assert_eq!(
+        mask.iter()
+            .layer(TestLayers::Layer1)
+            .filter(|&&v| v)
+            .count(),
+        5
+    );
+    assert!(mask[(TestLayers::Layer1, Point2::new(3, 4))]);
+    assert!(!mask[(TestLayers::Layer1, Point2::new(4, 4))]);
+    mask.erode_mask(TestLayers::Layer1, TestLayers::Layer2, &disc);
+    assert_eq!(
+        mask.iter()
+            .layer(TestLayers::Layer2)
+            .filter(|&&v| v)
+            .count(),
+        1
+    );
+
+    // Opening removes isolated noise, closing fills small holes
+    mask.open_mask(TestLayers::Layer0, TestLayers::Layer2, &disc);
+    assert!(mask.iter().layer(TestLayers::Layer2).all(|&v| !v));
+    let mut holes = CellMap::<TestLayers, bool>::new_from_elem(params, true);
+    holes[(TestLayers::Layer0, Point2::new(2, 5))] = false;
+    holes.close_mask(TestLayers::Layer0, TestLayers::Layer0, &disc);
+    assert!(holes.iter().layer(TestLayers::Layer0).all(|&v| v));
+
+    // Grayscale operations ignore NaN cells
+    let mut map = CellMap::<TestLayers, f64>::new_from_elem(params, 0.0);
+    map[(TestLayers::Layer0, Point2::new(3, 3))] = 2.0;
+    map[(TestLayers::Layer0, Point2::new(3, 4))] = f64::NAN;
+    map[(TestLayers::Layer0, Point2::new(0, 0))] = -1.0;
+    let square = StructuringElement::Rectangle(Vector2::new(1.0, 1.0));
+    map.dilate(TestLayers::Layer0, TestLayers::Layer1, &square);
+    assert_eq!(map[(TestLayers::Layer1, Point2::new(4, 4))], 2.0);
+    assert_eq!(map[(TestLayers::Layer1, Point2::new(5, 5))], 0.0);
+    assert!(map[(TestLayers::Layer1, Point2::new(3, 4))].is_nan());
+    map.erode(TestLayers::Layer0, TestLayers::Layer1, &square);
+    assert_eq!(map[(TestLayers::Layer1, Point2::new(1, 1))], -1.0);
+    assert_eq!(map[(TestLayers::Layer1, Point2::new(3, 3))], 0.0);
+    map.open(TestLayers::Layer0, TestLayers::Layer2, &square);
+    assert_eq!(map[(TestLayers::Layer2, Point2::new(3, 3))], 0.0);
+    map.close(TestLayers::Layer0, TestLayers::Layer2, &square);
+    assert_eq!(map[(TestLayers::Layer2, Point2::new(3, 3))], 2.0);
+}
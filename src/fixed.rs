@@ -0,0 +1,352 @@
+//! Provides [`FixedPointIndexer`], which converts between cell indexes and parent-frame positions
+//! given in whole millimetres using only integer arithmetic.
+//!
+//! This is intended for targets without fast floating-point hardware, and for safety cases where
+//! the indexing of a position must not depend on floating-point rounding. Integer arithmetic is
+//! exact, so a position on a cell boundary always belongs to the cell above the boundary, and the
+//! map's [`BoundaryRule`] isn't used.
+//!
+//! [`FixedPointIndexer::from_params()`] takes the map's parent-frame units to be metres, and its
+//! cell size and position must be whole numbers of millimetres. Rotated maps are supported by
+//! quantising the sine and cosine of the rotation once, when the indexer is built, after which
+//! indexing uses no floating-point operations at all. An unrotated indexer can be built without
+//! any floating-point operations using [`FixedPointIndexer::from_millimetres()`].
+//!
+//! [`BoundaryRule`]: crate::BoundaryRule
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+
+use crate::{
+    math, Bounds, CellMap, CellMapParams, CellOrigin, Error, GridConvention, Layer, YAxis,
+};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The number of fractional bits in the quantised sine and cosine of the map's rotation.
+const ROTATION_BITS: u32 = 30;
+
+/// The quantised value of `1.0`.
+const ROTATION_ONE: i128 = 1 << ROTATION_BITS;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Converts between cell indexes and parent-frame positions in whole millimetres using integer
+/// arithmetic, see the [module documentation](self).
+///
+/// The indexer is a snapshot of the map's parameters, so must be rebuilt if the map is moved or
+/// resized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedPointIndexer {
+    cell_bounds: Bounds,
+    cell_size_mm: Vector2<i64>,
+    position_mm: Vector2<i64>,
+    convention: GridConvention,
+
+    /// The cosine and sine of the map's rotation, scaled by [`ROTATION_ONE`].
+    cos: i64,
+    sin: i64,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl FixedPointIndexer {
+    /// Creates an indexer for an unrotated map with the given cell size and position in
+    /// millimetres, without using any floating-point operations.
+    ///
+    /// Returns [`Error::InvalidFixedPointParams`] if either component of the cell size isn't
+    /// positive.
+    pub fn from_millimetres(
+        cell_bounds: Bounds,
+        cell_size_mm: Vector2<i64>,
+        position_mm: Vector2<i64>,
+        convention: GridConvention,
+    ) -> Result<Self, Error> {
+        if cell_size_mm.x <= 0 || cell_size_mm.y <= 0 {
+            return Err(Error::InvalidFixedPointParams(format!(
+                "cell size {:?} mm isn't positive",
+                cell_size_mm
+            )));
+        }
+
+        Ok(Self {
+            cell_bounds,
+            cell_size_mm,
+            position_mm,
+            convention,
+            cos: ROTATION_ONE as i64,
+            sin: 0,
+        })
+    }
+
+    /// Creates an indexer for a map with the given parameters.
+    ///
+    /// Returns [`Error::InvalidFixedPointParams`] if the cell size or position aren't whole,
+    /// positive for the cell size, numbers of millimetres.
+    pub fn from_params(params: &CellMapParams) -> Result<Self, Error> {
+        let to_mm = |name: &str, metres: f64| {
+            let mm = (metres * 1000.0).round();
+            if (metres * 1000.0 - mm).abs() > 1e-6 || mm.abs() > i64::MAX as f64 / 2.0 {
+                Err(Error::InvalidFixedPointParams(format!(
+                    "{} ({}) isn't a whole number of millimetres",
+                    name, metres
+                )))
+            } else {
+                Ok(mm as i64)
+            }
+        };
+
+        let mut indexer = Self::from_millimetres(
+            params.cell_bounds,
+            Vector2::new(
+                to_mm("cell_size.x", params.cell_size.x)?,
+                to_mm("cell_size.y", params.cell_size.y)?,
+            ),
+            Vector2::new(
+                to_mm("position_in_parent.x", params.position_in_parent.x)?,
+                to_mm("position_in_parent.y", params.position_in_parent.y)?,
+            ),
+            params.convention,
+        )?;
+
+        if params.rotation_in_parent_rad != 0.0 {
+            let (sin, cos) = math::sin_cos(params.rotation_in_parent_rad);
+            indexer.cos = (cos * ROTATION_ONE as f64).round() as i64;
+            indexer.sin = (sin * ROTATION_ONE as f64).round() as i64;
+        }
+
+        Ok(indexer)
+    }
+
+    /// Returns the bounds of the map.
+    pub fn cell_bounds(&self) -> Bounds {
+        self.cell_bounds
+    }
+
+    /// Returns the size of each cell in millimetres.
+    pub fn cell_size_mm(&self) -> Vector2<i64> {
+        self.cell_size_mm
+    }
+
+    /// Returns the position of the map in the parent frame in millimetres.
+    pub fn position_mm(&self) -> Vector2<i64> {
+        self.position_mm
+    }
+
+    /// Returns the map-frame cell containing the parent-frame `position_mm`, which may be outside
+    /// the map.
+    pub fn get_cell(&self, position_mm: Point2<i64>) -> Point2<isize> {
+        let dx = (position_mm.x - self.position_mm.x) as i128;
+        let dy = (position_mm.y - self.position_mm.y) as i128;
+        let (cos, sin) = (self.cos as i128, self.sin as i128);
+
+        // Rotate into the map's axes, keeping the scale of the quantised rotation
+        let local_x = cos * dx + sin * dy;
+        let mut local_y = cos * dy - sin * dx;
+        if self.convention.y_axis == YAxis::Down {
+            local_y = -local_y;
+        }
+
+        // Divide by the cell size, working in half cells so that a centre origin can be offset by
+        // half a cell without leaving the integers
+        let centre = self.convention.origin == CellOrigin::Centre;
+        let to_cell = |local: i128, cell_size: i64| {
+            let cell_size = cell_size as i128 * ROTATION_ONE;
+            let offset = if centre { cell_size } else { 0 };
+            (2 * local + offset).div_euclid(2 * cell_size) as isize
+        };
+
+        Point2::new(
+            to_cell(local_x, self.cell_size_mm.x),
+            to_cell(local_y, self.cell_size_mm.y),
+        )
+    }
+
+    /// Returns the index of the cell containing the parent-frame `position_mm`, or `None` if the
+    /// position is outside the map.
+    pub fn index(&self, position_mm: Point2<i64>) -> Option<Point2<usize>> {
+        self.cell_bounds.get_index(self.get_cell(position_mm))
+    }
+
+    /// Returns the parent-frame position of the centre of the cell at `index`, rounded to the
+    /// nearest millimetre, or `None` if the index is outside the map.
+    pub fn position(&self, index: Point2<usize>) -> Option<Point2<i64>> {
+        let num_cells = self.cell_bounds.get_num_cells();
+        if index.x >= num_cells.x || index.y >= num_cells.y {
+            return None;
+        }
+
+        // Twice the offset of the cell's centre from the map's position, along the map's axes
+        let corner = if self.convention.origin == CellOrigin::Centre {
+            0
+        } else {
+            1
+        };
+        let local_x = (2 * (index.x as isize + self.cell_bounds.x.0) + corner) as i128
+            * self.cell_size_mm.x as i128;
+        let mut local_y = (2 * (index.y as isize + self.cell_bounds.y.0) + corner) as i128
+            * self.cell_size_mm.y as i128;
+        if self.convention.y_axis == YAxis::Down {
+            local_y = -local_y;
+        }
+
+        // Rotate into the parent's axes, then remove the factor of two and the scale of the
+        // quantised rotation, rounding to the nearest millimetre
+        let (cos, sin) = (self.cos as i128, self.sin as i128);
+        let scale = 2 * ROTATION_ONE;
+        let round = |v: i128| ((v + scale / 2).div_euclid(scale)) as i64;
+
+        Some(Point2::new(
+            self.position_mm.x + round(cos * local_x - sin * local_y),
+            self.position_mm.y + round(sin * local_x + cos * local_y),
+        ))
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+{
+    /// Returns a [`FixedPointIndexer`] for this map, which converts between indexes and
+    /// millimetre positions using integer arithmetic.
+    ///
+    /// Returns [`Error::InvalidFixedPointParams`] if the map's cell size or position aren't whole
+    /// numbers of millimetres.
+    pub fn fixed_point_indexer(&self) -> Result<FixedPointIndexer, Error> {
+        FixedPointIndexer::from_params(&self.params())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestLayers;
+
+    fn new_map(params: CellMapParams) -> CellMap<TestLayers, f64> {
+        CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-3, 5), (-2, 4)).unwrap(),
+                cell_size: Vector2::new(0.05, 0.1),
+                position_in_parent: Vector2::new(1.2, -0.4),
+                ..params
+            },
+            0.0,
+        )
+    }
+
+    #[test]
+    fn matches_float_indexing() {
+        let conventions = [
+            GridConvention::default(),
+            GridConvention {
+                y_axis: YAxis::Down,
+                origin: CellOrigin::Centre,
+            },
+        ];
+
+        for &convention in conventions.iter() {
+            for &rotation_in_parent_rad in [0.0, 0.3, -2.0].iter() {
+                let map = new_map(CellMapParams {
+                    convention,
+                    rotation_in_parent_rad,
+                    ..Default::default()
+                });
+                let indexer = map.fixed_point_indexer().unwrap();
+
+                // Every cell centre maps back to its own index
+                for index in map.iter().indexed().map(|((_, i), _)| i) {
+                    let position = indexer.position(index).unwrap();
+                    let expected = map.position(index).unwrap() * 1000.0;
+                    assert!((position.x as f64 - expected.x).abs() <= 0.5);
+                    assert!((position.y as f64 - expected.y).abs() <= 0.5);
+                    assert_eq!(indexer.index(position), Some(index));
+                }
+
+                // Positions clear of the cell boundaries agree with the floating-point index
+                for x in (600..=1800).step_by(37) {
+                    for y in (-1000..=200).step_by(41) {
+                        let position = Point2::new(x, y);
+                        let float = map.index(Point2::new(x as f64, y as f64) / 1000.0);
+                        let fixed = indexer.index(position);
+                        if float != fixed {
+                            // Only allowed within a rounding error of a boundary
+                            let cell = indexer.get_cell(position);
+                            let nudged =
+                                [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|(dx, dy)| {
+                                    indexer.get_cell(position + Vector2::new(*dx, *dy)) != cell
+                                });
+                            assert!(nudged, "{:?} {:?} != {:?}", position, fixed, float);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn exact_boundaries() {
+        let indexer = FixedPointIndexer::from_millimetres(
+            Bounds::new((0, 10), (0, 10)).unwrap(),
+            Vector2::new(100, 100),
+            Vector2::new(0, 0),
+            GridConvention::default(),
+        )
+        .unwrap();
+
+        // Boundaries belong to the cell above them, and the last boundary is outside the map
+        assert_eq!(indexer.index(Point2::new(700, 0)), Some(Point2::new(7, 0)));
+        assert_eq!(
+            indexer.index(Point2::new(699, 999)),
+            Some(Point2::new(6, 9))
+        );
+        assert_eq!(indexer.index(Point2::new(1000, 0)), None);
+        assert_eq!(indexer.index(Point2::new(-1, 0)), None);
+        assert_eq!(indexer.get_cell(Point2::new(-1, -100)), Point2::new(-1, -1));
+        assert_eq!(
+            indexer.position(Point2::new(7, 0)),
+            Some(Point2::new(750, 50))
+        );
+        assert_eq!(indexer.position(Point2::new(10, 0)), None);
+    }
+
+    #[test]
+    fn invalid_params() {
+        assert!(matches!(
+            FixedPointIndexer::from_params(&CellMapParams {
+                cell_size: Vector2::new(0.0505, 0.1),
+                ..Default::default()
+            }),
+            Err(Error::InvalidFixedPointParams(_))
+        ));
+        assert!(matches!(
+            FixedPointIndexer::from_params(&CellMapParams {
+                position_in_parent: Vector2::new(0.0, 1e-4),
+                ..Default::default()
+            }),
+            Err(Error::InvalidFixedPointParams(_))
+        ));
+
+        assert!(matches!(
+            FixedPointIndexer::from_millimetres(
+                Bounds::new((0, 1), (0, 1)).unwrap(),
+                Vector2::new(0, 10),
+                Vector2::new(0, 0),
+                GridConvention::default(),
+            ),
+            Err(Error::InvalidFixedPointParams(_))
+        ));
+    }
+}
@@ -0,0 +1,473 @@
+//! Provides generators which write synthetic terrain and obstacles into layers, for simulation and
+//! benchmarking without hand-crafted fixtures.
+//!
+//! Generators are defined in the map's parent frame, so the same generator gives the same terrain
+//! for maps with different cell sizes or positions. Terrain generators add to the existing values
+//! of the layer, so they can be combined, for example a ramp with fractal noise on top:
+//!
+//! ```rust
+//! # use cell_map::{CellMap, CellMapParams, Layer, Bounds};
+//! use cell_map::generators::{FractalNoiseParams, Pattern};
+//! use nalgebra::{Point2, Vector2};
+//! use rand::{rngs::StdRng, SeedableRng};
+//!
+//! # #[derive(Layer, Clone, Debug)]
+//! # enum MyLayer {
+//! #     Height,
+//! # }
+//! let mut map = CellMap::<MyLayer, f64>::new_from_elem(
+//!     CellMapParams {
+//!         cell_size: Vector2::new(0.1, 0.1),
+//!         cell_bounds: Bounds::new((0, 100), (0, 100)).unwrap(),
+//!         ..Default::default()
+//!     },
+//!     0.0,
+//! );
+//! let mut rng = StdRng::seed_from_u64(42);
+//!
+//! map.generate_pattern(
+//!     MyLayer::Height,
+//!     &Pattern::Ramp {
+//!         origin: Point2::new(2.0, 0.0),
+//!         direction: Vector2::new(1.0, 0.0),
+//!         gradient: 0.2,
+//!         length: 5.0,
+//!     },
+//! );
+//! map.generate_fractal_noise(MyLayer::Height, &FractalNoiseParams::default(), &mut rng);
+//! ```
+//!
+//! This module requires the `random` feature.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// Deterministic terrain patterns, which can be written into a layer with
+/// [`CellMap::generate_pattern()`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Pattern {
+    /// A step of the given height, whose edge passes through `origin` perpendicular to
+    /// `direction`. Cells in front of the edge, along `direction`, are raised by `height`.
+    Step {
+        /// A point on the edge of the step.
+        origin: Point2<f64>,
+        /// The direction the step rises in.
+        direction: Vector2<f64>,
+        /// The height of the step.
+        height: f64,
+    },
+
+    /// A ramp which starts at an edge through `origin` perpendicular to `direction`, and rises
+    /// along `direction` with the given gradient for `length`, after which it stays level.
+    Ramp {
+        /// A point on the bottom edge of the ramp.
+        origin: Point2<f64>,
+        /// The direction the ramp rises in.
+        direction: Vector2<f64>,
+        /// The rise of the ramp per unit distance.
+        gradient: f64,
+        /// The length of the ramp along `direction`.
+        length: f64,
+    },
+}
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Parameters for fractal Perlin noise terrain, see [`CellMap::generate_fractal_noise()`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FractalNoiseParams {
+    /// The wavelength of the first octave, in parent-frame units.
+    ///
+    /// # Default
+    ///
+    /// The default value is `5.0`.
+    pub wavelength: f64,
+
+    /// The maximum absolute value of the noise.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.5`.
+    pub amplitude: f64,
+
+    /// The number of octaves of noise to sum.
+    ///
+    /// # Default
+    ///
+    /// The default value is `4`.
+    pub octaves: usize,
+
+    /// The factor the amplitude is multiplied by for each octave.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.5`.
+    pub persistence: f64,
+
+    /// The factor the frequency is multiplied by for each octave.
+    ///
+    /// # Default
+    ///
+    /// The default value is `2.0`.
+    pub lacunarity: f64,
+}
+
+/// Parameters for a random field of circular obstacles, see [`CellMap::generate_obstacles()`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ObstacleFieldParams<T> {
+    /// The number of obstacles.
+    pub count: usize,
+
+    /// The minimum obstacle radius, in parent-frame units.
+    pub min_radius: f64,
+
+    /// The maximum obstacle radius, in parent-frame units.
+    pub max_radius: f64,
+
+    /// The value written into cells covered by an obstacle.
+    pub value: T,
+}
+
+/// Improved Perlin gradient noise.
+struct Perlin {
+    perm: [u8; 512],
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Adds fractal Perlin noise to `layer`, sampled at the centre of each cell.
+    ///
+    /// The noise is the sum of `octaves` layers of Perlin noise, each with a higher frequency and
+    /// lower amplitude than the last, normalised so that its magnitude never exceeds
+    /// [`FractalNoiseParams::amplitude`].
+    pub fn generate_fractal_noise<R>(&mut self, layer: L, params: &FractalNoiseParams, rng: &mut R)
+    where
+        R: Rng + ?Sized,
+    {
+        let perlin = Perlin::new(rng);
+
+        let octaves: Vec<(f64, f64)> = (0..params.octaves.max(1))
+            .map(|o| {
+                (
+                    params.lacunarity.powi(o as i32) / params.wavelength,
+                    params.persistence.powi(o as i32),
+                )
+            })
+            .collect();
+        let total: f64 = octaves.iter().map(|(_, a)| a).sum();
+
+        self.generate_with(layer, |p| {
+            let noise: f64 = octaves
+                .iter()
+                .map(|&(frequency, amplitude)| {
+                    amplitude * perlin.noise(p.x * frequency, p.y * frequency)
+                })
+                .sum();
+            params.amplitude * noise / total
+        });
+    }
+
+    /// Adds the given [`Pattern`] to `layer`.
+    pub fn generate_pattern(&mut self, layer: L, pattern: &Pattern) {
+        match *pattern {
+            Pattern::Step {
+                origin,
+                direction,
+                height,
+            } => {
+                let dir = direction.normalize();
+                self.generate_with(layer, |p| {
+                    if (p - origin).dot(&dir) >= 0.0 {
+                        height
+                    } else {
+                        0.0
+                    }
+                });
+            }
+            Pattern::Ramp {
+                origin,
+                direction,
+                gradient,
+                length,
+            } => {
+                let dir = direction.normalize();
+                self.generate_with(layer, |p| {
+                    (p - origin).dot(&dir).clamp(0.0, length.max(0.0)) * gradient
+                });
+            }
+        }
+    }
+
+    /// Adds `f(position)` to each cell of `layer`, where `position` is the centre of the cell in
+    /// the parent frame.
+    fn generate_with<F>(&mut self, layer: L, f: F)
+    where
+        F: Fn(Point2<f64>) -> f64,
+    {
+        let metadata = self.metadata;
+        for ((y, x), value) in self[layer].indexed_iter_mut() {
+            *value += f(metadata.position_unchecked(Point2::new(x, y)));
+        }
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    /// Writes a field of randomly placed circular obstacles into `layer`.
+    ///
+    /// The centre of each obstacle is uniformly distributed over the map, and its radius is
+    /// uniformly distributed between [`ObstacleFieldParams::min_radius`] and
+    /// [`ObstacleFieldParams::max_radius`]. Each cell whose centre is inside an obstacle is set to
+    /// [`ObstacleFieldParams::value`], other cells are unchanged.
+    pub fn generate_obstacles<R>(&mut self, layer: L, params: &ObstacleFieldParams<T>, rng: &mut R)
+    where
+        R: Rng + ?Sized,
+    {
+        let num_cells = self.num_cells();
+        if num_cells.x == 0 || num_cells.y == 0 {
+            return;
+        }
+
+        // Pick centres in index space so they're uniform over the map whatever its rotation
+        let obstacles: Vec<(Point2<f64>, f64)> = (0..params.count)
+            .map(|_| {
+                let index = Point2::new(
+                    rng.gen_range(0.0..num_cells.x as f64) - 0.5,
+                    rng.gen_range(0.0..num_cells.y as f64) - 0.5,
+                );
+                let centre = self.metadata.to_parent.transform_point(
+                    &(index
+                        + Vector2::new(
+                            self.metadata.cell_bounds.x.0 as f64 + 0.5,
+                            self.metadata.cell_bounds.y.0 as f64 + 0.5,
+                        )),
+                );
+                let radius = if params.max_radius > params.min_radius {
+                    rng.gen_range(params.min_radius..params.max_radius)
+                } else {
+                    params.min_radius
+                };
+                (centre, radius)
+            })
+            .collect();
+
+        let metadata = self.metadata;
+        for ((y, x), value) in self[layer].indexed_iter_mut() {
+            let position = metadata.position_unchecked(Point2::new(x, y));
+            if obstacles
+                .iter()
+                .any(|(centre, radius)| (position - centre).norm() <= *radius)
+            {
+                *value = params.value.clone();
+            }
+        }
+    }
+}
+
+impl Default for FractalNoiseParams {
+    fn default() -> Self {
+        Self {
+            wavelength: 5.0,
+            amplitude: 0.5,
+            octaves: 4,
+            persistence: 0.5,
+            lacunarity: 2.0,
+        }
+    }
+}
+
+impl Perlin {
+    /// Creates a new noise function with a random permutation table.
+    fn new<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        let mut table: Vec<u8> = (0..=255).collect();
+        table.shuffle(rng);
+
+        let mut perm = [0; 512];
+        for (i, p) in perm.iter_mut().enumerate() {
+            *p = table[i % 256];
+        }
+
+        Self { perm }
+    }
+
+    /// Samples the noise at the given point, returning a value in `[-1, 1]`.
+    fn noise(&self, x: f64, y: f64) -> f64 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (xi, yi) = ((x0 as i64 & 255) as usize, (y0 as i64 & 255) as usize);
+        let (xf, yf) = (x - x0, y - y0);
+        let (u, v) = (fade(xf), fade(yf));
+
+        let hash = |dx: usize, dy: usize| self.perm[self.perm[xi + dx] as usize + yi + dy];
+
+        let n00 = grad(hash(0, 0), xf, yf);
+        let n10 = grad(hash(1, 0), xf - 1.0, yf);
+        let n01 = grad(hash(0, 1), xf, yf - 1.0);
+        let n11 = grad(hash(1, 1), xf - 1.0, yf - 1.0);
+
+        let nx0 = n00 + u * (n10 - n00);
+        let nx1 = n01 + u * (n11 - n01);
+
+        // Each corner's contribution is at most 1, reached by a diagonal gradient pointing at the
+        // centre of the cell, and the interpolation weights sum to 1, so the noise lies within
+        // [-1, 1] without scaling
+        nx0 + v * (nx1 - nx0)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Perlin's quintic fade curve.
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+/// Dot product of the offset with one of eight gradients selected by `hash`.
+fn grad(hash: u8, x: f64, y: f64) -> f64 {
+    match hash & 7 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    fn new_map() -> CellMap<TestLayers, f64> {
+        CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-20, 20), (-20, 20)).unwrap(),
+                cell_size: Vector2::new(0.25, 0.25),
+                ..Default::default()
+            },
+            0.0,
+        )
+    }
+
+    #[test]
+    fn fractal_noise() {
+        let params = FractalNoiseParams::default();
+
+        let mut map = new_map();
+        map.generate_fractal_noise(TestLayers::Layer0, &params, &mut StdRng::seed_from_u64(1));
+        map.generate_fractal_noise(TestLayers::Layer1, &params, &mut StdRng::seed_from_u64(1));
+        map.generate_fractal_noise(TestLayers::Layer2, &params, &mut StdRng::seed_from_u64(2));
+
+        let layer0: Vec<f64> = map.iter().layer(TestLayers::Layer0).cloned().collect();
+        let layer1: Vec<f64> = map.iter().layer(TestLayers::Layer1).cloned().collect();
+        let layer2: Vec<f64> = map.iter().layer(TestLayers::Layer2).cloned().collect();
+
+        // Seeded noise is repeatable, bounded, and not flat
+        assert_eq!(layer0, layer1);
+        assert_ne!(layer0, layer2);
+        assert!(layer0.iter().all(|v| v.abs() <= params.amplitude));
+        assert!(layer0.iter().any(|v| v.abs() > 0.1 * params.amplitude));
+    }
+
+    #[test]
+    fn perlin_range() {
+        let perlin = Perlin::new(&mut StdRng::seed_from_u64(3));
+
+        // The noise is bounded by 1 without clamping, and uses most of that range
+        let samples: Vec<f64> = (0..200)
+            .flat_map(|i| (0..200).map(move |j| (i as f64 * 0.173, j as f64 * 0.131)))
+            .map(|(x, y)| perlin.noise(x, y))
+            .collect();
+        assert!(samples.iter().all(|v| v.abs() <= 1.0));
+        assert!(samples.iter().any(|v| v.abs() > 0.7));
+    }
+
+    #[test]
+    fn patterns() {
+        let mut map = new_map();
+
+        map.generate_pattern(
+            TestLayers::Layer0,
+            &Pattern::Step {
+                origin: Point2::new(1.0, 0.0),
+                direction: Vector2::new(2.0, 0.0),
+                height: 0.3,
+            },
+        );
+        map.generate_pattern(
+            TestLayers::Layer0,
+            &Pattern::Ramp {
+                origin: Point2::new(0.0, -2.0),
+                direction: Vector2::new(0.0, 1.0),
+                gradient: 0.5,
+                length: 2.0,
+            },
+        );
+
+        for ((_, index), &value) in map.iter().layer(TestLayers::Layer0).indexed() {
+            let p = map.position(index).unwrap();
+            let step = if p.x >= 1.0 { 0.3 } else { 0.0 };
+            let ramp = (p.y + 2.0).clamp(0.0, 2.0) * 0.5;
+            assert!((value - step - ramp).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn obstacles() {
+        let mut map = new_map();
+        let params = ObstacleFieldParams {
+            count: 5,
+            min_radius: 0.5,
+            max_radius: 1.0,
+            value: 1.0,
+        };
+
+        map.generate_obstacles(TestLayers::Layer0, &params, &mut StdRng::seed_from_u64(3));
+
+        // Obstacles cover between 1 and 5 discs worth of cells
+        let covered = map
+            .iter()
+            .layer(TestLayers::Layer0)
+            .filter(|&&v| v == 1.0)
+            .count() as f64;
+        let cell_area = 0.25 * 0.25;
+        assert!(covered > 0.5 * std::f64::consts::PI * 0.25 / cell_area);
+        assert!(covered <= 5.0 * std::f64::consts::PI * 1.1 / cell_area);
+        assert!(map
+            .iter()
+            .layer(TestLayers::Layer0)
+            .all(|&v| v == 0.0 || v == 1.0));
+    }
+}
@@ -0,0 +1,229 @@
+//! Provides the [`LayerGraph`] type, which presents a layer of a map as a graph of neighbouring
+//! cells without copying it, so that third-party search algorithms can run directly on the map.
+//!
+//! Graph search crates such as `pathfinding` take the graph as a closure returning the successors
+//! of each node, rather than through a trait, so [`LayerGraph::successors()`] and
+//! [`LayerGraph::neighbours()`] can be passed to them directly. Nodes are cell indices, which are
+//! `Clone + Eq + Hash` as these crates require:
+//!
+//! ```
+//! use cell_map::{graph::Connectivity, Bounds, CellMap, CellMapParams, Layer};
+//! use nalgebra::Point2;
+//!
+//! #[derive(Layer, Clone, Debug)]
+//! enum MyLayer {
+//!     Cost,
+//! }
+//!
+//! let map = CellMap::<MyLayer, u32>::new_from_elem(
+//!     CellMapParams {
+//!         cell_bounds: Bounds::new((0, 5), (0, 5)).unwrap(),
+//!         ..Default::default()
+//!     },
+//!     1,
+//! );
+//!
+//! // Moving into a cell costs that cell's value
+//! let graph = map.layer_graph(MyLayer::Cost, Connectivity::Four, |_, &to, _| Some(to));
+//!
+//! // With pathfinding this would be `dijkstra(&start, |n| graph.successors(n), |n| *n == goal)`
+//! let successors = graph.successors(&Point2::new(0, 0));
+//! assert_eq!(successors, vec![(Point2::new(1, 0), 1), (Point2::new(0, 1), 1)]);
+//! ```
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+use ndarray::ArrayView2;
+use serde::{Deserialize, Serialize};
+
+use crate::{math, CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A borrowed layer of a map viewed as a graph, see [`CellMap::layer_graph()`].
+#[derive(Debug, Clone)]
+pub struct LayerGraph<'m, T, F> {
+    layer: ArrayView2<'m, T>,
+    cell_size: Vector2<f64>,
+    connectivity: Connectivity,
+    cost: F,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// Which cells are connected to each other in a [`LayerGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Connectivity {
+    /// Cells are connected to the four cells they share an edge with.
+    Four,
+
+    /// Cells are connected to the eight cells they share an edge or a corner with.
+    Eight,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Connectivity {
+    /// Returns the `(x, y)` offsets of the connected cells.
+    pub fn offsets(&self) -> &'static [(isize, isize)] {
+        match self {
+            Self::Four => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+            Self::Eight => &[
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ],
+        }
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+{
+    /// Returns a graph over the cells of `layer`, in which moving between connected cells costs
+    /// the value returned by `cost`.
+    ///
+    /// `cost` is given the value of the cell being left, the value of the cell being entered, and
+    /// the parent-frame distance between their centres, and returns `None` if the move isn't
+    /// allowed, for example because the cell being entered is an obstacle.
+    pub fn layer_graph<C, F>(
+        &self,
+        layer: L,
+        connectivity: Connectivity,
+        cost: F,
+    ) -> LayerGraph<'_, T, F>
+    where
+        F: Fn(&T, &T, f64) -> Option<C>,
+    {
+        LayerGraph {
+            layer: self[layer].view(),
+            cell_size: self.cell_size(),
+            connectivity,
+            cost,
+        }
+    }
+}
+
+impl<T, F> LayerGraph<'_, T, F> {
+    /// Returns the number of nodes, i.e. cells, in the graph.
+    pub fn num_nodes(&self) -> usize {
+        self.layer.len()
+    }
+
+    /// Returns the cells connected to `index` which can be moved into, along with the cost of
+    /// moving into each, in the order given by [`Connectivity::offsets()`].
+    ///
+    /// Returns no successors if `index` is outside the map.
+    pub fn successors<C>(&self, index: &Point2<usize>) -> Vec<(Point2<usize>, C)>
+    where
+        F: Fn(&T, &T, f64) -> Option<C>,
+    {
+        let from = match self.layer.get((index.y, index.x)) {
+            Some(v) => v,
+            None => return Vec::new(),
+        };
+
+        self.connectivity
+            .offsets()
+            .iter()
+            .filter_map(|&(dx, dy)| {
+                let next = Point2::new(
+                    index.x.checked_add_signed(dx)?,
+                    index.y.checked_add_signed(dy)?,
+                );
+                let to = self.layer.get((next.y, next.x))?;
+                let distance =
+                    math::hypot(dx as f64 * self.cell_size.x, dy as f64 * self.cell_size.y);
+
+                Some((next, (self.cost)(from, to, distance)?))
+            })
+            .collect()
+    }
+
+    /// Returns the cells connected to `index` which can be moved into, ignoring the cost of the
+    /// move, for unweighted searches such as breadth-first search.
+    pub fn neighbours<C>(&self, index: &Point2<usize>) -> Vec<Point2<usize>>
+    where
+        F: Fn(&T, &T, f64) -> Option<C>,
+    {
+        self.successors(index)
+            .into_iter()
+            .map(|(next, _)| next)
+            .collect()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashSet, VecDeque};
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn layer_graph() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 4), (0, 3)).unwrap(),
+                cell_size: Vector2::new(1.0, 2.0),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // A wall along x = 2 with a gap at the top
+        map[(TestLayers::Layer0, Point2::new(2, 0))] = 1.0;
+        map[(TestLayers::Layer0, Point2::new(2, 1))] = 1.0;
+
+        let graph = map.layer_graph(TestLayers::Layer0, Connectivity::Eight, |_, &to, d| {
+            if to < 0.5 {
+                Some(d)
+            } else {
+                None
+            }
+        });
+        assert_eq!(graph.num_nodes(), 12);
+        assert!(graph.successors(&Point2::new(4, 0)).is_empty());
+
+        let successors = graph.successors(&Point2::new(1, 1));
+        assert_eq!(successors.len(), 6);
+        assert!(successors.contains(&(Point2::new(1, 0), 2.0)));
+        assert!(successors.contains(&(Point2::new(2, 2), 5f64.sqrt())));
+
+        // A breadth-first search driven only by the neighbours function, as an external search
+        // would be
+        let start = Point2::new(0, 0);
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        visited.insert(start);
+        queue.push_back(start);
+        while let Some(node) = queue.pop_front() {
+            for next in graph.neighbours(&node) {
+                if visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        assert_eq!(visited.len(), 10);
+        assert!(visited.contains(&Point2::new(3, 0)));
+    }
+}
@@ -0,0 +1,238 @@
+//! Provides the [`AutoGrow`] type, which wraps a [`CellMap`] so that writes to positions outside
+//! the map grow it instead of being ignored or returning an error.
+//!
+//! This is intended for exploration, where the final extent of the map isn't known when it's
+//! created. The map grows in whole chunks of cells rather than just far enough to fit each new
+//! cell, so that a robot moving steadily outwards only causes an occasional reallocation.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+
+use crate::{cell_map::Bounds, CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A [`CellMap`] which grows to fit writes outside its bounds.
+///
+/// New cells are filled with `T::default()`.
+#[derive(Debug, Clone)]
+pub struct AutoGrow<L, T>
+where
+    L: Layer,
+{
+    map: CellMap<L, T>,
+    chunk_size: usize,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> AutoGrow<L, T>
+where
+    L: Layer,
+    T: Clone + Default,
+{
+    /// Wraps the given map, which will grow by multiples of `chunk_size` cells along each edge
+    /// that needs to move. A `chunk_size` of `0` is treated as `1`.
+    pub fn new(map: CellMap<L, T>, chunk_size: usize) -> Self {
+        Self {
+            map,
+            chunk_size: chunk_size.max(1),
+        }
+    }
+
+    /// Returns the number of cells the map grows by at a time.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Returns a reference to the wrapped map.
+    pub fn map(&self) -> &CellMap<L, T> {
+        &self.map
+    }
+
+    /// Returns a mutable reference to the wrapped map, which doesn't grow on writes.
+    pub fn map_mut(&mut self) -> &mut CellMap<L, T> {
+        &mut self.map
+    }
+
+    /// Consumes the wrapper, returning the map.
+    pub fn into_map(self) -> CellMap<L, T> {
+        self.map
+    }
+
+    /// Returns a reference to the value at the given layer and parent-frame position, or `None`
+    /// if the position is outside the map. Reading never grows the map.
+    pub fn get(&self, layer: L, position: Point2<f64>) -> Option<&T> {
+        self.map.get(layer, self.map.index(position)?)
+    }
+
+    /// Sets the value at the given layer and parent-frame position, growing the map first if the
+    /// position is outside it. Returns the index of the cell which was set, which is only valid
+    /// until the map next grows.
+    pub fn set(&mut self, layer: L, position: Point2<f64>, value: T) -> Point2<usize> {
+        let index = self.grow_to(position);
+        self.map[(layer, index)] = value;
+        index
+    }
+
+    /// Sets the value of the cell containing each of the given parent-frame positions, growing
+    /// the map once to fit all of them. If more than one point falls in the same cell the last
+    /// one is used.
+    pub fn insert_points(&mut self, layer: L, points: Vec<(Point2<f64>, T)>) {
+        let cells: Vec<_> = points
+            .iter()
+            .map(|(p, _)| self.map.metadata.get_cell(*p))
+            .collect();
+        self.grow_to_cells(&cells);
+
+        let min = self.map.cell_bounds().as_corners().0;
+        for (cell, (_, value)) in cells.into_iter().zip(points) {
+            let index = Point2::from((cell - min).map(|v| v as usize));
+            self.map[(layer.clone(), index)] = value;
+        }
+    }
+
+    /// Grows the map, if needed, so that it contains the given parent-frame position, returning
+    /// the index of the cell containing the position.
+    pub fn grow_to(&mut self, position: Point2<f64>) -> Point2<usize> {
+        let cell = self.map.metadata.get_cell(position);
+        self.grow_to_cells(&[cell]);
+
+        let min = self.map.cell_bounds().as_corners().0;
+        Point2::from((cell - min).map(|v| v as usize))
+    }
+
+    /// Grows the map, if needed, so that it contains all of the given map-frame cells.
+    fn grow_to_cells(&mut self, cells: &[Point2<isize>]) {
+        let old = self.map.cell_bounds();
+        let chunk = self.chunk_size as isize;
+
+        // Round the distance each edge has to move up to a whole number of chunks
+        let grow = |needed: isize| {
+            if needed > 0 {
+                (needed + chunk - 1) / chunk * chunk
+            } else {
+                0
+            }
+        };
+        let (min, max) = cells.iter().fold(old.as_corners(), |(min, max), c| {
+            (min.inf(c), max.sup(&(c + Vector2::new(1, 1))))
+        });
+        let new = Bounds {
+            x: (
+                old.x.0 - grow(old.x.0 - min.x),
+                old.x.1 + grow(max.x - old.x.1),
+            ),
+            y: (
+                old.y.0 - grow(old.y.0 - min.y),
+                old.y.1 + grow(max.y - old.y.1),
+            ),
+        };
+
+        if new != old {
+            self.map.resize(new);
+        }
+    }
+}
+
+impl<L, T> From<AutoGrow<L, T>> for CellMap<L, T>
+where
+    L: Layer,
+    T: Clone + Default,
+{
+    fn from(grow: AutoGrow<L, T>) -> Self {
+        grow.into_map()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn auto_grow() {
+        let map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 4), (0, 4)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            1.0,
+        );
+        let mut map = AutoGrow::new(map, 8);
+
+        // Writes inside the map don't grow it
+        assert_eq!(
+            map.set(TestLayers::Layer0, Point2::new(0.25, 0.25), 2.0),
+            Point2::new(0, 0)
+        );
+        assert_eq!(
+            map.map().cell_bounds(),
+            Bounds::new((0, 4), (0, 4)).unwrap()
+        );
+
+        // Writes outside grow only the edges which need to move, by whole chunks
+        let index = map.set(TestLayers::Layer0, Point2::new(2.25, -0.25), 3.0);
+        assert_eq!(
+            map.map().cell_bounds(),
+            Bounds::new((0, 12), (-8, 4)).unwrap()
+        );
+        assert_eq!(index, Point2::new(4, 7));
+        assert_eq!(
+            map.get(TestLayers::Layer0, Point2::new(2.25, -0.25)),
+            Some(&3.0)
+        );
+
+        // Existing cells keep their values and positions, and new cells are the default
+        assert_eq!(
+            map.get(TestLayers::Layer0, Point2::new(0.25, 0.25)),
+            Some(&2.0)
+        );
+        assert_eq!(
+            map.get(TestLayers::Layer1, Point2::new(1.75, 1.75)),
+            Some(&1.0)
+        );
+        assert_eq!(
+            map.get(TestLayers::Layer1, Point2::new(5.75, -3.75)),
+            Some(&0.0)
+        );
+        assert_eq!(map.get(TestLayers::Layer1, Point2::new(-0.25, 0.0)), None);
+
+        // Points grow the map once to fit all of them
+        map.insert_points(
+            TestLayers::Layer2,
+            vec![
+                (Point2::new(-0.25, 0.25), 4.0),
+                (Point2::new(6.25, 2.25), 5.0),
+                (Point2::new(6.3, 2.3), 6.0),
+            ],
+        );
+        assert_eq!(
+            map.map().cell_bounds(),
+            Bounds::new((-8, 20), (-8, 12)).unwrap()
+        );
+        assert_eq!(
+            map.get(TestLayers::Layer2, Point2::new(-0.25, 0.25)),
+            Some(&4.0)
+        );
+        assert_eq!(
+            map.get(TestLayers::Layer2, Point2::new(6.25, 2.25)),
+            Some(&6.0)
+        );
+
+        let map: CellMap<_, _> = map.into();
+        assert_eq!(map.num_cells(), Vector2::new(28, 20));
+    }
+}
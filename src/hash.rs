@@ -0,0 +1,261 @@
+//! Provides stable content hashing of maps, see [`CellMap::content_hash()`].
+//!
+//! Content hashes are stable across platforms, processes and versions of this crate, unlike the
+//! standard library's [`Hash`](std::hash::Hash), so they can be compared between different nodes
+//! of a distributed system to check whether they hold identical maps before syncing them.
+//!
+//! Maps built by the same operations on different platforms only hash identically if their
+//! floating-point results are bit-identical, which requires the `deterministic` feature. Without
+//! it, transforms and filters use the platform's maths library, which may round differently.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use crate::{BoundaryRule, CellMap, CellMapParams, CellOrigin, GridConvention, Layer, YAxis};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// ------------------------------------------------------------------------------------------------
+// TRAITS
+// ------------------------------------------------------------------------------------------------
+
+/// Trait for values which can be included in a content hash.
+///
+/// Implementations must feed the same bytes into the hasher on every platform, so multi-byte
+/// values should be written in little-endian order.
+pub trait ContentHash {
+    /// Feeds this value into the given hasher.
+    fn content_hash(&self, hasher: &mut ContentHasher);
+}
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A stable 64-bit FNV-1a hasher used to compute content hashes.
+#[derive(Debug, Clone, Copy)]
+pub struct ContentHasher {
+    state: u64,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl ContentHasher {
+    /// Creates a new hasher.
+    pub fn new() -> Self {
+        Self {
+            state: FNV_OFFSET_BASIS,
+        }
+    }
+
+    /// Feeds the given bytes into the hasher.
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.state ^= b as u64;
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    /// Returns the hash of all bytes written so far.
+    pub fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+impl Default for ContentHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: ContentHash,
+{
+    /// Returns a stable digest of the map's parameters and the data in all of its layers.
+    ///
+    /// Two maps have the same hash if they have identical parameters and data, with floats
+    /// compared bitwise, so for example `0.0` and `-0.0` hash differently, while two `NaN`s with
+    /// the same bit pattern hash the same.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = ContentHasher::new();
+
+        self.params.content_hash(&mut hasher);
+        (self.data.len() as u64).content_hash(&mut hasher);
+
+        for layer in &self.data {
+            // Iterate in logical order so that the memory layout doesn't affect the hash
+            for value in layer.iter() {
+                value.content_hash(&mut hasher);
+            }
+        }
+
+        hasher.finish()
+    }
+}
+
+impl ContentHash for CellMapParams {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        self.cell_size.x.content_hash(hasher);
+        self.cell_size.y.content_hash(hasher);
+        (self.cell_bounds.x.0 as i64).content_hash(hasher);
+        (self.cell_bounds.x.1 as i64).content_hash(hasher);
+        (self.cell_bounds.y.0 as i64).content_hash(hasher);
+        (self.cell_bounds.y.1 as i64).content_hash(hasher);
+        self.rotation_in_parent_rad.content_hash(hasher);
+        self.position_in_parent.x.content_hash(hasher);
+        self.position_in_parent.y.content_hash(hasher);
+        self.cell_boundary_precision.content_hash(hasher);
+
+        // Only non-default conventions and boundary rules are hashed, so that hashes of maps using
+        // the defaults are unchanged
+        if self.convention != GridConvention::default() {
+            (self.convention.y_axis == YAxis::Down).content_hash(hasher);
+            (self.convention.origin == CellOrigin::Centre).content_hash(hasher);
+        }
+
+        if self.boundary_rule != BoundaryRule::default() {
+            self.boundary_rule.to_code().content_hash(hasher);
+        }
+    }
+}
+
+impl ContentHash for bool {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        hasher.write(&[*self as u8]);
+    }
+}
+
+impl<T: ContentHash> ContentHash for Option<T> {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        match self {
+            Some(v) => {
+                hasher.write(&[1]);
+                v.content_hash(hasher);
+            }
+            None => hasher.write(&[0]),
+        }
+    }
+}
+
+impl<T: ContentHash, const N: usize> ContentHash for [T; N] {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        for v in self {
+            v.content_hash(hasher);
+        }
+    }
+}
+
+/// Implements [`ContentHash`] for numeric types using their little-endian bytes.
+macro_rules! impl_content_hash_le {
+    ($($t:ty),*) => {
+        $(
+            impl ContentHash for $t {
+                fn content_hash(&self, hasher: &mut ContentHasher) {
+                    hasher.write(&self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_content_hash_le!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+// Sizes are always hashed as 64-bit values so they match across platforms
+impl ContentHash for usize {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        (*self as u64).content_hash(hasher);
+    }
+}
+
+impl ContentHash for isize {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        (*self as i64).content_hash(hasher);
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point2, Vector2};
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers};
+
+    fn new_map() -> CellMap<TestLayers, f64> {
+        CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 4), (0, 3)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            1.0,
+        )
+    }
+
+    #[test]
+    fn content_hash() {
+        let map = new_map();
+        let hash = map.content_hash();
+
+        // Hashes are stable, so changes to the hashing scheme must be deliberate
+        assert_eq!(hash, new_map().content_hash());
+        assert_eq!(hash, 0x7091_24fd_4b69_783a, "{:#x}", hash);
+
+        // Changing data, including the sign of zero, changes the hash
+        let mut other = new_map();
+        other[(TestLayers::Layer2, Point2::new(3, 2))] = 1.0 + f64::EPSILON;
+        assert_ne!(other.content_hash(), hash);
+
+        let mut zeros = new_map();
+        zeros[(TestLayers::Layer0, Point2::new(0, 0))] = 0.0;
+        let mut neg_zeros = new_map();
+        neg_zeros[(TestLayers::Layer0, Point2::new(0, 0))] = -0.0;
+        assert_ne!(zeros.content_hash(), neg_zeros.content_hash());
+
+        // As does moving the map
+        let mut moved = new_map();
+        moved.move_map(Vector2::new(0.0, 1.0), 0.0);
+        assert_ne!(moved.content_hash(), hash);
+
+        // Or changing its convention
+        let flipped = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                convention: GridConvention {
+                    y_axis: YAxis::Down,
+                    ..Default::default()
+                },
+                ..new_map().params()
+            },
+            1.0,
+        );
+        assert_ne!(flipped.content_hash(), hash);
+
+        // Or its boundary rule
+        let half_open = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                boundary_rule: BoundaryRule::HalfOpen,
+                ..new_map().params()
+            },
+            1.0,
+        );
+        assert_ne!(half_open.content_hash(), hash);
+
+        // FNV-1a test vector
+        let mut hasher = ContentHasher::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -0,0 +1,250 @@
+//! Provides reading and writing of maps as HDF5 files, for exchanging maps with MATLAB, Python
+//! (h5py) and other scientific tooling.
+//!
+//! Each layer is stored as a 2D dataset in the root group, named by the layer's [`Debug`]
+//! implementation and with shape `(y, x)`, matching the map's own layout. Tools which store
+//! arrays column-major, such as MATLAB, will see each layer transposed. The map's parameters are
+//! stored as attributes of the root group:
+//!
+//! | Attribute                 | Shape | Description                                   |
+//! |---------------------------|-------|-----------------------------------------------|
+//! | `cell_size`               | `[2]` | The `x` and `y` size of each cell             |
+//! | `cell_bounds`             | `[4]` | The bounds, as `[x_min, x_max, y_min, y_max]` |
+//! | `rotation_in_parent_rad`  | `[]`  | The rotation of the map in its parent frame   |
+//! | `position_in_parent`      | `[2]` | The position of the map in its parent frame   |
+//! | `cell_boundary_precision` | `[]`  | The cell boundary precision                   |
+//! | `y_down`                  | `[]`  | `1` if the map's `y` axis points down         |
+//! | `origin_at_centre`        | `[]`  | `1` if the map's origin is at a cell's centre |
+//! | `boundary_rule`           | `[]`  | The boundary rule, see below                  |
+//!
+//! The `y_down`, `origin_at_centre` and `boundary_rule` attributes are optional when reading, and
+//! default to `0`. The `boundary_rule` is `0` for [`BoundaryRule::EpsilonSnap`], `1` for
+//! [`BoundaryRule::HalfOpen`] and `2` for [`BoundaryRule::RoundNearest`].
+//!
+//! This module requires the `hdf5` feature, which needs the HDF5 library to be installed.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{fmt::Debug, path::Path};
+
+use hdf5::H5Type;
+use nalgebra::Vector2;
+
+use crate::{
+    cell_map::{BoundaryRule, Bounds, CellOrigin, GridConvention, YAxis},
+    CellMap, CellMapParams, Error, Layer,
+};
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer + Debug,
+    T: H5Type,
+{
+    /// Writes the map to the given path as an HDF5 file, overwriting any existing file.
+    pub fn write_hdf5<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        trace_span!("write_hdf5", path = %path.as_ref().display());
+        let file = hdf5::File::create(path).map_err(Error::Hdf5Error)?;
+
+        for (layer, data) in L::all().iter().zip(&self.data) {
+            file.new_dataset_builder()
+                .with_data(data)
+                .create(format!("{:?}", layer).as_str())
+                .map_err(Error::Hdf5Error)?;
+        }
+
+        let params = self.params;
+        let bounds = params.cell_bounds;
+        let vector_attrs = [
+            ("cell_size", vec![params.cell_size.x, params.cell_size.y]),
+            (
+                "position_in_parent",
+                vec![params.position_in_parent.x, params.position_in_parent.y],
+            ),
+        ];
+        let scalar_attrs = [
+            ("rotation_in_parent_rad", params.rotation_in_parent_rad),
+            ("cell_boundary_precision", params.cell_boundary_precision),
+        ];
+
+        for (name, values) in vector_attrs.iter() {
+            file.new_attr_builder()
+                .with_data(values.as_slice())
+                .create(*name)
+                .map_err(Error::Hdf5Error)?;
+        }
+
+        for (name, value) in scalar_attrs.iter() {
+            file.new_attr::<f64>()
+                .create(*name)
+                .and_then(|attr| attr.write_scalar(value))
+                .map_err(Error::Hdf5Error)?;
+        }
+
+        let u8_attrs = [
+            ("y_down", (params.convention.y_axis == YAxis::Down) as u8),
+            (
+                "origin_at_centre",
+                (params.convention.origin == CellOrigin::Centre) as u8,
+            ),
+            ("boundary_rule", params.boundary_rule.to_code()),
+        ];
+
+        for (name, value) in u8_attrs.iter() {
+            file.new_attr::<u8>()
+                .create(*name)
+                .and_then(|attr| attr.write_scalar(value))
+                .map_err(Error::Hdf5Error)?;
+        }
+
+        let bounds = [
+            bounds.x.0 as i64,
+            bounds.x.1 as i64,
+            bounds.y.0 as i64,
+            bounds.y.1 as i64,
+        ];
+        file.new_attr_builder()
+            .with_data(&bounds[..])
+            .create("cell_bounds")
+            .map_err(Error::Hdf5Error)?;
+
+        file.close().map_err(Error::Hdf5Error)
+    }
+
+    /// Loads a map from the HDF5 file at the given path, which must contain a dataset for every
+    /// layer and the attributes written by [`CellMap::write_hdf5()`].
+    pub fn from_hdf5<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        trace_span!("from_hdf5", path = %path.as_ref().display());
+        let file = hdf5::File::open(path).map_err(Error::Hdf5Error)?;
+
+        let read_vector = |name: &str| -> Result<Vector2<f64>, Error> {
+            let values = file
+                .attr(name)
+                .and_then(|attr| attr.read_1d::<f64>())
+                .map_err(Error::Hdf5Error)?;
+
+            match values.as_slice() {
+                Some(&[x, y]) => Ok(Vector2::new(x, y)),
+                _ => Err(Error::InvalidHdf5File(format!(
+                    "expected 2 values in {} but found {}",
+                    name,
+                    values.len()
+                ))),
+            }
+        };
+        let read_scalar = |name: &str| -> Result<f64, Error> {
+            file.attr(name)
+                .and_then(|attr| attr.read_scalar::<f64>())
+                .map_err(Error::Hdf5Error)
+        };
+
+        // The u8 attributes are optional, and are `0` if they're missing
+        let attr_names = file.attr_names().map_err(Error::Hdf5Error)?;
+        let read_u8 = |name: &str| -> Result<u8, Error> {
+            if !attr_names.iter().any(|n| n == name) {
+                return Ok(0);
+            }
+
+            file.attr(name)
+                .and_then(|attr| attr.read_scalar::<u8>())
+                .map_err(Error::Hdf5Error)
+        };
+        let read_flag = |name: &str| read_u8(name).map(|v| v != 0);
+        let boundary_rule = read_u8("boundary_rule")?;
+
+        let bounds = file
+            .attr("cell_bounds")
+            .and_then(|attr| attr.read_1d::<i64>())
+            .map_err(Error::Hdf5Error)?;
+        let cell_bounds = match bounds.as_slice() {
+            Some(&[x_min, x_max, y_min, y_max]) => Bounds::new(
+                (x_min as isize, x_max as isize),
+                (y_min as isize, y_max as isize),
+            )?,
+            _ => {
+                return Err(Error::InvalidHdf5File(format!(
+                    "expected 4 values in cell_bounds but found {}",
+                    bounds.len()
+                )))
+            }
+        };
+
+        let params = CellMapParams {
+            cell_size: read_vector("cell_size")?,
+            cell_bounds,
+            rotation_in_parent_rad: read_scalar("rotation_in_parent_rad")?,
+            position_in_parent: read_vector("position_in_parent")?,
+            cell_boundary_precision: read_scalar("cell_boundary_precision")?,
+            convention: GridConvention {
+                y_axis: if read_flag("y_down")? {
+                    YAxis::Down
+                } else {
+                    YAxis::Up
+                },
+                origin: if read_flag("origin_at_centre")? {
+                    CellOrigin::Centre
+                } else {
+                    CellOrigin::Corner
+                },
+            },
+            boundary_rule: BoundaryRule::from_code(boundary_rule).ok_or_else(|| {
+                Error::InvalidHdf5File(format!("invalid boundary rule {}", boundary_rule))
+            })?,
+        };
+
+        let data = L::all()
+            .iter()
+            .map(|layer| {
+                file.dataset(&format!("{:?}", layer))
+                    .and_then(|dataset| dataset.read_2d::<T>())
+                    .map_err(Error::Hdf5Error)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        CellMap::new_from_data(params, data)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+    use crate::test_utils::TestLayers;
+
+    #[test]
+    fn write_and_read() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-2, 3), (1, 4)).unwrap(),
+                cell_size: Vector2::new(0.1, 0.2),
+                rotation_in_parent_rad: 0.4,
+                position_in_parent: Vector2::new(-1.0, 2.0),
+                ..Default::default()
+            },
+            0.5,
+        );
+        map[(TestLayers::Layer1, Point2::new(4, 2))] = -3.0;
+
+        let path = std::env::temp_dir().join("cell_map_write_and_read.h5");
+        map.write_hdf5(&path).unwrap();
+
+        let read = CellMap::<TestLayers, f64>::from_hdf5(&path).unwrap();
+        assert_eq!(read.content_hash(), map.content_hash());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            CellMap::<TestLayers, f64>::from_hdf5(&path),
+            Err(Error::Hdf5Error(_))
+        ));
+    }
+}
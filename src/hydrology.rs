@@ -0,0 +1,205 @@
+//! Provides hydrology tools which trace how water flows over a height layer, using the D8 model
+//! in which water leaves each cell towards the single neighbour with the steepest downhill slope.
+//!
+//! [`CellMap::flow_direction()`] gives the direction water leaves each cell, and
+//! [`CellMap::flow_accumulation()`] the area which drains through each cell. Cells with a large
+//! accumulation are channels, where water collects after rain, while cells with no downhill
+//! neighbour are pits or flats where water ponds.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::Vector2;
+use ndarray::{Array2, ArrayView2};
+
+use crate::{math, CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The index offsets `(dx, dy)` of the 8 flow directions used by [`CellMap::flow_direction()`],
+/// starting along the `+x` axis and going anticlockwise for a map with an upwards `y` axis.
+pub const D8_DIRECTIONS: [(isize, isize); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Computes the D8 flow direction of each cell of `height_layer`, writing the result into
+    /// `dst_layer`.
+    ///
+    /// The direction is the index into [`D8_DIRECTIONS`] of the neighbour with the steepest
+    /// downhill slope, measured between cell centres in parent-frame units, with ties going to the
+    /// first direction. Cells with no lower neighbour, which are pits, flats, or outlets on the
+    /// edge of the map, and `NaN` cells have a direction of `NaN`. `NaN` neighbours are ignored.
+    pub fn flow_direction(&mut self, height_layer: L, dst_layer: L) {
+        let directions = d8_directions(self[height_layer].view(), self.cell_size());
+        self[dst_layer] = directions.mapv(|d| d.map_or(f64::NAN, |d| d as f64));
+    }
+
+    /// Computes the D8 flow accumulation of `height_layer`, the area in parent-frame units
+    /// squared of the cells whose water flows through each cell, including the cell itself,
+    /// writing the result into `dst_layer`.
+    ///
+    /// Water flows along the directions given by [`CellMap::flow_direction()`], and stops at cells
+    /// with no lower neighbour. `NaN` cells have `NaN` accumulation and don't contribute to any
+    /// other cell.
+    pub fn flow_accumulation(&mut self, height_layer: L, dst_layer: L) {
+        let cell_size = self.cell_size();
+        let data = self[height_layer].view();
+        let directions = d8_directions(data, cell_size);
+        let downstream = |(y, x): (usize, usize)| {
+            directions[(y, x)].map(|d| {
+                let (dx, dy) = D8_DIRECTIONS[d];
+                ((y as isize + dy) as usize, (x as isize + dx) as usize)
+            })
+        };
+
+        // The number of cells flowing directly into each cell
+        let mut inflows = Array2::<usize>::zeros(data.dim());
+        for index in directions.indexed_iter().filter_map(|(i, _)| downstream(i)) {
+            inflows[index] += 1;
+        }
+
+        // Pass each cell's area downstream once everything upstream of it has been added
+        let cell_area = cell_size.x * cell_size.y;
+        let mut accumulation = data.mapv(|h| if h.is_nan() { f64::NAN } else { cell_area });
+        let mut ready: Vec<_> = inflows
+            .indexed_iter()
+            .filter(|&(i, &n)| n == 0 && !data[i].is_nan())
+            .map(|(i, _)| i)
+            .collect();
+
+        while let Some(index) = ready.pop() {
+            if let Some(next) = downstream(index) {
+                accumulation[next] += accumulation[index];
+                inflows[next] -= 1;
+                if inflows[next] == 0 {
+                    ready.push(next);
+                }
+            }
+        }
+
+        self[dst_layer] = accumulation;
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Finds the index into [`D8_DIRECTIONS`] of the steepest downhill neighbour of each cell.
+fn d8_directions(data: ArrayView2<'_, f64>, cell_size: Vector2<f64>) -> Array2<Option<usize>> {
+    let (rows, cols) = data.dim();
+
+    Array2::from_shape_fn((rows, cols), |(y, x)| {
+        let height = data[(y, x)];
+        if height.is_nan() {
+            return None;
+        }
+
+        let mut steepest = None;
+        let mut max_slope = 0.0;
+
+        for (d, (dx, dy)) in D8_DIRECTIONS.iter().enumerate() {
+            let ny = y as isize + dy;
+            let nx = x as isize + dx;
+            if ny < 0 || nx < 0 || ny >= rows as isize || nx >= cols as isize {
+                continue;
+            }
+
+            // NaN neighbours give a NaN slope, which is never steeper
+            let drop = height - data[(ny as usize, nx as usize)];
+            let slope = drop / math::hypot(*dx as f64 * cell_size.x, *dy as f64 * cell_size.y);
+            if slope > max_slope {
+                max_slope = slope;
+                steepest = Some(d);
+            }
+        }
+
+        steepest
+    })
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    /// A valley along row 2 which slopes down towards `+x`.
+    fn valley() -> CellMap<TestLayers, f64> {
+        let mut map = CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 6), (0, 5)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        for ((_, index), height) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            *height = (index.y as f64 - 2.0).abs() + 0.1 * (5.0 - index.x as f64);
+        }
+
+        map
+    }
+
+    #[test]
+    fn flow_direction() {
+        let mut map = valley();
+        map.flow_direction(TestLayers::Layer0, TestLayers::Layer1);
+
+        let direction = |x, y| map[(TestLayers::Layer1, Point2::new(x, y))];
+        assert_eq!(direction(0, 0), 2.0);
+        assert_eq!(direction(3, 4), 6.0);
+        assert_eq!(direction(3, 2), 0.0);
+        assert!(direction(5, 2).is_nan());
+
+        // A flat map has no flow
+        map.flow_direction(TestLayers::Layer2, TestLayers::Layer1);
+        assert!(map.iter().layer(TestLayers::Layer1).all(|d| d.is_nan()));
+    }
+
+    #[test]
+    fn flow_accumulation() {
+        let mut map = valley();
+        map.flow_accumulation(TestLayers::Layer0, TestLayers::Layer1);
+
+        // Each cell is 0.25 square units
+        let area = |map: &CellMap<TestLayers, f64>, x, y| {
+            map[(TestLayers::Layer1, Point2::new(x, y))] / 0.25
+        };
+        assert_f64_eq!(area(&map, 0, 0), 1.0, 1e-12);
+        assert_f64_eq!(area(&map, 4, 3), 2.0, 1e-12);
+        assert_f64_eq!(area(&map, 2, 2), 15.0, 1e-12);
+        assert_f64_eq!(area(&map, 5, 2), 30.0, 1e-12);
+
+        // NaN cells don't contribute
+        map[(TestLayers::Layer0, Point2::new(0, 0))] = f64::NAN;
+        map.flow_accumulation(TestLayers::Layer0, TestLayers::Layer1);
+        assert!(area(&map, 0, 0).is_nan());
+        assert_f64_eq!(area(&map, 0, 1), 1.0, 1e-12);
+        assert_f64_eq!(area(&map, 5, 2), 29.0, 1e-12);
+    }
+}
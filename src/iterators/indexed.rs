@@ -55,16 +55,16 @@ where
 
     type OutputMut = ((L, Point2<usize>), S::OutputMut);
 
-    fn slice(&self, data: &'a ndarray::Array2<T>) -> Option<Self::Output> {
+    fn slice(&self, data: ndarray::ArrayView2<'a, T>) -> Option<Self::Output> {
         let item = self.slicer.slice(data)?;
 
-        Some(((self.layer.clone(), self.slicer.index().unwrap()), item))
+        Some(((self.layer.clone(), self.slicer.index()?), item))
     }
 
-    fn slice_mut(&self, data: &'a mut ndarray::Array2<T>) -> Option<Self::OutputMut> {
+    fn slice_mut(&self, data: ndarray::ArrayViewMut2<'a, T>) -> Option<Self::OutputMut> {
         let item = self.slicer.slice_mut(data)?;
 
-        Some(((self.layer.clone(), self.slicer.index().unwrap()), item))
+        Some(((self.layer.clone(), self.slicer.index()?), item))
     }
 
     fn advance(&mut self) {
@@ -40,6 +40,8 @@
 //! ```
 //!
 //! [`CellMap`]: crate::CellMap
+//! [`CellMap::iter()`]: crate::CellMap::iter
+//! [`CellMap::window_iter_mut()`]: crate::CellMap::window_iter_mut
 
 // ------------------------------------------------------------------------------------------------
 // MODULES
@@ -56,11 +58,16 @@ mod tests;
 // IMPORTS
 // ------------------------------------------------------------------------------------------------
 
+use std::marker::PhantomData;
+
 use layerers::*;
 use nalgebra::{Point2, Vector2};
+use ndarray::{Array2, ArrayView2, ArrayViewMut2};
+#[cfg(feature = "mmap")]
+use ndarray::{ArrayView3, Axis};
 use slicers::*;
 
-use crate::{CellMap, Error, Layer};
+use crate::{map_metadata::CellMapMetadata, Error, Layer};
 
 use self::{indexed::Indexed, positioned::Positioned};
 
@@ -70,6 +77,8 @@ use self::{indexed::Indexed, positioned::Positioned};
 
 /// A non-mutable iterator over a [`CellMap`], see [`Slicer`] and [`layerers`] for more
 /// information.
+///
+/// [`CellMap`]: crate::CellMap
 #[derive(Debug, Clone, Copy)]
 pub struct CellMapIter<'m, L, T, R, S>
 where
@@ -77,12 +86,16 @@ where
     R: Layerer<L>,
     S: Slicer<'m, L, T>,
 {
-    map: &'m CellMap<L, T>,
+    layers: Layers<'m, T>,
+    metadata: CellMapMetadata,
     layerer: R,
     slicer: S,
+    layer_type: PhantomData<L>,
 }
 
 /// A mutable iterator over a [`CellMap`], see [`Slicer`] and [`layerers`] for more information.
+///
+/// [`CellMap`]: crate::CellMap
 #[derive(Debug)]
 pub struct CellMapIterMut<'m, L, T, R, S>
 where
@@ -90,96 +103,246 @@ where
     R: Layerer<L>,
     S: Slicer<'m, L, T>,
 {
-    map: &'m mut CellMap<L, T>,
+    layers: LayersMut<'m, T>,
+    metadata: CellMapMetadata,
     layerer: R,
     slicer: S,
+    layer_type: PhantomData<L>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// The layers borrowed by a [`CellMapIter`], in [`Layer::to_index()`] order.
+///
+/// Each kind of map stores its layers differently, so they are borrowed as they are rather than
+/// collected into views, which keeps [`CellMapIter`] `Copy`.
+#[derive(Debug)]
+pub(crate) enum Layers<'m, T> {
+    /// The layers of a [`CellMap`](crate::CellMap).
+    Arrays(&'m [Array2<T>]),
+
+    /// The layers of a [`CellMapView`](crate::view::CellMapView).
+    Views(&'m [ArrayView2<'m, T>]),
+
+    /// The layers of a [`CellMapViewMut`](crate::view::CellMapViewMut).
+    ViewsMut(&'m [ArrayViewMut2<'m, T>]),
+
+    /// Layers stacked along the first axis, as in a memory-mapped file.
+    #[cfg(feature = "mmap")]
+    Stacked(ArrayView3<'m, T>),
+}
+
+/// The layers mutably borrowed by a [`CellMapIterMut`], in [`Layer::to_index()`] order.
+#[derive(Debug)]
+pub(crate) enum LayersMut<'m, T> {
+    /// The layers of a [`CellMap`](crate::CellMap).
+    Arrays(&'m mut [Array2<T>]),
+
+    /// Views of each layer, used by [`CellMapViewMut`](crate::view::CellMapViewMut) whose views
+    /// can't be reborrowed for `'m`.
+    Views(Vec<ArrayViewMut2<'m, T>>),
 }
 
 // ------------------------------------------------------------------------------------------------
 // IMPLS
 // ------------------------------------------------------------------------------------------------
 
+impl<'m, T> Layers<'m, T> {
+    /// Borrows the layers of a [`CellMapView`](crate::view::CellMapView) for `'m`.
+    pub(crate) fn views<'v: 'm>(layers: &'m [ArrayView2<'v, T>]) -> Self {
+        // Note: use of unsafe
+        //
+        // Views are invariant over their lifetime since `ArrayBase` uses an associated type of its
+        // storage, so they can't be coerced to the shorter `'m`. Shortening the lifetime of a
+        // shared view is always sound, it's what `ArrayView::reborrow()` does for a single view.
+        Layers::Views(unsafe {
+            std::mem::transmute::<&'m [ArrayView2<'v, T>], &'m [ArrayView2<'m, T>]>(layers)
+        })
+    }
+
+    /// Borrows the layers of a [`CellMapViewMut`](crate::view::CellMapViewMut) for `'m`.
+    pub(crate) fn views_mut<'v: 'm>(layers: &'m [ArrayViewMut2<'v, T>]) -> Self {
+        // Note: use of unsafe
+        //
+        // As in `Layers::views()`, and the views can only be read through a shared borrow.
+        Layers::ViewsMut(unsafe {
+            std::mem::transmute::<&'m [ArrayViewMut2<'v, T>], &'m [ArrayViewMut2<'m, T>]>(layers)
+        })
+    }
+
+    /// Returns a view of the layer with the given index.
+    fn view(self, index: usize) -> ArrayView2<'m, T> {
+        match self {
+            Layers::Arrays(layers) => layers[index].view(),
+            Layers::Views(layers) => layers[index],
+            Layers::ViewsMut(layers) => layers[index].view(),
+            #[cfg(feature = "mmap")]
+            Layers::Stacked(layers) => layers.index_axis_move(Axis(0), index),
+        }
+    }
+}
+
+// Implemented manually since deriving would require `T: Copy`
+impl<'m, T> Clone for Layers<'m, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'m, T> Copy for Layers<'m, T> {}
+
+impl<'m, T> LayersMut<'m, T> {
+    /// Returns a view of the layer with the given index which lives for `'m`.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the returned view doesn't alias with any mutable view of the
+    /// same layer which is still live.
+    unsafe fn view(&self, index: usize) -> ArrayView2<'m, T> {
+        match self {
+            LayersMut::Arrays(layers) => {
+                let layer: *const Array2<T> = &layers[index];
+                (*layer).view()
+            }
+            LayersMut::Views(layers) => {
+                let layer: *const ArrayViewMut2<'m, T> = &layers[index];
+                (*layer).view()
+            }
+        }
+    }
+
+    /// Returns a mutable view of the layer with the given index which lives for `'m`.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the returned view doesn't alias with any other view of the
+    /// same layer which is still live.
+    unsafe fn view_mut(&mut self, index: usize) -> ArrayViewMut2<'m, T> {
+        match self {
+            LayersMut::Arrays(layers) => {
+                let layer: *mut Array2<T> = &mut layers[index];
+                (*layer).view_mut()
+            }
+            LayersMut::Views(layers) => {
+                let layer: *mut ArrayViewMut2<'m, T> = &mut layers[index];
+                (*layer).view_mut()
+            }
+        }
+    }
+}
+
 impl<'m, L, T, R, S> CellMapIter<'m, L, T, R, S>
 where
     L: Layer,
     S: Slicer<'m, L, T>,
     R: Layerer<L>,
 {
-    pub(crate) fn new_cells(map: &'m CellMap<L, T>) -> CellMapIter<'m, L, T, Many<L>, Cells> {
+    /// Creates a new iterator over the given layers, which must be in [`Layer::to_index()`]
+    /// order, and produce data using the given `slicer`.
+    pub(crate) fn new(
+        layers: Layers<'m, T>,
+        metadata: CellMapMetadata,
+        slicer: S,
+    ) -> CellMapIter<'m, L, T, Many<L>, S> {
         CellMapIter {
-            map,
+            layers,
+            metadata,
             layerer: Many {
                 layers: L::all().into(),
             },
-            slicer: Cells::from_map(map),
+            slicer,
+            layer_type: PhantomData,
         }
     }
 
+    pub(crate) fn new_cells(
+        layers: Layers<'m, T>,
+        metadata: CellMapMetadata,
+    ) -> CellMapIter<'m, L, T, Many<L>, Cells> {
+        CellMapIter::<'m, L, T, Many<L>, Cells>::new(layers, metadata, Cells::from_map(&metadata))
+    }
+
     pub(crate) fn new_windows(
-        map: &'m CellMap<L, T>,
+        layers: Layers<'m, T>,
+        metadata: CellMapMetadata,
         semi_width: Vector2<usize>,
     ) -> Result<CellMapIter<'m, L, T, Many<L>, Windows>, Error> {
-        Ok(CellMapIter {
-            map,
-            layerer: Many {
-                layers: L::all().into(),
-            },
-            slicer: Windows::from_map(map, semi_width)?,
-        })
+        Ok(CellMapIter::<'m, L, T, Many<L>, Windows>::new(
+            layers,
+            metadata,
+            Windows::from_map(&metadata, semi_width)?,
+        ))
     }
 
     pub(crate) fn new_line(
-        map: &'m CellMap<L, T>,
+        layers: Layers<'m, T>,
+        metadata: CellMapMetadata,
         start_position: Point2<f64>,
         end_position: Point2<f64>,
     ) -> Result<CellMapIter<'m, L, T, Many<L>, Line>, Error> {
-        Ok(CellMapIter {
-            map,
-            layerer: Many {
-                layers: L::all().into(),
-            },
-            slicer: Line::from_map::<L, T>(map.metadata, start_position, end_position)?,
-        })
+        Ok(CellMapIter::<'m, L, T, Many<L>, Line>::new(
+            layers,
+            metadata,
+            Line::from_map(metadata, start_position, end_position)?,
+        ))
     }
 
     /// Converts this iterator to use a [`Single`] layerer, produing data from only one layer.
     pub fn layer(self, layer: L) -> CellMapIter<'m, L, T, Single<L>, S> {
         CellMapIter {
-            map: self.map,
+            layers: self.layers,
+            metadata: self.metadata,
             layerer: Single { layer },
             slicer: self.slicer,
+            layer_type: PhantomData,
         }
     }
 
+    /// Converts this iterator to use a [`Many`] layerer, produing data from the layers in the
+    /// given group, see [`Layer::groups()`].
+    pub fn group(self, group: &str) -> CellMapIter<'m, L, T, Many<L>, S> {
+        self.layers(&L::in_group(group))
+    }
+
     /// Converts this iterator to use a [`Many`] layerer, produing data from many layers.
     pub fn layers(self, layers: &[L]) -> CellMapIter<'m, L, T, Many<L>, S> {
         CellMapIter {
-            map: self.map,
+            layers: self.layers,
+            metadata: self.metadata,
             layerer: Many {
                 layers: layers.to_vec().into(),
             },
             slicer: self.slicer,
+            layer_type: PhantomData,
         }
     }
 
     /// Converts this iterator to also produce the index of the iterated item as well as its value.
     pub fn indexed(self) -> CellMapIter<'m, L, T, R, Indexed<'m, L, T, S>> {
-        let current_layer = self.layerer.current().unwrap();
+        // If there's no current layer the iterator is empty, so the layer is never used
+        let current_layer = self.layerer.current().unwrap_or(L::FIRST);
         CellMapIter {
-            map: self.map,
+            layers: self.layers,
+            metadata: self.metadata,
             layerer: self.layerer,
             slicer: Indexed::new(self.slicer, current_layer),
+            layer_type: PhantomData,
         }
     }
 
     /// Converts this iterator to also produce the position of the iterated item as well as its
     /// value.
     pub fn positioned(self) -> CellMapIter<'m, L, T, R, Positioned<'m, L, T, S>> {
-        let current_layer = self.layerer.current().unwrap();
+        // If there's no current layer the iterator is empty, so the layer is never used
+        let current_layer = self.layerer.current().unwrap_or(L::FIRST);
         CellMapIter {
-            map: self.map,
+            layers: self.layers,
+            metadata: self.metadata,
             layerer: self.layerer,
-            slicer: Positioned::new(self.slicer, current_layer, self.map.metadata),
+            slicer: Positioned::new(self.slicer, current_layer, self.metadata),
+            layer_type: PhantomData,
         }
     }
 }
@@ -190,100 +353,148 @@ where
     R: Layerer<L>,
     S: Slicer<'m, L, T>,
 {
-    pub(crate) fn new_cells(
-        map: &'m mut CellMap<L, T>,
-    ) -> CellMapIterMut<'m, L, T, Many<L>, Cells> {
-        let slicer = Cells::from_map(map);
-
+    /// Creates a new iterator over the given layers, which must be in [`Layer::to_index()`]
+    /// order, and produce data using the given `slicer`.
+    pub(crate) fn new(
+        layers: LayersMut<'m, T>,
+        metadata: CellMapMetadata,
+        slicer: S,
+    ) -> CellMapIterMut<'m, L, T, Many<L>, S> {
         CellMapIterMut {
-            map,
+            layers,
+            metadata,
             layerer: Many {
                 layers: L::all().into(),
             },
             slicer,
+            layer_type: PhantomData,
         }
     }
 
+    pub(crate) fn new_cells(
+        layers: LayersMut<'m, T>,
+        metadata: CellMapMetadata,
+    ) -> CellMapIterMut<'m, L, T, Many<L>, Cells> {
+        CellMapIterMut::<'m, L, T, Many<L>, Cells>::new(
+            layers,
+            metadata,
+            Cells::from_map(&metadata),
+        )
+    }
+
     pub(crate) fn new_windows(
-        map: &'m mut CellMap<L, T>,
+        layers: LayersMut<'m, T>,
+        metadata: CellMapMetadata,
         semi_width: Vector2<usize>,
     ) -> Result<CellMapIterMut<'m, L, T, Many<L>, Windows>, Error> {
-        let slicer = Windows::from_map(map, semi_width)?;
-
-        Ok(CellMapIterMut {
-            map,
-            layerer: Many {
-                layers: L::all().into(),
-            },
-            slicer,
-        })
+        Ok(CellMapIterMut::<'m, L, T, Many<L>, Windows>::new(
+            layers,
+            metadata,
+            Windows::from_map(&metadata, semi_width)?,
+        ))
     }
 
     pub(crate) fn new_line(
-        map: &'m mut CellMap<L, T>,
+        layers: LayersMut<'m, T>,
+        metadata: CellMapMetadata,
         start_position: Point2<f64>,
         end_position: Point2<f64>,
     ) -> Result<CellMapIterMut<'m, L, T, Many<L>, Line>, Error> {
-        let metadata = map.metadata;
-        Ok(CellMapIterMut {
-            map,
-            layerer: Many {
-                layers: L::all().into(),
-            },
-            slicer: Line::from_map::<L, T>(metadata, start_position, end_position)?,
-        })
+        Ok(CellMapIterMut::<'m, L, T, Many<L>, Line>::new(
+            layers,
+            metadata,
+            Line::from_map(metadata, start_position, end_position)?,
+        ))
     }
 
     /// Converts this iterator to use a [`Single`] layerer, produing data from only one layer.
     pub fn layer(self, layer: L) -> CellMapIterMut<'m, L, T, Single<L>, S> {
         CellMapIterMut {
-            map: self.map,
+            layers: self.layers,
+            metadata: self.metadata,
             layerer: Single { layer },
             slicer: self.slicer,
+            layer_type: PhantomData,
         }
     }
 
+    /// Converts this iterator to use a [`Many`] layerer, produing data from the layers in the
+    /// given group, see [`Layer::groups()`].
+    pub fn group(self, group: &str) -> CellMapIterMut<'m, L, T, Many<L>, S> {
+        self.layers(&L::in_group(group))
+    }
+
     /// Converts this iterator to use a [`Many`] layerer, produing data from many layers.
     pub fn layers(self, layers: &[L]) -> CellMapIterMut<'m, L, T, Many<L>, S> {
         CellMapIterMut {
-            map: self.map,
+            layers: self.layers,
+            metadata: self.metadata,
             layerer: Many {
                 layers: layers.to_vec().into(),
             },
             slicer: self.slicer,
+            layer_type: PhantomData,
         }
     }
 
     /// Converts this iterator to use a [`Map`] layerer, which maps data from one layer to another.
     pub fn map_layers(self, from: L, to: L) -> CellMapIterMut<'m, L, T, Map<L>, S> {
         CellMapIterMut {
-            map: self.map,
+            layers: self.layers,
+            metadata: self.metadata,
             layerer: Map { from, to },
             slicer: self.slicer,
+            layer_type: PhantomData,
         }
     }
 
     /// Converts this iterator to also produce the index of the iterated item as well as its value.
     pub fn indexed(self) -> CellMapIterMut<'m, L, T, R, Indexed<'m, L, T, S>> {
-        let current_layer = self.layerer.current().unwrap();
+        // If there's no current layer the iterator is empty, so the layer is never used
+        let current_layer = self.layerer.current().unwrap_or(L::FIRST);
         CellMapIterMut {
-            map: self.map,
+            layers: self.layers,
+            metadata: self.metadata,
             layerer: self.layerer,
             slicer: Indexed::new(self.slicer, current_layer),
+            layer_type: PhantomData,
         }
     }
 
     /// Converts this iterator to also produce the position of the iterated item as well as its
     /// value.
     pub fn positioned(self) -> CellMapIterMut<'m, L, T, R, Positioned<'m, L, T, S>> {
-        let current_layer = self.layerer.current().unwrap();
-        let map_meta = self.map.metadata;
+        // If there's no current layer the iterator is empty, so the layer is never used
+        let current_layer = self.layerer.current().unwrap_or(L::FIRST);
         CellMapIterMut {
-            map: self.map,
+            layers: self.layers,
+            metadata: self.metadata,
             layerer: self.layerer,
-            slicer: Positioned::new(self.slicer, current_layer, map_meta),
+            slicer: Positioned::new(self.slicer, current_layer, self.metadata),
+            layer_type: PhantomData,
         }
     }
+
+    /// Returns a view of the given layer which lives as long as the iterator's borrow of the map.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the returned view doesn't alias with any mutable view of the
+    /// same layer which is still live.
+    unsafe fn layer_view(&self, layer: &L) -> ArrayView2<'m, T> {
+        self.layers.view(layer.to_index())
+    }
+
+    /// Returns a mutable view of the given layer which lives as long as the iterator's borrow of
+    /// the map.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the returned view doesn't alias with any other view of the
+    /// same layer which is still live.
+    unsafe fn layer_view_mut(&mut self, layer: &L) -> ArrayViewMut2<'m, T> {
+        self.layers.view_mut(layer.to_index())
+    }
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -300,9 +511,10 @@ where
     fn next(&mut self) -> Option<Self::Item> {
         let item = self
             .slicer
-            .slice(&self.map.data[self.layerer.layer.to_index()]);
+            .slice(self.layers.view(self.layerer.layer.to_index()));
 
         self.slicer.advance();
+        count!(ItemsIterated, item.is_some());
 
         item
     }
@@ -322,15 +534,13 @@ where
         // in the map, which we can do since each call to this function will drop the previously
         // returned reference first.
         let item = unsafe {
-            let layer_ptr = self
-                .map
-                .data
-                .as_mut_ptr()
-                .add(self.layerer.layer.to_index());
-            self.slicer.slice_mut(&mut *layer_ptr)
+            let layer = self.layerer.layer.clone();
+            let view = self.layer_view_mut(&layer);
+            self.slicer.slice_mut(view)
         };
 
         self.slicer.advance();
+        count!(ItemsIterated, item.is_some());
 
         item
     }
@@ -346,9 +556,10 @@ where
     fn next(&mut self) -> Option<Self::Item> {
         let item = self
             .slicer
-            .slice(&self.map.data[self.layerer.layers.front()?.to_index()]);
+            .slice(self.layers.view(self.layerer.layers.front()?.to_index()));
 
         self.slicer.advance();
+        count!(ItemsIterated, item.is_some());
 
         if self.slicer.index().is_none() {
             self.layerer.layers.pop_front();
@@ -373,15 +584,13 @@ where
         // in the map, which we can do since each call to this function will drop the previously
         // returned reference first.
         let item = unsafe {
-            let layer_ptr = self
-                .map
-                .data
-                .as_mut_ptr()
-                .add(self.layerer.layers.front()?.to_index());
-            self.slicer.slice_mut(&mut *layer_ptr)
+            let layer = self.layerer.layers.front()?.clone();
+            let view = self.layer_view_mut(&layer);
+            self.slicer.slice_mut(view)
         };
 
         self.slicer.advance();
+        count!(ItemsIterated, item.is_some());
 
         if self.slicer.index().is_none() {
             self.layerer.layers.pop_front();
@@ -406,15 +615,17 @@ where
         // in the map, which we can do since each call to this function will drop the previously
         // returned reference first.
         let (from, to) = unsafe {
-            let from_ptr = self.map.data.as_ptr().add(self.layerer.from.to_index());
-            let from = self.slicer.slice(&*from_ptr);
-            let to_ptr = self.map.data.as_mut_ptr().add(self.layerer.to.to_index());
-            let to = self.slicer.slice_mut(&mut *to_ptr);
+            let from_layer = self.layerer.from.clone();
+            let to_layer = self.layerer.to.clone();
+            let from = self.slicer.slice(self.layer_view(&from_layer));
+            let to_view = self.layer_view_mut(&to_layer);
+            let to = self.slicer.slice_mut(to_view);
 
             (from, to)
         };
 
         self.slicer.advance();
+        count!(ItemsIterated, from.is_some() && to.is_some());
 
         match (from, to) {
             (Some(f), Some(t)) => Some((f, t)),
@@ -57,14 +57,14 @@ where
 
     type OutputMut = ((L, Point2<f64>), S::OutputMut);
 
-    fn slice(&self, data: &'a ndarray::Array2<T>) -> Option<Self::Output> {
+    fn slice(&self, data: ndarray::ArrayView2<'a, T>) -> Option<Self::Output> {
         let item = self.slicer.slice(data)?;
         let index = self.slicer.index()?;
 
         Some(((self.layer.clone(), self.map_meta.position(index)?), item))
     }
 
-    fn slice_mut(&self, data: &'a mut ndarray::Array2<T>) -> Option<Self::OutputMut> {
+    fn slice_mut(&self, data: ndarray::ArrayViewMut2<'a, T>) -> Option<Self::OutputMut> {
         let item = self.slicer.slice_mut(data)?;
         let index = self.slicer.index()?;
 
@@ -8,12 +8,11 @@
 // ------------------------------------------------------------------------------------------------
 
 use nalgebra::{Point2, Vector2};
-use ndarray::{s, Array2, ArrayView2, ArrayViewMut2};
+use ndarray::{s, ArrayView2, ArrayViewMut2, IndexLonger};
+#[cfg(feature = "debug_iters")]
 use serde::Serialize;
 
-use crate::{
-    cell_map::Bounds, extensions::Point2Ext, map_metadata::CellMapMetadata, CellMap, Error, Layer,
-};
+use crate::{cell_map::Bounds, extensions::Point2Ext, map_metadata::CellMapMetadata, Error, Layer};
 
 // ------------------------------------------------------------------------------------------------
 // TRAITS
@@ -43,11 +42,11 @@ where
 
     /// Perform the slice on the given `data` layer, or `None` if the slicer has reached the end of
     /// its data.
-    fn slice(&self, data: &'a Array2<T>) -> Option<Self::Output>;
+    fn slice(&self, data: ArrayView2<'a, T>) -> Option<Self::Output>;
 
     /// Perform a mutable slice on the given `data` layer, or `None` if the slicer has reached the
     /// end of its data.
-    fn slice_mut(&self, data: &'a mut Array2<T>) -> Option<Self::OutputMut>;
+    fn slice_mut(&self, data: ArrayViewMut2<'a, T>) -> Option<Self::OutputMut>;
 
     /// Advance the [`Slicer`] to the next index.
     fn advance(&mut self);
@@ -90,6 +89,7 @@ pub struct Windows {
 /// line intersects will be yielded only once.
 #[derive(Debug, Clone)]
 #[allow(missing_copy_implementations)]
+#[cfg_attr(not(feature = "debug_iters"), allow(dead_code))]
 pub struct Line {
     bounds: Bounds,
     map_meta: CellMapMetadata,
@@ -110,6 +110,7 @@ pub struct Line {
     step_report_file: std::sync::Arc<std::fs::File>,
 }
 
+#[cfg(feature = "debug_iters")]
 #[derive(Debug, Clone, Copy, Serialize)]
 struct LineStepData {
     start_parent: Point2<f64>,
@@ -132,8 +133,8 @@ struct LineStepData {
 // ------------------------------------------------------------------------------------------------
 
 impl Cells {
-    pub(crate) fn from_map<L: Layer, T>(map: &CellMap<L, T>) -> Self {
-        let cells = map.num_cells();
+    pub(crate) fn from_map(map_meta: &CellMapMetadata) -> Self {
+        let cells = map_meta.num_cells;
         Self {
             bounds: Vector2::new((0, cells.x), (0, cells.y)),
             index: Point2::new(0, 0),
@@ -149,12 +150,12 @@ where
     type Output = &'a T;
     type OutputMut = &'a mut T;
 
-    fn slice(&self, data: &'a Array2<T>) -> Option<Self::Output> {
-        data.get(self.index.as_array2_index())
+    fn slice(&self, data: ArrayView2<'a, T>) -> Option<Self::Output> {
+        IndexLonger::get(&data, self.index.as_array2_index())
     }
 
-    fn slice_mut(&self, data: &'a mut Array2<T>) -> Option<Self::OutputMut> {
-        data.get_mut(self.index.as_array2_index())
+    fn slice_mut(&self, data: ArrayViewMut2<'a, T>) -> Option<Self::OutputMut> {
+        get_mut(data, self.index.as_array2_index())
     }
 
     fn advance(&mut self) {
@@ -180,11 +181,11 @@ where
 }
 
 impl Windows {
-    pub(crate) fn from_map<L: Layer, T>(
-        map: &CellMap<L, T>,
+    pub(crate) fn from_map(
+        map_meta: &CellMapMetadata,
         semi_width: Vector2<usize>,
     ) -> Result<Self, Error> {
-        let cells = map.num_cells();
+        let cells = map_meta.num_cells;
 
         if semi_width.x * 2 + 1 > cells.x || semi_width.y * 2 + 1 > cells.y {
             Err(Error::WindowLargerThanMap(
@@ -214,25 +215,25 @@ where
     type Output = ArrayView2<'a, T>;
     type OutputMut = ArrayViewMut2<'a, T>;
 
-    fn slice(&self, data: &'a Array2<T>) -> Option<Self::Output> {
+    fn slice(&self, data: ArrayView2<'a, T>) -> Option<Self::Output> {
         if self.index.in_bounds(&self.bounds) {
             let x0 = self.index.x - self.semi_width.x;
             let x1 = self.index.x + self.semi_width.x + 1;
             let y0 = self.index.y - self.semi_width.y;
             let y1 = self.index.y + self.semi_width.y + 1;
-            Some(data.slice(s![y0..y1, x0..x1]))
+            Some(data.slice_move(s![y0..y1, x0..x1]))
         } else {
             None
         }
     }
 
-    fn slice_mut(&self, data: &'a mut Array2<T>) -> Option<Self::OutputMut> {
+    fn slice_mut(&self, data: ArrayViewMut2<'a, T>) -> Option<Self::OutputMut> {
         if self.index.in_bounds(&self.bounds) {
             let x0 = self.index.x - self.semi_width.x;
             let x1 = self.index.x + self.semi_width.x + 1;
             let y0 = self.index.y - self.semi_width.y;
             let y1 = self.index.y + self.semi_width.y + 1;
-            Some(data.slice_mut(s![y0..y1, x0..x1]))
+            Some(data.slice_move(s![y0..y1, x0..x1]))
         } else {
             None
         }
@@ -261,7 +262,7 @@ where
 }
 
 impl Line {
-    pub(crate) fn from_map<L: Layer, T>(
+    pub(crate) fn from_map(
         map_meta: CellMapMetadata,
         start_parent: Point2<f64>,
         end_parent: Point2<f64>,
@@ -331,7 +332,7 @@ impl Line {
                     .truncate(true)
                     .write(true)
                     .open("line_step_report.json")
-                    .unwrap(),
+                    .map_err(Error::IoError)?,
             ),
         })
     }
@@ -339,10 +340,23 @@ impl Line {
     /// Gets the current cell index to yield, or `None` if at the end of the line
     fn get_current_index(&self) -> Option<Point2<usize>> {
         // Current will be inside the map, since start and end were confirmed to be inside the map
-        // at construction, so simply cast, then convert from bounds to index
-        let current_map_isize = self.current_map?.map(|e| e as isize);
+        // at construction, so simply convert from bounds to index
+        let current_map_isize = self.cell_of(self.current_map?);
         self.map_meta.cell_bounds.get_index(current_map_isize)
     }
+
+    /// Gets the map-frame cell containing a point on the line.
+    ///
+    /// The start point may lie on a cell boundary, so it's classified using the map's boundary
+    /// rule, matching [`CellMap::index()`](crate::CellMap::index). Later points are always moved
+    /// past a boundary by [`Slicer::advance()`], so they can simply be floored.
+    fn cell_of(&self, current_map: Point2<f64>) -> Point2<isize> {
+        if current_map == self.start_map {
+            self.map_meta.map_point_to_cell(current_map)
+        } else {
+            current_map.map(|e| e.floor() as isize)
+        }
+    }
 }
 
 impl<'a, L, T> Slicer<'a, L, T> for Line
@@ -354,38 +368,40 @@ where
 
     type OutputMut = &'a mut T;
 
-    fn slice(&self, data: &'a Array2<T>) -> Option<Self::Output> {
+    fn slice(&self, data: ArrayView2<'a, T>) -> Option<Self::Output> {
         // Get the index
         let index = self.get_current_index()?;
 
-        data.get(index.as_array2_index())
+        IndexLonger::get(&data, index.as_array2_index())
     }
 
-    fn slice_mut(&self, data: &'a mut Array2<T>) -> Option<Self::OutputMut> {
+    fn slice_mut(&self, data: ArrayViewMut2<'a, T>) -> Option<Self::OutputMut> {
         // Get the index
         let index = self.get_current_index()?;
 
-        data.get_mut(index.as_array2_index())
+        get_mut(data, index.as_array2_index())
     }
 
     fn advance(&mut self) {
-        // Get the index of the current position, or just return if we're at the end
-        let curr_index = match self.get_current_index() {
-            Some(i) => i,
-            None => return,
+        // Get the current position, or just return if we're at the end
+        let current_map = match (self.current_map, self.get_current_index()) {
+            (Some(c), Some(_)) => c,
+            _ => return,
         };
 
+        // The map-frame corner of the current cell, which unlike the index includes the offset of
+        // the map's bounds
+        let curr_cell = self.cell_of(current_map).map(|e| e as f64);
+
         // Calculate the param value, i.e. how far along the line we are. This will be used to
         // check if we are beyond the end of the line
-        let param = (self.current_map.unwrap() - self.start_map).norm()
-            / (self.end_map - self.start_map).norm();
+        let param = (current_map - self.start_map).norm() / (self.end_map - self.start_map).norm();
 
         // Calculate the changes in the line parameter needed to reach the next x and y grid line
         // respectively. Also add on the cell boundary precision to ensure that we will actually
         // move over the cell boundary line.
-        let delta_param: Vector2<f64> = ((curr_index.cast() + self.dir_sign)
-            - self.current_map.unwrap())
-        .component_div(&self.dir)
+        let delta_param: Vector2<f64> = ((curr_cell + self.dir_sign) - current_map)
+            .component_div(&self.dir)
             + Vector2::from_element(self.map_meta.cell_boundary_precision);
 
         // Whichever component of delta is smaller is what we need to advance along the line by.
@@ -398,7 +414,7 @@ where
         }
         // Otherwise, move current to current + dir*delta
         else {
-            self.current_map = Some(self.current_map.unwrap() + (self.dir * delta));
+            self.current_map = Some(current_map + (self.dir * delta));
         }
 
         // Write new step report to file
@@ -418,14 +434,13 @@ where
                 delta: delta_param,
             };
 
-            let val = serde_json::to_string_pretty(&rpt).unwrap();
-
-            writeln!(
-                std::sync::Arc::<std::fs::File>::get_mut(&mut self.step_report_file).unwrap(),
-                "{},",
-                val
-            )
-            .unwrap();
+            // Failing to write a report shouldn't stop the iteration, so errors are ignored
+            if let (Ok(val), Some(file)) = (
+                serde_json::to_string_pretty(&rpt),
+                std::sync::Arc::<std::fs::File>::get_mut(&mut self.step_report_file),
+            ) {
+                let _ = writeln!(file, "{},", val);
+            }
         }
     }
 
@@ -437,3 +452,22 @@ where
         self.current_map = Some(self.start_map)
     }
 }
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Converts `data` into a mutable reference to the element at `index`, or `None` if the index is
+/// outside the view.
+///
+/// The bounds check is done here since [`IndexLonger::get`] on mutable views asserts that the
+/// index is in bounds in debug builds rather than returning `None`.
+fn get_mut<T>(data: ArrayViewMut2<'_, T>, index: [usize; 2]) -> Option<&'_ mut T> {
+    let (rows, cols) = data.dim();
+
+    if index[0] < rows && index[1] < cols {
+        IndexLonger::get(data, index)
+    } else {
+        None
+    }
+}
@@ -5,7 +5,7 @@
 // ------------------------------------------------------------------------------------------------
 
 use super::*;
-use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+use crate::{cell_map::Bounds, test_utils::TestLayers, CellMap, CellMapParams};
 
 /// Check that iterator constructors return the right ok or error.
 #[test]
@@ -66,6 +66,11 @@ fn counts() -> Result<(), Error> {
     assert_eq!(map.window_iter(Vector2::new(2, 2))?.count(), 3);
     assert_eq!(map.window_iter(Vector2::new(2, 2))?.count(), 3);
 
+    // Single layer iterators are Copy, so can be consumed more than once
+    let iter = map.iter().layer(TestLayers::Layer1);
+    assert_eq!(iter.count(), 25);
+    assert_eq!(iter.count(), 25);
+
     Ok(())
 }
 
@@ -164,5 +169,23 @@ fn line() -> Result<(), Error> {
 
     assert_eq!(indexes, vec![(4, 2), (3, 2), (3, 1), (2, 1), (1, 1)]);
 
+    // Lines in maps whose bounds don't start at zero, including negative map-frame positions
+    let map = CellMap::<TestLayers, f64>::new_from_elem(
+        CellMapParams {
+            cell_size: Vector2::new(1.0, 1.0),
+            cell_bounds: Bounds::new((-3, 3), (-3, 3)).unwrap(),
+            ..Default::default()
+        },
+        1.0,
+    );
+    let indexes: Vec<(usize, usize)> = map
+        .line_iter(Point2::new(0.5, 0.5), Point2::new(-2.5, 0.5))?
+        .layer(TestLayers::Layer0)
+        .indexed()
+        .map(|((_, i), _)| (i.x, i.y))
+        .collect();
+
+    assert_eq!(indexes, vec![(3, 3), (2, 3), (1, 3), (0, 3)]);
+
     Ok(())
 }
@@ -29,7 +29,55 @@
 /// }
 /// ```
 ///
+/// # Groups
+///
+/// Related layers can be put in named groups with the `#[layer(group = "name")]` attribute, so
+/// that they can be operated on together, for example with
+/// [`CellMapIter::group()`](crate::iterators::CellMapIter::group) or
+/// [`CellMap::clear_group()`]. A layer can be in any number of groups.
+///
+/// ```
+/// use cell_map::Layer;
+///
+/// #[derive(Layer, Clone, Debug, PartialEq)]
+/// enum MyLayer {
+///     #[layer(group = "terrain")]
+///     Height,
+///     #[layer(group = "terrain", group = "planning")]
+///     Gradient,
+///     #[layer(group = "planning")]
+///     Cost,
+/// }
+///
+/// assert_eq!(MyLayer::in_group("terrain"), vec![MyLayer::Height, MyLayer::Gradient]);
+/// assert_eq!(MyLayer::Cost.groups(), &["planning"]);
+/// ```
+///
+/// # Default values
+///
+/// The value that a layer's cells are reset to by [`CellMap::reset_layer()`] can be set with the
+/// `#[layer(default = value)]` attribute, where `value` is an `f64` expression. Layers without a
+/// default are reset to `T::default()`.
+///
+/// ```
+/// use cell_map::Layer;
+///
+/// #[derive(Layer, Clone)]
+/// enum MyLayer {
+///     Height,
+///     #[layer(default = 0.5)]
+///     Occupancy,
+///     #[layer(group = "planning", default = f64::INFINITY)]
+///     Distance,
+/// }
+///
+/// assert_eq!(MyLayer::Height.default_value(), None);
+/// assert_eq!(MyLayer::Distance.default_value(), Some(f64::INFINITY));
+/// ```
+///
 /// [`CellMap`]: crate::CellMap
+/// [`CellMap::clear_group()`]: crate::CellMap::clear_group
+/// [`CellMap::reset_layer()`]: crate::CellMap::reset_layer
 pub trait Layer: Clone {
     /// Contains the total number of layers possible with this [`Layer`]
     const NUM_LAYERS: usize;
@@ -48,6 +96,83 @@ pub trait Layer: Clone {
     /// If the provided index doesn't match a layer this function will panic.
     fn from_index(index: usize) -> Self;
 
+    /// Maps each layer index into a variant of the layer, returning `None` rather than panicking
+    /// if the index doesn't match a layer.
+    fn try_from_index(index: usize) -> Option<Self> {
+        if index < Self::NUM_LAYERS {
+            Some(Self::from_index(index))
+        } else {
+            None
+        }
+    }
+
     /// Returns a vector of all layers in index order.
     fn all() -> Vec<Self>;
+
+    /// Returns the names of the groups this layer is in.
+    ///
+    /// By default a layer isn't in any groups.
+    fn groups(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Returns the value this layer's cells are reset to, if it has one.
+    ///
+    /// By default a layer has no default value, so its cells are reset to `T::default()`.
+    fn default_value(&self) -> Option<f64> {
+        None
+    }
+
+    /// Returns a vector of all layers in the given group, in index order.
+    fn in_group(group: &str) -> Vec<Self> {
+        Self::all()
+            .into_iter()
+            .filter(|l| l.groups().contains(&group))
+            .collect()
+    }
+}
+
+/// Trait for cell types which can be built from a layer's [`Layer::default_value()`].
+pub trait FromLayerDefault {
+    /// Converts the layer's default value into a cell value.
+    fn from_layer_default(value: f64) -> Self;
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl FromLayerDefault for f64 {
+    fn from_layer_default(value: f64) -> Self {
+        value
+    }
+}
+
+impl FromLayerDefault for f32 {
+    fn from_layer_default(value: f64) -> Self {
+        value as f32
+    }
+}
+
+/// Implements [`FromLayerDefault`] for integer types, which round the default to the nearest
+/// integer, saturating at the bounds of the type.
+macro_rules! impl_from_layer_default_int {
+    ($($t:ty),*) => {
+        $(
+            impl FromLayerDefault for $t {
+                fn from_layer_default(value: f64) -> Self {
+                    value.round() as $t
+                }
+            }
+        )*
+    };
+}
+
+impl_from_layer_default_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+impl FromLayerDefault for bool {
+    /// Any non-zero default is `true`.
+    fn from_layer_default(value: f64) -> Self {
+        value != 0.0
+    }
 }
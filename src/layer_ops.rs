@@ -0,0 +1,405 @@
+//! Provides element-wise arithmetic between layers of a map.
+//!
+//! Simple operations have their own methods, such as [`CellMap::add_assign_layer()`] and
+//! [`CellMap::mul_layer_scalar()`], which work in place on any cell type supporting the operation.
+//! Longer expressions on `f64` layers can be written with a [`LayerExpr`] and evaluated into a
+//! layer with [`CellMap::layer_op()`]:
+//!
+//! ```
+//! use cell_map::{layer_ops::LayerExpr, Bounds, CellMap, CellMapParams, Layer};
+//!
+//! #[derive(Layer, Clone, Debug)]
+//! enum MyLayer {
+//!     Ground,
+//!     Obstacles,
+//!     Height,
+//! }
+//!
+//! let mut map = CellMap::<MyLayer, f64>::new_from_elem(
+//!     CellMapParams {
+//!         cell_bounds: Bounds::new((0, 5), (0, 5)).unwrap(),
+//!         ..Default::default()
+//!     },
+//!     2.0,
+//! );
+//!
+//! map.layer_op(
+//!     MyLayer::Height,
+//!     LayerExpr::layer(MyLayer::Ground) + LayerExpr::layer(MyLayer::Obstacles) * 0.5,
+//! );
+//! assert!(map[MyLayer::Height].iter().all(|&v| v == 3.0));
+//! ```
+//!
+//! Each step of an expression is a single whole-array operation, which `ndarray` vectorises where
+//! it can, rather than a loop over cells.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use ndarray::{Array2, Zip};
+
+use crate::{CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// An element-wise expression over the `f64` layers of a map, see the
+/// [module documentation](self).
+///
+/// Expressions are built from [`LayerExpr::layer()`] and [`LayerExpr::scalar()`] with the `+`,
+/// `-`, `*` and `/` operators, which also accept an `f64` on the right, and with
+/// [`LayerExpr::min()`] and [`LayerExpr::max()`].
+#[derive(Debug, Clone)]
+pub enum LayerExpr<L> {
+    /// The value of each cell in a layer.
+    Layer(L),
+
+    /// The same value for every cell.
+    Scalar(f64),
+
+    /// The sum of two expressions.
+    Add(Box<LayerExpr<L>>, Box<LayerExpr<L>>),
+
+    /// The difference of two expressions.
+    Sub(Box<LayerExpr<L>>, Box<LayerExpr<L>>),
+
+    /// The product of two expressions.
+    Mul(Box<LayerExpr<L>>, Box<LayerExpr<L>>),
+
+    /// The quotient of two expressions.
+    Div(Box<LayerExpr<L>>, Box<LayerExpr<L>>),
+
+    /// The smaller of two expressions, as given by [`f64::min()`].
+    Min(Box<LayerExpr<L>>, Box<LayerExpr<L>>),
+
+    /// The larger of two expressions, as given by [`f64::max()`].
+    Max(Box<LayerExpr<L>>, Box<LayerExpr<L>>),
+
+    /// The negation of an expression.
+    Neg(Box<LayerExpr<L>>),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    /// Adds each value in `src` to the value of the same cell in `dst`.
+    pub fn add_assign_layer(&mut self, dst: L, src: L)
+    where
+        T: AddAssign,
+    {
+        self.zip_layers(dst, src, |d, s| *d += s.clone());
+    }
+
+    /// Subtracts each value in `src` from the value of the same cell in `dst`.
+    pub fn sub_assign_layer(&mut self, dst: L, src: L)
+    where
+        T: SubAssign,
+    {
+        self.zip_layers(dst, src, |d, s| *d -= s.clone());
+    }
+
+    /// Multiplies each value in `dst` by the value of the same cell in `src`.
+    pub fn mul_assign_layer(&mut self, dst: L, src: L)
+    where
+        T: MulAssign,
+    {
+        self.zip_layers(dst, src, |d, s| *d *= s.clone());
+    }
+
+    /// Divides each value in `dst` by the value of the same cell in `src`.
+    pub fn div_assign_layer(&mut self, dst: L, src: L)
+    where
+        T: DivAssign,
+    {
+        self.zip_layers(dst, src, |d, s| *d /= s.clone());
+    }
+
+    /// Sets each value in `dst` to the smaller of it and the value of the same cell in `src`.
+    ///
+    /// If the values can't be compared, such as when either is `NaN`, the value in `dst` is kept.
+    pub fn min_layer(&mut self, dst: L, src: L)
+    where
+        T: PartialOrd,
+    {
+        self.zip_layers(dst, src, |d, s| {
+            if *s < *d {
+                *d = s.clone()
+            }
+        });
+    }
+
+    /// Sets each value in `dst` to the larger of it and the value of the same cell in `src`.
+    ///
+    /// If the values can't be compared, such as when either is `NaN`, the value in `dst` is kept.
+    pub fn max_layer(&mut self, dst: L, src: L)
+    where
+        T: PartialOrd,
+    {
+        self.zip_layers(dst, src, |d, s| {
+            if *s > *d {
+                *d = s.clone()
+            }
+        });
+    }
+
+    /// Limits every value in `layer` to between `min` and `max`.
+    ///
+    /// Values which can't be compared with the limits, such as `NaN`, are unchanged.
+    pub fn clamp_layer(&mut self, layer: L, min: T, max: T)
+    where
+        T: PartialOrd,
+    {
+        self[layer].map_inplace(|v| {
+            if *v < min {
+                *v = min.clone()
+            } else if *v > max {
+                *v = max.clone()
+            }
+        });
+    }
+
+    /// Adds `value` to every cell in `layer`.
+    pub fn add_layer_scalar(&mut self, layer: L, value: T)
+    where
+        T: AddAssign,
+    {
+        self[layer].map_inplace(|v| *v += value.clone());
+    }
+
+    /// Multiplies every cell in `layer` by `value`.
+    pub fn mul_layer_scalar(&mut self, layer: L, value: T)
+    where
+        T: MulAssign,
+    {
+        self[layer].map_inplace(|v| *v *= value.clone());
+    }
+
+    /// Calls `func` with each value in `dst` and the value of the same cell in `src`, which may be
+    /// the same layer.
+    fn zip_layers<F>(&mut self, dst: L, src: L, mut func: F)
+    where
+        F: FnMut(&mut T, &T),
+    {
+        let (dst, src) = (dst.to_index(), src.to_index());
+
+        if dst == src {
+            self.data[dst].map_inplace(|v| {
+                let s = v.clone();
+                func(v, &s)
+            });
+        } else {
+            // Split the layers so that both can be borrowed at once
+            let (dst, src) = if dst < src {
+                let (lo, hi) = self.data.split_at_mut(src);
+                (&mut lo[dst], &hi[0])
+            } else {
+                let (lo, hi) = self.data.split_at_mut(dst);
+                (&mut hi[0], &lo[src])
+            };
+
+            Zip::from(dst).and(src).for_each(func);
+        }
+    }
+}
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Evaluates `expr` for every cell and writes the result into `dst`, which may also appear in
+    /// the expression.
+    pub fn layer_op(&mut self, dst: L, expr: LayerExpr<L>) {
+        let result = self.eval_layer_expr(&expr);
+        self[dst].assign(&result);
+    }
+
+    /// Evaluates `expr` for every cell, returning the results as a new array with the same shape
+    /// as a layer.
+    pub fn eval_layer_expr(&self, expr: &LayerExpr<L>) -> Array2<f64> {
+        let binary = |a: &LayerExpr<L>, b: &LayerExpr<L>, func: fn(f64, f64) -> f64| {
+            // Avoid building an array for scalars
+            match (a, b) {
+                (a, LayerExpr::Scalar(b)) => self.eval_layer_expr(a).mapv_into(|a| func(a, *b)),
+                (LayerExpr::Scalar(a), b) => self.eval_layer_expr(b).mapv_into(|b| func(*a, b)),
+                (a, b) => {
+                    let mut a = self.eval_layer_expr(a);
+                    Zip::from(&mut a)
+                        .and(&self.eval_layer_expr(b))
+                        .for_each(|a, &b| *a = func(*a, b));
+                    a
+                }
+            }
+        };
+
+        match expr {
+            LayerExpr::Layer(layer) => self[layer.clone()].to_owned(),
+            LayerExpr::Scalar(value) => Array2::from_elem(self.cell_bounds().get_shape(), *value),
+            LayerExpr::Add(a, b) => binary(a, b, |a, b| a + b),
+            LayerExpr::Sub(a, b) => binary(a, b, |a, b| a - b),
+            LayerExpr::Mul(a, b) => binary(a, b, |a, b| a * b),
+            LayerExpr::Div(a, b) => binary(a, b, |a, b| a / b),
+            LayerExpr::Min(a, b) => binary(a, b, f64::min),
+            LayerExpr::Max(a, b) => binary(a, b, f64::max),
+            LayerExpr::Neg(a) => -self.eval_layer_expr(a),
+        }
+    }
+}
+
+impl<L> LayerExpr<L> {
+    /// Returns an expression for the values of `layer`.
+    pub fn layer(layer: L) -> Self {
+        Self::Layer(layer)
+    }
+
+    /// Returns an expression which is `value` in every cell.
+    pub fn scalar(value: f64) -> Self {
+        Self::Scalar(value)
+    }
+
+    /// Returns an expression for the smaller of `self` and `other` in each cell.
+    pub fn min<E: Into<Self>>(self, other: E) -> Self {
+        Self::Min(Box::new(self), Box::new(other.into()))
+    }
+
+    /// Returns an expression for the larger of `self` and `other` in each cell.
+    pub fn max<E: Into<Self>>(self, other: E) -> Self {
+        Self::Max(Box::new(self), Box::new(other.into()))
+    }
+}
+
+impl<L> From<f64> for LayerExpr<L> {
+    fn from(value: f64) -> Self {
+        Self::Scalar(value)
+    }
+}
+
+impl<L> Neg for LayerExpr<L> {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self::Neg(Box::new(self))
+    }
+}
+
+/// Implements a binary operator for [`LayerExpr`], with either another expression or an `f64` on
+/// the right.
+macro_rules! impl_layer_expr_op {
+    ($trait:ident, $method:ident, $variant:ident) => {
+        impl<L, E> $trait<E> for LayerExpr<L>
+        where
+            E: Into<LayerExpr<L>>,
+        {
+            type Output = Self;
+
+            fn $method(self, rhs: E) -> Self::Output {
+                Self::$variant(Box::new(self), Box::new(rhs.into()))
+            }
+        }
+    };
+}
+
+impl_layer_expr_op!(Add, add, Add);
+impl_layer_expr_op!(Sub, sub, Sub);
+impl_layer_expr_op!(Mul, mul, Mul);
+impl_layer_expr_op!(Div, div, Div);
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    fn new_map() -> CellMap<TestLayers, f64> {
+        let mut map = CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 3), (0, 2)).unwrap(),
+                ..Default::default()
+            },
+            2.0,
+        );
+
+        for ((_, index), v) in map.iter_mut().layer(TestLayers::Layer1).indexed() {
+            *v = index.x as f64;
+        }
+
+        map
+    }
+
+    #[test]
+    fn layer_methods() {
+        let mut map = new_map();
+
+        map.add_assign_layer(TestLayers::Layer0, TestLayers::Layer1);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(2, 1))], 4.0);
+
+        map.mul_assign_layer(TestLayers::Layer0, TestLayers::Layer0);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(2, 1))], 16.0);
+
+        map.sub_assign_layer(TestLayers::Layer1, TestLayers::Layer2);
+        map.div_assign_layer(TestLayers::Layer1, TestLayers::Layer2);
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(0, 0))], -1.0);
+
+        map.mul_layer_scalar(TestLayers::Layer2, 3.0);
+        map.add_layer_scalar(TestLayers::Layer2, -1.0);
+        assert!(map[TestLayers::Layer2].iter().all(|&v| v == 5.0));
+
+        // NaN values in the source are ignored by min and max
+        map[(TestLayers::Layer2, Point2::new(1, 0))] = f64::NAN;
+        map.min_layer(TestLayers::Layer0, TestLayers::Layer2);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(0, 0))], 4.0);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(1, 0))], 9.0);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(2, 0))], 5.0);
+
+        map.max_layer(TestLayers::Layer1, TestLayers::Layer0);
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(2, 1))], 5.0);
+
+        map.clamp_layer(TestLayers::Layer2, 0.0, 4.5);
+        assert_eq!(map[(TestLayers::Layer2, Point2::new(0, 0))], 4.5);
+        assert!(map[(TestLayers::Layer2, Point2::new(1, 0))].is_nan());
+        map.clamp_layer(TestLayers::Layer1, 4.5, 10.0);
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(0, 1))], 4.5);
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(1, 0))], 9.0);
+    }
+
+    #[test]
+    fn layer_expressions() {
+        let mut map = new_map();
+
+        map.layer_op(
+            TestLayers::Layer2,
+            LayerExpr::layer(TestLayers::Layer0) + LayerExpr::layer(TestLayers::Layer1) * 0.5,
+        );
+        assert_eq!(map[(TestLayers::Layer2, Point2::new(0, 0))], 2.0);
+        assert_eq!(map[(TestLayers::Layer2, Point2::new(2, 1))], 3.0);
+
+        // The destination may appear in the expression
+        map.layer_op(
+            TestLayers::Layer2,
+            (-LayerExpr::layer(TestLayers::Layer2) / 2.0 - 1.0)
+                .max(LayerExpr::layer(TestLayers::Layer1) - 4.5)
+                .min(LayerExpr::scalar(-2.1)),
+        );
+        assert_eq!(map[(TestLayers::Layer2, Point2::new(0, 0))], -2.1);
+        assert_eq!(map[(TestLayers::Layer2, Point2::new(1, 0))], -2.25);
+        assert_eq!(map[(TestLayers::Layer2, Point2::new(2, 0))], -2.5);
+
+        let scaled =
+            map.eval_layer_expr(&(LayerExpr::scalar(2.0) * LayerExpr::layer(TestLayers::Layer1)));
+        assert_eq!(scaled[[1, 2]], 4.0);
+    }
+}
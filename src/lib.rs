@@ -119,6 +119,16 @@
 #![warn(missing_docs)]
 #![warn(missing_copy_implementations)]
 #![warn(missing_debug_implementations)]
+// With the `strict` feature internal failures must be returned as `Error`s rather than panicking
+#![cfg_attr(
+    all(feature = "strict", not(test)),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::unreachable
+    )
+)]
 
 // ------------------------------------------------------------------------------------------------
 // MODULES
@@ -127,24 +137,105 @@
 #[macro_use]
 mod macros;
 
+pub mod access;
+pub mod analysis;
+pub mod area;
+pub mod atomic;
+pub mod automaton;
+pub mod bits;
 pub(crate) mod cell_map;
 pub mod cell_map_file;
+pub mod cell_view;
+pub mod clusters;
+pub mod collision;
+#[cfg(feature = "arrow")]
+pub mod columnar;
+pub mod comparison;
+pub mod cost;
+#[cfg(feature = "counters")]
+pub mod counters;
+pub mod coverage;
+pub mod csv;
+pub mod decay;
+pub mod diffusion;
 pub mod error;
 pub(crate) mod extensions;
+pub mod filters;
+pub mod fixed;
+#[cfg(feature = "random")]
+pub mod generators;
+pub mod graph;
+pub mod grow;
+pub mod hash;
+#[cfg(feature = "hdf5")]
+pub mod hdf5_io;
+pub mod hydrology;
 pub mod iterators;
 mod layer;
+pub mod layer_ops;
+#[cfg(feature = "cbor")]
+pub mod map_log;
 mod map_metadata;
+mod math;
+pub mod mesh;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod occupancy;
+pub mod out_of_bounds;
+pub mod point_cloud;
+pub mod potential;
+pub mod profile;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+pub mod queue;
+pub mod registration;
+#[cfg(feature = "json")]
+pub mod registry;
+pub mod render;
+pub mod rle;
+pub mod rolling;
+#[cfg(feature = "random")]
+pub mod sampling;
+pub mod server;
+pub mod smoothing;
+pub mod solar;
+pub mod spatial;
+pub mod stack;
+pub mod summary;
+#[cfg(feature = "cbor")]
+pub mod sync;
+pub mod temporal;
 #[cfg(test)]
 mod tests;
+#[cfg(feature = "tiles")]
+pub mod tile_store;
+pub mod tiling;
+pub mod traversability;
+pub mod updates;
+pub mod vectorise;
+pub mod view;
+pub mod viewshed;
+pub mod warp;
+pub mod wavefront;
+pub mod wrapping;
 
 // ------------------------------------------------------------------------------------------------
 // EXPORTS
 // ------------------------------------------------------------------------------------------------
 
-pub use crate::cell_map::{Bounds, CellMap, CellMapParams};
-pub use cell_map_macro::Layer;
+pub use crate::cell_map::{
+    nd_to_xy, xy_to_nd, BoundaryRule, Bounds, CellMap, CellMapParams, CellOrigin, GridConvention,
+    YAxis,
+};
+pub use cell_map_macro::{CellView, Layer};
+pub use cell_view::CellView;
 pub use error::Error;
-pub use layer::Layer;
+pub use layer::{FromLayerDefault, Layer};
+#[cfg(feature = "mmap")]
+pub use mmap::MmapCellMap;
+#[cfg(feature = "tiles")]
+pub use tile_store::{TileStore, TileStoreParams};
+pub use view::{CellMapView, CellMapViewMut};
 
 // ------------------------------------------------------------------------------------------------
 // USEFUL TEST UTILITIES
@@ -154,7 +245,11 @@ pub use layer::Layer;
 use serde::Serialize;
 
 /// Writes the given map to the given location, prepending "_debug_" to the name.
+///
+/// This is only used while debugging, so panics if the map can't be written even with the
+/// `strict` feature.
 #[cfg(feature = "debug_maps")]
+#[allow(clippy::expect_used)]
 pub fn write_debug_map<L: Layer + Serialize, T: Serialize + Clone>(
     map: &CellMap<L, T>,
     name: &str,
@@ -170,11 +265,11 @@ pub fn write_debug_map<L: Layer + Serialize, T: Serialize + Clone>(
 #[macro_use]
 pub(crate) mod test_utils {
 
-    use serde::Serialize;
+    use serde::{Deserialize, Serialize};
 
     use crate::Layer;
 
-    #[derive(Clone, Copy, Debug, Serialize)]
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
     #[allow(dead_code)]
     pub enum TestLayers {
         Layer0,
@@ -211,5 +306,19 @@ pub(crate) mod test_utils {
         fn all() -> Vec<Self> {
             vec![Self::Layer0, Self::Layer1, Self::Layer2]
         }
+
+        fn groups(&self) -> &'static [&'static str] {
+            match self {
+                Self::Layer0 | Self::Layer1 => &["group01"],
+                Self::Layer2 => &["group2"],
+            }
+        }
+
+        fn default_value(&self) -> Option<f64> {
+            match self {
+                Self::Layer2 => Some(-1.0),
+                _ => None,
+            }
+        }
     }
 }
@@ -4,7 +4,7 @@
 #[allow(unused_macros)]
 macro_rules! assert_f64_eq {
     ($x:expr, $y:expr) => {
-        if ($x - $y).abs() > std::f64::EPSILON {
+        if ($x - $y).abs() > f64::EPSILON {
             panic!("{} != {}", $x, $y);
         }
     };
@@ -30,3 +30,41 @@ macro_rules! assert_f64_iter_eq {
         }
     };
 }
+
+/// Macro which adds to one of the internal performance counters, if the `counters` feature is
+/// enabled. With the feature disabled this expands to nothing.
+///
+/// `count!(ItemsIterated)` adds one to the counter, while `count!(LayerAllocations, n)` adds `n`.
+macro_rules! count {
+    ($counter:ident) => {
+        count!($counter, 1)
+    };
+    ($counter:ident, $n:expr) => {
+        #[cfg(feature = "counters")]
+        crate::counters::add(crate::counters::Counter::$counter, $n as u64)
+    };
+}
+
+/// Macro which enters a `tracing` span lasting until the end of the enclosing scope, if the
+/// `tracing` feature is enabled. With the feature disabled this expands to nothing.
+///
+/// Takes the same arguments as `tracing::debug_span!`, for example
+/// `trace_span!("merge", num_cells = n)`.
+macro_rules! trace_span {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($($arg)*).entered();
+    };
+}
+
+/// Macro which emits a `tracing` event, if the `tracing` feature is enabled. With the feature
+/// disabled this expands to nothing.
+///
+/// Takes the same arguments as `tracing::debug!`.
+#[allow(unused_macros)]
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
@@ -0,0 +1,261 @@
+//! Provides the [`MapLog`] type, which records each [`UpdateQueue`] applied to a map to a file so
+//! that the same sequence of updates can be replayed later.
+//!
+//! This makes it possible to reproduce the exact state of a map from a field trial, for example
+//! when debugging a mapping issue, by replaying the log into a map in the same initial state with
+//! [`MapLog::replay_into()`].
+//!
+//! Logs are stored as a sequence of CBOR items, one per applied queue, so that every value,
+//! including `NaN`, is replayed exactly. Each entry is flushed as soon as it's written so that
+//! logs survive the process being killed. This module requires the `cbor` feature.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    marker::PhantomData,
+    path::Path,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{updates::UpdateQueue, CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Records the updates applied to a map to a log file.
+#[derive(Debug)]
+pub struct MapLog<L, T> {
+    writer: BufWriter<File>,
+    num_entries: usize,
+    layer_type: PhantomData<L>,
+    data_type: PhantomData<T>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> MapLog<L, T>
+where
+    L: Layer + Serialize,
+    T: Clone + Serialize,
+{
+    /// Starts recording a new log to the given path, overwriting any existing file.
+    pub fn record<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(path)
+            .map_err(Error::IoError)?;
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+            num_entries: 0,
+            layer_type: PhantomData,
+            data_type: PhantomData,
+        })
+    }
+
+    /// Returns the number of entries recorded so far.
+    pub fn num_entries(&self) -> usize {
+        self.num_entries
+    }
+
+    /// Applies all updates in `queue` to `map` using [`UpdateQueue::apply_all()`], recording them
+    /// in the log if they were applied successfully.
+    pub fn apply(
+        &mut self,
+        queue: &mut UpdateQueue<L, T>,
+        map: &mut CellMap<L, T>,
+    ) -> Result<(), Error> {
+        // Serialise before applying, since applying empties the queue
+        let entry = encode(queue)?;
+        queue.apply_all(map)?;
+        self.write_entry(&entry)
+    }
+
+    /// Records `queue` in the log without applying it, for updates which have been applied to the
+    /// map by some other means.
+    pub fn log(&mut self, queue: &UpdateQueue<L, T>) -> Result<(), Error> {
+        let entry = encode(queue)?;
+        self.write_entry(&entry)
+    }
+
+    fn write_entry(&mut self, entry: &[u8]) -> Result<(), Error> {
+        self.writer.write_all(entry).map_err(Error::IoError)?;
+        self.writer.flush().map_err(Error::IoError)?;
+        self.num_entries += 1;
+
+        Ok(())
+    }
+}
+
+impl<L, T> MapLog<L, T>
+where
+    L: Layer + DeserializeOwned,
+    T: Clone + DeserializeOwned,
+{
+    /// Reads every entry in the log at the given path.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<UpdateQueue<L, T>>, Error> {
+        let file = File::open(path).map_err(Error::IoError)?;
+        let mut reader = BufReader::new(file);
+        let mut entries = Vec::new();
+
+        while !reader.fill_buf().map_err(Error::IoError)?.is_empty() {
+            let entry =
+                ciborium::from_reader(&mut reader).map_err(|e| Error::CborError(e.to_string()))?;
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
+    /// Replays the log at the given path into `map`, applying each entry in the order it was
+    /// recorded. Returns the number of entries applied.
+    ///
+    /// To reproduce the original map exactly, `map` must be in the same state as the original map
+    /// was when recording started.
+    pub fn replay_into<P: AsRef<Path>>(path: P, map: &mut CellMap<L, T>) -> Result<usize, Error> {
+        trace_span!("replay_map_log", path = %path.as_ref().display());
+        let entries = Self::read(path)?;
+        let num_entries = entries.len();
+
+        for mut queue in entries {
+            queue.apply_all(map)?;
+        }
+
+        Ok(num_entries)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Encodes a single log entry.
+fn encode<L, T>(queue: &UpdateQueue<L, T>) -> Result<Vec<u8>, Error>
+where
+    L: Layer + Serialize,
+    T: Clone + Serialize,
+{
+    let mut entry = Vec::new();
+    ciborium::into_writer(queue, &mut entry).map_err(|e| Error::CborError(e.to_string()))?;
+    Ok(entry)
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point2, Vector2};
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn record_and_replay() {
+        let new_map = || {
+            CellMap::<TestLayers, f64>::new_from_elem(
+                CellMapParams {
+                    cell_bounds: Bounds::new((0, 8), (-4, 4)).unwrap(),
+                    cell_size: Vector2::new(0.2, 0.2),
+                    ..Default::default()
+                },
+                0.0,
+            )
+        };
+        let path = std::env::temp_dir().join("cell_map_record_and_replay.cbor");
+
+        let mut map = new_map();
+        let mut log = MapLog::record(&path).unwrap();
+
+        for i in 0..5 {
+            let mut queue = UpdateQueue::new();
+            queue.fill_region(
+                TestLayers::Layer0,
+                Bounds::new((i, i + 3), (-i, 1)).unwrap(),
+                i as f64,
+            );
+            queue.insert_points(
+                TestLayers::Layer1,
+                vec![(Point2::new(0.1 * i as f64, -0.3), 1.0 + i as f64)],
+            );
+            log.apply(&mut queue, &mut map).unwrap();
+        }
+
+        // Failed updates aren't logged
+        let mut queue = UpdateQueue::new();
+        queue.set_cells(TestLayers::Layer2, vec![(Point2::new(8, 0), 1.0)]);
+        assert!(log.apply(&mut queue, &mut map).is_err());
+        assert_eq!(log.num_entries(), 5);
+        drop(log);
+
+        let mut replayed = new_map();
+        assert_eq!(MapLog::replay_into(&path, &mut replayed).unwrap(), 5);
+        assert!(replayed.iter().eq(map.iter()));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replay_is_exact() {
+        let new_map = || {
+            CellMap::<TestLayers, f64>::new_from_elem(
+                CellMapParams {
+                    cell_bounds: Bounds::new((0, 4), (0, 4)).unwrap(),
+                    ..Default::default()
+                },
+                0.0,
+            )
+        };
+        let path = std::env::temp_dir().join("cell_map_replay_is_exact.cbor");
+
+        // Values which don't survive a round trip through JSON
+        let values = [
+            f64::NAN,
+            -f64::NAN,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            -0.0,
+            0.1 + 0.2,
+            1.0 / 3.0,
+            f64::MIN_POSITIVE / 3.0,
+            f64::MAX,
+            std::f64::consts::PI * 1e-300,
+            f64::from_bits(0x7ff8_dead_beef_0001),
+        ];
+
+        let mut map = new_map();
+        let mut log = MapLog::record(&path).unwrap();
+        for (i, &value) in values.iter().enumerate() {
+            let mut queue = UpdateQueue::new();
+            queue.set_cells(TestLayers::Layer0, vec![(Point2::new(i % 4, i / 4), value)]);
+            log.apply(&mut queue, &mut map).unwrap();
+        }
+        drop(log);
+
+        let mut replayed = new_map();
+        assert_eq!(
+            MapLog::replay_into(&path, &mut replayed).unwrap(),
+            values.len()
+        );
+        for (a, b) in replayed
+            .iter()
+            .layer(TestLayers::Layer0)
+            .zip(map.iter().layer(TestLayers::Layer0))
+        {
+            assert_eq!(a.to_bits(), b.to_bits());
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
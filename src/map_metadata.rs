@@ -7,10 +7,13 @@
 // IMPORTS
 // ------------------------------------------------------------------------------------------------
 
-use nalgebra::{Affine2, Isometry2, Matrix3, Point2, Vector2};
+use nalgebra::{Affine2, Isometry2, Matrix3, Point2, Translation2, UnitComplex, Vector2};
 use serde::{Deserialize, Serialize};
 
-use crate::{cell_map::Bounds, CellMapParams};
+use crate::{
+    cell_map::{BoundaryRule, Bounds, CellOrigin, GridConvention, YAxis},
+    math, CellMapParams,
+};
 
 // ------------------------------------------------------------------------------------------------
 // STRUCTS
@@ -47,6 +50,9 @@ pub(crate) struct CellMapMetadata {
     /// This value defaults to `1e-10`.
     pub cell_boundary_precision: f64,
 
+    /// How positions near cell boundaries are assigned to cells.
+    pub boundary_rule: BoundaryRule,
+
     /// The transform between the map's frame and the parent frame. This is the transform that will
     /// be applied when going from a cell index to a parent-frame position.
     pub to_parent: Affine2<f64>,
@@ -81,6 +87,8 @@ impl CellMapMetadata {
     /// This method won't panic if `index` is outside the map, but it's result can't be guaranteed
     /// to be a position in the map.
     pub fn position_unchecked(&self, index: Point2<usize>) -> Point2<f64> {
+        count!(PositionTransforms);
+
         // Get the centre of the cell, which is + 0.5 cells in the x and y direction, also account
         // for the bounds by adding the lower bound
         let index_centre = index.cast()
@@ -91,6 +99,20 @@ impl CellMapMetadata {
         self.to_parent.transform_point(&index_centre)
     }
 
+    /// Returns the parent-frame positions of the corners of the given cell index, without
+    /// checking that the `index` is inside the map, see [`CellMap::cell_corners()`].
+    ///
+    /// [`CellMap::cell_corners()`]: crate::CellMap::cell_corners
+    pub fn cell_corners_unchecked(&self, index: Point2<usize>) -> [Point2<f64>; 4] {
+        let corner =
+            index.cast() + Vector2::new(self.cell_bounds.x.0 as f64, self.cell_bounds.y.0 as f64);
+
+        [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)].map(|(dx, dy)| {
+            self.to_parent
+                .transform_point(&(corner + Vector2::new(dx, dy)))
+        })
+    }
+
     /// Get the cell index of the given poisition.
     ///
     /// Returns `None` if the given `position` is not inside the map.
@@ -119,6 +141,8 @@ impl CellMapMetadata {
     /// index into the map is not guaranteed to be safe. It is possible for this function to return
     /// a negative index value, which would indicate that the cell is outside the map.
     pub unsafe fn index_unchecked(&self, position: Point2<f64>) -> Point2<isize> {
+        count!(IndexTransforms);
+
         let cell = self.get_cell(position);
 
         // What we have now is a "point", i.e. a map-frame point relative to the map origin. But if
@@ -129,42 +153,51 @@ impl CellMapMetadata {
 
     /// Gets the map-origin relative cell location of the given position.
     pub fn get_cell(&self, position: Point2<f64>) -> Point2<isize> {
-        let els: Vec<isize> = self
-            .to_parent
-            .inverse_transform_point(&position)
-            .iter()
-            .zip(self.cell_size.iter())
-            .map(|(&v, &s)| {
-                let v_floor = v as isize;
-                let v_next_floor = (s * self.cell_boundary_precision + v) as isize;
-
-                if v_floor != v_next_floor {
-                    v_next_floor
-                } else {
-                    v_floor
-                }
-            })
-            .collect();
-
-        Point2::new(els[0], els[1])
+        self.map_point_to_cell(self.to_parent.inverse_transform_point(&position))
+    }
+
+    /// Gets the cell containing the given map-frame point, using the map's boundary rule.
+    pub fn map_point_to_cell(&self, point: Point2<f64>) -> Point2<isize> {
+        Point2::new(
+            self.boundary_rule
+                .cell(point.x, self.cell_size.x, self.cell_boundary_precision),
+            self.boundary_rule
+                .cell(point.y, self.cell_size.y, self.cell_boundary_precision),
+        )
     }
 
     pub(crate) fn calc_to_parent(
         position: Vector2<f64>,
         rotation_rad: f64,
         cell_size: Vector2<f64>,
+        convention: GridConvention,
     ) -> Affine2<f64> {
         // First build isometry to convert from the parent to map
-        let isom_from_parent = Isometry2::new(position, rotation_rad);
+        let (sin, cos) = math::sin_cos(rotation_rad);
+        let isom_from_parent = Isometry2::from_parts(
+            Translation2::from(position),
+            UnitComplex::from_cos_sin_unchecked(cos, sin),
+        );
+
+        // Scale transformation matrix, based on cell size. A downwards y axis is a negative scale.
+        let y_scale = match convention.y_axis {
+            YAxis::Up => cell_size.y,
+            YAxis::Down => -cell_size.y,
+        };
+        let scale = Matrix3::new(cell_size.x, 0.0, 0.0, 0.0, y_scale, 0.0, 0.0, 0.0, 1.0);
 
-        // Scale transformation matrix, based on cell size.
-        let scale = Matrix3::new(cell_size.x, 0.0, 0.0, 0.0, cell_size.y, 0.0, 0.0, 0.0, 1.0);
+        // Offset of the origin within the cell, in cells
+        let offset = match convention.origin {
+            CellOrigin::Corner => 0.0,
+            CellOrigin::Centre => -0.5,
+        };
+        let origin = Matrix3::new(1.0, 0.0, offset, 0.0, 1.0, offset, 0.0, 0.0, 1.0);
 
         // Build the affine by multiplying isom and scale, which will take the translation and
         // rotation of isom and scale it by the cell size. Scale must come first so that the isom,
         // which is in parent coordinates, is not scaled itself. Get the inverse of
         // isom_from_parent to get the to_parent
-        Affine2::from_matrix_unchecked(isom_from_parent.to_matrix() * scale)
+        Affine2::from_matrix_unchecked(isom_from_parent.to_matrix() * scale * origin)
     }
 }
 
@@ -174,6 +207,7 @@ impl From<CellMapParams> for CellMapMetadata {
             params.position_in_parent,
             params.rotation_in_parent_rad,
             params.cell_size,
+            params.convention,
         );
 
         Self {
@@ -181,7 +215,52 @@ impl From<CellMapParams> for CellMapMetadata {
             cell_bounds: params.cell_bounds,
             num_cells: params.cell_bounds.get_num_cells(),
             cell_boundary_precision: params.cell_boundary_precision,
+            boundary_rule: params.boundary_rule,
             to_parent,
         }
     }
 }
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_fractional_positions() {
+        for &boundary_rule in &[
+            BoundaryRule::EpsilonSnap,
+            BoundaryRule::HalfOpen,
+            BoundaryRule::RoundNearest,
+        ] {
+            let metadata = CellMapMetadata::from(CellMapParams {
+                cell_bounds: Bounds::new((-4, 4), (-4, 4)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.25),
+                boundary_rule,
+                ..Default::default()
+            });
+
+            // Positions less than one cell below the origin are in cell -1, not 0
+            let cell = |x, y| metadata.get_cell(Point2::new(x, y));
+            assert_eq!(cell(-0.1, -0.01), Point2::new(-1, -1));
+            assert_eq!(cell(-0.5, -0.25), Point2::new(-1, -1));
+            assert_eq!(cell(-0.6, -0.3), Point2::new(-2, -2));
+            assert_eq!(cell(-1.9, 0.1), Point2::new(-4, 0));
+            assert_eq!(cell(0.3, 0.2), Point2::new(0, 0));
+
+            // Which gives the index of the cell relative to the bottom of the bounds
+            assert_eq!(
+                metadata.index(Point2::new(-0.1, -0.01)),
+                Some(Point2::new(3, 3))
+            );
+            assert_eq!(
+                metadata.index(Point2::new(-1.9, -0.9)),
+                Some(Point2::new(0, 0))
+            );
+            assert_eq!(metadata.index(Point2::new(-2.1, 0.0)), None);
+        }
+    }
+}
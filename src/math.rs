@@ -0,0 +1,170 @@
+//! Provides the transcendental functions used by transforms and filters.
+//!
+//! The basic arithmetic operations and `sqrt` are correctly rounded by IEEE 754, and Rust never
+//! fuses a multiply and an add into an FMA instruction unless asked to with `mul_add`, so the only
+//! remaining source of platform differences in the crate's floating-point results is the system
+//! maths library, whose `exp`, `sin` and friends may differ in the last bit between platforms.
+//!
+//! With the `deterministic` feature enabled these functions use the pure Rust `libm` crate
+//! instead, so that maps built by the same sequence of operations are bit-identical, and so have
+//! the same [`CellMap::content_hash()`], on every platform. Without the feature they use the
+//! standard library, which is usually faster.
+//!
+//! Crate code should call these functions rather than the `f64` methods of the same names, and
+//! should not use `mul_add`. Sums are always accumulated sequentially in index order, which
+//! `Iterator::sum` does, rather than with `ndarray`'s `sum`, whose order depends on the memory
+//! layout of the array.
+//!
+//! [`CellMap::content_hash()`]: crate::CellMap::content_hash
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Returns `e^x`.
+#[inline]
+pub(crate) fn exp(x: f64) -> f64 {
+    #[cfg(feature = "deterministic")]
+    {
+        libm::exp(x)
+    }
+    #[cfg(not(feature = "deterministic"))]
+    {
+        x.exp()
+    }
+}
+
+/// Returns the natural logarithm of `x`.
+#[inline]
+pub(crate) fn ln(x: f64) -> f64 {
+    #[cfg(feature = "deterministic")]
+    {
+        libm::log(x)
+    }
+    #[cfg(not(feature = "deterministic"))]
+    {
+        x.ln()
+    }
+}
+
+/// Returns the sine and cosine of `x`, in radians.
+#[inline]
+pub(crate) fn sin_cos(x: f64) -> (f64, f64) {
+    #[cfg(feature = "deterministic")]
+    {
+        libm::sincos(x)
+    }
+    #[cfg(not(feature = "deterministic"))]
+    {
+        x.sin_cos()
+    }
+}
+
+/// Returns the arctangent of `x`, in radians.
+#[inline]
+pub(crate) fn atan(x: f64) -> f64 {
+    #[cfg(feature = "deterministic")]
+    {
+        libm::atan(x)
+    }
+    #[cfg(not(feature = "deterministic"))]
+    {
+        x.atan()
+    }
+}
+
+/// Returns the length of the hypotenuse of a right-angled triangle with sides `x` and `y`.
+#[inline]
+pub(crate) fn hypot(x: f64, y: f64) -> f64 {
+    #[cfg(feature = "deterministic")]
+    {
+        libm::hypot(x, y)
+    }
+    #[cfg(not(feature = "deterministic"))]
+    {
+        x.hypot(y)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point2, Vector2};
+
+    use super::*;
+    use crate::{
+        cell_map::Bounds,
+        filters::{Backend, InflationParams},
+        test_utils::TestLayers,
+        CellMap, CellMapParams,
+    };
+
+    #[test]
+    fn functions() {
+        // The implementations may differ in the last bit, which is the point of the feature
+        assert_f64_eq!(exp(1.0), std::f64::consts::E, 1e-15);
+        assert_f64_eq!(ln(std::f64::consts::E), 1.0, 1e-15);
+        assert_f64_eq!(atan(1.0), std::f64::consts::FRAC_PI_4, 1e-15);
+        assert_eq!(hypot(3.0, 4.0), 5.0);
+
+        let (sin, cos) = sin_cos(std::f64::consts::FRAC_PI_6);
+        assert_f64_eq!(sin, 0.5, 1e-15);
+        assert_f64_eq!(cos, 0.75f64.sqrt(), 1e-15);
+    }
+
+    /// Builds a rotated map and runs it through a transform and the main filters, so that any
+    /// change in their floating-point results changes the hash.
+    fn pipeline_hash() -> u64 {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-8, 8), (-6, 6)).unwrap(),
+                cell_size: Vector2::new(0.1, 0.15),
+                position_in_parent: Vector2::new(1.3, -0.7),
+                rotation_in_parent_rad: 0.7,
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        for i in 0..40 {
+            let (sin, cos) = sin_cos(i as f64 * 0.3);
+            let position = Point2::new(1.3 + 0.05 * i as f64 * cos, -0.7 + 0.04 * i as f64 * sin);
+            if let Some(index) = map.index(position) {
+                map[(TestLayers::Layer0, index)] = 1.0;
+            }
+        }
+
+        map.gaussian_filter(TestLayers::Layer0, TestLayers::Layer1, 0.2)
+            .unwrap();
+        map.inflate(
+            TestLayers::Layer0,
+            TestLayers::Layer2,
+            |v| v > 0.5,
+            &InflationParams {
+                inscribed_radius: 0.1,
+                inflation_radius: 0.5,
+                cost_scaling_factor: 3.0,
+            },
+            &Backend::Cpu,
+        )
+        .unwrap();
+
+        map.content_hash()
+    }
+
+    #[test]
+    fn pipeline_is_repeatable() {
+        assert_eq!(pipeline_hash(), pipeline_hash());
+    }
+
+    /// The hash must be the same on every platform, so if this fails after an intentional change
+    /// to a transform or filter the expected value should be updated.
+    #[cfg(feature = "deterministic")]
+    #[test]
+    fn pipeline_is_reproducible() {
+        assert_eq!(pipeline_hash(), 0x34ca_f562_1a3d_1760);
+    }
+}
@@ -0,0 +1,214 @@
+//! Provides export of elevation layers as triangulated surface meshes, for visualising terrain in
+//! 3D tools or generating simulation assets.
+//!
+//! The mesh has one vertex at the centre of each valid (non-`NaN`) cell, positioned in the map's
+//! parent frame with the cell's value as its height, so the mesh has the same metric scale as the
+//! map. Each square of four valid neighbouring cells is split into two triangles, and squares with
+//! any `NaN` cell are left as holes.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use nalgebra::{Point2, Point3};
+
+use crate::{CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// File formats supported by [`CellMap::export_mesh()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshFormat {
+    /// Wavefront OBJ.
+    Obj,
+
+    /// ASCII Polygon File Format (Stanford PLY).
+    Ply,
+}
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A triangle mesh generated from a layer by [`CellMap::to_mesh()`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mesh {
+    /// The vertices of the mesh in the parent frame.
+    pub vertices: Vec<Point3<f64>>,
+
+    /// The triangles of the mesh as indices into [`Mesh::vertices`], wound anticlockwise when
+    /// viewed from above.
+    pub triangles: Vec<[usize; 3]>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Builds a triangle mesh of the surface described by the given height layer.
+    pub fn to_mesh(&self, height_layer: L) -> Mesh {
+        let data = &self[height_layer];
+        let (rows, cols) = data.dim();
+
+        // Map each valid cell to its vertex index
+        let mut vertex_indices = vec![None; rows * cols];
+        let mut vertices = Vec::new();
+
+        for ((y, x), &height) in data.indexed_iter() {
+            if height.is_nan() {
+                continue;
+            }
+
+            let position = self.position_unchecked(Point2::new(x, y));
+            vertex_indices[y * cols + x] = Some(vertices.len());
+            vertices.push(Point3::new(position.x, position.y, height));
+        }
+
+        let mut triangles = Vec::new();
+
+        for y in 0..rows.saturating_sub(1) {
+            for x in 0..cols.saturating_sub(1) {
+                let corners = [
+                    vertex_indices[y * cols + x],
+                    vertex_indices[y * cols + x + 1],
+                    vertex_indices[(y + 1) * cols + x + 1],
+                    vertex_indices[(y + 1) * cols + x],
+                ];
+
+                if let [Some(a), Some(b), Some(c), Some(d)] = corners {
+                    triangles.push([a, b, c]);
+                    triangles.push([a, c, d]);
+                }
+            }
+        }
+
+        Mesh {
+            vertices,
+            triangles,
+        }
+    }
+
+    /// Writes a triangle mesh of the surface described by the given height layer to the given
+    /// path, see [`CellMap::to_mesh()`].
+    pub fn export_mesh<P: AsRef<Path>>(
+        &self,
+        height_layer: L,
+        path: P,
+        format: MeshFormat,
+    ) -> Result<(), Error> {
+        let file = File::create(path).map_err(Error::IoError)?;
+        let mut writer = BufWriter::new(file);
+
+        self.to_mesh(height_layer)
+            .write(&mut writer, format)
+            .and_then(|_| writer.flush())
+            .map_err(Error::IoError)
+    }
+}
+
+impl Mesh {
+    /// Writes the mesh to `writer` in the given format.
+    pub fn write<W: Write>(&self, writer: &mut W, format: MeshFormat) -> std::io::Result<()> {
+        match format {
+            MeshFormat::Obj => {
+                writeln!(writer, "# cell-map mesh")?;
+                for v in &self.vertices {
+                    writeln!(writer, "v {} {} {}", v.x, v.y, v.z)?;
+                }
+                // OBJ indices start at 1
+                for t in &self.triangles {
+                    writeln!(writer, "f {} {} {}", t[0] + 1, t[1] + 1, t[2] + 1)?;
+                }
+            }
+            MeshFormat::Ply => {
+                writeln!(writer, "ply")?;
+                writeln!(writer, "format ascii 1.0")?;
+                writeln!(writer, "element vertex {}", self.vertices.len())?;
+                writeln!(writer, "property double x")?;
+                writeln!(writer, "property double y")?;
+                writeln!(writer, "property double z")?;
+                writeln!(writer, "element face {}", self.triangles.len())?;
+                writeln!(writer, "property list uchar int vertex_indices")?;
+                writeln!(writer, "end_header")?;
+                for v in &self.vertices {
+                    writeln!(writer, "{} {} {}", v.x, v.y, v.z)?;
+                }
+                for t in &self.triangles {
+                    writeln!(writer, "3 {} {} {}", t[0], t[1], t[2])?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn mesh() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 3), (0, 3)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            1.0,
+        );
+        map[(TestLayers::Layer0, Point2::new(2, 2))] = f64::NAN;
+
+        let mesh = map.to_mesh(TestLayers::Layer0);
+
+        // 4 squares, one of which has a NaN corner
+        assert_eq!(mesh.vertices.len(), 8);
+        assert_eq!(mesh.triangles.len(), 6);
+        assert_eq!(mesh.vertices[0], Point3::new(0.25, 0.25, 1.0));
+        assert_eq!(mesh.vertices[7], Point3::new(0.75, 1.25, 1.0));
+
+        // Triangles face up
+        for t in &mesh.triangles {
+            let (a, b, c) = (
+                mesh.vertices[t[0]],
+                mesh.vertices[t[1]],
+                mesh.vertices[t[2]],
+            );
+            assert!((b - a).cross(&(c - a)).z > 0.0);
+        }
+
+        let mut obj = Vec::new();
+        mesh.write(&mut obj, MeshFormat::Obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 8);
+        assert!(obj.contains("\nf 1 2 5\n"));
+
+        let path = std::env::temp_dir().join("cell_map_mesh.ply");
+        map.export_mesh(TestLayers::Layer0, &path, MeshFormat::Ply)
+            .unwrap();
+        let ply = std::fs::read_to_string(&path).unwrap();
+        assert!(ply.contains("element vertex 8\n"));
+        assert!(ply.contains("element face 6\n"));
+        assert!(ply.ends_with("3 0 1 4\n3 0 4 3\n3 1 2 5\n3 1 5 4\n3 3 4 7\n3 3 7 6\n"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,569 @@
+//! Provides the [`MmapCellMap`] type, a read-only map whose layers are backed by a memory-mapped
+//! file rather than being held in memory.
+//!
+//! This is useful for very large static maps, such as prior orbital DEMs, which only need to be
+//! queried in small regions at a time. Layers are paged in by the OS as they are accessed, so
+//! opening a map is cheap regardless of its size.
+//!
+//! Files are written with [`CellMap::write_mmap_file()`] and opened with [`MmapCellMap::open()`].
+//! The file consists of a fixed size header, stored little endian, followed by the raw data of
+//! each layer in [`Layer::to_index()`] order, with each layer stored in row-major (`y`, `x`)
+//! order. Cell data is stored in native byte order, so files are not portable between machines of
+//! different endianness.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    marker::PhantomData,
+    path::Path,
+};
+
+use bytemuck::Pod;
+use memmap2::Mmap;
+use nalgebra::{Affine2, Point2, Vector2};
+use ndarray::{ArrayView2, ArrayView3};
+
+use crate::{
+    cell_map::{BoundaryRule, Bounds, CellOrigin, GridConvention, YAxis},
+    error::unwrap_checked,
+    iterators::{
+        layerers::Many,
+        slicers::{Cells, Line, Windows},
+        CellMapIter, Layers,
+    },
+    map_metadata::CellMapMetadata,
+    CellMap, CellMapParams, Error, Layer,
+};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Magic bytes at the start of every memory-mappable map file.
+const MAGIC: &[u8; 8] = b"CELLMAP\0";
+
+/// Version of the file format written by this version of the crate.
+const VERSION: u32 = 1;
+
+/// Length of the header in bytes. Layer data starts immediately after the header, and this length
+/// is a multiple of 64 so that the data is suitably aligned for any cell type.
+const HEADER_LEN: usize = 128;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A read-only [`CellMap`] whose layers are backed by a memory-mapped file.
+///
+/// [`MmapCellMap`] provides the same read-only accessors and iterators as [`CellMap`]. If the map
+/// needs to be modified it can be loaded into memory with [`MmapCellMap::to_cell_map()`].
+#[derive(Debug)]
+pub struct MmapCellMap<L, T>
+where
+    L: Layer,
+{
+    mmap: Mmap,
+    metadata: CellMapMetadata,
+    params: CellMapParams,
+    layer_type: PhantomData<L>,
+    data_type: PhantomData<T>,
+}
+
+/// The header of a memory-mappable map file.
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    num_layers: usize,
+    elem_size: usize,
+    params: CellMapParams,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> MmapCellMap<L, T>
+where
+    L: Layer,
+    T: Pod,
+{
+    /// Opens the memory-mappable map file at the given path.
+    ///
+    /// The file must have been written by [`CellMap::write_mmap_file()`] with the same layer and
+    /// cell types, otherwise [`Error::InvalidMmapFile`] is returned.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        trace_span!("open_mmap_file", path = %path.as_ref().display());
+        let file = File::open(path).map_err(Error::IoError)?;
+
+        // Safety: the map is only ever read through this type, however the file could be modified
+        // by another process while it is mapped, which we can't prevent. This is the usual
+        // contract of memory mapped files and is documented on `MmapCellMap`.
+        let mmap = unsafe { Mmap::map(&file) }.map_err(Error::IoError)?;
+
+        let header = Header::read(&mmap)?;
+
+        if header.num_layers != L::NUM_LAYERS {
+            return Err(Error::WrongNumberOfLayers(L::NUM_LAYERS, header.num_layers));
+        }
+
+        if header.elem_size != std::mem::size_of::<T>() {
+            return Err(Error::InvalidMmapFile(format!(
+                "expected cells of {} bytes but found {} bytes",
+                std::mem::size_of::<T>(),
+                header.elem_size
+            )));
+        }
+
+        let metadata: CellMapMetadata = header.params.into();
+        let expected_len = HEADER_LEN + layer_len::<T>(&metadata) * L::NUM_LAYERS;
+
+        if mmap.len() != expected_len {
+            return Err(Error::InvalidMmapFile(format!(
+                "expected {} bytes but found {} bytes",
+                expected_len,
+                mmap.len()
+            )));
+        }
+
+        Ok(Self {
+            mmap,
+            metadata,
+            params: header.params,
+            layer_type: PhantomData,
+            data_type: PhantomData,
+        })
+    }
+
+    /// Returns the size of the cells in the map.
+    pub fn cell_size(&self) -> Vector2<f64> {
+        self.metadata.cell_size
+    }
+
+    /// Returns the number of cells in each direction of the map.
+    pub fn num_cells(&self) -> Vector2<usize> {
+        self.metadata.num_cells
+    }
+
+    /// Returns the bounds of this map
+    pub fn cell_bounds(&self) -> Bounds {
+        self.metadata.cell_bounds
+    }
+
+    /// Returns the parameters used to build this map.
+    pub fn params(&self) -> CellMapParams {
+        self.params
+    }
+
+    /// Gets the [`nalgebra::Affine2<f64>`] transformation between the map frame and the parent
+    /// frame.
+    pub fn to_parent(&self) -> Affine2<f64> {
+        self.metadata.to_parent
+    }
+
+    /// Returns a view of the given layer.
+    pub fn layer(&self, layer: L) -> ArrayView2<'_, T> {
+        unwrap_checked(self.try_layer(layer))
+    }
+
+    /// Returns a view of the given layer, or an [`Error`] if the layer isn't in the file.
+    ///
+    /// This is the checked equivalent of [`MmapCellMap::layer()`], which panics in that case.
+    pub fn try_layer(&self, layer: L) -> Result<ArrayView2<'_, T>, Error> {
+        ArrayView2::from_shape(
+            self.metadata.cell_bounds.get_shape(),
+            self.layer_cells(layer)?,
+        )
+        .map_err(|e| Error::InvalidMmapFile(e.to_string()))
+    }
+
+    /// Get a reference to the value at the given layer and index. Returns `None` if the index is
+    /// outside the bounds of the map.
+    pub fn get(&self, layer: L, index: Point2<usize>) -> Option<&T> {
+        if self.metadata.is_in_map(index) {
+            self.layer_cells(layer)
+                .ok()?
+                .get(index.y * self.metadata.num_cells.x + index.x)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the position in the parent frame of the centre of the given cell index.
+    ///
+    /// Returns `None` if the given `index` is not inside the map.
+    pub fn position(&self, index: Point2<usize>) -> Option<Point2<f64>> {
+        self.metadata.position(index)
+    }
+
+    /// Get the cell index of the given poisition.
+    ///
+    /// Returns `None` if the given `position` is not inside the map.
+    pub fn index(&self, position: Point2<f64>) -> Option<Point2<usize>> {
+        self.metadata.index(position)
+    }
+
+    /// Returns an iterator over each cell in all layers of the map.
+    pub fn iter(&self) -> CellMapIter<'_, L, T, Many<L>, Cells> {
+        CellMapIter::<'_, L, T, Many<L>, Cells>::new_cells(
+            Layers::Stacked(self.stacked_layers()),
+            self.metadata,
+        )
+    }
+
+    /// Returns an iterator over windows of cells in the map.
+    ///
+    /// The `semi_width` is half the size of the window in the x and y axes, not including
+    /// the central cell. E.g. to have a window which is in total 5x5, the `semi_window_size` should
+    /// be `Vector2::new(2, 2)`.
+    pub fn window_iter(
+        &self,
+        semi_width: Vector2<usize>,
+    ) -> Result<CellMapIter<'_, L, T, Many<L>, Windows>, Error> {
+        CellMapIter::<'_, L, T, Many<L>, Windows>::new_windows(
+            Layers::Stacked(self.stacked_layers()),
+            self.metadata,
+            semi_width,
+        )
+    }
+
+    /// Returns an iterator over cells along the line joining `start_position` and
+    /// `end_position`, which are expressed as positions in the map's parent frame.
+    pub fn line_iter(
+        &self,
+        start_position: Point2<f64>,
+        end_position: Point2<f64>,
+    ) -> Result<CellMapIter<'_, L, T, Many<L>, Line>, Error> {
+        CellMapIter::<'_, L, T, Many<L>, Line>::new_line(
+            Layers::Stacked(self.stacked_layers()),
+            self.metadata,
+            start_position,
+            end_position,
+        )
+    }
+
+    /// Loads the whole map into memory as a [`CellMap`].
+    pub fn to_cell_map(&self) -> CellMap<L, T> {
+        let data = L::all()
+            .into_iter()
+            .map(|layer| self.layer(layer).to_owned())
+            .collect();
+
+        // Every layer has the map's shape, which was checked when the file was opened
+        CellMap::new_from_data_unchecked(self.params, data)
+    }
+
+    /// Returns the cells of the given layer in standard layout.
+    fn layer_cells(&self, layer: L) -> Result<&[T], Error> {
+        let index = layer.to_index();
+        let len = layer_len::<T>(&self.metadata);
+        let start = HEADER_LEN + index * len;
+        let bytes = self
+            .mmap
+            .get(start..start + len)
+            .ok_or(Error::InvalidLayerIndex(index, L::NUM_LAYERS))?;
+
+        bytemuck::try_cast_slice(bytes).map_err(|e| Error::InvalidMmapFile(format!("{:?}", e)))
+    }
+
+    /// Returns a view of every layer, stacked along the first axis in [`Layer::to_index()`] order.
+    fn stacked_layers(&self) -> ArrayView3<'_, T> {
+        let (rows, cols) = self.metadata.cell_bounds.get_shape();
+        let bytes = &self.mmap[HEADER_LEN..];
+
+        // The file's length was checked when it was opened
+        unwrap_checked(
+            bytemuck::try_cast_slice(bytes)
+                .map_err(|e| Error::InvalidMmapFile(format!("{:?}", e)))
+                .and_then(|cells| {
+                    ArrayView3::from_shape((L::NUM_LAYERS, rows, cols), cells)
+                        .map_err(|e| Error::InvalidMmapFile(e.to_string()))
+                }),
+        )
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Pod,
+{
+    /// Writes the map to the given path in a format which can be opened with
+    /// [`MmapCellMap::open()`], overwriting any existing file.
+    pub fn write_mmap_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        trace_span!("write_mmap_file", path = %path.as_ref().display());
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(path)
+            .map_err(Error::IoError)?;
+        let mut writer = BufWriter::new(file);
+
+        let header = Header {
+            num_layers: L::NUM_LAYERS,
+            elem_size: std::mem::size_of::<T>(),
+            params: self.params,
+        };
+        writer
+            .write_all(&header.to_bytes())
+            .map_err(Error::IoError)?;
+
+        for layer in self.data.iter() {
+            // Layers may not be contiguous if they've been built from views, so copy those which
+            // aren't into a standard layout first.
+            match layer.as_slice() {
+                Some(cells) => writer.write_all(bytemuck::cast_slice(cells)),
+                None => {
+                    let cells: Vec<T> = layer.iter().copied().collect();
+                    writer.write_all(bytemuck::cast_slice(&cells))
+                }
+            }
+            .map_err(Error::IoError)?;
+        }
+
+        writer.flush().map_err(Error::IoError)
+    }
+}
+
+impl Header {
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let params = self.params;
+        let mut bytes = [0u8; HEADER_LEN];
+        let mut cursor = 0;
+        let mut put = |field: &[u8]| {
+            bytes[cursor..cursor + field.len()].copy_from_slice(field);
+            cursor += field.len();
+        };
+
+        put(MAGIC);
+        put(&VERSION.to_le_bytes());
+        put(&(self.num_layers as u32).to_le_bytes());
+        put(&(self.elem_size as u64).to_le_bytes());
+        put(&params.cell_size.x.to_le_bytes());
+        put(&params.cell_size.y.to_le_bytes());
+        put(&(params.cell_bounds.x.0 as i64).to_le_bytes());
+        put(&(params.cell_bounds.x.1 as i64).to_le_bytes());
+        put(&(params.cell_bounds.y.0 as i64).to_le_bytes());
+        put(&(params.cell_bounds.y.1 as i64).to_le_bytes());
+        put(&params.rotation_in_parent_rad.to_le_bytes());
+        put(&params.position_in_parent.x.to_le_bytes());
+        put(&params.position_in_parent.y.to_le_bytes());
+        put(&params.cell_boundary_precision.to_le_bytes());
+
+        // The convention and boundary rule are stored in otherwise zeroed padding, so that files
+        // without them read as the defaults
+        put(&[
+            match params.convention.y_axis {
+                YAxis::Up => 0,
+                YAxis::Down => 1,
+            },
+            match params.convention.origin {
+                CellOrigin::Corner => 0,
+                CellOrigin::Centre => 1,
+            },
+            params.boundary_rule.to_code(),
+        ]);
+
+        bytes
+    }
+
+    fn read(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_LEN || &bytes[0..8] != MAGIC {
+            return Err(Error::InvalidMmapFile("missing file header".into()));
+        }
+
+        let mut rest = &bytes[8..HEADER_LEN];
+        let mut take = |len: usize| {
+            let (field, tail) = rest.split_at(len);
+            rest = tail;
+            field
+        };
+
+        let mut word = [0u8; 4];
+        word.copy_from_slice(take(4));
+        let version = u32::from_le_bytes(word);
+        if version != VERSION {
+            return Err(Error::InvalidMmapFile(format!(
+                "unsupported file version {}",
+                version
+            )));
+        }
+
+        word.copy_from_slice(take(4));
+        let num_layers = u32::from_le_bytes(word) as usize;
+
+        // All remaining fields are 8 bytes long
+        let mut fields = [[0u8; 8]; 11];
+        for field in fields.iter_mut() {
+            field.copy_from_slice(take(8));
+        }
+        let f = |i: usize| f64::from_le_bytes(fields[i]);
+        let i = |i: usize| i64::from_le_bytes(fields[i]) as isize;
+
+        let elem_size = u64::from_le_bytes(fields[0]) as usize;
+        let cell_size = Vector2::new(f(1), f(2));
+        let cell_bounds = Bounds::new((i(3), i(4)), (i(5), i(6)))?;
+        let rotation_in_parent_rad = f(7);
+        let position_in_parent = Vector2::new(f(8), f(9));
+        let cell_boundary_precision = f(10);
+
+        let convention = GridConvention {
+            y_axis: match take(1)[0] {
+                0 => YAxis::Up,
+                1 => YAxis::Down,
+                v => return Err(Error::InvalidMmapFile(format!("invalid y axis {}", v))),
+            },
+            origin: match take(1)[0] {
+                0 => CellOrigin::Corner,
+                1 => CellOrigin::Centre,
+                v => return Err(Error::InvalidMmapFile(format!("invalid cell origin {}", v))),
+            },
+        };
+        let code = take(1)[0];
+        let boundary_rule = BoundaryRule::from_code(code)
+            .ok_or_else(|| Error::InvalidMmapFile(format!("invalid boundary rule {}", code)))?;
+
+        Ok(Self {
+            num_layers,
+            elem_size,
+            params: CellMapParams {
+                cell_size,
+                cell_bounds,
+                rotation_in_parent_rad,
+                position_in_parent,
+                cell_boundary_precision,
+                convention,
+                boundary_rule,
+            },
+        })
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Returns the length of a single layer of a map with the given metadata, in bytes.
+fn layer_len<T>(metadata: &CellMapMetadata) -> usize {
+    metadata.num_cells.x * metadata.num_cells.y * std::mem::size_of::<T>()
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point2, Vector2};
+
+    use super::*;
+    use crate::test_utils::TestLayers;
+
+    fn test_map() -> CellMap<TestLayers, f64> {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_size: Vector2::new(0.5, 0.25),
+                cell_bounds: Bounds::new((0, 7), (0, 5)).unwrap(),
+                rotation_in_parent_rad: 0.3,
+                position_in_parent: Vector2::new(1.0, -2.0),
+                convention: GridConvention {
+                    y_axis: YAxis::Down,
+                    origin: CellOrigin::Centre,
+                },
+                boundary_rule: BoundaryRule::RoundNearest,
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        for ((layer, index), value) in map.iter_mut().indexed() {
+            *value = (layer.to_index() * 100 + index.y * 10 + index.x) as f64;
+        }
+
+        map
+    }
+
+    #[test]
+    fn round_trip() {
+        let map = test_map();
+        let path = std::env::temp_dir().join("cell_map_mmap_round_trip.cellmap");
+        map.write_mmap_file(&path).unwrap();
+
+        let mmap = MmapCellMap::<TestLayers, f64>::open(&path).unwrap();
+
+        assert_eq!(mmap.cell_bounds(), map.cell_bounds());
+        assert_eq!(mmap.cell_size(), map.cell_size());
+        assert_eq!(mmap.to_parent(), map.to_parent());
+        assert_eq!(mmap.num_cells(), map.num_cells());
+        assert_eq!(mmap.layer(TestLayers::Layer1), map[TestLayers::Layer1]);
+        assert_eq!(
+            mmap.get(TestLayers::Layer2, Point2::new(3, 4)),
+            map.get(TestLayers::Layer2, Point2::new(3, 4))
+        );
+        assert_eq!(mmap.get(TestLayers::Layer0, Point2::new(7, 0)), None);
+
+        // Iterators should produce exactly the same data as the in-memory map
+        assert!(mmap.iter().eq(map.iter()));
+        assert!(mmap
+            .window_iter(Vector2::new(1, 1))
+            .unwrap()
+            .eq(map.window_iter(Vector2::new(1, 1)).unwrap()));
+        let start = map.position(Point2::new(4, 1)).unwrap();
+        let end = map.position(Point2::new(6, 4)).unwrap();
+        assert!(mmap
+            .line_iter(start, end)
+            .unwrap()
+            .positioned()
+            .map(|((_, pos), value)| (pos, *value))
+            .eq(map
+                .line_iter(start, end)
+                .unwrap()
+                .positioned()
+                .map(|((_, pos), value)| (pos, *value))));
+
+        assert_eq!(
+            mmap.to_cell_map().iter().sum::<f64>(),
+            map.iter().sum::<f64>()
+        );
+        assert_eq!(
+            mmap.to_cell_map().params().boundary_rule,
+            BoundaryRule::RoundNearest
+        );
+
+        drop(mmap);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn invalid_files() {
+        let map = test_map();
+        let path = std::env::temp_dir().join("cell_map_mmap_invalid.cellmap");
+        map.write_mmap_file(&path).unwrap();
+
+        // Wrong cell type
+        assert!(matches!(
+            MmapCellMap::<TestLayers, f32>::open(&path),
+            Err(Error::InvalidMmapFile(_))
+        ));
+
+        // Truncated file
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 8]).unwrap();
+        assert!(matches!(
+            MmapCellMap::<TestLayers, f64>::open(&path),
+            Err(Error::InvalidMmapFile(_))
+        ));
+
+        // Not a map file at all
+        std::fs::write(&path, b"not a map").unwrap();
+        assert!(matches!(
+            MmapCellMap::<TestLayers, f64>::open(&path),
+            Err(Error::InvalidMmapFile(_))
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,400 @@
+//! Provides Bayesian occupancy mapping, fusing observations from different sensors into a single
+//! occupancy layer.
+//!
+//! Occupancy layers store the log-odds of each cell being occupied, so `0.0` means the cell is
+//! unknown, positive values mean it's likely occupied and negative values mean it's likely free.
+//! Use [`probability()`] to convert a cell back to a probability.
+//!
+//! Each sensor is described by a [`SensorModel`], an inverse sensor model which maps a single
+//! observation to the probability that each cell it covers is occupied. Observations are fused
+//! into a layer with [`CellMap::integrate_scan()`], so any number of sensors can feed the same
+//! layer consistently. [`RayModel`] is provided for range sensors which measure along a ray, such
+//! as lidar, and planar scanners can be integrated directly with [`CellMap::integrate_scan_2d()`].
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Isometry2, Point2, Vector2};
+
+use crate::{math, CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The largest magnitude of log-odds stored by [`CellMap::integrate_scan()`].
+///
+/// Clamping the log-odds stops cells becoming so certain that they can't react to changes in the
+/// environment. `10.0` corresponds to a probability of about `0.99995`.
+pub const MAX_LOG_ODDS: f64 = 10.0;
+
+// ------------------------------------------------------------------------------------------------
+// TRAITS
+// ------------------------------------------------------------------------------------------------
+
+/// An inverse sensor model, which maps an observation to the probability that each cell it covers
+/// is occupied.
+pub trait SensorModel {
+    /// The type of a single observation, such as a single ray of a scan.
+    type Observation;
+
+    /// Returns the cells covered by `observation` in `map`, with the probability that each is
+    /// occupied given the observation.
+    ///
+    /// A probability of `0.5` gives no information, and cells not returned are unchanged.
+    fn cell_updates<L: Layer, T>(
+        &self,
+        map: &CellMap<L, T>,
+        observation: &Self::Observation,
+    ) -> Result<Vec<(Point2<usize>, f64)>, Error>;
+}
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A single ray measured by a range sensor, expressed in the map's parent frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    /// The position of the sensor.
+    pub origin: Point2<f64>,
+
+    /// The end of the ray.
+    pub end: Point2<f64>,
+
+    /// Whether the ray ended on an obstacle, or `false` if it reached the sensor's maximum range
+    /// without a return.
+    pub hit: bool,
+}
+
+/// A simple inverse sensor model for range sensors, which marks the cells along each [`Ray`] as
+/// likely free and the cell at the end of the ray as likely occupied if the ray hit something.
+///
+/// Rays are clipped to the map, so rays which start or end outside it still update the cells they
+/// cross. If a ray is clipped at its end the end cell isn't marked as occupied, since the obstacle
+/// is outside the map.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayModel {
+    /// The probability that a cell the ray passed through is occupied.
+    pub p_free: f64,
+
+    /// The probability that the cell the ray hit is occupied.
+    pub p_occupied: f64,
+}
+
+/// A scan from a planar range sensor, in the same layout as a ROS `sensor_msgs/LaserScan`.
+///
+/// The `i`th range is measured along the direction at `angle_min + i * angle_increment` radians
+/// from the sensor's `x` axis.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaserScan {
+    /// The angle of the first range, in radians.
+    pub angle_min: f64,
+
+    /// The angle between consecutive ranges, in radians.
+    pub angle_increment: f64,
+
+    /// The maximum range of the sensor. Ranges at or beyond this, including infinite ranges, are
+    /// treated as having no return, so only clear the cells up to `max_range`.
+    pub max_range: f64,
+
+    /// The measured ranges. Ranges which are `NaN`, negative or zero are ignored.
+    pub ranges: Vec<f64>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Fuses each observation in `scan` into the occupancy `layer`, using the given sensor model.
+    ///
+    /// The log-odds of each cell returned by [`SensorModel::cell_updates()`] is increased by the
+    /// log-odds of its probability, and clamped to [`MAX_LOG_ODDS`]. If an error is returned the
+    /// observations before the failing one will already have been fused.
+    pub fn integrate_scan<M: SensorModel>(
+        &mut self,
+        layer: L,
+        model: &M,
+        scan: &[M::Observation],
+    ) -> Result<(), Error> {
+        trace_span!("integrate_scan", num_observations = scan.len());
+
+        for observation in scan {
+            for (index, p) in model.cell_updates(self, observation)? {
+                let cell = &mut self[(layer.clone(), index)];
+                *cell = (*cell + log_odds(p)).clamp(-MAX_LOG_ODDS, MAX_LOG_ODDS);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fuses a planar scan taken by a sensor at `sensor_pose` in the parent frame into the
+    /// occupancy `layer`, clearing the cells along each ray and marking the cells where rays hit
+    /// obstacles.
+    ///
+    /// This is a convenience for converting the scan with [`LaserScan::rays()`] and integrating the
+    /// rays with [`CellMap::integrate_scan()`].
+    pub fn integrate_scan_2d(
+        &mut self,
+        layer: L,
+        model: &RayModel,
+        sensor_pose: Isometry2<f64>,
+        scan: &LaserScan,
+    ) -> Result<(), Error> {
+        self.integrate_scan(layer, model, &scan.rays(sensor_pose))
+    }
+}
+
+impl LaserScan {
+    /// Converts the scan taken by a sensor at `sensor_pose` in the parent frame into rays in the
+    /// parent frame.
+    pub fn rays(&self, sensor_pose: Isometry2<f64>) -> Vec<Ray> {
+        let origin = sensor_pose * Point2::origin();
+
+        self.ranges
+            .iter()
+            .enumerate()
+            .filter(|(_, &range)| range > 0.0)
+            .map(|(i, &range)| {
+                let hit = range.is_finite() && range < self.max_range;
+                let length = if hit { range } else { self.max_range };
+                let (sin, cos) = math::sin_cos(self.angle_min + i as f64 * self.angle_increment);
+
+                Ray {
+                    origin,
+                    end: sensor_pose * Point2::new(length * cos, length * sin),
+                    hit,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for RayModel {
+    fn default() -> Self {
+        Self {
+            p_free: 0.4,
+            p_occupied: 0.7,
+        }
+    }
+}
+
+impl SensorModel for RayModel {
+    type Observation = Ray;
+
+    fn cell_updates<L: Layer, T>(
+        &self,
+        map: &CellMap<L, T>,
+        ray: &Ray,
+    ) -> Result<Vec<(Point2<usize>, f64)>, Error> {
+        let (start, end, end_clipped) = match clip_to_map(map, ray.origin, ray.end) {
+            Some(clipped) => clipped,
+            None => return Ok(Vec::new()),
+        };
+
+        let hit_index = if ray.hit && !end_clipped {
+            map.index(end)
+        } else {
+            None
+        };
+
+        let mut updates: Vec<_> = map
+            .line_iter(start, end)?
+            .layer(L::FIRST)
+            .indexed()
+            .map(|((_, index), _)| index)
+            .filter(|&index| Some(index) != hit_index)
+            .map(|index| (index, self.p_free))
+            .collect();
+
+        if let Some(index) = hit_index {
+            updates.push((index, self.p_occupied));
+        }
+
+        Ok(updates)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Converts a probability into log-odds.
+pub fn log_odds(p: f64) -> f64 {
+    math::ln(p / (1.0 - p))
+}
+
+/// Converts log-odds into a probability.
+pub fn probability(log_odds: f64) -> f64 {
+    1.0 - 1.0 / (1.0 + math::exp(log_odds))
+}
+
+/// Clips the line between the parent-frame positions `start` and `end` to the map, returning the
+/// clipped start and end and whether the end was moved, or `None` if the line doesn't cross the
+/// map.
+fn clip_to_map<L: Layer, T>(
+    map: &CellMap<L, T>,
+    start: Point2<f64>,
+    end: Point2<f64>,
+) -> Option<(Point2<f64>, Point2<f64>, bool)> {
+    // Positions exactly on the upper edge of the map aren't inside any cell, so keep the clipped
+    // line slightly inside the map
+    const EPSILON: f64 = 1e-9;
+
+    // Clip in the map frame, where the map is an axis aligned rectangle, using Liang-Barsky
+    let to_parent = map.to_parent();
+    let start_map = to_parent.inverse_transform_point(&start);
+    let end_map = to_parent.inverse_transform_point(&end);
+    let dir: Vector2<f64> = end_map - start_map;
+    let bounds = map.cell_bounds();
+
+    let mut t_start: f64 = 0.0;
+    let mut t_end: f64 = 1.0;
+
+    for (p, q) in [
+        (-dir.x, start_map.x - bounds.x.0 as f64),
+        (dir.x, bounds.x.1 as f64 - EPSILON - start_map.x),
+        (-dir.y, start_map.y - bounds.y.0 as f64),
+        (dir.y, bounds.y.1 as f64 - EPSILON - start_map.y),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t_start = t_start.max(q / p);
+        } else {
+            t_end = t_end.min(q / p);
+        }
+    }
+
+    if t_start > t_end {
+        return None;
+    }
+
+    Some((
+        to_parent.transform_point(&(start_map + dir * t_start)),
+        to_parent.transform_point(&(start_map + dir * t_end)),
+        t_end < 1.0,
+    ))
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    /// A sensor which observes a single cell as occupied with the given probability, like a
+    /// bump sensor.
+    struct CellModel;
+
+    impl SensorModel for CellModel {
+        type Observation = (Point2<f64>, f64);
+
+        fn cell_updates<L: Layer, T>(
+            &self,
+            map: &CellMap<L, T>,
+            &(position, p): &Self::Observation,
+        ) -> Result<Vec<(Point2<usize>, f64)>, Error> {
+            Ok(map.index(position).map(|i| (i, p)).into_iter().collect())
+        }
+    }
+
+    #[test]
+    fn integrate_scan() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 10), (0, 5)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // A ray which hits an obstacle, and one which leaves the map
+        let model = RayModel::default();
+        let scan = [
+            Ray {
+                origin: Point2::new(0.5, 0.5),
+                end: Point2::new(5.5, 0.5),
+                hit: true,
+            },
+            Ray {
+                origin: Point2::new(0.5, 2.5),
+                end: Point2::new(15.5, 2.5),
+                hit: true,
+            },
+        ];
+        map.integrate_scan(TestLayers::Layer0, &model, &scan)
+            .unwrap();
+
+        let cell = |x, y| map[(TestLayers::Layer0, Point2::new(x, y))];
+        assert!((cell(2, 0) - log_odds(0.4)).abs() < 1e-12);
+        assert!((cell(5, 0) - log_odds(0.7)).abs() < 1e-12);
+        assert_eq!(cell(6, 0), 0.0);
+        assert!(cell(9, 2) < 0.0);
+        assert_eq!(cell(0, 1), 0.0);
+
+        // Other sensors feed the same layer, and log-odds are clamped
+        let bumps = vec![(Point2::new(5.5, 0.5), 0.999); 10];
+        map.integrate_scan(TestLayers::Layer0, &CellModel, &bumps)
+            .unwrap();
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(5, 0))], MAX_LOG_ODDS);
+        assert!(probability(MAX_LOG_ODDS) > 0.9999);
+        assert!((probability(log_odds(0.3)) - 0.3).abs() < 1e-12);
+
+        // Rays which miss the map entirely do nothing
+        let miss = Ray {
+            origin: Point2::new(-5.0, -5.0),
+            end: Point2::new(-1.0, 8.0),
+            hit: true,
+        };
+        assert!(model.cell_updates(&map, &miss).unwrap().is_empty());
+    }
+
+    #[test]
+    fn integrate_scan_2d() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-5, 5), (-5, 5)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // A sensor at (0.5, 0.5) facing along +y, with rays along +y, -x and +x
+        let pose = Isometry2::new(Vector2::new(0.5, 0.5), std::f64::consts::FRAC_PI_2);
+        let scan = LaserScan {
+            angle_min: 0.0,
+            angle_increment: std::f64::consts::FRAC_PI_2,
+            max_range: 3.0,
+            ranges: vec![2.0, f64::INFINITY, f64::NAN, 1.0],
+        };
+
+        let rays = scan.rays(pose);
+        assert_eq!(rays.len(), 3);
+        assert!(!rays[1].hit);
+        assert!((rays[1].end - Point2::new(-2.5, 0.5)).norm() < 1e-9);
+
+        map.integrate_scan_2d(TestLayers::Layer0, &RayModel::default(), pose, &scan)
+            .unwrap();
+
+        let cell =
+            |x: f64, y: f64| map[(TestLayers::Layer0, map.index(Point2::new(x, y)).unwrap())];
+        assert!(cell(0.5, 2.5) > 0.0);
+        assert!(cell(0.5, 1.5) < 0.0);
+        assert!(cell(-2.5, 0.5) < 0.0);
+        assert_eq!(cell(-3.5, 0.5), 0.0);
+        assert!(cell(1.5, 0.5) > 0.0);
+        assert_eq!(cell(0.5, -0.5), 0.0);
+    }
+}
@@ -0,0 +1,483 @@
+//! Provides [`OutOfBounds`], which chooses what windows, rays and interpolation do when they reach
+//! past the edge of the map.
+//!
+//! Whether the cells beyond the edge should be treated as unknown but traversable, as a wall, or
+//! as a continuation of the edge depends on the application, so each operation here takes the
+//! behaviour as an argument:
+//!
+//! - [`CellMap::padded_window()`] and [`CellMap::padded_window_iter()`] return windows around
+//!   cells at the edge of the map, which [`CellMap::window_iter()`] skips.
+//! - [`CellMap::ray()`] returns the cells along a line whose ends may be outside the map, which
+//!   [`CellMap::line_iter()`] doesn't allow.
+//! - [`CellMap::interpolate()`] bilinearly interpolates a layer at any position.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+use ndarray::Array2;
+
+use crate::{cell_map::Bounds, CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// What to do when an operation needs the value of a cell outside the map, see the
+/// [module documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutOfBounds<T> {
+    /// Return an error.
+    Error,
+
+    /// Use the value of the closest cell inside the map, which extends the edges of the map
+    /// outwards.
+    Clamp,
+
+    /// Use the given value.
+    Fill(T),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    /// Returns the value of `layer` at the given map-frame cell location, which may be outside
+    /// the map, or `None` if it's outside and `oob` is [`OutOfBounds::Error`].
+    ///
+    /// [`OutOfBounds::Clamp`] also gives `None` if the map has no cells.
+    pub fn get_or_oob(&self, layer: L, cell: Point2<isize>, oob: &OutOfBounds<T>) -> Option<T> {
+        let bounds = self.cell_bounds();
+        let data = &self[layer];
+
+        let index = |cell: Point2<isize>| {
+            (
+                (cell.y - bounds.y.0) as usize,
+                (cell.x - bounds.x.0) as usize,
+            )
+        };
+
+        if bounds.contains(cell) {
+            return Some(data[index(cell)].clone());
+        }
+
+        match oob {
+            OutOfBounds::Error => None,
+            OutOfBounds::Clamp => {
+                if data.is_empty() {
+                    return None;
+                }
+                let clamped = Point2::new(
+                    cell.x.clamp(bounds.x.0, bounds.x.1 - 1),
+                    cell.y.clamp(bounds.y.0, bounds.y.1 - 1),
+                );
+                Some(data[index(clamped)].clone())
+            }
+            OutOfBounds::Fill(value) => Some(value.clone()),
+        }
+    }
+
+    /// Returns the window of `layer` around `index`, which must be inside the map, with cells
+    /// outside the map given by `oob`.
+    ///
+    /// The `semi_width` is half the size of the window in the x and y axes, not including the
+    /// central cell, as for [`CellMap::window_iter()`].
+    ///
+    /// Returns [`Error::IndexOutsideLayer`] if `index` is outside the map, or
+    /// [`Error::BoundsOutsideMap`] if the window leaves the map and `oob` is
+    /// [`OutOfBounds::Error`].
+    pub fn padded_window(
+        &self,
+        layer: L,
+        index: Point2<usize>,
+        semi_width: Vector2<usize>,
+        oob: &OutOfBounds<T>,
+    ) -> Result<Array2<T>, Error> {
+        if !self.index_in_map(index) {
+            return Err(Error::IndexOutsideLayer(
+                layer.to_index(),
+                index,
+                self.cell_bounds(),
+            ));
+        }
+
+        let min = self.cell_bounds().as_corners().0;
+        let centre = min + index.coords.map(|v| v as isize);
+        let semi_width = semi_width.map(|v| v as isize);
+        let window = Bounds {
+            x: (centre.x - semi_width.x, centre.x + semi_width.x + 1),
+            y: (centre.y - semi_width.y, centre.y + semi_width.y + 1),
+        };
+
+        let mut values = Vec::with_capacity(window.get_shape().0 * window.get_shape().1);
+        for y in window.y.0..window.y.1 {
+            for x in window.x.0..window.x.1 {
+                values.push(
+                    self.get_or_oob(layer.clone(), Point2::new(x, y), oob)
+                        .ok_or(Error::BoundsOutsideMap(window, self.cell_bounds()))?,
+                );
+            }
+        }
+
+        Ok(Array2::from_shape_vec(window.get_shape(), values)?)
+    }
+
+    /// Returns an iterator over the index and window of `layer` around every cell in the map, in
+    /// the same order as [`CellMap::iter()`], with cells outside the map given by `oob`.
+    ///
+    /// With [`OutOfBounds::Error`] windows which leave the map are skipped, so the same windows
+    /// are visited as by [`CellMap::window_iter()`].
+    pub fn padded_window_iter<'a>(
+        &'a self,
+        layer: L,
+        semi_width: Vector2<usize>,
+        oob: &'a OutOfBounds<T>,
+    ) -> impl Iterator<Item = (Point2<usize>, Array2<T>)> + 'a {
+        let num_cells = self.num_cells();
+
+        (0..num_cells.y)
+            .flat_map(move |y| (0..num_cells.x).map(move |x| Point2::new(x, y)))
+            .filter_map(move |index| {
+                self.padded_window(layer.clone(), index, semi_width, oob)
+                    .ok()
+                    .map(|window| (index, window))
+            })
+    }
+
+    /// Returns the map-frame cell location and value of `layer` of every cell the line from
+    /// `start` to `end` passes through, in order from `start`, with cells outside the map given
+    /// by `oob`. The ends are positions in the parent frame and may be outside the map.
+    ///
+    /// Returns [`Error::PositionOutsideMap`] if the line leaves the map and `oob` is
+    /// [`OutOfBounds::Error`].
+    pub fn ray(
+        &self,
+        layer: L,
+        start: Point2<f64>,
+        end: Point2<f64>,
+        oob: &OutOfBounds<T>,
+    ) -> Result<Vec<(Point2<isize>, T)>, Error> {
+        self.ray_cells(start, end)
+            .into_iter()
+            .map(|cell| {
+                let value = self.get_or_oob(layer.clone(), cell, oob).ok_or_else(|| {
+                    let (name, position) = if self.index(start).is_none() {
+                        ("ray start", start)
+                    } else {
+                        ("ray end", end)
+                    };
+                    Error::PositionOutsideMap(name.into(), position)
+                })?;
+                Ok((cell, value))
+            })
+            .collect()
+    }
+
+    /// Returns the map-frame cell location of every cell the line between the given parent-frame
+    /// positions passes through, using the algorithm of Amanatides and Woo.
+    fn ray_cells(&self, start: Point2<f64>, end: Point2<f64>) -> Vec<Point2<isize>> {
+        let to_map = |p: &Point2<f64>| self.metadata.to_parent.inverse_transform_point(p);
+        let (start_map, end_map) = (to_map(&start), to_map(&end));
+
+        let mut cell = self.metadata.map_point_to_cell(start_map);
+        let end_cell = self.metadata.map_point_to_cell(end_map);
+        let dir = end_map - start_map;
+        let step = end_cell - cell;
+        let step = Vector2::new(step.x.signum(), step.y.signum());
+
+        // The distance along the line, as a fraction of its length, to the next cell edge in each
+        // axis, and between cell edges
+        let next_edge = |c: isize, s: isize, p: f64, d: f64| {
+            if s == 0 {
+                f64::INFINITY
+            } else {
+                let edge = if s > 0 { c + 1 } else { c } as f64;
+                (edge - p) / d
+            }
+        };
+        let mut t_max = Vector2::new(
+            next_edge(cell.x, step.x, start_map.x, dir.x),
+            next_edge(cell.y, step.y, start_map.y, dir.y),
+        );
+        let t_delta = Vector2::new(1.0 / dir.x.abs(), 1.0 / dir.y.abs());
+
+        let num_steps = (end_cell - cell).abs().sum() as usize;
+        let mut cells = Vec::with_capacity(num_steps + 1);
+        cells.push(cell);
+
+        for _ in 0..num_steps {
+            if (t_max.x < t_max.y && cell.x != end_cell.x) || cell.y == end_cell.y {
+                cell.x += step.x;
+                t_max.x += t_delta.x;
+            } else {
+                cell.y += step.y;
+                t_max.y += t_delta.y;
+            }
+            cells.push(cell);
+        }
+
+        cells
+    }
+}
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Returns the value of `layer` at the given parent-frame position, bilinearly interpolated
+    /// between the centres of the four closest cells, with cells outside the map given by `oob`.
+    ///
+    /// Positions within half a cell of the edge of the map need cells outside it, so with
+    /// [`OutOfBounds::Error`] they return [`Error::PositionOutsideMap`], as do positions outside
+    /// the map.
+    ///
+    /// Cells with zero weight, such as the neighbours of a cell whose centre is at `position`,
+    /// don't affect the result, but if any cell with non-zero weight is `NaN` the result is
+    /// `NaN`.
+    pub fn interpolate(
+        &self,
+        layer: L,
+        position: Point2<f64>,
+        oob: &OutOfBounds<f64>,
+    ) -> Result<f64, Error> {
+        // Cell centres are at half-integer map-frame coordinates
+        let point =
+            self.metadata.to_parent.inverse_transform_point(&position) - Vector2::new(0.5, 0.5);
+        let base = point.map(f64::floor);
+        let frac = point - base;
+        let base = base.map(|v| v as isize);
+
+        let value = |dx: isize, dy: isize| {
+            self.get_or_oob(layer.clone(), base + Vector2::new(dx, dy), oob)
+                .ok_or_else(|| Error::PositionOutsideMap("interpolate".into(), position))
+        };
+
+        let taps = [
+            (0, 0, (1.0 - frac.x) * (1.0 - frac.y)),
+            (1, 0, frac.x * (1.0 - frac.y)),
+            (0, 1, (1.0 - frac.x) * frac.y),
+            (1, 1, frac.x * frac.y),
+        ];
+
+        // Cells with zero weight are skipped so that a NaN cell doesn't affect its neighbours
+        let mut total = 0.0;
+        for (dx, dy, weight) in taps {
+            let value = value(dx, dy)?;
+            if weight != 0.0 {
+                total += value * weight;
+            }
+        }
+
+        Ok(total)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::TestLayers, CellMapParams};
+
+    fn new_map() -> CellMap<TestLayers, f64> {
+        let mut map = CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-2, 2), (0, 3)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        for ((_, index), v) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            *v = (index.x + 10 * index.y) as f64;
+        }
+
+        map
+    }
+
+    #[test]
+    fn padded_windows() {
+        let map = new_map();
+        let semi_width = Vector2::new(1, 1);
+
+        let window = map
+            .padded_window(
+                TestLayers::Layer0,
+                Point2::new(0, 0),
+                semi_width,
+                &OutOfBounds::Fill(-1.0),
+            )
+            .unwrap();
+        assert_eq!(window.row(0).to_vec(), vec![-1.0, -1.0, -1.0]);
+        assert_eq!(window.row(1).to_vec(), vec![-1.0, 0.0, 1.0]);
+        assert_eq!(window.row(2).to_vec(), vec![-1.0, 10.0, 11.0]);
+
+        let window = map
+            .padded_window(
+                TestLayers::Layer0,
+                Point2::new(3, 2),
+                semi_width,
+                &OutOfBounds::Clamp,
+            )
+            .unwrap();
+        assert_eq!(window.row(0).to_vec(), vec![12.0, 13.0, 13.0]);
+        assert_eq!(window.row(2).to_vec(), vec![22.0, 23.0, 23.0]);
+
+        assert!(matches!(
+            map.padded_window(
+                TestLayers::Layer0,
+                Point2::new(3, 2),
+                semi_width,
+                &OutOfBounds::Error
+            ),
+            Err(Error::BoundsOutsideMap(_, _))
+        ));
+        assert!(matches!(
+            map.padded_window(
+                TestLayers::Layer0,
+                Point2::new(4, 2),
+                semi_width,
+                &OutOfBounds::Clamp
+            ),
+            Err(Error::IndexOutsideLayer(0, _, _))
+        ));
+
+        // Every cell has a window, except with errors where only interior windows are visited
+        let clamp = OutOfBounds::Clamp;
+        assert_eq!(
+            map.padded_window_iter(TestLayers::Layer0, semi_width, &clamp)
+                .count(),
+            12
+        );
+        let error = OutOfBounds::Error;
+        let windows: Vec<_> = map
+            .padded_window_iter(TestLayers::Layer0, semi_width, &error)
+            .collect();
+        let dense: Vec<_> = map
+            .window_iter(semi_width)
+            .unwrap()
+            .layer(TestLayers::Layer0)
+            .collect();
+        assert_eq!(windows.len(), dense.len());
+        assert!(windows.iter().zip(dense).all(|((_, a), b)| a == b));
+    }
+
+    #[test]
+    fn rays() {
+        let map = new_map();
+
+        // A ray from outside the map, through it and out the other side
+        let ray = map
+            .ray(
+                TestLayers::Layer0,
+                Point2::new(-3.5, 1.5),
+                Point2::new(3.5, 1.5),
+                &OutOfBounds::Fill(-1.0),
+            )
+            .unwrap();
+        let values: Vec<_> = ray.iter().map(|(_, v)| *v).collect();
+        assert_eq!(values, vec![-1.0, -1.0, 10.0, 11.0, 12.0, 13.0, -1.0, -1.0]);
+        assert_eq!(ray[0].0, Point2::new(-4, 1));
+
+        // A diagonal ray visits each cell it touches, in order
+        let ray = map
+            .ray(
+                TestLayers::Layer0,
+                Point2::new(-1.5, 0.2),
+                Point2::new(1.5, 2.8),
+                &OutOfBounds::Error,
+            )
+            .unwrap();
+        assert_eq!(ray.first().unwrap().0, Point2::new(-2, 0));
+        assert_eq!(ray.last().unwrap().0, Point2::new(1, 2));
+        assert!(ray.windows(2).all(|w| (w[1].0 - w[0].0).abs().sum() == 1));
+
+        let ray = map
+            .ray(
+                TestLayers::Layer0,
+                Point2::new(0.5, 0.5),
+                Point2::new(0.5, 5.5),
+                &OutOfBounds::Clamp,
+            )
+            .unwrap();
+        let values: Vec<_> = ray.iter().map(|(_, v)| *v).collect();
+        assert_eq!(values, vec![2.0, 12.0, 22.0, 22.0, 22.0, 22.0]);
+
+        assert!(matches!(
+            map.ray(
+                TestLayers::Layer0,
+                Point2::new(0.5, 0.5),
+                Point2::new(0.5, 5.5),
+                &OutOfBounds::Error
+            ),
+            Err(Error::PositionOutsideMap(_, _))
+        ));
+    }
+
+    #[test]
+    fn interpolate() {
+        let map = new_map();
+        let error = OutOfBounds::Error;
+
+        // At cell centres the value is exact
+        assert_f64_eq!(
+            map.interpolate(TestLayers::Layer0, Point2::new(-0.5, 1.5), &error)
+                .unwrap(),
+            11.0,
+            1e-12
+        );
+        assert_f64_eq!(
+            map.interpolate(TestLayers::Layer0, Point2::new(-1.25, 1.0), &error)
+                .unwrap(),
+            5.25,
+            1e-12
+        );
+
+        // Near the edge the cells outside the map are used
+        assert!(map
+            .interpolate(TestLayers::Layer0, Point2::new(-1.8, 1.5), &error)
+            .is_err());
+        assert_f64_eq!(
+            map.interpolate(
+                TestLayers::Layer0,
+                Point2::new(-2.0, 1.5),
+                &OutOfBounds::Clamp
+            )
+            .unwrap(),
+            10.0,
+            1e-12
+        );
+        assert_f64_eq!(
+            map.interpolate(
+                TestLayers::Layer0,
+                Point2::new(-2.0, 1.5),
+                &OutOfBounds::Fill(0.0)
+            )
+            .unwrap(),
+            5.0,
+            1e-12
+        );
+
+        // NaN cells only affect positions interpolated from them
+        let mut map = map;
+        map[(TestLayers::Layer0, Point2::new(0, 0))] = f64::NAN;
+        map[(TestLayers::Layer0, Point2::new(2, 2))] = f64::NAN;
+        assert_eq!(
+            map.interpolate(TestLayers::Layer0, Point2::new(-0.5, 1.5), &error)
+                .unwrap(),
+            11.0
+        );
+        assert!(map
+            .interpolate(TestLayers::Layer0, Point2::new(-1.25, 1.0), &error)
+            .unwrap()
+            .is_nan());
+    }
+}
@@ -0,0 +1,189 @@
+//! Provides conversion of height layers into point clouds, for example to register a map against
+//! incoming scans with ICP.
+//!
+//! Writing point clouds to PLY and PCD files requires the `point_cloud_io` feature.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(feature = "point_cloud_io")]
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use nalgebra::{Point2, Point3};
+
+#[cfg(feature = "point_cloud_io")]
+use crate::Error;
+use crate::{CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// File formats supported by [`write_point_cloud()`].
+#[cfg(feature = "point_cloud_io")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointCloudFormat {
+    /// ASCII Polygon File Format (Stanford PLY).
+    Ply,
+
+    /// ASCII Point Cloud Data format, as used by the Point Cloud Library.
+    Pcd,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Converts the given height layer into a point cloud, with one point for each valid
+    /// (non-`NaN`) cell.
+    ///
+    /// Each point is at the centre of its cell in the parent frame, with the cell's value as its
+    /// `z` coordinate. Points are in the same order as the cells in [`CellMap::iter()`].
+    pub fn to_point_cloud(&self, height_layer: L) -> Vec<Point3<f64>> {
+        self[height_layer]
+            .indexed_iter()
+            .filter(|(_, height)| !height.is_nan())
+            .map(|((y, x), &height)| {
+                let position = self.position_unchecked(Point2::new(x, y));
+                Point3::new(position.x, position.y, height)
+            })
+            .collect()
+    }
+
+    /// Writes the point cloud of the given height layer to the given path, see
+    /// [`CellMap::to_point_cloud()`].
+    #[cfg(feature = "point_cloud_io")]
+    pub fn export_point_cloud<P: AsRef<Path>>(
+        &self,
+        height_layer: L,
+        path: P,
+        format: PointCloudFormat,
+    ) -> Result<(), Error> {
+        write_point_cloud(path, &self.to_point_cloud(height_layer), format)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Writes the given points to the given path in the given format.
+#[cfg(feature = "point_cloud_io")]
+pub fn write_point_cloud<P: AsRef<Path>>(
+    path: P,
+    points: &[Point3<f64>],
+    format: PointCloudFormat,
+) -> Result<(), Error> {
+    let file = File::create(path).map_err(Error::IoError)?;
+    let mut writer = BufWriter::new(file);
+
+    write_points(&mut writer, points, format)
+        .and_then(|_| writer.flush())
+        .map_err(Error::IoError)
+}
+
+#[cfg(feature = "point_cloud_io")]
+fn write_points<W: Write>(
+    writer: &mut W,
+    points: &[Point3<f64>],
+    format: PointCloudFormat,
+) -> std::io::Result<()> {
+    match format {
+        PointCloudFormat::Ply => {
+            writeln!(writer, "ply")?;
+            writeln!(writer, "format ascii 1.0")?;
+            writeln!(writer, "element vertex {}", points.len())?;
+            writeln!(writer, "property double x")?;
+            writeln!(writer, "property double y")?;
+            writeln!(writer, "property double z")?;
+            writeln!(writer, "end_header")?;
+        }
+        PointCloudFormat::Pcd => {
+            writeln!(writer, "# .PCD v0.7 - Point Cloud Data file format")?;
+            writeln!(writer, "VERSION 0.7")?;
+            writeln!(writer, "FIELDS x y z")?;
+            writeln!(writer, "SIZE 8 8 8")?;
+            writeln!(writer, "TYPE F F F")?;
+            writeln!(writer, "COUNT 1 1 1")?;
+            writeln!(writer, "WIDTH {}", points.len())?;
+            writeln!(writer, "HEIGHT 1")?;
+            writeln!(writer, "VIEWPOINT 0 0 0 1 0 0 0")?;
+            writeln!(writer, "POINTS {}", points.len())?;
+            writeln!(writer, "DATA ascii")?;
+        }
+    }
+
+    for p in points {
+        writeln!(writer, "{} {} {}", p.x, p.y, p.z)?;
+    }
+
+    Ok(())
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    fn new_map() -> CellMap<TestLayers, f64> {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-1, 1), (0, 2)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            2.0,
+        );
+        map[(TestLayers::Layer0, Point2::new(1, 0))] = f64::NAN;
+        map
+    }
+
+    #[test]
+    fn to_point_cloud() {
+        let points = new_map().to_point_cloud(TestLayers::Layer0);
+
+        assert_eq!(
+            points,
+            vec![
+                Point3::new(-0.25, 0.25, 2.0),
+                Point3::new(-0.25, 0.75, 2.0),
+                Point3::new(0.25, 0.75, 2.0),
+            ]
+        );
+    }
+
+    #[cfg(feature = "point_cloud_io")]
+    #[test]
+    fn write_point_cloud() {
+        let map = new_map();
+        let path = std::env::temp_dir().join("cell_map_point_cloud.pcd");
+
+        map.export_point_cloud(TestLayers::Layer0, &path, PointCloudFormat::Pcd)
+            .unwrap();
+        let pcd = std::fs::read_to_string(&path).unwrap();
+        assert!(pcd.contains("\nPOINTS 3\nDATA ascii\n-0.25 0.25 2\n"));
+
+        map.export_point_cloud(TestLayers::Layer0, &path, PointCloudFormat::Ply)
+            .unwrap();
+        let ply = std::fs::read_to_string(&path).unwrap();
+        assert!(ply.starts_with("ply\nformat ascii 1.0\nelement vertex 3\n"));
+        assert!(ply.ends_with("end_header\n-0.25 0.25 2\n-0.25 0.75 2\n0.25 0.75 2\n"));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
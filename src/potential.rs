@@ -0,0 +1,293 @@
+//! Provides potential fields, navigation functions which give the cost of the cheapest path from
+//! every cell to a goal.
+//!
+//! The field is computed with Dijkstra's algorithm from the goal cell over the 8-connected grid
+//! of cells (a weighted brushfire). Moving between two cells costs the distance between their
+//! centres, scaled by one plus the mean of their costs, so paths prefer cheap cells but will cross
+//! expensive ones when the detour would cost more. Since the field has no local minima other than
+//! the goal, a controller can reach the goal from any reachable cell by repeatedly moving to the
+//! neighbour with the lowest potential, see [`steepest_descent()`].
+//!
+//! The same expansion, limited to a maximum path cost, gives the cells which can be reached from
+//! a start cell with [`CellMap::reachable_from()`], for example to show where a rover can go with
+//! the energy it has left.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+};
+
+use nalgebra::Point2;
+use ndarray::{Array2, ArrayView2};
+
+use crate::{math, CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Cells with a cost at or above this value are impassable, matching the lethal cost of
+/// [`CellMap::inflate()`] and [`CellMap::traversability_cost()`].
+pub const LETHAL_COST: f64 = 1.0;
+
+/// The offsets of the 8 neighbours of a cell.
+const NEIGHBOURS: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// An entry in a min-heap of cells, such as the Dijkstra queue, ordered by value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Entry {
+    pub(crate) value: f64,
+    pub(crate) index: (usize, usize),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value.total_cmp(&other.value)
+    }
+}
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Computes the potential field towards `goal`, given in the parent frame, over the costs in
+    /// `obstacle_layer`.
+    ///
+    /// The returned array has the same shape as the map's layers and holds the cost of the
+    /// cheapest path from each cell to the goal cell. Cells with a cost of at least
+    /// [`LETHAL_COST`] or a `NaN` cost are impassable, and they and any cells which cannot reach
+    /// the goal have infinite potential. Costs are expected to be non-negative.
+    ///
+    /// Returns [`Error::PositionOutsideMap`] if the goal is outside the map.
+    pub fn potential_field(
+        &self,
+        goal: Point2<f64>,
+        obstacle_layer: L,
+    ) -> Result<Array2<f64>, Error> {
+        let goal_index = self
+            .index(goal)
+            .ok_or_else(|| Error::PositionOutsideMap("goal".into(), goal))?;
+
+        Ok(self.expand(goal_index, obstacle_layer, f64::INFINITY))
+    }
+
+    /// Returns a mask of the cells which can be reached from `start`, given in the parent frame,
+    /// with a path cost of at most `max_cost` over the costs in `cost_layer`.
+    ///
+    /// Path costs are calculated in the same way as [`CellMap::potential_field()`], but expansion
+    /// stops at `max_cost`, so this is cheaper than computing the whole field when only nearby
+    /// cells are reachable. The start cell is reachable unless it is impassable.
+    ///
+    /// Returns [`Error::PositionOutsideMap`] if the start is outside the map.
+    pub fn reachable_from(
+        &self,
+        start: Point2<f64>,
+        cost_layer: L,
+        max_cost: f64,
+    ) -> Result<Array2<bool>, Error> {
+        let start_index = self
+            .index(start)
+            .ok_or_else(|| Error::PositionOutsideMap("start".into(), start))?;
+
+        Ok(self
+            .expand(start_index, cost_layer, max_cost)
+            .mapv(|p| p <= max_cost))
+    }
+
+    /// Runs Dijkstra's algorithm from `start` over the costs in `layer`, returning the cost of the
+    /// cheapest path to each cell. Cells whose cost would exceed `limit` are not expanded and are
+    /// left infinite.
+    fn expand(&self, start: Point2<usize>, layer: L, limit: f64) -> Array2<f64> {
+        let cost = &self[layer];
+        let (rows, cols) = cost.dim();
+        let cell_size = self.cell_size();
+        let passable = |c: f64| c < LETHAL_COST;
+
+        let mut field = Array2::from_elem((rows, cols), f64::INFINITY);
+        let mut queue = BinaryHeap::new();
+
+        let start = (start.y, start.x);
+        if passable(cost[start]) {
+            field[start] = 0.0;
+            queue.push(Reverse(Entry {
+                value: 0.0,
+                index: start,
+            }));
+        }
+
+        while let Some(Reverse(Entry {
+            value: potential,
+            index,
+        })) = queue.pop()
+        {
+            // Skip entries which were superseded by a cheaper path
+            if potential > field[index] {
+                continue;
+            }
+
+            for (dx, dy) in NEIGHBOURS.iter() {
+                let (y, x) = (index.0 as isize + dy, index.1 as isize + dx);
+                if y < 0 || x < 0 || y >= rows as isize || x >= cols as isize {
+                    continue;
+                }
+                let neighbour = (y as usize, x as usize);
+
+                if !passable(cost[neighbour]) {
+                    continue;
+                }
+
+                let distance = math::hypot(*dx as f64 * cell_size.x, *dy as f64 * cell_size.y);
+                let next = potential + distance * (1.0 + (cost[index] + cost[neighbour]) / 2.0);
+
+                if next <= limit && next < field[neighbour] {
+                    field[neighbour] = next;
+                    queue.push(Reverse(Entry {
+                        value: next,
+                        index: neighbour,
+                    }));
+                }
+            }
+        }
+
+        field
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Returns the neighbour of `index` with the lowest potential in `field`, if it is lower than the
+/// potential of `index` itself.
+///
+/// Returns `None` if `index` is outside the field, is the goal, or cannot reach the goal.
+pub fn steepest_descent(field: ArrayView2<'_, f64>, index: Point2<usize>) -> Option<Point2<usize>> {
+    let current = *field.get((index.y, index.x))?;
+
+    NEIGHBOURS
+        .iter()
+        .filter_map(|(dx, dy)| {
+            let x = index.x.checked_add_signed(*dx)?;
+            let y = index.y.checked_add_signed(*dy)?;
+            field.get((y, x)).map(|&p| (Point2::new(x, y), p))
+        })
+        .filter(|&(_, p)| p < current)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn potential_field() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 5), (0, 5)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // A wall along x = 2, with a gap at the top
+        for y in 0..4 {
+            map[(TestLayers::Layer0, Point2::new(2, y))] = 1.0;
+        }
+
+        let field = map
+            .potential_field(Point2::new(0.5, 0.5), TestLayers::Layer0)
+            .unwrap();
+        assert_eq!(field[[0, 0]], 0.0);
+        assert_eq!(field[[0, 1]], 1.0);
+        assert!((field[[1, 1]] - 2f64.sqrt()).abs() < 1e-12);
+        assert!(field[[0, 2]].is_infinite());
+
+        // Cells behind the wall have to go round through the gap
+        assert!(field[[0, 3]] > 6.0 && field[[0, 3]].is_finite());
+
+        // Following the steepest descent from behind the wall reaches the goal
+        let mut index = Point2::new(4, 0);
+        let mut steps = 0;
+        while let Some(next) = steepest_descent(field.view(), index) {
+            index = next;
+            steps += 1;
+        }
+        assert_eq!(index, Point2::new(0, 0));
+        assert!(steps >= 8);
+
+        assert!(matches!(
+            map.potential_field(Point2::new(5.5, 0.5), TestLayers::Layer0),
+            Err(Error::PositionOutsideMap(_, _))
+        ));
+    }
+
+    #[test]
+    fn reachable_from() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 5), (0, 5)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+        map[(TestLayers::Layer0, Point2::new(1, 0))] = 1.0;
+        map[(TestLayers::Layer0, Point2::new(0, 1))] = 0.5;
+
+        let reachable = map
+            .reachable_from(Point2::new(0.5, 0.5), TestLayers::Layer0, 1.5)
+            .unwrap();
+        assert!(reachable[[0, 0]]);
+        assert!(!reachable[[0, 1]]);
+        assert!(reachable[[1, 0]]);
+        assert!(reachable[[1, 1]]);
+        assert!(!reachable[[2, 2]]);
+        assert_eq!(reachable.iter().filter(|&&r| r).count(), 3);
+
+        // Lethal start cells reach nothing
+        map[(TestLayers::Layer0, Point2::new(0, 0))] = 1.0;
+        let reachable = map
+            .reachable_from(Point2::new(0.5, 0.5), TestLayers::Layer0, 100.0)
+            .unwrap();
+        assert!(reachable.iter().all(|&r| !r));
+
+        assert!(matches!(
+            map.reachable_from(Point2::new(0.5, -0.5), TestLayers::Layer0, 1.0),
+            Err(Error::PositionOutsideMap(_, _))
+        ));
+    }
+}
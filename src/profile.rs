@@ -0,0 +1,232 @@
+//! Provides 1D slices of a layer: the rows and columns of the map, and profiles along arbitrary
+//! lines through it, see [`CellMap::profile_along()`].
+//!
+//! These are intended for extracting cross-sections of terrain, either to plot or to feed 1D
+//! controllers, without the caller having to track cell positions.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::Point2;
+use ndarray::{ArrayView1, Axis};
+use serde::{Deserialize, Serialize};
+
+use crate::{CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A single cell of a profile along a line, see [`CellMap::profile_along()`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ProfileSample<T> {
+    /// The distance along the line from its start to the closest point to the cell's centre, in
+    /// parent-frame units.
+    pub distance: f64,
+
+    /// The closest point on the line to the cell's centre, in the parent frame.
+    pub position: Point2<f64>,
+
+    /// The index of the cell.
+    pub index: Point2<usize>,
+
+    /// The value of the cell.
+    pub value: T,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+{
+    /// Returns a view of row `y` of `layer`, which contains the cells with index `(x, y)` for
+    /// every `x`, or `None` if the row is outside the map.
+    pub fn row(&self, layer: L, y: usize) -> Option<ArrayView1<'_, T>> {
+        if y < self.num_cells().y {
+            Some(self[layer].index_axis(Axis(0), y))
+        } else {
+            None
+        }
+    }
+
+    /// Returns a view of column `x` of `layer`, which contains the cells with index `(x, y)` for
+    /// every `y`, or `None` if the column is outside the map.
+    pub fn col(&self, layer: L, x: usize) -> Option<ArrayView1<'_, T>> {
+        if x < self.num_cells().x {
+            Some(self[layer].index_axis(Axis(1), x))
+        } else {
+            None
+        }
+    }
+
+    /// Returns an iterator over the parent-frame position of the centre and the value of each
+    /// cell in row `y` of `layer`, in order of increasing `x` index.
+    ///
+    /// The iterator is empty if the row is outside the map.
+    pub fn row_iter(&self, layer: L, y: usize) -> impl Iterator<Item = (Point2<f64>, &T)> + '_ {
+        self.row(layer, y)
+            .into_iter()
+            .flat_map(|row| row.into_iter().enumerate())
+            .map(move |(x, v)| (self.position_unchecked(Point2::new(x, y)), v))
+    }
+
+    /// Returns an iterator over the parent-frame position of the centre and the value of each
+    /// cell in column `x` of `layer`, in order of increasing `y` index.
+    ///
+    /// The iterator is empty if the column is outside the map.
+    pub fn col_iter(&self, layer: L, x: usize) -> impl Iterator<Item = (Point2<f64>, &T)> + '_ {
+        self.col(layer, x)
+            .into_iter()
+            .flat_map(|col| col.into_iter().enumerate())
+            .map(move |(y, v)| (self.position_unchecked(Point2::new(x, y)), v))
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    /// Returns the profile of `layer` along the line from `start` to `end`, which are positions in
+    /// the parent frame, with one sample for each cell the line passes through in order from
+    /// `start`.
+    ///
+    /// Returns an error if either end of the line is outside the map, as with
+    /// [`CellMap::line_iter()`].
+    pub fn profile_along(
+        &self,
+        layer: L,
+        start: Point2<f64>,
+        end: Point2<f64>,
+    ) -> Result<Vec<ProfileSample<T>>, Error> {
+        let line = end - start;
+        let length = line.norm();
+
+        Ok(self
+            .line_iter(start, end)?
+            .layer(layer)
+            .indexed()
+            .map(|((_, index), value)| {
+                // Project the cell's centre onto the line, keeping the point between the ends
+                let centre = self.position_unchecked(index);
+                let distance = if length > 0.0 {
+                    ((centre - start).dot(&line) / length).clamp(0.0, length)
+                } else {
+                    0.0
+                };
+                let position = if length > 0.0 {
+                    start + line * (distance / length)
+                } else {
+                    start
+                };
+
+                ProfileSample {
+                    distance,
+                    position,
+                    index,
+                    value: value.clone(),
+                }
+            })
+            .collect())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    fn new_map() -> CellMap<TestLayers, f64> {
+        let mut map = CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 4), (0, 3)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                position_in_parent: Vector2::new(1.0, 0.0),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        for ((_, index), v) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            *v = (index.x + 10 * index.y) as f64;
+        }
+
+        map
+    }
+
+    #[test]
+    fn rows_and_cols() {
+        let map = new_map();
+
+        assert_eq!(
+            map.row(TestLayers::Layer0, 1).unwrap().to_vec(),
+            vec![10.0, 11.0, 12.0, 13.0]
+        );
+        assert_eq!(
+            map.col(TestLayers::Layer0, 2).unwrap().to_vec(),
+            vec![2.0, 12.0, 22.0]
+        );
+        assert!(map.row(TestLayers::Layer0, 3).is_none());
+        assert!(map.col(TestLayers::Layer0, 4).is_none());
+
+        let row: Vec<_> = map.row_iter(TestLayers::Layer0, 2).collect();
+        assert_eq!(row.len(), 4);
+        assert_eq!(row[3], (Point2::new(2.75, 1.25), &23.0));
+
+        let col: Vec<_> = map.col_iter(TestLayers::Layer0, 0).collect();
+        assert_eq!(col.len(), 3);
+        assert_eq!(col[1], (Point2::new(1.25, 0.75), &10.0));
+
+        assert_eq!(map.row_iter(TestLayers::Layer0, 5).count(), 0);
+    }
+
+    #[test]
+    fn profile_along() {
+        let map = new_map();
+
+        // A horizontal line through the middle of row 1
+        let profile = map
+            .profile_along(
+                TestLayers::Layer0,
+                Point2::new(1.1, 0.75),
+                Point2::new(2.9, 0.75),
+            )
+            .unwrap();
+        let values: Vec<_> = profile.iter().map(|s| s.value).collect();
+        assert_eq!(values, vec![10.0, 11.0, 12.0, 13.0]);
+        assert_f64_eq!(profile[0].distance, 0.15, 1e-12);
+        assert_f64_eq!(profile[2].distance, 1.15, 1e-12);
+        assert_eq!(profile[2].index, Point2::new(2, 1));
+        assert_f64_eq!(profile[2].position.x, 2.25, 1e-12);
+
+        // Cells are sampled in order from the start of the line
+        let profile = map
+            .profile_along(
+                TestLayers::Layer0,
+                Point2::new(2.9, 1.4),
+                Point2::new(1.1, 0.1),
+            )
+            .unwrap();
+        assert!(profile.windows(2).all(|w| w[0].distance <= w[1].distance));
+        assert_eq!(profile.first().unwrap().index, Point2::new(3, 2));
+        assert_eq!(profile.last().unwrap().index, Point2::new(0, 0));
+
+        assert!(map
+            .profile_along(
+                TestLayers::Layer0,
+                Point2::new(0.0, 0.0),
+                Point2::new(2.0, 1.0)
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,544 @@
+//! Provides encoding and decoding of maps as protobuf messages, for pipelines where JSON is too
+//! large, such as telemetry downlinks.
+//!
+//! The messages in this module are defined by the schema in [`PROTO_SCHEMA`], which can be used
+//! to generate decoders for the same messages in other languages. Maps can be converted to and
+//! from [`CellMapProto`] either directly, with [`CellMap::to_protobuf()`] and
+//! [`CellMap::from_protobuf()`], or through a [`CellMapFile`]. Only cell types implementing
+//! [`ProtobufValue`] can be encoded.
+//!
+//! This module requires the `protobuf` feature.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::convert::TryFrom;
+
+use nalgebra::Vector2;
+use ndarray::Array2;
+use prost::Message;
+
+use crate::{
+    cell_map::{BoundaryRule, Bounds, CellOrigin, GridConvention, YAxis},
+    cell_map_file::CellMapFile,
+    map_metadata::CellMapMetadata,
+    CellMap, CellMapParams, Error, Layer,
+};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The protobuf schema defining the messages in this module.
+pub const PROTO_SCHEMA: &str = include_str!("../proto/cell_map.proto");
+
+// ------------------------------------------------------------------------------------------------
+// TRAITS
+// ------------------------------------------------------------------------------------------------
+
+/// Trait for cell values which can be stored in a [`LayerProto`].
+pub trait ProtobufValue: Sized {
+    /// Converts the values of a layer, in row-major order, into their protobuf representation.
+    fn to_layer_values(values: Vec<Self>) -> LayerValues;
+
+    /// Converts the protobuf representation of a layer back into its values, returning `None` if
+    /// the layer holds a different type of value.
+    fn from_layer_values(values: LayerValues) -> Option<Vec<Self>>;
+}
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A whole map, equivalent to a [`CellMapFile`].
+#[derive(Clone, PartialEq, Message)]
+pub struct CellMapProto {
+    /// The bounds of the map.
+    #[prost(message, optional, tag = "1")]
+    pub cell_bounds: Option<BoundsProto>,
+
+    /// The size of each cell in the `x` direction, in parent-frame units.
+    #[prost(double, tag = "2")]
+    pub cell_size_x: f64,
+
+    /// The size of each cell in the `y` direction, in parent-frame units.
+    #[prost(double, tag = "3")]
+    pub cell_size_y: f64,
+
+    /// The precision used when calculating cell boundaries, relative to the cell size.
+    #[prost(double, tag = "4")]
+    pub cell_boundary_precision: f64,
+
+    /// The rotation of the map in its parent frame, in radians.
+    #[prost(double, tag = "5")]
+    pub rotation_in_parent_rad: f64,
+
+    /// The `x` position of the map's origin in its parent frame.
+    #[prost(double, tag = "6")]
+    pub position_in_parent_x: f64,
+
+    /// The `y` position of the map's origin in its parent frame.
+    #[prost(double, tag = "7")]
+    pub position_in_parent_y: f64,
+
+    /// Each layer of the map, in [`Layer::to_index()`] order.
+    #[prost(message, repeated, tag = "8")]
+    pub layers: Vec<LayerProto>,
+
+    /// `true` if the map's `y` axis is [`YAxis::Down`].
+    #[prost(bool, tag = "9")]
+    pub y_down: bool,
+
+    /// `true` if the map's cell origin is [`CellOrigin::Centre`].
+    #[prost(bool, tag = "10")]
+    pub origin_at_centre: bool,
+
+    /// The map's [`BoundaryRule`], where `0` is the default [`BoundaryRule::EpsilonSnap`], `1` is
+    /// [`BoundaryRule::HalfOpen`] and `2` is [`BoundaryRule::RoundNearest`].
+    #[prost(uint32, tag = "11")]
+    pub boundary_rule: u32,
+}
+
+/// The half-open bounds of a map, equivalent to [`Bounds`].
+#[derive(Clone, Copy, PartialEq, Message)]
+pub struct BoundsProto {
+    /// The minimum `x` index.
+    #[prost(sint64, tag = "1")]
+    pub x_min: i64,
+
+    /// The maximum `x` index, exclusive.
+    #[prost(sint64, tag = "2")]
+    pub x_max: i64,
+
+    /// The minimum `y` index.
+    #[prost(sint64, tag = "3")]
+    pub y_min: i64,
+
+    /// The maximum `y` index, exclusive.
+    #[prost(sint64, tag = "4")]
+    pub y_max: i64,
+}
+
+/// The values of every cell in a single layer, in row-major (`y`, `x`) order.
+#[derive(Clone, PartialEq, Message)]
+pub struct LayerProto {
+    /// The values of the layer, or `None` if they weren't set.
+    #[prost(oneof = "LayerValues", tags = "1, 2, 3, 4, 5, 6")]
+    pub values: Option<LayerValues>,
+}
+
+/// Values of a layer of `f64`s.
+#[derive(Clone, PartialEq, Message)]
+pub struct DoubleValues {
+    /// The values.
+    #[prost(double, repeated, tag = "1")]
+    pub values: Vec<f64>,
+}
+
+/// Values of a layer of `f32`s.
+#[derive(Clone, PartialEq, Message)]
+pub struct FloatValues {
+    /// The values.
+    #[prost(float, repeated, tag = "1")]
+    pub values: Vec<f32>,
+}
+
+/// Values of a layer of `bool`s.
+#[derive(Clone, PartialEq, Message)]
+pub struct BoolValues {
+    /// The values.
+    #[prost(bool, repeated, tag = "1")]
+    pub values: Vec<bool>,
+}
+
+/// Values of a layer of `i64`s.
+#[derive(Clone, PartialEq, Message)]
+pub struct Int64Values {
+    /// The values.
+    #[prost(sint64, repeated, tag = "1")]
+    pub values: Vec<i64>,
+}
+
+/// Values of a layer of `u64`s.
+#[derive(Clone, PartialEq, Message)]
+pub struct UInt64Values {
+    /// The values.
+    #[prost(uint64, repeated, tag = "1")]
+    pub values: Vec<u64>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// The typed values of a [`LayerProto`].
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum LayerValues {
+    /// A layer of `f64`s.
+    #[prost(message, tag = "1")]
+    Doubles(DoubleValues),
+
+    /// A layer of `f32`s.
+    #[prost(message, tag = "2")]
+    Floats(FloatValues),
+
+    /// A layer of `bool`s.
+    #[prost(message, tag = "3")]
+    Bools(BoolValues),
+
+    /// A layer of `i64`s.
+    #[prost(message, tag = "4")]
+    Int64s(Int64Values),
+
+    /// A layer of `u64`s.
+    #[prost(message, tag = "5")]
+    Uint64s(UInt64Values),
+
+    /// A layer of `u8`s.
+    #[prost(bytes = "vec", tag = "6")]
+    Uint8s(Vec<u8>),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: ProtobufValue + Clone,
+{
+    /// Converts the map into a protobuf message.
+    pub fn to_protobuf(&self) -> CellMapProto {
+        to_proto(&self.params, &self.data)
+    }
+
+    /// Encodes the map as protobuf bytes.
+    pub fn encode_protobuf(&self) -> Vec<u8> {
+        trace_span!(
+            "encode_protobuf",
+            num_cells = self.num_cells().iter().product::<usize>()
+        );
+        self.to_protobuf().encode_to_vec()
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: ProtobufValue,
+{
+    /// Builds a map from a protobuf message.
+    ///
+    /// The message must contain exactly one layer for each variant of `L`, with the right number
+    /// of values of type `T`.
+    pub fn from_protobuf(proto: CellMapProto) -> Result<Self, Error> {
+        let (params, data) = from_proto::<L, T>(proto)?;
+        CellMap::new_from_data(params, data)
+    }
+
+    /// Decodes a map from protobuf bytes produced by [`CellMap::encode_protobuf()`].
+    pub fn decode_protobuf(bytes: &[u8]) -> Result<Self, Error> {
+        trace_span!("decode_protobuf", num_bytes = bytes.len());
+        Self::from_protobuf(CellMapProto::decode(bytes).map_err(Error::ProtobufDecodeError)?)
+    }
+}
+
+impl<L, T> CellMapFile<L, T>
+where
+    L: Layer,
+    T: ProtobufValue + Clone,
+{
+    /// Converts the file into a protobuf message.
+    pub fn to_protobuf(&self) -> CellMapProto {
+        let params = CellMapParams {
+            cell_size: self.cell_size,
+            cell_bounds: self.cell_bounds,
+            rotation_in_parent_rad: self.from_parent_angle_rad,
+            position_in_parent: self.from_parent_translation,
+            cell_boundary_precision: self.cell_boundary_precision,
+            convention: self.convention,
+            boundary_rule: self.boundary_rule,
+        };
+
+        to_proto(&params, &self.data)
+    }
+}
+
+impl<L, T> CellMapFile<L, T>
+where
+    L: Layer,
+    T: ProtobufValue,
+{
+    /// Builds a file from a protobuf message, with the same requirements as
+    /// [`CellMap::from_protobuf()`].
+    pub fn from_protobuf(proto: CellMapProto) -> Result<Self, Error> {
+        let (params, data) = from_proto::<L, T>(proto)?;
+        let to_parent = CellMapMetadata::calc_to_parent(
+            params.position_in_parent,
+            params.rotation_in_parent_rad,
+            params.cell_size,
+            params.convention,
+        );
+
+        Ok(Self {
+            num_layers: L::NUM_LAYERS,
+            layers: L::all(),
+            cell_bounds: params.cell_bounds,
+            cell_size: params.cell_size,
+            cell_boundary_precision: params.cell_boundary_precision,
+            from_parent_angle_rad: params.rotation_in_parent_rad,
+            from_parent_translation: params.position_in_parent,
+            convention: params.convention,
+            boundary_rule: params.boundary_rule,
+            from_parent_matrix: to_parent.inverse(),
+            data,
+        })
+    }
+}
+
+impl From<Bounds> for BoundsProto {
+    fn from(bounds: Bounds) -> Self {
+        Self {
+            x_min: bounds.x.0 as i64,
+            x_max: bounds.x.1 as i64,
+            y_min: bounds.y.0 as i64,
+            y_max: bounds.y.1 as i64,
+        }
+    }
+}
+
+impl From<BoundsProto> for Bounds {
+    fn from(bounds: BoundsProto) -> Self {
+        Self {
+            x: (bounds.x_min as isize, bounds.x_max as isize),
+            y: (bounds.y_min as isize, bounds.y_max as isize),
+        }
+    }
+}
+
+/// Implements [`ProtobufValue`] for types stored in a message with a `values` field.
+macro_rules! impl_protobuf_value {
+    ($($t:ty => $variant:ident($message:ident)),*) => {
+        $(
+            impl ProtobufValue for $t {
+                fn to_layer_values(values: Vec<Self>) -> LayerValues {
+                    LayerValues::$variant($message { values })
+                }
+
+                fn from_layer_values(values: LayerValues) -> Option<Vec<Self>> {
+                    match values {
+                        LayerValues::$variant(m) => Some(m.values),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_protobuf_value!(
+    f64 => Doubles(DoubleValues),
+    f32 => Floats(FloatValues),
+    bool => Bools(BoolValues),
+    i64 => Int64s(Int64Values),
+    u64 => Uint64s(UInt64Values)
+);
+
+impl ProtobufValue for u8 {
+    fn to_layer_values(values: Vec<Self>) -> LayerValues {
+        LayerValues::Uint8s(values)
+    }
+
+    fn from_layer_values(values: LayerValues) -> Option<Vec<Self>> {
+        match values {
+            LayerValues::Uint8s(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Builds a message from the parameters and layers of a map.
+fn to_proto<T>(params: &CellMapParams, data: &[Array2<T>]) -> CellMapProto
+where
+    T: ProtobufValue + Clone,
+{
+    CellMapProto {
+        cell_bounds: Some(params.cell_bounds.into()),
+        cell_size_x: params.cell_size.x,
+        cell_size_y: params.cell_size.y,
+        cell_boundary_precision: params.cell_boundary_precision,
+        rotation_in_parent_rad: params.rotation_in_parent_rad,
+        position_in_parent_x: params.position_in_parent.x,
+        position_in_parent_y: params.position_in_parent.y,
+        y_down: params.convention.y_axis == YAxis::Down,
+        origin_at_centre: params.convention.origin == CellOrigin::Centre,
+        boundary_rule: params.boundary_rule.to_code() as u32,
+        layers: data
+            .iter()
+            .map(|layer| LayerProto {
+                // Iterate in logical order in case the layer isn't in standard layout
+                values: Some(T::to_layer_values(layer.iter().cloned().collect())),
+            })
+            .collect(),
+    }
+}
+
+/// Extracts the parameters and layers of a map from a message, checking that the layers match
+/// the map's layer and cell types.
+fn from_proto<L, T>(proto: CellMapProto) -> Result<(CellMapParams, Vec<Array2<T>>), Error>
+where
+    L: Layer,
+    T: ProtobufValue,
+{
+    let cell_bounds: Bounds = proto
+        .cell_bounds
+        .ok_or_else(|| Error::InvalidProtobuf("missing cell bounds".into()))?
+        .into();
+    if !cell_bounds.is_valid() {
+        return Err(Error::InvalidBounds(cell_bounds));
+    }
+
+    if proto.layers.len() != L::NUM_LAYERS {
+        return Err(Error::WrongNumberOfLayers(
+            L::NUM_LAYERS,
+            proto.layers.len(),
+        ));
+    }
+
+    let boundary_rule = proto.boundary_rule;
+    let shape = cell_bounds.get_shape();
+    let data = proto
+        .layers
+        .into_iter()
+        .enumerate()
+        .map(|(index, layer)| {
+            let values = layer.values.and_then(T::from_layer_values).ok_or_else(|| {
+                Error::InvalidProtobuf(format!("layer {} has the wrong type of values", index))
+            })?;
+            let num_values = values.len();
+
+            Array2::from_shape_vec(shape, values).map_err(|_| {
+                Error::InvalidProtobuf(format!(
+                    "expected {} values in layer {} but found {}",
+                    shape.0 * shape.1,
+                    index,
+                    num_values
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let params = CellMapParams {
+        cell_size: Vector2::new(proto.cell_size_x, proto.cell_size_y),
+        cell_bounds,
+        rotation_in_parent_rad: proto.rotation_in_parent_rad,
+        position_in_parent: Vector2::new(proto.position_in_parent_x, proto.position_in_parent_y),
+        cell_boundary_precision: proto.cell_boundary_precision,
+        convention: GridConvention {
+            y_axis: if proto.y_down { YAxis::Down } else { YAxis::Up },
+            origin: if proto.origin_at_centre {
+                CellOrigin::Centre
+            } else {
+                CellOrigin::Corner
+            },
+        },
+        boundary_rule: u8::try_from(boundary_rule)
+            .ok()
+            .and_then(BoundaryRule::from_code)
+            .ok_or_else(|| {
+                Error::InvalidProtobuf(format!("invalid boundary rule {}", boundary_rule))
+            })?,
+    };
+
+    Ok((params, data))
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+    use crate::test_utils::TestLayers;
+
+    fn new_params() -> CellMapParams {
+        CellMapParams {
+            cell_bounds: Bounds::new((-3, 5), (2, 6)).unwrap(),
+            cell_size: Vector2::new(0.25, 0.5),
+            rotation_in_parent_rad: 0.3,
+            position_in_parent: Vector2::new(1.0, -2.0),
+            convention: GridConvention {
+                y_axis: YAxis::Down,
+                origin: CellOrigin::Centre,
+            },
+            boundary_rule: BoundaryRule::HalfOpen,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(new_params(), 1.5);
+        map[(TestLayers::Layer0, Point2::new(2, 1))] = f64::NAN;
+        map[(TestLayers::Layer2, Point2::new(7, 3))] = -4.0;
+
+        let decoded = CellMap::<TestLayers, f64>::decode_protobuf(&map.encode_protobuf()).unwrap();
+        assert_eq!(
+            decoded.params().position_in_parent,
+            map.params().position_in_parent
+        );
+        assert_eq!(decoded.content_hash(), map.content_hash());
+
+        // Through a CellMapFile
+        let file = CellMapFile::<TestLayers, f64>::from_protobuf(map.to_protobuf()).unwrap();
+        // Compare encoded bytes, since the NaN cell makes the messages unequal
+        assert_eq!(file.to_protobuf().encode_to_vec(), map.encode_protobuf());
+        assert_eq!(
+            file.into_cell_map().unwrap().content_hash(),
+            map.content_hash()
+        );
+
+        let mut map = CellMap::<TestLayers, u8>::new_from_elem(new_params(), 0);
+        map[(TestLayers::Layer1, Point2::new(0, 3))] = 255;
+        let decoded = CellMap::<TestLayers, u8>::decode_protobuf(&map.encode_protobuf()).unwrap();
+        assert!(decoded.iter().eq(map.iter()));
+    }
+
+    #[test]
+    fn invalid_messages() {
+        let map = CellMap::<TestLayers, f64>::new_from_elem(new_params(), 0.0);
+
+        assert!(matches!(
+            CellMap::<TestLayers, f32>::from_protobuf(map.to_protobuf()),
+            Err(Error::InvalidProtobuf(_))
+        ));
+
+        let mut proto = map.to_protobuf();
+        proto.layers.pop();
+        assert!(matches!(
+            CellMap::<TestLayers, f64>::from_protobuf(proto),
+            Err(Error::WrongNumberOfLayers(3, 2))
+        ));
+
+        let mut proto = map.to_protobuf();
+        proto.cell_bounds = Some(Bounds::new((-3, 4), (2, 6)).unwrap().into());
+        assert!(matches!(
+            CellMap::<TestLayers, f64>::from_protobuf(proto),
+            Err(Error::InvalidProtobuf(_))
+        ));
+
+        assert!(matches!(
+            CellMap::<TestLayers, f64>::decode_protobuf(&[0xff, 0xff]),
+            Err(Error::ProtobufDecodeError(_))
+        ));
+    }
+}
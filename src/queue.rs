@@ -0,0 +1,223 @@
+//! Provides the [`CellQueue`] type, a bucketed priority queue of cell indices for building
+//! propagation algorithms, such as brushfire or Dijkstra's algorithm, on the grid.
+//!
+//! Entries are grouped into buckets by priority, so pushing an entry is constant time, and popping
+//! only has to sort the entries in a single bucket. The queue is a min-queue which pops entries in
+//! exactly ascending order of priority, so it can be used anywhere a binary heap would be. It's
+//! the queue used by [`IncrementalInflation`].
+//!
+//! [`IncrementalInflation`]: crate::filters::IncrementalInflation
+//!
+//! ```
+//! use cell_map::queue::CellQueue;
+//! use nalgebra::Point2;
+//!
+//! let mut queue = CellQueue::new(1.0);
+//! queue.push(Point2::new(3, 1), 2.5);
+//! queue.push(Point2::new(0, 0), 0.0);
+//! queue.push(Point2::new(2, 2), 2.25);
+//!
+//! assert_eq!(queue.pop(), Some((Point2::new(0, 0), 0.0)));
+//! assert_eq!(queue.pop(), Some((Point2::new(2, 2), 2.25)));
+//! assert_eq!(queue.pop(), Some((Point2::new(3, 1), 2.5)));
+//! assert_eq!(queue.pop(), None);
+//! ```
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::Point2;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A min-priority queue of cell indices, see the [module documentation](self).
+///
+/// Priorities must be finite and non-negative, such as distances or path costs. The queue holds
+/// one bucket for every `bucket_width` of priority up to the largest pushed, so the bucket width
+/// should be around the smallest step between priorities, such as the smallest cell size for a
+/// brushfire.
+///
+/// The queue doesn't remove duplicate indices, so an index can be pushed again with a lower
+/// priority instead of decreasing its key. The usual approach is to skip popped entries whose
+/// priority is higher than the best known for the cell.
+#[derive(Debug, Clone)]
+pub struct CellQueue {
+    bucket_width: f64,
+    buckets: Vec<Vec<(f64, Point2<usize>)>>,
+
+    /// The lowest bucket which may contain entries.
+    current: usize,
+
+    /// Whether the current bucket is sorted by descending priority.
+    sorted: bool,
+
+    len: usize,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl CellQueue {
+    /// Creates a new empty queue with the given bucket width.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bucket_width` is not positive and finite.
+    pub fn new(bucket_width: f64) -> Self {
+        assert!(
+            bucket_width > 0.0 && bucket_width.is_finite(),
+            "The bucket width of a CellQueue must be positive and finite"
+        );
+
+        Self {
+            bucket_width,
+            buckets: Vec::new(),
+            current: 0,
+            sorted: false,
+            len: 0,
+        }
+    }
+
+    /// Returns the bucket width of the queue.
+    pub fn bucket_width(&self) -> f64 {
+        self.bucket_width
+    }
+
+    /// Returns the number of entries in the queue.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes every entry from the queue, keeping its allocated buckets.
+    pub fn clear(&mut self) {
+        self.buckets.iter_mut().for_each(Vec::clear);
+        self.current = 0;
+        self.sorted = false;
+        self.len = 0;
+    }
+
+    /// Adds `index` to the queue with the given priority.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `priority` is negative or not finite.
+    pub fn push(&mut self, index: Point2<usize>, priority: f64) {
+        assert!(
+            priority >= 0.0 && priority.is_finite(),
+            "CellQueue priorities must be non-negative and finite, got {}",
+            priority
+        );
+
+        let bucket = (priority / self.bucket_width) as usize;
+        if bucket >= self.buckets.len() {
+            self.buckets.resize_with(bucket + 1, Vec::new);
+        }
+
+        if bucket < self.current {
+            self.current = bucket;
+            self.sorted = false;
+        }
+
+        let entries = &mut self.buckets[bucket];
+        if bucket == self.current && self.sorted {
+            // Keep the current bucket sorted so it doesn't have to be sorted again
+            let at = entries.partition_point(|(p, _)| *p > priority);
+            entries.insert(at, (priority, index));
+        } else {
+            entries.push((priority, index));
+        }
+
+        self.len += 1;
+    }
+
+    /// Removes and returns the index with the lowest priority, and its priority, or `None` if the
+    /// queue is empty.
+    ///
+    /// Entries with equal priority are returned in an unspecified order.
+    pub fn pop(&mut self) -> Option<(Point2<usize>, f64)> {
+        if self.len == 0 {
+            return None;
+        }
+
+        while self.buckets[self.current].is_empty() {
+            self.current += 1;
+            self.sorted = false;
+        }
+
+        let entries = &mut self.buckets[self.current];
+        if !self.sorted {
+            entries.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+            self.sorted = true;
+        }
+
+        let (priority, index) = entries.pop()?;
+        self.len -= 1;
+
+        // Start from the first bucket again once the queue is empty
+        if self.len == 0 {
+            self.current = 0;
+            self.sorted = false;
+        }
+
+        Some((index, priority))
+    }
+
+    /// Returns the lowest priority in the queue, or `None` if the queue is empty.
+    pub fn peek_priority(&self) -> Option<f64> {
+        self.buckets[self.current..]
+            .iter()
+            .find(|entries| !entries.is_empty())?
+            .iter()
+            .map(|(p, _)| *p)
+            .min_by(f64::total_cmp)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordering() {
+        let mut queue = CellQueue::new(0.5);
+        let priorities = [3.2, 0.1, 7.0, 0.4, 3.1, 0.1, 12.5, 3.3];
+
+        for (i, &p) in priorities.iter().enumerate() {
+            queue.push(Point2::new(i, 0), p);
+        }
+        assert_eq!(queue.len(), 8);
+        assert_eq!(queue.peek_priority(), Some(0.1));
+
+        // Pushing into and below the bucket being popped from
+        assert_eq!(queue.pop().map(|(_, p)| p), Some(0.1));
+        queue.push(Point2::new(8, 0), 0.2);
+        queue.push(Point2::new(9, 0), 0.0);
+        assert_eq!(queue.pop(), Some((Point2::new(9, 0), 0.0)));
+
+        let popped: Vec<_> = std::iter::from_fn(|| queue.pop()).map(|(_, p)| p).collect();
+        assert_eq!(popped, vec![0.1, 0.2, 0.4, 3.1, 3.2, 3.3, 7.0, 12.5]);
+        assert!(queue.is_empty());
+        assert_eq!(queue.peek_priority(), None);
+
+        // The queue can be reused once empty or cleared
+        queue.push(Point2::new(1, 1), 1.0);
+        queue.push(Point2::new(2, 2), 5.0);
+        assert_eq!(queue.pop(), Some((Point2::new(1, 1), 1.0)));
+        queue.clear();
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop(), None);
+    }
+}
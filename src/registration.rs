@@ -0,0 +1,438 @@
+//! Provides methods for registering maps against each other, such as localising a known terrain
+//! patch within a map with [`CellMap::match_template()`], or co-registering overlapping maps with
+//! [`estimate_alignment()`].
+//!
+//! Values are read through [`CompareValue::as_f64()`], so cells which are invalid in either the
+//! map or the template, such as `NaN` cells, are ignored when scoring a match.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Isometry2, Point2, Vector2};
+use ndarray::{Array2, ArrayView2};
+use serde::{Deserialize, Serialize};
+
+use crate::{comparison::CompareValue, CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Parameters which control the search performed by [`estimate_alignment()`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AlignmentParams {
+    /// The largest translation searched along each axis, in parent-frame units.
+    ///
+    /// # Default
+    ///
+    /// The default value is `1.0`.
+    pub max_translation: f64,
+
+    /// The spacing of the coarse search over translations, in parent-frame units.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.25`.
+    pub translation_step: f64,
+
+    /// The largest rotation searched in either direction, in radians.
+    ///
+    /// # Default
+    ///
+    /// The default value is 10 degrees.
+    pub max_rotation: f64,
+
+    /// The spacing of the coarse search over rotations, in radians.
+    ///
+    /// # Default
+    ///
+    /// The default value is 2.5 degrees.
+    pub rotation_step: f64,
+
+    /// The number of times the search is refined around the best alignment, halving both steps
+    /// each time.
+    ///
+    /// # Default
+    ///
+    /// The default value is `4`.
+    pub refinement_levels: usize,
+
+    /// The fraction of the valid cells in the first map which must overlap valid cells in the
+    /// second map for an alignment to be considered.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.5`.
+    pub min_overlap: f64,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// How a template is scored against the map by [`CellMap::match_template()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MatchMethod {
+    /// The sum of squared differences between the template and the map, divided by the number of
+    /// cells compared so that positions with missing cells aren't favoured. Lower scores are
+    /// better matches, with `0.0` being an exact match.
+    SumSquaredDifference,
+
+    /// The zero-mean normalised cross-correlation between the template and the map, which is
+    /// insensitive to offsets and scaling of the values. Scores range from `-1.0` to `1.0`, and
+    /// higher scores are better matches.
+    NormalisedCrossCorrelation,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Default for AlignmentParams {
+    fn default() -> Self {
+        Self {
+            max_translation: 1.0,
+            translation_step: 0.25,
+            max_rotation: 10f64.to_radians(),
+            rotation_step: 2.5f64.to_radians(),
+            refinement_levels: 4,
+            min_overlap: 0.5,
+        }
+    }
+}
+
+impl MatchMethod {
+    /// Returns the index and score of the best match in `scores`, as returned by
+    /// [`CellMap::match_template()`], or `None` if every score is `NaN`.
+    pub fn best_match(&self, scores: ArrayView2<'_, f64>) -> Option<(Point2<usize>, f64)> {
+        let valid = scores
+            .indexed_iter()
+            .filter(|(_, s)| !s.is_nan())
+            .map(|((y, x), &s)| (Point2::new(x, y), s));
+
+        match self {
+            Self::SumSquaredDifference => valid.min_by(|a, b| a.1.total_cmp(&b.1)),
+            Self::NormalisedCrossCorrelation => valid.max_by(|a, b| a.1.total_cmp(&b.1)),
+        }
+    }
+
+    /// Scores the given pairs of map and template values.
+    fn score(&self, pairs: &[(f64, f64)]) -> f64 {
+        if pairs.is_empty() {
+            return f64::NAN;
+        }
+
+        let n = pairs.len() as f64;
+
+        match self {
+            Self::SumSquaredDifference => {
+                pairs.iter().map(|(a, b)| (a - b).powi(2)).sum::<f64>() / n
+            }
+            Self::NormalisedCrossCorrelation => {
+                let mean_a = pairs.iter().map(|(a, _)| a).sum::<f64>() / n;
+                let mean_b = pairs.iter().map(|(_, b)| b).sum::<f64>() / n;
+
+                let (cov, var_a, var_b) =
+                    pairs
+                        .iter()
+                        .fold((0.0, 0.0, 0.0), |(cov, var_a, var_b), (a, b)| {
+                            let (da, db) = (a - mean_a, b - mean_b);
+                            (cov + da * db, var_a + da * da, var_b + db * db)
+                        });
+
+                let denominator = (var_a * var_b).sqrt();
+                if denominator > 0.0 {
+                    cov / denominator
+                } else {
+                    f64::NAN
+                }
+            }
+        }
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: CompareValue,
+{
+    /// Scores `template` against every position in `layer` where it fits entirely inside the map,
+    /// using the given [`MatchMethod`].
+    ///
+    /// The template is indexed `(y, x)` like the map's layers. The returned array has one score
+    /// for each position of the template's first cell, so the score at `[y, x]` compares the
+    /// template with the cells from index `(x, y)` onwards. Positions where no valid cells overlap,
+    /// or where the cross-correlation is undefined because either side is constant, score `NaN`.
+    /// Use [`MatchMethod::best_match()`] to find the best position.
+    ///
+    /// Returns [`Error::WindowLargerThanMap`] if the template is larger than the map.
+    pub fn match_template(
+        &self,
+        layer: L,
+        template: &Array2<T>,
+        method: MatchMethod,
+    ) -> Result<Array2<f64>, Error> {
+        let data = self[layer].map(T::as_f64);
+        let template = template.map(T::as_f64);
+        let (rows, cols) = data.dim();
+        let (t_rows, t_cols) = template.dim();
+
+        if t_rows > rows || t_cols > cols {
+            return Err(Error::WindowLargerThanMap(
+                Vector2::new(t_cols, t_rows),
+                Vector2::new(cols, rows),
+            ));
+        }
+
+        let mut pairs = Vec::with_capacity(t_rows * t_cols);
+
+        Ok(Array2::from_shape_fn(
+            (rows - t_rows + 1, cols - t_cols + 1),
+            |(y, x)| {
+                pairs.clear();
+                for ((ty, tx), t) in template.indexed_iter() {
+                    if let (Some(a), Some(b)) = (data[(y + ty, x + tx)], t) {
+                        pairs.push((a, *b));
+                    }
+                }
+                method.score(&pairs)
+            },
+        ))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Estimates the rigid transform which aligns `layer` of `map_b` with the same layer of `map_a`,
+/// for example to co-register the maps from two sorties before merging them.
+///
+/// The returned transform takes points in `map_b`'s parent frame to the matching points in
+/// `map_a`'s parent frame, so moving `map_b` by it aligns the two maps. The maps don't need to
+/// have the same bounds or cell size.
+///
+/// The alignment is found by minimising the mean squared difference between the valid cells of
+/// `map_a` and the cells of `map_b` containing their centres, first with a coarse search over
+/// translations and rotations about the centroid of `map_a`'s valid cells, and then refining the
+/// search around the best alignment, see [`AlignmentParams`]. The result is only as accurate as
+/// the final search step and the cell size, and the true alignment must lie within the search
+/// range.
+///
+/// Returns [`Error::NotEnoughValidCells`] if no alignment overlaps enough valid cells.
+pub fn estimate_alignment<L, T>(
+    map_a: &CellMap<L, T>,
+    map_b: &CellMap<L, T>,
+    layer: L,
+    params: &AlignmentParams,
+) -> Result<Isometry2<f64>, Error>
+where
+    L: Layer,
+    T: CompareValue,
+{
+    let cells_a: Vec<(Point2<f64>, f64)> = map_a[layer.clone()]
+        .indexed_iter()
+        .filter_map(|((y, x), v)| Some((map_a.position_unchecked(Point2::new(x, y)), v.as_f64()?)))
+        .collect();
+    let values_b = map_b[layer].map(T::as_f64);
+
+    let min_count = ((cells_a.len() as f64 * params.min_overlap).ceil() as usize).max(1);
+    let centroid =
+        cells_a.iter().map(|(p, _)| p.coords).sum::<Vector2<f64>>() / (cells_a.len().max(1)) as f64;
+
+    // Rotations are about the centroid, so that they don't also move the map
+    let transform = |t: Vector2<f64>, angle: f64| {
+        Isometry2::translation(centroid.x + t.x, centroid.y + t.y)
+            * Isometry2::rotation(angle)
+            * Isometry2::translation(-centroid.x, -centroid.y)
+    };
+
+    // Returns the mean squared difference and the number of overlapping cells
+    let score = |t: Vector2<f64>, angle: f64| {
+        let inverse = transform(t, angle).inverse();
+        let (sum, count) = cells_a
+            .iter()
+            .filter_map(|(p, a)| {
+                let index = map_b.index(inverse * p)?;
+                values_b[(index.y, index.x)].map(|b| (a - b).powi(2))
+            })
+            .fold((0.0, 0usize), |(sum, count), d| (sum + d, count + 1));
+
+        (sum / count.max(1) as f64, count)
+    };
+
+    let mut best: Option<(Vector2<f64>, f64, f64)> = None;
+    let mut best_count = 0;
+    let mut search = |t: Vector2<f64>, angle: f64, best: &mut Option<(Vector2<f64>, f64, f64)>| {
+        let (cost, count) = score(t, angle);
+        best_count = best_count.max(count);
+
+        if count >= min_count && best.is_none_or(|(_, _, c)| cost < c) {
+            *best = Some((t, angle, cost));
+        }
+    };
+
+    let mut t_step = params.translation_step;
+    let mut r_step = params.rotation_step;
+    let num_t = (params.max_translation / t_step).floor() as isize;
+    let num_r = (params.max_rotation / r_step).floor() as isize;
+
+    for r in -num_r..=num_r {
+        for ty in -num_t..=num_t {
+            for tx in -num_t..=num_t {
+                let t = Vector2::new(tx as f64, ty as f64) * t_step;
+                search(t, r as f64 * r_step, &mut best);
+            }
+        }
+    }
+
+    for _ in 0..params.refinement_levels {
+        t_step /= 2.0;
+        r_step /= 2.0;
+
+        if let Some((centre, angle, _)) = best {
+            for r in -1..=1 {
+                for ty in -1..=1 {
+                    for tx in -1..=1 {
+                        let t = centre + Vector2::new(tx as f64, ty as f64) * t_step;
+                        search(t, angle + r as f64 * r_step, &mut best);
+                    }
+                }
+            }
+        }
+    }
+
+    best.map(|(t, angle, _)| transform(t, angle))
+        .ok_or(Error::NotEnoughValidCells(min_count, best_count))
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ndarray::s;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn match_template() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 10), (0, 8)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // Pseudo-random terrain
+        for ((_, index), height) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            let (x, y) = (index.x, index.y);
+            *height = ((x * x * 7 + y * y * 3 + x * y) % 17) as f64 * 0.1;
+        }
+
+        // A patch cut out of the map, with a missing cell
+        let mut template = map[TestLayers::Layer0].slice(s![2..5, 3..7]).to_owned();
+        template[[1, 1]] = f64::NAN;
+
+        let ssd = map
+            .match_template(
+                TestLayers::Layer0,
+                &template,
+                MatchMethod::SumSquaredDifference,
+            )
+            .unwrap();
+        assert_eq!(ssd.dim(), (6, 7));
+        assert_eq!(ssd[[2, 3]], 0.0);
+        assert_eq!(
+            MatchMethod::SumSquaredDifference.best_match(ssd.view()),
+            Some((Point2::new(3, 2), 0.0))
+        );
+
+        // Cross-correlation ignores changes of scale and offset in the template
+        let scaled = template.mapv(|v| 2.0 * v + 1.0);
+        let ncc = map
+            .match_template(
+                TestLayers::Layer0,
+                &scaled,
+                MatchMethod::NormalisedCrossCorrelation,
+            )
+            .unwrap();
+        let (index, score) = MatchMethod::NormalisedCrossCorrelation
+            .best_match(ncc.view())
+            .unwrap();
+        assert_eq!(index, Point2::new(3, 2));
+        assert!((score - 1.0).abs() < 1e-9);
+
+        // A constant layer has undefined cross-correlation everywhere
+        let ncc = map
+            .match_template(
+                TestLayers::Layer1,
+                &template,
+                MatchMethod::NormalisedCrossCorrelation,
+            )
+            .unwrap();
+        assert_eq!(
+            MatchMethod::NormalisedCrossCorrelation.best_match(ncc.view()),
+            None
+        );
+
+        assert!(matches!(
+            map.match_template(
+                TestLayers::Layer0,
+                &Array2::zeros((9, 2)),
+                MatchMethod::SumSquaredDifference
+            ),
+            Err(Error::WindowLargerThanMap(_, _))
+        ));
+    }
+
+    #[test]
+    fn estimate_alignment() {
+        let params = CellMapParams {
+            cell_bounds: Bounds::new((0, 40), (0, 40)).unwrap(),
+            cell_size: Vector2::new(0.1, 0.1),
+            ..Default::default()
+        };
+        let terrain = |p: Point2<f64>| (2.0 * p.x).sin() + (3.0 * p.y).cos() + 0.3 * p.x * p.y;
+
+        // Map B sees the same terrain as map A, but its poses are off by a known transform
+        let truth = Isometry2::new(Vector2::new(0.3, -0.2), 4f64.to_radians());
+        let mut map_a = CellMap::<TestLayers, f64>::new_from_elem(params, f64::NAN);
+        let mut map_b = map_a.clone();
+        for ((_, p), v) in map_a.iter_mut().layer(TestLayers::Layer0).positioned() {
+            *v = terrain(p);
+        }
+        for ((_, p), v) in map_b.iter_mut().layer(TestLayers::Layer0).positioned() {
+            *v = terrain(truth * p);
+        }
+
+        let estimate = super::estimate_alignment(
+            &map_a,
+            &map_b,
+            TestLayers::Layer0,
+            &AlignmentParams::default(),
+        )
+        .unwrap();
+        let error = estimate.inverse() * truth;
+        assert!(error.translation.vector.norm() < 0.1, "{}", estimate);
+        assert!(
+            error.rotation.angle().abs() < 1f64.to_radians(),
+            "{}",
+            estimate
+        );
+
+        assert!(matches!(
+            super::estimate_alignment(
+                &map_a,
+                &map_b,
+                TestLayers::Layer1,
+                &AlignmentParams::default()
+            ),
+            Err(Error::NotEnoughValidCells(1, 0))
+        ));
+    }
+}
@@ -0,0 +1,324 @@
+//! Provides the [`MapRegistry`] type, which holds several maps of different types under string
+//! names, for systems which juggle for example a "global", a "local" and a "science" map.
+//!
+//! Maps are retrieved by name and type, and the whole registry can be written to and loaded from
+//! a single JSON file. Since the types of the maps aren't stored in the file, maps loaded from a
+//! file are kept in their serialised form until they are first retrieved, at which point they are
+//! deserialised into the requested type.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{any::Any, collections::BTreeMap, fmt, path::Path, sync::OnceLock};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{cell_map_file::CellMapFile, CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A collection of maps of any type, keyed by name.
+#[derive(Debug, Default)]
+pub struct MapRegistry {
+    entries: BTreeMap<String, Entry>,
+}
+
+/// A type-erased map.
+type AnyMap = Box<dyn Any + Send + Sync>;
+
+/// A function which serialises the type-erased map with the given name into a JSON
+/// [`CellMapFile`].
+type ToJsonFn = fn(&str, &AnyMap) -> Result<serde_json::Value, Error>;
+
+/// A single map in a [`MapRegistry`], either deserialised or still in its serialised form.
+struct Entry {
+    /// The deserialised map and the function which serialises it, set on insertion or on the
+    /// first retrieval of a map loaded from a file.
+    map: OnceLock<(AnyMap, ToJsonFn)>,
+
+    /// The serialised form of a map loaded from a file.
+    raw: Option<serde_json::Value>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl MapRegistry {
+    /// Creates a new empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of maps in the registry.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the registry contains no maps.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns `true` if the registry contains a map with the given name, of any type.
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// Returns the names of the maps in the registry, in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// Inserts a map into the registry under the given name, replacing any existing map with that
+    /// name regardless of its type.
+    pub fn insert<L, T>(&mut self, name: impl Into<String>, map: CellMap<L, T>)
+    where
+        L: Layer + Serialize + DeserializeOwned + Send + Sync + 'static,
+        T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        let entry = Entry {
+            map: OnceLock::new(),
+            raw: None,
+        };
+        let _ = entry.map.set((Box::new(map), to_json::<L, T>));
+
+        self.entries.insert(name.into(), entry);
+    }
+
+    /// Returns a reference to the map with the given name.
+    ///
+    /// Maps loaded from a file are deserialised as a `CellMap<L, T>` the first time they are
+    /// retrieved, after which they keep that type.
+    ///
+    /// Returns [`Error::MapNotFound`] if there's no map with this name, or
+    /// [`Error::MapWrongType`] if the map isn't a `CellMap<L, T>`.
+    pub fn get<L, T>(&self, name: &str) -> Result<&CellMap<L, T>, Error>
+    where
+        L: Layer + Serialize + DeserializeOwned + Send + Sync + 'static,
+        T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        self.resolve::<L, T>(name)?
+            .downcast_ref()
+            .ok_or_else(|| Error::MapWrongType(name.into()))
+    }
+
+    /// Returns a mutable reference to the map with the given name.
+    ///
+    /// Returns [`Error::MapNotFound`] if there's no map with this name, or
+    /// [`Error::MapWrongType`] if the map isn't a `CellMap<L, T>`.
+    pub fn get_mut<L, T>(&mut self, name: &str) -> Result<&mut CellMap<L, T>, Error>
+    where
+        L: Layer + Serialize + DeserializeOwned + Send + Sync + 'static,
+        T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        self.resolve::<L, T>(name)?;
+
+        self.entries
+            .get_mut(name)
+            .and_then(|e| e.map.get_mut())
+            .and_then(|(map, _)| map.downcast_mut())
+            .ok_or_else(|| Error::MapWrongType(name.into()))
+    }
+
+    /// Removes the map with the given name from the registry and returns it.
+    ///
+    /// If the map isn't a `CellMap<L, T>` [`Error::MapWrongType`] is returned and the map is left
+    /// in the registry.
+    pub fn remove<L, T>(&mut self, name: &str) -> Result<CellMap<L, T>, Error>
+    where
+        L: Layer + Serialize + DeserializeOwned + Send + Sync + 'static,
+        T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        self.get::<L, T>(name)?;
+
+        self.entries
+            .remove(name)
+            .and_then(|e| e.map.into_inner())
+            .and_then(|(map, _)| map.downcast().ok())
+            .map(|map| *map)
+            .ok_or_else(|| Error::MapWrongType(name.into()))
+    }
+
+    /// Converts the registry into a JSON object with one [`CellMapFile`] per map, keyed by name.
+    pub fn to_json_value(&self) -> Result<serde_json::Value, Error> {
+        let mut object = serde_json::Map::new();
+
+        for (name, entry) in &self.entries {
+            let value = match (entry.map.get(), &entry.raw) {
+                (Some((map, to_json)), _) => to_json(name, map)?,
+                (None, Some(raw)) => raw.clone(),
+                (None, None) => return Err(Error::MapNotFound(name.clone())),
+            };
+            object.insert(name.clone(), value);
+        }
+
+        Ok(serde_json::Value::Object(object))
+    }
+
+    /// Builds a registry from a JSON object produced by [`MapRegistry::to_json_value()`].
+    ///
+    /// Maps are only deserialised when they are first retrieved, so errors in the data of
+    /// individual maps are reported then.
+    pub fn from_json_value(value: serde_json::Value) -> Result<Self, Error> {
+        let object: serde_json::Map<String, serde_json::Value> = serde_json::from_value(value)?;
+
+        Ok(Self {
+            entries: object
+                .into_iter()
+                .map(|(name, raw)| {
+                    (
+                        name,
+                        Entry {
+                            map: OnceLock::new(),
+                            raw: Some(raw),
+                        },
+                    )
+                })
+                .collect(),
+        })
+    }
+
+    /// Writes every map in the registry to the given path as a single JSON file, overwriting any
+    /// existing file.
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        trace_span!("write_registry_json", path = %path.as_ref().display());
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, &self.to_json_value()?)?;
+
+        Ok(())
+    }
+
+    /// Loads a registry from a JSON file written by [`MapRegistry::write_json()`].
+    pub fn from_json<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        trace_span!("registry_from_json", path = %path.as_ref().display());
+        let file = std::fs::File::open(path)?;
+
+        Self::from_json_value(serde_json::from_reader(file)?)
+    }
+
+    /// Returns the map with the given name, deserialising it as a `CellMap<L, T>` if it was
+    /// loaded from a file and hasn't been retrieved yet.
+    fn resolve<L, T>(&self, name: &str) -> Result<&AnyMap, Error>
+    where
+        L: Layer + Serialize + DeserializeOwned + Send + Sync + 'static,
+        T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        let entry = self
+            .entries
+            .get(name)
+            .ok_or_else(|| Error::MapNotFound(name.into()))?;
+
+        if let Some((map, _)) = entry.map.get() {
+            return Ok(map);
+        }
+
+        let raw = entry
+            .raw
+            .clone()
+            .ok_or_else(|| Error::MapWrongType(name.into()))?;
+        let map = serde_json::from_value::<CellMapFile<L, T>>(raw)
+            .map_err(|_| Error::MapWrongType(name.into()))?
+            .into_cell_map()?;
+
+        let (map, _) = entry.map.get_or_init(|| (Box::new(map), to_json::<L, T>));
+        Ok(map)
+    }
+}
+
+impl fmt::Debug for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Entry")
+            .field("loaded", &self.map.get().is_some())
+            .finish()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Serialises the type-erased `CellMap<L, T>` with the given name into a JSON [`CellMapFile`].
+fn to_json<L, T>(name: &str, map: &AnyMap) -> Result<serde_json::Value, Error>
+where
+    L: Layer + Serialize + 'static,
+    T: Clone + Serialize + 'static,
+{
+    let map = map
+        .downcast_ref::<CellMap<L, T>>()
+        .ok_or_else(|| Error::MapWrongType(name.into()))?;
+
+    Ok(serde_json::to_value(map.to_cell_map_file())?)
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn map_registry() {
+        let params = CellMapParams {
+            cell_bounds: Bounds::new((0, 4), (0, 4)).unwrap(),
+            ..Default::default()
+        };
+
+        let mut registry = MapRegistry::new();
+        registry.insert(
+            "global",
+            CellMap::<TestLayers, f64>::new_from_elem(params, 1.5),
+        );
+        registry.insert(
+            "science",
+            CellMap::<TestLayers, u8>::new_from_elem(params, 7),
+        );
+        assert_eq!(registry.len(), 2);
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            vec!["global", "science"]
+        );
+
+        // Typed retrieval
+        registry.get_mut::<TestLayers, f64>("global").unwrap()
+            [(TestLayers::Layer0, Point2::new(1, 1))] = 3.0;
+        assert!(matches!(
+            registry.get::<TestLayers, f64>("science"),
+            Err(Error::MapWrongType(_))
+        ));
+        assert!(matches!(
+            registry.get::<TestLayers, f64>("local"),
+            Err(Error::MapNotFound(_))
+        ));
+
+        // Round trip the whole registry, the loaded maps are typed on retrieval
+        let loaded = MapRegistry::from_json_value(registry.to_json_value().unwrap()).unwrap();
+        assert!(loaded.contains("science"));
+        assert!(matches!(
+            loaded.get::<TestLayers, bool>("global"),
+            Err(Error::MapWrongType(_))
+        ));
+        let global = loaded.get::<TestLayers, f64>("global").unwrap();
+        assert_eq!(global[(TestLayers::Layer0, Point2::new(1, 1))], 3.0);
+        assert_eq!(global[(TestLayers::Layer2, Point2::new(0, 0))], 1.5);
+
+        // Once retrieved a loaded map keeps its type
+        let mut loaded = loaded;
+        assert!(loaded.get::<TestLayers, u8>("science").is_ok());
+        assert!(matches!(
+            loaded.remove::<TestLayers, f64>("science"),
+            Err(Error::MapWrongType(_))
+        ));
+        let science = loaded.remove::<TestLayers, u8>("science").unwrap();
+        assert_eq!(science[(TestLayers::Layer1, Point2::new(3, 3))], 7);
+        assert_eq!(loaded.len(), 1);
+    }
+}
@@ -0,0 +1,189 @@
+//! Provides the [`LayerTexture`] widget for displaying layers in an [`egui`] UI.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use egui::{ColorImage, Context, Image, Response, Sense, TextureHandle, TextureOptions, Ui, Vec2};
+use nalgebra::Point2;
+use ndarray::Array2;
+
+use super::{render_rgba, RenderOptions};
+use crate::{map_metadata::CellMapMetadata, CellMap, Layer, YAxis};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A layer rendered into an egui texture, which can be shown in a [`Ui`] with hover-to-inspect of
+/// cell values.
+///
+/// The texture is uploaded once when the [`LayerTexture`] is created or updated, so it should be
+/// kept between frames rather than rebuilt each time it's shown.
+pub struct LayerTexture {
+    texture: TextureHandle,
+    values: Array2<f64>,
+    metadata: CellMapMetadata,
+    y_axis: YAxis,
+}
+
+/// Information about the cell under the pointer in a [`LayerTexture`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HoveredCell {
+    /// The index of the cell in the map.
+    pub index: Point2<usize>,
+
+    /// The position of the centre of the cell in the map's parent frame.
+    pub position: Point2<f64>,
+
+    /// The value of the cell.
+    pub value: f64,
+}
+
+/// The result of showing a [`LayerTexture`].
+#[derive(Debug)]
+pub struct LayerResponse {
+    /// The response of the image widget.
+    pub response: Response,
+
+    /// The cell under the pointer, if any.
+    pub hovered: Option<HoveredCell>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl LayerTexture {
+    /// Renders the given layer of `map` into a new texture with the given name.
+    ///
+    /// [`RenderOptions::cell_pixels`] is ignored, since the texture is scaled when shown. Cells
+    /// are drawn without filtering so that cell boundaries stay sharp.
+    pub fn new<L: Layer>(
+        ctx: &Context,
+        name: impl Into<String>,
+        map: &CellMap<L, f64>,
+        layer: L,
+        options: &RenderOptions,
+    ) -> Self {
+        let values = map[layer].clone();
+        let y_axis = map.params.convention.y_axis;
+        let texture = ctx.load_texture(
+            name,
+            color_image(&values, y_axis, options),
+            TextureOptions::NEAREST,
+        );
+
+        Self {
+            texture,
+            values,
+            metadata: map.metadata,
+            y_axis,
+        }
+    }
+
+    /// Re-renders the texture from the given layer of `map`, which may have a different size or
+    /// position to the map the texture was created from.
+    pub fn update<L: Layer>(&mut self, map: &CellMap<L, f64>, layer: L, options: &RenderOptions) {
+        self.values = map[layer].clone();
+        self.metadata = map.metadata;
+        self.y_axis = map.params.convention.y_axis;
+        self.texture.set(
+            color_image(&self.values, self.y_axis, options),
+            TextureOptions::NEAREST,
+        );
+    }
+
+    /// Returns the underlying texture.
+    pub fn texture(&self) -> &TextureHandle {
+        &self.texture
+    }
+
+    /// Shows the layer in `ui` at the given size in points.
+    ///
+    /// While the pointer is over the layer a tooltip shows the index, position and value of the
+    /// cell under it, which is also returned in [`LayerResponse::hovered`].
+    pub fn show(&self, ui: &mut Ui, size: Vec2) -> LayerResponse {
+        let response = ui.add(Image::new((self.texture.id(), size)).sense(Sense::hover()));
+
+        let hovered = response
+            .hover_pos()
+            .and_then(|pos| {
+                let rect = response.rect;
+                uv_to_index(
+                    ((pos.x - rect.min.x) / rect.width()) as f64,
+                    ((pos.y - rect.min.y) / rect.height()) as f64,
+                    self.values.dim(),
+                    self.y_axis,
+                )
+            })
+            .map(|index| HoveredCell {
+                index,
+                position: self.metadata.position_unchecked(index),
+                value: self.values[(index.y, index.x)],
+            });
+
+        let response = match hovered {
+            Some(cell) => response.on_hover_text_at_pointer(format!(
+                "index: ({}, {})\nposition: ({:.3}, {:.3})\nvalue: {}",
+                cell.index.x, cell.index.y, cell.position.x, cell.position.y, cell.value
+            )),
+            None => response,
+        };
+
+        LayerResponse { response, hovered }
+    }
+}
+
+impl std::fmt::Debug for LayerTexture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LayerTexture")
+            .field("texture", &self.texture.id())
+            .field("num_cells", &self.metadata.num_cells)
+            .finish()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Renders `values` into an egui image.
+fn color_image(values: &Array2<f64>, y_axis: YAxis, options: &RenderOptions) -> ColorImage {
+    let image = render_rgba(
+        values.view(),
+        y_axis,
+        &RenderOptions {
+            cell_pixels: 1,
+            ..*options
+        },
+    );
+
+    ColorImage::from_rgba_unmultiplied([image.width, image.height], &image.pixels)
+}
+
+/// Converts a position within a rendered layer, as a fraction of its width and height from the
+/// top left corner, into the index of the cell at that position. `dim` is the `(rows, cols)` shape
+/// of the layer, and `y_axis` the direction of the map's `y` axis.
+pub(crate) fn uv_to_index(
+    u: f64,
+    v: f64,
+    dim: (usize, usize),
+    y_axis: YAxis,
+) -> Option<Point2<usize>> {
+    let (rows, cols) = dim;
+
+    if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+        return None;
+    }
+
+    // Rendered images have +y up, so the top row of the image is the last row of cells unless the
+    // map's y axis points down
+    let x = (u * cols as f64) as usize;
+    let y = match y_axis {
+        YAxis::Up => rows - 1 - (v * rows as f64) as usize,
+        YAxis::Down => (v * rows as f64) as usize,
+    };
+
+    Some(Point2::new(x, y))
+}
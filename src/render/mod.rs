@@ -0,0 +1,347 @@
+//! Provides functions for rendering layers of a [`CellMap`] into images, for quick-look products
+//! and debugging.
+//!
+//! Layers are rendered by mapping each cell's value through a [`Colormap`], with invalid (`NaN`)
+//! cells left transparent. The rendered image always has the map's `+y` axis pointing up, so the
+//! first row of the image is the row of cells with the largest `y` index.
+//!
+//! The following outputs are available:
+//!
+//! - [`CellMap::render_layer_rgba()`] renders to an in-memory [`RgbaImage`].
+//! - [`CellMap::render_layer_png()`] writes a PNG file, and requires the `render_png` feature.
+//! - [`CellMap::render_svg()`] renders an SVG document, with optional vector [`Overlay`]s such
+//!   as paths and polygons drawn on top of the map.
+//! - [`CellMap::debug_print()`] prints the layer to the terminal as characters or coloured blocks,
+//!   which is useful for inspecting small maps in tests.
+//! - [`LayerTexture`] shows the layer in an `egui` UI with hover-to-inspect of cell values, and
+//!   requires the `egui` feature.
+//!
+//! [`CellMap`]: crate::CellMap
+//! [`CellMap::render_layer_rgba()`]: crate::CellMap::render_layer_rgba
+//! [`CellMap::render_layer_png()`]: crate::CellMap::render_layer_png
+//! [`CellMap::render_svg()`]: crate::CellMap::render_svg
+//! [`CellMap::debug_print()`]: crate::CellMap::debug_print
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use ndarray::ArrayView2;
+
+use crate::{CellMap, Layer, YAxis};
+
+// ------------------------------------------------------------------------------------------------
+// MODULES
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(feature = "egui")]
+mod egui_view;
+#[cfg(feature = "render_png")]
+mod png_file;
+mod svg;
+mod terminal;
+#[cfg(test)]
+mod tests;
+
+// ------------------------------------------------------------------------------------------------
+// EXPORTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(feature = "egui")]
+pub use egui_view::{HoveredCell, LayerResponse, LayerTexture};
+pub use svg::{Overlay, OverlayStyle};
+pub use terminal::{TerminalStyle, DEFAULT_CHARSET};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Samples of the viridis colormap at 11 evenly spaced points.
+const VIRIDIS: [[u8; 3]; 11] = [
+    [68, 1, 84],
+    [72, 36, 117],
+    [65, 68, 135],
+    [53, 95, 141],
+    [42, 120, 142],
+    [33, 145, 140],
+    [34, 168, 132],
+    [68, 191, 112],
+    [122, 209, 81],
+    [189, 223, 38],
+    [253, 231, 37],
+];
+
+/// Samples of the plasma colormap at 11 evenly spaced points.
+const PLASMA: [[u8; 3]; 11] = [
+    [13, 8, 135],
+    [65, 4, 157],
+    [106, 0, 168],
+    [143, 13, 164],
+    [177, 42, 144],
+    [204, 71, 120],
+    [225, 100, 98],
+    [242, 132, 75],
+    [252, 166, 54],
+    [252, 206, 37],
+    [240, 249, 33],
+];
+
+/// Samples of the magma colormap at 11 evenly spaced points.
+const MAGMA: [[u8; 3]; 11] = [
+    [0, 0, 4],
+    [20, 14, 54],
+    [59, 15, 112],
+    [100, 26, 128],
+    [140, 41, 129],
+    [183, 55, 121],
+    [222, 73, 104],
+    [247, 112, 92],
+    [254, 159, 109],
+    [254, 207, 146],
+    [252, 253, 191],
+];
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// Colormaps used to convert cell values into colours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Colormap {
+    /// Linear greyscale from black to white.
+    Greyscale,
+
+    /// The perceptually uniform viridis colormap, from dark blue to yellow.
+    #[default]
+    Viridis,
+
+    /// The perceptually uniform plasma colormap, from dark blue to yellow through magenta.
+    Plasma,
+
+    /// The perceptually uniform magma colormap, from black to pale yellow through purple.
+    Magma,
+}
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Options controlling how a layer is rendered.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderOptions {
+    /// The colormap to use.
+    ///
+    /// # Default
+    ///
+    /// The default value is [`Colormap::Viridis`].
+    pub colormap: Colormap,
+
+    /// The range of values which is mapped onto the colormap, values outside the range are
+    /// clamped to it. If `None` the range of the finite values in the layer is used.
+    ///
+    /// # Default
+    ///
+    /// The default value is `None`.
+    pub range: Option<(f64, f64)>,
+
+    /// The width and height of each cell in the image, in pixels.
+    ///
+    /// # Default
+    ///
+    /// The default value is `1`.
+    pub cell_pixels: usize,
+
+    /// Grid lines to draw over the image, if any.
+    ///
+    /// # Default
+    ///
+    /// The default value is `None`.
+    pub grid: Option<GridLines>,
+}
+
+/// Grid lines drawn along cell boundaries.
+#[derive(Debug, Clone, Copy)]
+pub struct GridLines {
+    /// The number of cells between each grid line.
+    pub spacing: usize,
+
+    /// The RGBA colour of the lines.
+    pub colour: [u8; 4],
+}
+
+/// An 8-bit RGBA image, stored in row-major order starting from the top left pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    /// The width of the image in pixels.
+    pub width: usize,
+
+    /// The height of the image in pixels.
+    pub height: usize,
+
+    /// The pixel data, with four bytes per pixel.
+    pub pixels: Vec<u8>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Colormap {
+    /// Returns the RGB colour of the given value in the colormap, which is clamped to `[0, 1]`.
+    pub fn colour(&self, value: f64) -> [u8; 3] {
+        let value = if value.is_nan() {
+            0.0
+        } else {
+            value.clamp(0.0, 1.0)
+        };
+
+        let samples = match self {
+            Colormap::Greyscale => {
+                let v = (value * 255.0).round() as u8;
+                return [v, v, v];
+            }
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Plasma => &PLASMA,
+            Colormap::Magma => &MAGMA,
+        };
+
+        // Linearly interpolate between the two nearest samples
+        let pos = value * (samples.len() - 1) as f64;
+        let lower = (pos.floor() as usize).min(samples.len() - 2);
+        let frac = pos - lower as f64;
+
+        let mut colour = [0; 3];
+        for (c, (&a, &b)) in colour
+            .iter_mut()
+            .zip(samples[lower].iter().zip(samples[lower + 1].iter()))
+        {
+            *c = (a as f64 + (b as f64 - a as f64) * frac).round() as u8;
+        }
+
+        colour
+    }
+}
+
+impl RgbaImage {
+    /// Returns the RGBA value of the pixel at the given column and row.
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * self.width + x) * 4;
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+}
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Renders the given layer into an [`RgbaImage`].
+    ///
+    /// Cells which are `NaN` are fully transparent, all other cells are opaque.
+    pub fn render_layer_rgba(&self, layer: L, options: &RenderOptions) -> RgbaImage {
+        render_rgba(self[layer].view(), self.params.convention.y_axis, options)
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            colormap: Colormap::Viridis,
+            range: None,
+            cell_pixels: 1,
+            grid: None,
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Returns the range of values to render, either the given range or the range of finite values in
+/// the layer. Returns `(0, 1)` if there are no finite values.
+pub(crate) fn value_range(layer: ArrayView2<'_, f64>, range: Option<(f64, f64)>) -> (f64, f64) {
+    range.unwrap_or_else(|| {
+        layer
+            .iter()
+            .filter(|v| v.is_finite())
+            .fold(None, |acc: Option<(f64, f64)>, &v| match acc {
+                Some((min, max)) => Some((min.min(v), max.max(v))),
+                None => Some((v, v)),
+            })
+            .unwrap_or((0.0, 1.0))
+    })
+}
+
+/// Normalises `value` into the given range, returning `None` if the value is `NaN`. A range of
+/// zero width maps all values to `0.5`.
+pub(crate) fn normalise(value: f64, range: (f64, f64)) -> Option<f64> {
+    if value.is_nan() {
+        None
+    } else if range.1 > range.0 {
+        Some(((value - range.0) / (range.1 - range.0)).clamp(0.0, 1.0))
+    } else {
+        Some(0.5)
+    }
+}
+
+/// Renders a layer into an image, see [`CellMap::render_layer_rgba()`]. `y_axis` is the
+/// direction of the map's `y` axis, so that the image always has the map's `+y` up.
+pub(crate) fn render_rgba(
+    layer: ArrayView2<'_, f64>,
+    y_axis: YAxis,
+    options: &RenderOptions,
+) -> RgbaImage {
+    let range = value_range(layer, options.range);
+    let scale = options.cell_pixels.max(1);
+    let (rows, cols) = layer.dim();
+    let (width, height) = (cols * scale, rows * scale);
+    let mut pixels = vec![0; width * height * 4];
+
+    for py in 0..height {
+        // Flip y so that the image has +y up, which is the last row of cells unless the map's y
+        // axis already points down
+        let (cy, bottom_edge) = match y_axis {
+            YAxis::Up => (rows - 1 - py / scale, py % scale == scale - 1),
+            YAxis::Down => (py / scale, py % scale == 0),
+        };
+
+        for px in 0..width {
+            let cx = px / scale;
+
+            let colour = match normalise(layer[(cy, cx)], range) {
+                Some(v) => {
+                    let [r, g, b] = options.colormap.colour(v);
+                    [r, g, b, 255]
+                }
+                None => [0, 0, 0, 0],
+            };
+
+            // Grid lines are drawn along the lower y and left edges of cells whose index is a
+            // multiple of the spacing
+            let colour = match options.grid {
+                Some(grid)
+                    if grid.spacing > 0
+                        && ((cx.is_multiple_of(grid.spacing) && px % scale == 0)
+                            || (cy.is_multiple_of(grid.spacing) && bottom_edge)) =>
+                {
+                    grid.colour
+                }
+                _ => colour,
+            };
+
+            let i = (py * width + px) * 4;
+            pixels[i..i + 4].copy_from_slice(&colour);
+        }
+    }
+
+    RgbaImage {
+        width,
+        height,
+        pixels,
+    }
+}
@@ -0,0 +1,69 @@
+//! Provides PNG output of rendered layers.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{fs::File, io::BufWriter, path::Path};
+
+use super::{Colormap, RenderOptions, RgbaImage};
+use crate::{CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl RgbaImage {
+    /// Writes the image to the given path as a PNG file, overwriting any existing file.
+    pub fn write_png<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let file = File::create(path).map_err(Error::IoError)?;
+
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(Error::PngError)?;
+        writer
+            .write_image_data(&self.pixels)
+            .map_err(Error::PngError)
+    }
+}
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Renders the given layer to a PNG file at `path` using `colormap`, with one pixel per cell.
+    ///
+    /// `range` gives the values mapped to either end of the colormap, or if `None` the range of
+    /// the layer's finite values is used. `NaN` cells are transparent. See
+    /// [`CellMap::render_layer_png_with_options()`] for more control over the output.
+    pub fn render_layer_png<P: AsRef<Path>>(
+        &self,
+        layer: L,
+        path: P,
+        colormap: Colormap,
+        range: Option<(f64, f64)>,
+    ) -> Result<(), Error> {
+        self.render_layer_png_with_options(
+            layer,
+            path,
+            &RenderOptions {
+                colormap,
+                range,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Renders the given layer to a PNG file at `path` using the given options.
+    pub fn render_layer_png_with_options<P: AsRef<Path>>(
+        &self,
+        layer: L,
+        path: P,
+        options: &RenderOptions,
+    ) -> Result<(), Error> {
+        self.render_layer_rgba(layer, options).write_png(path)
+    }
+}
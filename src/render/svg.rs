@@ -0,0 +1,332 @@
+//! Provides SVG output of rendered layers with vector overlays.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::fmt::Write;
+
+use nalgebra::Point2;
+
+use super::{normalise, value_range, RenderOptions};
+use crate::{CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// Vector shapes which can be drawn over a layer in [`CellMap::render_svg()`]. All points are
+/// positions in the map's parent frame.
+#[derive(Debug, Clone)]
+pub enum Overlay {
+    /// An open path through the given points.
+    Path {
+        /// The points along the path.
+        points: Vec<Point2<f64>>,
+        /// The style of the path, the fill is ignored.
+        style: OverlayStyle,
+    },
+
+    /// A closed polygon with the given vertices.
+    Polygon {
+        /// The vertices of the polygon.
+        points: Vec<Point2<f64>>,
+        /// The style of the polygon.
+        style: OverlayStyle,
+    },
+
+    /// A circular marker at each of the given points.
+    Points {
+        /// The centre of each marker.
+        points: Vec<Point2<f64>>,
+        /// The radius of each marker in parent frame units.
+        radius: f64,
+        /// The style of the markers.
+        style: OverlayStyle,
+    },
+}
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// The style of an [`Overlay`].
+#[derive(Debug, Clone, Copy)]
+pub struct OverlayStyle {
+    /// The RGBA colour of the outline.
+    ///
+    /// # Default
+    ///
+    /// The default value is opaque red.
+    pub stroke: [u8; 4],
+
+    /// The width of the outline in parent frame units.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.1`.
+    pub stroke_width: f64,
+
+    /// The RGBA colour to fill the shape with, or `None` for no fill.
+    ///
+    /// # Default
+    ///
+    /// The default value is `None`.
+    pub fill: Option<[u8; 4]>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Renders the given layer as an SVG document, drawing the given overlays on top of it.
+    ///
+    /// The document's coordinates are the parent frame of the map with `y` flipped, so that `+y`
+    /// points up, and the view box covers the map and all overlays. Each cell is drawn as a square
+    /// coloured with the default [`RenderOptions`], and `NaN` cells are not drawn.
+    pub fn render_svg(&self, layer: L, overlays: &[Overlay]) -> String {
+        self.render_svg_with_options(layer, overlays, &RenderOptions::default())
+    }
+
+    /// Renders the given layer as an SVG document using the given options, see
+    /// [`CellMap::render_svg()`].
+    ///
+    /// [`RenderOptions::cell_pixels`] is ignored, since SVGs are scalable.
+    pub fn render_svg_with_options(
+        &self,
+        layer: L,
+        overlays: &[Overlay],
+        options: &RenderOptions,
+    ) -> String {
+        let data = &self[layer];
+        let range = value_range(data.view(), options.range);
+        let bounds = self.cell_bounds();
+        let to_parent = self.to_parent();
+
+        // Find the view box in the parent frame from the map's corners and the overlays
+        let corners = [
+            (bounds.x.0, bounds.y.0),
+            (bounds.x.1, bounds.y.0),
+            (bounds.x.0, bounds.y.1),
+            (bounds.x.1, bounds.y.1),
+        ]
+        .iter()
+        .map(|&(x, y)| to_parent.transform_point(&Point2::new(x as f64, y as f64)))
+        .collect::<Vec<_>>();
+        let overlay_points = overlays.iter().flat_map(|o| o.extent_points());
+        let (min, max) = corners.into_iter().chain(overlay_points).fold(
+            (
+                Point2::new(f64::INFINITY, f64::INFINITY),
+                Point2::new(f64::NEG_INFINITY, f64::NEG_INFINITY),
+            ),
+            |(min, max), p| (min.inf(&p), max.sup(&p)),
+        );
+
+        let mut svg = String::new();
+
+        // Writing to a string can't fail so results are ignored
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+            min.x,
+            -max.y,
+            max.x - min.x,
+            max.y - min.y
+        )
+        .ok();
+        writeln!(svg, r#"<g transform="scale(1,-1)">"#).ok();
+
+        // Cells are drawn in the map frame, which is transformed into the parent frame
+        let m = to_parent.matrix();
+        writeln!(
+            svg,
+            r#"<g transform="matrix({} {} {} {} {} {})" shape-rendering="crispEdges">"#,
+            m[(0, 0)],
+            m[(1, 0)],
+            m[(0, 1)],
+            m[(1, 1)],
+            m[(0, 2)],
+            m[(1, 2)]
+        )
+        .ok();
+
+        for ((y, x), &value) in data.indexed_iter() {
+            if let Some(v) = normalise(value, range) {
+                writeln!(
+                    svg,
+                    r#"<rect x="{}" y="{}" width="1" height="1" fill="{}"/>"#,
+                    bounds.x.0 + x as isize,
+                    bounds.y.0 + y as isize,
+                    hex(options.colormap.colour(v))
+                )
+                .ok();
+            }
+        }
+
+        if let Some(grid) = options.grid.filter(|g| g.spacing > 0) {
+            let style = format!(
+                r#"stroke="{}" stroke-opacity="{}" stroke-width="0.05""#,
+                hex([grid.colour[0], grid.colour[1], grid.colour[2]]),
+                grid.colour[3] as f64 / 255.0
+            );
+            for x in (bounds.x.0..=bounds.x.1).filter(|x| x.rem_euclid(grid.spacing as isize) == 0)
+            {
+                writeln!(
+                    svg,
+                    r#"<line x1="{x}" y1="{}" x2="{x}" y2="{}" {}/>"#,
+                    bounds.y.0,
+                    bounds.y.1,
+                    style,
+                    x = x
+                )
+                .ok();
+            }
+            for y in (bounds.y.0..=bounds.y.1).filter(|y| y.rem_euclid(grid.spacing as isize) == 0)
+            {
+                writeln!(
+                    svg,
+                    r#"<line x1="{}" y1="{y}" x2="{}" y2="{y}" {}/>"#,
+                    bounds.x.0,
+                    bounds.x.1,
+                    style,
+                    y = y
+                )
+                .ok();
+            }
+        }
+
+        writeln!(svg, "</g>").ok();
+
+        for overlay in overlays {
+            overlay.write_svg(&mut svg);
+        }
+
+        writeln!(svg, "</g>").ok();
+        writeln!(svg, "</svg>").ok();
+
+        svg
+    }
+}
+
+impl Overlay {
+    /// Returns points which bound the extent of this overlay.
+    fn extent_points(&self) -> Vec<Point2<f64>> {
+        match self {
+            Overlay::Path { points, style } | Overlay::Polygon { points, style } => {
+                let r = style.stroke_width / 2.0;
+                points
+                    .iter()
+                    .flat_map(|p| vec![p.map(|v| v - r), p.map(|v| v + r)])
+                    .collect()
+            }
+            Overlay::Points {
+                points,
+                radius,
+                style,
+            } => {
+                let r = radius + style.stroke_width / 2.0;
+                points
+                    .iter()
+                    .flat_map(|p| vec![p.map(|v| v - r), p.map(|v| v + r)])
+                    .collect()
+            }
+        }
+    }
+
+    /// Writes this overlay as SVG elements into `svg`.
+    fn write_svg(&self, svg: &mut String) {
+        match self {
+            Overlay::Path { points, style } => {
+                writeln!(
+                    svg,
+                    r#"<polyline points="{}" {} fill="none"/>"#,
+                    points_attr(points),
+                    style.stroke_attrs()
+                )
+                .ok();
+            }
+            Overlay::Polygon { points, style } => {
+                writeln!(
+                    svg,
+                    r#"<polygon points="{}" {} {}/>"#,
+                    points_attr(points),
+                    style.stroke_attrs(),
+                    style.fill_attrs()
+                )
+                .ok();
+            }
+            Overlay::Points {
+                points,
+                radius,
+                style,
+            } => {
+                for p in points {
+                    writeln!(
+                        svg,
+                        r#"<circle cx="{}" cy="{}" r="{}" {} {}/>"#,
+                        p.x,
+                        p.y,
+                        radius,
+                        style.stroke_attrs(),
+                        style.fill_attrs()
+                    )
+                    .ok();
+                }
+            }
+        }
+    }
+}
+
+impl OverlayStyle {
+    fn stroke_attrs(&self) -> String {
+        format!(
+            r#"stroke="{}" stroke-opacity="{}" stroke-width="{}""#,
+            hex([self.stroke[0], self.stroke[1], self.stroke[2]]),
+            self.stroke[3] as f64 / 255.0,
+            self.stroke_width
+        )
+    }
+
+    fn fill_attrs(&self) -> String {
+        match self.fill {
+            Some(fill) => format!(
+                r#"fill="{}" fill-opacity="{}""#,
+                hex([fill[0], fill[1], fill[2]]),
+                fill[3] as f64 / 255.0
+            ),
+            None => r#"fill="none""#.into(),
+        }
+    }
+}
+
+impl Default for OverlayStyle {
+    fn default() -> Self {
+        Self {
+            stroke: [255, 0, 0, 255],
+            stroke_width: 0.1,
+            fill: None,
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Formats an RGB colour as a hex string.
+fn hex(colour: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", colour[0], colour[1], colour[2])
+}
+
+/// Formats a list of points as an SVG `points` attribute.
+fn points_attr(points: &[Point2<f64>]) -> String {
+    points
+        .iter()
+        .map(|p| format!("{},{}", p.x, p.y))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
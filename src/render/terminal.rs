@@ -0,0 +1,116 @@
+//! Provides rendering of layers as text, for inspecting small maps in a terminal.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::fmt::Write;
+
+use super::{normalise, value_range, Colormap};
+use crate::{CellMap, Layer, YAxis};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The default character ramp used by [`TerminalStyle::Charset`], from lowest to highest value.
+pub const DEFAULT_CHARSET: &str = " .:-=+*#%@";
+
+/// The character used for `NaN` cells in character based styles.
+const INVALID_CHAR: char = '?';
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// How cells are drawn by [`CellMap::debug_print()`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TerminalStyle {
+    /// Draws each cell as one character from the given ramp, with the first character used for
+    /// the lowest value in the layer and the last for the highest.
+    Charset(String),
+
+    /// Draws cells with values greater than or equal to the threshold as `#` and all others as
+    /// `.`.
+    Threshold(f64),
+
+    /// Draws each cell as a block coloured with the given colormap, using 24-bit ANSI escape
+    /// codes. Each cell is two characters wide so that cells are roughly square.
+    Ansi(Colormap),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Prints the given layer to stdout, see [`CellMap::debug_string()`].
+    pub fn debug_print(&self, layer: L, style: &TerminalStyle) {
+        print!("{}", self.debug_string(layer, style))
+    }
+
+    /// Renders the given layer as text, with one line per row of cells.
+    ///
+    /// The first line is the row with the highest `y` index, or the lowest for maps whose `y` axis
+    /// is [`YAxis::Down`], so that `+y` points up. `NaN` cells
+    /// are drawn as `?`, or left uncoloured in the [`TerminalStyle::Ansi`] style.
+    pub fn debug_string(&self, layer: L, style: &TerminalStyle) -> String {
+        let data = &self[layer];
+        let range = value_range(data.view(), None);
+        let charset: Vec<char> = match style {
+            TerminalStyle::Charset(chars) if !chars.is_empty() => chars.chars().collect(),
+            _ => DEFAULT_CHARSET.chars().collect(),
+        };
+
+        let mut out = String::new();
+
+        // Put +y at the top, which is the last row unless the map's y axis already points down
+        let rows: Box<dyn Iterator<Item = _>> = match self.params.convention.y_axis {
+            YAxis::Up => Box::new(data.outer_iter().rev()),
+            YAxis::Down => Box::new(data.outer_iter()),
+        };
+
+        for row in rows {
+            for &value in row.iter() {
+                match style {
+                    TerminalStyle::Charset(_) => out.push(match normalise(value, range) {
+                        Some(v) => {
+                            charset[((v * charset.len() as f64) as usize).min(charset.len() - 1)]
+                        }
+                        None => INVALID_CHAR,
+                    }),
+                    TerminalStyle::Threshold(threshold) => out.push(if value.is_nan() {
+                        INVALID_CHAR
+                    } else if value >= *threshold {
+                        '#'
+                    } else {
+                        '.'
+                    }),
+                    TerminalStyle::Ansi(colormap) => match normalise(value, range) {
+                        Some(v) => {
+                            let [r, g, b] = colormap.colour(v);
+                            write!(out, "\x1b[48;2;{};{};{}m  ", r, g, b).ok();
+                        }
+                        None => out.push_str("\x1b[0m  "),
+                    },
+                }
+            }
+
+            if let TerminalStyle::Ansi(_) = style {
+                out.push_str("\x1b[0m");
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+impl Default for TerminalStyle {
+    fn default() -> Self {
+        TerminalStyle::Charset(DEFAULT_CHARSET.into())
+    }
+}
@@ -0,0 +1,219 @@
+//! Tests for rendering
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+
+use super::*;
+use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+fn gradient_map() -> CellMap<TestLayers, f64> {
+    let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+        CellMapParams {
+            cell_bounds: Bounds::new((0, 4), (0, 3)).unwrap(),
+            cell_size: Vector2::new(1.0, 1.0),
+            ..Default::default()
+        },
+        0.0,
+    );
+
+    for ((_, index), value) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+        *value = (index.x + 4 * index.y) as f64;
+    }
+    map[(TestLayers::Layer0, Point2::new(1, 1))] = f64::NAN;
+
+    map
+}
+
+#[test]
+fn colormaps() {
+    for colormap in [
+        Colormap::Greyscale,
+        Colormap::Viridis,
+        Colormap::Plasma,
+        Colormap::Magma,
+    ] {
+        // Values outside [0, 1] are clamped
+        assert_eq!(colormap.colour(-1.0), colormap.colour(0.0));
+        assert_eq!(colormap.colour(2.0), colormap.colour(1.0));
+    }
+
+    assert_eq!(Colormap::Greyscale.colour(0.5), [128, 128, 128]);
+    assert_eq!(Colormap::Viridis.colour(0.0), VIRIDIS[0]);
+    assert_eq!(Colormap::Viridis.colour(1.0), VIRIDIS[10]);
+    assert_eq!(Colormap::Viridis.colour(0.15), [69, 52, 126]);
+}
+
+#[test]
+fn rgba() {
+    let map = gradient_map();
+    let image = map.render_layer_rgba(
+        TestLayers::Layer0,
+        &RenderOptions {
+            colormap: Colormap::Greyscale,
+            cell_pixels: 2,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!((image.width, image.height), (8, 6));
+
+    // The image has +y up, so the bottom left cell is the minimum value
+    assert_eq!(image.pixel(0, 5), [0, 0, 0, 255]);
+    assert_eq!(image.pixel(7, 0), [255, 255, 255, 255]);
+
+    // NaN cells are transparent
+    assert_eq!(image.pixel(2, 2)[3], 0);
+    assert_eq!(image.pixel(3, 3)[3], 0);
+
+    // Grid lines along every second cell boundary
+    let image = map.render_layer_rgba(
+        TestLayers::Layer0,
+        &RenderOptions {
+            range: Some((0.0, 100.0)),
+            cell_pixels: 2,
+            grid: Some(GridLines {
+                spacing: 2,
+                colour: [255, 0, 0, 255],
+            }),
+            ..Default::default()
+        },
+    );
+    assert_eq!(image.pixel(4, 2), [255, 0, 0, 255]);
+    assert_eq!(image.pixel(3, 1), [255, 0, 0, 255]);
+    assert_ne!(image.pixel(3, 2), [255, 0, 0, 255]);
+}
+
+#[cfg(feature = "render_png")]
+#[test]
+fn png() {
+    let map = gradient_map();
+    let path = std::env::temp_dir().join("cell_map_render.png");
+
+    map.render_layer_png(TestLayers::Layer0, &path, Colormap::Viridis, None)
+        .unwrap();
+
+    // Decode the image again and check it matches the in-memory render
+    let decoder = ::png::Decoder::new(std::fs::File::open(&path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+
+    assert_eq!((info.width, info.height), (4, 3));
+    assert_eq!(
+        pixels,
+        map.render_layer_rgba(TestLayers::Layer0, &RenderOptions::default())
+            .pixels
+    );
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn svg() {
+    let mut map = gradient_map();
+    map.move_map(Vector2::new(10.0, -5.0), std::f64::consts::FRAC_PI_2);
+
+    let svg = map.render_svg(
+        TestLayers::Layer0,
+        &[
+            Overlay::Path {
+                points: vec![Point2::new(10.0, -5.0), Point2::new(20.0, 0.0)],
+                style: OverlayStyle::default(),
+            },
+            Overlay::Polygon {
+                points: vec![
+                    Point2::new(8.0, -4.0),
+                    Point2::new(9.0, -4.0),
+                    Point2::new(9.0, -3.0),
+                ],
+                style: OverlayStyle {
+                    fill: Some([0, 0, 255, 128]),
+                    ..Default::default()
+                },
+            },
+            Overlay::Points {
+                points: vec![Point2::new(11.0, -3.0); 2],
+                radius: 0.5,
+                style: OverlayStyle::default(),
+            },
+        ],
+    );
+
+    // One rect for each cell which isn't NaN
+    assert_eq!(svg.matches("<rect").count(), 11);
+    assert_eq!(svg.matches("<polyline").count(), 1);
+    assert_eq!(svg.matches("<polygon").count(), 1);
+    assert_eq!(svg.matches("<circle").count(), 2);
+    assert!(svg.contains(r#"points="8,-4 9,-4 9,-3""#));
+    assert!(svg.contains(r##"fill="#0000ff" fill-opacity="0.5019607843137255""##));
+
+    // The map is rotated by 90 degrees, so covers x from 7 to 10 and y from -5 to -1. The path
+    // then extends the view box to (20, 0), with half the stroke width either side of its points.
+    assert!(svg.contains(r#"viewBox="7 -0.05 13.05 5.1""#));
+    assert!(svg.contains(r#"matrix(0.00000000000000006123233995736766 1 -1"#));
+}
+
+#[test]
+fn terminal() {
+    let map = gradient_map();
+
+    assert_eq!(
+        map.debug_string(TestLayers::Layer0, &TerminalStyle::default()),
+        "#%@@\n-?+*\n  .:\n"
+    );
+    assert_eq!(
+        map.debug_string(TestLayers::Layer0, &TerminalStyle::Charset("ab".into())),
+        "bbbb\na?bb\naaaa\n"
+    );
+    assert_eq!(
+        map.debug_string(TestLayers::Layer0, &TerminalStyle::Threshold(6.0)),
+        "####\n.?##\n....\n"
+    );
+
+    let ansi = map.debug_string(
+        TestLayers::Layer0,
+        &TerminalStyle::Ansi(Colormap::Greyscale),
+    );
+    assert_eq!(ansi.lines().count(), 3);
+    assert!(ansi.starts_with("\x1b[48;2;185;185;185m  "));
+    assert!(ansi.ends_with("\x1b[48;2;70;70;70m  \x1b[0m\n"));
+}
+
+#[cfg(feature = "egui")]
+#[test]
+fn egui_texture() {
+    let map = gradient_map();
+    let ctx = egui::Context::default();
+    let texture = LayerTexture::new(
+        &ctx,
+        "layer0",
+        &map,
+        TestLayers::Layer0,
+        &RenderOptions::default(),
+    );
+    assert_eq!(texture.texture().size(), [4, 3]);
+
+    // The top left of the image is the last row of cells
+    let dim = (3, 4);
+    assert_eq!(
+        egui_view::uv_to_index(0.0, 0.0, dim, YAxis::Up),
+        Some(Point2::new(0, 2))
+    );
+    assert_eq!(
+        egui_view::uv_to_index(0.99, 0.99, dim, YAxis::Up),
+        Some(Point2::new(3, 0))
+    );
+    assert_eq!(
+        egui_view::uv_to_index(0.5, 0.5, dim, YAxis::Up),
+        Some(Point2::new(2, 1))
+    );
+    assert_eq!(egui_view::uv_to_index(1.0, 0.5, dim, YAxis::Up), None);
+    assert_eq!(egui_view::uv_to_index(0.5, -0.1, dim, YAxis::Up), None);
+}
@@ -0,0 +1,397 @@
+//! Provides the [`RleLayer`] type, a single layer compressed with run-length encoding.
+//!
+//! Large layers which are mostly uniform, such as a flag marking explored cells, are wasteful to
+//! store densely. An [`RleLayer`] stores only the runs of equal values in row-major order, so its
+//! size depends on how often the value changes rather than on the number of cells.
+//!
+//! The layer is intended to be read much more often than it's written. Reads decompress only the
+//! cells they need, including whole windows with [`RleLayer::window()`], while each write may
+//! have to move every run after the written cell.
+//!
+//! ```
+//! use cell_map::{rle::RleLayer, Bounds, CellMap, CellMapParams, Layer};
+//! use nalgebra::{Point2, Vector2};
+//!
+//! #[derive(Layer, Clone, Debug)]
+//! enum MyLayer {
+//!     Explored,
+//! }
+//!
+//! let mut map = CellMap::<MyLayer, bool>::new(CellMapParams {
+//!     cell_bounds: Bounds::new((0, 100), (0, 100)).unwrap(),
+//!     ..Default::default()
+//! });
+//! map[(MyLayer::Explored, Point2::new(50, 50))] = true;
+//!
+//! let explored = map.compress_layer(MyLayer::Explored);
+//! assert_eq!(explored.num_runs(), 3);
+//! assert_eq!(explored.get(Point2::new(50, 50)), Some(&true));
+//!
+//! let window = explored.window(Point2::new(51, 50), Vector2::new(1, 1)).unwrap();
+//! assert_eq!(window.iter().filter(|&&v| v).count(), 1);
+//! ```
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+use ndarray::Array2;
+
+use crate::{
+    cell_map::Bounds, map_metadata::CellMapMetadata, CellMap, CellMapParams, Error, Layer,
+};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A single layer compressed with run-length encoding, with the same shape and position as a
+/// [`CellMap`] built from the same [`CellMapParams`].
+///
+/// Cells are numbered in the same order as [`CellMap::iter()`] visits them, in row-major order,
+/// and each run is a value and the number of the first cell it covers, so a run may span several
+/// rows.
+#[derive(Debug, Clone)]
+pub struct RleLayer<T> {
+    /// The number of the first cell and the value of each run, in order. There is always at least
+    /// one run unless the layer is empty, and adjacent runs always have different values.
+    runs: Vec<(usize, T)>,
+    metadata: CellMapMetadata,
+    params: CellMapParams,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<T> RleLayer<T>
+where
+    T: Clone + PartialEq,
+{
+    /// Creates a new layer with the given parameters, with every cell set to `value`.
+    pub fn new(params: CellMapParams, value: T) -> Self {
+        let metadata: CellMapMetadata = params.into();
+        let runs = if metadata.num_cells.x * metadata.num_cells.y > 0 {
+            vec![(0, value)]
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            runs,
+            metadata,
+            params,
+        }
+    }
+
+    /// Compresses `layer` of `map` into a new layer.
+    pub fn from_layer<L: Layer>(map: &CellMap<L, T>, layer: L) -> Self {
+        let mut runs: Vec<(usize, T)> = Vec::new();
+
+        for (i, v) in map[layer].iter().enumerate() {
+            match runs.last() {
+                Some((_, last)) if last == v => (),
+                _ => runs.push((i, v.clone())),
+            }
+        }
+
+        Self {
+            runs,
+            metadata: map.metadata,
+            params: map.params(),
+        }
+    }
+
+    /// Decompresses this layer into `layer` of `map`.
+    ///
+    /// The map must have the same shape as this layer, otherwise [`Error::LayerWrongShape`] is
+    /// returned and the map is unchanged.
+    pub fn write_into<L: Layer>(&self, map: &mut CellMap<L, T>, layer: L) -> Result<(), Error> {
+        let shape = self.metadata.cell_bounds.get_shape();
+        let map_shape = map.cell_bounds().get_shape();
+        if shape != map_shape {
+            return Err(Error::LayerWrongShape(shape, map_shape));
+        }
+
+        for (v, value) in map[layer].iter_mut().zip(self.iter()) {
+            *v = value.clone();
+        }
+
+        Ok(())
+    }
+
+    /// Returns the whole layer decompressed into an array.
+    pub fn decompress(&self) -> Array2<T> {
+        let shape = self.metadata.cell_bounds.get_shape();
+        Array2::from_shape_fn(shape, |(y, x)| {
+            self.runs[self.run_of(y * shape.1 + x)].1.clone()
+        })
+    }
+
+    /// Returns the number of cells in each direction of the layer.
+    pub fn num_cells(&self) -> Vector2<usize> {
+        self.metadata.num_cells
+    }
+
+    /// Returns the bounds of the layer.
+    pub fn cell_bounds(&self) -> Bounds {
+        self.metadata.cell_bounds
+    }
+
+    /// Returns the parameters used to build this layer.
+    pub fn params(&self) -> CellMapParams {
+        self.params
+    }
+
+    /// Returns the number of runs the layer is stored as.
+    pub fn num_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Returns a reference to the value of the cell at the given index, or `None` if the index is
+    /// outside the layer.
+    pub fn get(&self, index: Point2<usize>) -> Option<&T> {
+        let cell = self.cell(index)?;
+        Some(&self.runs[self.run_of(cell)].1)
+    }
+
+    /// Returns a reference to the value of the cell containing the given parent-frame position,
+    /// or `None` if the position is outside the layer.
+    pub fn get_position(&self, position: Point2<f64>) -> Option<&T> {
+        self.get(self.metadata.index(position)?)
+    }
+
+    /// Sets the value of the cell at the given index, splitting or merging runs as needed.
+    ///
+    /// Returns [`Error::IndexOutsideMap`] if the index is outside the layer.
+    pub fn set(&mut self, index: Point2<usize>, value: T) -> Result<(), Error> {
+        let cell = self.cell(index).ok_or(Error::IndexOutsideMap(index))?;
+        let run = self.run_of(cell);
+        if self.runs[run].1 == value {
+            return Ok(());
+        }
+
+        // Replace the cell's run with the part before the cell, the cell, and the part after it,
+        // then merge the new run with its neighbours if they have the same value
+        let start = self.runs[run].0;
+        let end = self.run_end(run);
+        let old = self.runs[run].1.clone();
+
+        let mut replacement = Vec::with_capacity(3);
+        if start < cell {
+            replacement.push((start, old.clone()));
+        }
+        replacement.push((cell, value));
+        if cell + 1 < end {
+            replacement.push((cell + 1, old));
+        }
+        self.runs.splice(run..=run, replacement);
+
+        let new = if start < cell { run + 1 } else { run };
+        if new + 1 < self.runs.len() && self.runs[new + 1].1 == self.runs[new].1 {
+            self.runs.remove(new + 1);
+        }
+        if new > 0 && self.runs[new - 1].1 == self.runs[new].1 {
+            self.runs.remove(new);
+        }
+
+        Ok(())
+    }
+
+    /// Returns an iterator over the value of every cell, in the same order as [`CellMap::iter()`].
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        (0..self.runs.len()).flat_map(move |r| {
+            std::iter::repeat_n(&self.runs[r].1, self.run_end(r) - self.runs[r].0)
+        })
+    }
+
+    /// Returns the window of cells around `index` decompressed into an array, or `None` if the
+    /// window isn't entirely inside the layer.
+    ///
+    /// The `semi_width` is half the size of the window in the x and y axes, not including the
+    /// central cell, as for [`CellMap::window_iter()`].
+    pub fn window(&self, index: Point2<usize>, semi_width: Vector2<usize>) -> Option<Array2<T>> {
+        let num_cells = self.metadata.num_cells;
+        if index.x < semi_width.x
+            || index.y < semi_width.y
+            || index.x + semi_width.x >= num_cells.x
+            || index.y + semi_width.y >= num_cells.y
+        {
+            return None;
+        }
+
+        let (x0, y0) = (index.x - semi_width.x, index.y - semi_width.y);
+        let shape = (semi_width.y * 2 + 1, semi_width.x * 2 + 1);
+        let mut values = Vec::with_capacity(shape.0 * shape.1);
+
+        // Walk the runs covering each row of the window
+        for y in y0..y0 + shape.0 {
+            let first = y * num_cells.x + x0;
+            let mut run = self.run_of(first);
+            for cell in first..first + shape.1 {
+                while self.run_end(run) <= cell {
+                    run += 1;
+                }
+                values.push(self.runs[run].1.clone());
+            }
+        }
+
+        Array2::from_shape_vec(shape, values).ok()
+    }
+
+    /// Returns an iterator over the index of every cell whose window is entirely inside the layer,
+    /// and the decompressed window around it, in the same order as [`CellMap::window_iter()`].
+    ///
+    /// Returns [`Error::WindowLargerThanMap`] if the window is larger than the layer.
+    pub fn window_iter(
+        &self,
+        semi_width: Vector2<usize>,
+    ) -> Result<impl Iterator<Item = (Point2<usize>, Array2<T>)> + '_, Error> {
+        let num_cells = self.metadata.num_cells;
+        let window_size = semi_width * 2 + Vector2::new(1, 1);
+        if window_size.x > num_cells.x || window_size.y > num_cells.y {
+            return Err(Error::WindowLargerThanMap(window_size, num_cells));
+        }
+
+        Ok((semi_width.y..num_cells.y - semi_width.y)
+            .flat_map(move |y| {
+                (semi_width.x..num_cells.x - semi_width.x).map(move |x| Point2::new(x, y))
+            })
+            .filter_map(move |index| Some((index, self.window(index, semi_width)?))))
+    }
+
+    /// Returns the number of the given cell, or `None` if the index is outside the layer.
+    fn cell(&self, index: Point2<usize>) -> Option<usize> {
+        if self.metadata.is_in_map(index) {
+            Some(index.y * self.metadata.num_cells.x + index.x)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the index of the run containing the given cell number.
+    fn run_of(&self, cell: usize) -> usize {
+        self.runs.partition_point(|(start, _)| *start <= cell) - 1
+    }
+
+    /// Returns the number of the cell after the end of the given run.
+    fn run_end(&self, run: usize) -> usize {
+        self.runs
+            .get(run + 1)
+            .map(|(start, _)| *start)
+            .unwrap_or(self.metadata.num_cells.x * self.metadata.num_cells.y)
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Clone + PartialEq,
+{
+    /// Compresses `layer` into an [`RleLayer`].
+    pub fn compress_layer(&self, layer: L) -> RleLayer<T> {
+        RleLayer::from_layer(self, layer)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestLayers;
+
+    fn new_map() -> CellMap<TestLayers, u8> {
+        let mut map = CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-3, 4), (0, 5)).unwrap(),
+                ..Default::default()
+            },
+            0,
+        );
+
+        for ((_, index), v) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            *v = (index.x / 3 + index.y / 2) as u8;
+        }
+
+        map
+    }
+
+    #[test]
+    fn compression() {
+        let map = new_map();
+        let rle = map.compress_layer(TestLayers::Layer0);
+
+        // Three runs per row
+        assert_eq!(rle.num_runs(), 15);
+        assert!(rle.iter().eq(map.iter().layer(TestLayers::Layer0)));
+        assert_eq!(rle.decompress(), map[TestLayers::Layer0]);
+        assert_eq!(rle.get(Point2::new(6, 4)), Some(&4));
+        assert_eq!(rle.get(Point2::new(7, 0)), None);
+        assert_eq!(rle.get_position(Point2::new(-2.5, 2.5)), Some(&1));
+
+        let uniform = map.compress_layer(TestLayers::Layer1);
+        assert_eq!(uniform.num_runs(), 1);
+
+        let mut out = map.clone();
+        out[TestLayers::Layer1].fill(9);
+        rle.write_into(&mut out, TestLayers::Layer1).unwrap();
+        assert_eq!(out[TestLayers::Layer1], map[TestLayers::Layer0]);
+    }
+
+    #[test]
+    fn set() {
+        let map = new_map();
+        let mut rle = RleLayer::new(map.params(), 0u8);
+        let mut dense = map.clone();
+
+        // Setting cells must keep the runs minimal, so compare with compressing the dense layer
+        let writes = [
+            ((3, 2), 1),
+            ((4, 2), 1),
+            ((3, 2), 0),
+            ((6, 4), 2),
+            ((0, 3), 2),
+            ((0, 0), 3),
+            ((6, 2), 1),
+            ((0, 3), 1),
+            ((5, 2), 1),
+            ((4, 2), 1),
+            ((0, 3), 1),
+        ];
+        for &((x, y), v) in writes.iter() {
+            let index = Point2::new(x, y);
+            rle.set(index, v).unwrap();
+            dense[(TestLayers::Layer2, index)] = v;
+
+            let expected = dense.compress_layer(TestLayers::Layer2);
+            assert_eq!(rle.runs, expected.runs);
+        }
+
+        assert!(rle.set(Point2::new(0, 5), 1).is_err());
+    }
+
+    #[test]
+    fn windows() {
+        let map = new_map();
+        let rle = map.compress_layer(TestLayers::Layer0);
+
+        let semi_width = Vector2::new(1, 2);
+        let dense: Vec<_> = map
+            .window_iter(semi_width)
+            .unwrap()
+            .layer(TestLayers::Layer0)
+            .map(|w| w.to_owned())
+            .collect();
+        let windows: Vec<_> = rle.window_iter(semi_width).unwrap().collect();
+        assert_eq!(windows.len(), dense.len());
+        assert!(windows.iter().zip(dense.iter()).all(|((_, a), b)| a == b));
+        assert_eq!(windows[0].0, Point2::new(1, 2));
+
+        assert!(rle.window(Point2::new(0, 2), semi_width).is_none());
+        assert!(rle.window_iter(Vector2::new(4, 0)).is_err());
+    }
+}
@@ -0,0 +1,495 @@
+//! Provides the [`RollingMap`] type, a fixed-size map which follows the robot by moving its bounds
+//! without moving its data, in the same way as `grid_map`'s circular buffer.
+//!
+//! Moving an ordinary [`CellMap`] to keep it centred on the robot means copying every cell which
+//! stays in the map to its new index. A [`RollingMap`] stores its cells in a [`WrappingMap`], so
+//! each cell stays in the same place in memory for as long as it's in the map, and moving the map
+//! only has to reset the cells which have just come into it.
+//!
+//! ```
+//! use cell_map::{rolling::RollingMap, Bounds, CellMapParams, Layer};
+//! use nalgebra::Point2;
+//!
+//! #[derive(Layer, Clone, Debug)]
+//! enum MyLayer {
+//!     Height,
+//! }
+//!
+//! let mut map = RollingMap::<MyLayer, f64>::new(CellMapParams {
+//!     cell_bounds: Bounds::new((-5, 5), (-5, 5)).unwrap(),
+//!     ..Default::default()
+//! });
+//! map.set_position(MyLayer::Height, Point2::new(2.5, 0.5), 1.0);
+//!
+//! // Follow the robot 3 cells along x. The cell keeps its value but its index changes.
+//! map.move_to(Point2::new(3.5, 0.5));
+//! assert_eq!(map.cell_bounds(), Bounds::new((-2, 8), (-5, 5)).unwrap());
+//! assert_eq!(map.get_position(MyLayer::Height, Point2::new(2.5, 0.5)), Some(&1.0));
+//! assert_eq!(map.get(MyLayer::Height, Point2::new(4, 5)), Some(&1.0));
+//! ```
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+use ndarray::Array2;
+
+use crate::{cell_map::Bounds, wrapping::WrappingMap, CellMap, CellMapParams, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A fixed-size map whose bounds can move without moving its data, see the
+/// [module documentation](self).
+///
+/// The map has the same interface as a [`CellMap`] in terms of indices, which are relative to the
+/// current [`RollingMap::cell_bounds()`], and parent-frame positions. The map frame itself doesn't
+/// move in the parent frame, only the bounds do, so each cell keeps its map-frame cell location
+/// and position.
+///
+/// Cells which come into the map when it moves are set to `T::default()`, unless the map is moved
+/// with [`RollingMap::shift_uninit()`] or [`RollingMap::move_to_uninit()`], which return the
+/// regions of new cells so they can be initialised by the caller.
+#[derive(Debug, Clone)]
+pub struct RollingMap<L, T>
+where
+    L: Layer,
+{
+    storage: WrappingMap<L, T>,
+    bounds: Bounds,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> RollingMap<L, T>
+where
+    L: Layer,
+    T: Clone + Default,
+{
+    /// Creates a new map with the given parameters, filling each cell with `T::default()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the map would have no cells.
+    pub fn new(params: CellMapParams) -> Self {
+        Self::from_map(CellMap::new(params))
+    }
+
+    /// Creates a new map with the same bounds and contents as `map`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the map has no cells.
+    pub fn from_map(map: CellMap<L, T>) -> Self {
+        let bounds = map.cell_bounds();
+
+        Self {
+            storage: WrappingMap::new(map),
+            bounds,
+        }
+    }
+
+    /// Returns the current bounds of the map.
+    pub fn cell_bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    /// Returns the number of cells in each direction of the map, which never changes.
+    pub fn num_cells(&self) -> Vector2<usize> {
+        self.storage.map().num_cells()
+    }
+
+    /// Returns the parameters of the map with its current bounds.
+    pub fn params(&self) -> CellMapParams {
+        let mut params = self.storage.map().params();
+        params.cell_bounds = self.bounds;
+        params
+    }
+
+    /// Returns the index in the underlying storage of the cell at index `(0, 0)` of the map, i.e.
+    /// the start of the circular buffer.
+    pub fn buffer_start(&self) -> Point2<usize> {
+        self.storage.wrap(self.bounds.as_corners().0)
+    }
+
+    /// Returns the index of the cell containing the given parent-frame position, or `None` if the
+    /// position is outside the map.
+    pub fn index(&self, position: Point2<f64>) -> Option<Point2<usize>> {
+        self.bounds.get_index(self.storage.cell(position))
+    }
+
+    /// Returns the parent-frame position of the centre of the cell at the given index, or `None`
+    /// if the index is outside the map.
+    pub fn position(&self, index: Point2<usize>) -> Option<Point2<f64>> {
+        let cell = self.cell_of(index)?.map(|v| v as f64);
+        Some(
+            self.storage
+                .map()
+                .to_parent()
+                .transform_point(&(cell + Vector2::new(0.5, 0.5))),
+        )
+    }
+
+    /// Returns a reference to the value at the given layer and index, or `None` if the index is
+    /// outside the map.
+    pub fn get(&self, layer: L, index: Point2<usize>) -> Option<&T> {
+        Some(self.storage.get(layer, self.cell_of(index)?))
+    }
+
+    /// Returns a mutable reference to the value at the given layer and index, or `None` if the
+    /// index is outside the map.
+    pub fn get_mut(&mut self, layer: L, index: Point2<usize>) -> Option<&mut T> {
+        let cell = self.cell_of(index)?;
+        Some(self.storage.get_mut(layer, cell))
+    }
+
+    /// Sets the value at the given layer and index, returning `false` and leaving the map
+    /// unchanged if the index is outside the map.
+    pub fn set(&mut self, layer: L, index: Point2<usize>, value: T) -> bool {
+        match self.get_mut(layer, index) {
+            Some(v) => {
+                *v = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns a reference to the value at the given layer and parent-frame position, or `None`
+    /// if the position is outside the map.
+    pub fn get_position(&self, layer: L, position: Point2<f64>) -> Option<&T> {
+        self.get(layer, self.index(position)?)
+    }
+
+    /// Sets the value at the given layer and parent-frame position, returning `false` and leaving
+    /// the map unchanged if the position is outside the map.
+    pub fn set_position(&mut self, layer: L, position: Point2<f64>, value: T) -> bool {
+        match self.index(position) {
+            Some(index) => self.set(layer, index, value),
+            None => false,
+        }
+    }
+
+    /// Moves the map so that the cell containing the given parent-frame position is at its centre,
+    /// or just past the centre along axes with an even number of cells.
+    ///
+    /// The cells which come into the map are reset as for [`RollingMap::shift()`], and the regions
+    /// they cover are returned.
+    pub fn move_to(&mut self, position: Point2<f64>) -> Vec<Bounds> {
+        let offset = self.offset_to(position);
+        self.shift(offset)
+    }
+
+    /// Moves the map as for [`RollingMap::move_to()`], but without resetting the cells which come
+    /// into the map, returning the regions they cover.
+    ///
+    /// The cells in the returned regions still hold the values of the cells which left the map,
+    /// and should be initialised by the caller, for example using [`RollingMap::fill_region()`].
+    pub fn move_to_uninit(&mut self, position: Point2<f64>) -> Vec<Bounds> {
+        let offset = self.offset_to(position);
+        self.shift_uninit(offset)
+    }
+
+    /// Moves the bounds of the map by `offset` cells, resetting the cells which come into the map
+    /// to `T::default()` in every layer.
+    ///
+    /// Only the new cells are written, so this takes time proportional to the distance moved
+    /// rather than the size of the map. The regions covered by the new cells are returned, see
+    /// [`RollingMap::shift_uninit()`].
+    pub fn shift(&mut self, offset: Vector2<isize>) -> Vec<Bounds> {
+        let regions = self.shift_uninit(offset);
+
+        for layer in L::all() {
+            for region in &regions {
+                self.fill_region(layer.clone(), region, |_| T::default());
+            }
+        }
+
+        regions
+    }
+
+    /// Moves the bounds of the map by `offset` cells without resetting the cells which come into
+    /// the map, returning the regions they cover.
+    ///
+    /// The regions are disjoint map-frame cell bounds whose union is every cell in the new bounds
+    /// which wasn't in the old bounds. There are at most two: a strip of columns covering the full
+    /// height of the map and a strip of rows covering the rest of its width. The cells in the
+    /// regions still hold the values of the cells which left the map, and should be initialised
+    /// by the caller, for example using [`RollingMap::fill_region()`].
+    pub fn shift_uninit(&mut self, offset: Vector2<isize>) -> Vec<Bounds> {
+        let old = self.bounds;
+        let new = Bounds {
+            x: (old.x.0 + offset.x, old.x.1 + offset.x),
+            y: (old.y.0 + offset.y, old.y.1 + offset.y),
+        };
+        self.bounds = new;
+
+        // The new columns or rows along one axis, and the range along that axis which isn't in
+        // them
+        let split = |old: (isize, isize), new: (isize, isize)| {
+            if new.0 >= old.1 || new.1 <= old.0 {
+                (new, (new.1, new.1))
+            } else if new.0 < old.0 {
+                ((new.0, old.0), (old.0, new.1))
+            } else {
+                ((old.1, new.1), (new.0, old.1))
+            }
+        };
+        let (new_x, rest_x) = split(old.x, new.x);
+        let (new_y, _) = split(old.y, new.y);
+
+        [
+            Bounds { x: new_x, y: new.y },
+            Bounds {
+                x: rest_x,
+                y: new_y,
+            },
+        ]
+        .iter()
+        .filter(|b| b.x.0 < b.x.1 && b.y.0 < b.y.1)
+        .copied()
+        .collect()
+    }
+
+    /// Sets every cell of `layer` in `region` to the value returned by `func` for the
+    /// parent-frame position of the cell's centre.
+    ///
+    /// `region` is in map-frame cell locations, as returned by [`RollingMap::shift_uninit()`], and
+    /// any part of it outside the map is ignored.
+    pub fn fill_region<F>(&mut self, layer: L, region: &Bounds, mut func: F)
+    where
+        F: FnMut(Point2<f64>) -> T,
+    {
+        let region = match self.bounds.intersect(region) {
+            Some(r) => r,
+            None => return,
+        };
+        let to_parent = self.storage.map().to_parent();
+
+        for y in region.y.0..region.y.1 {
+            for x in region.x.0..region.x.1 {
+                let position =
+                    to_parent.transform_point(&Point2::new(x as f64 + 0.5, y as f64 + 0.5));
+                self.storage
+                    .set(layer.clone(), Point2::new(x, y), func(position));
+            }
+        }
+    }
+
+    /// Returns an iterator over the values of `layer`, in the same order as [`CellMap::iter()`]
+    /// with the map's current bounds.
+    pub fn iter(&self, layer: L) -> impl Iterator<Item = &T> + '_ {
+        self.indexed(layer).map(|(_, v)| v)
+    }
+
+    /// Returns an iterator over the index and value of each cell in `layer`, in the same order as
+    /// [`CellMap::iter()`] with the map's current bounds.
+    pub fn indexed(&self, layer: L) -> impl Iterator<Item = (Point2<usize>, &T)> + '_ {
+        let num_cells = self.num_cells();
+        let min = self.bounds.as_corners().0;
+
+        (0..num_cells.y)
+            .flat_map(move |y| (0..num_cells.x).map(move |x| Point2::new(x, y)))
+            .map(move |index| {
+                let cell = min + index.coords.map(|v| v as isize);
+                (index, self.storage.get(layer.clone(), cell))
+            })
+    }
+
+    /// Returns the window of `layer` around `index`, or `None` if the window isn't entirely inside
+    /// the map.
+    ///
+    /// The `semi_width` is half the size of the window in the x and y axes, not including the
+    /// central cell, as for [`CellMap::window_iter()`].
+    pub fn window(
+        &self,
+        layer: L,
+        index: Point2<usize>,
+        semi_width: Vector2<usize>,
+    ) -> Option<Array2<T>> {
+        let num_cells = self.num_cells();
+        if index.x < semi_width.x
+            || index.y < semi_width.y
+            || index.x + semi_width.x >= num_cells.x
+            || index.y + semi_width.y >= num_cells.y
+        {
+            return None;
+        }
+
+        let centre = self.cell_of(index)?;
+        Some(self.storage.window(layer, centre, semi_width))
+    }
+
+    /// Copies the map into a [`CellMap`] with its current bounds.
+    pub fn to_cell_map(&self) -> CellMap<L, T> {
+        let mut map = CellMap::new(self.params());
+
+        for layer in L::all() {
+            for (index, value) in self.indexed(layer.clone()) {
+                map[(layer.clone(), index)] = value.clone();
+            }
+        }
+
+        map
+    }
+
+    /// Returns the offset which moves the map's centre to the cell containing `position`.
+    fn offset_to(&self, position: Point2<f64>) -> Vector2<isize> {
+        let cell = self.storage.cell(position);
+        let half = self.num_cells().map(|v| (v / 2) as isize);
+
+        (cell - half) - self.bounds.as_corners().0
+    }
+
+    /// Returns the map-frame cell location of the given index, or `None` if the index is outside
+    /// the map.
+    fn cell_of(&self, index: Point2<usize>) -> Option<Point2<isize>> {
+        let num_cells = self.num_cells();
+        if index.x < num_cells.x && index.y < num_cells.y {
+            Some(self.bounds.as_corners().0 + index.coords.map(|v| v as isize))
+        } else {
+            None
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestLayers;
+
+    fn new_map() -> RollingMap<TestLayers, f64> {
+        let mut map = CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 4), (0, 3)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                position_in_parent: Vector2::new(1.0, 1.0),
+                rotation_in_parent_rad: 0.3,
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        for ((_, index), v) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            *v = (1 + index.x + 10 * index.y) as f64;
+        }
+
+        RollingMap::from_map(map)
+    }
+
+    #[test]
+    fn shifting() {
+        let mut map = new_map();
+        let before = map.to_cell_map();
+        assert_eq!(map.buffer_start(), Point2::new(0, 0));
+
+        map.shift(Vector2::new(1, -2));
+        assert_eq!(map.cell_bounds(), Bounds::new((1, 5), (-2, 1)).unwrap());
+        assert_eq!(map.buffer_start(), Point2::new(1, 1));
+
+        // Cells which stayed in the map keep their values and positions
+        assert_eq!(map.get(TestLayers::Layer0, Point2::new(0, 2)), Some(&2.0));
+        assert_f64_iter_eq!(
+            map.position(Point2::new(0, 2)).unwrap().coords,
+            before.position(Point2::new(1, 0)).unwrap().coords,
+            1e-12
+        );
+        assert_eq!(
+            map.index(before.position(Point2::new(3, 0)).unwrap()),
+            Some(Point2::new(2, 2))
+        );
+
+        // New cells are reset in every layer
+        assert_eq!(
+            map.iter(TestLayers::Layer0).copied().collect::<Vec<_>>(),
+            vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 3.0, 4.0, 0.0]
+        );
+
+        // Shifting further than the size of the map resets everything
+        map.set(TestLayers::Layer1, Point2::new(1, 1), 5.0);
+        map.shift(Vector2::new(-10, 0));
+        assert!(map.iter(TestLayers::Layer0).all(|&v| v == 0.0));
+        assert!(map.iter(TestLayers::Layer1).all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn scrolled_in_regions() {
+        let mut map = new_map();
+
+        assert!(map.shift(Vector2::zeros()).is_empty());
+
+        let regions = map.shift_uninit(Vector2::new(1, -2));
+        assert_eq!(
+            regions,
+            vec![
+                Bounds::new((4, 5), (-2, 1)).unwrap(),
+                Bounds::new((1, 4), (-2, 0)).unwrap()
+            ]
+        );
+
+        // The new cells hold stale values until they're filled
+        assert_eq!(map.get(TestLayers::Layer0, Point2::new(3, 2)), Some(&1.0));
+        for region in &regions {
+            map.fill_region(TestLayers::Layer0, region, |p| p.x);
+        }
+        assert_f64_eq!(
+            *map.get(TestLayers::Layer0, Point2::new(3, 2)).unwrap(),
+            map.position(Point2::new(3, 2)).unwrap().x
+        );
+        assert_eq!(map.get(TestLayers::Layer0, Point2::new(0, 2)), Some(&2.0));
+
+        // Regions are clipped to the map
+        map.fill_region(
+            TestLayers::Layer1,
+            &Bounds::new((-10, 2), (0, 10)).unwrap(),
+            |_| 1.0,
+        );
+        assert_eq!(
+            map.iter(TestLayers::Layer1).filter(|&&v| v == 1.0).count(),
+            1
+        );
+
+        // Moving further than the size of the map replaces the whole map
+        let regions = map.move_to_uninit(Point2::new(-20.0, 0.0));
+        assert_eq!(regions, vec![map.cell_bounds()]);
+    }
+
+    #[test]
+    fn moving() {
+        let mut map = new_map();
+        let target = map.position(Point2::new(3, 2)).unwrap();
+
+        map.move_to(target);
+        assert_eq!(map.cell_bounds(), Bounds::new((1, 5), (1, 4)).unwrap());
+        assert_eq!(map.index(target), Some(Point2::new(2, 1)));
+        assert_eq!(map.get_position(TestLayers::Layer0, target), Some(&24.0));
+
+        assert!(map.set_position(TestLayers::Layer2, target, 1.0));
+        assert!(!map.set_position(TestLayers::Layer2, Point2::new(-5.0, -5.0), 1.0));
+        assert!(!map.set(TestLayers::Layer2, Point2::new(4, 0), 1.0));
+
+        // Converting to a normal map uses the current bounds
+        let copy = map.to_cell_map();
+        assert_eq!(copy.cell_bounds(), map.cell_bounds());
+        assert_eq!(copy[(TestLayers::Layer0, Point2::new(2, 1))], 24.0);
+        assert_eq!(copy.index(target), Some(Point2::new(2, 1)));
+
+        let window = map
+            .window(TestLayers::Layer0, Point2::new(1, 1), Vector2::new(1, 1))
+            .unwrap();
+        assert_eq!(window.row(0).to_vec(), vec![12.0, 13.0, 14.0]);
+        assert_eq!(window.row(1).to_vec(), vec![22.0, 23.0, 24.0]);
+        assert_eq!(window.row(2).to_vec(), vec![0.0, 0.0, 0.0]);
+        assert!(map
+            .window(TestLayers::Layer0, Point2::new(0, 1), Vector2::new(1, 1))
+            .is_none());
+    }
+}
@@ -0,0 +1,128 @@
+//! Provides random sampling of cells, for example to sample goals in free space for RRT planners or
+//! to draw cells for Monte Carlo terrain evaluation.
+//!
+//! This module requires the `random` feature.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::Point2;
+use rand::Rng;
+
+use crate::{CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+{
+    /// Samples up to `n` distinct cells of `layer` for which `predicate` returns `true`, returning
+    /// their indices.
+    ///
+    /// Every matching cell is equally likely to be sampled. If fewer than `n` cells match all
+    /// matching cells are returned. Sampling uses reservoir sampling, so needs a single pass over
+    /// the layer and no memory beyond the `n` samples. The order of the returned indices is not
+    /// meaningful.
+    pub fn sample_cells<F, R>(
+        &self,
+        layer: L,
+        predicate: F,
+        n: usize,
+        rng: &mut R,
+    ) -> Vec<Point2<usize>>
+    where
+        F: Fn(&T) -> bool,
+        R: Rng + ?Sized,
+    {
+        let mut samples = Vec::with_capacity(n);
+
+        if n == 0 {
+            return samples;
+        }
+
+        let matching = self[layer]
+            .indexed_iter()
+            .filter(|(_, v)| predicate(v))
+            .map(|((y, x), _)| Point2::new(x, y));
+
+        for (i, index) in matching.enumerate() {
+            if i < n {
+                samples.push(index);
+            } else {
+                // Replace a sample with probability n / (i + 1)
+                let j = rng.gen_range(0..=i);
+                if j < n {
+                    samples[j] = index;
+                }
+            }
+        }
+
+        samples
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn sample_cells() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 10), (0, 10)).unwrap(),
+                cell_size: Vector2::new(1.0, 1.0),
+                ..Default::default()
+            },
+            1.0,
+        );
+
+        // Mark 10 free cells along the diagonal
+        for i in 0..10 {
+            map[(TestLayers::Layer0, Point2::new(i, i))] = 0.0;
+        }
+        let is_free = |v: &f64| *v == 0.0;
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // Each free cell should be sampled about 3 / 10 of the time
+        let mut counts = [0; 10];
+        for _ in 0..3000 {
+            let samples = map.sample_cells(TestLayers::Layer0, is_free, 3, &mut rng);
+            assert_eq!(samples.len(), 3);
+
+            for (i, s) in samples.iter().enumerate() {
+                assert_eq!(s.x, s.y);
+                assert!(!samples[..i].contains(s));
+                counts[s.x] += 1;
+            }
+        }
+        assert!(
+            counts.iter().all(|&c| (800..1000).contains(&c)),
+            "{:?}",
+            counts
+        );
+
+        // Asking for more cells than match returns all of them
+        let mut samples = map.sample_cells(TestLayers::Layer0, is_free, 20, &mut rng);
+        samples.sort_by_key(|s| s.x);
+        assert_eq!(
+            samples,
+            (0..10).map(|i| Point2::new(i, i)).collect::<Vec<_>>()
+        );
+
+        assert!(map
+            .sample_cells(TestLayers::Layer1, is_free, 5, &mut rng)
+            .is_empty());
+    }
+}
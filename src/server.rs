@@ -0,0 +1,411 @@
+//! Provides the [`MapServer`] type, which owns a [`CellMap`] and serves queries and updates from
+//! any number of [`MapClient`]s over a channel.
+//!
+//! This allows several consumers, for example different tasks in a robot's software stack, to
+//! read from and update a single map without sharing it behind a lock. Requests from all clients
+//! are handled in the order they're received, so every query sees the map with all previously
+//! sent updates applied.
+//!
+//! The server can either be driven manually, by calling [`MapServer::handle_pending()`] from the
+//! owner's own loop, or moved onto its own thread with [`MapServer::run()`].
+//!
+//! # Examples
+//!
+//! ```
+//! # use cell_map::{Bounds, CellMap, CellMapParams, Layer, server::MapServer};
+//! # use nalgebra::{Point2, Vector2};
+//! # #[derive(Layer, Clone, Debug)]
+//! # enum MyLayer {
+//! #     Height,
+//! # }
+//! let map = CellMap::<MyLayer, f64>::new_from_elem(
+//!     CellMapParams {
+//!         cell_bounds: Bounds::new((0, 10), (0, 10)).unwrap(),
+//!         ..Default::default()
+//!     },
+//!     0.0,
+//! );
+//!
+//! let server = MapServer::new(map);
+//! let client = server.client();
+//! let handle = std::thread::spawn(move || server.run());
+//!
+//! client
+//!     .update(|map| map.set(MyLayer::Height, Point2::new(2, 3), 1.5).unwrap())
+//!     .unwrap();
+//! assert_eq!(
+//!     client.get(MyLayer::Height, Point2::new(2.5, 3.5)).unwrap(),
+//!     Some(1.5)
+//! );
+//!
+//! let submap = client.submap(Bounds::new((1, 4), (1, 4)).unwrap()).unwrap();
+//! assert_eq!(submap.num_cells(), Vector2::new(3, 3));
+//!
+//! // The server stops once all clients have been dropped
+//! drop(client);
+//! let map = handle.join().unwrap();
+//! ```
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use nalgebra::Point2;
+
+use crate::{cell_map::Bounds, updates::UpdateQueue, CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Owns a [`CellMap`] and handles requests sent to it by [`MapClient`]s.
+#[derive(Debug)]
+pub struct MapServer<L, T>
+where
+    L: Layer,
+{
+    map: CellMap<L, T>,
+    sender: Sender<Request<L, T>>,
+    receiver: Receiver<Request<L, T>>,
+}
+
+/// A handle used to send requests to a [`MapServer`].
+///
+/// Clients can be cloned and sent between threads freely. All requests block until the server has
+/// handled them.
+#[derive(Debug)]
+pub struct MapClient<L, T>
+where
+    L: Layer,
+{
+    sender: Sender<Request<L, T>>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// A function which updates the map in a [`MapServer`].
+type Update<L, T> = Box<dyn FnOnce(&mut CellMap<L, T>) + Send>;
+
+/// Requests which can be sent to a [`MapServer`].
+enum Request<L, T>
+where
+    L: Layer,
+{
+    Update(Update<L, T>, Sender<()>),
+    Apply(UpdateQueue<L, T>, Sender<Result<(), Error>>),
+    Get(L, Point2<f64>, Sender<Option<T>>),
+    Submap(Bounds, Sender<Result<CellMap<L, T>, Error>>),
+    Snapshot(Sender<CellMap<L, T>>),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> MapServer<L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    /// Creates a new server which owns the given map.
+    pub fn new(map: CellMap<L, T>) -> Self {
+        let (sender, receiver) = channel();
+
+        Self {
+            map,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Creates a new client which can send requests to this server.
+    pub fn client(&self) -> MapClient<L, T> {
+        MapClient {
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// Returns a reference to the served map.
+    pub fn map(&self) -> &CellMap<L, T> {
+        &self.map
+    }
+
+    /// Applies the given update to the map immediately.
+    pub fn update<F: FnOnce(&mut CellMap<L, T>)>(&mut self, update: F) {
+        update(&mut self.map)
+    }
+
+    /// Handles all requests which have been received but not yet handled, without blocking.
+    ///
+    /// Returns the number of requests which were handled.
+    pub fn handle_pending(&mut self) -> usize {
+        let mut handled = 0;
+
+        while let Ok(request) = self.receiver.try_recv() {
+            self.handle(request);
+            handled += 1;
+        }
+
+        handled
+    }
+
+    /// Handles requests until all [`MapClient`]s have been dropped, then returns the map.
+    ///
+    /// The server can't create new clients while it's running, so all clients which will be used
+    /// must be created before calling this function.
+    pub fn run(self) -> CellMap<L, T> {
+        let Self {
+            mut map,
+            sender,
+            receiver,
+        } = self;
+
+        // Drop our own sender so that the channel closes once all clients are gone
+        drop(sender);
+
+        for request in receiver.iter() {
+            Self::handle_request(&mut map, request);
+        }
+
+        map
+    }
+
+    /// Consumes the server, returning the map.
+    ///
+    /// Any requests which haven't been handled are dropped, and their clients will receive
+    /// [`Error::ServerDisconnected`].
+    pub fn into_map(self) -> CellMap<L, T> {
+        self.map
+    }
+
+    fn handle(&mut self, request: Request<L, T>) {
+        Self::handle_request(&mut self.map, request)
+    }
+
+    fn handle_request(map: &mut CellMap<L, T>, request: Request<L, T>) {
+        // Errors sending replies are ignored, since they only mean that the client gave up
+        // waiting for the reply.
+        match request {
+            Request::Update(update, reply) => {
+                update(map);
+                reply.send(()).ok();
+            }
+            Request::Apply(mut queue, reply) => {
+                reply.send(queue.apply_all(map)).ok();
+            }
+            Request::Get(layer, position, reply) => {
+                let value = map
+                    .index(position)
+                    .and_then(|index| map.get(layer, index).cloned());
+                reply.send(value).ok();
+            }
+            Request::Submap(bounds, reply) => {
+                reply.send(map.submap(bounds)).ok();
+            }
+            Request::Snapshot(reply) => {
+                reply.send(map.clone()).ok();
+            }
+        };
+    }
+}
+
+impl<L, T> MapClient<L, T>
+where
+    L: Layer,
+{
+    /// Applies the given update to the served map, returning once it has been applied.
+    pub fn update<F>(&self, update: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut CellMap<L, T>) + Send + 'static,
+    {
+        self.request(|reply| Request::Update(Box::new(update), reply))
+    }
+
+    /// Applies all updates in the given queue to the served map, see
+    /// [`UpdateQueue::apply_all()`].
+    pub fn apply(&self, queue: UpdateQueue<L, T>) -> Result<(), Error> {
+        self.request(|reply| Request::Apply(queue, reply))?
+    }
+
+    /// Gets the value of the given layer at the given parent-frame position, or `None` if the
+    /// position is outside the map.
+    pub fn get(&self, layer: L, position: Point2<f64>) -> Result<Option<T>, Error> {
+        self.request(|reply| Request::Get(layer, position, reply))
+    }
+
+    /// Gets a copy of the region of the map within the given `bounds`, see
+    /// [`CellMap::submap()`].
+    pub fn submap(&self, bounds: Bounds) -> Result<CellMap<L, T>, Error> {
+        self.request(|reply| Request::Submap(bounds, reply))?
+    }
+
+    /// Gets a copy of the whole map.
+    pub fn snapshot(&self) -> Result<CellMap<L, T>, Error> {
+        self.request(Request::Snapshot)
+    }
+
+    /// Sends a request to the server and waits for its reply.
+    fn request<R, F>(&self, build: F) -> Result<R, Error>
+    where
+        F: FnOnce(Sender<R>) -> Request<L, T>,
+    {
+        let (reply, response) = channel();
+
+        self.sender
+            .send(build(reply))
+            .map_err(|_| Error::ServerDisconnected)?;
+
+        response.recv().map_err(|_| Error::ServerDisconnected)
+    }
+}
+
+impl<L, T> Clone for MapClient<L, T>
+where
+    L: Layer,
+{
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<L, T> std::fmt::Debug for Request<L, T>
+where
+    L: Layer,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Request::Update(..) => write!(f, "Update"),
+            Request::Apply(queue, _) => write!(f, "Apply({} updates)", queue.len()),
+            Request::Get(_, position, _) => write!(f, "Get({})", position),
+            Request::Submap(bounds, _) => write!(f, "Submap({:?})", bounds),
+            Request::Snapshot(_) => write!(f, "Snapshot"),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn requests_are_ordered() {
+        let map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-5, 5), (0, 8)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        let server = MapServer::new(map);
+        let clients: Vec<_> = (0..4).map(|_| server.client()).collect();
+        let handle = std::thread::spawn(move || server.run());
+
+        let workers: Vec<_> = clients
+            .into_iter()
+            .enumerate()
+            .map(|(i, client)| {
+                std::thread::spawn(move || {
+                    for _ in 0..10 {
+                        client
+                            .update(move |map| {
+                                map[(TestLayers::Layer0, Point2::new(i, 0))] += 1.0;
+                            })
+                            .unwrap();
+                    }
+
+                    // Each client's updates have been applied before its query
+                    let value = client
+                        .get(
+                            TestLayers::Layer0,
+                            Point2::new(-2.25 + 0.5 * i as f64, 0.25),
+                        )
+                        .unwrap();
+                    assert_eq!(value, Some(10.0));
+
+                    client
+                })
+            })
+            .collect();
+
+        let client = workers
+            .into_iter()
+            .map(|w| w.join().unwrap())
+            .last()
+            .unwrap();
+        let submap = client
+            .submap(Bounds::new((-5, 0), (0, 1)).unwrap())
+            .unwrap();
+        assert_eq!(submap[TestLayers::Layer0].sum(), 40.0);
+        assert!(matches!(
+            client.submap(Bounds::new((-6, 0), (0, 1)).unwrap()),
+            Err(Error::BoundsOutsideMap(..))
+        ));
+
+        // Queues are applied atomically on the server
+        let mut queue = UpdateQueue::new();
+        queue.fill_region(
+            TestLayers::Layer1,
+            Bounds::new((0, 1), (0, 1)).unwrap(),
+            2.0,
+        );
+        queue.set_cells(TestLayers::Layer1, vec![(Point2::new(10, 0), 1.0)]);
+        assert!(client.apply(queue).is_err());
+
+        let mut queue = UpdateQueue::new();
+        queue.fill_region(
+            TestLayers::Layer1,
+            Bounds::new((0, 1), (0, 1)).unwrap(),
+            2.0,
+        );
+        queue.set_cells(TestLayers::Layer1, vec![(Point2::new(9, 0), 1.0)]);
+        client.apply(queue).unwrap();
+
+        drop(client);
+        let map = handle.join().unwrap();
+        assert_eq!(map.iter().sum::<f64>(), 43.0);
+    }
+
+    #[test]
+    fn manual_handling() {
+        let map = CellMap::<TestLayers, u8>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 4), (0, 4)).unwrap(),
+                ..Default::default()
+            },
+            0,
+        );
+
+        let mut server = MapServer::new(map);
+        let client = server.client();
+        assert_eq!(server.handle_pending(), 0);
+
+        let worker = std::thread::spawn(move || client.snapshot());
+        while server.handle_pending() == 0 {
+            std::thread::yield_now();
+        }
+        assert_eq!(
+            worker.join().unwrap().unwrap().num_cells(),
+            Vector2::new(4, 4)
+        );
+
+        // Clients of a dropped server are disconnected
+        let client = server.client();
+        drop(server);
+        assert!(matches!(client.snapshot(), Err(Error::ServerDisconnected)));
+    }
+}
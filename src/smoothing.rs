@@ -0,0 +1,212 @@
+//! Provides gradient-descent smoothing of paths over a cost layer, for polishing the output of
+//! grid planners.
+//!
+//! Each interior waypoint is repeatedly moved by the sum of three terms:
+//!
+//!  - a data term pulling it back towards its original position, so the path doesn't drift far
+//!    from what the planner found,
+//!  - a smoothness term pulling it towards the midpoint of its neighbours, which limits the
+//!    curvature of the path,
+//!  - a cost term moving it down the gradient of the cost layer, away from expensive cells.
+//!
+//! The first and last waypoints are never moved.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+use serde::{Deserialize, Serialize};
+
+use crate::{CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Parameters which control how paths are smoothed by [`CellMap::smooth_path()`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SmoothingParams {
+    /// The weight of the term pulling waypoints towards their original positions.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.2`.
+    pub data_weight: f64,
+
+    /// The weight of the term pulling waypoints towards the midpoint of their neighbours. Larger
+    /// values give straighter paths with lower curvature.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.4`.
+    pub smoothness_weight: f64,
+
+    /// The weight of the term moving waypoints down the cost gradient.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.1`.
+    pub cost_weight: f64,
+
+    /// The fraction of the combined update applied to each waypoint per iteration.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.5`.
+    pub step_size: f64,
+
+    /// The maximum number of iterations.
+    ///
+    /// # Default
+    ///
+    /// The default value is `100`.
+    pub max_iterations: usize,
+
+    /// Smoothing stops early once the total distance moved by all waypoints in an iteration is
+    /// below this value, in parent-frame units.
+    ///
+    /// # Default
+    ///
+    /// The default value is `1e-6`.
+    pub tolerance: f64,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Default for SmoothingParams {
+    fn default() -> Self {
+        Self {
+            data_weight: 0.2,
+            smoothness_weight: 0.4,
+            cost_weight: 0.1,
+            step_size: 0.5,
+            max_iterations: 100,
+            tolerance: 1e-6,
+        }
+    }
+}
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Smooths `path`, given in the parent frame, by gradient descent on `cost_layer`, returning
+    /// the smoothed path.
+    ///
+    /// The cost gradient at a waypoint is found using central differences one cell size either
+    /// side of it. Components of the gradient which would use `NaN` cells or cells outside the
+    /// map are zero, so waypoints near unknown space are only moved by the data and smoothness
+    /// terms. Paths with fewer than three waypoints are returned unchanged.
+    pub fn smooth_path(
+        &self,
+        path: &[Point2<f64>],
+        cost_layer: L,
+        params: &SmoothingParams,
+    ) -> Vec<Point2<f64>> {
+        let mut smoothed = path.to_vec();
+
+        if path.len() < 3 {
+            return smoothed;
+        }
+
+        let cost = &self[cost_layer];
+        let cost_at = |position: Point2<f64>| {
+            self.index(position)
+                .map(|i| cost[(i.y, i.x)])
+                .filter(|c| !c.is_nan())
+        };
+        let step = self.cell_size();
+        let derivative = |position: Point2<f64>, offset: Vector2<f64>| match (
+            cost_at(position + offset),
+            cost_at(position - offset),
+        ) {
+            (Some(a), Some(b)) => (a - b) / (2.0 * offset.norm()),
+            _ => 0.0,
+        };
+
+        for _ in 0..params.max_iterations {
+            let mut moved = 0.0;
+
+            for i in 1..smoothed.len() - 1 {
+                let current = smoothed[i];
+
+                let data = path[i] - current;
+                let smoothness =
+                    (smoothed[i - 1].coords + smoothed[i + 1].coords) / 2.0 - current.coords;
+                let gradient = Vector2::new(
+                    derivative(current, Vector2::new(step.x, 0.0)),
+                    derivative(current, Vector2::new(0.0, step.y)),
+                );
+
+                let update = params.step_size
+                    * (params.data_weight * data + params.smoothness_weight * smoothness
+                        - params.cost_weight * gradient);
+
+                smoothed[i] += update;
+                moved += update.norm();
+            }
+
+            if moved < params.tolerance {
+                break;
+            }
+        }
+
+        smoothed
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn smooth_path() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 10), (0, 10)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // A path with a sharp corner is straightened, keeping its end points
+        let corner = vec![
+            Point2::new(1.5, 5.0),
+            Point2::new(3.5, 5.0),
+            Point2::new(5.0, 7.0),
+            Point2::new(6.5, 5.0),
+            Point2::new(8.5, 5.0),
+        ];
+        let params = SmoothingParams {
+            cost_weight: 0.0,
+            ..Default::default()
+        };
+        let smoothed = map.smooth_path(&corner, TestLayers::Layer0, &params);
+        assert_eq!(smoothed[0], corner[0]);
+        assert_eq!(smoothed[4], corner[4]);
+        assert!(smoothed[2].y < 6.5);
+        assert!(smoothed[2].y > 5.0);
+
+        // Cost rising towards +y pushes the waypoints of a straight path down the gradient
+        for ((_, index), cost) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            *cost = index.y as f64;
+        }
+        let straight: Vec<_> = (1..9).map(|x| Point2::new(x as f64 + 0.5, 5.5)).collect();
+        let smoothed = map.smooth_path(&straight, TestLayers::Layer0, &SmoothingParams::default());
+        assert!(smoothed[1..7].iter().all(|p| p.y < 5.5));
+        assert_eq!(smoothed[7], straight[7]);
+
+        assert_eq!(
+            map.smooth_path(&straight[..2], TestLayers::Layer0, &params),
+            straight[..2].to_vec()
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! Provides sun exposure analysis of height layers: hillshading, cast shadows, and the cumulative
+//! insolation of each cell over a series of sun positions.
+//!
+//! Hillshading gives the fraction of full sunlight falling on each cell for a single sun position
+//! using Lambert's cosine law, which is a common way to visualise terrain. For planning, such as
+//! keeping a solar-powered rover in the sun, [`CellMap::insolation()`] also accounts for shadows
+//! cast by the surrounding terrain and accumulates the exposure over a day.
+//!
+//! Sun directions are given in the parent frame. The azimuth is the angle of the direction
+//! towards the sun, anticlockwise from the parent frame's `+x` axis, and the elevation is its
+//! angle above the horizontal, both in radians. Heights are in the same units as the parent
+//! frame.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Matrix2, Point2, Vector2, Vector3};
+use ndarray::{Array2, ArrayView2};
+use serde::{Deserialize, Serialize};
+
+use crate::{math, CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// The direction towards the sun at one instant, see the [module documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SunPosition {
+    /// The angle of the direction towards the sun, anticlockwise from the parent frame's `+x`
+    /// axis, in radians.
+    pub azimuth: f64,
+
+    /// The angle of the sun above the horizontal, in radians. The sun is below the horizon if
+    /// this is not positive.
+    pub elevation: f64,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl SunPosition {
+    /// Returns the unit vector towards the sun in the parent frame, with `z` upwards.
+    fn direction(&self) -> Vector3<f64> {
+        let (sin_az, cos_az) = math::sin_cos(self.azimuth);
+        let (sin_el, cos_el) = math::sin_cos(self.elevation);
+
+        Vector3::new(cos_el * cos_az, cos_el * sin_az, sin_el)
+    }
+}
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Computes the hillshade of `height_layer` for a sun at the given azimuth and elevation,
+    /// writing the result into `dst_layer`.
+    ///
+    /// Each cell is set to the cosine of the angle between its surface normal and the direction
+    /// towards the sun, clamped to zero for cells facing away from the sun, so a cell facing the
+    /// sun directly has a value of `1.0`. Shadows cast by other cells are not included, see
+    /// [`CellMap::sun_shadows()`].
+    ///
+    /// The surface normal is found from the gradient of the height, using central differences
+    /// or one-sided differences at the edge of the map as for [`CellMap::slope()`]. Cells whose
+    /// neighbours are `NaN` are `NaN`.
+    pub fn hillshade(
+        &mut self,
+        height_layer: L,
+        dst_layer: L,
+        sun_azimuth: f64,
+        sun_elevation: f64,
+    ) {
+        let sun = SunPosition {
+            azimuth: sun_azimuth,
+            elevation: sun_elevation,
+        };
+
+        self[dst_layer] = self.illumination(self[height_layer].view(), sun.direction());
+    }
+
+    /// Returns a mask of the cells of `height_layer` which are in a shadow cast by other cells
+    /// for the given sun position, indexed by `(y, x)`.
+    ///
+    /// A cell is in shadow if the ray from its centre towards the sun passes below the height of
+    /// any cell it crosses before leaving the map. The ray is sampled every half of the smallest
+    /// cell side. Every cell is in shadow if the sun is below the horizon, and `NaN` cells are
+    /// never in shadow and never cast shadows.
+    pub fn sun_shadows(&self, height_layer: L, sun: &SunPosition) -> Array2<bool> {
+        let data = self[height_layer].view();
+        if sun.elevation <= 0.0 {
+            return Array2::from_elem(data.dim(), true);
+        }
+
+        let (sin_az, cos_az) = math::sin_cos(sun.azimuth);
+        let (sin_el, cos_el) = math::sin_cos(sun.elevation);
+        let direction = Vector2::new(cos_az, sin_az);
+        let rise = sin_el / cos_el;
+        let step = self.cell_size().min() / 2.0;
+
+        Array2::from_shape_fn(data.dim(), |(y, x)| {
+            let height = data[(y, x)];
+            if height.is_nan() {
+                return false;
+            }
+
+            let start = self.position_unchecked(Point2::new(x, y));
+            let mut distance = step;
+
+            while let Some(index) = self.index(start + direction * distance) {
+                if data[(index.y, index.x)] > height + distance * rise {
+                    return true;
+                }
+                distance += step;
+            }
+
+            false
+        })
+    }
+
+    /// Computes the cumulative insolation of `height_layer` over the given sun positions, writing
+    /// the result into `dst_layer`.
+    ///
+    /// Each sun position adds the [hillshade](CellMap::hillshade()) of the cell multiplied by
+    /// `duration`, unless the cell is in a [shadow](CellMap::sun_shadows()), so with positions
+    /// every hour through a day and a `duration` of `1.0` the result is the number of hours of
+    /// equivalent full sun each cell receives. Positions with the sun below the horizon add
+    /// nothing. Cells whose neighbours are `NaN` are `NaN`.
+    pub fn insolation(
+        &mut self,
+        height_layer: L,
+        dst_layer: L,
+        sun_positions: &[SunPosition],
+        duration: f64,
+    ) {
+        let data = self[height_layer.clone()].view();
+        // Start from zero, or NaN for the cells whose normal can't be found
+        let mut total = self.illumination(data, Vector3::z()).mapv(|v| v * 0.0);
+
+        for sun in sun_positions.iter().filter(|s| s.elevation > 0.0) {
+            let illumination = self.illumination(data, sun.direction());
+            let shadows = self.sun_shadows(height_layer.clone(), sun);
+
+            ndarray::Zip::from(&mut total)
+                .and(&illumination)
+                .and(&shadows)
+                .for_each(|t, &i, &s| {
+                    if !s {
+                        *t += i * duration;
+                    }
+                });
+        }
+
+        self[dst_layer] = total;
+    }
+
+    /// Returns the cosine of the angle between each cell's surface normal and `sun`, clamped to
+    /// zero.
+    fn illumination(&self, data: ArrayView2<'_, f64>, sun: Vector3<f64>) -> Array2<f64> {
+        let (rows, cols) = data.dim();
+
+        // The gradient per cell in the map's index axes is converted to the parent frame with
+        // the inverse transpose of the map's linear transform, which is always invertible since
+        // maps have non-zero cell sizes
+        let m = self.to_parent().to_homogeneous();
+        let det = m[(0, 0)] * m[(1, 1)] - m[(0, 1)] * m[(1, 0)];
+        let to_gradient = Matrix2::new(m[(1, 1)], -m[(1, 0)], -m[(0, 1)], m[(0, 0)]) / det;
+
+        Array2::from_shape_fn((rows, cols), |(y, x)| {
+            let (x0, x1) = (x.saturating_sub(1), (x + 1).min(cols - 1));
+            let (y0, y1) = (y.saturating_sub(1), (y + 1).min(rows - 1));
+
+            let dx = if x1 > x0 {
+                (data[(y, x1)] - data[(y, x0)]) / (x1 - x0) as f64
+            } else {
+                0.0
+            };
+            let dy = if y1 > y0 {
+                (data[(y1, x)] - data[(y0, x)]) / (y1 - y0) as f64
+            } else {
+                0.0
+            };
+
+            let gradient = to_gradient * Vector2::new(dx, dy);
+            let normal = Vector3::new(-gradient.x, -gradient.y, 1.0);
+            let cos = normal.dot(&sun) / normal.norm();
+
+            if cos.is_nan() || data[(y, x)].is_nan() {
+                f64::NAN
+            } else {
+                cos.max(0.0)
+            }
+        })
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, FRAC_PI_6, PI};
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    /// A plane rising at 45 degrees along the parent frame's `+x` axis, for a map with the given
+    /// rotation.
+    fn ramp(rotation: f64) -> CellMap<TestLayers, f64> {
+        let mut map = CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 5), (0, 4)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.25),
+                rotation_in_parent_rad: rotation,
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        let positions: Vec<_> = map.iter().indexed().map(|((_, i), _)| i).collect();
+        for index in positions {
+            map[(TestLayers::Layer0, index)] = map.position(index).unwrap().x;
+        }
+
+        map
+    }
+
+    #[test]
+    fn hillshade() {
+        for &rotation in &[0.0, FRAC_PI_6, PI] {
+            let mut map = ramp(rotation);
+
+            // The ramp faces the sun directly when it's 45 degrees up towards -x
+            map.hillshade(TestLayers::Layer0, TestLayers::Layer1, PI, FRAC_PI_4);
+            for &v in map.iter().layer(TestLayers::Layer1) {
+                assert_f64_eq!(v, 1.0, 1e-9);
+            }
+
+            // And is edge-on to a sun 45 degrees up towards +x
+            map.hillshade(TestLayers::Layer0, TestLayers::Layer1, 0.0, FRAC_PI_4);
+            for &v in map.iter().layer(TestLayers::Layer1) {
+                assert_f64_eq!(v, 0.0, 1e-9);
+            }
+
+            // Flat ground gets the sine of the elevation
+            map.hillshade(TestLayers::Layer2, TestLayers::Layer1, 1.0, FRAC_PI_6);
+            for &v in map.iter().layer(TestLayers::Layer1) {
+                assert_f64_eq!(v, 0.5, 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn shadows_and_insolation() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 8), (0, 3)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // A 2 high wall in column 4
+        for y in 0..3 {
+            map[(TestLayers::Layer0, Point2::new(4, y))] = 2.0;
+        }
+
+        // With the sun low towards +x the wall shades the two cells in front of it
+        let low = SunPosition {
+            azimuth: 0.0,
+            elevation: 0.6,
+        };
+        let shadows = map.sun_shadows(TestLayers::Layer0, &low);
+        assert_eq!(
+            shadows.row(1).to_vec(),
+            vec![false, true, true, true, false, false, false, false]
+        );
+
+        let overhead = SunPosition {
+            azimuth: 0.0,
+            elevation: FRAC_PI_2,
+        };
+        let below = SunPosition {
+            azimuth: 0.0,
+            elevation: -0.1,
+        };
+        assert!(map
+            .sun_shadows(TestLayers::Layer0, &below)
+            .iter()
+            .all(|&s| s));
+
+        map.insolation(
+            TestLayers::Layer0,
+            TestLayers::Layer1,
+            &[low, overhead, below],
+            2.0,
+        );
+
+        // Cells away from the wall get both positions, and shaded cells only the overhead sun
+        let insolation = |x| map[(TestLayers::Layer1, Point2::new(x, 1))];
+        assert_f64_eq!(insolation(0), 2.0 + 2.0 * 0.6f64.sin(), 1e-9);
+        assert_f64_eq!(insolation(2), 2.0, 1e-9);
+        assert_f64_eq!(insolation(7), 2.0 + 2.0 * 0.6f64.sin(), 1e-9);
+    }
+}
@@ -0,0 +1,375 @@
+//! Provides nearest-cell queries, such as snapping a goal to the closest free cell, and the
+//! [`CellSpatialIndex`] type for repeated proximity queries over a fixed set of cells.
+//!
+//! [`CellMap::nearest_cell_where()`] searches the map directly, so is best for one-off queries.
+//! When many queries are made against the same cells, for example checking the clearance of every
+//! pose in a set of candidate trajectories, building a [`CellSpatialIndex`] once avoids scanning
+//! the grid for each query.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::Point2;
+
+use crate::{CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A k-d tree over the centres of a set of cells, see [`CellMap::build_index()`].
+///
+/// The index is a snapshot of the map when it was built, and isn't updated if the map changes.
+#[derive(Debug, Clone, Default)]
+pub struct CellSpatialIndex {
+    /// The parent-frame centre and index of each cell, arranged as an implicit tree in which the
+    /// middle element of each slice splits the rest along the axis for its depth.
+    nodes: Vec<(Point2<f64>, Point2<usize>)>,
+}
+
+/// The best cells found so far by a search of a [`CellSpatialIndex`], sorted by distance.
+struct Neighbours {
+    k: usize,
+    radius: f64,
+    found: Vec<(Point2<usize>, f64)>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+{
+    /// Returns the cell of `layer` closest to the parent-frame `position` for which `predicate`
+    /// returns `true`, along with the distance from `position` to the centre of that cell.
+    ///
+    /// Cells are searched in square rings spiralling outwards from the cell containing
+    /// `position`, which may be outside the map, and the search stops once no closer cell can be
+    /// found. Cells whose centres are further than `max_radius` from `position` are ignored, so
+    /// `None` is returned if there are no matching cells within `max_radius`.
+    pub fn nearest_cell_where<F>(
+        &self,
+        layer: L,
+        position: Point2<f64>,
+        predicate: F,
+        max_radius: f64,
+    ) -> Option<(Point2<usize>, f64)>
+    where
+        F: Fn(&T) -> bool,
+    {
+        let data = &self[layer];
+        let (rows, cols) = data.dim();
+        if rows == 0 || cols == 0 {
+            return None;
+        }
+
+        // The search starts from the cell containing the position, relative to the first cell
+        let bounds = self.cell_bounds();
+        let cell = self.metadata.get_cell(position);
+        let (sx, sy) = (cell.x - bounds.x.0, cell.y - bounds.y.0);
+
+        // Cells in ring r are at least r - 1/2 cells from the position along one axis
+        let min_cell_size = self.cell_size().min();
+        let max_ring = [sx, cols as isize - 1 - sx, sy, rows as isize - 1 - sy]
+            .iter()
+            .map(|d| d.unsigned_abs())
+            .max()
+            .unwrap_or(0);
+
+        let mut best: Option<(Point2<usize>, f64)> = None;
+
+        for r in 0..=max_ring {
+            let ring_distance = (r as f64 - 0.5).max(0.0) * min_cell_size;
+            if ring_distance > max_radius || best.is_some_and(|(_, d)| ring_distance > d) {
+                break;
+            }
+
+            let r = r as isize;
+            for dy in -r..=r {
+                // Only the first and last rows of the ring are full, the rest are just the ends
+                let step = if dy.abs() == r {
+                    1
+                } else {
+                    (2 * r).max(1) as usize
+                };
+
+                for dx in (-r..=r).step_by(step) {
+                    let (x, y) = (sx + dx, sy + dy);
+                    if x < 0 || y < 0 || x >= cols as isize || y >= rows as isize {
+                        continue;
+                    }
+
+                    let index = Point2::new(x as usize, y as usize);
+                    if !predicate(&data[(index.y, index.x)]) {
+                        continue;
+                    }
+
+                    let distance = (self.position_unchecked(index) - position).norm();
+                    if distance <= max_radius && best.is_none_or(|(_, d)| distance < d) {
+                        best = Some((index, distance));
+                    }
+                }
+            }
+        }
+
+        best
+    }
+
+    /// Builds a [`CellSpatialIndex`] over the cells of `layer` for which `predicate` returns
+    /// `true`, such as the obstacle cells of a map.
+    pub fn build_index<F>(&self, layer: L, predicate: F) -> CellSpatialIndex
+    where
+        F: Fn(&T) -> bool,
+    {
+        let mut nodes: Vec<_> = self[layer]
+            .indexed_iter()
+            .filter(|(_, v)| predicate(v))
+            .map(|((y, x), _)| {
+                let index = Point2::new(x, y);
+                (self.position_unchecked(index), index)
+            })
+            .collect();
+
+        build(&mut nodes, 0);
+
+        CellSpatialIndex { nodes }
+    }
+}
+
+impl CellSpatialIndex {
+    /// Returns the number of cells in the index.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if there are no cells in the index.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the cell whose centre is closest to the parent-frame `position`, along with the
+    /// distance to it, or `None` if the index is empty.
+    pub fn nearest(&self, position: Point2<f64>) -> Option<(Point2<usize>, f64)> {
+        self.k_nearest(position, 1).pop()
+    }
+
+    /// Returns the `k` cells whose centres are closest to the parent-frame `position`, along with
+    /// the distance to each, sorted from nearest to furthest.
+    ///
+    /// Fewer than `k` cells are returned if the index contains fewer than `k` cells.
+    pub fn k_nearest(&self, position: Point2<f64>, k: usize) -> Vec<(Point2<usize>, f64)> {
+        self.search(position, k, f64::INFINITY)
+    }
+
+    /// Returns every cell whose centre is within `radius` of the parent-frame `position`, along
+    /// with the distance to each, sorted from nearest to furthest.
+    pub fn within_radius(&self, position: Point2<f64>, radius: f64) -> Vec<(Point2<usize>, f64)> {
+        self.search(position, usize::MAX, radius)
+    }
+
+    /// Returns up to `k` of the nearest cells within `radius` of `position`.
+    fn search(&self, position: Point2<f64>, k: usize, radius: f64) -> Vec<(Point2<usize>, f64)> {
+        let mut neighbours = Neighbours {
+            k,
+            radius,
+            found: Vec::new(),
+        };
+
+        if k > 0 {
+            search(&self.nodes, 0, position, &mut neighbours);
+        }
+
+        neighbours.found
+    }
+}
+
+impl Neighbours {
+    /// Returns the distance beyond which cells can't be added to the neighbours.
+    fn bound(&self) -> f64 {
+        match self.found.last() {
+            Some(&(_, d)) if self.found.len() >= self.k => d,
+            _ => self.radius,
+        }
+    }
+
+    /// Adds the given cell to the neighbours if it's closer than the current bound.
+    fn offer(&mut self, index: Point2<usize>, distance: f64) {
+        if distance > self.radius || (self.found.len() >= self.k && distance >= self.bound()) {
+            return;
+        }
+
+        let at = self.found.partition_point(|&(_, d)| d <= distance);
+        self.found.insert(at, (index, distance));
+        self.found.truncate(self.k);
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Arranges `nodes` into an implicit k-d tree, splitting along `x` at even depths and `y` at odd
+/// depths.
+fn build(nodes: &mut [(Point2<f64>, Point2<usize>)], depth: usize) {
+    if nodes.len() <= 1 {
+        return;
+    }
+
+    let mid = nodes.len() / 2;
+    let axis = depth % 2;
+    nodes.select_nth_unstable_by(mid, |a, b| a.0[axis].total_cmp(&b.0[axis]));
+
+    let (below, above) = nodes.split_at_mut(mid);
+    build(below, depth + 1);
+    build(&mut above[1..], depth + 1);
+}
+
+/// Searches the implicit k-d tree `nodes` for the neighbours of `position`, visiting the side of
+/// each split containing `position` first and skipping the other side if it's beyond the bound.
+fn search(
+    nodes: &[(Point2<f64>, Point2<usize>)],
+    depth: usize,
+    position: Point2<f64>,
+    neighbours: &mut Neighbours,
+) {
+    if nodes.is_empty() {
+        return;
+    }
+
+    let mid = nodes.len() / 2;
+    let (centre, index) = nodes[mid];
+    neighbours.offer(index, (centre - position).norm());
+
+    let axis = depth % 2;
+    let offset = position[axis] - centre[axis];
+    let (near, far) = if offset < 0.0 {
+        (&nodes[..mid], &nodes[mid + 1..])
+    } else {
+        (&nodes[mid + 1..], &nodes[..mid])
+    };
+
+    search(near, depth + 1, position, neighbours);
+    if offset.abs() <= neighbours.bound() {
+        search(far, depth + 1, position, neighbours);
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn nearest_cell_where() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-5, 5), (-5, 5)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            1.0,
+        );
+
+        // Free cells are 0.0
+        map[(TestLayers::Layer0, Point2::new(8, 5))] = 0.0;
+        map[(TestLayers::Layer0, Point2::new(2, 2))] = 0.0;
+
+        let free = |&v: &f64| v < 0.5;
+        let (index, distance) = map
+            .nearest_cell_where(TestLayers::Layer0, Point2::new(0.1, 0.1), free, 10.0)
+            .unwrap();
+        assert_eq!(index, Point2::new(8, 5));
+        assert!((distance - (1.65f64.powi(2) + 0.15f64.powi(2)).sqrt()).abs() < 1e-12);
+
+        // A closer cell further around the spiral is preferred
+        let (index, _) = map
+            .nearest_cell_where(TestLayers::Layer0, Point2::new(-1.0, -1.0), free, 10.0)
+            .unwrap();
+        assert_eq!(index, Point2::new(2, 2));
+
+        // Positions outside the map search inwards
+        let (index, distance) = map
+            .nearest_cell_where(TestLayers::Layer0, Point2::new(6.0, 0.25), free, 10.0)
+            .unwrap();
+        assert_eq!(index, Point2::new(8, 5));
+        assert!((distance - 4.25).abs() < 1e-12);
+
+        // Nothing within the radius
+        assert!(map
+            .nearest_cell_where(TestLayers::Layer0, Point2::new(-2.0, 2.0), free, 1.0)
+            .is_none());
+        assert!(map
+            .nearest_cell_where(TestLayers::Layer1, Point2::new(0.0, 0.0), free, 100.0)
+            .is_none());
+    }
+
+    #[test]
+    fn cell_spatial_index() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 12), (0, 9)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.25),
+                rotation_in_parent_rad: 0.3,
+                position_in_parent: Vector2::new(1.0, -2.0),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // A scattering of obstacles
+        for i in 0..40usize {
+            map[(TestLayers::Layer0, Point2::new(i * 7 % 12, i * 5 % 9))] = 1.0;
+        }
+        let obstacle = |&v: &f64| v > 0.5;
+        let index = map.build_index(TestLayers::Layer0, obstacle);
+        let obstacles: Vec<_> = map
+            .iter()
+            .layer(TestLayers::Layer0)
+            .indexed()
+            .filter(|(_, v)| obstacle(v))
+            .map(|((_, i), _)| i)
+            .collect();
+        assert_eq!(index.len(), obstacles.len());
+
+        // Compare against a brute force search from points around and outside the map
+        for &(x, y) in &[(1.0, -2.0), (3.2, 0.4), (-1.0, 5.0), (2.5, -1.1)] {
+            let position = Point2::new(x, y);
+            let mut expected: Vec<f64> = obstacles
+                .iter()
+                .map(|&i| (map.position(i).unwrap() - position).norm())
+                .collect();
+            expected.sort_by(|a, b| a.total_cmp(b));
+
+            let nearest = index.k_nearest(position, 5);
+            assert_eq!(nearest.len(), 5);
+            for ((i, d), e) in nearest.iter().zip(&expected) {
+                assert!((d - e).abs() < 1e-12);
+                assert!(((map.position(*i).unwrap() - position).norm() - d).abs() < 1e-12);
+            }
+            assert_eq!(index.nearest(position).map(|(_, d)| d), Some(expected[0]));
+
+            let within = index.within_radius(position, 1.0);
+            assert_eq!(within.len(), expected.iter().filter(|&&d| d <= 1.0).count());
+            assert!(within.windows(2).all(|w| w[0].1 <= w[1].1));
+        }
+
+        // Asking for more cells than there are returns all of them
+        assert_eq!(
+            index.k_nearest(Point2::origin(), 1000).len(),
+            obstacles.len()
+        );
+        assert!(map
+            .build_index(TestLayers::Layer1, obstacle)
+            .nearest(Point2::origin())
+            .is_none());
+    }
+}
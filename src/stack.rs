@@ -0,0 +1,183 @@
+//! Provides the [`MapStack`] type, which answers point queries over several maps at once, so that
+//! for example a global prior map and a local sensor map can be queried through one interface.
+//!
+//! The maps in a stack can have different cell sizes, bounds and positions, since they are only
+//! queried by parent-frame position. Queries either take the value from the map with the highest
+//! precedence, or blend the values of every map.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::Point2;
+
+use crate::{comparison::CompareValue, CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A stack of borrowed maps which share a parent frame, queried by position.
+///
+/// Maps pushed later have higher precedence, so the usual order is to push the coarsest, oldest
+/// map first and the most detailed, up to date map last.
+#[derive(Debug, Clone)]
+pub struct MapStack<'m, L, T>
+where
+    L: Layer,
+{
+    /// The maps and their blending weights, from lowest to highest precedence.
+    maps: Vec<(&'m CellMap<L, T>, f64)>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<'m, L, T> MapStack<'m, L, T>
+where
+    L: Layer,
+{
+    /// Creates a new empty stack.
+    pub fn new() -> Self {
+        Self { maps: Vec::new() }
+    }
+
+    /// Pushes a map onto the top of the stack, giving it precedence over all maps already in the
+    /// stack. `weight` is used when blending values with [`MapStack::blend()`].
+    pub fn push(&mut self, map: &'m CellMap<L, T>, weight: f64) {
+        self.maps.push((map, weight));
+    }
+
+    /// Returns the number of maps in the stack.
+    pub fn len(&self) -> usize {
+        self.maps.len()
+    }
+
+    /// Returns `true` if the stack contains no maps.
+    pub fn is_empty(&self) -> bool {
+        self.maps.is_empty()
+    }
+
+    /// Returns the value of `layer` at the given parent-frame position in the highest precedence
+    /// map which contains the position, or `None` if no map contains it.
+    pub fn get(&self, layer: L, position: Point2<f64>) -> Option<&'m T> {
+        self.values(layer, position).next()
+    }
+
+    /// Returns the values of `layer` at the given position in every map which contains it, from
+    /// highest to lowest precedence.
+    fn values(&self, layer: L, position: Point2<f64>) -> impl Iterator<Item = &'m T> + '_ {
+        self.weighted_values(layer, position).map(|(v, _)| v)
+    }
+
+    /// Returns the values of `layer` at the given position in every map which contains it, from
+    /// highest to lowest precedence, along with the weight of each map.
+    fn weighted_values(
+        &self,
+        layer: L,
+        position: Point2<f64>,
+    ) -> impl Iterator<Item = (&'m T, f64)> + '_ {
+        self.maps.iter().rev().filter_map(move |&(map, weight)| {
+            let index = map.index(position)?;
+            map.get(layer.clone(), index).map(|v| (v, weight))
+        })
+    }
+}
+
+impl<'m, L, T> MapStack<'m, L, T>
+where
+    L: Layer,
+    T: CompareValue,
+{
+    /// Returns the value of `layer` at the given parent-frame position in the highest precedence
+    /// map which has a valid value there, as defined by [`CompareValue::as_f64()`], so that gaps
+    /// in a local map are filled from the maps below it.
+    pub fn get_valid(&self, layer: L, position: Point2<f64>) -> Option<&'m T> {
+        self.values(layer, position).find(|v| v.as_f64().is_some())
+    }
+
+    /// Returns the weighted mean of the valid values of `layer` at the given parent-frame
+    /// position in every map, using the weight each map was pushed with.
+    ///
+    /// Returns `None` if no map has a valid value at the position or their weights sum to zero.
+    pub fn blend(&self, layer: L, position: Point2<f64>) -> Option<f64> {
+        let (sum, total_weight) = self
+            .weighted_values(layer, position)
+            .filter_map(|(v, w)| Some((v.as_f64()?, w)))
+            .fold((0.0, 0.0), |(sum, total), (v, w)| (sum + v * w, total + w));
+
+        if total_weight != 0.0 {
+            Some(sum / total_weight)
+        } else {
+            None
+        }
+    }
+}
+
+impl<L, T> Default for MapStack<'_, L, T>
+where
+    L: Layer,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn map_stack() {
+        // A coarse global map and a fine local map covering part of it
+        let global = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-10, 10), (-10, 10)).unwrap(),
+                ..Default::default()
+            },
+            1.0,
+        );
+        let mut local = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 8), (0, 8)).unwrap(),
+                cell_size: Vector2::new(0.25, 0.25),
+                position_in_parent: Vector2::new(2.0, 2.0),
+                ..Default::default()
+            },
+            5.0,
+        );
+        local[(TestLayers::Layer0, Point2::new(0, 0))] = f64::NAN;
+
+        let mut stack = MapStack::new();
+        assert!(stack.is_empty());
+        stack.push(&global, 1.0);
+        stack.push(&local, 3.0);
+        assert_eq!(stack.len(), 2);
+
+        // Inside the local map it takes precedence, except where it has no valid data
+        let inside = Point2::new(3.0, 3.0);
+        let gap = Point2::new(2.1, 2.1);
+        assert_eq!(stack.get(TestLayers::Layer0, inside), Some(&5.0));
+        assert!(stack.get(TestLayers::Layer0, gap).unwrap().is_nan());
+        assert_eq!(stack.get_valid(TestLayers::Layer0, gap), Some(&1.0));
+        assert_eq!(stack.blend(TestLayers::Layer0, inside), Some(4.0));
+        assert_eq!(stack.blend(TestLayers::Layer0, gap), Some(1.0));
+
+        // Outside the local map only the global map answers
+        let outside = Point2::new(-5.0, 5.0);
+        assert_eq!(stack.get(TestLayers::Layer0, outside), Some(&1.0));
+        assert_eq!(stack.get(TestLayers::Layer0, Point2::new(20.0, 0.0)), None);
+        assert_eq!(
+            stack.blend(TestLayers::Layer0, Point2::new(20.0, 0.0)),
+            None
+        );
+    }
+}
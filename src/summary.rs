@@ -0,0 +1,226 @@
+//! Provides a short human-readable summary of a map, see [`CellMap::summary()`].
+//!
+//! Summaries are meant for logging the state of a map without dumping all of its data, which the
+//! [`Debug`] implementation of [`CellMap`] does. [`CellMap`] also implements [`Display`] using its
+//! summary, for any cell type implementing [`CompareValue`], which includes the float, integer and
+//! `bool` types.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::fmt::{self, Debug, Display};
+
+use nalgebra::Vector2;
+use serde::{Deserialize, Serialize};
+
+use crate::{cell_map::Bounds, comparison::CompareValue, CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A summary of a map's parameters and the values in each of its layers, produced by
+/// [`CellMap::summary()`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapSummary<L> {
+    /// The bounds of the map.
+    pub cell_bounds: Bounds,
+
+    /// The size of each cell in the map.
+    pub cell_size: Vector2<f64>,
+
+    /// The position of the map in its parent frame.
+    pub position_in_parent: Vector2<f64>,
+
+    /// The rotation of the map in its parent frame, in radians.
+    pub rotation_in_parent_rad: f64,
+
+    /// A summary of each layer, in [`Layer::to_index()`] order.
+    pub layers: Vec<LayerSummary<L>>,
+}
+
+/// A summary of the values in a single layer, see [`MapSummary`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerSummary<L> {
+    /// The layer being summarised.
+    pub layer: L,
+
+    /// The smallest valid value in the layer, or `None` if the layer has no valid values.
+    pub min: Option<f64>,
+
+    /// The largest valid value in the layer, or `None` if the layer has no valid values.
+    pub max: Option<f64>,
+
+    /// The number of valid cells in the layer, as defined by [`CompareValue::as_f64()`].
+    pub num_valid: usize,
+
+    /// The total number of cells in the layer.
+    pub num_cells: usize,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: CompareValue,
+{
+    /// Summarises the map's parameters and the range and number of valid values in each layer.
+    pub fn summary(&self) -> MapSummary<L> {
+        let layers = L::all()
+            .into_iter()
+            .zip(&self.data)
+            .map(|(layer, data)| {
+                let mut min: Option<f64> = None;
+                let mut max: Option<f64> = None;
+                let mut num_valid = 0;
+
+                for value in data.iter().filter_map(CompareValue::as_f64) {
+                    min = Some(min.map_or(value, |m| m.min(value)));
+                    max = Some(max.map_or(value, |m| m.max(value)));
+                    num_valid += 1;
+                }
+
+                LayerSummary {
+                    layer,
+                    min,
+                    max,
+                    num_valid,
+                    num_cells: data.len(),
+                }
+            })
+            .collect();
+
+        MapSummary {
+            cell_bounds: self.cell_bounds(),
+            cell_size: self.cell_size(),
+            position_in_parent: self.params.position_in_parent,
+            rotation_in_parent_rad: self.params.rotation_in_parent_rad,
+            layers,
+        }
+    }
+}
+
+impl<L> Display for MapSummary<L>
+where
+    L: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (rows, cols) = self.cell_bounds.get_shape();
+
+        writeln!(
+            f,
+            "CellMap: {} layers, {}x{} cells",
+            self.layers.len(),
+            cols,
+            rows
+        )?;
+        writeln!(
+            f,
+            "  bounds: x [{}, {}), y [{}, {})",
+            self.cell_bounds.x.0, self.cell_bounds.x.1, self.cell_bounds.y.0, self.cell_bounds.y.1
+        )?;
+        writeln!(
+            f,
+            "  cell size: {} x {}",
+            self.cell_size.x, self.cell_size.y
+        )?;
+        write!(
+            f,
+            "  pose: position ({}, {}), rotation {} rad",
+            self.position_in_parent.x, self.position_in_parent.y, self.rotation_in_parent_rad
+        )?;
+
+        for layer in &self.layers {
+            write!(f, "\n  {:?}: ", layer.layer)?;
+
+            match (layer.min, layer.max) {
+                (Some(min), Some(max)) => write!(f, "min {}, max {}, ", min, max)?,
+                _ => write!(f, "no valid values, ")?,
+            }
+
+            write!(f, "{}/{} valid", layer.num_valid, layer.num_cells)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<L, T> Display for CellMap<L, T>
+where
+    L: Layer + Debug,
+    T: CompareValue,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.summary(), f)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+    use crate::{test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn summary() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-2, 3), (0, 2)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.25),
+                position_in_parent: Vector2::new(1.0, -2.0),
+                rotation_in_parent_rad: 0.5,
+                ..Default::default()
+            },
+            1.0,
+        );
+        map[(TestLayers::Layer0, Point2::new(1, 1))] = -2.5;
+        map[(TestLayers::Layer0, Point2::new(3, 0))] = f64::NAN;
+        map.iter_mut()
+            .layer(TestLayers::Layer2)
+            .for_each(|v| *v = f64::NAN);
+
+        let summary = map.summary();
+        assert_eq!(summary.layers[0].min, Some(-2.5));
+        assert_eq!(summary.layers[0].max, Some(1.0));
+        assert_eq!(summary.layers[0].num_valid, 9);
+        assert_eq!(summary.layers[2].max, None);
+
+        assert_eq!(
+            map.to_string(),
+            "CellMap: 3 layers, 5x2 cells\n\
+             \x20 bounds: x [-2, 3), y [0, 2)\n\
+             \x20 cell size: 0.5 x 0.25\n\
+             \x20 pose: position (1, -2), rotation 0.5 rad\n\
+             \x20 Layer0: min -2.5, max 1, 9/10 valid\n\
+             \x20 Layer1: min 1, max 1, 10/10 valid\n\
+             \x20 Layer2: no valid values, 0/10 valid"
+        );
+
+        // Integer maps can be summarised too
+        let mut map = CellMap::<TestLayers, u8>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 2), (0, 2)).unwrap(),
+                ..Default::default()
+            },
+            3,
+        );
+        map[(TestLayers::Layer1, Point2::new(1, 0))] = 200;
+
+        let summary = map.summary();
+        assert_eq!(summary.layers[0].min, Some(3.0));
+        assert_eq!(summary.layers[1].max, Some(200.0));
+        assert_eq!(summary.layers[1].num_valid, 4);
+        assert!(map
+            .to_string()
+            .contains("Layer1: min 3, max 200, 4/4 valid"));
+    }
+}
@@ -0,0 +1,573 @@
+//! Provides a simple protocol for keeping two copies of a map in sync over a slow link, for
+//! example between a rover and its ground station.
+//!
+//! One side, the source, calls [`serve_sync()`] with its map, while the other side calls
+//! [`request_sync()`] with its own, possibly outdated, copy. The two functions exchange messages
+//! over any [`Read`] + [`Write`] transport, such as a [`TcpStream`](std::net::TcpStream):
+//!
+//! 1. The requester sends the [content hash](crate::hash) of its copy, along with a hash of each
+//!    square tile of cells.
+//! 2. If the hashes match the source replies that the maps are already in sync. Otherwise, if the
+//!    maps have the same parameters, it replies with a [`MapPatch`] holding only the tiles whose
+//!    hashes differ. If the parameters differ the source sends its whole map instead.
+//! 3. The requester applies the reply, and checks that its copy now has the same hash as the
+//!    source.
+//!
+//! Messages are CBOR encoded, so every value, including `NaN`, is sent exactly, and each is
+//! prefixed with its length in bytes as a little-endian `u64`. Messages longer than
+//! [`MAX_MESSAGE_LEN`] are rejected. This module requires the `cbor` feature.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::io::{Read, Write};
+
+use nalgebra::Point2;
+use ndarray::{s, Array2};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    hash::{ContentHash, ContentHasher},
+    CellMap, CellMapParams, Error, Layer,
+};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The default width of tiles compared by [`request_sync()`], in cells.
+pub const DEFAULT_TILE_SIZE: usize = 16;
+
+/// The largest message, in bytes, which will be read from a peer. Larger messages give an
+/// [`Error::SyncMessageTooLarge`].
+pub const MAX_MESSAGE_LEN: u64 = 1 << 30;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A set of tiles which differ between two maps with the same parameters, built by
+/// [`CellMap::diff_tiles()`] and applied with [`CellMap::apply_patch()`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapPatch<T> {
+    /// The tiles to copy into the map.
+    pub tiles: Vec<TilePatch<T>>,
+}
+
+/// The data in every layer of a single rectangular tile of a map.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TilePatch<T> {
+    /// The index of the tile's first cell, the one with the smallest `x` and `y` indices.
+    pub index: Point2<usize>,
+
+    /// The tile's data in each layer, in the same `(y, x)` order as the map's layers.
+    pub data: Vec<Array2<T>>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// The result of a successful sync, as seen by either side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncOutcome {
+    /// The maps were already identical, so no data was sent.
+    InSync,
+
+    /// The requester's map was updated with a patch containing the given number of tiles.
+    Patched(usize),
+
+    /// The maps had different parameters, so the requester's map was replaced entirely.
+    Replaced,
+}
+
+/// Messages exchanged during a sync.
+#[derive(Debug, Serialize, Deserialize)]
+enum Message<T> {
+    Request {
+        hash: u64,
+        params: CellMapParams,
+        tile_size: usize,
+        tile_hashes: Array2<u64>,
+    },
+    InSync,
+    Patch {
+        hash: u64,
+        patch: MapPatch<T>,
+    },
+    Replace {
+        hash: u64,
+        params: CellMapParams,
+        data: Vec<Array2<T>>,
+    },
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: ContentHash,
+{
+    /// Returns the content hash of every square tile of `tile_size` cells in the map, covering
+    /// all layers.
+    ///
+    /// Tiles start at index `(0, 0)`, and tiles on the top and right edges of the map are
+    /// truncated if the map isn't a whole number of tiles wide. The hash of the tile starting at
+    /// index `(x, y) * tile_size` is at `(y, x)` in the returned array.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tile_size` is zero.
+    pub fn tile_hashes(&self, tile_size: usize) -> Array2<u64> {
+        assert!(tile_size > 0, "Tile size must be non-zero");
+
+        let num_cells = self.num_cells();
+        let shape = (
+            num_cells.y.div_ceil(tile_size),
+            num_cells.x.div_ceil(tile_size),
+        );
+
+        Array2::from_shape_fn(shape, |(ty, tx)| {
+            let mut hasher = ContentHasher::new();
+
+            for layer in &self.data {
+                let tile = layer.slice(s![
+                    ty * tile_size..((ty + 1) * tile_size).min(num_cells.y),
+                    tx * tile_size..((tx + 1) * tile_size).min(num_cells.x)
+                ]);
+
+                for value in tile.iter() {
+                    value.content_hash(&mut hasher);
+                }
+            }
+
+            hasher.finish()
+        })
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: ContentHash + Clone,
+{
+    /// Builds a patch containing every tile of this map whose hash differs from `hashes`, which
+    /// are the [tile hashes](CellMap::tile_hashes()) of another map with the same parameters.
+    ///
+    /// Applying the patch to the other map makes its data identical to this map's. Tiles missing
+    /// from `hashes`, for example because it was computed for a different size of map, are always
+    /// included.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tile_size` is zero.
+    pub fn diff_tiles(&self, tile_size: usize, hashes: &Array2<u64>) -> MapPatch<T> {
+        let num_cells = self.num_cells();
+
+        let tiles = self
+            .tile_hashes(tile_size)
+            .indexed_iter()
+            .filter(|&(index, hash)| hashes.get(index) != Some(hash))
+            .map(|((ty, tx), _)| {
+                let index = Point2::new(tx * tile_size, ty * tile_size);
+                let data = self
+                    .data
+                    .iter()
+                    .map(|layer| {
+                        layer
+                            .slice(s![
+                                index.y..(index.y + tile_size).min(num_cells.y),
+                                index.x..(index.x + tile_size).min(num_cells.x)
+                            ])
+                            .to_owned()
+                    })
+                    .collect();
+
+                TilePatch { index, data }
+            })
+            .collect();
+
+        MapPatch { tiles }
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    /// Copies every tile in `patch` into this map.
+    ///
+    /// The patch is checked before any data is copied, so if an error is returned the map is
+    /// unchanged. Each tile must have data for every layer and lie entirely inside the map.
+    pub fn apply_patch(&mut self, patch: &MapPatch<T>) -> Result<(), Error> {
+        let num_cells = self.num_cells();
+
+        for tile in &patch.tiles {
+            if tile.data.len() != L::NUM_LAYERS {
+                return Err(Error::WrongNumberOfLayers(L::NUM_LAYERS, tile.data.len()));
+            }
+
+            let (rows, cols) = tile.data.first().map_or((0, 0), |d| d.dim());
+            if let Some((i, d)) = tile
+                .data
+                .iter()
+                .enumerate()
+                .find(|(_, d)| d.dim() != (rows, cols))
+            {
+                return Err(Error::LayerDataWrongShape(i, d.dim(), (rows, cols)));
+            }
+
+            // The index comes from the peer, so may be anywhere, including near usize::MAX
+            let end = match (
+                tile.index.x.checked_add(cols),
+                tile.index.y.checked_add(rows),
+            ) {
+                (Some(x), Some(y)) => Point2::new(x, y),
+                _ => return Err(Error::IndexOutsideMap(tile.index)),
+            };
+            if end.x > num_cells.x || end.y > num_cells.y {
+                return Err(Error::IndexOutsideMap(end.map(|v| v.saturating_sub(1))));
+            }
+        }
+
+        for tile in &patch.tiles {
+            let (rows, cols) = tile.data[0].dim();
+
+            for (layer, data) in self.data.iter_mut().zip(&tile.data) {
+                layer
+                    .slice_mut(s![
+                        tile.index.y..tile.index.y + rows,
+                        tile.index.x..tile.index.x + cols
+                    ])
+                    .assign(data);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Serves a single sync request from a peer calling [`request_sync()`] over `transport`, sending
+/// it whatever it needs to make its copy of the map identical to `map`.
+///
+/// This blocks until the peer's request has been received and the reply has been sent.
+pub fn serve_sync<L, T, S>(map: &CellMap<L, T>, transport: &mut S) -> Result<SyncOutcome, Error>
+where
+    L: Layer,
+    T: ContentHash + Clone + Serialize + DeserializeOwned,
+    S: Read + Write,
+{
+    let (peer_hash, peer_params, tile_size, tile_hashes) = match read_message::<T, S>(transport)? {
+        Message::Request {
+            hash,
+            params,
+            tile_size,
+            tile_hashes,
+        } => (hash, params, tile_size, tile_hashes),
+        _ => return Err(Error::UnexpectedSyncMessage),
+    };
+
+    trace_span!("serve_sync", tile_size);
+    let hash = map.content_hash();
+
+    let (reply, outcome) = if peer_hash == hash {
+        (Message::InSync, SyncOutcome::InSync)
+    } else if tile_size > 0 && params_hash(&peer_params) == params_hash(&map.params) {
+        let patch = map.diff_tiles(tile_size, &tile_hashes);
+        let num_tiles = patch.tiles.len();
+        (
+            Message::Patch { hash, patch },
+            SyncOutcome::Patched(num_tiles),
+        )
+    } else {
+        (
+            Message::Replace {
+                hash,
+                params: map.params,
+                data: map.data.clone(),
+            },
+            SyncOutcome::Replaced,
+        )
+    };
+
+    write_message(transport, &reply)?;
+    trace_event!(?outcome, "Served sync request");
+
+    Ok(outcome)
+}
+
+/// Requests a sync from a peer calling [`serve_sync()`] over `transport`, updating `map` to be
+/// identical to the peer's map.
+///
+/// Only tiles of `tile_size` cells which differ between the maps are sent, so smaller tiles
+/// reduce the amount of data sent for sparse changes, at the cost of a larger request.
+/// [`DEFAULT_TILE_SIZE`] is a reasonable starting point. If `map` doesn't have the same hash as
+/// the peer's map after the update [`Error::SyncHashMismatch`] is returned.
+///
+/// # Panics
+///
+/// Panics if `tile_size` is zero.
+pub fn request_sync<L, T, S>(
+    map: &mut CellMap<L, T>,
+    transport: &mut S,
+    tile_size: usize,
+) -> Result<SyncOutcome, Error>
+where
+    L: Layer,
+    T: ContentHash + Clone + Serialize + DeserializeOwned,
+    S: Read + Write,
+{
+    trace_span!("request_sync", tile_size);
+    let request: Message<T> = Message::Request {
+        hash: map.content_hash(),
+        params: map.params,
+        tile_size,
+        tile_hashes: map.tile_hashes(tile_size),
+    };
+    write_message(transport, &request)?;
+
+    let (expected_hash, outcome) = match read_message::<T, S>(transport)? {
+        Message::InSync => return Ok(SyncOutcome::InSync),
+        Message::Patch { hash, patch } => {
+            map.apply_patch(&patch)?;
+            (hash, SyncOutcome::Patched(patch.tiles.len()))
+        }
+        Message::Replace { hash, params, data } => {
+            *map = CellMap::new_from_data(params, data)?;
+            (hash, SyncOutcome::Replaced)
+        }
+        Message::Request { .. } => return Err(Error::UnexpectedSyncMessage),
+    };
+
+    let hash = map.content_hash();
+    if hash != expected_hash {
+        return Err(Error::SyncHashMismatch(hash, expected_hash));
+    }
+    trace_event!(?outcome, "Synced map");
+
+    Ok(outcome)
+}
+
+/// Returns the content hash of only the given parameters.
+fn params_hash(params: &CellMapParams) -> u64 {
+    let mut hasher = ContentHasher::new();
+    params.content_hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Writes a length-prefixed message to the transport.
+fn write_message<T, W>(transport: &mut W, message: &Message<T>) -> Result<(), Error>
+where
+    T: Serialize,
+    W: Write,
+{
+    let mut bytes = Vec::new();
+    ciborium::into_writer(message, &mut bytes).map_err(|e| Error::CborError(e.to_string()))?;
+
+    transport
+        .write_all(&(bytes.len() as u64).to_le_bytes())
+        .map_err(Error::IoError)?;
+    transport.write_all(&bytes).map_err(Error::IoError)?;
+    transport.flush().map_err(Error::IoError)
+}
+
+/// Reads a length-prefixed message from the transport.
+fn read_message<T, R>(transport: &mut R) -> Result<Message<T>, Error>
+where
+    T: DeserializeOwned,
+    R: Read,
+{
+    let mut len = [0; 8];
+    transport.read_exact(&mut len).map_err(Error::IoError)?;
+
+    let len = u64::from_le_bytes(len);
+    if len > MAX_MESSAGE_LEN {
+        return Err(Error::SyncMessageTooLarge(len, MAX_MESSAGE_LEN));
+    }
+
+    // Grow the buffer as data arrives rather than trusting the length up front
+    let mut bytes = Vec::new();
+    transport
+        .by_ref()
+        .take(len)
+        .read_to_end(&mut bytes)
+        .map_err(Error::IoError)?;
+    if (bytes.len() as u64) < len {
+        return Err(Error::IoError(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+
+    ciborium::from_reader(bytes.as_slice()).map_err(|e| Error::CborError(e.to_string()))
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::net::{TcpListener, TcpStream};
+
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers};
+
+    fn new_map(bounds: Bounds) -> CellMap<TestLayers, f64> {
+        CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: bounds,
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            0.0,
+        )
+    }
+
+    #[test]
+    fn diff_and_patch() {
+        let bounds = Bounds::new((0, 10), (0, 7)).unwrap();
+        let mut source = new_map(bounds);
+        let mut dest = new_map(bounds);
+
+        source[(TestLayers::Layer0, Point2::new(1, 1))] = 1.0;
+        source[(TestLayers::Layer2, Point2::new(9, 6))] = 2.0;
+        assert_eq!(source.tile_hashes(4).dim(), (2, 3));
+
+        // Only the bottom left and truncated top right tiles differ
+        let patch = source.diff_tiles(4, &dest.tile_hashes(4));
+        assert_eq!(patch.tiles.len(), 2);
+        assert_eq!(patch.tiles[1].index, Point2::new(8, 4));
+        assert_eq!(patch.tiles[1].data[0].dim(), (3, 2));
+
+        dest.apply_patch(&patch).unwrap();
+        assert_eq!(dest.content_hash(), source.content_hash());
+        assert!(source.diff_tiles(4, &dest.tile_hashes(4)).tiles.is_empty());
+
+        // Tiles outside the map are rejected without changing it
+        let mut small = new_map(Bounds::new((0, 9), (0, 7)).unwrap());
+        assert!(matches!(
+            small.apply_patch(&patch),
+            Err(Error::IndexOutsideMap(_))
+        ));
+        assert_eq!(small[(TestLayers::Layer0, Point2::new(1, 1))], 0.0);
+
+        // Indices which would overflow are rejected rather than wrapping or panicking
+        let mut overflowing = patch.clone();
+        overflowing.tiles[0].index = Point2::new(usize::MAX, 0);
+        assert!(matches!(
+            dest.apply_patch(&overflowing),
+            Err(Error::IndexOutsideMap(_))
+        ));
+        overflowing.tiles[0].index = Point2::new(0, usize::MAX - 1);
+        assert!(matches!(
+            dest.apply_patch(&overflowing),
+            Err(Error::IndexOutsideMap(_))
+        ));
+    }
+
+    #[test]
+    fn exact_values() {
+        let bounds = Bounds::new((0, 6), (0, 6)).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Values which don't survive a round trip through JSON
+        let mut source = new_map(bounds);
+        source[(TestLayers::Layer0, Point2::new(1, 1))] = f64::NAN;
+        source[(TestLayers::Layer0, Point2::new(2, 1))] = f64::NEG_INFINITY;
+        source[(TestLayers::Layer1, Point2::new(5, 5))] = 0.1 + 0.2;
+        source[(TestLayers::Layer2, Point2::new(0, 4))] = 1.0 / 3.0;
+        let hash = source.content_hash();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            serve_sync(&source, &mut stream).unwrap()
+        });
+
+        // The sync checks the hash after patching, so would fail if any value changed
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut dest = new_map(bounds);
+        assert_eq!(
+            request_sync(&mut dest, &mut stream, 4).unwrap(),
+            SyncOutcome::Patched(3)
+        );
+        assert!(dest[(TestLayers::Layer0, Point2::new(1, 1))].is_nan());
+        assert_eq!(dest.content_hash(), hash);
+        assert_eq!(server.join().unwrap(), SyncOutcome::Patched(3));
+    }
+
+    #[test]
+    fn invalid_lengths() {
+        // Oversized messages are rejected before reading them
+        let mut message = (MAX_MESSAGE_LEN + 1).to_le_bytes().to_vec();
+        message.extend_from_slice(&[0; 16]);
+        assert!(matches!(
+            read_message::<f64, _>(&mut message.as_slice()),
+            Err(Error::SyncMessageTooLarge(_, MAX_MESSAGE_LEN))
+        ));
+
+        // And messages shorter than their length are an IO error
+        let mut message = 1000u64.to_le_bytes().to_vec();
+        message.extend_from_slice(&[0; 16]);
+        assert!(matches!(
+            read_message::<f64, _>(&mut message.as_slice()),
+            Err(Error::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn sync_over_tcp() {
+        let bounds = Bounds::new((-8, 8), (-8, 8)).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut source = new_map(bounds);
+        source[(TestLayers::Layer1, Point2::new(3, 12))] = 4.0;
+        let mut moved = source.clone();
+        moved.move_map(Vector2::new(1.0, 2.0), 0.5);
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            [&source, &source, &moved]
+                .iter()
+                .map(|map| serve_sync(*map, &mut stream).unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut dest = new_map(bounds);
+
+        assert_eq!(
+            request_sync(&mut dest, &mut stream, 4).unwrap(),
+            SyncOutcome::Patched(1)
+        );
+        assert_eq!(dest[(TestLayers::Layer1, Point2::new(3, 12))], 4.0);
+        assert_eq!(
+            request_sync(&mut dest, &mut stream, 4).unwrap(),
+            SyncOutcome::InSync
+        );
+        assert_eq!(
+            request_sync(&mut dest, &mut stream, 4).unwrap(),
+            SyncOutcome::Replaced
+        );
+        assert_eq!(dest.params().rotation_in_parent_rad, 0.5);
+
+        assert_eq!(
+            server.join().unwrap(),
+            vec![
+                SyncOutcome::Patched(1),
+                SyncOutcome::InSync,
+                SyncOutcome::Replaced
+            ]
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! Provides the [`TemporalLayer`] type, which stores the last few values of every cell in a
+//! layer, for filtering values which change over time.
+//!
+//! A typical use is removing flickering obstacles from an occupancy layer, by pushing the layer
+//! into a [`TemporalLayer`] after each update and using the median of each cell's history instead
+//! of its latest value.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+use ndarray::{s, Array2, Array3, ArrayView2, Axis};
+
+use crate::{
+    cell_map::Bounds, comparison::CompareValue, map_metadata::CellMapMetadata, CellMap,
+    CellMapParams, Error, Layer,
+};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Stores the last `capacity` values of every cell in a layer, as a ring buffer of frames.
+///
+/// Frames are pushed with [`TemporalLayer::push()`] or [`TemporalLayer::push_layer()`], and once
+/// the buffer is full each new frame replaces the oldest one.
+#[derive(Debug, Clone)]
+pub struct TemporalLayer<T> {
+    /// Frames stored with shape `(capacity, y, x)`.
+    data: Array3<T>,
+
+    /// The index in `data` that the next frame will be written to.
+    next: usize,
+
+    /// The number of frames which have been written, up to `capacity`.
+    len: usize,
+
+    metadata: CellMapMetadata,
+    params: CellMapParams,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<T> TemporalLayer<T>
+where
+    T: Clone + Default,
+{
+    /// Creates a new empty layer storing up to `capacity` frames of a map with the given
+    /// parameters.
+    ///
+    /// Returns [`Error::InvalidTemporalCapacity`] if `capacity` is zero.
+    pub fn new(params: CellMapParams, capacity: usize) -> Result<Self, Error> {
+        if capacity == 0 {
+            return Err(Error::InvalidTemporalCapacity);
+        }
+
+        let (rows, cols) = params.cell_bounds.get_shape();
+
+        Ok(Self {
+            data: Array3::default((capacity, rows, cols)),
+            next: 0,
+            len: 0,
+            metadata: params.into(),
+            params,
+        })
+    }
+}
+
+impl<T> TemporalLayer<T> {
+    /// Returns the number of cells in each direction of the layer.
+    pub fn num_cells(&self) -> Vector2<usize> {
+        self.metadata.num_cells
+    }
+
+    /// Returns the bounds of the layer.
+    pub fn cell_bounds(&self) -> Bounds {
+        self.metadata.cell_bounds
+    }
+
+    /// Returns the parameters used to build this layer.
+    pub fn params(&self) -> CellMapParams {
+        self.params
+    }
+
+    /// Returns the maximum number of frames stored.
+    pub fn capacity(&self) -> usize {
+        self.data.len_of(Axis(0))
+    }
+
+    /// Returns the number of frames currently stored.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no frames have been pushed yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all stored frames.
+    pub fn clear(&mut self) {
+        self.next = 0;
+        self.len = 0;
+    }
+
+    /// Returns an iterator over the stored values of the given cell, from oldest to newest, or
+    /// `None` if the index is outside the layer.
+    pub fn history(&self, index: Point2<usize>) -> Option<impl Iterator<Item = &T> + '_> {
+        if !self.metadata.is_in_map(index) {
+            return None;
+        }
+
+        let capacity = self.capacity();
+        let oldest = (self.next + capacity - self.len) % capacity;
+
+        Some((0..self.len).map(move |i| &self.data[[(oldest + i) % capacity, index.y, index.x]]))
+    }
+
+    /// Returns the most recently pushed value of the given cell, or `None` if the index is outside
+    /// the layer or no frames have been pushed.
+    pub fn latest(&self, index: Point2<usize>) -> Option<&T> {
+        self.history(index)?.last()
+    }
+}
+
+impl<T> TemporalLayer<T>
+where
+    T: Clone,
+{
+    /// Pushes a new frame, replacing the oldest frame if the layer is full.
+    ///
+    /// The frame must have the same shape as the layer, otherwise [`Error::LayerWrongShape`] is
+    /// returned.
+    pub fn push(&mut self, frame: ArrayView2<'_, T>) -> Result<(), Error> {
+        let shape = self.metadata.cell_bounds.get_shape();
+        if frame.dim() != shape {
+            return Err(Error::LayerWrongShape(frame.dim(), shape));
+        }
+
+        self.data.slice_mut(s![self.next, .., ..]).assign(&frame);
+        self.next = (self.next + 1) % self.capacity();
+        self.len = (self.len + 1).min(self.capacity());
+
+        Ok(())
+    }
+
+    /// Pushes the current values of `layer` in `map` as a new frame, see
+    /// [`TemporalLayer::push()`].
+    pub fn push_layer<L: Layer>(&mut self, map: &CellMap<L, T>, layer: L) -> Result<(), Error> {
+        self.push(map[layer].view())
+    }
+}
+
+impl<T> TemporalLayer<T>
+where
+    T: CompareValue,
+{
+    /// Returns the median of the valid stored values of the given cell, as defined by
+    /// [`CompareValue::as_f64()`].
+    ///
+    /// Returns `None` if the index is outside the layer or the cell has no valid values. For an
+    /// even number of values the mean of the two middle values is returned.
+    pub fn median_over_time(&self, index: Point2<usize>) -> Option<f64> {
+        let mut values: Vec<f64> = self.history(index)?.filter_map(T::as_f64).collect();
+        median(&mut values)
+    }
+
+    /// Returns the mean of the valid stored values of the given cell, as defined by
+    /// [`CompareValue::as_f64()`].
+    ///
+    /// Returns `None` if the index is outside the layer or the cell has no valid values.
+    pub fn mean_over_time(&self, index: Point2<usize>) -> Option<f64> {
+        let (sum, count) = self
+            .history(index)?
+            .filter_map(T::as_f64)
+            .fold((0.0, 0usize), |(sum, count), v| (sum + v, count + 1));
+
+        if count > 0 {
+            Some(sum / count as f64)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the median over time of every cell, see [`TemporalLayer::median_over_time()`].
+    /// Cells with no valid values are `NaN`.
+    pub fn median_layer(&self) -> Array2<f64> {
+        Array2::from_shape_fn(self.metadata.cell_bounds.get_shape(), |(y, x)| {
+            self.median_over_time(Point2::new(x, y)).unwrap_or(f64::NAN)
+        })
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Returns the median of the given values, reordering them in the process.
+fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+
+    if values.len().is_multiple_of(2) {
+        Some((values[mid - 1] + values[mid]) / 2.0)
+    } else {
+        Some(values[mid])
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestLayers;
+
+    #[test]
+    fn ring_buffer() {
+        let params = CellMapParams {
+            cell_bounds: Bounds::new((0, 3), (0, 2)).unwrap(),
+            ..Default::default()
+        };
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(params, 0.0);
+        let mut temporal = TemporalLayer::<f64>::new(params, 3).unwrap();
+        let cell = Point2::new(2, 1);
+
+        assert!(temporal.is_empty());
+        assert_eq!(temporal.median_over_time(cell), None);
+
+        // An obstacle which flickers in a single frame is removed by the median
+        for value in [1.0, 9.0, 2.0, f64::NAN, 3.0] {
+            map[(TestLayers::Layer1, cell)] = value;
+            temporal.push_layer(&map, TestLayers::Layer1).unwrap();
+        }
+
+        assert_eq!(temporal.len(), 3);
+        let history: Vec<_> = temporal.history(cell).unwrap().cloned().collect();
+        assert_eq!(history[0], 2.0);
+        assert!(history[1].is_nan());
+        assert_eq!(temporal.latest(cell), Some(&3.0));
+        assert_eq!(temporal.median_over_time(cell), Some(2.5));
+        assert_eq!(temporal.mean_over_time(cell), Some(2.5));
+        assert_eq!(temporal.median_over_time(Point2::new(0, 0)), Some(0.0));
+        assert!(temporal.history(Point2::new(3, 0)).is_none());
+
+        let medians = temporal.median_layer();
+        assert_eq!(medians[[1, 2]], 2.5);
+        assert_eq!(medians.sum(), 2.5);
+
+        let wrong = Array2::<f64>::zeros((3, 2));
+        assert!(matches!(
+            temporal.push(wrong.view()),
+            Err(Error::LayerWrongShape(_, _))
+        ));
+        assert!(matches!(
+            TemporalLayer::<f64>::new(params, 0),
+            Err(Error::InvalidTemporalCapacity)
+        ));
+    }
+}
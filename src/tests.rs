@@ -129,6 +129,63 @@ fn get_cell_positions() {
     );
 }
 
+#[test]
+fn test_params_constructors() {
+    // An even number of cells centres the map on the nearest cell boundary
+    let params = CellMapParams::centred(
+        Vector2::new(10, 4),
+        Vector2::new(0.5, 0.5),
+        Point2::new(3.1, -1.9),
+    );
+    assert_eq!(params.cell_bounds, Bounds::new((1, 11), (-6, -2)).unwrap());
+    assert_eq!(params.position_in_parent, Vector2::zeros());
+
+    // An odd number of cells centres it on the nearest cell centre
+    let map = CellMap::<TestLayers, f64>::new(CellMapParams::centred(
+        Vector2::new(5, 3),
+        Vector2::new(0.1, 0.2),
+        Point2::new(0.72, 0.5),
+    ));
+    assert_eq!(map.num_cells(), Vector2::new(5, 3));
+    let centre = map.position(Point2::new(2, 1)).unwrap();
+    assert!((centre - Point2::new(0.75, 0.5)).norm() < 1e-12);
+
+    // Corners on cell boundaries aren't moved down a cell by rounding errors
+    let params = CellMapParams::from_corner(
+        Point2::new(0.7, -0.3),
+        Vector2::new(3, 2),
+        Vector2::new(0.1, 0.1),
+    );
+    assert_eq!(params.cell_bounds, Bounds::new((7, 10), (-3, -1)).unwrap());
+    let params = CellMapParams::from_corner(
+        Point2::new(0.75, -0.25),
+        Vector2::new(3, 2),
+        Vector2::new(0.1, 0.1),
+    );
+    assert_eq!(params.cell_bounds, Bounds::new((7, 10), (-3, -1)).unwrap());
+}
+
+#[test]
+fn test_non_square_cells() {
+    let map = CellMap::<TestLayers, f64>::new(CellMapParams {
+        cell_bounds: Bounds::new((0, 10), (0, 10)).unwrap(),
+        cell_size: Vector2::new(0.1, 0.4),
+        rotation_in_parent_rad: 0.7,
+        ..Default::default()
+    });
+
+    assert_eq!(map.radius_in_cells(0.3), Vector2::new(3, 0));
+    assert_eq!(map.radius_in_cells(0.8), Vector2::new(8, 2));
+    assert_eq!(map.radius_in_cells(-1.0), Vector2::new(0, 0));
+
+    // Distances between cells match the distance between their positions
+    let (a, b) = (Point2::new(1, 7), Point2::new(6, 2));
+    let expected = (map.position(a).unwrap() - map.position(b).unwrap()).norm();
+    assert!((map.index_distance(a, b) - expected).abs() < 1e-12);
+    assert!((map.index_distance(a, b) - 0.5f64.hypot(2.0)).abs() < 1e-12);
+    assert_eq!(map.index_distance(a, a), 0.0);
+}
+
 #[test]
 fn test_resize() {
     let mut map = CellMap::<TestLayers, Option<i32>>::new_from_elem(
@@ -254,3 +311,586 @@ fn test_merge() {
     }
     println!();
 }
+
+#[test]
+fn test_window_map() {
+    let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+        CellMapParams {
+            cell_bounds: Bounds::new((0, 5), (0, 4)).unwrap(),
+            cell_size: Vector2::new(1.0, 1.0),
+            ..Default::default()
+        },
+        1.0,
+    );
+
+    // Fill layer 0 with the x index of each cell
+    map.iter_mut()
+        .layer(TestLayers::Layer0)
+        .indexed()
+        .for_each(|((_, idx), v)| *v = idx.x as f64);
+
+    // Sum each 3x3 window into layer 1
+    map.window_map(
+        TestLayers::Layer0,
+        TestLayers::Layer1,
+        Vector2::new(1, 1),
+        |w| w.sum(),
+    )
+    .unwrap();
+
+    for ((_, idx), &val) in map.iter().layer(TestLayers::Layer1).indexed() {
+        if idx.x == 0 || idx.x == 4 || idx.y == 0 || idx.y == 3 {
+            assert_eq!(val, 1.0);
+        } else {
+            assert_eq!(val, 9.0 * idx.x as f64);
+        }
+    }
+
+    // Mapping a layer into itself must use the original values in each window
+    map.window_map(
+        TestLayers::Layer0,
+        TestLayers::Layer0,
+        Vector2::new(1, 0),
+        |w| w.sum(),
+    )
+    .unwrap();
+    assert_eq!(map[(TestLayers::Layer0, Point2::new(2, 1))], 6.0);
+    assert_eq!(map[(TestLayers::Layer0, Point2::new(4, 1))], 4.0);
+
+    // Parallel mapping should produce the same result as the serial one
+    #[cfg(feature = "parallel")]
+    {
+        let mut par_map = map.clone();
+        map.window_map(
+            TestLayers::Layer0,
+            TestLayers::Layer2,
+            Vector2::new(1, 1),
+            |w| w.sum(),
+        )
+        .unwrap();
+        par_map
+            .par_window_map(
+                TestLayers::Layer0,
+                TestLayers::Layer2,
+                Vector2::new(1, 1),
+                |w| w.sum(),
+            )
+            .unwrap();
+        assert_eq!(map[TestLayers::Layer2], par_map[TestLayers::Layer2]);
+    }
+
+    // Windows larger than the map are an error
+    assert!(map
+        .window_map(
+            TestLayers::Layer0,
+            TestLayers::Layer1,
+            Vector2::new(1, 2),
+            |w| w.sum()
+        )
+        .is_err());
+}
+
+#[test]
+fn test_submap() {
+    let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+        CellMapParams {
+            cell_bounds: Bounds::new((-3, 5), (2, 8)).unwrap(),
+            cell_size: Vector2::new(0.5, 0.25),
+            rotation_in_parent_rad: 0.4,
+            position_in_parent: Vector2::new(1.0, 2.0),
+            ..Default::default()
+        },
+        0.0,
+    );
+
+    for ((_, index), value) in map.iter_mut().layer(TestLayers::Layer1).indexed() {
+        *value = (index.y * 10 + index.x) as f64;
+    }
+
+    let submap = map.submap(Bounds::new((-1, 3), (4, 6)).unwrap()).unwrap();
+    assert_eq!(submap.num_cells(), Vector2::new(4, 2));
+
+    // Cells keep both their value and their position in the parent frame
+    for ((_, index), value) in submap.iter().layer(TestLayers::Layer1).indexed() {
+        let position = submap.position(index).unwrap();
+        let map_index = map.index(position).unwrap();
+        assert_eq!(map_index, Point2::new(index.x + 2, index.y + 2));
+        assert_eq!(*value, map[(TestLayers::Layer1, map_index)]);
+    }
+
+    // Bounds must be inside the map
+    assert!(map.submap(Bounds::new((-4, 3), (4, 6)).unwrap()).is_err());
+}
+
+#[test]
+fn test_decimate() {
+    for &origin in [CellOrigin::Corner, CellOrigin::Centre].iter() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-2, 3), (1, 5)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.25),
+                position_in_parent: Vector2::new(1.0, 2.0),
+                rotation_in_parent_rad: 0.3,
+                convention: GridConvention {
+                    origin,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            0.0,
+        );
+        for ((_, index), v) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            *v = (index.x + 10 * index.y) as f64;
+        }
+
+        let decimated = map.decimate(2);
+        assert_eq!(decimated.num_cells(), Vector2::new(3, 2));
+        assert_eq!(decimated.cell_size(), Vector2::new(1.0, 0.5));
+        assert_eq!(
+            decimated.cell_bounds(),
+            Bounds::new((0, 3), (0, 2)).unwrap()
+        );
+
+        // Values come from the cell nearest each block's centre, or the edge for partial blocks
+        assert_eq!(decimated[(TestLayers::Layer0, Point2::new(0, 0))], 11.0);
+        assert_eq!(decimated[(TestLayers::Layer0, Point2::new(1, 1))], 33.0);
+        assert_eq!(decimated[(TestLayers::Layer0, Point2::new(2, 1))], 34.0);
+
+        // Each block keeps its position in the parent frame
+        let block_centre = (map.position(Point2::new(2, 2)).unwrap().coords
+            + map.position(Point2::new(3, 3)).unwrap().coords)
+            / 2.0;
+        assert_f64_iter_eq!(
+            decimated.position(Point2::new(1, 1)).unwrap().coords,
+            block_centre,
+            1e-12
+        );
+
+        // Decimating by one, or zero, copies the map
+        for &n in [0, 1].iter() {
+            let copy = map.decimate(n);
+            assert_eq!(copy[TestLayers::Layer0], map[TestLayers::Layer0]);
+            assert_f64_iter_eq!(
+                copy.position(Point2::new(4, 3)).unwrap().coords,
+                map.position(Point2::new(4, 3)).unwrap().coords,
+                1e-12
+            );
+        }
+    }
+}
+
+#[test]
+fn test_crop_to_valid() {
+    let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+        CellMapParams {
+            cell_bounds: Bounds::new((-5, 5), (0, 10)).unwrap(),
+            cell_size: Vector2::new(0.5, 0.25),
+            rotation_in_parent_rad: 0.4,
+            position_in_parent: Vector2::new(1.0, 2.0),
+            ..Default::default()
+        },
+        f64::NAN,
+    );
+
+    map[(TestLayers::Layer0, Point2::new(2, 3))] = 1.0;
+    map[(TestLayers::Layer0, Point2::new(6, 8))] = 2.0;
+    map[(TestLayers::Layer1, Point2::new(6, 8))] = 3.0;
+    let position = map.position(Point2::new(6, 8)).unwrap();
+
+    let bounds = map.crop_to_valid(TestLayers::Layer0, |v| !v.is_nan());
+    assert_eq!(bounds, Bounds::new((-3, 2), (3, 9)).unwrap());
+    assert_eq!(map.cell_bounds(), bounds);
+    assert_eq!(map.params().cell_bounds, bounds);
+    assert_eq!(map.num_cells(), Vector2::new(5, 6));
+    assert_eq!(map.layer_view(TestLayers::Layer2).dim(), (6, 5));
+
+    // Cells keep their values and positions
+    let index = map.index(position).unwrap();
+    assert_eq!(index, Point2::new(4, 5));
+    assert_eq!(map[(TestLayers::Layer0, index)], 2.0);
+    assert_eq!(map[(TestLayers::Layer1, index)], 3.0);
+    assert_eq!(map[(TestLayers::Layer0, Point2::new(0, 0))], 1.0);
+
+    // Cropping again changes nothing, and with no valid cells the map is emptied
+    assert_eq!(
+        map.crop_to_valid(TestLayers::Layer0, |v| !v.is_nan()),
+        bounds
+    );
+    let bounds = map.crop_to_valid(TestLayers::Layer2, |v| !v.is_nan());
+    assert_eq!(bounds, Bounds::new((-3, -3), (3, 3)).unwrap());
+    assert_eq!(map.num_cells(), Vector2::new(0, 0));
+}
+
+#[test]
+fn test_layer_views() {
+    let mut map = CellMap::<TestLayers, u32>::new_from_elem(
+        CellMapParams {
+            cell_bounds: Bounds::new((0, 3), (0, 2)).unwrap(),
+            ..Default::default()
+        },
+        0,
+    );
+
+    let index = Point2::new(2, 1);
+    assert_eq!(xy_to_nd(index), (1, 2));
+    assert_eq!(nd_to_xy(xy_to_nd(index)), index);
+
+    map.layer_view_mut(TestLayers::Layer0)[xy_to_nd(index)] = 5;
+    assert_eq!(map[(TestLayers::Layer0, index)], 5);
+    assert_eq!(map.layer_view(TestLayers::Layer0).dim(), (2, 3));
+
+    // Standard layout layers are borrowed, anything else is copied into standard layout
+    assert!(map.as_standard_layout(TestLayers::Layer0).is_view());
+    map[TestLayers::Layer1] =
+        ndarray::Array2::from_shape_fn((3, 2), |(r, c)| (r * 2 + c) as u32).reversed_axes();
+    let standard = map.as_standard_layout(TestLayers::Layer1);
+    assert!(standard.is_owned());
+    assert_eq!(standard.as_slice().unwrap(), &[0, 2, 4, 1, 3, 5]);
+}
+
+#[test]
+fn test_grid_convention() {
+    let params = CellMapParams {
+        cell_bounds: Bounds::new((0, 4), (0, 3)).unwrap(),
+        cell_size: Vector2::new(0.5, 1.0),
+        position_in_parent: Vector2::new(10.0, 20.0),
+        ..Default::default()
+    };
+
+    // Image-style, with the first row at the top and the position at the centre of the first cell
+    let image = CellMap::<TestLayers, f64>::new_from_elem(
+        CellMapParams {
+            convention: GridConvention {
+                y_axis: YAxis::Down,
+                origin: CellOrigin::Centre,
+            },
+            ..params
+        },
+        0.0,
+    );
+    assert_eq!(
+        image.position(Point2::new(0, 0)),
+        Some(Point2::new(10.0, 20.0))
+    );
+    assert_eq!(
+        image.position(Point2::new(3, 2)),
+        Some(Point2::new(11.5, 18.0))
+    );
+    assert_eq!(
+        image.index(Point2::new(11.6, 18.4)),
+        Some(Point2::new(3, 2))
+    );
+    assert_eq!(image.index(Point2::new(10.0, 20.6)), None);
+
+    // Only flipping y keeps the corner origin
+    let flipped = CellMap::<TestLayers, f64>::new_from_elem(
+        CellMapParams {
+            convention: GridConvention {
+                y_axis: YAxis::Down,
+                ..Default::default()
+            },
+            ..params
+        },
+        0.0,
+    );
+    assert_eq!(
+        flipped.position(Point2::new(0, 0)),
+        Some(Point2::new(10.25, 19.5))
+    );
+    assert_eq!(
+        flipped.index(Point2::new(10.1, 19.9)),
+        Some(Point2::new(0, 0))
+    );
+
+    // The convention survives conversion to a file
+    let file = image.to_cell_map_file();
+    assert_eq!(file.convention, image.params().convention);
+    let loaded = file.into_cell_map().unwrap();
+    assert_eq!(
+        loaded.position(Point2::new(3, 2)),
+        Some(Point2::new(11.5, 18.0))
+    );
+}
+
+#[test]
+fn test_boundary_rules() {
+    let new_map = |boundary_rule| {
+        CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 10), (0, 10)).unwrap(),
+                cell_size: Vector2::new(0.1, 0.1),
+                boundary_rule,
+                ..Default::default()
+            },
+            0.0,
+        )
+    };
+    let snap = new_map(BoundaryRule::EpsilonSnap);
+    let half_open = new_map(BoundaryRule::HalfOpen);
+    let nearest = new_map(BoundaryRule::RoundNearest);
+
+    // 0.7 / 0.1 is just below 7 in floating point, so only the half-open rule puts it in cell 6
+    let on_boundary = Point2::new(0.7, 0.05);
+    assert_eq!(snap.index(on_boundary), Some(Point2::new(7, 0)));
+    assert_eq!(half_open.index(on_boundary), Some(Point2::new(6, 0)));
+    assert_eq!(nearest.index(on_boundary), Some(Point2::new(7, 0)));
+
+    // Positions clearly inside a cell are classified the same by every rule
+    for map in [&snap, &half_open, &nearest].iter() {
+        assert_eq!(map.index(Point2::new(0.55, 0.25)), Some(Point2::new(5, 2)));
+    }
+
+    // Rounding to the nearest quantum gives the same cell for values either side of a boundary
+    let below = Point2::new(0.3 - 1e-13, 0.05);
+    let above = Point2::new(0.3 + 1e-13, 0.05);
+    assert_eq!(nearest.index(below), nearest.index(above));
+    assert_eq!(nearest.index(above), Some(Point2::new(3, 0)));
+
+    // A line starting on a boundary starts in the same cell as index() gives
+    for map in [&snap, &half_open, &nearest].iter() {
+        let cells: Vec<_> = map
+            .line_iter(on_boundary, Point2::new(0.95, 0.05))
+            .unwrap()
+            .layer(TestLayers::Layer0)
+            .indexed()
+            .map(|((_, index), _)| index)
+            .collect();
+        let first = map.index(on_boundary).unwrap();
+        assert_eq!(cells.first(), Some(&first));
+        assert_eq!(cells.last(), Some(&Point2::new(9, 0)));
+        assert_eq!(cells.len(), 10 - first.x);
+    }
+
+    // The rule survives conversion to a file
+    let file = nearest.to_cell_map_file();
+    assert_eq!(file.boundary_rule, BoundaryRule::RoundNearest);
+    let loaded = file.into_cell_map().unwrap();
+    assert_eq!(loaded.params().boundary_rule, BoundaryRule::RoundNearest);
+}
+
+#[test]
+fn test_layer_groups() {
+    let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+        CellMapParams {
+            cell_bounds: Bounds::new((0, 3), (0, 2)).unwrap(),
+            cell_size: Vector2::new(1.0, 1.0),
+            ..Default::default()
+        },
+        1.0,
+    );
+    map.iter_mut().group("group2").for_each(|v| *v = 2.0);
+
+    // Iterating over a group only visits its layers
+    assert_eq!(map.iter().group("group01").count(), 12);
+    assert!(map.iter().group("group01").all(|&v| v == 1.0));
+    assert_eq!(map.iter().group("missing").count(), 0);
+
+    // Serialising a group only stores its layers, which can be loaded back into a map
+    let file = map.serialise_group("group2");
+    assert_eq!(file.num_layers, 1);
+    assert_eq!(file.data.len(), 1);
+    assert!(matches!(
+        file.clone().into_cell_map(),
+        Err(Error::WrongNumberOfLayers(3, 1))
+    ));
+
+    // Clearing a group resets only its layers
+    map.clear_group("group2");
+    assert!(map[TestLayers::Layer2].iter().all(|&v| v == 0.0));
+    assert!(map[TestLayers::Layer0].iter().all(|&v| v == 1.0));
+
+    map.load_layers(file).unwrap();
+    assert!(map[TestLayers::Layer2].iter().all(|&v| v == 2.0));
+    assert!(map[TestLayers::Layer1].iter().all(|&v| v == 1.0));
+
+    // Files with different bounds can't be loaded
+    let mut other = map.clone();
+    other.resize(Bounds::new((0, 4), (0, 2)).unwrap());
+    assert!(matches!(
+        other.load_layers(map.serialise_group("group01")),
+        Err(Error::BoundsOutsideMap(..))
+    ));
+}
+
+#[test]
+fn test_layer_defaults() {
+    let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+        CellMapParams {
+            cell_bounds: Bounds::new((0, 3), (0, 2)).unwrap(),
+            cell_size: Vector2::new(1.0, 1.0),
+            ..Default::default()
+        },
+        5.0,
+    );
+    assert_eq!(map.layer_default(&TestLayers::Layer0), 0.0);
+    assert_eq!(map.layer_default(&TestLayers::Layer2), -1.0);
+
+    // Resetting a layer only changes that layer
+    map.reset_layer(TestLayers::Layer2);
+    assert!(map[TestLayers::Layer2].iter().all(|&v| v == -1.0));
+    assert!(map[TestLayers::Layer1].iter().all(|&v| v == 5.0));
+
+    // Scrolled-in cells get each layer's default, while existing cells are kept
+    map.resize_with_defaults(Bounds::new((1, 4), (0, 2)).unwrap());
+    assert_eq!(map[(TestLayers::Layer1, Point2::new(1, 1))], 5.0);
+    assert_eq!(map[(TestLayers::Layer1, Point2::new(2, 1))], 0.0);
+    assert_eq!(map[(TestLayers::Layer2, Point2::new(2, 1))], -1.0);
+    map[(TestLayers::Layer2, Point2::new(0, 0))] = 3.0;
+    map.resize_with_defaults(Bounds::new((1, 5), (0, 2)).unwrap());
+    assert_eq!(map[(TestLayers::Layer2, Point2::new(0, 0))], 3.0);
+    assert_eq!(map[(TestLayers::Layer2, Point2::new(3, 0))], -1.0);
+
+    map.reset_all();
+    assert!(map[TestLayers::Layer0].iter().all(|&v| v == 0.0));
+    assert!(map[TestLayers::Layer2].iter().all(|&v| v == -1.0));
+
+    // Defaults are converted to other cell types
+    let mut flags = CellMap::<TestLayers, bool>::new(map.params());
+    flags.reset_all();
+    assert!(flags[TestLayers::Layer2].iter().all(|&v| v));
+    assert!(flags[TestLayers::Layer0].iter().all(|&v| !v));
+    assert_eq!(u8::from_layer_default(-1.0), 0);
+    assert_eq!(i32::from_layer_default(2.6), 3);
+}
+
+#[test]
+fn test_cell_corners() {
+    let map = CellMap::<TestLayers, f64>::new_from_elem(
+        CellMapParams {
+            cell_bounds: Bounds::new((-1, 2), (0, 2)).unwrap(),
+            cell_size: Vector2::new(0.5, 1.0),
+            rotation_in_parent_rad: std::f64::consts::FRAC_PI_2,
+            position_in_parent: Vector2::new(1.0, 2.0),
+            ..Default::default()
+        },
+        0.0,
+    );
+    let close = |a: Point2<f64>, b: Point2<f64>| (a - b).norm() < 1e-9;
+
+    // Index (1, 0) is the cell at the map origin, rotated a quarter turn
+    let corners = map.cell_corners(Point2::new(1, 0)).unwrap();
+    let expected = [(1.0, 2.0), (1.0, 2.5), (0.0, 2.5), (0.0, 2.0)];
+    for (corner, &(x, y)) in corners.iter().zip(expected.iter()) {
+        assert!(close(*corner, Point2::new(x, y)), "{}", corner);
+    }
+
+    let (min, max) = map.cell_bounds_in_parent(Point2::new(1, 0)).unwrap();
+    assert!(close(min, Point2::new(0.0, 2.0)));
+    assert!(close(max, Point2::new(1.0, 2.5)));
+
+    // The centre of the cell is the centre of its corners
+    let centre = corners.iter().map(|c| c.coords).sum::<Vector2<f64>>() / 4.0;
+    assert!(close(
+        centre.into(),
+        map.position(Point2::new(1, 0)).unwrap()
+    ));
+
+    assert!(map.cell_corners(Point2::new(3, 0)).is_none());
+    assert!(map.cell_bounds_in_parent(Point2::new(0, 2)).is_none());
+}
+
+#[test]
+fn test_errors() {
+    let params = CellMapParams {
+        cell_bounds: Bounds::new((0, 3), (0, 2)).unwrap(),
+        ..Default::default()
+    };
+    let mut map = CellMap::<TestLayers, f64>::new_from_elem(params, 0.0);
+
+    let err = map
+        .set(TestLayers::Layer2, Point2::new(3, 0), 1.0)
+        .unwrap_err();
+    assert!(matches!(err, Error::IndexOutsideLayer(2, _, _)));
+    assert!(err.is_out_of_bounds());
+    assert!(map
+        .submap(Bounds::new((1, 4), (0, 1)).unwrap())
+        .unwrap_err()
+        .is_out_of_bounds());
+
+    // The layer with the wrong shape is reported, not just the first
+    let mut data = vec![ndarray::Array2::zeros((2, 3)); 3];
+    data[1] = ndarray::Array2::zeros((3, 2));
+    let err = CellMap::<TestLayers, f64>::new_from_data(params, data).unwrap_err();
+    assert!(matches!(err, Error::LayerDataWrongShape(1, (3, 2), (2, 3))));
+    assert!(!err.is_out_of_bounds());
+
+    let err: Error = std::io::Error::from(std::io::ErrorKind::NotFound).into();
+    assert!(matches!(err, Error::IoError(_)));
+}
+
+#[test]
+fn test_panic_free() {
+    let params = CellMapParams {
+        cell_bounds: Bounds::new((0, 3), (0, 3)).unwrap(),
+        ..Default::default()
+    };
+    let mut map = CellMap::<TestLayers, f64>::new_from_elem(params, 1.0);
+
+    assert!(TestLayers::try_from_index(2).is_some());
+    assert!(TestLayers::try_from_index(3).is_none());
+
+    // Iterators over no layers are empty rather than panicking
+    assert_eq!(map.iter().layers(&[]).indexed().count(), 0);
+    assert_eq!(map.iter_mut().layers(&[]).positioned().count(), 0);
+
+    let other = CellMap::<TestLayers, f64>::new_from_elem(
+        CellMapParams {
+            cell_bounds: Bounds::new((2, 5), (1, 3)).unwrap(),
+            ..params
+        },
+        3.0,
+    );
+    map.try_merge(&other, |&a, bs| bs.iter().fold(a, |acc, &b| acc.max(b)))
+        .unwrap();
+    assert_eq!(map.cell_bounds(), Bounds::new((0, 6), (0, 4)).unwrap());
+    assert_eq!(map[(TestLayers::Layer0, Point2::new(4, 2))], 3.0);
+}
+
+#[test]
+fn test_checked_layers() {
+    // A layer which doesn't uphold the trait's contract, so has no data in the map
+    #[derive(Clone, Debug)]
+    struct BadLayer;
+
+    impl Layer for BadLayer {
+        const NUM_LAYERS: usize = 1;
+        const FIRST: Self = BadLayer;
+
+        fn to_index(&self) -> usize {
+            1
+        }
+
+        fn from_index(_index: usize) -> Self {
+            BadLayer
+        }
+
+        fn all() -> Vec<Self> {
+            vec![BadLayer]
+        }
+    }
+
+    let params = CellMapParams {
+        cell_bounds: Bounds::new((0, 3), (0, 2)).unwrap(),
+        ..Default::default()
+    };
+    let mut map = CellMap::<BadLayer, f64>::new_from_elem(params, 0.0);
+    let index = Point2::new(1, 1);
+
+    assert!(matches!(
+        map.try_layer(BadLayer),
+        Err(Error::InvalidLayerIndex(1, 1))
+    ));
+    assert!(matches!(
+        map.try_layer_mut(BadLayer),
+        Err(Error::InvalidLayerIndex(1, 1))
+    ));
+    assert!(map.get(BadLayer, index).is_none());
+    assert!(map.get_mut(BadLayer, index).is_none());
+    assert!(matches!(
+        map.set(BadLayer, index, 1.0),
+        Err(Error::IndexOutsideLayer(1, _, _))
+    ));
+
+    let map = CellMap::<TestLayers, f64>::new_from_elem(params, 2.0);
+    assert_eq!(map.try_layer(TestLayers::Layer1).unwrap()[(1, 1)], 2.0);
+}
@@ -0,0 +1,521 @@
+//! Provides the [`TileStore`] type, which persists an unbounded map to disk as a grid of tiles and
+//! keeps the most recently used tiles in memory.
+//!
+//! This is intended for long duration exploration, where the explored area eventually becomes too
+//! large to hold in a single [`CellMap`]. The world is split into square tiles of
+//! [`TileStoreParams::tile_size`] cells, each of which is stored as a separate [`CellMap`] file in
+//! the store's directory using the format written by [`CellMap::write_mmap_file()`]. Tiles which
+//! have never been written are filled with a default value when first accessed.
+//!
+//! Cells in the store are addressed by their map-frame cell location, which may be negative, as
+//! given by [`TileStore::cell()`].
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::{Path, PathBuf},
+};
+
+use bytemuck::Pod;
+use nalgebra::{Point2, Vector2};
+
+use crate::{
+    cell_map::{Bounds, GridConvention},
+    map_metadata::CellMapMetadata,
+    CellMap, CellMapParams, Error, Layer, MmapCellMap,
+};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Parameters describing the layout of a [`TileStore`].
+///
+/// The same parameters must be used each time a store's directory is opened.
+#[derive(Debug, Clone, Copy)]
+pub struct TileStoreParams {
+    /// The number of cells along each side of a tile.
+    ///
+    /// # Default
+    ///
+    /// The default value is `256`.
+    pub tile_size: usize,
+
+    /// The size (resolution) of each cell in the map, in parent frame coordinates.
+    ///
+    /// # Default
+    ///
+    /// The default value is `[1.0, 1.0]`.
+    pub cell_size: Vector2<f64>,
+
+    /// The rotation of the map's Z axis about the parent Z axis in radians.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.0`.
+    pub rotation_in_parent_rad: f64,
+
+    /// The position of the origin of the map in the parent frame, in parent frame units.
+    ///
+    /// # Default
+    ///
+    /// The default value is `[0.0, 0.0]`.
+    pub position_in_parent: Vector2<f64>,
+
+    /// The direction of the map's `y` axis and the anchor of `position_in_parent` within a cell.
+    ///
+    /// # Default
+    ///
+    /// The default value is [`GridConvention::default()`].
+    pub convention: GridConvention,
+
+    /// The maximum number of tiles to keep in memory at once.
+    ///
+    /// # Default
+    ///
+    /// The default value is `16`.
+    pub cache_capacity: usize,
+}
+
+/// A map of unbounded size which is persisted to disk in tiles, keeping a least recently used
+/// cache of tiles in memory.
+///
+/// Modified tiles are written back to disk when they are evicted from the cache, when
+/// [`TileStore::flush()`] is called, or when the store is dropped. Since errors can't be reported
+/// on drop, [`TileStore::flush()`] should be called explicitly when the caller needs to know that
+/// the data has been saved.
+#[derive(Debug)]
+pub struct TileStore<L, T>
+where
+    L: Layer,
+    T: Pod,
+{
+    dir: PathBuf,
+    params: TileStoreParams,
+    metadata: CellMapMetadata,
+    default: T,
+    cache: HashMap<Point2<isize>, CachedTile<L, T>>,
+    tick: u64,
+}
+
+/// A tile held in the [`TileStore`] cache.
+#[derive(Debug)]
+struct CachedTile<L, T>
+where
+    L: Layer,
+{
+    map: CellMap<L, T>,
+    dirty: bool,
+    last_used: u64,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> TileStore<L, T>
+where
+    L: Layer,
+    T: Pod,
+{
+    /// Opens the store in the given directory, creating the directory if it doesn't exist.
+    ///
+    /// Cells in tiles which haven't been stored yet will be initialised to `default`.
+    pub fn open<P: AsRef<Path>>(
+        dir: P,
+        params: TileStoreParams,
+        default: T,
+    ) -> Result<Self, Error> {
+        if params.tile_size == 0 || params.cache_capacity == 0 {
+            return Err(Error::InvalidTileStoreParams);
+        }
+
+        std::fs::create_dir_all(dir.as_ref()).map_err(Error::IoError)?;
+
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            params,
+            metadata: params.tile_params(Point2::origin()).into(),
+            default,
+            cache: HashMap::new(),
+            tick: 0,
+        })
+    }
+
+    /// Returns the parameters of the store.
+    pub fn params(&self) -> TileStoreParams {
+        self.params
+    }
+
+    /// Returns the number of tiles currently held in memory.
+    pub fn num_cached(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Returns the map-frame cell location containing the given parent-frame position.
+    pub fn cell(&self, position: Point2<f64>) -> Point2<isize> {
+        self.metadata.get_cell(position)
+    }
+
+    /// Returns the position in the parent frame of the centre of the given cell.
+    pub fn position(&self, cell: Point2<isize>) -> Point2<f64> {
+        self.metadata
+            .to_parent
+            .transform_point(&(cell.cast() + Vector2::new(0.5, 0.5)))
+    }
+
+    /// Returns the index of the tile containing the given cell.
+    pub fn tile_index(&self, cell: Point2<isize>) -> Point2<isize> {
+        let size = self.params.tile_size as isize;
+        Point2::new(cell.x.div_euclid(size), cell.y.div_euclid(size))
+    }
+
+    /// Gets the value of the given layer and cell, loading its tile if necessary.
+    pub fn get(&mut self, layer: L, cell: Point2<isize>) -> Result<T, Error> {
+        let (tile, index) = self.locate(cell);
+        Ok(self.tile(tile)?[(layer, index)])
+    }
+
+    /// Gets the value of the given layer at the given parent-frame position, loading its tile if
+    /// necessary.
+    pub fn get_position(&mut self, layer: L, position: Point2<f64>) -> Result<T, Error> {
+        self.get(layer, self.cell(position))
+    }
+
+    /// Sets the value of the given layer and cell, loading its tile if necessary.
+    pub fn set(&mut self, layer: L, cell: Point2<isize>, value: T) -> Result<(), Error> {
+        let (tile, index) = self.locate(cell);
+        self.tile_mut(tile)?[(layer, index)] = value;
+        Ok(())
+    }
+
+    /// Returns a reference to the given tile, loading it if necessary.
+    pub fn tile(&mut self, tile: Point2<isize>) -> Result<&CellMap<L, T>, Error> {
+        Ok(&self.load(tile)?.map)
+    }
+
+    /// Returns a mutable reference to the given tile, loading it if necessary. The tile will be
+    /// written back to disk when it's evicted or the store is flushed.
+    pub fn tile_mut(&mut self, tile: Point2<isize>) -> Result<&mut CellMap<L, T>, Error> {
+        let cached = self.load(tile)?;
+        cached.dirty = true;
+        Ok(&mut cached.map)
+    }
+
+    /// Copies the cells within the given map-frame `bounds` into a new [`CellMap`], loading tiles
+    /// as required.
+    pub fn submap(&mut self, bounds: Bounds) -> Result<CellMap<L, T>, Error> {
+        if !bounds.is_valid() {
+            return Err(Error::InvalidBounds(bounds));
+        }
+
+        let mut params = self.params.tile_params(Point2::origin());
+        params.cell_bounds = bounds;
+        let mut map = CellMap::new_from_elem(params, self.default);
+
+        let (min_cell, max_cell) = bounds.as_corners();
+        let min_tile = self.tile_index(min_cell);
+        let max_tile = self.tile_index(max_cell - Vector2::new(1, 1));
+
+        for ty in min_tile.y..=max_tile.y {
+            for tx in min_tile.x..=max_tile.x {
+                let tile = self.tile(Point2::new(tx, ty))?;
+
+                // Copy the overlapping region of each layer from the tile to the submap
+                let tile_bounds = tile.cell_bounds();
+                let outside = || Error::BoundsOutsideMap(bounds, tile_bounds);
+                let overlap = tile_bounds.intersect(&bounds).ok_or_else(outside)?;
+                let src = tile_bounds
+                    .get_slice_of_other(&overlap)
+                    .ok_or_else(outside)?;
+                let dst = bounds.get_slice_of_other(&overlap).ok_or_else(outside)?;
+
+                for layer in L::all() {
+                    map[layer.clone()]
+                        .slice_mut(ndarray::s![dst.y.0..dst.y.1, dst.x.0..dst.x.1])
+                        .assign(
+                            &tile[layer].slice(ndarray::s![src.y.0..src.y.1, src.x.0..src.x.1]),
+                        );
+                }
+            }
+        }
+
+        Ok(map)
+    }
+
+    /// Writes all modified tiles in the cache to disk.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        for (tile, cached) in self.cache.iter_mut() {
+            if cached.dirty {
+                cached
+                    .map
+                    .write_mmap_file(self.dir.join(tile_file_name(*tile)))?;
+                cached.dirty = false;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the tile containing the given cell and the cell's index within that tile.
+    fn locate(&self, cell: Point2<isize>) -> (Point2<isize>, Point2<usize>) {
+        let tile = self.tile_index(cell);
+        let size = self.params.tile_size as isize;
+        let index = Point2::new(cell.x - tile.x * size, cell.y - tile.y * size).map(|v| v as usize);
+
+        (tile, index)
+    }
+
+    /// Returns the given tile from the cache, loading it from disk or creating it if it isn't
+    /// cached, and evicting the least recently used tile if the cache is full.
+    fn load(&mut self, tile: Point2<isize>) -> Result<&mut CachedTile<L, T>, Error> {
+        self.tick += 1;
+
+        if !self.cache.contains_key(&tile) && self.cache.len() >= self.params.cache_capacity {
+            self.evict()?;
+        }
+
+        let cached = match self.cache.entry(tile) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let path = self.dir.join(tile_file_name(tile));
+                let params = self.params.tile_params(tile);
+
+                trace_event!(tile = %tile, from_disk = path.exists(), "Loading tile");
+                let map = if path.exists() {
+                    let map = MmapCellMap::<L, T>::open(&path)?.to_cell_map();
+
+                    if map.cell_bounds() != params.cell_bounds
+                        || map.cell_size() != params.cell_size
+                    {
+                        return Err(Error::TileMismatch(tile));
+                    }
+
+                    map
+                } else {
+                    CellMap::new_from_elem(params, self.default)
+                };
+
+                entry.insert(CachedTile {
+                    map,
+                    dirty: false,
+                    last_used: 0,
+                })
+            }
+        };
+        cached.last_used = self.tick;
+
+        Ok(cached)
+    }
+
+    /// Removes the least recently used tile from the cache, writing it to disk if it was modified.
+    fn evict(&mut self) -> Result<(), Error> {
+        let lru = match self.cache.iter().min_by_key(|(_, cached)| cached.last_used) {
+            Some((&tile, _)) => tile,
+            None => return Ok(()),
+        };
+
+        if let Some(cached) = self.cache.get(&lru) {
+            trace_event!(tile = %lru, dirty = cached.dirty, "Evicting tile");
+
+            // Write the tile before removing it from the cache, so its changes aren't lost if the
+            // write fails
+            if cached.dirty {
+                cached
+                    .map
+                    .write_mmap_file(self.dir.join(tile_file_name(lru)))?;
+            }
+        }
+
+        self.cache.remove(&lru);
+        Ok(())
+    }
+}
+
+impl<L, T> Drop for TileStore<L, T>
+where
+    L: Layer,
+    T: Pod,
+{
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl TileStoreParams {
+    /// Returns the [`CellMapParams`] of the given tile.
+    fn tile_params(&self, tile: Point2<isize>) -> CellMapParams {
+        let size = self.tile_size as isize;
+
+        CellMapParams {
+            cell_size: self.cell_size,
+            cell_bounds: Bounds {
+                x: (tile.x * size, (tile.x + 1) * size),
+                y: (tile.y * size, (tile.y + 1) * size),
+            },
+            rotation_in_parent_rad: self.rotation_in_parent_rad,
+            position_in_parent: self.position_in_parent,
+            convention: self.convention,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for TileStoreParams {
+    fn default() -> Self {
+        Self {
+            tile_size: 256,
+            cell_size: Vector2::new(1.0, 1.0),
+            rotation_in_parent_rad: 0.0,
+            position_in_parent: Vector2::zeros(),
+            convention: GridConvention::default(),
+            cache_capacity: 16,
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Returns the name of the file the given tile is stored in.
+fn tile_file_name(tile: Point2<isize>) -> String {
+    format!("tile_{}_{}.cellmap", tile.x, tile.y)
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestLayers;
+
+    fn params() -> TileStoreParams {
+        TileStoreParams {
+            tile_size: 4,
+            cell_size: Vector2::new(0.5, 0.5),
+            cache_capacity: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tile_indices() {
+        let dir = std::env::temp_dir().join("cell_map_tile_indices");
+        let store = TileStore::<TestLayers, f64>::open(&dir, params(), 0.0).unwrap();
+
+        assert_eq!(store.tile_index(Point2::new(0, 3)), Point2::new(0, 0));
+        assert_eq!(store.tile_index(Point2::new(4, -1)), Point2::new(1, -1));
+        assert_eq!(store.tile_index(Point2::new(-4, -5)), Point2::new(-1, -2));
+
+        assert_eq!(store.cell(Point2::new(-0.25, 0.75)), Point2::new(-1, 1));
+        assert_eq!(
+            store.cell(store.position(Point2::new(-7, 3))),
+            Point2::new(-7, 3)
+        );
+
+        drop(store);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn persistence() {
+        let dir = std::env::temp_dir().join("cell_map_tile_persistence");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let cells = [
+            Point2::new(0, 0),
+            Point2::new(-1, -1),
+            Point2::new(9, -6),
+            Point2::new(-13, 2),
+        ];
+
+        {
+            let mut store = TileStore::<TestLayers, f64>::open(&dir, params(), -1.0).unwrap();
+
+            for (i, &cell) in cells.iter().enumerate() {
+                store.set(TestLayers::Layer1, cell, i as f64).unwrap();
+            }
+
+            // Four tiles have been touched but only two may be cached at once
+            assert_eq!(store.num_cached(), 2);
+            assert_eq!(
+                store.get(TestLayers::Layer0, Point2::new(0, 0)).unwrap(),
+                -1.0
+            );
+            assert_eq!(
+                store.get(TestLayers::Layer1, Point2::new(-1, -1)).unwrap(),
+                1.0
+            );
+        }
+
+        // Reopening the store should load the values written by the evictions and drop
+        let mut store = TileStore::<TestLayers, f64>::open(&dir, params(), -1.0).unwrap();
+        for (i, &cell) in cells.iter().enumerate() {
+            assert_eq!(store.get(TestLayers::Layer1, cell).unwrap(), i as f64);
+        }
+
+        // Submaps cross tile boundaries
+        let submap = store
+            .submap(Bounds::new((-2, 10), (-6, 1)).unwrap())
+            .unwrap();
+        assert_eq!(submap.num_cells(), Vector2::new(12, 7));
+        assert_eq!(submap[(TestLayers::Layer1, Point2::new(1, 5))], 1.0);
+        assert_eq!(submap[(TestLayers::Layer1, Point2::new(11, 0))], 2.0);
+        assert_eq!(submap[(TestLayers::Layer1, Point2::new(2, 6))], 0.0);
+        assert_eq!(submap[(TestLayers::Layer1, Point2::new(3, 3))], -1.0);
+        assert_eq!(
+            submap.position(Point2::new(1, 5)).unwrap(),
+            store.position(Point2::new(-1, -1))
+        );
+
+        drop(store);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_eviction() {
+        let dir = std::env::temp_dir().join("cell_map_tile_failed_eviction");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut store = TileStore::<TestLayers, f64>::open(&dir, params(), -1.0).unwrap();
+        store
+            .set(TestLayers::Layer0, Point2::new(0, 0), 1.0)
+            .unwrap();
+        store
+            .set(TestLayers::Layer0, Point2::new(4, 0), 2.0)
+            .unwrap();
+
+        // With the directory gone the least recently used tile can't be written, so loading a
+        // third tile fails but the modified tiles are kept
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(store.get(TestLayers::Layer0, Point2::new(8, 0)).is_err());
+        assert_eq!(store.num_cached(), 2);
+        assert_eq!(
+            store.get(TestLayers::Layer0, Point2::new(0, 0)).unwrap(),
+            1.0
+        );
+        assert_eq!(
+            store.get(TestLayers::Layer0, Point2::new(4, 0)).unwrap(),
+            2.0
+        );
+
+        // Once the directory is back the tiles are written as normal
+        std::fs::create_dir_all(&dir).unwrap();
+        store.flush().unwrap();
+        drop(store);
+        let mut store = TileStore::<TestLayers, f64>::open(&dir, params(), -1.0).unwrap();
+        assert_eq!(
+            store.get(TestLayers::Layer0, Point2::new(0, 0)).unwrap(),
+            1.0
+        );
+
+        drop(store);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
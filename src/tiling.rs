@@ -0,0 +1,393 @@
+//! Provides [`CellMap::split()`] and [`CellMap::assemble()`], which split a map into a grid of
+//! smaller tiles and put them back together, and [`CellMap::concat_x()`] and
+//! [`CellMap::concat_y()`], which join two adjacent maps.
+//!
+//! This is intended for distributing the processing of a large map across several threads or
+//! compute nodes. Each tile is an ordinary [`CellMap`] covering part of the original map's bounds,
+//! with the same position in the parent frame, so cells keep both their parent-frame positions and
+//! their map-frame cell locations.
+//!
+//! Concatenation is intended for stitching together strips of a survey, which are usually built
+//! as separate maps. The maps only need to share a grid, as described by
+//! [`CellMap::grid_offset()`], rather than the same parameters.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+use ndarray::{concatenate, s, Axis};
+
+use crate::{cell_map::Bounds, CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The largest difference, in cells, between two maps' grids for them to be considered aligned.
+const ALIGNMENT_TOLERANCE: f64 = 1e-6;
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    /// Splits the map into a grid of `n_x` by `n_y` tiles, returned in row-major order, i.e.
+    /// the tile at column `i` and row `j` of the grid is at index `j * n_x + i`.
+    ///
+    /// The tiles differ in size by at most one cell along each axis. The number of tiles along an
+    /// axis is clamped between `1` and the number of cells along that axis, so that no tile is
+    /// empty unless the map is.
+    ///
+    /// Each tile has the same parameters as this map except for its `cell_bounds`, which is the
+    /// part of this map's bounds it covers. [`CellMap::assemble()`] puts the tiles back together.
+    pub fn split(&self, n_x: usize, n_y: usize) -> Vec<CellMap<L, T>> {
+        let bounds = self.cell_bounds();
+        let num_cells = self.num_cells();
+        let n_x = n_x.min(num_cells.x).max(1);
+        let n_y = n_y.min(num_cells.y).max(1);
+
+        // The edges of the tiles along an axis, spreading any remainder across the tiles
+        let edges = |min: isize, num: usize, n: usize| -> Vec<isize> {
+            (0..=n).map(|i| min + (i * num / n) as isize).collect()
+        };
+        let xs = edges(bounds.x.0, num_cells.x, n_x);
+        let ys = edges(bounds.y.0, num_cells.y, n_y);
+
+        let mut tiles = Vec::with_capacity(n_x * n_y);
+        for y in ys.windows(2) {
+            for x in xs.windows(2) {
+                let tile = Bounds {
+                    x: (x[0], x[1]),
+                    y: (y[0], y[1]),
+                };
+
+                // Tiles always lie inside the map's bounds, so don't need checking
+                tiles.push(self.submap_unchecked(tile));
+            }
+        }
+
+        tiles
+    }
+
+    /// Returns the offset to add to a map-frame cell location in `other` to get the location of
+    /// the same cell in this map's frame, or `None` if the two maps' grids aren't aligned.
+    ///
+    /// Grids are aligned if they have the same cell size, rotation and grid convention, and their
+    /// cells' edges coincide in the parent frame.
+    pub fn grid_offset(&self, other: &CellMap<L, T>) -> Option<Vector2<isize>> {
+        let relative = self.to_parent().inverse() * other.to_parent();
+        let matrix = relative.matrix();
+
+        // The linear part must be the identity, since the grids must have the same scale and
+        // orientation, and the translation must be a whole number of cells
+        let linear_ok = (matrix[(0, 0)] - 1.0).abs() < ALIGNMENT_TOLERANCE
+            && matrix[(0, 1)].abs() < ALIGNMENT_TOLERANCE
+            && matrix[(1, 0)].abs() < ALIGNMENT_TOLERANCE
+            && (matrix[(1, 1)] - 1.0).abs() < ALIGNMENT_TOLERANCE;
+        let translation = Vector2::new(matrix[(0, 2)], matrix[(1, 2)]);
+        let offset = translation.map(f64::round);
+
+        if linear_ok && (translation - offset).abs().max() < ALIGNMENT_TOLERANCE {
+            Some(offset.map(|v| v as isize))
+        } else {
+            None
+        }
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Clone + Default,
+{
+    /// Assembles a set of tiles, such as those returned by [`CellMap::split()`], into a single
+    /// map. The tiles may be given in any order.
+    ///
+    /// The tiles' grids must all be aligned with the first tile's, as described by
+    /// [`CellMap::grid_offset()`], and the assembled map has the same parameters as the first
+    /// tile except for its `cell_bounds`, which covers every tile.
+    ///
+    /// Returns [`Error::OverlappingRegions`] if any two tiles overlap, and
+    /// [`Error::CannotCombineMaps`] if there are no tiles, the tiles aren't aligned, or they leave
+    /// gaps in the assembled map.
+    pub fn assemble(tiles: &[CellMap<L, T>]) -> Result<CellMap<L, T>, Error> {
+        let first = tiles
+            .first()
+            .ok_or_else(|| Error::CannotCombineMaps("no tiles were given".into()))?;
+
+        // Find the bounds of each tile in the first tile's map frame
+        let bounds = tiles
+            .iter()
+            .map(|tile| {
+                let offset = first.grid_offset(tile).ok_or_else(|| {
+                    Error::CannotCombineMaps(format!(
+                        "the tile with bounds {:?} isn't aligned with the first tile",
+                        tile.cell_bounds()
+                    ))
+                })?;
+                let b = tile.cell_bounds();
+                Ok(Bounds {
+                    x: (b.x.0 + offset.x, b.x.1 + offset.x),
+                    y: (b.y.0 + offset.y, b.y.1 + offset.y),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        for (i, a) in bounds.iter().enumerate() {
+            for b in &bounds[i + 1..] {
+                if let Some(overlap) = a.intersect(b) {
+                    if overlap.get_num_cells().iter().product::<usize>() > 0 {
+                        return Err(Error::OverlappingRegions(*a, *b));
+                    }
+                }
+            }
+        }
+
+        // Since the tiles don't overlap they cover the union exactly if they contain as many
+        // cells as it does
+        let union = bounds
+            .iter()
+            .skip(1)
+            .fold(bounds[0], |union, b| union.union(b));
+        let num_tile_cells: usize = bounds
+            .iter()
+            .map(|b| b.get_num_cells().iter().product::<usize>())
+            .sum();
+        if num_tile_cells != union.get_num_cells().iter().product::<usize>() {
+            return Err(Error::CannotCombineMaps(format!(
+                "the tiles don't cover the whole of their bounds {:?}",
+                union
+            )));
+        }
+
+        let mut params = first.params();
+        params.cell_bounds = union;
+        let mut map = CellMap::new(params);
+
+        for (tile, b) in tiles.iter().zip(bounds) {
+            let min = Point2::new(b.x.0 - union.x.0, b.y.0 - union.y.0).map(|v| v as usize);
+            let max = min + b.get_num_cells();
+            for (dst, src) in map.data.iter_mut().zip(tile.data.iter()) {
+                dst.slice_mut(s![min.y..max.y, min.x..max.x]).assign(src);
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    /// Joins `b` onto the maximum `x` edge of `a`, returning a single map with the parameters of
+    /// `a` which covers both.
+    ///
+    /// The maps' grids must be aligned, as described by [`CellMap::grid_offset()`], and `b` must
+    /// cover exactly the same range of `y` cells as `a`, starting at the cell after the maximum
+    /// `x` edge of `a`. Otherwise [`Error::CannotCombineMaps`] is returned.
+    pub fn concat_x(a: &CellMap<L, T>, b: &CellMap<L, T>) -> Result<CellMap<L, T>, Error> {
+        Self::concat(a, b, Axis(1))
+    }
+
+    /// Joins `b` onto the maximum `y` edge of `a`, returning a single map with the parameters of
+    /// `a` which covers both.
+    ///
+    /// The maps' grids must be aligned, as described by [`CellMap::grid_offset()`], and `b` must
+    /// cover exactly the same range of `x` cells as `a`, starting at the cell after the maximum
+    /// `y` edge of `a`. Otherwise [`Error::CannotCombineMaps`] is returned.
+    pub fn concat_y(a: &CellMap<L, T>, b: &CellMap<L, T>) -> Result<CellMap<L, T>, Error> {
+        Self::concat(a, b, Axis(0))
+    }
+
+    /// Joins `b` onto the maximum edge of `a` along the given array axis, which is `1` for `x`
+    /// and `0` for `y`.
+    fn concat(a: &CellMap<L, T>, b: &CellMap<L, T>, axis: Axis) -> Result<CellMap<L, T>, Error> {
+        let offset = a
+            .grid_offset(b)
+            .ok_or_else(|| Error::CannotCombineMaps("the maps' grids aren't aligned".into()))?;
+
+        // The bounds of b in a's map frame
+        let a_bounds = a.cell_bounds();
+        let b_bounds = b.cell_bounds();
+        let b_bounds = Bounds {
+            x: (b_bounds.x.0 + offset.x, b_bounds.x.1 + offset.x),
+            y: (b_bounds.y.0 + offset.y, b_bounds.y.1 + offset.y),
+        };
+
+        let (along, across, name) = if axis == Axis(1) {
+            ((a_bounds.x, b_bounds.x), (a_bounds.y, b_bounds.y), "x")
+        } else {
+            ((a_bounds.y, b_bounds.y), (a_bounds.x, b_bounds.x), "y")
+        };
+        if across.0 != across.1 || along.0 .1 != along.1 .0 {
+            return Err(Error::CannotCombineMaps(format!(
+                "the map with bounds {:?} isn't adjacent to the maximum {} edge of the map with \
+                 bounds {:?}",
+                b_bounds, name, a_bounds
+            )));
+        }
+
+        let data = a
+            .data
+            .iter()
+            .zip(b.data.iter())
+            .map(|(a, b)| concatenate(axis, &[a.view(), b.view()]))
+            .collect::<Result<_, _>>()?;
+
+        let mut params = a.params();
+        params.cell_bounds = a_bounds.union(&b_bounds);
+
+        CellMap::new_from_data(params, data)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::TestLayers, CellMapParams};
+
+    fn new_map() -> CellMap<TestLayers, f64> {
+        let mut map = CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-3, 4), (2, 7)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.25),
+                position_in_parent: Vector2::new(1.0, -2.0),
+                rotation_in_parent_rad: 0.4,
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        for ((_, index), v) in map.iter_mut().indexed() {
+            *v = (index.x + 10 * index.y) as f64;
+        }
+
+        map
+    }
+
+    #[test]
+    fn split() {
+        let map = new_map();
+        let tiles = map.split(2, 3);
+        assert_eq!(tiles.len(), 6);
+
+        // Tiles are in row-major order and spread the remainder along each axis
+        assert_eq!(
+            tiles[0].cell_bounds(),
+            Bounds::new((-3, 0), (2, 3)).unwrap()
+        );
+        assert_eq!(tiles[1].cell_bounds(), Bounds::new((0, 4), (2, 3)).unwrap());
+        assert_eq!(tiles[5].cell_bounds(), Bounds::new((0, 4), (5, 7)).unwrap());
+
+        // Cells keep their values and positions
+        let tile = &tiles[3];
+        assert_eq!(
+            tile[(TestLayers::Layer1, Point2::new(2, 1))],
+            map[(TestLayers::Layer1, Point2::new(5, 2))]
+        );
+        assert_f64_iter_eq!(
+            tile.position(Point2::new(2, 1)).unwrap().coords,
+            map.position(Point2::new(5, 2)).unwrap().coords,
+            1e-12
+        );
+
+        // The number of tiles is clamped to the number of cells
+        assert_eq!(map.split(0, 100).len(), 5);
+    }
+
+    #[test]
+    fn assemble() {
+        let map = new_map();
+        let mut tiles = map.split(3, 2);
+        tiles.reverse();
+
+        let assembled = CellMap::assemble(&tiles).unwrap();
+        assert_eq!(assembled.cell_bounds(), map.cell_bounds());
+        assert_eq!(assembled.to_parent(), map.to_parent());
+        for layer in TestLayers::all() {
+            assert_eq!(assembled[layer], map[layer]);
+        }
+
+        // Gaps, overlaps and misaligned tiles are rejected
+        assert!(matches!(
+            CellMap::assemble(&tiles[1..]),
+            Err(Error::CannotCombineMaps(_))
+        ));
+
+        let mut overlapping = tiles.clone();
+        overlapping.push(tiles[0].clone());
+        assert!(matches!(
+            CellMap::assemble(&overlapping),
+            Err(Error::OverlappingRegions(_, _))
+        ));
+
+        let mut misaligned = tiles.clone();
+        misaligned[2].move_map(Vector2::new(1.1, -2.0), 0.4);
+        assert!(matches!(
+            CellMap::assemble(&misaligned),
+            Err(Error::CannotCombineMaps(_))
+        ));
+
+        assert!(CellMap::<TestLayers, f64>::assemble(&[]).is_err());
+    }
+
+    #[test]
+    fn concat() {
+        let map = new_map();
+        let tiles = map.split(2, 2);
+
+        let bottom = CellMap::concat_x(&tiles[0], &tiles[1]).unwrap();
+        let top = CellMap::concat_x(&tiles[2], &tiles[3]).unwrap();
+        let joined = CellMap::concat_y(&bottom, &top).unwrap();
+        assert_eq!(joined.cell_bounds(), map.cell_bounds());
+        for layer in TestLayers::all() {
+            assert_eq!(joined[layer], map[layer]);
+        }
+
+        // A strip built with its own origin can be joined as long as its grid is aligned
+        let mut strip = tiles[1].clone();
+        let shift =
+            strip.to_parent() * Point2::new(3.0, 0.0) - strip.to_parent() * Point2::origin();
+        strip.move_map(Vector2::new(1.0, -2.0) + shift, 0.4);
+        strip.params.cell_bounds.x = (-3, 1);
+        strip.metadata.cell_bounds.x = (-3, 1);
+        let joined = CellMap::concat_x(&tiles[0], &strip).unwrap();
+        assert_eq!(joined.cell_bounds(), bottom.cell_bounds());
+        assert_eq!(joined[TestLayers::Layer0], bottom[TestLayers::Layer0]);
+
+        // The maps must be adjacent along the right axis and cover the same cells across it
+        assert!(CellMap::concat_x(&tiles[1], &tiles[0]).is_err());
+        assert!(CellMap::concat_y(&tiles[0], &tiles[1]).is_err());
+        assert!(CellMap::concat_x(&tiles[0], &top).is_err());
+        assert!(matches!(
+            CellMap::concat_x(&tiles[0], &tiles[3]),
+            Err(Error::CannotCombineMaps(_))
+        ));
+    }
+
+    #[test]
+    fn grid_offset() {
+        let map = new_map();
+
+        // A map whose origin is a whole number of cells away is aligned
+        let mut other = map.clone();
+        let shift = map.to_parent() * Point2::new(2.0, -3.0) - map.to_parent() * Point2::origin();
+        other.move_map(Vector2::new(1.0, -2.0) + shift, 0.4);
+        assert_eq!(map.grid_offset(&other), Some(Vector2::new(2, -3)));
+        assert_eq!(other.grid_offset(&map), Some(Vector2::new(-2, 3)));
+
+        other.move_map(Vector2::new(1.0, -2.0), 0.5);
+        assert_eq!(map.grid_offset(&other), None);
+    }
+}
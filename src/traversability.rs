@@ -0,0 +1,793 @@
+//! Provides a terrain traversability pipeline, which combines slope, roughness and step height
+//! into a single cost layer for planning.
+//!
+//! The pipeline has two stages, which can be run separately or together with
+//! [`CellMap::traversability()`]:
+//!
+//! 1. Feature layers are computed from a height layer with [`CellMap::slope()`],
+//!    [`CellMap::roughness()`] and [`CellMap::step_height()`], from the [`analysis`] module.
+//!    Roughness at several scales can be computed together with [`CellMap::roughness_pyramid()`].
+//! 2. The feature layers are combined into a cost layer with [`CellMap::traversability_cost()`].
+//!
+//! The cost of each feature is its value divided by the feature's critical value, so that a
+//! feature at its critical value has cost `1.0`. The cost of a cell is the weighted mean of the
+//! feature costs, or `1.0` if any feature reaches its critical value, so costs range from `0.0`
+//! for flat, smooth terrain to `1.0` for untraversable terrain. Cells where any feature is `NaN`
+//! have a `NaN` cost, marking them as unknown.
+//!
+//! Planners which only need a binary occupancy layer can instead use
+//! [`CellMap::project_height_to_occupancy()`], which thresholds the slope and step height.
+//!
+//! [`analysis`]: crate::analysis
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::Vector2;
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+
+use crate::{math, CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The largest rounding error, relative to a window's variance, accepted from the integral images
+/// in [`CellMap::roughness_pyramid()`] before the window is summed directly instead.
+const MAX_RELATIVE_ERROR: f64 = 1e-8;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Parameters which control how traversability is computed.
+///
+/// The defaults are typical of a small planetary rover.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TraversabilityParams {
+    /// The slope, in radians, at and above which terrain is untraversable.
+    ///
+    /// # Default
+    ///
+    /// The default value is 30 degrees.
+    pub critical_slope: f64,
+
+    /// The roughness, the standard deviation of height in the roughness window, at and above which
+    /// terrain is untraversable.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.05`.
+    pub critical_roughness: f64,
+
+    /// The step height, the largest height difference between a cell and the cells within the
+    /// step radius, at and above which terrain is untraversable.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.2`.
+    pub critical_step: f64,
+
+    /// The weight of the slope cost.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.4`.
+    pub slope_weight: f64,
+
+    /// The weight of the roughness cost.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.3`.
+    pub roughness_weight: f64,
+
+    /// The weight of the step height cost.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.3`.
+    pub step_weight: f64,
+
+    /// The radius of the window used to compute roughness, in parent-frame units.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.3`.
+    pub roughness_radius: f64,
+
+    /// The radius used to compute step height, in parent-frame units. This is
+    /// typically around the size of the robot's wheels.
+    ///
+    /// # Default
+    ///
+    /// The default value is `0.15`.
+    pub step_radius: f64,
+}
+
+/// The layers used by [`CellMap::traversability()`].
+#[derive(Debug, Clone, Copy)]
+pub struct TraversabilityLayers<L> {
+    /// The layer containing terrain height.
+    pub height: L,
+
+    /// The layer to write the slope into.
+    pub slope: L,
+
+    /// The layer to write the roughness into.
+    pub roughness: L,
+
+    /// The layer to write the step height into.
+    pub step: L,
+
+    /// The layer to write the cost into.
+    pub cost: L,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl TraversabilityParams {
+    /// Gets the cost of a cell with the given slope, roughness and step height.
+    pub fn cost(&self, slope: f64, roughness: f64, step: f64) -> f64 {
+        let features = [
+            (slope, self.critical_slope, self.slope_weight),
+            (roughness, self.critical_roughness, self.roughness_weight),
+            (step, self.critical_step, self.step_weight),
+        ];
+
+        if features.iter().any(|(v, _, _)| v.is_nan()) {
+            return f64::NAN;
+        }
+
+        if features.iter().any(|&(v, critical, _)| v >= critical) {
+            return 1.0;
+        }
+
+        let total_weight: f64 = features.iter().map(|(_, _, w)| w).sum();
+        if total_weight <= 0.0 {
+            return 0.0;
+        }
+
+        features
+            .iter()
+            .map(|&(v, critical, w)| w * v / critical)
+            .sum::<f64>()
+            / total_weight
+    }
+}
+
+impl Default for TraversabilityParams {
+    fn default() -> Self {
+        Self {
+            critical_slope: 30f64.to_radians(),
+            critical_roughness: 0.05,
+            critical_step: 0.2,
+            slope_weight: 0.4,
+            roughness_weight: 0.3,
+            step_weight: 0.3,
+            roughness_radius: 0.3,
+            step_radius: 0.15,
+        }
+    }
+}
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Computes the slope of `height_layer` in radians, writing the result into `dst_layer`.
+    ///
+    /// The gradient is found using central differences, or one-sided differences at the edge of
+    /// the map. Cells whose neighbours are `NaN` have `NaN` slope.
+    pub fn slope(&mut self, height_layer: L, dst_layer: L) {
+        self[dst_layer] = self.compute_slope(height_layer);
+    }
+
+    /// Computes the roughness of `height_layer`, the standard deviation of the height in a window
+    /// around each cell, writing the result into `dst_layer`.
+    ///
+    /// The window covers the cells whose centres are within `radius` of the cell's centre along
+    /// each axis, so for non-square cells it spans a different number of cells along `x` and `y`,
+    /// see [`CellMap::radius_in_cells()`].
+    ///
+    /// `NaN` cells in the window are ignored, and cells which are `NaN` themselves have `NaN`
+    /// roughness.
+    pub fn roughness(&mut self, height_layer: L, dst_layer: L, radius: f64) {
+        let roughness = self.window_stat(height_layer, radius, std_dev);
+
+        self[dst_layer] = roughness;
+    }
+
+    /// Computes the [roughness](CellMap::roughness()) of `height_layer` for each radius in
+    /// `radii`, writing the result for `radii[i]` into `dst_layers[i]`.
+    ///
+    /// This gives the same result as calling [`CellMap::roughness()`] once per radius, to within
+    /// rounding error, but builds integral images of the height once and reuses them for every
+    /// radius, so the cost of each radius doesn't depend on the size of its window. Windows where
+    /// the integral images would lose too much precision, such as flat terrain next to large
+    /// relief, are summed directly instead. All outputs are computed before any are written, so
+    /// `height_layer` may also be one of the `dst_layers`.
+    ///
+    /// Returns [`Error::WrongNumberOfLayers`] if `radii` and `dst_layers` have different lengths.
+    pub fn roughness_pyramid(
+        &mut self,
+        height_layer: L,
+        radii: &[f64],
+        dst_layers: &[L],
+    ) -> Result<(), Error> {
+        if radii.len() != dst_layers.len() {
+            return Err(Error::WrongNumberOfLayers(radii.len(), dst_layers.len()));
+        }
+
+        trace_span!("roughness_pyramid", num_radii = radii.len());
+
+        let data = &self[height_layer];
+        let (rows, cols) = data.dim();
+
+        // Heights are offset by their mean so the sum of squares doesn't lose precision
+        let (total, count) = data
+            .iter()
+            .filter(|v| !v.is_nan())
+            .fold((0.0, 0usize), |(s, n), v| (s + v, n + 1));
+        let offset = if count > 0 { total / count as f64 } else { 0.0 };
+
+        // Integral images of the number of valid cells, their heights and squared heights, with
+        // an extra leading row and column of zeros
+        let mut counts = Array2::<f64>::zeros((rows + 1, cols + 1));
+        let mut sums = counts.clone();
+        let mut squares = counts.clone();
+        for y in 0..rows {
+            for x in 0..cols {
+                let v = data[(y, x)] - offset;
+                let (n, s, q) = if v.is_nan() {
+                    (0.0, 0.0, 0.0)
+                } else {
+                    (1.0, v, v * v)
+                };
+
+                for (table, value) in [(&mut counts, n), (&mut sums, s), (&mut squares, q)] {
+                    table[(y + 1, x + 1)] =
+                        value + table[(y, x + 1)] + table[(y + 1, x)] - table[(y, x)];
+                }
+            }
+        }
+
+        let roughnesses: Vec<_> = radii
+            .iter()
+            .map(|&radius| {
+                let semi_width = self.radius_in_cells(radius);
+                let mut values = Vec::new();
+
+                Array2::from_shape_fn((rows, cols), |(y, x)| {
+                    if data[(y, x)].is_nan() {
+                        return f64::NAN;
+                    }
+
+                    let (y0, y1) = (
+                        y.saturating_sub(semi_width.y),
+                        (y + semi_width.y + 1).min(rows),
+                    );
+                    let (x0, x1) = (
+                        x.saturating_sub(semi_width.x),
+                        (x + semi_width.x + 1).min(cols),
+                    );
+                    let corners = |table: &Array2<f64>| {
+                        [
+                            table[(y1, x1)],
+                            table[(y0, x1)],
+                            table[(y1, x0)],
+                            table[(y0, x0)],
+                        ]
+                    };
+                    let window = |table: &Array2<f64>| {
+                        let [a, b, c, d] = corners(table);
+                        a - b - c + d
+                    };
+                    let magnitude = |table: &Array2<f64>| {
+                        corners(table).iter().fold(0.0_f64, |m, v| m.max(v.abs()))
+                    };
+
+                    let n = window(&counts);
+                    let mean = window(&sums) / n;
+                    let mean_square = window(&squares) / n;
+                    let variance = mean_square - mean * mean;
+
+                    // The tables' rounding error grows with the magnitude of the tables at the
+                    // window's corners, which may be far larger than the window's own values, and
+                    // is amplified by the cancellation in the variance. Only use the variance from
+                    // the tables if it's well above that error, and otherwise sum the window
+                    // directly, as roughness() does.
+                    let error = 4.0
+                        * f64::EPSILON
+                        * (magnitude(&squares) + 2.0 * mean.abs() * magnitude(&sums))
+                        / n
+                        + f64::EPSILON * mean_square;
+                    if variance * MAX_RELATIVE_ERROR > error {
+                        variance.sqrt()
+                    } else if error == 0.0 {
+                        0.0
+                    } else {
+                        window_values(data, (y, x), semi_width, &mut values);
+                        std_dev(&values)
+                    }
+                })
+            })
+            .collect();
+
+        for (layer, roughness) in dst_layers.iter().zip(roughnesses) {
+            self[layer.clone()] = roughness;
+        }
+
+        Ok(())
+    }
+
+    /// Combines the given slope, roughness and step height layers into a cost layer using
+    /// [`TraversabilityParams::cost()`], writing the result into `dst_layer`.
+    pub fn traversability_cost(
+        &mut self,
+        slope_layer: L,
+        roughness_layer: L,
+        step_layer: L,
+        dst_layer: L,
+        params: &TraversabilityParams,
+    ) {
+        let cost = ndarray::Zip::from(&self[slope_layer])
+            .and(&self[roughness_layer])
+            .and(&self[step_layer])
+            .map_collect(|&slope, &roughness, &step| params.cost(slope, roughness, step));
+
+        self[dst_layer] = cost;
+    }
+
+    /// Runs the full traversability pipeline, computing the slope, roughness and step height of
+    /// the height layer and combining them into the cost layer.
+    pub fn traversability(
+        &mut self,
+        layers: TraversabilityLayers<L>,
+        params: &TraversabilityParams,
+    ) {
+        self.slope(layers.height.clone(), layers.slope.clone());
+        self.roughness(
+            layers.height.clone(),
+            layers.roughness.clone(),
+            params.roughness_radius,
+        );
+        self.step_height(layers.height, layers.step.clone(), params.step_radius);
+        self.traversability_cost(
+            layers.slope,
+            layers.roughness,
+            layers.step,
+            layers.cost,
+            params,
+        );
+    }
+
+    /// Projects `height_layer` into a 2D occupancy layer in `dst_layer`, the usual reduction of a
+    /// 2.5D elevation map for planners which only need to know where the robot can't go.
+    ///
+    /// A cell is occupied (`1.0`) if its slope is above `slope_limit`, in radians, or if the
+    /// height difference to any of its immediate neighbours is above `robot_clearance`, otherwise
+    /// it's free (`0.0`). Cells with `NaN` height are unknown and are `NaN`. Both cells either
+    /// side of a step are occupied, since the robot can't cross the step from either side.
+    pub fn project_height_to_occupancy(
+        &mut self,
+        height_layer: L,
+        dst_layer: L,
+        robot_clearance: f64,
+        slope_limit: f64,
+    ) {
+        let slope = self.compute_slope(height_layer.clone());
+
+        // A radius of one cell diagonal covers all 8 neighbours
+        let radius = math::hypot(self.cell_size().x, self.cell_size().y);
+        let step = self.compute_step_height(height_layer.clone(), radius);
+
+        let occupancy = ndarray::Zip::from(&self[height_layer])
+            .and(&slope)
+            .and(&step)
+            .map_collect(|&height, &slope, &step| {
+                if height.is_nan() {
+                    f64::NAN
+                } else if slope > slope_limit || step > robot_clearance {
+                    1.0
+                } else {
+                    0.0
+                }
+            });
+
+        self[dst_layer] = occupancy;
+    }
+
+    /// Returns the slope of `height_layer` in radians, see [`CellMap::slope()`].
+    fn compute_slope(&self, height_layer: L) -> Array2<f64> {
+        let cell_size = self.cell_size();
+        let height = &self[height_layer];
+        let (rows, cols) = height.dim();
+
+        Array2::from_shape_fn((rows, cols), |(y, x)| {
+            let (x0, x1) = (x.saturating_sub(1), (x + 1).min(cols - 1));
+            let (y0, y1) = (y.saturating_sub(1), (y + 1).min(rows - 1));
+
+            let dx = if x1 > x0 {
+                (height[(y, x1)] - height[(y, x0)]) / ((x1 - x0) as f64 * cell_size.x)
+            } else {
+                0.0
+            };
+            let dy = if y1 > y0 {
+                (height[(y1, x)] - height[(y0, x)]) / ((y1 - y0) as f64 * cell_size.y)
+            } else {
+                0.0
+            };
+
+            math::atan(math::hypot(dx, dy))
+        })
+    }
+
+    /// Applies `stat` to the valid values in a rectangular window of the given radius around each cell
+    /// in `layer`.
+    fn window_stat<F>(&self, layer: L, radius: f64, stat: F) -> Array2<f64>
+    where
+        F: Fn(&[f64]) -> f64,
+    {
+        let data = &self[layer];
+        let semi_width = self.radius_in_cells(radius);
+        let mut values = Vec::new();
+
+        Array2::from_shape_fn(data.dim(), |(y, x)| {
+            if data[(y, x)].is_nan() {
+                return f64::NAN;
+            }
+
+            window_values(data, (y, x), semi_width, &mut values);
+            stat(&values)
+        })
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Replaces `values` with the valid values of `data` in the window of the given semi width
+/// around the cell `(y, x)`, in row-major order.
+fn window_values(
+    data: &Array2<f64>,
+    (y, x): (usize, usize),
+    semi_width: Vector2<usize>,
+    values: &mut Vec<f64>,
+) {
+    let (rows, cols) = data.dim();
+
+    values.clear();
+    for wy in y.saturating_sub(semi_width.y)..(y + semi_width.y + 1).min(rows) {
+        for wx in x.saturating_sub(semi_width.x)..(x + semi_width.x + 1).min(cols) {
+            let v = data[(wy, wx)];
+            if !v.is_nan() {
+                values.push(v);
+            }
+        }
+    }
+}
+
+/// Returns the population standard deviation of `values`.
+fn std_dev(values: &[f64]) -> f64 {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt()
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point2, Vector2};
+
+    use super::*;
+    use crate::{cell_map::Bounds, CellMapParams};
+
+    #[derive(Clone, Copy, Debug)]
+    enum Terrain {
+        Height,
+        Slope,
+        Roughness,
+        Step,
+        Cost,
+    }
+
+    // Manual impl since the derive doesn't work inside this crate, see `test_utils`
+    impl Layer for Terrain {
+        const NUM_LAYERS: usize = 5;
+        const FIRST: Self = Self::Height;
+
+        fn to_index(&self) -> usize {
+            *self as usize
+        }
+
+        fn from_index(index: usize) -> Self {
+            Self::all()[index]
+        }
+
+        fn all() -> Vec<Self> {
+            vec![
+                Self::Height,
+                Self::Slope,
+                Self::Roughness,
+                Self::Step,
+                Self::Cost,
+            ]
+        }
+    }
+
+    const LAYERS: TraversabilityLayers<Terrain> = TraversabilityLayers {
+        height: Terrain::Height,
+        slope: Terrain::Slope,
+        roughness: Terrain::Roughness,
+        step: Terrain::Step,
+        cost: Terrain::Cost,
+    };
+
+    fn new_map() -> CellMap<Terrain, f64> {
+        CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 10), (0, 10)).unwrap(),
+                cell_size: Vector2::new(0.1, 0.1),
+                ..Default::default()
+            },
+            0.0,
+        )
+    }
+
+    #[test]
+    fn cost() {
+        let params = TraversabilityParams::default();
+
+        assert_eq!(params.cost(0.0, 0.0, 0.0), 0.0);
+        assert_eq!(params.cost(params.critical_slope, 0.0, 0.0), 1.0);
+        assert_eq!(params.cost(0.0, 0.0, 0.3), 1.0);
+        assert!(params.cost(0.0, f64::NAN, 0.0).is_nan());
+
+        // Half of each critical value gives half cost
+        let cost = params.cost(
+            params.critical_slope / 2.0,
+            params.critical_roughness / 2.0,
+            params.critical_step / 2.0,
+        );
+        assert!((cost - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn pipeline() {
+        let params = TraversabilityParams::default();
+
+        // Flat terrain is free
+        let mut map = new_map();
+        map.traversability(LAYERS, &params);
+        assert!(map.iter().layer(Terrain::Cost).all(|&c| c == 0.0));
+
+        // A 1 in 10 ramp has a constant slope and no roughness or steps
+        for ((_, index), height) in map.iter_mut().layer(Terrain::Height).indexed() {
+            *height = 0.01 * index.x as f64;
+        }
+        map.traversability(LAYERS, &params);
+        for &slope in map.iter().layer(Terrain::Slope) {
+            assert!((slope - 0.1f64.atan()).abs() < 1e-9);
+        }
+        assert!(map.iter().layer(Terrain::Cost).all(|&c| c > 0.0 && c < 1.0));
+
+        // A rock is untraversable, since it's a step larger than the critical step height, but
+        // cells far from it aren't affected
+        let mut map = new_map();
+        map[(Terrain::Height, Point2::new(5, 5))] = 0.3;
+        map[(Terrain::Height, Point2::new(0, 9))] = f64::NAN;
+        map.traversability(LAYERS, &params);
+
+        assert_eq!(map[(Terrain::Cost, Point2::new(5, 5))], 1.0);
+        assert_eq!(map[(Terrain::Cost, Point2::new(4, 4))], 1.0);
+        assert_eq!(map[(Terrain::Cost, Point2::new(9, 0))], 0.0);
+        assert!(map[(Terrain::Cost, Point2::new(0, 9))].is_nan());
+    }
+
+    #[test]
+    fn project_height_to_occupancy() {
+        let mut map = new_map();
+        let slope_limit = 20f64.to_radians();
+
+        // A gentle ramp is free
+        for ((_, index), height) in map.iter_mut().layer(Terrain::Height).indexed() {
+            *height = 0.01 * index.x as f64;
+        }
+        map.project_height_to_occupancy(Terrain::Height, Terrain::Cost, 0.1, slope_limit);
+        assert!(map.iter().layer(Terrain::Cost).all(|&c| c == 0.0));
+
+        // A steep ramp is occupied
+        map[Terrain::Height].mapv_inplace(|h| h * 50.0);
+        map.project_height_to_occupancy(Terrain::Height, Terrain::Cost, 10.0, slope_limit);
+        assert!(map.iter().layer(Terrain::Cost).all(|&c| c == 1.0));
+
+        // A rock taller than the clearance blocks itself and its neighbours
+        let mut map = new_map();
+        map[(Terrain::Height, Point2::new(5, 5))] = 0.3;
+        map[(Terrain::Height, Point2::new(0, 9))] = f64::NAN;
+        map.project_height_to_occupancy(Terrain::Height, Terrain::Cost, 0.2, std::f64::consts::PI);
+        assert_eq!(map[(Terrain::Cost, Point2::new(5, 5))], 1.0);
+        assert_eq!(map[(Terrain::Cost, Point2::new(4, 6))], 1.0);
+        assert_eq!(map[(Terrain::Cost, Point2::new(3, 5))], 0.0);
+        assert!(map[(Terrain::Cost, Point2::new(0, 9))].is_nan());
+        assert_eq!(
+            map.iter()
+                .layer(Terrain::Cost)
+                .filter(|&&c| c == 1.0)
+                .count(),
+            9
+        );
+
+        // Projecting the height layer onto itself gives the same occupancy
+        let expected = map[Terrain::Cost].clone();
+        map.project_height_to_occupancy(
+            Terrain::Height,
+            Terrain::Height,
+            0.2,
+            std::f64::consts::PI,
+        );
+        assert!(map[Terrain::Height]
+            .iter()
+            .zip(expected.iter())
+            .all(|(a, b)| a == b || (a.is_nan() && b.is_nan())));
+    }
+
+    #[test]
+    fn non_square_cells() {
+        let mut map = CellMap::<Terrain, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 10), (0, 5)).unwrap(),
+                cell_size: Vector2::new(0.1, 0.4),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // A plane rising by 0.1 per unit along both x and y, i.e. more per cell along y
+        for ((_, index), height) in map.iter_mut().layer(Terrain::Height).indexed() {
+            *height = 0.01 * index.x as f64 + 0.04 * index.y as f64;
+        }
+        map.slope(Terrain::Height, Terrain::Slope);
+        for &slope in map.iter().layer(Terrain::Slope) {
+            assert!((slope - 0.1f64.hypot(0.1).atan()).abs() < 1e-9);
+        }
+
+        // The roughness window spans 4 cells either side along x but only 1 along y
+        let mut map = CellMap::<Terrain, f64>::new_from_elem(map.params(), 0.0);
+        map[(Terrain::Height, Point2::new(0, 0))] = 1.0;
+        map.roughness(Terrain::Height, Terrain::Roughness, 0.4);
+        assert!(map[(Terrain::Roughness, Point2::new(4, 1))] > 0.0);
+        assert_eq!(map[(Terrain::Roughness, Point2::new(5, 0))], 0.0);
+        assert_eq!(map[(Terrain::Roughness, Point2::new(0, 2))], 0.0);
+    }
+
+    #[test]
+    fn roughness_pyramid() {
+        let mut map = CellMap::<Terrain, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 12), (0, 9)).unwrap(),
+                cell_size: Vector2::new(0.1, 0.25),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // Uneven terrain with some holes
+        for ((_, index), height) in map.iter_mut().layer(Terrain::Height).indexed() {
+            *height = if (index.x + 2 * index.y) % 7 == 3 {
+                f64::NAN
+            } else {
+                100.0 + 0.3 * ((index.x * index.y) % 5) as f64 - 0.1 * index.x as f64
+            };
+        }
+
+        let radii = [0.1, 0.3, 0.6];
+        map.roughness_pyramid(
+            Terrain::Height,
+            &radii,
+            &[Terrain::Slope, Terrain::Roughness, Terrain::Step],
+        )
+        .unwrap();
+
+        let mut expected = map.clone();
+        for (&radius, layer) in
+            radii
+                .iter()
+                .zip([Terrain::Slope, Terrain::Roughness, Terrain::Step])
+        {
+            expected.roughness(Terrain::Height, Terrain::Cost, radius);
+            for (a, b) in map
+                .iter()
+                .layer(layer)
+                .zip(expected.iter().layer(Terrain::Cost))
+            {
+                assert!(a.is_nan() && b.is_nan() || (a - b).abs() < 1e-6);
+            }
+        }
+
+        // The height layer can be overwritten
+        map.roughness_pyramid(Terrain::Height, &[0.3], &[Terrain::Height])
+            .unwrap();
+        for (a, b) in map
+            .iter()
+            .layer(Terrain::Height)
+            .zip(map.iter().layer(Terrain::Roughness))
+        {
+            assert!(a.is_nan() && b.is_nan() || (a - b).abs() < 1e-12);
+        }
+
+        assert!(matches!(
+            map.roughness_pyramid(Terrain::Height, &[0.1, 0.2], &[Terrain::Cost]),
+            Err(Error::WrongNumberOfLayers(2, 1))
+        ));
+
+        // Small variations after much larger ones in the tables aren't treated as zero
+        let mut map = CellMap::<Terrain, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 40), (0, 1)).unwrap(),
+                cell_size: Vector2::new(0.1, 0.1),
+                ..Default::default()
+            },
+            0.0,
+        );
+        for ((_, index), height) in map.iter_mut().layer(Terrain::Height).indexed() {
+            let sign = if index.x % 2 == 0 { 1.0 } else { -1.0 };
+            *height = if index.x < 20 {
+                sign * 4096.0
+            } else {
+                sign / 4096.0
+            };
+        }
+        map.roughness_pyramid(Terrain::Height, &[0.1], &[Terrain::Roughness])
+            .unwrap();
+        map.roughness(Terrain::Height, Terrain::Cost, 0.1);
+        for x in 21..40 {
+            let index = Point2::new(x, 0);
+            let expected = map[(Terrain::Cost, index)];
+            assert!(expected > 0.0);
+            assert!((map[(Terrain::Roughness, index)] - expected).abs() < 1e-9);
+        }
+
+        // Flat terrain with small steps next to large relief matches roughness(), and uniform
+        // windows are exactly zero
+        let mut map = CellMap::<Terrain, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 60), (0, 60)).unwrap(),
+                cell_size: Vector2::new(0.1, 0.1),
+                ..Default::default()
+            },
+            0.0,
+        );
+        for ((_, index), height) in map.iter_mut().layer(Terrain::Height).indexed() {
+            *height = if index.y < 30 {
+                1000.0 * (0.7 * index.x as f64 + 0.3 * index.y as f64).sin()
+            } else {
+                0.1 + 0.001 * (index.x / 7) as f64
+            };
+        }
+        map.roughness_pyramid(Terrain::Height, &[0.2], &[Terrain::Roughness])
+            .unwrap();
+        map.roughness(Terrain::Height, Terrain::Cost, 0.2);
+        let mut uniform = 0;
+        for (&a, &b) in map
+            .iter()
+            .layer(Terrain::Roughness)
+            .zip(map.iter().layer(Terrain::Cost))
+        {
+            assert!((a - b).abs() <= 1e-8 * b, "{} != {}", a, b);
+            if b == 0.0 {
+                uniform += 1;
+            }
+        }
+        assert!(uniform > 0);
+    }
+}
@@ -0,0 +1,351 @@
+//! Provides the [`UpdateQueue`] type, a serialisable queue of [`MapUpdate`]s which can be built
+//! away from the map and applied to it in one go.
+//!
+//! This allows sensor processing to happen on other threads or tasks, which only need to produce
+//! updates rather than having access to the map. The owner of the map then applies each queue
+//! with [`UpdateQueue::apply_all()`], or sends it to a [`MapServer`] with
+//! [`MapClient::apply()`].
+//!
+//! [`MapServer`]: crate::server::MapServer
+//! [`MapClient::apply()`]: crate::server::MapClient::apply
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::Point2;
+use ndarray::{s, Array2};
+use serde::{Deserialize, Serialize};
+
+use crate::{cell_map::Bounds, CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// A single operation on a map which can be stored in an [`UpdateQueue`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MapUpdate<L, T> {
+    /// Sets the value of each of the given cell indices.
+    ///
+    /// All indices must be inside the map.
+    SetCells {
+        /// The layer to update.
+        layer: L,
+        /// The index and new value of each cell.
+        cells: Vec<(Point2<usize>, T)>,
+    },
+
+    /// Sets every cell within the given map-frame `bounds` to `value`. Any part of the bounds
+    /// outside the map is ignored.
+    FillRegion {
+        /// The layer to update.
+        layer: L,
+        /// The region of the map to fill.
+        bounds: Bounds,
+        /// The value to set each cell to.
+        value: T,
+    },
+
+    /// Copies `patch` into the map, centred on the cell containing the parent-frame `position`.
+    /// Any part of the patch outside the map is ignored.
+    ///
+    /// The centre of the patch is the cell at `(rows / 2, cols / 2)`, which for patches with an
+    /// even size is the cell just above and to the right of the geometric centre.
+    Stamp {
+        /// The layer to update.
+        layer: L,
+        /// The position to centre the patch on.
+        position: Point2<f64>,
+        /// The values to stamp into the map, in the same `(y, x)` order as the map's layers.
+        patch: Array2<T>,
+    },
+
+    /// Sets the value of the cell containing each of the given parent-frame positions. Points
+    /// outside the map are ignored, and if more than one point falls in the same cell the last one
+    /// is used.
+    InsertPoints {
+        /// The layer to update.
+        layer: L,
+        /// The position and value of each point.
+        points: Vec<(Point2<f64>, T)>,
+    },
+}
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A queue of [`MapUpdate`]s to be applied to a map together.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateQueue<L, T> {
+    updates: Vec<MapUpdate<L, T>>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> UpdateQueue<L, T>
+where
+    L: Layer,
+{
+    /// Creates a new empty queue.
+    pub fn new() -> Self {
+        Self {
+            updates: Vec::new(),
+        }
+    }
+
+    /// Returns the number of updates in the queue.
+    pub fn len(&self) -> usize {
+        self.updates.len()
+    }
+
+    /// Returns whether or not the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// Returns the updates in the queue, in the order they will be applied.
+    pub fn updates(&self) -> &[MapUpdate<L, T>] {
+        &self.updates
+    }
+
+    /// Adds an update to the end of the queue.
+    pub fn push(&mut self, update: MapUpdate<L, T>) {
+        self.updates.push(update)
+    }
+
+    /// Moves all updates in `other` to the end of this queue.
+    pub fn append(&mut self, other: &mut UpdateQueue<L, T>) {
+        self.updates.append(&mut other.updates)
+    }
+
+    /// Adds a [`MapUpdate::SetCells`] update to the queue.
+    pub fn set_cells(&mut self, layer: L, cells: Vec<(Point2<usize>, T)>) {
+        self.push(MapUpdate::SetCells { layer, cells })
+    }
+
+    /// Adds a [`MapUpdate::FillRegion`] update to the queue.
+    pub fn fill_region(&mut self, layer: L, bounds: Bounds, value: T) {
+        self.push(MapUpdate::FillRegion {
+            layer,
+            bounds,
+            value,
+        })
+    }
+
+    /// Adds a [`MapUpdate::Stamp`] update to the queue.
+    pub fn stamp(&mut self, layer: L, position: Point2<f64>, patch: Array2<T>) {
+        self.push(MapUpdate::Stamp {
+            layer,
+            position,
+            patch,
+        })
+    }
+
+    /// Adds a [`MapUpdate::InsertPoints`] update to the queue.
+    pub fn insert_points(&mut self, layer: L, points: Vec<(Point2<f64>, T)>) {
+        self.push(MapUpdate::InsertPoints { layer, points })
+    }
+}
+
+impl<L, T> UpdateQueue<L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    /// Applies every update in the queue to `map` in order, leaving the queue empty.
+    ///
+    /// The updates are applied atomically: they are all checked against the map first, and if any
+    /// of them is invalid an error is returned without modifying either the map or the queue.
+    pub fn apply_all(&mut self, map: &mut CellMap<L, T>) -> Result<(), Error> {
+        for update in self.updates.iter() {
+            update.check(map)?;
+        }
+
+        for update in self.updates.drain(..) {
+            update.apply(map);
+        }
+
+        Ok(())
+    }
+}
+
+impl<L, T> MapUpdate<L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    /// Checks that this update can be applied to the given map.
+    fn check(&self, map: &CellMap<L, T>) -> Result<(), Error> {
+        if let MapUpdate::SetCells { layer, cells } = self {
+            if let Some((index, _)) = cells.iter().find(|(i, _)| !map.index_in_map(*i)) {
+                return Err(Error::IndexOutsideLayer(
+                    layer.to_index(),
+                    *index,
+                    map.cell_bounds(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies this update to the map, which must have been checked first.
+    fn apply(self, map: &mut CellMap<L, T>) {
+        match self {
+            MapUpdate::SetCells { layer, cells } => {
+                for (index, value) in cells {
+                    map[(layer.clone(), index)] = value;
+                }
+            }
+            MapUpdate::FillRegion {
+                layer,
+                bounds,
+                value,
+            } => {
+                if let Some(slice) = map.cell_bounds().get_slice_of_other(&bounds) {
+                    map[layer]
+                        .slice_mut(s![slice.y.0..slice.y.1, slice.x.0..slice.x.1])
+                        .fill(value);
+                }
+            }
+            MapUpdate::Stamp {
+                layer,
+                position,
+                patch,
+            } => {
+                // Get the bounds the patch covers in the map frame, and the part of the patch
+                // which lies inside the map.
+                let centre = unsafe { map.index_unchecked(position) };
+                let map_bounds = map.cell_bounds();
+                let (rows, cols) = patch.dim();
+                let min = Point2::new(
+                    map_bounds.x.0 + centre.x - (cols / 2) as isize,
+                    map_bounds.y.0 + centre.y - (rows / 2) as isize,
+                );
+                let patch_bounds = Bounds {
+                    x: (min.x, min.x + cols as isize),
+                    y: (min.y, min.y + rows as isize),
+                };
+
+                if let (Some(dst), Some(src)) = (
+                    map_bounds.get_slice_of_other(&patch_bounds),
+                    patch_bounds.get_slice_of_other(&map_bounds),
+                ) {
+                    map[layer]
+                        .slice_mut(s![dst.y.0..dst.y.1, dst.x.0..dst.x.1])
+                        .assign(&patch.slice(s![src.y.0..src.y.1, src.x.0..src.x.1]));
+                }
+            }
+            MapUpdate::InsertPoints { layer, points } => {
+                for (position, value) in points {
+                    if let Some(index) = map.index(position) {
+                        map[(layer.clone(), index)] = value;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<L, T> Default for UpdateQueue<L, T>
+where
+    L: Layer,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+    use ndarray::arr2;
+
+    use super::*;
+    use crate::{test_utils::TestLayers, CellMapParams};
+
+    fn map() -> CellMap<TestLayers, i32> {
+        CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-2, 4), (0, 5)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            0,
+        )
+    }
+
+    #[test]
+    fn apply_all() {
+        let mut map = map();
+        let mut queue = UpdateQueue::new();
+
+        queue.fill_region(
+            TestLayers::Layer0,
+            Bounds::new((2, 10), (3, 10)).unwrap(),
+            1,
+        );
+        queue.set_cells(
+            TestLayers::Layer1,
+            vec![(Point2::new(0, 0), 2), (Point2::new(5, 4), 3)],
+        );
+        queue.stamp(
+            TestLayers::Layer2,
+            Point2::new(-0.75, 0.25),
+            arr2(&[[1, 2, 3], [4, 5, 6], [7, 8, 9]]),
+        );
+        queue.insert_points(
+            TestLayers::Layer0,
+            vec![
+                (Point2::new(-0.25, 0.75), 4),
+                (Point2::new(-0.1, 0.9), 5),
+                (Point2::new(100.0, 0.0), 6),
+            ],
+        );
+        assert_eq!(queue.len(), 4);
+
+        queue.apply_all(&mut map).unwrap();
+        assert!(queue.is_empty());
+
+        // Fill is clipped to the map
+        assert_eq!(map[TestLayers::Layer0].sum(), 2 * 2 + 5);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(4, 3))], 1);
+
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(0, 0))], 2);
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(5, 4))], 3);
+
+        // Stamp is centred on cell (-2, 0), so only its top right four cells are in the map
+        assert_eq!(
+            map[TestLayers::Layer2].slice(s![0..2, 0..2]),
+            arr2(&[[5, 6], [8, 9]])
+        );
+        assert_eq!(map[TestLayers::Layer2].sum(), 5 + 6 + 8 + 9);
+
+        // Both points are in cell (-1, 1), so the last is used
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(1, 1))], 5);
+    }
+
+    #[test]
+    fn atomic() {
+        let mut map = map();
+        let mut queue = UpdateQueue::new();
+
+        queue.fill_region(TestLayers::Layer0, Bounds::new((-2, 4), (0, 5)).unwrap(), 1);
+        queue.set_cells(TestLayers::Layer1, vec![(Point2::new(6, 0), 1)]);
+
+        assert!(matches!(
+            queue.apply_all(&mut map),
+            Err(Error::IndexOutsideLayer(1, _, _))
+        ));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(map.iter().sum::<i32>(), 0);
+    }
+}
@@ -0,0 +1,517 @@
+//! Provides methods for converting the contents of layers into vector geometry, such as contour
+//! lines or the boundaries of regions, so that it can be visualised or consumed by algorithms
+//! which work in continuous space.
+//!
+//! All geometry is returned as points in the map's parent frame.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::collections::{HashMap, HashSet};
+
+use nalgebra::{Point2, Vector2};
+use ndarray::Array2;
+
+use crate::{map_metadata::CellMapMetadata, CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A polygon with optional holes, produced by [`CellMap::region_boundaries()`].
+///
+/// Rings are implicitly closed, so the first point isn't repeated at the end of each ring.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    /// The outer boundary of the polygon, anticlockwise in the map frame.
+    pub exterior: Vec<Point2<f64>>,
+
+    /// The boundaries of any holes in the polygon, clockwise in the map frame.
+    pub holes: Vec<Vec<Point2<f64>>>,
+}
+
+/// A corner between cells, where `(x, y)` is the lower left corner of the cell with index
+/// `(x, y)`.
+type Corner = (usize, usize);
+
+/// Identifies the edge between two neighbouring cell centres, given by the lower of the two cells
+/// and whether the edge runs along `x` (`true`) or `y` (`false`).
+type EdgeKey = (usize, usize, bool);
+
+/// A line segment between two edges of a marching squares grid.
+type Segment = [(EdgeKey, Point2<f64>); 2];
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Extracts the contour lines (iso-lines) of the given layer at each of the given levels,
+    /// using marching squares.
+    ///
+    /// The contours are found between the centres of cells, with the crossing point along each
+    /// edge linearly interpolated between the two cell values. Cells which are `NaN` are treated
+    /// as holes, so contours stop at them. Each contour is a list of points in the parent frame,
+    /// and closed contours have the same first and last point. The contours of each level are
+    /// returned in turn, in the same order as `levels`.
+    pub fn contours(&self, layer: L, levels: &[f64]) -> Vec<Vec<Point2<f64>>> {
+        let data = &self[layer];
+
+        levels
+            .iter()
+            .flat_map(|&level| {
+                join_segments(marching_squares(data, level))
+                    .into_iter()
+                    .map(|line| {
+                        line.iter()
+                            .map(|p| grid_to_parent(&self.metadata, p))
+                            .collect()
+                    })
+            })
+            .collect()
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+{
+    /// Traces the boundaries of the regions of cells in the given layer for which `predicate`
+    /// returns `true`, for example to convert obstacle cells into polygons.
+    ///
+    /// Boundaries follow the edges of cells, and cells are only part of the same region if they
+    /// share an edge, so cells which only touch at a corner produce separate polygons. Regions
+    /// which enclose cells not matching the predicate have those cells as holes. All points are
+    /// in the parent frame, with collinear points removed.
+    pub fn region_boundaries<F>(&self, layer: L, predicate: F) -> Vec<Polygon>
+    where
+        F: Fn(&T) -> bool,
+    {
+        let data = &self[layer];
+        let (rows, cols) = data.dim();
+        let in_region = |x: isize, y: isize| {
+            x >= 0
+                && y >= 0
+                && (x as usize) < cols
+                && (y as usize) < rows
+                && predicate(&data[(y as usize, x as usize)])
+        };
+
+        // Collect the directed edges between region cells and other cells, such that the region is
+        // always on the left of the edge
+        let mut edges = Vec::new();
+        for y in 0..rows {
+            for x in 0..cols {
+                let (xi, yi) = (x as isize, y as isize);
+                if !in_region(xi, yi) {
+                    continue;
+                }
+                if !in_region(xi, yi - 1) {
+                    edges.push(((x, y), (x + 1, y)));
+                }
+                if !in_region(xi + 1, yi) {
+                    edges.push(((x + 1, y), (x + 1, y + 1)));
+                }
+                if !in_region(xi, yi + 1) {
+                    edges.push(((x + 1, y + 1), (x, y + 1)));
+                }
+                if !in_region(xi - 1, yi) {
+                    edges.push(((x, y + 1), (x, y)));
+                }
+            }
+        }
+
+        let rings = trace_rings(&edges);
+
+        // Rings with positive area are exteriors, the rest are holes
+        let (exteriors, holes): (Vec<_>, Vec<_>) =
+            rings.into_iter().partition(|r| signed_area(r) > 0.0);
+
+        let mut polygons: Vec<(Vec<Corner>, Vec<Vec<Corner>>)> =
+            exteriors.into_iter().map(|e| (e, Vec::new())).collect();
+
+        // Each hole belongs to the smallest exterior containing it. The midpoint of a hole's first
+        // edge can't lie on any other ring, so it's used for the containment test.
+        for hole in holes {
+            let (a, b) = (hole[0], hole[1]);
+            let point = ((a.0 + b.0) as f64 / 2.0, (a.1 + b.1) as f64 / 2.0);
+
+            let owner = polygons
+                .iter_mut()
+                .filter(|(exterior, _)| ring_contains(exterior, point))
+                .min_by(|(a, _), (b, _)| signed_area(a).total_cmp(&signed_area(b)));
+
+            if let Some((_, holes)) = owner {
+                holes.push(hole);
+            }
+        }
+
+        let to_parent = |ring: &Vec<Corner>| -> Vec<Point2<f64>> {
+            ring.iter()
+                .map(|&(x, y)| {
+                    self.metadata.to_parent.transform_point(&Point2::new(
+                        (x as isize + self.metadata.cell_bounds.x.0) as f64,
+                        (y as isize + self.metadata.cell_bounds.y.0) as f64,
+                    ))
+                })
+                .collect()
+        };
+
+        polygons
+            .iter()
+            .map(|(exterior, holes)| Polygon {
+                exterior: to_parent(exterior),
+                holes: holes.iter().map(to_parent).collect(),
+            })
+            .collect()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Joins directed edges into closed rings of corners, with collinear corners removed.
+///
+/// Where two rings touch at a corner the ring always turns left, which keeps regions that only
+/// touch diagonally separate.
+fn trace_rings(edges: &[(Corner, Corner)]) -> Vec<Vec<Corner>> {
+    let mut outgoing: HashMap<Corner, Vec<Corner>> = HashMap::new();
+    for &(from, to) in edges {
+        outgoing.entry(from).or_default().push(to);
+    }
+
+    // Returns the edge which follows the given edge in its ring
+    let next = |(from, to): (Corner, Corner)| -> (Corner, Corner) {
+        let ends = &outgoing[&to];
+        if ends.len() == 1 {
+            return (to, ends[0]);
+        }
+
+        let dir = (
+            to.0 as isize - from.0 as isize,
+            to.1 as isize - from.1 as isize,
+        );
+        let left = (-dir.1, dir.0);
+        let end = ends
+            .iter()
+            .find(|&&end| {
+                (
+                    end.0 as isize - to.0 as isize,
+                    end.1 as isize - to.1 as isize,
+                ) == left
+            })
+            .unwrap_or(&ends[0]);
+
+        (to, *end)
+    };
+
+    let mut used = HashSet::new();
+    let mut rings = Vec::new();
+
+    for &start in edges {
+        if used.contains(&start) {
+            continue;
+        }
+
+        let mut ring = Vec::new();
+        let mut edge = start;
+        loop {
+            used.insert(edge);
+            ring.push(edge.0);
+            edge = next(edge);
+            if edge == start {
+                break;
+            }
+        }
+
+        rings.push(remove_collinear(ring));
+    }
+
+    rings
+}
+
+/// Removes corners where the ring continues in a straight line.
+fn remove_collinear(ring: Vec<Corner>) -> Vec<Corner> {
+    let n = ring.len();
+    let dir = |a: Corner, b: Corner| (b.0 as isize - a.0 as isize, b.1 as isize - a.1 as isize);
+
+    (0..n)
+        .filter(|&i| {
+            let (prev, curr, next) = (ring[(i + n - 1) % n], ring[i], ring[(i + 1) % n]);
+            dir(prev, curr) != dir(curr, next)
+        })
+        .map(|i| ring[i])
+        .collect()
+}
+
+/// Returns twice the signed area of a ring, which is positive for anticlockwise rings.
+fn signed_area(ring: &[Corner]) -> f64 {
+    let n = ring.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % n]);
+            a.0 as f64 * b.1 as f64 - b.0 as f64 * a.1 as f64
+        })
+        .sum()
+}
+
+/// Returns whether `point` is inside the ring, using the even-odd rule.
+fn ring_contains(ring: &[Corner], point: (f64, f64)) -> bool {
+    let n = ring.len();
+    let mut inside = false;
+
+    for i in 0..n {
+        let (a, b) = (ring[i], ring[(i + 1) % n]);
+        let (ax, ay, bx, by) = (a.0 as f64, a.1 as f64, b.0 as f64, b.1 as f64);
+
+        if (ay > point.1) != (by > point.1) && point.0 < ax + (point.1 - ay) * (bx - ax) / (by - ay)
+        {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
+
+/// Converts a point in fractional index coordinates, where integer values are cell centres, into
+/// the parent frame.
+fn grid_to_parent(metadata: &CellMapMetadata, point: &Point2<f64>) -> Point2<f64> {
+    let map_point = point
+        + Vector2::new(
+            metadata.cell_bounds.x.0 as f64 + 0.5,
+            metadata.cell_bounds.y.0 as f64 + 0.5,
+        );
+    metadata.to_parent.transform_point(&map_point)
+}
+
+/// Finds the contour segments at `level` in each square between four cell centres. Points are in
+/// fractional index coordinates.
+fn marching_squares(data: &Array2<f64>, level: f64) -> Vec<Segment> {
+    let (rows, cols) = data.dim();
+    let mut segments = Vec::new();
+
+    if rows < 2 || cols < 2 {
+        return segments;
+    }
+
+    for y in 0..rows - 1 {
+        for x in 0..cols - 1 {
+            // Corners in anticlockwise order from the lower left
+            let corners = [
+                data[(y, x)],
+                data[(y, x + 1)],
+                data[(y + 1, x + 1)],
+                data[(y + 1, x)],
+            ];
+
+            if corners.iter().any(|v| v.is_nan()) {
+                continue;
+            }
+
+            let above = corners.map(|v| v >= level);
+
+            // Edges in the same order, where edge i runs from corner i to corner i + 1
+            let edges: [EdgeKey; 4] = [
+                (x, y, true),
+                (x + 1, y, false),
+                (x, y + 1, true),
+                (x, y, false),
+            ];
+            let corner_points = [
+                Point2::new(x as f64, y as f64),
+                Point2::new(x as f64 + 1.0, y as f64),
+                Point2::new(x as f64 + 1.0, y as f64 + 1.0),
+                Point2::new(x as f64, y as f64 + 1.0),
+            ];
+
+            let crossing = |i: usize| -> (EdgeKey, Point2<f64>) {
+                let j = (i + 1) % 4;
+                let t = (level - corners[i]) / (corners[j] - corners[i]);
+                (
+                    edges[i],
+                    corner_points[i] + (corner_points[j] - corner_points[i]) * t,
+                )
+            };
+
+            let crossed: Vec<usize> = (0..4).filter(|&i| above[i] != above[(i + 1) % 4]).collect();
+
+            match crossed.len() {
+                2 => segments.push([crossing(crossed[0]), crossing(crossed[1])]),
+                4 => {
+                    // Saddle point, use the average value of the square to decide which pair of
+                    // opposite corners are connected, and cut off each of the other corners
+                    let centre_above = corners.iter().sum::<f64>() / 4.0 >= level;
+                    for corner in (0..4).filter(|&c| above[c] != centre_above) {
+                        segments.push([crossing((corner + 3) % 4), crossing(corner)]);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    segments
+}
+
+/// Joins segments which share an edge into polylines.
+fn join_segments(segments: Vec<Segment>) -> Vec<Vec<Point2<f64>>> {
+    let mut by_edge: HashMap<EdgeKey, Vec<usize>> = HashMap::new();
+    for (i, segment) in segments.iter().enumerate() {
+        for (key, _) in segment {
+            by_edge.entry(*key).or_default().push(i);
+        }
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut lines = Vec::new();
+
+    // Walks from the given segment, starting at its `start` end, until there are no more
+    // connected segments
+    let walk = |first: usize, start: usize, used: &mut Vec<bool>| {
+        used[first] = true;
+        let mut line = vec![segments[first][start].1, segments[first][1 - start].1];
+        let mut key = segments[first][1 - start].0;
+
+        while let Some(&next) = by_edge[&key].iter().find(|&&s| !used[s]) {
+            used[next] = true;
+            let end = if segments[next][0].0 == key { 1 } else { 0 };
+            line.push(segments[next][end].1);
+            key = segments[next][end].0;
+        }
+
+        line
+    };
+
+    // Open lines first, starting from the end which only belongs to one segment, then closed
+    // loops, which will end on the point they started from
+    for i in 0..segments.len() {
+        if used[i] {
+            continue;
+        }
+        if let Some(start) = (0..2).find(|&e| by_edge[&segments[i][e].0].len() == 1) {
+            lines.push(walk(i, start, &mut used));
+        }
+    }
+    for i in 0..segments.len() {
+        if !used[i] {
+            lines.push(walk(i, 0, &mut used));
+        }
+    }
+
+    lines
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    fn new_map(bounds: Bounds) -> CellMap<TestLayers, f64> {
+        CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: bounds,
+                cell_size: Vector2::new(1.0, 1.0),
+                ..Default::default()
+            },
+            0.0,
+        )
+    }
+
+    #[test]
+    fn contours() {
+        // A ramp along x gives one straight open contour per level
+        let mut map = new_map(Bounds::new((0, 4), (0, 3)).unwrap());
+        for ((_, index), value) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            *value = index.x as f64;
+        }
+
+        let contours = map.contours(TestLayers::Layer0, &[1.5, 2.25, 10.0]);
+        assert_eq!(contours.len(), 2);
+        for (contour, x) in contours.iter().zip([2.0, 2.75]) {
+            assert_eq!(contour.len(), 3);
+            assert!(contour.iter().all(|p| (p.x - x).abs() < 1e-9));
+            let mut ys: Vec<f64> = contour.iter().map(|p| p.y).collect();
+            ys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(ys, vec![0.5, 1.5, 2.5]);
+        }
+
+        // A single peak gives a closed diamond around its centre at (0.5, 0.5) in the parent frame
+        let mut map = new_map(Bounds::new((-1, 2), (-1, 2)).unwrap());
+        map[(TestLayers::Layer0, Point2::new(1, 1))] = 1.0;
+
+        let contours = map.contours(TestLayers::Layer0, &[0.5]);
+        assert_eq!(contours.len(), 1);
+        assert_eq!(contours[0].len(), 5);
+        assert_eq!(contours[0].first(), contours[0].last());
+        for p in &contours[0][..4] {
+            assert!((((p.x - 0.5).abs() + (p.y - 0.5).abs()) - 0.5).abs() < 1e-9);
+        }
+
+        // NaN cells cut the contour
+        map[(TestLayers::Layer0, Point2::new(0, 0))] = f64::NAN;
+        let contours = map.contours(TestLayers::Layer0, &[0.5]);
+        assert_eq!(contours.len(), 1);
+        assert_eq!(contours[0].len(), 4);
+        assert_ne!(contours[0].first(), contours[0].last());
+    }
+
+    #[test]
+    fn region_boundaries() {
+        let mut map = new_map(Bounds::new((-2, 5), (0, 5)).unwrap());
+
+        // A 3x3 ring with a hole in the middle, a single cell, and two cells touching diagonally
+        for x in 0..3 {
+            for y in 0..3 {
+                if (x, y) != (1, 1) {
+                    map[(TestLayers::Layer0, Point2::new(x, y))] = 1.0;
+                }
+            }
+        }
+        map[(TestLayers::Layer0, Point2::new(6, 0))] = 1.0;
+        map[(TestLayers::Layer0, Point2::new(4, 3))] = 1.0;
+        map[(TestLayers::Layer0, Point2::new(5, 4))] = 1.0;
+
+        let polygons = map.region_boundaries(TestLayers::Layer0, |&v| v > 0.5);
+        assert_eq!(polygons.len(), 4);
+
+        let square = |x: f64, y: f64, w: f64| {
+            vec![
+                Point2::new(x, y),
+                Point2::new(x + w, y),
+                Point2::new(x + w, y + w),
+                Point2::new(x, y + w),
+            ]
+        };
+
+        // The map's bounds start at x = -2, so cell (0, 0) is at (-2, 0) in the parent frame
+        assert_eq!(polygons[0].exterior, square(-2.0, 0.0, 3.0));
+        assert_eq!(polygons[0].holes.len(), 1);
+
+        // Holes run clockwise, so compare against the reversed square
+        let mut hole = square(-1.0, 1.0, 1.0);
+        hole.reverse();
+        let start = hole
+            .iter()
+            .position(|p| *p == polygons[0].holes[0][0])
+            .unwrap();
+        hole.rotate_left(start);
+        assert_eq!(polygons[0].holes[0], hole);
+
+        let exteriors: Vec<_> = polygons[1..].iter().map(|p| p.exterior.clone()).collect();
+        assert!(exteriors.contains(&square(4.0, 0.0, 1.0)));
+        assert!(exteriors.contains(&square(2.0, 3.0, 1.0)));
+        assert!(exteriors.contains(&square(3.0, 4.0, 1.0)));
+        assert!(polygons[1..].iter().all(|p| p.holes.is_empty()));
+    }
+}
@@ -0,0 +1,626 @@
+//! Provides the [`CellMapView`] type, a read-only map which borrows layers from an existing
+//! [`CellMap`] under a different layer type, and the [`CellMapViewMut`] type, a mutable view of a
+//! region of a map.
+//!
+//! This allows different parts of an application to use their own layer enums without copying
+//! data. For example a planner can define a layer type containing only the layers it needs, and
+//! view the mapper's map through it:
+//!
+//! ```
+//! use cell_map::{Bounds, CellMap, CellMapParams, Layer};
+//! use nalgebra::Point2;
+//!
+//! #[derive(Layer, Clone, Debug)]
+//! enum MapperLayer {
+//!     Height,
+//!     Roughness,
+//!     Visits,
+//! }
+//!
+//! #[derive(Layer, Clone, Debug)]
+//! enum PlannerLayer {
+//!     Height,
+//!     Cost,
+//! }
+//!
+//! let map = CellMap::<MapperLayer, f64>::new_from_elem(
+//!     CellMapParams {
+//!         cell_bounds: Bounds::new((0, 5), (0, 5)).unwrap(),
+//!         ..Default::default()
+//!     },
+//!     1.0,
+//! );
+//!
+//! let view = map.view_as(|layer: &PlannerLayer| match layer {
+//!     PlannerLayer::Height => MapperLayer::Height,
+//!     PlannerLayer::Cost => MapperLayer::Roughness,
+//! });
+//!
+//! assert_eq!(view.get(PlannerLayer::Cost, Point2::new(1, 2)), Some(&1.0));
+//! ```
+//!
+//! Mutable views of separate regions of a map can be created with [`CellMap::split_regions_mut()`],
+//! which allows different threads to update different parts of the same map at once. With the
+//! `parallel` feature, [`CellMap::par_apply_tiles()`] does this for a grid of tiles covering the
+//! map using the `rayon` thread pool.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{
+    marker::PhantomData,
+    ops::{Index, IndexMut},
+};
+
+use nalgebra::{Affine2, Point2, Vector2};
+#[cfg(feature = "parallel")]
+use ndarray::parallel::prelude::*;
+use ndarray::{s, ArrayView2, ArrayViewMut2, IndexLonger};
+
+use crate::{
+    cell_map::Bounds,
+    extensions::Point2Ext,
+    iterators::{
+        layerers::Many,
+        slicers::{Cells, Line, Windows},
+        CellMapIter, CellMapIterMut, Layers, LayersMut,
+    },
+    map_metadata::CellMapMetadata,
+    CellMap, CellMapParams, Error, Layer,
+};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A read-only [`CellMap`] with layer type `L`, whose layers are borrowed from another map, see
+/// [`CellMap::view_as()`].
+///
+/// [`CellMapView`] provides the same read-only accessors and iterators as [`CellMap`]. If the
+/// view needs to be modified it can be copied into a new map with [`CellMapView::to_cell_map()`].
+#[derive(Debug, Clone)]
+pub struct CellMapView<'m, L, T>
+where
+    L: Layer,
+{
+    layers: Vec<ArrayView2<'m, T>>,
+    metadata: CellMapMetadata,
+    params: CellMapParams,
+    layer_type: PhantomData<L>,
+}
+
+/// A mutable view of a region of a [`CellMap`], see [`CellMap::split_regions_mut()`].
+///
+/// The view behaves like a map covering only its region, with the same cell size and position in
+/// the parent frame as the map it borrows from. Cell indices are relative to the region, so index
+/// `(0, 0)` is the first cell of the region, not of the whole map.
+#[derive(Debug)]
+pub struct CellMapViewMut<'m, L, T>
+where
+    L: Layer,
+{
+    layers: Vec<ArrayViewMut2<'m, T>>,
+    metadata: CellMapMetadata,
+    params: CellMapParams,
+    layer_type: PhantomData<L>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+{
+    /// Returns a view of this map with the layer type `V`, where each layer of the view borrows
+    /// the layer of this map given by `mapping`.
+    ///
+    /// Several layers of the view may map to the same layer of this map, and layers of this map
+    /// which aren't mapped to are not visible through the view.
+    pub fn view_as<V, F>(&self, mapping: F) -> CellMapView<'_, V, T>
+    where
+        V: Layer,
+        F: Fn(&V) -> L,
+    {
+        CellMapView {
+            layers: V::all()
+                .iter()
+                .map(|layer| self.data[mapping(layer).to_index()].view())
+                .collect(),
+            metadata: self.metadata,
+            params: self.params,
+            layer_type: PhantomData,
+        }
+    }
+
+    /// Splits the map into mutable views of each of the given regions, which must be valid, lie
+    /// entirely inside the map, and not overlap each other.
+    ///
+    /// Since the views don't overlap they can be sent to, and modified by, different threads at
+    /// the same time. Views are returned in the same order as `regions`.
+    pub fn split_regions_mut(
+        &mut self,
+        regions: &[Bounds],
+    ) -> Result<Vec<CellMapViewMut<'_, L, T>>, Error> {
+        let map_bounds = self.metadata.cell_bounds;
+
+        for (i, region) in regions.iter().enumerate() {
+            if !region.is_valid() {
+                return Err(Error::InvalidBounds(*region));
+            }
+
+            if map_bounds.intersect(region) != Some(*region) {
+                return Err(Error::BoundsOutsideMap(*region, map_bounds));
+            }
+
+            if let Some(other) = regions[..i].iter().find(|other| {
+                other.intersect(region).is_some_and(|overlap| {
+                    overlap.get_num_cells().x * overlap.get_num_cells().y > 0
+                })
+            }) {
+                return Err(Error::OverlappingRegions(*other, *region));
+            }
+        }
+
+        Ok(self.split_regions_mut_unchecked(regions))
+    }
+
+    /// Splits the map into mutable views of each of the given regions without checking them.
+    ///
+    /// The regions must be valid, lie entirely inside the map, and not overlap each other, which
+    /// is up to the caller to ensure.
+    fn split_regions_mut_unchecked(&mut self, regions: &[Bounds]) -> Vec<CellMapViewMut<'_, L, T>> {
+        let min = self.metadata.cell_bounds.as_corners().0;
+
+        let mut views: Vec<_> = regions
+            .iter()
+            .map(|region| {
+                let params = CellMapParams {
+                    cell_bounds: *region,
+                    ..self.params
+                };
+
+                CellMapViewMut {
+                    layers: Vec::with_capacity(L::NUM_LAYERS),
+                    metadata: params.into(),
+                    params,
+                    layer_type: PhantomData,
+                }
+            })
+            .collect();
+
+        for layer in self.data.iter_mut() {
+            let raw = layer.raw_view_mut();
+
+            for (view, region) in views.iter_mut().zip(regions) {
+                let region = raw.slice_move(s![
+                    region.y.0 - min.y..region.y.1 - min.y,
+                    region.x.0 - min.x..region.x.1 - min.x
+                ]);
+
+                // Safety: the caller ensures the regions don't overlap, so no two views can alias
+                // the same cell, and each view borrows from `self` for its whole lifetime.
+                view.layers.push(unsafe { region.deref_into_view_mut() });
+            }
+        }
+
+        views
+    }
+}
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+{
+    /// Returns the regions of the tiles covering the map, each `tile_size` cells in size except
+    /// those at the upper x and y edges, which are cut short by the edge of the map.
+    ///
+    /// Tiles are in row-major order, and don't overlap, so can be passed to
+    /// [`CellMap::split_regions_mut()`]. Zero-sized tiles are treated as one cell in size.
+    pub fn tile_regions(&self, tile_size: Vector2<usize>) -> Vec<Bounds> {
+        let bounds = self.metadata.cell_bounds;
+        let tile_size = tile_size.map(|v| v.max(1) as isize);
+
+        let starts = |(min, max): (isize, isize), step: isize| {
+            (min..max)
+                .step_by(step as usize)
+                .map(move |start| (start, (start + step).min(max)))
+        };
+
+        starts(bounds.y, tile_size.y)
+            .flat_map(|y| starts(bounds.x, tile_size.x).map(move |x| Bounds { x, y }))
+            .collect()
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<L, T> CellMap<L, T>
+where
+    L: Layer + Send,
+    T: Send,
+{
+    /// Splits the map into tiles of `tile_size` cells, as given by [`CellMap::tile_regions()`],
+    /// and calls `func` with a mutable view of each tile on the `rayon` thread pool.
+    ///
+    /// This is the building block for parallel operations whose result in each cell only depends
+    /// on the cells in the same tile. The order in which tiles are processed is unspecified.
+    pub fn par_apply_tiles<F>(&mut self, tile_size: Vector2<usize>, func: F)
+    where
+        F: Fn(CellMapViewMut<'_, L, T>) + Send + Sync,
+    {
+        trace_span!(
+            "par_apply_tiles",
+            num_cells = self.num_cells().iter().product::<usize>()
+        );
+
+        // Tile regions are always inside the map and never overlap, so don't need checking
+        let regions = self.tile_regions(tile_size);
+        self.split_regions_mut_unchecked(&regions)
+            .into_par_iter()
+            .for_each(func);
+    }
+}
+
+impl<'m, L, T> CellMapView<'m, L, T>
+where
+    L: Layer,
+{
+    /// Returns the size of the cells in the map.
+    pub fn cell_size(&self) -> Vector2<f64> {
+        self.metadata.cell_size
+    }
+
+    /// Returns the number of cells in each direction of the map.
+    pub fn num_cells(&self) -> Vector2<usize> {
+        self.metadata.num_cells
+    }
+
+    /// Returns the bounds of this map
+    pub fn cell_bounds(&self) -> Bounds {
+        self.metadata.cell_bounds
+    }
+
+    /// Returns the parameters used to build this map.
+    pub fn params(&self) -> CellMapParams {
+        self.params
+    }
+
+    /// Gets the [`nalgebra::Affine2<f64>`] transformation between the map frame and the parent
+    /// frame.
+    pub fn to_parent(&self) -> Affine2<f64> {
+        self.metadata.to_parent
+    }
+
+    /// Returns a view of the given layer.
+    pub fn layer(&self, layer: L) -> ArrayView2<'m, T> {
+        self.layers[layer.to_index()]
+    }
+
+    /// Get a reference to the value at the given layer and index. Returns `None` if the index is
+    /// outside the bounds of the map.
+    pub fn get(&self, layer: L, index: Point2<usize>) -> Option<&'m T> {
+        IndexLonger::get(&self.layer(layer), index.as_array2_index())
+    }
+
+    /// Returns the position in the parent frame of the centre of the given cell index.
+    ///
+    /// Returns `None` if the given `index` is not inside the map.
+    pub fn position(&self, index: Point2<usize>) -> Option<Point2<f64>> {
+        self.metadata.position(index)
+    }
+
+    /// Get the cell index of the given poisition.
+    ///
+    /// Returns `None` if the given `position` is not inside the map.
+    pub fn index(&self, position: Point2<f64>) -> Option<Point2<usize>> {
+        self.metadata.index(position)
+    }
+
+    /// Returns an iterator over each cell in all layers of the map.
+    pub fn iter(&self) -> CellMapIter<'_, L, T, Many<L>, Cells> {
+        CellMapIter::<'_, L, T, Many<L>, Cells>::new_cells(
+            Layers::views(&self.layers),
+            self.metadata,
+        )
+    }
+
+    /// Returns an iterator over windows of cells in the map.
+    ///
+    /// The `semi_width` is half the size of the window in the x and y axes, not including
+    /// the central cell. E.g. to have a window which is in total 5x5, the `semi_window_size` should
+    /// be `Vector2::new(2, 2)`.
+    pub fn window_iter(
+        &self,
+        semi_width: Vector2<usize>,
+    ) -> Result<CellMapIter<'_, L, T, Many<L>, Windows>, Error> {
+        CellMapIter::<'_, L, T, Many<L>, Windows>::new_windows(
+            Layers::views(&self.layers),
+            self.metadata,
+            semi_width,
+        )
+    }
+
+    /// Returns an iterator over cells along the line joining `start_position` and
+    /// `end_position`, which are expressed as positions in the map's parent frame.
+    pub fn line_iter(
+        &self,
+        start_position: Point2<f64>,
+        end_position: Point2<f64>,
+    ) -> Result<CellMapIter<'_, L, T, Many<L>, Line>, Error> {
+        CellMapIter::<'_, L, T, Many<L>, Line>::new_line(
+            Layers::views(&self.layers),
+            self.metadata,
+            start_position,
+            end_position,
+        )
+    }
+}
+
+impl<'m, L, T> CellMapView<'m, L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    /// Copies the viewed layers into a new [`CellMap`].
+    pub fn to_cell_map(&self) -> CellMap<L, T> {
+        // Views always have one layer of the map's shape for each variant of L
+        CellMap::new_from_data_unchecked(
+            self.params,
+            self.layers.iter().map(|layer| layer.to_owned()).collect(),
+        )
+    }
+}
+
+impl<'m, L, T> CellMapViewMut<'m, L, T>
+where
+    L: Layer,
+{
+    /// Returns the size of the cells in the view.
+    pub fn cell_size(&self) -> Vector2<f64> {
+        self.metadata.cell_size
+    }
+
+    /// Returns the number of cells in each direction of the view.
+    pub fn num_cells(&self) -> Vector2<usize> {
+        self.metadata.num_cells
+    }
+
+    /// Returns the bounds of the view's region in the map it borrows from.
+    pub fn cell_bounds(&self) -> Bounds {
+        self.metadata.cell_bounds
+    }
+
+    /// Returns the parameters of the view's region.
+    pub fn params(&self) -> CellMapParams {
+        self.params
+    }
+
+    /// Returns a view of the given layer.
+    pub fn layer(&self, layer: L) -> ArrayView2<'_, T> {
+        self.layers[layer.to_index()].view()
+    }
+
+    /// Returns a mutable view of the given layer.
+    pub fn layer_mut(&mut self, layer: L) -> ArrayViewMut2<'_, T> {
+        self.layers[layer.to_index()].view_mut()
+    }
+
+    /// Get a reference to the value at the given layer and index. Returns `None` if the index is
+    /// outside the view.
+    pub fn get(&self, layer: L, index: Point2<usize>) -> Option<&T> {
+        ndarray::ArrayBase::get(&self.layers[layer.to_index()], index.as_array2_index())
+    }
+
+    /// Get a mutable reference to the value at the given layer and index. Returns `None` if the
+    /// index is outside the view.
+    pub fn get_mut(&mut self, layer: L, index: Point2<usize>) -> Option<&mut T> {
+        self.layers[layer.to_index()].get_mut(index.as_array2_index())
+    }
+
+    /// Returns the position in the parent frame of the centre of the given cell index.
+    ///
+    /// Returns `None` if the given `index` is not inside the view.
+    pub fn position(&self, index: Point2<usize>) -> Option<Point2<f64>> {
+        self.metadata.position(index)
+    }
+
+    /// Get the cell index of the given poisition.
+    ///
+    /// Returns `None` if the given `position` is not inside the view.
+    pub fn index(&self, position: Point2<f64>) -> Option<Point2<usize>> {
+        self.metadata.index(position)
+    }
+
+    /// Returns an iterator over each cell in all layers of the view.
+    pub fn iter(&self) -> CellMapIter<'_, L, T, Many<L>, Cells> {
+        CellMapIter::<'_, L, T, Many<L>, Cells>::new_cells(
+            Layers::views_mut(&self.layers),
+            self.metadata,
+        )
+    }
+
+    /// Returns a mutable iterator over each cell in all layers of the view.
+    pub fn iter_mut(&mut self) -> CellMapIterMut<'_, L, T, Many<L>, Cells> {
+        let metadata = self.metadata;
+        CellMapIterMut::<'_, L, T, Many<L>, Cells>::new_cells(
+            LayersMut::Views(
+                self.layers
+                    .iter_mut()
+                    .map(|layer| layer.view_mut())
+                    .collect(),
+            ),
+            metadata,
+        )
+    }
+}
+
+impl<'m, L, T> Index<(L, Point2<usize>)> for CellMapViewMut<'m, L, T>
+where
+    L: Layer,
+{
+    type Output = T;
+
+    fn index(&self, (layer, index): (L, Point2<usize>)) -> &Self::Output {
+        &self.layers[layer.to_index()][index.as_array2_index()]
+    }
+}
+
+impl<'m, L, T> IndexMut<(L, Point2<usize>)> for CellMapViewMut<'m, L, T>
+where
+    L: Layer,
+{
+    fn index_mut(&mut self, (layer, index): (L, Point2<usize>)) -> &mut Self::Output {
+        &mut self.layers[layer.to_index()][index.as_array2_index()]
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestLayers;
+
+    #[test]
+    fn view_as() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-1, 3), (2, 5)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+        map[(TestLayers::Layer2, Point2::new(3, 1))] = 4.0;
+
+        // Swap the first and last layers, and alias the middle one to the last
+        let mapping = |layer: &TestLayers| match layer {
+            TestLayers::Layer0 => TestLayers::Layer2,
+            TestLayers::Layer1 => TestLayers::Layer2,
+            TestLayers::Layer2 => TestLayers::Layer0,
+        };
+        let view = map.view_as(mapping);
+
+        assert_eq!(view.cell_bounds(), map.cell_bounds());
+        assert_eq!(view.get(TestLayers::Layer0, Point2::new(3, 1)), Some(&4.0));
+        assert_eq!(view.get(TestLayers::Layer1, Point2::new(3, 1)), Some(&4.0));
+        assert_eq!(view.get(TestLayers::Layer2, Point2::new(3, 1)), Some(&0.0));
+        assert_eq!(view.get(TestLayers::Layer0, Point2::new(4, 1)), None);
+        assert_eq!(
+            view.iter().layer(TestLayers::Layer1).sum::<f64>(),
+            map.iter().layer(TestLayers::Layer2).sum::<f64>()
+        );
+
+        let copy = view.to_cell_map();
+        assert_eq!(copy[(TestLayers::Layer1, Point2::new(3, 1))], 4.0);
+        assert_eq!(copy.iter().layer(TestLayers::Layer2).sum::<f64>(), 0.0);
+    }
+
+    #[test]
+    fn split_regions_mut() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 4), (-2, 2)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        let quadrants = [
+            Bounds::new((0, 2), (-2, 0)).unwrap(),
+            Bounds::new((2, 4), (-2, 0)).unwrap(),
+            Bounds::new((0, 2), (0, 2)).unwrap(),
+            Bounds::new((2, 4), (0, 2)).unwrap(),
+        ];
+
+        let views = map.split_regions_mut(&quadrants).unwrap();
+        std::thread::scope(|scope| {
+            for (i, mut view) in views.into_iter().enumerate() {
+                scope.spawn(move || {
+                    view.iter_mut()
+                        .layer(TestLayers::Layer1)
+                        .for_each(|v| *v = i as f64);
+                    assert_eq!(
+                        view.iter().layer(TestLayers::Layer1).sum::<f64>(),
+                        4.0 * i as f64
+                    );
+                    view[(TestLayers::Layer0, Point2::new(1, 1))] = 10.0;
+                });
+            }
+        });
+
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(3, 0))], 1.0);
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(1, 3))], 2.0);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(3, 3))], 10.0);
+        assert_eq!(map.iter().layer(TestLayers::Layer0).sum::<f64>(), 40.0);
+        assert_eq!(map.iter().layer(TestLayers::Layer1).sum::<f64>(), 24.0);
+
+        // Regions which touch are fine, but overlapping or outside the map are not
+        let views = map.split_regions_mut(&quadrants[..2]).unwrap();
+        assert_eq!(
+            views[1].position(Point2::new(0, 0)),
+            Some(Point2::new(2.5, -1.5))
+        );
+        assert!(matches!(
+            map.split_regions_mut(&[quadrants[0], Bounds::new((1, 3), (-1, 1)).unwrap()]),
+            Err(Error::OverlappingRegions(_, _))
+        ));
+        assert!(map
+            .split_regions_mut(&[Bounds::new((3, 5), (0, 1)).unwrap()])
+            .unwrap_err()
+            .is_out_of_bounds());
+    }
+
+    #[test]
+    fn tiles() {
+        let map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-1, 4), (0, 3)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        assert_eq!(
+            map.tile_regions(Vector2::new(2, 2)),
+            vec![
+                Bounds::new((-1, 1), (0, 2)).unwrap(),
+                Bounds::new((1, 3), (0, 2)).unwrap(),
+                Bounds::new((3, 4), (0, 2)).unwrap(),
+                Bounds::new((-1, 1), (2, 3)).unwrap(),
+                Bounds::new((1, 3), (2, 3)).unwrap(),
+                Bounds::new((3, 4), (2, 3)).unwrap(),
+            ]
+        );
+        assert_eq!(map.tile_regions(Vector2::new(0, 10)).len(), 5);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn par_apply_tiles() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-1, 4), (0, 3)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // Each cell is set to the number of cells in its tile
+        map.par_apply_tiles(Vector2::new(2, 2), |mut tile| {
+            let num_cells = tile.num_cells();
+            tile.iter_mut()
+                .layer(TestLayers::Layer0)
+                .for_each(|v| *v = (num_cells.x * num_cells.y) as f64);
+        });
+
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(0, 0))], 4.0);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(4, 1))], 2.0);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(2, 2))], 2.0);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(4, 2))], 1.0);
+        assert_eq!(map.iter().layer(TestLayers::Layer0).sum::<f64>(), 45.0);
+    }
+}
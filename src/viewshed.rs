@@ -0,0 +1,177 @@
+//! Provides viewshed analysis, which finds the cells of a height layer which are visible from an
+//! observer, for example for comms planning or selecting science targets.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::Point2;
+use ndarray::Array2;
+
+use crate::{CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Computes which cells of the given height layer are visible from an observer at
+    /// `observer_position` in the parent frame, whose eye is `observer_height` above the terrain.
+    ///
+    /// A cell is visible if the line of sight from the observer to the top of the cell isn't
+    /// blocked by any cell between them, where the line of sight is traced through the centres of
+    /// the cells using Bresenham's algorithm. `NaN` cells never block the line of sight and are
+    /// never visible. If the observer's own cell is `NaN`, `observer_height` is used as the
+    /// absolute height of the observer's eye.
+    ///
+    /// The returned array has the same shape as the layer, so is indexed by `(y, x)`.
+    pub fn viewshed(
+        &self,
+        height_layer: L,
+        observer_position: Point2<f64>,
+        observer_height: f64,
+    ) -> Result<Array2<bool>, Error> {
+        let observer = self
+            .index(observer_position)
+            .ok_or_else(|| Error::PositionOutsideMap("Observer".into(), observer_position))?;
+
+        let data = &self[height_layer];
+        let ground = data[(observer.y, observer.x)];
+        let eye = if ground.is_nan() {
+            observer_height
+        } else {
+            ground + observer_height
+        };
+        let eye_position = self.position_unchecked(observer);
+
+        // The gradient of the line of sight from the observer to the top of the given cell
+        let gradient = |x: usize, y: usize| {
+            let height = data[(y, x)];
+            let distance = (self.position_unchecked(Point2::new(x, y)) - eye_position).norm();
+            (height - eye) / distance
+        };
+
+        let mut visible = Array2::from_elem(data.dim(), false);
+
+        for ((y, x), &height) in data.indexed_iter() {
+            if height.is_nan() {
+                continue;
+            }
+
+            if (x, y) == (observer.x, observer.y) {
+                visible[(y, x)] = true;
+                continue;
+            }
+
+            // The target is visible if its gradient is at least as steep as every cell in between
+            let target = gradient(x, y);
+            visible[(y, x)] = bresenham((observer.x, observer.y), (x, y))
+                .into_iter()
+                .skip(1)
+                .filter(|&c| c != (x, y))
+                .filter(|&(cx, cy)| !data[(cy, cx)].is_nan())
+                .all(|(cx, cy)| gradient(cx, cy) <= target);
+        }
+
+        Ok(visible)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Returns the cells on the line between `start` and `end` inclusive.
+fn bresenham(start: (usize, usize), end: (usize, usize)) -> Vec<(usize, usize)> {
+    let (mut x, mut y) = (start.0 as isize, start.1 as isize);
+    let (x1, y1) = (end.0 as isize, end.1 as isize);
+    let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+    let (sx, sy) = ((x1 - x).signum(), (y1 - y).signum());
+    let mut err = dx + dy;
+    let mut cells = Vec::with_capacity((dx - dy) as usize + 1);
+
+    loop {
+        cells.push((x as usize, y as usize));
+        if (x, y) == (x1, y1) {
+            break;
+        }
+
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+
+    cells
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn viewshed() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 7), (0, 3)).unwrap(),
+                cell_size: Vector2::new(1.0, 1.0),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        // A wall across x = 3 in the bottom row, and a NaN cell at the top
+        map[(TestLayers::Layer0, Point2::new(3, 0))] = 2.0;
+        map[(TestLayers::Layer0, Point2::new(3, 2))] = f64::NAN;
+
+        let visible = map
+            .viewshed(TestLayers::Layer0, Point2::new(0.5, 0.5), 1.0)
+            .unwrap();
+
+        // The wall is visible but hides the cells behind it, while the NaN cell doesn't block
+        // anything
+        assert!(visible[(0, 0)]);
+        assert!(visible[(0, 3)]);
+        assert!(!visible[(0, 4)]);
+        assert!(!visible[(0, 6)]);
+        assert!(visible[(1, 6)]);
+        assert!(!visible[(2, 3)]);
+        assert!(visible[(2, 6)]);
+
+        // From higher up the observer can see over the wall
+        let visible = map
+            .viewshed(TestLayers::Layer0, Point2::new(0.5, 0.5), 10.0)
+            .unwrap();
+        assert!(visible[(0, 6)]);
+
+        assert!(matches!(
+            map.viewshed(TestLayers::Layer0, Point2::new(-1.0, 0.5), 1.0),
+            Err(Error::PositionOutsideMap(_, _))
+        ));
+    }
+
+    #[test]
+    fn bresenham_lines() {
+        assert_eq!(
+            bresenham((0, 0), (3, 1)),
+            vec![(0, 0), (1, 0), (2, 1), (3, 1)]
+        );
+        assert_eq!(bresenham((2, 3), (2, 1)), vec![(2, 3), (2, 2), (2, 1)]);
+        assert_eq!(bresenham((1, 1), (1, 1)), vec![(1, 1)]);
+    }
+}
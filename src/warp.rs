@@ -0,0 +1,247 @@
+//! Provides warping of layers through a deformation field, for applying corrections such as a
+//! SLAM loop closure to a map which has already been built, rather than rebuilding it from
+//! scratch.
+//!
+//! Warping is a backward mapping: the deformation is given each position of the corrected map and
+//! returns the position in the current map which should be moved there. For a pose-graph update
+//! this is usually found by interpolating the correction of the nearest poses, inverted.
+//!
+//! [`CellMap::resample_into()`] re-expresses a whole map in a different frame and set of bounds,
+//! such as a robot's local frame, interpolating between the cells of the original map.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+use ndarray::Array2;
+
+use crate::{CellMap, CellMapParams, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> CellMap<L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    /// Resamples each of `layers` through `deformation`, which maps each parent-frame position in
+    /// the corrected map to the parent-frame position in the current map it takes its value from.
+    ///
+    /// Each cell takes the value of the cell containing the deformed position of its centre
+    /// (nearest-neighbour sampling), so values are never mixed. Cells whose deformed position is
+    /// outside the map are set to `fill`. Layers not in `layers` are unchanged.
+    pub fn warp<F>(&mut self, layers: &[L], deformation: F, fill: T)
+    where
+        F: Fn(Point2<f64>) -> Point2<f64>,
+    {
+        trace_span!("warp", num_layers = layers.len());
+
+        // The source index of each cell is the same for every layer
+        let shape = self.cell_bounds().get_shape();
+        let sources = Array2::from_shape_fn(shape, |(y, x)| {
+            self.index(deformation(self.position_unchecked(Point2::new(x, y))))
+        });
+
+        for layer in layers {
+            let data = &self[layer.clone()];
+            let warped = sources.map(|source| match source {
+                Some(i) => data[(i.y, i.x)].clone(),
+                None => fill.clone(),
+            });
+
+            self[layer.clone()] = warped;
+        }
+    }
+}
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Returns a new map with the given parameters, each cell of which is bilinearly interpolated
+    /// from this map at the position of the cell's centre.
+    ///
+    /// This is intended for re-expressing a map in a moving frame, such as a global map in the
+    /// robot's local frame, each cycle. The interpolation weights are calculated once and shared
+    /// by every layer.
+    ///
+    /// Cells whose centre is inside this map always get a value, using the nearest edge cells in
+    /// place of cells outside the map, as with [`OutOfBounds::Clamp`]. Cells whose centre is
+    /// outside this map are set to their layer's default value, see [`CellMap::layer_default()`].
+    ///
+    /// Source cells with zero weight are skipped, so a `NaN` cell only affects the target cells
+    /// which are actually interpolated from it. Those are `NaN`, as unknown cells can't be
+    /// interpolated.
+    ///
+    /// [`OutOfBounds::Clamp`]: crate::out_of_bounds::OutOfBounds::Clamp
+    pub fn resample_into(&self, target_params: CellMapParams) -> CellMap<L, f64> {
+        trace_span!("resample_into");
+
+        let mut target = CellMap::new(target_params);
+        let bounds = self.cell_bounds();
+        let max = Point2::new(bounds.x.1 - 1, bounds.y.1 - 1);
+
+        // The transformation from the target map frame to this map frame, in which cell centres
+        // are at half-integer coordinates
+        let to_source = self.to_parent().inverse() * target.to_parent();
+
+        // For each target cell, the four source cells around it and their weights
+        let shape = target.cell_bounds().get_shape();
+        let samples = Array2::from_shape_fn(shape, |(y, x)| {
+            let cell = target.cell_bounds().as_corners().0 + Vector2::new(x as isize, y as isize);
+            let point = to_source.transform_point(&cell.map(|v| v as f64 + 0.5));
+
+            if !bounds.contains(point.map(|v| v.floor() as isize)) {
+                return None;
+            }
+
+            let point = point - Vector2::new(0.5, 0.5);
+            let base = point.map(f64::floor);
+            let frac = point - base;
+            let base = base.map(|v| v as isize);
+
+            let index = |dx: isize, dy: isize| {
+                (
+                    ((base.y + dy).clamp(bounds.y.0, max.y) - bounds.y.0) as usize,
+                    ((base.x + dx).clamp(bounds.x.0, max.x) - bounds.x.0) as usize,
+                )
+            };
+
+            Some([
+                (index(0, 0), (1.0 - frac.x) * (1.0 - frac.y)),
+                (index(1, 0), frac.x * (1.0 - frac.y)),
+                (index(0, 1), (1.0 - frac.x) * frac.y),
+                (index(1, 1), frac.x * frac.y),
+            ])
+        });
+
+        for layer in L::all() {
+            let data = &self[layer.clone()];
+            let fill = self.layer_default(&layer);
+
+            target[layer] = samples.map(|sample| match sample {
+                Some(weights) => weights
+                    .iter()
+                    .filter(|(_, w)| *w != 0.0)
+                    .map(|&(i, w)| data[i] * w)
+                    .sum(),
+                None => fill,
+            });
+        }
+
+        target
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Isometry2, Vector2};
+
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn warp() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-2, 2), (-2, 2)).unwrap(),
+                ..Default::default()
+            },
+            0.0,
+        );
+        for ((_, index), v) in map.iter_mut().indexed() {
+            *v = (index.x + 10 * index.y) as f64;
+        }
+
+        // Shifting the map one cell along x
+        map.warp(
+            &[TestLayers::Layer0],
+            |p| p - Vector2::new(1.0, 0.0),
+            f64::NAN,
+        );
+        assert!(map[(TestLayers::Layer0, Point2::new(0, 2))].is_nan());
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(1, 2))], 20.0);
+        assert_eq!(map[(TestLayers::Layer0, Point2::new(3, 2))], 22.0);
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(3, 2))], 23.0);
+
+        // Rotating by a quarter turn about the centre of the map
+        let rotation = Isometry2::rotation(std::f64::consts::FRAC_PI_2);
+        map.warp(
+            &[TestLayers::Layer1, TestLayers::Layer2],
+            |p| rotation.inverse_transform_point(&p),
+            f64::NAN,
+        );
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(3, 0))], 0.0);
+        assert_eq!(map[(TestLayers::Layer2, Point2::new(3, 3))], 3.0);
+        assert_eq!(map[(TestLayers::Layer1, Point2::new(0, 0))], 30.0);
+    }
+
+    #[test]
+    fn resample_into() {
+        let mut map = CellMap::<TestLayers, f64>::new(CellMapParams {
+            cell_bounds: Bounds::new((0, 4), (0, 4)).unwrap(),
+            ..Default::default()
+        });
+        for ((_, index), v) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            *v = (index.x + 10 * index.y) as f64;
+        }
+
+        // Resampling with the same params copies the map
+        let same = map.resample_into(map.params());
+        assert_eq!(same[TestLayers::Layer0], map[TestLayers::Layer0]);
+
+        // Half-size cells offset by a quarter cell land between the original cells, and cells
+        // outside the original map get the layer default
+        let resampled = map.resample_into(CellMapParams {
+            cell_bounds: Bounds::new((0, 10), (0, 2)).unwrap(),
+            cell_size: Vector2::new(0.5, 0.5),
+            position_in_parent: Vector2::new(0.0, 1.25),
+            ..Default::default()
+        });
+        let row: Vec<_> = resampled[TestLayers::Layer0].row(0).to_vec();
+        assert_f64_iter_eq!(
+            row,
+            [10.0, 10.25, 10.75, 11.25, 11.75, 12.25, 12.75, 13.0, 0.0, 0.0],
+            1e-12
+        );
+        assert_f64_eq!(
+            resampled[(TestLayers::Layer0, Point2::new(3, 1))],
+            16.25,
+            1e-12
+        );
+
+        let outside = map.resample_into(CellMapParams {
+            cell_bounds: Bounds::new((4, 6), (0, 1)).unwrap(),
+            ..Default::default()
+        });
+        assert_eq!(outside[(TestLayers::Layer2, Point2::new(1, 0))], -1.0);
+        assert_eq!(outside[(TestLayers::Layer0, Point2::new(1, 0))], 0.0);
+
+        // Unknown cells don't spread when resampling with the same params, but do spread to the
+        // cells interpolated from them
+        map[(TestLayers::Layer0, Point2::new(2, 2))] = f64::NAN;
+        let same = map.resample_into(map.params());
+        for (a, b) in same[TestLayers::Layer0]
+            .iter()
+            .zip(map[TestLayers::Layer0].iter())
+        {
+            assert!(a == b || (a.is_nan() && b.is_nan()));
+        }
+        let resampled = map.resample_into(CellMapParams {
+            cell_bounds: Bounds::new((0, 4), (0, 4)).unwrap(),
+            position_in_parent: Vector2::new(0.5, 0.0),
+            ..Default::default()
+        });
+        assert!(resampled[(TestLayers::Layer0, Point2::new(1, 2))].is_nan());
+        assert!(resampled[(TestLayers::Layer0, Point2::new(2, 2))].is_nan());
+        assert_eq!(resampled[(TestLayers::Layer0, Point2::new(0, 2))], 20.5);
+        assert_eq!(resampled[(TestLayers::Layer0, Point2::new(1, 1))], 11.5);
+    }
+}
@@ -0,0 +1,240 @@
+//! Provides wavefront propagation with the fast marching method, which computes the time for a
+//! front starting at a set of seed cells to reach every other cell.
+//!
+//! The front moves through each cell at the speed given by a speed layer, so the arrival time is
+//! the solution of the Eikonal equation $|\nabla T| = 1 / F$, where $F$ is the speed. With a
+//! uniform speed of `1.0` the arrival time is the geodesic distance from the nearest seed, going
+//! around impassable cells, which makes it a building block for coverage planning and
+//! reachability analysis as well as navigation.
+//!
+//! Unlike the graph search used by [`CellMap::potential_field()`], fast marching is not restricted
+//! to the 8 grid directions, so its distances are much closer to the true Euclidean distance.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use nalgebra::{Point2, Vector2};
+use ndarray::{Array2, ArrayView2};
+
+use crate::{potential::Entry, CellMap, Error, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// The offsets of the 4 neighbours of a cell, which are the cells used by the upwind update.
+const NEIGHBOURS: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L> CellMap<L, f64>
+where
+    L: Layer,
+{
+    /// Propagates a wavefront from `seeds`, given in the parent frame, through the speeds in
+    /// `speed_layer`, returning the arrival time of the front at every cell.
+    ///
+    /// Speeds are in parent-frame units per unit time. Cells with a speed which is not positive
+    /// or is `NaN` are impassable, and they and any cells the front cannot reach have an infinite
+    /// arrival time. The returned array has the same shape as the map's layers.
+    ///
+    /// Returns [`Error::PositionOutsideMap`] if any seed is outside the map.
+    pub fn propagate(&self, seeds: &[Point2<f64>], speed_layer: L) -> Result<Array2<f64>, Error> {
+        let seeds = seeds
+            .iter()
+            .map(|&s| {
+                self.index(s)
+                    .ok_or_else(|| Error::PositionOutsideMap("seed".into(), s))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(fast_march(
+            self[speed_layer].view(),
+            self.cell_size(),
+            &seeds,
+        ))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Runs the fast marching method over `speed` from the given seed indices.
+fn fast_march(
+    speed: ArrayView2<'_, f64>,
+    cell_size: Vector2<f64>,
+    seeds: &[Point2<usize>],
+) -> Array2<f64> {
+    let (rows, cols) = speed.dim();
+    let passable = |i: (usize, usize)| speed[i] > 0.0;
+
+    let mut time = Array2::from_elem((rows, cols), f64::INFINITY);
+    let mut known = Array2::from_elem((rows, cols), false);
+    let mut queue = BinaryHeap::new();
+
+    for seed in seeds {
+        let index = (seed.y, seed.x);
+        if passable(index) {
+            time[index] = 0.0;
+            queue.push(Reverse(Entry { value: 0.0, index }));
+        }
+    }
+
+    while let Some(Reverse(Entry { value, index })) = queue.pop() {
+        // Skip entries which have already been accepted or were superseded by an earlier arrival
+        if known[index] || value > time[index] {
+            continue;
+        }
+        known[index] = true;
+
+        for (dx, dy) in NEIGHBOURS.iter() {
+            let (y, x) = (index.0 as isize + dy, index.1 as isize + dx);
+            if y < 0 || x < 0 || y >= rows as isize || x >= cols as isize {
+                continue;
+            }
+            let neighbour = (y as usize, x as usize);
+
+            if known[neighbour] || !passable(neighbour) {
+                continue;
+            }
+
+            let next = arrival_time(&time, &known, neighbour, speed[neighbour], cell_size);
+
+            if next < time[neighbour] {
+                time[neighbour] = next;
+                queue.push(Reverse(Entry {
+                    value: next,
+                    index: neighbour,
+                }));
+            }
+        }
+    }
+
+    time
+}
+
+/// Solves the first-order upwind discretisation of the Eikonal equation at `index`, using the
+/// known arrival times of its neighbours.
+fn arrival_time(
+    time: &Array2<f64>,
+    known: &Array2<bool>,
+    index: (usize, usize),
+    speed: f64,
+    cell_size: Vector2<f64>,
+) -> f64 {
+    let (rows, cols) = time.dim();
+    let (y, x) = index;
+
+    // The smallest known arrival time of the neighbours along each axis
+    let known_time = |i: (usize, usize)| {
+        if known[i] {
+            time[i]
+        } else {
+            f64::INFINITY
+        }
+    };
+    let min_x = match (x.checked_sub(1), x + 1 < cols) {
+        (Some(x0), true) => known_time((y, x0)).min(known_time((y, x + 1))),
+        (Some(x0), false) => known_time((y, x0)),
+        (None, true) => known_time((y, x + 1)),
+        (None, false) => f64::INFINITY,
+    };
+    let min_y = match (y.checked_sub(1), y + 1 < rows) {
+        (Some(y0), true) => known_time((y0, x)).min(known_time((y + 1, x))),
+        (Some(y0), false) => known_time((y0, x)),
+        (None, true) => known_time((y + 1, x)),
+        (None, false) => f64::INFINITY,
+    };
+
+    let slowness = 1.0 / speed;
+    let one_sided = (min_x + cell_size.x * slowness).min(min_y + cell_size.y * slowness);
+
+    if !min_x.is_finite() || !min_y.is_finite() {
+        return one_sided;
+    }
+
+    // Solve (t - min_x)^2 / hx^2 + (t - min_y)^2 / hy^2 = slowness^2 for the larger root
+    let (wx, wy) = (cell_size.x.powi(-2), cell_size.y.powi(-2));
+    let a = wx + wy;
+    let b = -2.0 * (wx * min_x + wy * min_y);
+    let c = wx * min_x * min_x + wy * min_y * min_y - slowness * slowness;
+    let discriminant = b * b - 4.0 * a * c;
+
+    if discriminant < 0.0 {
+        return one_sided;
+    }
+
+    let t = (-b + discriminant.sqrt()) / (2.0 * a);
+
+    // The two-sided solution is only valid if the front arrives after both neighbours
+    if t >= min_x.max(min_y) {
+        t
+    } else {
+        one_sided
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    #[test]
+    fn propagate() {
+        let mut map = CellMap::<TestLayers, f64>::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((0, 21), (0, 21)).unwrap(),
+                ..Default::default()
+            },
+            1.0,
+        );
+
+        // With unit speed, arrival times approximate the Euclidean distance from the seed
+        let time = map
+            .propagate(&[Point2::new(10.5, 10.5)], TestLayers::Layer0)
+            .unwrap();
+        assert_eq!(time[[10, 10]], 0.0);
+        assert!((time[[10, 20]] - 10.0).abs() < 1e-9);
+        let diagonal = 10.0 * 2f64.sqrt();
+        assert!((time[[20, 20]] - diagonal).abs() / diagonal < 0.1);
+
+        // Doubling the speed halves the arrival times
+        map[TestLayers::Layer1].fill(2.0);
+        let fast = map
+            .propagate(&[Point2::new(10.5, 10.5)], TestLayers::Layer1)
+            .unwrap();
+        assert!((fast[[20, 20]] - time[[20, 20]] / 2.0).abs() < 1e-9);
+
+        // A wall with a gap at the top makes the front go round it
+        for y in 0..20 {
+            map[(TestLayers::Layer0, Point2::new(5, y))] = 0.0;
+        }
+        map[(TestLayers::Layer0, Point2::new(5, 20))] = f64::NAN;
+        let time = map
+            .propagate(&[Point2::new(0.5, 0.5)], TestLayers::Layer0)
+            .unwrap();
+        assert!(time[[0, 5]].is_infinite());
+        assert!(time[[0, 6]].is_infinite());
+
+        map[(TestLayers::Layer0, Point2::new(5, 20))] = 1.0;
+        let time = map
+            .propagate(&[Point2::new(0.5, 0.5)], TestLayers::Layer0)
+            .unwrap();
+        assert!(time[[0, 6]] > 35.0 && time[[0, 6]].is_finite());
+
+        assert!(matches!(
+            map.propagate(&[Point2::new(-0.5, 0.5)], TestLayers::Layer0),
+            Err(Error::PositionOutsideMap(_, _))
+        ));
+    }
+}
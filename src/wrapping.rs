@@ -0,0 +1,260 @@
+//! Provides the [`WrappingMap`] type, which wraps a [`CellMap`] so that its edges join up into a
+//! torus.
+//!
+//! Each map-frame cell location, including those outside the map's bounds, is stored in the cell
+//! whose location is equal to it modulo the size of the map. This is the basis of the classic
+//! scrolling local map: a map as large as the sensor's range is kept around the robot, and as the
+//! robot moves the cells which fall out behind it are reused for the cells coming into view ahead,
+//! without copying any data.
+//!
+//! ```
+//! use cell_map::{wrapping::WrappingMap, Bounds, CellMap, CellMapParams, Layer};
+//! use nalgebra::Point2;
+//!
+//! #[derive(Layer, Clone, Debug)]
+//! enum MyLayer {
+//!     Height,
+//! }
+//!
+//! let map = CellMap::<MyLayer, f64>::new(CellMapParams {
+//!     cell_bounds: Bounds::new((0, 10), (0, 10)).unwrap(),
+//!     ..Default::default()
+//! });
+//! let mut map = WrappingMap::new(map);
+//!
+//! // Cell (12, -1) is stored in cell (2, 9) of the map
+//! map.set(MyLayer::Height, Point2::new(12, -1), 1.5);
+//! assert_eq!(map.get(MyLayer::Height, Point2::new(2, 9)), &1.5);
+//! assert_eq!(map.get_position(MyLayer::Height, Point2::new(12.5, -0.5)), &1.5);
+//! ```
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use nalgebra::{Point2, Vector2};
+use ndarray::Array2;
+
+use crate::{CellMap, Layer};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A [`CellMap`] whose edges wrap around, see the [module documentation](self).
+///
+/// Cells are addressed by their map-frame cell location, as given by
+/// [`WrappingMap::cell()`], rather than by index, since every location is in the map.
+///
+/// The wrapped map must have at least one cell, since every location has to be stored somewhere.
+#[derive(Debug, Clone)]
+pub struct WrappingMap<L, T>
+where
+    L: Layer,
+{
+    map: CellMap<L, T>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<L, T> WrappingMap<L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    /// Wraps the given map.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the map has no cells.
+    pub fn new(map: CellMap<L, T>) -> Self {
+        assert!(
+            map.num_cells().x > 0 && map.num_cells().y > 0,
+            "Wrapping maps must have at least one cell"
+        );
+
+        Self { map }
+    }
+
+    /// Returns a reference to the wrapped map.
+    pub fn map(&self) -> &CellMap<L, T> {
+        &self.map
+    }
+
+    /// Returns a mutable reference to the wrapped map.
+    pub fn map_mut(&mut self) -> &mut CellMap<L, T> {
+        &mut self.map
+    }
+
+    /// Consumes the wrapper, returning the map.
+    pub fn into_map(self) -> CellMap<L, T> {
+        self.map
+    }
+
+    /// Returns the map-frame cell location containing the given parent-frame position.
+    pub fn cell(&self, position: Point2<f64>) -> Point2<isize> {
+        self.map.metadata.get_cell(position)
+    }
+
+    /// Returns the index in the wrapped map of the cell where the given map-frame cell location is
+    /// stored.
+    pub fn wrap(&self, cell: Point2<isize>) -> Point2<usize> {
+        let min = self.map.cell_bounds().as_corners().0;
+        let num_cells = self.map.num_cells().map(|v| v as isize);
+
+        Point2::new(
+            (cell.x - min.x).rem_euclid(num_cells.x) as usize,
+            (cell.y - min.y).rem_euclid(num_cells.y) as usize,
+        )
+    }
+
+    /// Returns a reference to the value at the given layer and map-frame cell location.
+    pub fn get(&self, layer: L, cell: Point2<isize>) -> &T {
+        &self.map[(layer, self.wrap(cell))]
+    }
+
+    /// Returns a mutable reference to the value at the given layer and map-frame cell location.
+    pub fn get_mut(&mut self, layer: L, cell: Point2<isize>) -> &mut T {
+        let index = self.wrap(cell);
+        &mut self.map[(layer, index)]
+    }
+
+    /// Sets the value at the given layer and map-frame cell location.
+    pub fn set(&mut self, layer: L, cell: Point2<isize>, value: T) {
+        *self.get_mut(layer, cell) = value;
+    }
+
+    /// Returns a reference to the value at the given layer and parent-frame position.
+    pub fn get_position(&self, layer: L, position: Point2<f64>) -> &T {
+        self.get(layer, self.cell(position))
+    }
+
+    /// Sets the value at the given layer and parent-frame position.
+    pub fn set_position(&mut self, layer: L, position: Point2<f64>, value: T) {
+        let cell = self.cell(position);
+        self.set(layer, cell, value)
+    }
+
+    /// Returns the window of `layer` around the given map-frame cell location, wrapping around the
+    /// edges of the map.
+    ///
+    /// The `semi_width` is half the size of the window in the x and y axes, not including the
+    /// central cell, as for [`CellMap::window_iter()`]. Windows larger than the map contain some
+    /// cells more than once.
+    pub fn window(&self, layer: L, cell: Point2<isize>, semi_width: Vector2<usize>) -> Array2<T> {
+        let data = &self.map[layer];
+        let semi_width = semi_width.map(|v| v as isize);
+
+        Array2::from_shape_fn(
+            (2 * semi_width.y as usize + 1, 2 * semi_width.x as usize + 1),
+            |(y, x)| {
+                let index = self.wrap(
+                    cell + Vector2::new(x as isize - semi_width.x, y as isize - semi_width.y),
+                );
+                data[(index.y, index.x)].clone()
+            },
+        )
+    }
+
+    /// Returns an iterator over the map-frame cell location and window of `layer` around every
+    /// cell in the map's bounds, in the same order as [`CellMap::iter()`], wrapping around the
+    /// edges of the map.
+    pub fn window_iter(
+        &self,
+        layer: L,
+        semi_width: Vector2<usize>,
+    ) -> impl Iterator<Item = (Point2<isize>, Array2<T>)> + '_ {
+        let bounds = self.map.cell_bounds();
+
+        (bounds.y.0..bounds.y.1)
+            .flat_map(move |y| (bounds.x.0..bounds.x.1).map(move |x| Point2::new(x, y)))
+            .map(move |cell| (cell, self.window(layer.clone(), cell, semi_width)))
+    }
+}
+
+impl<L, T> From<WrappingMap<L, T>> for CellMap<L, T>
+where
+    L: Layer,
+    T: Clone,
+{
+    fn from(map: WrappingMap<L, T>) -> Self {
+        map.into_map()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cell_map::Bounds, test_utils::TestLayers, CellMapParams};
+
+    fn new_map() -> WrappingMap<TestLayers, f64> {
+        let mut map = CellMap::new_from_elem(
+            CellMapParams {
+                cell_bounds: Bounds::new((-2, 2), (1, 4)).unwrap(),
+                cell_size: Vector2::new(0.5, 0.5),
+                ..Default::default()
+            },
+            0.0,
+        );
+
+        for ((_, index), v) in map.iter_mut().layer(TestLayers::Layer0).indexed() {
+            *v = (index.x + 10 * index.y) as f64;
+        }
+
+        WrappingMap::new(map)
+    }
+
+    #[test]
+    fn wrapping() {
+        let mut map = new_map();
+
+        assert_eq!(map.wrap(Point2::new(-2, 1)), Point2::new(0, 0));
+        assert_eq!(map.wrap(Point2::new(2, 1)), Point2::new(0, 0));
+        assert_eq!(map.wrap(Point2::new(-3, 0)), Point2::new(3, 2));
+        assert_eq!(map.wrap(Point2::new(9, -8)), Point2::new(3, 0));
+
+        assert_eq!(map.get(TestLayers::Layer0, Point2::new(-9, 5)), &11.0);
+        assert_eq!(
+            map.get_position(TestLayers::Layer0, Point2::new(1.75, 1.75)),
+            &21.0
+        );
+
+        map.set_position(TestLayers::Layer1, Point2::new(-1.75, 0.25), 3.0);
+        assert_eq!(map.cell(Point2::new(-1.75, 0.25)), Point2::new(-4, 0));
+        assert_eq!(map.map()[(TestLayers::Layer1, Point2::new(2, 2))], 3.0);
+
+        let map: CellMap<_, _> = map.into();
+        assert_eq!(map.cell_bounds(), Bounds::new((-2, 2), (1, 4)).unwrap());
+    }
+
+    #[test]
+    fn windows() {
+        let map = new_map();
+
+        // The window around the first cell wraps to the opposite edges
+        let window = map.window(TestLayers::Layer0, Point2::new(-2, 1), Vector2::new(1, 1));
+        assert_eq!(window.row(0).to_vec(), vec![23.0, 20.0, 21.0]);
+        assert_eq!(window.row(1).to_vec(), vec![3.0, 0.0, 1.0]);
+        assert_eq!(window.row(2).to_vec(), vec![13.0, 10.0, 11.0]);
+
+        // Windows larger than the map repeat cells
+        let window = map.window(TestLayers::Layer0, Point2::new(0, 2), Vector2::new(3, 0));
+        assert_eq!(
+            window.row(0).to_vec(),
+            vec![13.0, 10.0, 11.0, 12.0, 13.0, 10.0, 11.0]
+        );
+
+        let windows: Vec<_> = map
+            .window_iter(TestLayers::Layer0, Vector2::new(1, 1))
+            .collect();
+        assert_eq!(windows.len(), 12);
+        assert_eq!(windows[5].0, Point2::new(-1, 2));
+        assert_eq!(windows[5].1[(1, 1)], 11.0);
+    }
+}